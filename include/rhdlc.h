@@ -0,0 +1,91 @@
+/*
+ * The C API of rhdlc, the compiler for RHDL, in librhdlc built with `--features capi`.
+ *
+ * Strings are UTF-8 and NUL-terminated. What's returned by rhdlc_options_new and rhdlc_compile is
+ * owned by the caller until it's passed to the matching _free function. Functions returning an
+ * int return the exit status of rhdlc for it: 0 on success, or 2 for an invalid argument.
+ */
+#ifndef RHDLC_H
+#define RHDLC_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Options that affect compilation, like the command line arguments of rhdlc */
+typedef struct rhdlc_options rhdlc_options;
+
+/* What compiling a design reported */
+typedef struct rhdlc_output rhdlc_output;
+
+/*
+ * The value of an integer port, or of an element of a vector port: 128 bits, least significant
+ * word first, that are unknown where unknown is set, X if their bit is clear or Z if it's set.
+ */
+typedef struct rhdlc_logic {
+    uint64_t bits[2];
+    uint64_t unknown[2];
+} rhdlc_logic;
+
+/*
+ * A behavioral model that simulates the black boxes of an entity. Ports are passed as an array
+ * of len values in declaration order, with the elements of vector ports in order.
+ *
+ * instantiate returns the state of the instance of the black box at path, the names of the
+ * instances from the testbench down to it separated by ".", which the other callbacks are given
+ * and free frees. Without instantiate, every instance is given user_data, which isn't freed.
+ * eval writes the outputs for the inputs and the state, and is called any number of times while
+ * the design settles, so it doesn't change the state. update is called once the design settled
+ * after its ports changed with the clocks of the testbench, with their values before and after,
+ * and returns whether it changed the state, like a register on an edge of its clock.
+ */
+typedef struct rhdlc_model {
+    void *user_data;
+    void *(*instantiate)(void *user_data, const char *path);
+    void (*eval)(void *state, rhdlc_logic *ports, size_t len);
+    int (*update)(void *state, const rhdlc_logic *before, const rhdlc_logic *ports, size_t len);
+    void (*free)(void *state);
+} rhdlc_model;
+
+/* The version of rhdlc, like "0.1.0" */
+const char *rhdlc_version(void);
+
+/* The options of rhdlc without any arguments, except that the config file isn't read */
+rhdlc_options *rhdlc_options_new(void);
+void rhdlc_options_free(rhdlc_options *options);
+
+/* --top: the path of the entity to elaborate the design from */
+int rhdlc_options_set_top(rhdlc_options *options, const char *top);
+/* --lib name=path: a library registered by name, as a directory or root file */
+int rhdlc_options_add_library(rhdlc_options *options, const char *name, const char *path);
+/* --error-format sarif when sarif isn't zero, and rendered diagnostics otherwise */
+int rhdlc_options_set_sarif(rhdlc_options *options, int sarif);
+/* --error-limit: the number of errors to report before stopping, where 0 reports all of them */
+int rhdlc_options_set_error_limit(rhdlc_options *options, size_t limit);
+/*
+ * rhdlc sim --cycles cycles -o output: simulates the top entity as a testbench, reporting to
+ * output, or to standard output if it's NULL
+ */
+int rhdlc_options_set_simulate(rhdlc_options *options, uint64_t cycles, const char *output);
+/* Simulates the black boxes of the entity named entity with model, which is copied */
+int rhdlc_options_add_model(rhdlc_options *options, const char *entity, const rhdlc_model *model);
+
+/*
+ * Compiles the design whose root file is at path, with the default options if options is NULL.
+ * A panic in rhdlc is reported as an internal compiler error.
+ */
+rhdlc_output *rhdlc_compile(const char *path, const rhdlc_options *options);
+/* The status rhdlc would exit with, as in its README */
+int rhdlc_output_status(const rhdlc_output *output);
+/* The diagnostics as rhdlc writes them to standard error, which live as long as output */
+const char *rhdlc_output_diagnostics(const rhdlc_output *output);
+void rhdlc_output_free(rhdlc_output *output);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RHDLC_H */
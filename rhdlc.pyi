@@ -0,0 +1,52 @@
+"""The compiler for RHDL, built from the `python` feature of rhdlc."""
+
+from os import PathLike
+from typing import List, Mapping, Optional, Union
+
+_Path = Union[str, PathLike]
+
+__version__: str
+
+class Diagnostic:
+    """A diagnostic reported by compiling a design, located by its primary label if it has one"""
+
+    severity: str
+    """`error`, `warning`, or `note`"""
+    code: Optional[str]
+    message: str
+    """Along with its notes, each on a line of its own"""
+    file: Optional[str]
+    line: Optional[int]
+    """One-based, like the lines and columns of editors"""
+    column: Optional[int]
+
+class Output:
+    """What compiling a design reported"""
+
+    status: int
+    """The status `rhdlc` would exit with"""
+    diagnostics: List[Diagnostic]
+
+class Symbol:
+    """An item declared in a file of a design, and the items declared inside it"""
+
+    name: str
+    kind: str
+    """Like `entity` or `fn`"""
+    detail: Optional[str]
+    """The name of an architecture"""
+    file: str
+    line: int
+    """Where its name starts"""
+    column: int
+    children: List["Symbol"]
+
+def compile(
+    path: _Path,
+    top: Optional[str] = None,
+    libraries: Optional[Mapping[str, _Path]] = None,
+) -> Output:
+    """Compiles the design whose root file is at `path` like `rhdlc` does"""
+
+def symbols(path: _Path, libraries: Optional[Mapping[str, _Path]] = None) -> List[Symbol]:
+    """The items declared at the top level of each file of the design whose root file is at `path`"""
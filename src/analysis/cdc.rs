@@ -0,0 +1,84 @@
+use std::collections::BTreeSet;
+
+use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
+
+use super::signal_graph::{DriverRole, SignalGraph, SignalIndex};
+use crate::attribute;
+use crate::error::*;
+
+/// The clock domains of each signal, identified by the root of each clock net.
+/// Registers belong to the domain of their clock and combinational signals
+/// belong to every domain they read from. Signals that don't appear have no domain,
+/// like top level inputs.
+pub fn clock_domains(graph: &SignalGraph) -> HashMap<SignalIndex, BTreeSet<SignalIndex>> {
+    let mut domains: HashMap<SignalIndex, BTreeSet<SignalIndex>> = HashMap::default();
+    loop {
+        let mut changed = false;
+        for driver in graph.drivers.iter() {
+            let driver_domains: BTreeSet<SignalIndex> = match (driver.role, driver.clocking) {
+                (DriverRole::Register, Some(clocking))
+                | (DriverRole::ResetValue, Some(clocking)) => {
+                    std::iter::once(graph.net_root(clocking.clock)).collect()
+                }
+                _ => driver
+                    .reads
+                    .iter()
+                    .filter_map(|(read, _)| domains.get(read))
+                    .flatten()
+                    .copied()
+                    .collect(),
+            };
+            let target_domains = domains.entry(driver.target).or_default();
+            for domain in driver_domains {
+                changed |= target_domains.insert(domain);
+            }
+        }
+        if !changed {
+            break domains;
+        }
+    }
+}
+
+/// Finds registers that sample a signal from another clock domain.
+/// The first register of a synchronizer is marked with `#[synchronizer]` to allow the crossing.
+pub struct ClockDomainChecker<'a, 'ast> {
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> ClockDomainChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let graph = self.graph;
+        let domains = clock_domains(graph);
+        let mut reported: HashSet<(SignalIndex, SignalIndex)> = HashSet::default();
+        for driver in graph.drivers.iter() {
+            let clocking = match (driver.role, driver.clocking) {
+                (DriverRole::Register, Some(clocking)) => clocking,
+                _ => continue,
+            };
+            let target = &graph[driver.target];
+            if attribute::find_flag(target.attrs, "synchronizer").is_some() {
+                continue;
+            }
+            let clock = graph.net_root(clocking.clock);
+            for (read, span) in driver.reads.iter() {
+                let foreign_domain = domains
+                    .get(read)
+                    .and_then(|read_domains| read_domains.iter().find(|domain| **domain != clock));
+                if let Some(foreign_domain) = foreign_domain {
+                    if reported.insert((driver.target, *read)) {
+                        self.errors.push(clock_domain_crossing(
+                            driver.file,
+                            *span,
+                            graph[*read].ident,
+                            graph[*foreign_domain].ident,
+                            clocking.span,
+                            target.ident,
+                            graph[clock].ident,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
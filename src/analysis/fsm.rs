@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::ptr;
+
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{Expr, ExprAssign, ExprMatch, Pat, Type};
+use rhdl::visit::Visit;
+
+use super::signal_graph::{DriverRole, SignalGraph, SignalIndex, SignalKind};
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::{
+    enums::{find_enum, find_variant, Encoding, Enum},
+    Design, InstanceIndex,
+};
+use crate::error::*;
+use crate::ir::process::single_ident;
+use crate::resolution::{item_arch, ResolutionIndex, Resolver};
+
+/// A register of an enum type that an architecture matches on to decide its next value
+#[derive(Debug)]
+pub struct Fsm<'ast> {
+    pub signal: SignalIndex,
+    pub states: Enum<'ast>,
+    /// The reset value, or the first state without a reset
+    pub initial: usize,
+    /// Sorted pairs of states the register can go from and to
+    pub transitions: Vec<(usize, usize)>,
+}
+
+/// The state machines of an instance, in signal order.
+///
+/// Every value of the register must be a state, like `state = State::Busy`. A transition leaves
+/// the states of the `match state { ... }` arms the assignment is in,
+/// or every state outside of one.
+pub fn find_fsms<'ast>(
+    resolver: &Resolver<'ast>,
+    design: &Design<'ast>,
+    graph: &SignalGraph<'ast>,
+    instance: InstanceIndex,
+) -> Vec<Fsm<'ast>> {
+    let (entity, arch) = match design[instance].arch {
+        Some(arch) => (design[instance].entity, arch),
+        None => return vec![],
+    };
+    graph
+        .instance_signals
+        .get(&instance)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|&signal| {
+            let scope = match graph[signal].kind {
+                SignalKind::Local => arch,
+                _ => entity,
+            };
+            let states = match graph[signal].ty {
+                Some(Type::Path(type_path)) => find_enum(resolver, scope, type_path)?,
+                _ => return None,
+            };
+            if !graph.is_register(signal) {
+                return None;
+            }
+
+            let mut finder = TransitionFinder {
+                resolver,
+                arch,
+                graph,
+                instance,
+                signal,
+                states,
+                current: None,
+                matched: false,
+                assignments: vec![],
+            };
+            resolver.resolution_graph[arch].visit(&mut finder);
+            if !finder.matched {
+                return None;
+            }
+
+            let mut initial = 0;
+            let mut transitions = vec![];
+            for driver in graph.drivers_of(signal) {
+                let expr = driver.expr?;
+                let to = finder.state(expr)?;
+                match driver.role {
+                    DriverRole::ResetValue => initial = to,
+                    _ => {
+                        let from = finder
+                            .assignments
+                            .iter()
+                            .find(|(right, _)| ptr::eq(*right, expr))
+                            .and_then(|(_, from)| from.clone())
+                            .unwrap_or_else(|| (0..states.item_enum.variants.len()).collect());
+                        transitions.extend(from.into_iter().map(|from| (from, to)));
+                    }
+                }
+            }
+            transitions.sort_unstable();
+            transitions.dedup();
+            Some(Fsm {
+                signal,
+                states,
+                initial,
+                transitions,
+            })
+        })
+        .collect()
+}
+
+/// Finds the states the register of a state machine is in at each assignment
+struct TransitionFinder<'a, 'ast> {
+    resolver: &'a Resolver<'ast>,
+    arch: ResolutionIndex,
+    graph: &'a SignalGraph<'ast>,
+    instance: InstanceIndex,
+    signal: SignalIndex,
+    states: Enum<'ast>,
+    /// The states of the arms around the current expression,
+    /// `None` outside of a match on the register
+    current: Option<Vec<usize>>,
+    /// Whether there's a match on the register
+    matched: bool,
+    /// The right-hand side of every assignment and the states it's made in
+    assignments: Vec<(&'ast Expr, Option<Vec<usize>>)>,
+}
+
+impl<'a, 'ast> TransitionFinder<'a, 'ast> {
+    /// The state a value like `State::Busy` names
+    fn state(&self, expr: &Expr) -> Option<usize> {
+        match expr {
+            Expr::Path(expr_path) => {
+                match find_variant(self.resolver, self.arch, &expr_path.path) {
+                    Some((found, variant)) if found.index == self.states.index => Some(variant),
+                    _ => None,
+                }
+            }
+            Expr::Paren(expr_paren) => self.state(&expr_paren.expr),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'ast> Visit<'ast> for TransitionFinder<'a, 'ast> {
+    fn visit_expr_assign(&mut self, expr_assign: &'ast ExprAssign) {
+        self.assignments
+            .push((&*expr_assign.right, self.current.clone()));
+        self.visit_expr(&expr_assign.right);
+    }
+
+    fn visit_expr_match(&mut self, expr_match: &'ast ExprMatch) {
+        self.visit_expr(&expr_match.expr);
+        let on_register = single_ident(&expr_match.expr)
+            .and_then(|ident| self.graph.lookup(self.instance, ident))
+            == Some(self.signal);
+        if !on_register {
+            for arm in expr_match.arms.iter() {
+                if let Some((_, guard)) = &arm.guard {
+                    self.visit_expr(guard);
+                }
+                self.visit_expr(&arm.body);
+            }
+            return;
+        }
+
+        self.matched = true;
+        let outer = self.current.clone();
+        // the states no earlier arm matches
+        let mut remaining = outer
+            .clone()
+            .unwrap_or_else(|| (0..self.states.item_enum.variants.len()).collect());
+        for arm in expr_match.arms.iter() {
+            let arm_states = match &arm.pat {
+                Pat::Path(pat_path) => {
+                    match find_variant(self.resolver, self.arch, &pat_path.path) {
+                        Some((found, variant)) if found.index == self.states.index => remaining
+                            .iter()
+                            .copied()
+                            .filter(|state| *state == variant)
+                            .collect(),
+                        _ => remaining.clone(),
+                    }
+                }
+                _ => remaining.clone(),
+            };
+            if let Some((_, guard)) = &arm.guard {
+                self.visit_expr(guard);
+            } else {
+                remaining.retain(|state| !arm_states.contains(state));
+            }
+            self.current = Some(arm_states);
+            self.visit_expr(&arm.body);
+        }
+        self.current = outer;
+    }
+}
+
+/// Checks the state machines of each architecture for:
+/// * states no transition from the initial state leads to, unless `#[allow(unreachable_state)]`
+/// * reachable states with no transition to another state, unless `#[allow(dead_end_state)]`
+/// * transitions from reachable states of gray coded enums that change more than one bit of the
+///   register, unless `#[allow(non_gray_transition)]`
+///
+/// The `allow` attributes apply to an architecture or a single state register.
+pub struct FsmChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub design: &'a Design<'ast>,
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> FsmChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let lint_levels = &self.resolver.lint_levels;
+        // instances of the same architecture share their state machines
+        let mut checked_arches = HashSet::default();
+        for instance in self.design.instance_indices() {
+            let arch = match self.design[instance].arch {
+                Some(arch) if checked_arches.insert(arch) => arch,
+                _ => continue,
+            };
+            let arch_attrs = &item_arch(resolution_graph, arch).attrs;
+            for fsm in find_fsms(self.resolver, self.design, self.graph, instance) {
+                let signal = &self.graph[fsm.signal];
+                let is_allowed = |lint| {
+                    attribute::is_allowed(arch_attrs, lint)
+                        || attribute::is_allowed(signal.attrs, lint)
+                        || lint_levels.get(lint) == Some(&LintLevel::Allow)
+                };
+                let enum_file = resolution_graph.file(fsm.states.index);
+                let variants = &fsm.states.item_enum.variants;
+
+                let mut reachable = vec![false; variants.len()];
+                reachable[fsm.initial] = true;
+                let mut queue = VecDeque::from(vec![fsm.initial]);
+                while let Some(from) = queue.pop_front() {
+                    for (_, to) in fsm.transitions.iter().filter(|(f, _)| *f == from) {
+                        if !reachable[*to] {
+                            reachable[*to] = true;
+                            queue.push_back(*to);
+                        }
+                    }
+                }
+
+                for (state, variant) in variants.iter().enumerate() {
+                    if !reachable[state] {
+                        if !is_allowed("unreachable_state") {
+                            self.errors.push(unreachable_state(
+                                enum_file,
+                                &variant.ident,
+                                signal.ident,
+                            ));
+                        }
+                        continue;
+                    }
+                    let has_exit = fsm
+                        .transitions
+                        .iter()
+                        .any(|(from, to)| *from == state && *to != state);
+                    if !has_exit && !is_allowed("dead_end_state") {
+                        self.errors
+                            .push(dead_end_state(enum_file, &variant.ident, signal.ident));
+                    }
+                }
+
+                if fsm.states.encoding() == Encoding::Gray && !is_allowed("non_gray_transition") {
+                    for (from, to) in fsm.transitions.iter() {
+                        let changed = fsm.states.code(*from) ^ fsm.states.code(*to);
+                        if reachable[*from] && changed.count_ones() > 1 {
+                            self.errors.push(non_gray_transition(
+                                enum_file,
+                                &variants[*from].ident,
+                                &variants[*to].ident,
+                                signal.ident,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
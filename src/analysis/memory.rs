@@ -0,0 +1,379 @@
+use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use rhdl::ast::{Expr, ExprAssign, ExprAssignOp, ExprIf, ExprIndex, LitStr, Span, Spanned, Type};
+use rhdl::visit::Visit;
+
+use super::signal_graph::{DriverRole, SignalGraph, SignalIndex, SignalKind};
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::{width::type_width, Design, InstanceIndex};
+use crate::error::*;
+use crate::ir::process::{clocked_if, single_ident};
+use crate::resolution::{item_arch, Resolver};
+
+/// The kinds of block RAM a memory can be declared to be with `#[block_ram = "kind"]`
+const TARGETS: &[Target] = &[
+    Target {
+        name: "single-port",
+        read_ports: 1,
+        write_ports: 1,
+        ports: 1,
+    },
+    Target {
+        name: "simple-dual-port",
+        read_ports: 1,
+        write_ports: 1,
+        ports: 2,
+    },
+    Target {
+        name: "true-dual-port",
+        read_ports: 2,
+        write_ports: 2,
+        ports: 2,
+    },
+];
+
+/// Without a target, a memory can have as many ports as the largest block RAM
+const MAX_PORTS: usize = 2;
+
+struct Target {
+    name: &'static str,
+    read_ports: usize,
+    write_ports: usize,
+    /// Ports of either kind
+    ports: usize,
+}
+
+/// A register array of words written one word at a time, like `mem[addr] = data`
+#[derive(Debug)]
+pub struct Memory<'ast> {
+    pub signal: SignalIndex,
+    pub words: u128,
+    pub word_width: u128,
+    pub reads: Vec<Access<'ast>>,
+    pub writes: Vec<Access<'ast>>,
+    /// Assignments to the whole array
+    pub whole_writes: Vec<Span>,
+}
+
+/// A read or write of a single word
+#[derive(Debug, Clone, Copy)]
+pub struct Access<'ast> {
+    pub address: &'ast Expr,
+    pub span: Span,
+    /// Whether the access is made on a clock edge
+    pub clocked: bool,
+}
+
+/// The memories of an instance, in signal order
+pub fn find_memories<'ast>(
+    resolver: &Resolver<'ast>,
+    design: &Design<'ast>,
+    graph: &SignalGraph<'ast>,
+    instance: InstanceIndex,
+) -> Vec<Memory<'ast>> {
+    let (entity, arch) = match design[instance].arch {
+        Some(arch) => (design[instance].entity, arch),
+        None => return vec![],
+    };
+    let mut finder = AccessFinder {
+        graph,
+        instance,
+        clocked: false,
+        accesses: HashMap::default(),
+    };
+    resolver.resolution_graph[arch].visit(&mut finder);
+
+    graph
+        .instance_signals
+        .get(&instance)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|&signal| {
+            let scope = match graph[signal].kind {
+                SignalKind::Local => arch,
+                _ => entity,
+            };
+            let bindings = &design[instance].generics;
+            let word_width = match graph[signal].ty {
+                Some(Type::Array(type_array)) => {
+                    type_width(resolver, scope, bindings, &type_array.elem)?
+                }
+                _ => return None,
+            };
+            // arrays of bits are integers
+            if word_width <= 1 || !graph.is_register(signal) {
+                return None;
+            }
+            let accesses = finder.accesses.remove(&signal)?;
+            let computed_write = accesses
+                .writes
+                .iter()
+                .any(|write| !matches!(write.address, Expr::Lit(_)));
+            if !computed_write {
+                return None;
+            }
+            Some(Memory {
+                signal,
+                words: graph[signal].width? / word_width,
+                word_width,
+                ..accesses
+            })
+        })
+        .collect()
+}
+
+/// Accesses at the same address signal share a port, and any other address takes one of its own.
+/// The access that takes a port past `limit`, if any.
+fn excess_access<'b, 'ast>(
+    accesses: impl Iterator<Item = &'b Access<'ast>>,
+    limit: usize,
+) -> Option<&'b Access<'ast>>
+where
+    'ast: 'b,
+{
+    let mut addresses = HashSet::default();
+    let mut ports = 0;
+    for access in accesses {
+        let new_port = match single_ident(access.address) {
+            Some(ident) => addresses.insert(ident.to_string()),
+            None => true,
+        };
+        if new_port {
+            ports += 1;
+            if ports > limit {
+                return Some(access);
+            }
+        }
+    }
+    None
+}
+
+/// Finds the reads and writes of single words of every array
+struct AccessFinder<'a, 'ast> {
+    graph: &'a SignalGraph<'ast>,
+    instance: InstanceIndex,
+    clocked: bool,
+    accesses: HashMap<SignalIndex, Memory<'ast>>,
+}
+
+impl<'a, 'ast> AccessFinder<'a, 'ast> {
+    fn accesses_of(&mut self, expr: &'ast Expr) -> Option<&mut Memory<'ast>> {
+        let signal = self.graph.lookup(self.instance, single_ident(expr)?)?;
+        Some(self.accesses.entry(signal).or_insert(Memory {
+            signal,
+            words: 0,
+            word_width: 0,
+            reads: vec![],
+            writes: vec![],
+            whole_writes: vec![],
+        }))
+    }
+
+    fn access(&self, expr_index: &'ast ExprIndex) -> Access<'ast> {
+        Access {
+            address: &expr_index.index,
+            span: expr_index.span(),
+            clocked: self.clocked,
+        }
+    }
+
+    fn visit_target(&mut self, left: &'ast Expr) {
+        match left {
+            Expr::Index(expr_index) => {
+                let access = self.access(expr_index);
+                if let Some(memory) = self.accesses_of(&expr_index.expr) {
+                    memory.writes.push(access);
+                }
+                self.visit_expr(&expr_index.index);
+            }
+            other => {
+                let span = other.span();
+                if let Some(memory) = self.accesses_of(other) {
+                    memory.whole_writes.push(span);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, 'ast> Visit<'ast> for AccessFinder<'a, 'ast> {
+    fn visit_expr_assign(&mut self, expr_assign: &'ast ExprAssign) {
+        self.visit_target(&expr_assign.left);
+        self.visit_expr(&expr_assign.right);
+    }
+
+    fn visit_expr_assign_op(&mut self, expr_assign_op: &'ast ExprAssignOp) {
+        self.visit_target(&expr_assign_op.left);
+        // the old value is read too
+        self.visit_expr(&expr_assign_op.left);
+        self.visit_expr(&expr_assign_op.right);
+    }
+
+    fn visit_expr_index(&mut self, expr_index: &'ast ExprIndex) {
+        let access = self.access(expr_index);
+        match self.accesses_of(&expr_index.expr) {
+            Some(memory) => memory.reads.push(access),
+            None => self.visit_expr(&expr_index.expr),
+        }
+        self.visit_expr(&expr_index.index);
+    }
+
+    fn visit_expr_if(&mut self, expr_if: &'ast ExprIf) {
+        if let Some(clocked_if) = clocked_if(expr_if) {
+            let outer = self.clocked;
+            self.clocked = true;
+            if let Some((_, _, reset_block)) = clocked_if.reset {
+                self.visit_block(reset_block);
+            }
+            self.visit_block(clocked_if.body);
+            self.clocked = outer;
+            return;
+        }
+        self.visit_expr(&expr_if.cond);
+        self.visit_block(&expr_if.then_branch);
+        if let Some((_, else_branch)) = &expr_if.else_branch {
+            self.visit_expr(else_branch);
+        }
+    }
+}
+
+/// Checks that memories can be block RAMs:
+/// * memories declared with `#[block_ram = "kind"]` have no more read and write ports than that
+///   kind of block RAM, where `kind` is `single-port`, `simple-dual-port`, or `true-dual-port`
+/// * other memories that would be built from registers are reported,
+///   unless `#[allow(distributed_memory)]`: those read without a clock, reset,
+///   written whole, or accessed at more addresses than a block RAM has ports
+///
+/// The `allow` attribute applies to an architecture or a single memory.
+pub struct MemoryChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub design: &'a Design<'ast>,
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> MemoryChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let graph = self.graph;
+        let resolution_graph = &self.resolver.resolution_graph;
+        let lint_levels = &self.resolver.lint_levels;
+        // instances of the same architecture share their memories
+        let mut checked_arches = HashSet::default();
+        for instance in self.design.instance_indices() {
+            let arch = match self.design[instance].arch {
+                Some(arch) if checked_arches.insert(arch) => arch,
+                _ => continue,
+            };
+            let arch_file = resolution_graph.file(arch);
+            let arch_attrs = &item_arch(resolution_graph, arch).attrs;
+            for memory in find_memories(self.resolver, self.design, graph, instance) {
+                let signal = &graph[memory.signal];
+                let target = match attribute::find_str(signal.attrs, "block_ram") {
+                    Some(lit_str) => match TARGETS.iter().find(|t| lit_str.inner == t.name) {
+                        Some(target) => Some((lit_str, target)),
+                        None => {
+                            self.errors.push(invalid_attribute_value(
+                                arch_file,
+                                lit_str.span(),
+                                "block_ram",
+                                &TARGETS.iter().map(|t| t.name).collect::<Vec<_>>(),
+                            ));
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                if let Some((lit_str, target)) = target {
+                    self.check_ports(&memory, lit_str, target);
+                }
+
+                let is_allowed = attribute::is_allowed(arch_attrs, "distributed_memory")
+                    || attribute::is_allowed(signal.attrs, "distributed_memory")
+                    || lint_levels.get("distributed_memory") == Some(&LintLevel::Allow);
+                if is_allowed {
+                    continue;
+                }
+                let all_accesses = || memory.writes.iter().chain(memory.reads.iter());
+                let reset = graph
+                    .drivers_of(memory.signal)
+                    .find(|driver| driver.role == DriverRole::ResetValue);
+                let reason = if let Some(read) = memory.reads.iter().find(|read| !read.clocked) {
+                    Some((
+                        read.span,
+                        "read without a clock, but block RAM reads are registered".to_string(),
+                    ))
+                } else if let Some(reset) = reset {
+                    Some((
+                        reset.span,
+                        "reset here, but block RAMs can't be reset".to_string(),
+                    ))
+                } else if let Some(span) = memory.whole_writes.first() {
+                    Some((
+                        *span,
+                        "written whole, but block RAMs are written a word at a time".to_string(),
+                    ))
+                } else if let (None, Some(access)) =
+                    (target, excess_access(all_accesses(), MAX_PORTS))
+                {
+                    Some((
+                        access.span,
+                        format!(
+                            "accessed at another address, but block RAMs have at most {} ports",
+                            MAX_PORTS
+                        ),
+                    ))
+                } else {
+                    None
+                };
+                if let Some((span, reason)) = reason {
+                    self.errors
+                        .push(distributed_memory(arch_file, span, signal.ident, &reason));
+                }
+            }
+        }
+    }
+
+    fn check_ports(&mut self, memory: &Memory, lit_str: &LitStr, target: &Target) {
+        let file = self.graph[memory.signal].file;
+        let excess = excess_access(memory.reads.iter(), target.read_ports)
+            .map(|access| (access, "read"))
+            .or_else(|| {
+                excess_access(memory.writes.iter(), target.write_ports)
+                    .map(|access| (access, "written"))
+            })
+            .or_else(|| {
+                excess_access(
+                    memory.writes.iter().chain(memory.reads.iter()),
+                    target.ports,
+                )
+                .map(|access| (access, "accessed"))
+            });
+        if let Some((access, kind)) = excess {
+            self.errors.push(too_many_memory_ports(
+                file,
+                access.span,
+                self.graph[memory.signal].ident,
+                kind,
+                lit_str.span(),
+                target.name,
+                &describe_ports(target),
+            ));
+        }
+    }
+}
+
+/// i.e. "1 read port and 1 write port", or "2 read/write ports" when each port does both
+fn describe_ports(target: &Target) -> String {
+    let plural = |ports| if ports == 1 { "port" } else { "ports" };
+    if target.read_ports == target.ports && target.write_ports == target.ports {
+        format!("{} read/write {}", target.ports, plural(target.ports))
+    } else {
+        format!(
+            "{} read {} and {} write {}",
+            target.read_ports,
+            plural(target.read_ports),
+            target.write_ports,
+            plural(target.write_ports)
+        )
+    }
+}
@@ -0,0 +1,79 @@
+//! Analyses that find hardware design mistakes the compiler can't reject outright,
+//! like unsynchronized clock domain crossings or constructs that can't be synthesized.
+use crate::elaboration::Design;
+use crate::error::Diagnostic;
+use crate::resolution::Resolver;
+
+pub mod cdc;
+pub mod fsm;
+pub mod memory;
+pub mod reset;
+pub mod sharing;
+pub mod signal_graph;
+pub mod signedness;
+pub mod synth;
+pub mod timing;
+pub mod tristate;
+pub mod unread;
+
+use signal_graph::SignalGraph;
+
+pub fn check_design<'ast>(
+    resolver: &Resolver<'ast>,
+    design: &Design<'ast>,
+    graph: &SignalGraph<'ast>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    cdc::ClockDomainChecker {
+        graph,
+        errors,
+    }
+    .check();
+    timing::TimingChecker {
+        graph,
+        errors,
+    }
+    .check();
+    reset::ResetChecker {
+        resolver,
+        design,
+        graph,
+        errors,
+    }
+    .check();
+    fsm::FsmChecker {
+        resolver,
+        design,
+        graph,
+        errors,
+    }
+    .check();
+    memory::MemoryChecker {
+        resolver,
+        design,
+        graph,
+        errors,
+    }
+    .check();
+    signedness::SignednessChecker {
+        resolver,
+        design,
+        graph,
+        errors,
+    }
+    .check();
+    tristate::TristateChecker {
+        resolver,
+        design,
+        graph,
+        errors,
+    }
+    .check();
+    unread::UnreadChecker {
+        resolver,
+        design,
+        graph,
+        errors,
+    }
+    .check();
+}
@@ -0,0 +1,166 @@
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::Spanned;
+
+use super::signal_graph::{Clocking, DriverRole, Reset, ResetKind, SignalGraph, SignalIndex};
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::Design;
+use crate::error::*;
+use crate::resolution::{item_arch, Resolver};
+
+/// A register and how it is reset, if at all
+#[derive(Debug, Clone, Copy)]
+pub struct Register {
+    pub signal: SignalIndex,
+    pub clocking: Clocking,
+    pub reset: Option<Reset>,
+}
+
+/// Every register in the design, in signal order
+pub fn registers(graph: &SignalGraph) -> Vec<Register> {
+    graph
+        .signal_indices()
+        .filter_map(|signal| {
+            let clocking = graph
+                .drivers_of(signal)
+                .filter(|driver| driver.role == DriverRole::Register)
+                .find_map(|driver| driver.clocking)?;
+            let reset = graph
+                .drivers_of(signal)
+                .filter(|driver| driver.role == DriverRole::ResetValue)
+                .find_map(|driver| driver.clocking.and_then(|clocking| clocking.reset));
+            Some(Register {
+                signal,
+                clocking,
+                reset,
+            })
+        })
+        .collect()
+}
+
+/// Checks that the registers of an architecture are reset consistently:
+/// * every register has a reset, unless `#[allow(missing_reset)]`
+/// * all resets have the same kind, signal, and polarity, unless `#[allow(mixed_reset_styles)]`
+/// * resets are of the kind required by `#[reset_style = "sync"]` or `#[reset_style = "async"]`
+///
+/// The `allow` attributes apply to an architecture or a single register.
+pub struct ResetChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub design: &'a Design<'ast>,
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> ResetChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let graph = self.graph;
+        let registers = registers(graph);
+        let lint_levels = &self.resolver.lint_levels;
+        // instances of the same architecture share their registers' declarations
+        let mut checked_arches = HashSet::default();
+        for instance in self.design.instance_indices() {
+            let arch = match self.design[instance].arch {
+                Some(arch) if checked_arches.insert(arch) => arch,
+                _ => continue,
+            };
+            let arch_file = self.resolver.resolution_graph.file(arch);
+            let arch_attrs = &item_arch(&self.resolver.resolution_graph, arch).attrs;
+            let required_kind = match attribute::find_str(arch_attrs, "reset_style") {
+                None => None,
+                Some(style) if style.inner == "sync" => Some(ResetKind::Sync),
+                Some(style) if style.inner == "async" => Some(ResetKind::Async),
+                Some(style) => {
+                    self.errors.push(invalid_attribute_value(
+                        arch_file,
+                        style.span(),
+                        "reset_style",
+                        &["sync", "async"],
+                    ));
+                    None
+                }
+            };
+
+            let mut first_reset: Option<(Register, Reset)> = None;
+            for register in registers
+                .iter()
+                .filter(|register| graph[register.signal].instance == instance)
+            {
+                let signal = &graph[register.signal];
+                let is_allowed = |lint| {
+                    attribute::is_allowed(arch_attrs, lint)
+                        || attribute::is_allowed(signal.attrs, lint)
+                        || lint_levels.get(lint) == Some(&LintLevel::Allow)
+                };
+                let reset = match register.reset {
+                    Some(reset) => reset,
+                    None => {
+                        if !is_allowed("missing_reset") {
+                            self.errors.push(missing_reset(
+                                arch_file,
+                                register.clocking.span,
+                                signal.ident,
+                            ));
+                        }
+                        continue;
+                    }
+                };
+
+                if let Some(required_kind) = required_kind {
+                    if reset.kind != required_kind {
+                        self.errors.push(unexpected_reset_style(
+                            arch_file,
+                            reset.span,
+                            signal.ident,
+                            &describe(graph, reset),
+                            describe_kind(required_kind),
+                        ));
+                    }
+                }
+
+                match first_reset {
+                    None => first_reset = Some((*register, reset)),
+                    Some((first_register, first)) => {
+                        let same_style = first.kind == reset.kind
+                            && first.active_high == reset.active_high
+                            && graph.net_root(first.signal) == graph.net_root(reset.signal);
+                        if !same_style && !is_allowed("mixed_reset_styles") {
+                            self.errors.push(mixed_reset_styles(
+                                arch_file,
+                                (
+                                    first.span,
+                                    graph[first_register.signal].ident,
+                                    &describe(graph, first),
+                                ),
+                                (reset.span, signal.ident, &describe(graph, reset)),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn describe_kind(kind: ResetKind) -> &'static str {
+    match kind {
+        ResetKind::Sync => "synchronous",
+        ResetKind::Async => "asynchronous",
+    }
+}
+
+/// i.e. "an asynchronous active-low reset on `rst_n`"
+fn describe(graph: &SignalGraph, reset: Reset) -> String {
+    format!(
+        "{} {} {} reset on `{}`",
+        match reset.kind {
+            ResetKind::Sync => "a",
+            ResetKind::Async => "an",
+        },
+        describe_kind(reset.kind),
+        if reset.active_high {
+            "active-high"
+        } else {
+            "active-low"
+        },
+        graph[reset.signal].ident
+    )
+}
@@ -0,0 +1,230 @@
+//! `repeated_logic`: the same combinational logic written out more than once in a module, which
+//! synthesis tools don't always share, so area-sensitive designs can assign it to a local once
+//! instead. It's found on the optimized intermediate representation, where expressions that
+//! only differ in how they're written are the same, and is estimated like `--emit resources`.
+use std::fmt::Write;
+
+use codespan_reporting::diagnostic::Severity;
+use fxhash::FxHashMap as HashMap;
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::Span;
+
+use crate::attribute::{self, LintLevel};
+use crate::codegen::resources::{bodies, expr_luts, memories};
+use crate::error::*;
+use crate::ir::{Connection, Expr, ExprKind, Ir, Module, SignalIndex, Stmt};
+use crate::resolution::{item_arch, Resolver};
+
+const LINT: &str = "repeated_logic";
+
+/// Expressions smaller than an 8 bit adder aren't worth a signal of their own
+const MIN_LUTS: u128 = 8;
+
+/// Warns about combinational expressions of at least [`MIN_LUTS`] that are repeated in a module,
+/// leaving out those that are only repeated as part of a larger one. Modules that are
+/// specializations of the same architecture are reported once.
+///
+/// The warning can be set to another level with `#[allow(repeated_logic)]` on the architecture,
+/// or in the config file.
+pub struct SharingChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub ir: &'a Ir<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+/// An expression and where it's written
+struct Repeated {
+    luts: u128,
+    spans: Vec<Span>,
+}
+
+impl<'a, 'ast> SharingChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let mut reported = HashSet::default();
+        for module in self.ir.modules.iter() {
+            let arch = match module.arch {
+                Some(arch) => arch,
+                None => continue,
+            };
+            let level = attribute::lint_level(
+                &item_arch(&self.resolver.resolution_graph, arch).attrs,
+                LINT,
+            )
+            .or_else(|| self.resolver.lint_levels.get(LINT).copied())
+            .unwrap_or(LintLevel::Warn);
+            if level == LintLevel::Allow {
+                continue;
+            }
+            for repeated in repeated(module) {
+                let key = (
+                    module.file,
+                    repeated
+                        .spans
+                        .iter()
+                        .map(|span| (span.start, span.end))
+                        .collect::<Vec<_>>(),
+                );
+                if !reported.insert(key) {
+                    continue;
+                }
+                let duplicated = repeated.luts * (repeated.spans.len() as u128 - 1);
+                let mut diagnostic = repeated_logic(module.file, &repeated.spans, duplicated);
+                if level == LintLevel::Deny {
+                    diagnostic.severity = Severity::Error;
+                }
+                self.errors.push(diagnostic);
+            }
+        }
+    }
+}
+
+/// The expressions of a module written in more than one place, largest first
+fn repeated(module: &Module) -> Vec<Repeated> {
+    let memories = memories(module);
+    let mut finder = Finder {
+        memories: &memories,
+        keys: vec![],
+        found: HashMap::default(),
+    };
+    for process in module.processes.iter() {
+        for body in bodies(process) {
+            finder.stmts(body);
+        }
+    }
+    for instance in module.instances.iter() {
+        for connection in instance.connections.iter() {
+            if let Connection::Input { value, .. } = connection {
+                finder.expr(value);
+            }
+        }
+    }
+    let mut repeated = finder
+        .keys
+        .iter()
+        .filter_map(|key| finder.found.remove(key))
+        .filter(|repeated| repeated.spans.len() > 1)
+        .collect::<Vec<_>>();
+    // stable, so ties stay in the order they're written in
+    repeated.sort_by_key(|repeated| std::cmp::Reverse(repeated.luts));
+    let mut maximal: Vec<Repeated> = vec![];
+    for candidate in repeated {
+        let within_larger = candidate.spans.iter().all(|span| {
+            maximal
+                .iter()
+                .flat_map(|larger| larger.spans.iter())
+                .any(|larger| larger.start <= span.start && span.end <= larger.end)
+        });
+        if !within_larger {
+            maximal.push(candidate);
+        }
+    }
+    maximal
+}
+
+/// Collects the large expressions of a module by their shape
+struct Finder<'m> {
+    memories: &'m [SignalIndex],
+    /// In the order they're first found
+    keys: Vec<String>,
+    found: HashMap<String, Repeated>,
+}
+
+impl<'m> Finder<'m> {
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Assign { index, value, .. } => {
+                    if let Some(index) = index {
+                        self.expr(index);
+                    }
+                    self.expr(value);
+                }
+                Stmt::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => {
+                    self.expr(cond);
+                    self.stmts(then_branch);
+                    self.stmts(else_branch);
+                }
+                Stmt::Property { .. } => {}
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let luts = expr_luts(self.memories, expr);
+        if luts < MIN_LUTS {
+            return;
+        }
+        let mut key = String::new();
+        shape(expr, &mut key);
+        if !self.found.contains_key(&key) {
+            self.keys.push(key.clone());
+        }
+        let repeated = self.found.entry(key).or_insert(Repeated {
+            luts,
+            spans: vec![],
+        });
+        // the body of an unrolled loop is the same expression written once
+        if !repeated.spans.contains(&expr.span) {
+            repeated.spans.push(expr.span);
+        }
+        match &expr.kind {
+            ExprKind::Const(_) | ExprKind::Signal(_) => {}
+            ExprKind::Unary(_, operand) | ExprKind::Resize(operand) => self.expr(operand),
+            ExprKind::Binary(_, left, right) | ExprKind::Index(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Mux(cond, then_value, else_value) => {
+                self.expr(cond);
+                self.expr(then_value);
+                self.expr(else_value);
+            }
+        }
+    }
+}
+
+/// Writes an expression without its spans, so the same logic written twice has the same shape
+fn shape(expr: &Expr, out: &mut String) {
+    write!(out, "{:?} ", expr.ty).unwrap();
+    match &expr.kind {
+        ExprKind::Const(value) => write!(out, "{}", value).unwrap(),
+        ExprKind::Signal(signal) => write!(out, "s{}", signal.index()).unwrap(),
+        ExprKind::Unary(op, operand) => {
+            write!(out, "({:?} ", op).unwrap();
+            shape(operand, out);
+            out.push(')');
+        }
+        ExprKind::Binary(op, left, right) => {
+            write!(out, "({:?} ", op).unwrap();
+            shape(left, out);
+            out.push(' ');
+            shape(right, out);
+            out.push(')');
+        }
+        ExprKind::Index(base, index) => {
+            out.push_str("(Index ");
+            shape(base, out);
+            out.push(' ');
+            shape(index, out);
+            out.push(')');
+        }
+        ExprKind::Resize(operand) => {
+            out.push_str("(Resize ");
+            shape(operand, out);
+            out.push(')');
+        }
+        ExprKind::Mux(cond, then_value, else_value) => {
+            out.push_str("(Mux ");
+            shape(cond, out);
+            out.push(' ');
+            shape(then_value, out);
+            out.push(' ');
+            shape(else_value, out);
+            out.push(')');
+        }
+    }
+}
@@ -0,0 +1,633 @@
+use fxhash::FxHashMap as HashMap;
+use rhdl::{
+    ast::{
+        Attribute, Block, Expr, ExprAssign, ExprAssignOp, ExprIf, ExprMatch, ExprPath, Ident,
+        Local, Pat, PortDir, Span, Spanned, Stmt, Type, UnOp,
+    },
+    visit::Visit,
+};
+
+use crate::attribute;
+use crate::elaboration::{item_entity, width::type_width, Design, InstanceIndex};
+use crate::find_file::FileId;
+use crate::resolution::Resolver;
+
+/// Every signal of every instance in an elaborated design and what drives it
+#[derive(Debug, Default)]
+pub struct SignalGraph<'ast> {
+    pub signals: Vec<Signal<'ast>>,
+    pub drivers: Vec<Driver<'ast>>,
+    /// Indices of the drivers of each signal in `drivers`
+    drivers_by_target: HashMap<SignalIndex, Vec<usize>>,
+    /// Signals of each instance, ports first and then locals in declaration order
+    pub instance_signals: HashMap<InstanceIndex, Vec<SignalIndex>>,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SignalIndex(usize);
+
+#[derive(Debug)]
+pub struct Signal<'ast> {
+    pub instance: InstanceIndex,
+    pub file: FileId,
+    pub ident: &'ast Ident,
+    pub kind: SignalKind,
+    pub ty: Option<&'ast Type>,
+    /// `None` when the width can't be determined during elaboration
+    pub width: Option<u128>,
+    pub attrs: &'ast [Attribute],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalKind {
+    Input,
+    Output,
+    InOut,
+    Local,
+}
+
+#[derive(Debug)]
+pub struct Driver<'ast> {
+    pub target: SignalIndex,
+    pub file: FileId,
+    pub span: Span,
+    /// `None` for connections made by instantiations
+    pub expr: Option<&'ast Expr>,
+    pub role: DriverRole,
+    /// Signals read by the driving expression and the conditions around it
+    pub reads: Vec<(SignalIndex, Span)>,
+    pub clocking: Option<Clocking>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverRole {
+    /// Combinational logic
+    Comb,
+    /// The next value of a register
+    Register,
+    /// The value a register takes while its reset is active
+    ResetValue,
+    /// A port of an instance connected to a signal of its parent
+    Connection,
+}
+
+/// How a register is clocked, i.e. `if clk.rising_edge() { ... }`
+#[derive(Debug, Clone, Copy)]
+pub struct Clocking {
+    pub clock: SignalIndex,
+    pub edge: Edge,
+    /// The clocking expression
+    pub span: Span,
+    pub reset: Option<Reset>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Reset {
+    pub signal: SignalIndex,
+    pub active_high: bool,
+    pub kind: ResetKind,
+    /// The reset condition
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetKind {
+    Sync,
+    Async,
+}
+
+impl<'ast> std::ops::Index<SignalIndex> for SignalGraph<'ast> {
+    type Output = Signal<'ast>;
+    fn index(&self, index: SignalIndex) -> &<Self as std::ops::Index<SignalIndex>>::Output {
+        &self.signals[index.0]
+    }
+}
+
+impl<'ast> SignalGraph<'ast> {
+    pub fn build(resolver: &Resolver<'ast>, design: &Design<'ast>) -> Self {
+        let mut graph = Self::default();
+        for instance in design.instance_indices() {
+            graph.add_signals(resolver, design, instance);
+        }
+        for instance in design.instance_indices() {
+            if let Some(arch) = design[instance].arch {
+                let mut process_visitor = ProcessVisitor {
+                    graph: &mut graph,
+                    instance,
+                    file: resolver.resolution_graph.file(arch),
+                    clocking: None,
+                    role: DriverRole::Comb,
+                    conditions: vec![],
+                };
+                resolver.resolution_graph[arch].visit(&mut process_visitor);
+            }
+            graph.add_connections(design, instance);
+        }
+        graph
+    }
+
+    pub fn signal_indices(&self) -> impl Iterator<Item = SignalIndex> {
+        (0..self.signals.len()).map(SignalIndex)
+    }
+
+    /// Find a signal of an instance by name. Locals shadow ports.
+    pub fn lookup(&self, instance: InstanceIndex, ident: &Ident) -> Option<SignalIndex> {
+        self.instance_signals
+            .get(&instance)?
+            .iter()
+            .rev()
+            .copied()
+            .find(|signal| self[*signal].ident == ident)
+    }
+
+    pub fn drivers_of(&self, signal: SignalIndex) -> impl Iterator<Item = &Driver<'ast>> {
+        self.drivers_by_target
+            .get(&signal)
+            .into_iter()
+            .flatten()
+            .map(move |index| &self.drivers[*index])
+    }
+
+    fn add_driver(&mut self, driver: Driver<'ast>) {
+        self.drivers_by_target
+            .entry(driver.target)
+            .or_default()
+            .push(self.drivers.len());
+        self.drivers.push(driver);
+    }
+
+    pub fn is_register(&self, signal: SignalIndex) -> bool {
+        self.drivers_of(signal)
+            .any(|driver| driver.role == DriverRole::Register)
+    }
+
+    /// Follow connections and plain copies back to the signal that actually drives `signal`.
+    /// A clock connected through several levels of hierarchy belongs to the same net at every level.
+    pub fn net_root(&self, signal: SignalIndex) -> SignalIndex {
+        let mut current = signal;
+        let mut visited = vec![current];
+        loop {
+            let drivers = self.drivers_of(current).collect::<Vec<_>>();
+            let source = match drivers.as_slice() {
+                [driver] if driver.reads.len() == 1 => match (driver.role, driver.expr) {
+                    (DriverRole::Connection, _) => driver.reads[0].0,
+                    (DriverRole::Comb, Some(Expr::Path(_))) => driver.reads[0].0,
+                    _ => return current,
+                },
+                _ => return current,
+            };
+            if visited.contains(&source) {
+                return current;
+            }
+            visited.push(source);
+            current = source;
+        }
+    }
+
+    fn add_signal(&mut self, signal: Signal<'ast>) -> SignalIndex {
+        let idx = SignalIndex(self.signals.len());
+        self.instance_signals
+            .entry(signal.instance)
+            .or_default()
+            .push(idx);
+        self.signals.push(signal);
+        idx
+    }
+
+    fn add_signals(
+        &mut self,
+        resolver: &Resolver<'ast>,
+        design: &Design<'ast>,
+        instance: InstanceIndex,
+    ) {
+        let entity = design[instance].entity;
+        let bindings = &design[instance].generics;
+        let entity_file = resolver.resolution_graph.file(entity);
+        for port in item_entity(resolver, entity).ports.iter() {
+            self.add_signal(Signal {
+                instance,
+                file: entity_file,
+                ident: &port.ident,
+                kind: match port.dir {
+                    PortDir::In(_) => SignalKind::Input,
+                    PortDir::Out(_) => SignalKind::Output,
+                    PortDir::InOut(_) => SignalKind::InOut,
+                },
+                ty: Some(&port.ty),
+                width: type_width(resolver, entity, bindings, &port.ty),
+                attrs: &port.attrs,
+            });
+        }
+        if let Some(arch) = design[instance].arch {
+            let mut local_collector = LocalCollector { locals: vec![] };
+            resolver.resolution_graph[arch].visit(&mut local_collector);
+            let arch_file = resolver.resolution_graph.file(arch);
+            // the names given to instances aren't signals
+            let instance_locals = design[instance]
+                .children
+                .iter()
+                .filter_map(|child| design[*child].instantiation)
+                .filter_map(|instantiation| instantiation.local)
+                .collect::<Vec<&'ast Local>>();
+            for (ident, local) in local_collector.locals {
+                if instance_locals
+                    .iter()
+                    .any(|instance_local| std::ptr::eq(*instance_local, local))
+                {
+                    continue;
+                }
+                let ty = local.ty.as_ref().map(|(_, ty)| ty);
+                self.add_signal(Signal {
+                    instance,
+                    file: arch_file,
+                    ident,
+                    kind: SignalKind::Local,
+                    ty,
+                    width: ty.and_then(|ty| type_width(resolver, arch, bindings, ty)),
+                    attrs: &local.attrs,
+                });
+            }
+        }
+    }
+
+    /// Ports of `instance` are driven by its parent for inputs and drive its parent for outputs
+    fn add_connections(&mut self, design: &Design<'ast>, instance: InstanceIndex) {
+        let (instantiation, parent) =
+            match (design[instance].instantiation, design[instance].parent) {
+                (Some(instantiation), Some(parent)) => (instantiation, parent),
+                _ => return,
+            };
+        for field_value in instantiation.expr.fields.iter() {
+            let port = match self.lookup(instance, &field_value.ident) {
+                Some(port) => port,
+                None => continue,
+            };
+            let (expr, span) = match &field_value.expr {
+                Some((_, expr)) => (Some(expr), expr.span()),
+                None => (None, field_value.ident.span()),
+            };
+            let mut reads = vec![];
+            match expr {
+                Some(expr) => ReadCollector {
+                    graph: self,
+                    instance: parent,
+                    reads: &mut reads,
+                }
+                .visit_expr(expr),
+                None => reads.extend(
+                    self.lookup(parent, &field_value.ident)
+                        .map(|signal| (signal, span)),
+                ),
+            }
+            match self[port].kind {
+                SignalKind::Output => {
+                    for (parent_signal, span) in reads {
+                        self.add_driver(Driver {
+                            target: parent_signal,
+                            file: instantiation.file,
+                            span,
+                            expr: None,
+                            role: DriverRole::Connection,
+                            reads: vec![(port, span)],
+                            clocking: None,
+                        });
+                    }
+                }
+                _ => self.add_driver(Driver {
+                    target: port,
+                    file: instantiation.file,
+                    span,
+                    expr: None,
+                    role: DriverRole::Connection,
+                    reads,
+                    clocking: None,
+                }),
+            }
+        }
+    }
+}
+
+/// Whether a signal is a reset, either by being marked `#[reset]` or by a word of its name like
+/// `rst` in `rst_n` or `Reset` in `coreReset`, but not `rst` in `first`
+pub fn is_reset_signal(signal: &Signal) -> bool {
+    attribute::find_flag(signal.attrs, "reset").is_some()
+        || name_words(&signal.ident.to_string()).any(|word| {
+            ["rst", "rstn", "nrst", "reset", "resetn", "nreset"].contains(&word.as_str())
+        })
+}
+
+/// The lowercase words of a snake or camel case name
+fn name_words(name: &str) -> impl Iterator<Item = String> + '_ {
+    name.split('_').flat_map(|part| {
+        let mut words = vec![];
+        let mut word = String::new();
+        let mut after_lowercase = false;
+        for c in part.chars() {
+            if c.is_uppercase() && after_lowercase {
+                words.push(std::mem::take(&mut word));
+            }
+            after_lowercase = c.is_lowercase();
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+        words.into_iter().filter(|word| !word.is_empty())
+    })
+}
+
+struct LocalCollector<'ast> {
+    locals: Vec<(&'ast Ident, &'ast Local)>,
+}
+
+impl<'ast> Visit<'ast> for LocalCollector<'ast> {
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let Pat::Ident(pat_ident) = &local.pat {
+            self.locals.push((pat_ident, local));
+        }
+        if let Some((_, init)) = &local.init {
+            self.visit_expr(init);
+        }
+    }
+}
+
+/// Collects the signals an expression reads
+struct ReadCollector<'g, 'ast> {
+    graph: &'g SignalGraph<'ast>,
+    instance: InstanceIndex,
+    reads: &'g mut Vec<(SignalIndex, Span)>,
+}
+
+impl<'g, 'ast> Visit<'ast> for ReadCollector<'g, 'ast> {
+    fn visit_expr_path(&mut self, expr_path: &'ast ExprPath) {
+        if let Some(ident) = single_ident(expr_path) {
+            if let Some(signal) = self.graph.lookup(self.instance, ident) {
+                self.reads.push((signal, expr_path.span()));
+            }
+        }
+    }
+}
+
+fn single_ident(expr_path: &ExprPath) -> Option<&Ident> {
+    let path = &expr_path.path;
+    if path.leading_sep.is_some() || path.segments.len() != 1 {
+        return None;
+    }
+    let segment = path.segments.first().unwrap();
+    if segment.generic_args.is_some() {
+        return None;
+    }
+    Some(&segment.ident)
+}
+
+/// The signal assigned by the left hand side of an assignment, i.e. `q`, `q[0]`, or `q.valid`
+fn assigned_signal<'ast>(
+    graph: &SignalGraph<'ast>,
+    instance: InstanceIndex,
+    left: &'ast Expr,
+) -> Option<SignalIndex> {
+    match left {
+        Expr::Path(expr_path) => graph.lookup(instance, single_ident(expr_path)?),
+        Expr::Index(expr_index) => assigned_signal(graph, instance, &expr_index.expr),
+        Expr::Field(expr_field) => assigned_signal(graph, instance, &expr_field.base),
+        Expr::Paren(expr_paren) => assigned_signal(graph, instance, &expr_paren.expr),
+        _ => None,
+    }
+}
+
+/// Walks the processes of an architecture, recording a driver for every assignment
+struct ProcessVisitor<'g, 'ast> {
+    graph: &'g mut SignalGraph<'ast>,
+    instance: InstanceIndex,
+    file: FileId,
+    clocking: Option<Clocking>,
+    role: DriverRole,
+    /// Signals read by the conditions around the current statement
+    conditions: Vec<(SignalIndex, Span)>,
+}
+
+impl<'g, 'ast> ProcessVisitor<'g, 'ast> {
+    /// `clk.rising_edge()` or `clk.falling_edge()`
+    fn edge(&self, cond: &'ast Expr) -> Option<(SignalIndex, Edge)> {
+        match cond {
+            Expr::MethodCall(method_call) if method_call.args.is_empty() => {
+                let edge = if method_call.method == "rising_edge" {
+                    Edge::Rising
+                } else if method_call.method == "falling_edge" {
+                    Edge::Falling
+                } else {
+                    return None;
+                };
+                match &*method_call.receiver {
+                    Expr::Path(expr_path) => self
+                        .graph
+                        .lookup(self.instance, single_ident(expr_path)?)
+                        .map(|clock| (clock, edge)),
+                    _ => None,
+                }
+            }
+            Expr::Paren(expr_paren) => self.edge(&expr_paren.expr),
+            _ => None,
+        }
+    }
+
+    /// `rst` or `!rst`, giving the signal and whether it is active high
+    fn reset_condition(&self, cond: &'ast Expr) -> Option<(SignalIndex, bool)> {
+        match cond {
+            Expr::Path(expr_path) => self
+                .graph
+                .lookup(self.instance, single_ident(expr_path)?)
+                .map(|signal| (signal, true)),
+            Expr::Unary(expr_unary) if matches!(expr_unary.op, UnOp::Not(_)) => self
+                .reset_condition(&expr_unary.expr)
+                .map(|(signal, active_high)| (signal, !active_high)),
+            Expr::Paren(expr_paren) => self.reset_condition(&expr_paren.expr),
+            _ => None,
+        }
+    }
+
+    fn reads(&self, expr: &'ast Expr) -> Vec<(SignalIndex, Span)> {
+        let mut reads = self.conditions.clone();
+        ReadCollector {
+            graph: &*self.graph,
+            instance: self.instance,
+            reads: &mut reads,
+        }
+        .visit_expr(expr);
+        reads
+    }
+
+    fn add_driver(
+        &mut self,
+        target: SignalIndex,
+        expr: &'ast Expr,
+        mut reads: Vec<(SignalIndex, Span)>,
+    ) {
+        reads.extend(self.reads(expr));
+        self.graph.add_driver(Driver {
+            target,
+            file: self.file,
+            span: expr.span(),
+            expr: Some(expr),
+            role: self.role,
+            reads,
+            clocking: self.clocking,
+        });
+    }
+
+    fn with_clocking<F>(&mut self, clocking: Clocking, role: DriverRole, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let outer = (self.clocking, self.role);
+        self.clocking = Some(clocking);
+        self.role = role;
+        f(self);
+        self.clocking = outer.0;
+        self.role = outer.1;
+    }
+
+    fn visit_clocked(&mut self, block: &'ast Block, clocking: Clocking, role: DriverRole) {
+        self.with_clocking(clocking, role, |visitor| visitor.visit_block(block));
+    }
+
+    /// Everything assigned under a condition also depends on the signals the condition reads
+    fn visit_conditional(
+        &mut self,
+        cond: &'ast Expr,
+        then_branch: &'ast Block,
+        else_branch: Option<&'ast Expr>,
+    ) {
+        let depth = self.conditions.len();
+        self.conditions = self.reads(cond);
+        self.visit_block(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.visit_expr(else_branch);
+        }
+        self.conditions.truncate(depth);
+    }
+}
+
+impl<'g, 'ast> Visit<'ast> for ProcessVisitor<'g, 'ast> {
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let (Pat::Ident(pat_ident), Some((_, init))) = (&local.pat, &local.init) {
+            if let Some(target) = self.graph.lookup(self.instance, pat_ident) {
+                self.add_driver(target, init, vec![]);
+            }
+        }
+        if let Some((_, init)) = &local.init {
+            self.visit_expr(init);
+        }
+    }
+
+    fn visit_expr_assign(&mut self, expr_assign: &'ast ExprAssign) {
+        if let Some(target) = assigned_signal(self.graph, self.instance, &expr_assign.left) {
+            // indices of a partial assignment are read too
+            let index_reads = match &*expr_assign.left {
+                Expr::Index(expr_index) => self.reads(&expr_index.index),
+                _ => vec![],
+            };
+            self.add_driver(target, &expr_assign.right, index_reads);
+        }
+        self.visit_expr(&expr_assign.right);
+    }
+
+    fn visit_expr_assign_op(&mut self, expr_assign_op: &'ast ExprAssignOp) {
+        if let Some(target) = assigned_signal(self.graph, self.instance, &expr_assign_op.left) {
+            let target_reads = self.reads(&expr_assign_op.left);
+            self.add_driver(target, &expr_assign_op.right, target_reads);
+        }
+        self.visit_expr(&expr_assign_op.right);
+    }
+
+    fn visit_expr_if(&mut self, expr_if: &'ast ExprIf) {
+        if let Some((clock, edge)) = self.edge(&expr_if.cond) {
+            let clocking = Clocking {
+                clock,
+                edge,
+                span: expr_if.cond.span(),
+                reset: None,
+            };
+            // synchronous reset: `if clk.rising_edge() { if rst { ... } else { ... } }`
+            if let [Stmt::Expr(Expr::If(inner))] | [Stmt::Semi(Expr::If(inner), _)] =
+                expr_if.then_branch.statements.as_slice()
+            {
+                if let Some((signal, active_high)) = self.reset_condition(&inner.cond) {
+                    if is_reset_signal(&self.graph[signal]) {
+                        let clocking = Clocking {
+                            reset: Some(Reset {
+                                signal,
+                                active_high,
+                                kind: ResetKind::Sync,
+                                span: inner.cond.span(),
+                            }),
+                            ..clocking
+                        };
+                        self.visit_clocked(&inner.then_branch, clocking, DriverRole::ResetValue);
+                        if let Some((_, else_branch)) = &inner.else_branch {
+                            self.with_clocking(clocking, DriverRole::Register, |visitor| {
+                                visitor.visit_expr(else_branch)
+                            });
+                        }
+                        return;
+                    }
+                }
+            }
+            self.visit_clocked(&expr_if.then_branch, clocking, DriverRole::Register);
+            return;
+        }
+
+        // asynchronous reset: `if rst { ... } else if clk.rising_edge() { ... }`
+        if let Some((_, else_branch)) = &expr_if.else_branch {
+            if let Expr::If(else_if) = &**else_branch {
+                if let (Some((signal, active_high)), Some((clock, edge))) = (
+                    self.reset_condition(&expr_if.cond),
+                    self.edge(&else_if.cond),
+                ) {
+                    let clocking = Clocking {
+                        clock,
+                        edge,
+                        span: else_if.cond.span(),
+                        reset: Some(Reset {
+                            signal,
+                            active_high,
+                            kind: ResetKind::Async,
+                            span: expr_if.cond.span(),
+                        }),
+                    };
+                    self.visit_clocked(&expr_if.then_branch, clocking, DriverRole::ResetValue);
+                    self.visit_clocked(&else_if.then_branch, clocking, DriverRole::Register);
+                    return;
+                }
+            }
+        }
+
+        self.visit_conditional(
+            &expr_if.cond,
+            &expr_if.then_branch,
+            expr_if
+                .else_branch
+                .as_ref()
+                .map(|(_, else_branch)| &**else_branch),
+        );
+    }
+
+    /// Every arm is conditional on the value matched and the guards before it
+    fn visit_expr_match(&mut self, expr_match: &'ast ExprMatch) {
+        self.visit_expr(&expr_match.expr);
+        let depth = self.conditions.len();
+        self.conditions = self.reads(&expr_match.expr);
+        for arm in expr_match.arms.iter() {
+            if let Some((_, guard)) = &arm.guard {
+                self.visit_expr(guard);
+                self.conditions = self.reads(guard);
+            }
+            self.visit_expr(&arm.body);
+        }
+        self.conditions.truncate(depth);
+    }
+}
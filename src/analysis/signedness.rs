@@ -0,0 +1,248 @@
+//! `mixed_signedness`: arithmetic and comparisons between a signed and an unsigned operand, which
+//! are lowered as unsigned, so a negative value of the signed operand is read as a large positive
+//! number. Signedness follows the lowering: signals and casts have the signedness of their type,
+//! operators of their operands, and literals take that of the other operand.
+use codespan_reporting::diagnostic::Severity;
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{Attribute, BinOp, Expr, ExprAssignOp, ExprBinary, Span, Spanned, Stmt, Type};
+use rhdl::visit::Visit;
+
+use super::signal_graph::SignalGraph;
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::{width::type_width, Design, InstanceIndex};
+use crate::error::*;
+use crate::ir::lower::is_signed;
+use crate::ir::process::{single_ident, FnCollector};
+use crate::resolution::{item_arch, ResolutionIndex, Resolver};
+
+const LINT: &str = "mixed_signedness";
+
+/// Warns about operators of the elaborated architectures that mix signed and unsigned operands.
+///
+/// The warning can be set to another level with `#[allow(mixed_signedness)]` on a function or
+/// else its architecture, or in the config file.
+pub struct SignednessChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub design: &'a Design<'ast>,
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> SignednessChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let resolution_graph = &self.resolver.resolution_graph;
+        // the signedness of a signal doesn't depend on the generics of its instance
+        let mut checked_arches = HashSet::default();
+        for instance in self.design.instance_indices() {
+            let arch = match self.design[instance].arch {
+                Some(arch) if checked_arches.insert(arch) => arch,
+                _ => continue,
+            };
+            let arch_attrs: &[Attribute] = &item_arch(resolution_graph, arch).attrs;
+            let mut fn_collector = FnCollector { fns: vec![] };
+            resolution_graph[arch].visit(&mut fn_collector);
+            for item_fn in fn_collector.fns {
+                let level = [&item_fn.attrs[..], arch_attrs]
+                    .iter()
+                    .find_map(|attrs| attribute::lint_level(attrs, LINT))
+                    .or_else(|| self.resolver.lint_levels.get(LINT).copied())
+                    .unwrap_or(LintLevel::Warn);
+                if level == LintLevel::Allow {
+                    continue;
+                }
+                let mut finder = MixFinder {
+                    checker: &*self,
+                    instance,
+                    arch,
+                    mixes: vec![],
+                };
+                finder.visit_block(&item_fn.block);
+                let file = resolution_graph.file(arch);
+                for mix in finder.mixes {
+                    let mut diagnostic = mixed_signedness(
+                        file,
+                        mix.span,
+                        mix.signed.span(),
+                        mix.unsigned.span(),
+                        mix.comparison,
+                        mix.unsigned_width,
+                    );
+                    if level == LintLevel::Deny {
+                        diagnostic.severity = Severity::Error;
+                    }
+                    self.errors.push(diagnostic);
+                }
+            }
+        }
+    }
+
+    /// Whether `expr` is signed as lowered in `instance`, or `None` if it takes the signedness of
+    /// the other operand or isn't known
+    fn signedness(&self, instance: InstanceIndex, expr: &'ast Expr) -> Option<bool> {
+        match expr {
+            Expr::Path(_) => {
+                let signal = self.graph.lookup(instance, single_ident(expr)?)?;
+                self.graph[signal].ty.map(is_signed)
+            }
+            Expr::Cast(expr_cast) => Some(is_signed(&expr_cast.ty)),
+            Expr::Paren(expr_paren) => self.signedness(instance, &expr_paren.expr),
+            Expr::Unary(expr_unary) => self.signedness(instance, &expr_unary.expr),
+            Expr::Index(expr_index) => {
+                let signal = self
+                    .graph
+                    .lookup(instance, single_ident(&expr_index.expr)?)?;
+                match self.graph[signal].ty? {
+                    Type::Array(type_array) => Some(is_signed(&type_array.elem)),
+                    // a bit of an integer
+                    _ => Some(false),
+                }
+            }
+            Expr::Binary(expr_binary) => match (kind(&expr_binary.op), &expr_binary.op) {
+                // single bits
+                (Some(OpKind::Comparison), _) | (None, BinOp::And(_)) | (None, BinOp::Or(_)) => {
+                    Some(false)
+                }
+                (None, BinOp::Shl(_)) | (None, BinOp::Shr(_)) => {
+                    self.signedness(instance, &expr_binary.left)
+                }
+                _ => {
+                    let left = self.signedness(instance, &expr_binary.left);
+                    let right = self.signedness(instance, &expr_binary.right);
+                    combine(left, right)
+                }
+            },
+            Expr::If(expr_if) => {
+                let then_value = match expr_if.then_branch.statements.as_slice() {
+                    [Stmt::Expr(then_value)] => self.signedness(instance, then_value),
+                    _ => None,
+                };
+                let else_value = expr_if
+                    .else_branch
+                    .as_ref()
+                    .and_then(|(_, else_value)| self.signedness(instance, else_value));
+                combine(then_value, else_value)
+            }
+            Expr::Block(expr_block) => match expr_block.block.statements.as_slice() {
+                [Stmt::Expr(inner)] => self.signedness(instance, inner),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The width of an unsigned operand, for suggesting a signed type that can hold its values
+    fn width(
+        &self,
+        instance: InstanceIndex,
+        arch: ResolutionIndex,
+        expr: &'ast Expr,
+    ) -> Option<u128> {
+        match expr {
+            Expr::Path(_) => {
+                let signal = self.graph.lookup(instance, single_ident(expr)?)?;
+                self.graph[signal].width
+            }
+            Expr::Cast(expr_cast) => type_width(
+                self.resolver,
+                arch,
+                &self.design[instance].generics,
+                &expr_cast.ty,
+            ),
+            Expr::Paren(expr_paren) => self.width(instance, arch, &expr_paren.expr),
+            _ => None,
+        }
+    }
+}
+
+/// The operators linted, whose results depend on the signedness of their operands
+enum OpKind {
+    Arithmetic,
+    Comparison,
+}
+
+fn kind(op: &BinOp) -> Option<OpKind> {
+    match op {
+        BinOp::Add(_) | BinOp::Sub(_) | BinOp::Mul(_) | BinOp::Div(_) | BinOp::Rem(_) => {
+            Some(OpKind::Arithmetic)
+        }
+        BinOp::Eq(_) | BinOp::Ne(_) | BinOp::Lt(_) | BinOp::Le(_) | BinOp::Gt(_) | BinOp::Ge(_) => {
+            Some(OpKind::Comparison)
+        }
+        _ => None,
+    }
+}
+
+/// The signedness of the result of two operands, which is unsigned unless both are signed
+fn combine(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(left && right),
+        (known, None) | (None, known) => known,
+    }
+}
+
+/// An operator with a signed and an unsigned operand
+struct Mix<'ast> {
+    span: Span,
+    signed: &'ast Expr,
+    unsigned: &'ast Expr,
+    comparison: bool,
+    unsigned_width: Option<u128>,
+}
+
+/// Finds the operators that mix signedness in a function of an architecture
+struct MixFinder<'c, 'a, 'ast> {
+    checker: &'c SignednessChecker<'a, 'ast>,
+    instance: InstanceIndex,
+    arch: ResolutionIndex,
+    mixes: Vec<Mix<'ast>>,
+}
+
+impl<'c, 'a, 'ast> MixFinder<'c, 'a, 'ast> {
+    fn check(&mut self, span: Span, left: &'ast Expr, right: &'ast Expr, comparison: bool) {
+        let checker = self.checker;
+        let (signed, unsigned) = match (
+            checker.signedness(self.instance, left),
+            checker.signedness(self.instance, right),
+        ) {
+            (Some(true), Some(false)) => (left, right),
+            (Some(false), Some(true)) => (right, left),
+            _ => return,
+        };
+        self.mixes.push(Mix {
+            span,
+            signed,
+            unsigned,
+            comparison,
+            unsigned_width: checker.width(self.instance, self.arch, unsigned),
+        });
+    }
+}
+
+impl<'c, 'a, 'ast> Visit<'ast> for MixFinder<'c, 'a, 'ast> {
+    fn visit_expr_binary(&mut self, expr_binary: &'ast ExprBinary) {
+        if let Some(kind) = kind(&expr_binary.op) {
+            self.check(
+                expr_binary.span(),
+                &expr_binary.left,
+                &expr_binary.right,
+                matches!(kind, OpKind::Comparison),
+            );
+        }
+        self.visit_expr(&expr_binary.left);
+        self.visit_expr(&expr_binary.right);
+    }
+
+    /// `x += y` is `x = x + y`
+    fn visit_expr_assign_op(&mut self, expr_assign_op: &'ast ExprAssignOp) {
+        if let Some(OpKind::Arithmetic) = kind(&expr_assign_op.op) {
+            self.check(
+                expr_assign_op.span(),
+                &expr_assign_op.left,
+                &expr_assign_op.right,
+                false,
+            );
+        }
+        self.visit_expr(&expr_assign_op.left);
+        self.visit_expr(&expr_assign_op.right);
+    }
+}
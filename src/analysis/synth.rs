@@ -0,0 +1,231 @@
+use codespan_reporting::diagnostic::Severity;
+use rhdl::ast::{
+    Attribute, Expr, ExprCall, ExprForLoop, ExprLoop, ExprMacro, ExprPath, ExprWhile, GenericArg,
+    Ident, ItemFn, Span, Spanned, TypePath,
+};
+use rhdl::visit::Visit;
+
+use crate::attribute::{self, LintLevel, LintLevels};
+use crate::error::*;
+use crate::find_file::FileId;
+use crate::ir::process::{single_ident, FnCollector};
+use crate::resolution::{is_float, item_arch, Resolver};
+
+/// Sets the level of every synthesizability lint at once
+const GROUP: &str = "synthesizability";
+
+/// Macros that allocate on the heap
+const HEAP_MACROS: &[&str] = &["vec", "format"];
+/// Functions and macros that wait for a time
+const DELAYS: &[&str] = &["delay", "wait", "sleep"];
+
+/// Lints the architectures of every file for constructs the synthesis backends can't lower,
+/// so they're reported at check time instead of failing code generation later:
+/// * `unbounded_loop`: `while`, `loop`, and `for` over a range without an end
+/// * `recursion`: functions of an architecture that call themselves, directly or through each other
+/// * `heap_allocation`: macros like `vec!` and `format!`
+/// * `floating_point`: `f32` and `f64`
+/// * `delay`: calls and macros like `delay` and `wait`
+///
+/// They're warnings unless set to another level with `#[allow(lint)]`, `#[warn(lint)]`,
+/// or `#[deny(lint)]` on an architecture or one of its functions, the function taking precedence,
+/// or for the whole design in the config file. `synthesizability` in place of a lint's name sets
+/// all of them.
+pub struct SynthesisLinter<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> SynthesisLinter<'a, 'ast> {
+    pub fn check(&mut self) {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let mut arches = resolution_graph
+            .node_indices()
+            .filter(|node| resolution_graph[*node].is_entity())
+            .flat_map(|entity| resolution_graph.architectures(entity))
+            .copied()
+            .collect::<Vec<_>>();
+        arches.sort_by_key(|arch| Into::<usize>::into(*arch));
+        for arch in arches {
+            let file = resolution_graph.file(arch);
+            let arch_attrs = &item_arch(resolution_graph, arch).attrs;
+            let mut fn_collector = FnCollector { fns: vec![] };
+            resolution_graph[arch].visit(&mut fn_collector);
+            let fns = fn_collector.fns;
+
+            let (calls, lints): (Vec<_>, Vec<_>) = fns
+                .iter()
+                .map(|&item_fn| {
+                    let mut linter = FnLinter {
+                        file,
+                        fns: &fns,
+                        calls: vec![],
+                        lints: vec![],
+                    };
+                    linter.visit_block(&item_fn.block);
+                    (linter.calls, linter.lints)
+                })
+                .unzip();
+
+            for (i, (item_fn, mut lints)) in fns.iter().zip(lints).enumerate() {
+                for (callee, ident, span) in calls[i].iter() {
+                    if *callee == i || reaches(&calls, *callee, i) {
+                        lints.push(("recursion", recursion(file, *span, ident)));
+                    }
+                }
+                for (lint, mut diagnostic) in lints {
+                    match level(&item_fn.attrs, arch_attrs, lint, &self.resolver.lint_levels) {
+                        LintLevel::Allow => continue,
+                        LintLevel::Warn => {}
+                        LintLevel::Deny => diagnostic.severity = Severity::Error,
+                    }
+                    self.errors.push(diagnostic);
+                }
+            }
+        }
+    }
+}
+
+/// The level of a lint, set on a function or else its architecture, or else for the design
+fn level(
+    fn_attrs: &[Attribute],
+    arch_attrs: &[Attribute],
+    lint: &str,
+    lint_levels: &LintLevels,
+) -> LintLevel {
+    [fn_attrs, arch_attrs]
+        .iter()
+        .find_map(|attrs| {
+            attribute::lint_level(attrs, lint).or_else(|| attribute::lint_level(attrs, GROUP))
+        })
+        .or_else(|| {
+            lint_levels
+                .get(lint)
+                .or_else(|| lint_levels.get(GROUP))
+                .copied()
+        })
+        .unwrap_or(LintLevel::Warn)
+}
+
+/// Whether a function of an architecture calls another, directly or indirectly
+fn reaches(calls: &[Vec<(usize, &Ident, Span)>], from: usize, to: usize) -> bool {
+    let mut visited = vec![false; calls.len()];
+    let mut stack = vec![from];
+    while let Some(caller) = stack.pop() {
+        for (callee, _, _) in calls[caller].iter() {
+            if *callee == to {
+                return true;
+            }
+            if !visited[*callee] {
+                visited[*callee] = true;
+                stack.push(*callee);
+            }
+        }
+    }
+    false
+}
+
+/// Finds the lints in the body of a function of an architecture
+struct FnLinter<'a, 'ast> {
+    file: FileId,
+    /// The functions of the architecture
+    fns: &'a [&'ast ItemFn],
+    /// The index of each function of the architecture called, and the call
+    calls: Vec<(usize, &'ast Ident, Span)>,
+    lints: Vec<(&'static str, Diagnostic)>,
+}
+
+impl<'a, 'ast> Visit<'ast> for FnLinter<'a, 'ast> {
+    fn visit_expr_while(&mut self, expr_while: &'ast ExprWhile) {
+        self.lints.push((
+            "unbounded_loop",
+            unbounded_loop(self.file, expr_while.span()),
+        ));
+        self.visit_expr(&expr_while.cond);
+        self.visit_block(&expr_while.body);
+    }
+
+    fn visit_expr_loop(&mut self, expr_loop: &'ast ExprLoop) {
+        self.lints.push((
+            "unbounded_loop",
+            unbounded_loop(self.file, expr_loop.span()),
+        ));
+        self.visit_block(&expr_loop.body);
+    }
+
+    fn visit_expr_for_loop(&mut self, expr_for_loop: &'ast ExprForLoop) {
+        // ranges of unknown length are only known once generics are elaborated
+        let bounded = match &*expr_for_loop.expr {
+            Expr::Range(expr_range) => expr_range.to.is_some(),
+            _ => true,
+        };
+        if !bounded {
+            self.lints.push((
+                "unbounded_loop",
+                unbounded_loop(self.file, expr_for_loop.span()),
+            ));
+        }
+        self.visit_expr(&expr_for_loop.expr);
+        self.visit_block(&expr_for_loop.body);
+    }
+
+    fn visit_expr_call(&mut self, expr_call: &'ast ExprCall) {
+        if let Some(callee) = single_ident(&expr_call.func) {
+            match self.fns.iter().position(|item_fn| item_fn.ident == *callee) {
+                Some(index) => self.calls.push((index, callee, expr_call.span())),
+                None => {
+                    if let Some(name) = DELAYS.iter().find(|name| *callee == **name) {
+                        self.lints
+                            .push(("delay", delay(self.file, expr_call.span(), name)));
+                    }
+                }
+            }
+        }
+        self.visit_expr(&expr_call.func);
+        for arg in expr_call.args.iter() {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_expr_macro(&mut self, expr_macro: &'ast ExprMacro) {
+        let is_named = |name: &&&str| attribute::is_named(&expr_macro.path, name);
+        if let Some(name) = DELAYS.iter().find(is_named) {
+            self.lints.push((
+                "delay",
+                delay(self.file, expr_macro.span(), &format!("{}!", name)),
+            ));
+        } else if let Some(name) = HEAP_MACROS.iter().find(is_named) {
+            self.lints.push((
+                "heap_allocation",
+                heap_allocation(self.file, expr_macro.span(), &format!("{}!", name)),
+            ));
+        }
+        for arg in expr_macro.args.iter() {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_expr_path(&mut self, expr_path: &'ast ExprPath) {
+        self.visit_type_path(&expr_path.path);
+    }
+
+    fn visit_type_path(&mut self, type_path: &'ast TypePath) {
+        for segment in type_path.segments.iter() {
+            let name = segment.ident.to_string();
+            if is_float(&name) {
+                self.lints.push((
+                    "floating_point",
+                    floating_point(self.file, segment.ident.span(), &name),
+                ));
+            }
+            if let Some(generic_args) = &segment.generic_args {
+                for arg in generic_args.args.iter() {
+                    match arg {
+                        GenericArg::Type(ty) => self.visit_type(ty),
+                        GenericArg::Const(expr) => self.visit_expr(expr),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Checks the timing constraints declared on clocks with `#[clock(freq = "100MHz")]`,
+//! which are emitted with `--emit sdc`.
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::Span;
+
+use super::signal_graph::{SignalGraph, SignalIndex};
+use crate::attribute;
+use crate::error::*;
+use crate::find_file::FileId;
+
+/// Checks that clock frequencies are valid, and that the signals of a clock net don't
+/// declare different ones
+pub struct TimingChecker<'a, 'ast> {
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> TimingChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let graph = self.graph;
+        // the first frequency declared for each clock net
+        let mut frequencies: HashMap<SignalIndex, (FileId, Span, f64)> = HashMap::default();
+        // the signals of entities instantiated more than once share their attributes
+        let mut reported: Vec<(FileId, Span)> = vec![];
+        for signal in graph.signal_indices() {
+            let (span, frequency) = match attribute::clock_frequency(graph[signal].attrs) {
+                Some(clock_frequency) => clock_frequency,
+                None => continue,
+            };
+            let file = graph[signal].file;
+            let frequency = match frequency {
+                Some(frequency) => frequency,
+                None => {
+                    if !reported.contains(&(file, span)) {
+                        reported.push((file, span));
+                        self.errors.push(invalid_clock_frequency(file, span));
+                    }
+                    continue;
+                }
+            };
+            let root = graph.net_root(signal);
+            match frequencies.get(&root) {
+                Some((first_file, first_span, first))
+                    if *first != frequency && !reported.contains(&(file, span)) =>
+                {
+                    reported.push((file, span));
+                    self.errors.push(conflicting_clock_frequencies(
+                        file,
+                        span,
+                        *first_file,
+                        *first_span,
+                        graph[root].ident,
+                    ))
+                }
+                Some(_) => {}
+                None => {
+                    frequencies.insert(root, (file, span, frequency));
+                }
+            }
+        }
+    }
+}
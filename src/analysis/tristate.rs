@@ -0,0 +1,507 @@
+//! Tri-state buses, signals marked `#[tristate]` that several functions or instances can drive as
+//! long as each of them only does so while it's enabled, leaving the signal high-impedance
+//! otherwise:
+//! * a signal driven by more than one function or instance is an error, unless it's a tri-state
+//!   bus and every driver can be disabled: a function that only assigns it under a condition, or
+//!   an instance that drives it from a port that is itself marked `#[tristate]`
+//! * two functions whose enables can hold at the same time drive the bus against each other.
+//!   Enables are compared where they are comparisons of signals with literals, like `sel == 2`,
+//!   `en`, or `!en`, and conjunctions of those. Others aren't assumed to overlap.
+//! * `deep_inout`: bidirectional ports only reach an I/O buffer through the ports of every
+//!   instance above them, so they're denied below the instances of the top
+//!
+//! Assignments to an element or a field of a signal only drive part of it, so they aren't counted.
+use codespan_reporting::diagnostic::Severity;
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{
+    BinOp, Expr, ExprAssign, ExprAssignOp, ExprIf, ExprMatch, Ident, Lit, Local, Pat, Span,
+    Spanned, UnOp,
+};
+use rhdl::visit::Visit;
+
+use super::signal_graph::{DriverRole, SignalGraph, SignalIndex, SignalKind};
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::{const_eval::parse_int, item_entity, Design, InstanceIndex};
+use crate::error::*;
+use crate::find_file::FileId;
+use crate::ir::process::{clocked_if, single_ident, FnCollector};
+use crate::resolution::{ResolutionIndex, Resolver};
+
+const DEEP_INOUT: &str = "deep_inout";
+
+/// Checks the drivers of the signals of each elaborated architecture, and that bidirectional
+/// ports are close enough to the top.
+///
+/// Bidirectional ports further down can be allowed with `#[allow(deep_inout)]` on the port
+/// or its entity, or in the config file.
+pub struct TristateChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub design: &'a Design<'ast>,
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+/// A function or instance that drives a signal
+struct Source {
+    span: Span,
+    /// Whether it can leave the signal undriven
+    tristate: bool,
+    /// The assignments of a function, empty for an instance
+    assignments: Vec<Assignment>,
+}
+
+/// An assignment of a whole signal and the enables it's under
+struct Assignment {
+    span: Span,
+    clocked: bool,
+    /// Whether it's under any condition
+    conditional: bool,
+    /// What the conditions around it say about signals, and whether that's all they say
+    literals: Vec<Literal>,
+    exact: bool,
+}
+
+/// `signal == value`, or `signal != value` if not `equal`
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Literal {
+    signal: SignalIndex,
+    value: i128,
+    equal: bool,
+}
+
+impl<'a, 'ast> TristateChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let resolver = self.resolver;
+        let resolution_graph = &resolver.resolution_graph;
+        // the drivers of a signal don't depend on the generics of its instance
+        let mut checked_arches = HashSet::default();
+        let mut reported_ports = vec![];
+        for instance in self.design.instance_indices() {
+            self.check_depth(instance, &mut reported_ports);
+            let arch = match self.design[instance].arch {
+                Some(arch) if checked_arches.insert(arch) => arch,
+                _ => continue,
+            };
+            let file = resolution_graph.file(arch);
+            let sources = self.sources(instance, arch);
+            for signal in self.signals(instance) {
+                let sources = match sources.iter().find(|(target, _)| *target == signal) {
+                    Some((_, sources)) if sources.len() > 1 => sources,
+                    _ => continue,
+                };
+                let ident = self.graph[signal].ident;
+                if attribute::find_flag(self.graph[signal].attrs, "tristate").is_none() {
+                    let spans = sources.iter().map(|source| source.span).collect::<Vec<_>>();
+                    self.errors.push(multiple_drivers(file, ident, &spans));
+                    continue;
+                }
+                let mut always_enabled = false;
+                for source in sources.iter().filter(|source| !source.tristate) {
+                    always_enabled = true;
+                    self.errors.push(always_driven(
+                        file,
+                        ident,
+                        source.span,
+                        source.assignments.is_empty(),
+                    ));
+                }
+                if always_enabled {
+                    continue;
+                }
+                for (i, first) in sources.iter().enumerate() {
+                    for second in sources[i + 1..].iter() {
+                        if let Some((a, b)) = contention(self.graph, first, second) {
+                            self.errors.push(tristate_contention(file, ident, a, b));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn signals(&self, instance: InstanceIndex) -> impl Iterator<Item = SignalIndex> + 'a {
+        self.graph
+            .instance_signals
+            .get(&instance)
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+
+    /// Denies bidirectional ports of instances below those of the top
+    fn check_depth(&mut self, instance: InstanceIndex, reported: &mut Vec<(FileId, Span)>) {
+        let mut depth = 0;
+        let mut parent = self.design[instance].parent;
+        while let Some(index) = parent {
+            depth += 1;
+            parent = self.design[index].parent;
+        }
+        let instantiation = match self.design[instance].instantiation {
+            Some(instantiation) if depth >= 2 => instantiation,
+            _ => return,
+        };
+        let item_entity = item_entity(self.resolver, self.design[instance].entity);
+        for port in self.signals(instance) {
+            if self.graph[port].kind != SignalKind::InOut {
+                continue;
+            }
+            let level = [self.graph[port].attrs, &item_entity.attrs[..]]
+                .iter()
+                .find_map(|attrs| attribute::lint_level(attrs, DEEP_INOUT))
+                .or_else(|| self.resolver.lint_levels.get(DEEP_INOUT).copied())
+                .unwrap_or(LintLevel::Deny);
+            let ident = self.graph[port].ident;
+            let span = instantiation
+                .expr
+                .fields
+                .iter()
+                .find(|field_value| field_value.ident == *ident)
+                .map_or_else(
+                    || instantiation.expr.span(),
+                    |field_value| field_value.span(),
+                );
+            // instances made in a loop share their instantiation
+            if level == LintLevel::Allow || reported.contains(&(instantiation.file, span)) {
+                continue;
+            }
+            reported.push((instantiation.file, span));
+            let mut diagnostic =
+                deep_inout(instantiation.file, span, ident, &item_entity.ident, depth);
+            if level == LintLevel::Warn {
+                diagnostic.severity = Severity::Warning;
+            }
+            self.errors.push(diagnostic);
+        }
+    }
+
+    /// The functions and instances that drive each signal of an instance
+    fn sources(
+        &self,
+        instance: InstanceIndex,
+        arch: ResolutionIndex,
+    ) -> Vec<(SignalIndex, Vec<Source>)> {
+        let mut sources = vec![];
+        let mut fn_collector = FnCollector { fns: vec![] };
+        self.resolver.resolution_graph[arch].visit(&mut fn_collector);
+        for item_fn in fn_collector.fns {
+            let mut finder = AssignmentFinder {
+                graph: self.graph,
+                instance,
+                locals: vec![],
+                clocked: false,
+                conditions: vec![],
+                assignments: vec![],
+            };
+            finder.visit_block(&item_fn.block);
+            let mut assigned: Vec<(SignalIndex, Vec<Assignment>)> = vec![];
+            for (signal, assignment) in finder.assignments {
+                // locals of the same name in other functions are other signals
+                if self.graph[signal].kind == SignalKind::Local
+                    && !finder
+                        .locals
+                        .iter()
+                        .any(|local| std::ptr::eq(*local, self.graph[signal].ident))
+                {
+                    continue;
+                }
+                match assigned.iter_mut().find(|(target, _)| *target == signal) {
+                    Some((_, assignments)) => assignments.push(assignment),
+                    None => assigned.push((signal, vec![assignment])),
+                }
+            }
+            for (signal, assignments) in assigned {
+                add_source(
+                    &mut sources,
+                    signal,
+                    Source {
+                        span: assignments[0].span,
+                        tristate: assignments
+                            .iter()
+                            .all(|assignment| assignment.conditional && !assignment.clocked),
+                        assignments,
+                    },
+                );
+            }
+        }
+
+        // instances made in a loop share their instantiation
+        let mut instantiations = vec![];
+        for child in self.design[instance].children.iter().copied() {
+            let instantiation = match self.design[child].instantiation {
+                Some(instantiation) => instantiation,
+                None => continue,
+            };
+            if instantiations
+                .iter()
+                .any(|expr| std::ptr::eq(*expr, instantiation.expr))
+            {
+                continue;
+            }
+            instantiations.push(instantiation.expr);
+            for field_value in instantiation.expr.fields.iter() {
+                let port = match self.graph.lookup(child, &field_value.ident) {
+                    Some(port) => port,
+                    None => continue,
+                };
+                let drives = match self.graph[port].kind {
+                    SignalKind::Output => true,
+                    SignalKind::InOut => self
+                        .graph
+                        .drivers_of(port)
+                        .any(|driver| driver.role != DriverRole::Connection),
+                    SignalKind::Input | SignalKind::Local => false,
+                };
+                let ident = match &field_value.expr {
+                    Some((_, expr)) => single_ident(expr),
+                    None => Some(&field_value.ident),
+                };
+                let signal = match ident.and_then(|ident| self.graph.lookup(instance, ident)) {
+                    Some(signal) if drives => signal,
+                    _ => continue,
+                };
+                add_source(
+                    &mut sources,
+                    signal,
+                    Source {
+                        span: field_value.span(),
+                        tristate: attribute::find_flag(self.graph[port].attrs, "tristate")
+                            .is_some(),
+                        assignments: vec![],
+                    },
+                );
+            }
+        }
+        sources
+    }
+}
+
+fn add_source(sources: &mut Vec<(SignalIndex, Vec<Source>)>, signal: SignalIndex, source: Source) {
+    match sources.iter_mut().find(|(target, _)| *target == signal) {
+        Some((_, signal_sources)) => signal_sources.push(source),
+        None => sources.push((signal, vec![source])),
+    }
+}
+
+/// The literals that all hold if a condition does, or `None` if it isn't made of them
+fn literals(
+    graph: &SignalGraph,
+    instance: InstanceIndex,
+    cond: &Expr,
+    holds: bool,
+) -> Option<Vec<Literal>> {
+    match cond {
+        Expr::Paren(expr_paren) => literals(graph, instance, &expr_paren.expr, holds),
+        Expr::Unary(expr_unary) if matches!(expr_unary.op, UnOp::Not(_)) => {
+            literals(graph, instance, &expr_unary.expr, !holds)
+        }
+        Expr::Binary(expr_binary) if holds && matches!(expr_binary.op, BinOp::And(_)) => {
+            let mut both = literals(graph, instance, &expr_binary.left, true)?;
+            both.extend(literals(graph, instance, &expr_binary.right, true)?);
+            Some(both)
+        }
+        Expr::Binary(expr_binary) if !holds && matches!(expr_binary.op, BinOp::Or(_)) => {
+            let mut both = literals(graph, instance, &expr_binary.left, false)?;
+            both.extend(literals(graph, instance, &expr_binary.right, false)?);
+            Some(both)
+        }
+        _ => literal(graph, instance, cond, holds).map(|literal| vec![literal]),
+    }
+}
+
+/// `signal == value` or its negation, for a condition that holds if `holds`
+fn literal(
+    graph: &SignalGraph,
+    instance: InstanceIndex,
+    cond: &Expr,
+    holds: bool,
+) -> Option<Literal> {
+    let (operand, value, equal) = match cond {
+        Expr::Path(_) => (cond, 1, true),
+        Expr::Binary(expr_binary) => {
+            let equal = match expr_binary.op {
+                BinOp::Eq(_) => true,
+                BinOp::Ne(_) => false,
+                _ => return None,
+            };
+            match (lit_value(&expr_binary.left), lit_value(&expr_binary.right)) {
+                (None, Some(value)) => (&*expr_binary.left, value, equal),
+                (Some(value), None) => (&*expr_binary.right, value, equal),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    let signal = graph.lookup(instance, single_ident(operand)?)?;
+    // a bit that isn't one value is the other
+    if equal != holds && graph[signal].width == Some(1) {
+        return Some(Literal {
+            signal,
+            value: 1 - value,
+            equal: true,
+        });
+    }
+    Some(Literal {
+        signal,
+        value,
+        equal: equal == holds,
+    })
+}
+
+fn lit_value(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Lit(expr_lit) => match &expr_lit.lit {
+            Lit::Int(lit_int) => parse_int(&lit_int.inner),
+            Lit::Bool(lit_bool) => Some(lit_bool.value as i128),
+            _ => None,
+        },
+        Expr::Paren(expr_paren) => lit_value(&expr_paren.expr),
+        _ => None,
+    }
+}
+
+/// Assignments of two functions that drive a bus at the same time, if their enables are exactly
+/// known and can hold together
+fn contention(graph: &SignalGraph, first: &Source, second: &Source) -> Option<(Span, Span)> {
+    first.assignments.iter().find_map(|a| {
+        second
+            .assignments
+            .iter()
+            .find(|b| {
+                let literals = a
+                    .literals
+                    .iter()
+                    .chain(b.literals.iter())
+                    .copied()
+                    .collect::<Vec<_>>();
+                a.exact && b.exact && satisfiable(graph, &literals)
+            })
+            .map(|b| (a.span, b.span))
+    })
+}
+
+/// Whether some values of the signals make every literal hold
+fn satisfiable(graph: &SignalGraph, literals: &[Literal]) -> bool {
+    literals.iter().all(|literal| {
+        let mut others = literals
+            .iter()
+            .filter(|other| other.signal == literal.signal);
+        if literal.equal {
+            return others.all(|other| (other.value == literal.value) == other.equal);
+        }
+        // a value is left unless every one of them is excluded
+        let excluded = others
+            .filter(|other| !other.equal)
+            .map(|other| other.value)
+            .collect::<HashSet<_>>()
+            .len() as u128;
+        match graph[literal.signal].width {
+            Some(width) if width < 128 => excluded < 1 << width,
+            _ => true,
+        }
+    })
+}
+
+/// Collects the assignments of whole signals in a function and the conditions they're under
+struct AssignmentFinder<'g, 'ast> {
+    graph: &'g SignalGraph<'ast>,
+    instance: InstanceIndex,
+    /// The locals declared in the function
+    locals: Vec<&'ast Ident>,
+    clocked: bool,
+    /// The literals of each condition around the current statement,
+    /// `None` for one that isn't made of them
+    conditions: Vec<Option<Vec<Literal>>>,
+    assignments: Vec<(SignalIndex, Assignment)>,
+}
+
+impl<'g, 'ast> AssignmentFinder<'g, 'ast> {
+    fn assign(&mut self, left: &'ast Expr, span: Span) {
+        if let Some(signal) =
+            single_ident(left).and_then(|ident| self.graph.lookup(self.instance, ident))
+        {
+            let assignment = self.assignment(span);
+            self.assignments.push((signal, assignment));
+        }
+    }
+
+    fn assignment(&self, span: Span) -> Assignment {
+        Assignment {
+            span,
+            clocked: self.clocked,
+            conditional: !self.conditions.is_empty(),
+            literals: self
+                .conditions
+                .iter()
+                .flatten()
+                .flatten()
+                .copied()
+                .collect(),
+            exact: self.conditions.iter().all(Option::is_some),
+        }
+    }
+
+    fn with_condition<F>(&mut self, condition: Option<Vec<Literal>>, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.conditions.push(condition);
+        f(self);
+        self.conditions.pop();
+    }
+}
+
+impl<'g, 'ast> Visit<'ast> for AssignmentFinder<'g, 'ast> {
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let Pat::Ident(pat_ident) = &local.pat {
+            self.locals.push(pat_ident);
+            let signal = self.graph.lookup(self.instance, pat_ident);
+            if let (Some(signal), Some(_)) = (signal, &local.init) {
+                let assignment = self.assignment(local.span());
+                self.assignments.push((signal, assignment));
+            }
+        }
+        if let Some((_, init)) = &local.init {
+            self.visit_expr(init);
+        }
+    }
+
+    fn visit_expr_assign(&mut self, expr_assign: &'ast ExprAssign) {
+        self.assign(&expr_assign.left, expr_assign.span());
+        self.visit_expr(&expr_assign.right);
+    }
+
+    fn visit_expr_assign_op(&mut self, expr_assign_op: &'ast ExprAssignOp) {
+        self.assign(&expr_assign_op.left, expr_assign_op.span());
+        self.visit_expr(&expr_assign_op.right);
+    }
+
+    fn visit_expr_if(&mut self, expr_if: &'ast ExprIf) {
+        if let Some(clocked_if) = clocked_if(expr_if) {
+            let outer = self.clocked;
+            self.clocked = true;
+            if let Some((_, _, reset_block)) = clocked_if.reset {
+                self.visit_block(reset_block);
+            }
+            self.visit_block(clocked_if.body);
+            self.clocked = outer;
+            return;
+        }
+        let (graph, instance) = (self.graph, self.instance);
+        self.with_condition(literals(graph, instance, &expr_if.cond, true), |finder| {
+            finder.visit_block(&expr_if.then_branch)
+        });
+        if let Some((_, else_branch)) = &expr_if.else_branch {
+            self.with_condition(literals(graph, instance, &expr_if.cond, false), |finder| {
+                finder.visit_expr(else_branch)
+            });
+        }
+    }
+
+    fn visit_expr_match(&mut self, expr_match: &'ast ExprMatch) {
+        self.with_condition(None, |finder| {
+            for arm in expr_match.arms.iter() {
+                finder.visit_expr(&arm.body);
+            }
+        });
+    }
+}
@@ -0,0 +1,203 @@
+//! `unread_signal` and `unread_port`: locals of an architecture that are assigned but never read,
+//! and inputs of an entity that none of its architectures read, which are usually left over from
+//! a change or connected by mistake. Like unused variables in Rust, names starting with `_` aren't
+//! reported.
+use codespan_reporting::diagnostic::Severity;
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{
+    Attribute, Expr, ExprAssign, ExprAssignOp, ExprLit, ExprMacro, ExprPath, ExprStruct, Lit,
+    PortDir,
+};
+use rhdl::visit::Visit;
+
+use super::signal_graph::{SignalGraph, SignalKind};
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::{item_entity, Design};
+use crate::error::*;
+use crate::ir::lower::{parse_message, Piece};
+use crate::resolution::{item_arch, ResolutionIndex, Resolver};
+
+/// Warns about the signals of the elaborated architectures that are assigned but never read, and
+/// the inputs of their entities that no architecture reads. Entities whose architectures are all
+/// empty are still being written, so their inputs aren't linted.
+///
+/// The warnings can be set to another level with `#[allow(unread_signal)]` on the local or the
+/// architecture, `#[allow(unread_port)]` on the port or the entity, or in the config file.
+pub struct UnreadChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub design: &'a Design<'ast>,
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> UnreadChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let resolver = self.resolver;
+        let resolution_graph = &resolver.resolution_graph;
+        let graph = self.graph;
+        // instances of the same architecture share their locals' declarations
+        let mut checked_arches = HashSet::default();
+        let mut checked_entities = HashSet::default();
+        for instance in self.design.instance_indices() {
+            let entity = self.design[instance].entity;
+            if resolution_graph.is_extern(entity) {
+                continue;
+            }
+            if checked_entities.insert(entity) {
+                self.check_ports(entity);
+            }
+            let arch = match self.design[instance].arch {
+                Some(arch) if checked_arches.insert(arch) => arch,
+                _ => continue,
+            };
+            let arch_attrs: &[Attribute] = &item_arch(resolution_graph, arch).attrs;
+            let read = read_names(resolver, arch);
+            for signal in graph.instance_signals.get(&instance).into_iter().flatten() {
+                let local = &graph[*signal];
+                let name = local.ident.to_string();
+                let is_assigned = graph.drivers_of(*signal).next().is_some();
+                if local.kind != SignalKind::Local
+                    || !is_assigned
+                    || name.starts_with('_')
+                    || read.contains(&name)
+                {
+                    continue;
+                }
+                self.report(
+                    "unread_signal",
+                    &[local.attrs, arch_attrs],
+                    unread_signal(local.file, local.ident),
+                );
+            }
+        }
+    }
+
+    fn check_ports(&mut self, entity: ResolutionIndex) {
+        let resolver = self.resolver;
+        let resolution_graph = &resolver.resolution_graph;
+        let arches = resolution_graph.architectures(entity);
+        let is_empty = |arch: &ResolutionIndex| {
+            resolution_graph[*arch]
+                .children()
+                .map_or(true, |children| children.is_empty())
+        };
+        if arches.iter().all(is_empty) {
+            return;
+        }
+        let read = arches
+            .iter()
+            .flat_map(|arch| read_names(resolver, *arch))
+            .collect::<HashSet<_>>();
+        let item_entity = item_entity(resolver, entity);
+        let file = resolution_graph.file(entity);
+        for port in item_entity.ports.iter() {
+            let name = port.ident.to_string();
+            if !matches!(port.dir, PortDir::In(_)) || name.starts_with('_') || read.contains(&name)
+            {
+                continue;
+            }
+            self.report(
+                "unread_port",
+                &[&port.attrs[..], &item_entity.attrs[..]],
+                unread_port(file, &port.ident, &item_entity.ident),
+            );
+        }
+    }
+
+    /// Reports `diagnostic` at the level of `lint` set by the innermost of `attrs` that sets it,
+    /// or else by the config file
+    fn report(&mut self, lint: &str, attrs: &[&[Attribute]], mut diagnostic: Diagnostic) {
+        let level = attrs
+            .iter()
+            .find_map(|attrs| attribute::lint_level(attrs, lint))
+            .or_else(|| self.resolver.lint_levels.get(lint).copied())
+            .unwrap_or(LintLevel::Warn);
+        match level {
+            LintLevel::Allow => return,
+            LintLevel::Warn => {}
+            LintLevel::Deny => diagnostic.severity = Severity::Error,
+        }
+        self.errors.push(diagnostic);
+    }
+}
+
+/// The names that are read in `arch`, which are every name in it except those that are only
+/// assigned to
+fn read_names(resolver: &Resolver, arch: ResolutionIndex) -> HashSet<String> {
+    let mut reader = NameReader {
+        names: HashSet::default(),
+    };
+    resolver.resolution_graph[arch].visit(&mut reader);
+    reader.names
+}
+
+struct NameReader {
+    names: HashSet<String>,
+}
+
+impl NameReader {
+    /// The left hand side of an assignment, where only indices are read, i.e. `i` in `q[i] = d`
+    fn visit_target<'ast>(&mut self, left: &'ast Expr) {
+        match left {
+            Expr::Path(_) => {}
+            Expr::Index(expr_index) => {
+                self.visit_target(&expr_index.expr);
+                self.visit_expr(&expr_index.index);
+            }
+            Expr::Field(expr_field) => self.visit_target(&expr_field.base),
+            Expr::Paren(expr_paren) => self.visit_target(&expr_paren.expr),
+            other => self.visit_expr(other),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for NameReader {
+    fn visit_expr_path(&mut self, expr_path: &'ast ExprPath) {
+        let path = &expr_path.path;
+        if path.leading_sep.is_none() && path.segments.len() == 1 {
+            let segment = path.segments.first().unwrap();
+            self.names.insert(segment.ident.to_string());
+        }
+    }
+
+    fn visit_expr_assign(&mut self, expr_assign: &'ast ExprAssign) {
+        self.visit_target(&expr_assign.left);
+        self.visit_expr(&expr_assign.right);
+    }
+
+    fn visit_expr_assign_op(&mut self, expr_assign_op: &'ast ExprAssignOp) {
+        self.visit_target(&expr_assign_op.left);
+        self.visit_expr(&expr_assign_op.right);
+    }
+
+    /// `Counter { clk }` connects `clk` to the instance, which might read it
+    fn visit_expr_struct(&mut self, expr_struct: &'ast ExprStruct) {
+        for field_value in expr_struct.fields.iter() {
+            match &field_value.expr {
+                Some((_, expr)) => self.visit_expr(expr),
+                None => {
+                    self.names.insert(field_value.ident.to_string());
+                }
+            }
+        }
+    }
+
+    /// The signals named in the message of a property are read when it fails
+    fn visit_expr_macro(&mut self, expr_macro: &'ast ExprMacro) {
+        for arg in expr_macro.args.iter() {
+            if let Expr::Lit(ExprLit {
+                lit: Lit::Str(lit_str),
+                ..
+            }) = arg
+            {
+                let pieces = parse_message(&lit_str.inner).unwrap_or_default();
+                for piece in pieces {
+                    if let Piece::Named(name) = piece {
+                        self.names.insert(name.to_string());
+                    }
+                }
+            }
+            self.visit_expr(arg);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! `rhdlc api-diff`: how the public API of a design changed between two revisions of it.
+//!
+//! The items exported by each revision (see [`Resolver::exports`]) are matched by path. Removing
+//! an item or changing its kind or signature is breaking, as is adding a field or variant, since
+//! struct literals and exhaustive matches stop compiling. Adding any other item is additive. The
+//! signatures of entities are compared port by port.
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use rhdl::ast::{PortDir, Spanned};
+
+use crate::error::{write_output, Diagnostic};
+use crate::find_file::{FileContentProvider, FileFinder, FileGraph};
+use crate::resolution::{context, Branch, Leaf, ResolutionNode, Resolver, SymbolKind};
+use crate::status::Status;
+use crate::{has_error_severity, report, sort_diagnostics, Options};
+
+/// `rhdlc api-diff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiDiff {
+    /// The root file of the old revision, which the design is compared against
+    pub old: PathBuf,
+}
+
+/// The items exported by a design, by path
+#[derive(Debug, Default)]
+pub struct Api {
+    items: BTreeMap<String, Vec<ApiItem>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ApiItem {
+    kind: SymbolKind,
+    /// The source of the item with its whitespace collapsed, or the type of a field. Empty for
+    /// the items whose members are exported on their own.
+    signature: String,
+    /// The ports of an entity, as `in clk: bool`
+    ports: Vec<(String, String)>,
+}
+
+impl Api {
+    pub fn of(resolver: &Resolver, file_graph: &FileGraph) -> Self {
+        let mut api = Self::default();
+        for export in resolver.exports() {
+            let node = &resolver.resolution_graph[export.node];
+            let ports = match node {
+                ResolutionNode::Branch {
+                    branch: Branch::Entity(item_entity),
+                    ..
+                } => {
+                    let file = resolver.resolution_graph.file(export.node);
+                    let source = file_graph[file].as_ref();
+                    item_entity
+                        .ports
+                        .iter()
+                        .map(|port| {
+                            let dir = match port.dir {
+                                PortDir::In(_) => "in",
+                                PortDir::Out(_) => "out",
+                                PortDir::InOut(_) => "inout",
+                            };
+                            let ty: std::ops::Range<usize> = port.ty.span().into();
+                            (
+                                port.ident.to_string(),
+                                format!("{} {}: {}", dir, port.ident, collapse(&source[ty])),
+                            )
+                        })
+                        .collect()
+                }
+                _ => vec![],
+            };
+            let signature = match node {
+                ResolutionNode::Leaf {
+                    leaf: Leaf::NamedField(field),
+                    ..
+                } => {
+                    let file = resolver.resolution_graph.file(export.node);
+                    let ty: std::ops::Range<usize> = field.ty.span().into();
+                    collapse(&file_graph[file].as_ref()[ty])
+                }
+                _ if matches!(
+                    export.kind,
+                    SymbolKind::Mod | SymbolKind::Struct | SymbolKind::Enum | SymbolKind::Entity
+                ) =>
+                {
+                    String::new()
+                }
+                _ => resolver
+                    .describe(export.node)
+                    .map(|hover| collapse(&hover.signature))
+                    .unwrap_or_default(),
+            };
+            api.items.entry(export.path).or_default().push(ApiItem {
+                kind: export.kind,
+                signature,
+                ports,
+            });
+        }
+        api
+    }
+}
+
+/// The source of a signature on one line, without comments
+fn collapse(source: &str) -> String {
+    source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A change to an item of the public API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let class = if self.breaking {
+            "breaking"
+        } else {
+            "additive"
+        };
+        write!(f, "{}: {}", class, self.description)
+    }
+}
+
+/// The changes from `old` to `new`, ordered by the paths of the items
+pub fn diff(old: &Api, new: &Api) -> Vec<Change> {
+    let mut changes = vec![];
+    let none = vec![];
+    let mut paths = old.items.keys().chain(new.items.keys()).collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        let old_items = old.items.get(path).unwrap_or(&none);
+        let new_items = new.items.get(path).unwrap_or(&none);
+        let removed = old_items
+            .iter()
+            .filter(|old| !new_items.iter().any(|new| new.kind == old.kind))
+            .collect::<Vec<_>>();
+        let added = new_items
+            .iter()
+            .filter(|new| !old_items.iter().any(|old| old.kind == new.kind))
+            .collect::<Vec<_>>();
+        if let ([old], [new]) = (removed.as_slice(), added.as_slice()) {
+            changes.push(Change {
+                breaking: true,
+                description: format!(
+                    "changed {} from {} to {}",
+                    path,
+                    old.kind.name(),
+                    new.kind.name()
+                ),
+            });
+        } else {
+            for old in removed {
+                changes.push(Change {
+                    breaking: true,
+                    description: format!("removed {} {}", old.kind.name(), path),
+                });
+            }
+            for new in added {
+                changes.push(Change {
+                    breaking: matches!(new.kind, SymbolKind::Field | SymbolKind::Variant),
+                    description: format!("added {} {}", new.kind.name(), path),
+                });
+            }
+        }
+        for old in old_items {
+            if let Some(new) = new_items.iter().find(|new| new.kind == old.kind) {
+                changes.extend(item_changes(path, old, new));
+            }
+        }
+    }
+    changes
+}
+
+/// The changes to the signature and ports of an item that's in both revisions
+fn item_changes(path: &str, old: &ApiItem, new: &ApiItem) -> Vec<Change> {
+    let mut changes = vec![];
+    if old.signature != new.signature {
+        changes.push(Change {
+            breaking: true,
+            description: format!(
+                "changed {} {} from `{}` to `{}`",
+                old.kind.name(),
+                path,
+                old.signature,
+                new.signature
+            ),
+        });
+    }
+    for (name, old_port) in old.ports.iter() {
+        match new.ports.iter().find(|(new_name, _)| new_name == name) {
+            None => changes.push(Change {
+                breaking: true,
+                description: format!("removed port `{}` of entity {}", old_port, path),
+            }),
+            Some((_, new_port)) if new_port != old_port => changes.push(Change {
+                breaking: true,
+                description: format!(
+                    "changed port `{}` of entity {} to `{}`",
+                    old_port, path, new_port
+                ),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (name, new_port) in new.ports.iter() {
+        if !old.ports.iter().any(|(old_name, _)| old_name == name) {
+            // instances have to connect it
+            changes.push(Change {
+                breaking: true,
+                description: format!("added port `{}` to entity {}", new_port, path),
+            });
+        }
+    }
+    changes
+}
+
+/// The API of the design at `root`, or the diagnostics that kept it from being resolved and the
+/// status to exit with for them
+pub fn api_at(root: &Path, options: &Options) -> Result<Api, (String, Status)> {
+    let mut finder = FileFinder::default();
+    finder.lossy_utf8 = options.lossy_utf8;
+    finder.max_file_size = options.max_file_size;
+    finder.max_files = options.max_files;
+    finder.libraries = options.libraries.clone();
+    finder.library_path = options.library_path.clone();
+    finder.find_tree(FileContentProvider::File(root.to_owned()));
+    sort_diagnostics(&mut finder.errors);
+    let mut reporter = report::Reporter::new(&finder.file_graph, options);
+    // only the new revision is summarized
+    reporter.summary = None;
+    reporter.report(&finder.errors);
+    if has_error_severity(&finder.errors) {
+        return Err(reporter.finish());
+    }
+    let ctx = context();
+    let mut resolver = Resolver::build(&finder.file_graph, &ctx);
+    resolver.build_graph();
+    resolver.check_graph();
+    sort_diagnostics(&mut resolver.errors);
+    reporter.report(&resolver.errors);
+    if has_error_severity(&resolver.errors) {
+        return Err(reporter.finish());
+    }
+    Ok(Api::of(&resolver, &finder.file_graph))
+}
+
+/// Writes the changes from `old` to `new` to standard output, a line each, then how many of them
+/// are breaking
+pub fn report(old: &Api, new: &Api, errors: &mut Vec<Diagnostic>) {
+    let changes = diff(old, new);
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    let breaking = changes.iter().filter(|change| change.breaking).count();
+    let written = changes
+        .iter()
+        .try_for_each(|change| writeln!(stdout, "{}", change))
+        .and_then(|_| {
+            writeln!(
+                stdout,
+                "{} breaking, {} additive",
+                breaking,
+                changes.len() - breaking
+            )
+        });
+    if let Err(err) = written {
+        errors.push(write_output("stdout", err));
+    }
+}
@@ -0,0 +1,264 @@
+//! Attributes configure how hardware items are elaborated and emitted.
+//! `#[synthesis(...)]` attributes are passed on to synthesis tools as pragmas,
+//! and `#[clock(freq = "100MHz")]` to timing analysis as constraints.
+//! Only single-segment attribute paths are matched, so `#[name = "rtl"]` and `#[rhdl::name = "rtl"]` are distinct.
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::{Attribute, Lit, LitStr, Meta, NestedMeta, SimplePath, Span, Spanned};
+
+use crate::elaboration::const_eval::parse_int;
+
+/// Whether a path is the single identifier `name`, like the path of an attribute or macro
+pub fn is_named(path: &SimplePath, name: &str) -> bool {
+    path.leading_sep.is_none()
+        && path.segments.len() == 1
+        && path
+            .segments
+            .first()
+            .map(|segment| segment == name)
+            .unwrap_or_default()
+}
+
+/// Find a flag attribute like `#[default]`
+pub fn find_flag<'ast>(attrs: &'ast [Attribute], name: &str) -> Option<&'ast Attribute> {
+    attrs.iter().find(|attr| match &attr.meta {
+        Meta::Path(path) => is_named(path, name),
+        _ => false,
+    })
+}
+
+/// Find the string value of an attribute like `#[name = "rtl"]`
+pub fn find_str<'ast>(attrs: &'ast [Attribute], name: &str) -> Option<&'ast LitStr> {
+    attrs.iter().find_map(|attr| match &attr.meta {
+        Meta::NameValue(name_value) if is_named(&name_value.path, name) => match &name_value.lit {
+            Lit::Str(lit_str) => Some(lit_str),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// How a lint is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The levels lints are set to for a whole design, by name, which attributes on items override
+pub type LintLevels = HashMap<String, LintLevel>;
+
+/// The level a lint is set to with `#[allow(lint_name)]`, `#[warn(lint_name)]`,
+/// or `#[deny(lint_name)]`. The last attribute wins.
+pub fn lint_level(attrs: &[Attribute], lint: &str) -> Option<LintLevel> {
+    attrs.iter().rev().find_map(|attr| match &attr.meta {
+        Meta::List(meta_list) => {
+            let level = [
+                ("allow", LintLevel::Allow),
+                ("warn", LintLevel::Warn),
+                ("deny", LintLevel::Deny),
+            ]
+            .iter()
+            .find(|(name, _)| is_named(&meta_list.path, name))
+            .map(|(_, level)| *level)?;
+            let names_lint = meta_list.nested.iter().any(|nested| match nested {
+                NestedMeta::Meta(Meta::Path(path)) => is_named(path, lint),
+                _ => false,
+            });
+            if names_lint {
+                Some(level)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    })
+}
+
+/// Whether a lint is turned off with `#[allow(lint_name)]`
+pub fn is_allowed(attrs: &[Attribute], lint: &str) -> bool {
+    lint_level(attrs, lint) == Some(LintLevel::Allow)
+}
+
+/// Units of clock frequencies and their value in hertz
+const FREQUENCY_UNITS: &[(&str, f64)] = &[("GHz", 1e9), ("MHz", 1e6), ("kHz", 1e3), ("Hz", 1.0)];
+
+/// The frequency of a clock declared with `#[clock(freq = "100MHz")]`, with the span of the
+/// frequency, or of the attribute if it has none. The frequency is in hertz, and `None` unless
+/// it is a positive number with a unit of `Hz`, `kHz`, `MHz`, or `GHz`.
+pub fn clock_frequency(attrs: &[Attribute]) -> Option<(Span, Option<f64>)> {
+    attrs.iter().find_map(|attr| match &attr.meta {
+        Meta::List(meta_list) if is_named(&meta_list.path, "clock") => {
+            let freq = meta_list.nested.iter().find_map(|nested| match nested {
+                NestedMeta::Meta(Meta::NameValue(name_value))
+                    if is_named(&name_value.path, "freq") =>
+                {
+                    Some(&name_value.lit)
+                }
+                _ => None,
+            });
+            Some(match freq {
+                Some(Lit::Str(lit_str)) => (lit_str.span(), parse_frequency(&lit_str.inner)),
+                Some(lit) => (lit.span(), None),
+                None => (attr.span(), None),
+            })
+        }
+        Meta::Path(path) if is_named(path, "clock") => Some((attr.span(), None)),
+        _ => None,
+    })
+}
+
+fn parse_frequency(freq: &str) -> Option<f64> {
+    FREQUENCY_UNITS
+        .iter()
+        .find_map(|(unit, hertz)| {
+            let number = freq.trim().strip_suffix(unit)?.trim_end();
+            if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                return None;
+            }
+            Some(number.parse::<f64>().ok()? * hertz)
+        })
+        .filter(|hertz| *hertz > 0.0)
+}
+
+/// A tool pragma passed on from `#[synthesis(...)]`, like `keep` or `max_fanout = 8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pragma {
+    pub name: &'static str,
+    /// The value of pragmas like `max_fanout`, `None` for flags
+    pub value: Option<u128>,
+}
+
+/// What a pragma is applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PragmaTarget {
+    /// A port or local
+    Signal,
+    Entity,
+}
+
+impl PragmaTarget {
+    pub fn name(self) -> &'static str {
+        match self {
+            PragmaTarget::Signal => "signals",
+            PragmaTarget::Entity => "entities",
+        }
+    }
+}
+
+struct PragmaDef {
+    name: &'static str,
+    /// Whether it takes a positive integer, like `max_fanout = 8`
+    takes_value: bool,
+    targets: &'static [PragmaTarget],
+}
+
+const PRAGMAS: &[PragmaDef] = &[
+    PragmaDef {
+        name: "keep",
+        takes_value: false,
+        targets: &[PragmaTarget::Signal],
+    },
+    PragmaDef {
+        name: "dont_touch",
+        takes_value: false,
+        targets: &[PragmaTarget::Signal, PragmaTarget::Entity],
+    },
+    PragmaDef {
+        name: "async_reg",
+        takes_value: false,
+        targets: &[PragmaTarget::Signal],
+    },
+    PragmaDef {
+        name: "max_fanout",
+        takes_value: true,
+        targets: &[PragmaTarget::Signal],
+    },
+    PragmaDef {
+        name: "keep_hierarchy",
+        takes_value: false,
+        targets: &[PragmaTarget::Entity],
+    },
+];
+
+/// Why an item of `#[synthesis(...)]` isn't a pragma
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PragmaError {
+    /// Not one of [`pragma_names`]
+    Unknown,
+    /// A flag with a value, or a pragma that takes a value without one
+    Value {
+        name: &'static str,
+        takes_value: bool,
+    },
+    /// Applied to something it doesn't apply to
+    Target {
+        name: &'static str,
+        targets: &'static [PragmaTarget],
+    },
+}
+
+/// The pragmas that can be passed on to synthesis tools
+pub fn pragma_names() -> Vec<&'static str> {
+    PRAGMAS.iter().map(|def| def.name).collect()
+}
+
+/// The pragmas of the `#[synthesis(...)]` attributes of an item, in order,
+/// with the span and reason of every item that isn't a valid pragma for `target`
+pub fn synthesis_pragmas(
+    attrs: &[Attribute],
+    target: PragmaTarget,
+) -> Vec<Result<Pragma, (Span, PragmaError)>> {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::List(meta_list) if is_named(&meta_list.path, "synthesis") => {
+                Some(meta_list.nested.iter())
+            }
+            _ => None,
+        })
+        .flatten()
+        .map(|nested| {
+            let (path, lit) = match nested {
+                NestedMeta::Meta(Meta::Path(path)) => (path, None),
+                NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                    (&name_value.path, Some(&name_value.lit))
+                }
+                _ => return Err((nested.span(), PragmaError::Unknown)),
+            };
+            let def = PRAGMAS
+                .iter()
+                .find(|def| is_named(path, def.name))
+                .ok_or_else(|| (path.span(), PragmaError::Unknown))?;
+            if !def.targets.contains(&target) {
+                return Err((
+                    path.span(),
+                    PragmaError::Target {
+                        name: def.name,
+                        targets: def.targets,
+                    },
+                ));
+            }
+            let value = match (def.takes_value, lit) {
+                (true, Some(Lit::Int(lit_int))) => parse_int(&lit_int.inner)
+                    .filter(|value| *value > 0)
+                    .map(|value| Some(value as u128)),
+                (false, None) => Some(None),
+                _ => None,
+            };
+            let value = value.ok_or_else(|| {
+                (
+                    nested.span(),
+                    PragmaError::Value {
+                        name: def.name,
+                        takes_value: def.takes_value,
+                    },
+                )
+            })?;
+            Ok(Pragma {
+                name: def.name,
+                value,
+            })
+        })
+        .collect()
+}
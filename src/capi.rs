@@ -0,0 +1,335 @@
+//! The C API of the `capi` feature, for EDA tools in C, C++, or Python to embed rhdlc with instead
+//! of running it. It's declared in `include/rhdlc.h`, and `librhdlc` is built with it.
+//!
+//! Strings are UTF-8 and NUL-terminated. What's returned by `rhdlc_options_new` and
+//! `rhdlc_compile` is owned by the caller until it's passed to the matching `_free`, and the
+//! pointers given to the other functions must be null or come from them.
+//!
+//! Black boxes are simulated by models whose callbacks are given to `rhdlc_options_add_model`,
+//! which are called from the thread that calls `rhdlc_compile`.
+#![allow(clippy::missing_safety_doc)]
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+
+use crate::find_file::{is_library_name, rhdl_path, FileContentProvider};
+use crate::report::ErrorFormat;
+use crate::sim::model::{Model, ModelContext, ModelInstance};
+use crate::sim::{Backend, Simulate, Value};
+use crate::status::Status;
+use crate::Options;
+
+/// `rhdlc_output`: what compiling a design reported
+pub struct Output {
+    status: Status,
+    diagnostics: CString,
+}
+
+/// The string `ptr` points to, or `None` if it's null or isn't UTF-8
+unsafe fn string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok().map(str::to_string)
+}
+
+/// The status of setting an option, which is only a usage error
+fn set(is_set: bool) -> c_int {
+    if is_set {
+        Status::Success.code()
+    } else {
+        Status::Usage.code()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn rhdlc_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// The options of `rhdlc` without any arguments, except that the config file isn't read
+fn default_options() -> Options {
+    Options {
+        error_limit: Some(50),
+        library_path: rhdl_path(),
+        ..Default::default()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn rhdlc_options_new() -> *mut Options {
+    Box::into_raw(Box::new(default_options()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_options_free(options: *mut Options) {
+    if !options.is_null() {
+        drop(Box::from_raw(options));
+    }
+}
+
+/// `--top`
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_options_set_top(options: *mut Options, top: *const c_char) -> c_int {
+    match (options.as_mut(), string(top)) {
+        (Some(options), Some(top)) => {
+            options.top = Some(top);
+            set(true)
+        }
+        _ => set(false),
+    }
+}
+
+/// `--lib <name>=<path>`
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_options_add_library(
+    options: *mut Options,
+    name: *const c_char,
+    path: *const c_char,
+) -> c_int {
+    match (options.as_mut(), string(name), string(path)) {
+        (Some(options), Some(name), Some(path)) if is_library_name(&name) && !path.is_empty() => {
+            options.libraries.push((name, path.into()));
+            set(true)
+        }
+        _ => set(false),
+    }
+}
+
+/// `--error-format sarif` when `sarif` isn't zero
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_options_set_sarif(options: *mut Options, sarif: c_int) -> c_int {
+    match options.as_mut() {
+        Some(options) => {
+            options.error_format = if sarif != 0 {
+                ErrorFormat::Sarif
+            } else {
+                ErrorFormat::Human
+            };
+            set(true)
+        }
+        None => set(false),
+    }
+}
+
+/// `--error-limit`, where zero reports every error
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_options_set_error_limit(
+    options: *mut Options,
+    limit: usize,
+) -> c_int {
+    match options.as_mut() {
+        Some(options) => {
+            options.error_limit = Some(limit).filter(|limit| *limit > 0);
+            set(true)
+        }
+        None => set(false),
+    }
+}
+
+/// `rhdlc sim --cycles <cycles> -o <output>`, or to standard output if `output` is null
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_options_set_simulate(
+    options: *mut Options,
+    cycles: u64,
+    output: *const c_char,
+) -> c_int {
+    let output = match (output.is_null(), string(output)) {
+        (true, _) => None,
+        (false, Some(output)) => Some(PathBuf::from(output)),
+        (false, None) => return set(false),
+    };
+    match options.as_mut() {
+        Some(options) => {
+            // models registered here only run in the interpreter
+            options.simulate = Some(Simulate {
+                cycles,
+                output,
+                backend: Backend::Interpreted,
+                seed: None,
+                coverage: None,
+            });
+            set(true)
+        }
+        None => set(false),
+    }
+}
+
+/// `rhdlc_logic`: the value of an integer, 128 bits that are unknown where `unknown` is set,
+/// X if their bit is clear or Z if it's set, with the least significant word first
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Logic {
+    bits: [u64; 2],
+    unknown: [u64; 2],
+}
+
+/// `rhdlc_model`: the callbacks of a model of a black box. `instantiate` returns the state of
+/// an instance of the model, which the other callbacks are given and `free` frees.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CModel {
+    user_data: *mut c_void,
+    instantiate: Option<unsafe extern "C" fn(*mut c_void, *const c_char) -> *mut c_void>,
+    eval: Option<unsafe extern "C" fn(*mut c_void, *mut Logic, usize)>,
+    update: Option<unsafe extern "C" fn(*mut c_void, *const Logic, *const Logic, usize) -> c_int>,
+    free: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+// the caller of `rhdlc_options_add_model` answers for its callbacks
+unsafe impl Send for CModel {}
+unsafe impl Sync for CModel {}
+
+impl Model for CModel {
+    fn instantiate(&self, cx: &ModelContext) -> Box<dyn ModelInstance> {
+        let path = CString::new(cx.path).unwrap_or_default();
+        let state = match self.instantiate {
+            Some(instantiate) => unsafe { instantiate(self.user_data, path.as_ptr()) },
+            None => self.user_data,
+        };
+        Box::new(CModelInstance {
+            model: *self,
+            state,
+        })
+    }
+}
+
+struct CModelInstance {
+    model: CModel,
+    state: *mut c_void,
+}
+
+impl ModelInstance for CModelInstance {
+    fn eval(&self, ports: &mut [Value]) {
+        if let Some(eval) = self.model.eval {
+            let mut logic = to_logic(ports);
+            unsafe { eval(self.state, logic.as_mut_ptr(), logic.len()) };
+            from_logic(ports, &mut logic.into_iter());
+        }
+    }
+
+    fn update(&mut self, before: &[Value], ports: &[Value]) -> bool {
+        match self.model.update {
+            Some(update) => {
+                let (before, ports) = (to_logic(before), to_logic(ports));
+                unsafe { update(self.state, before.as_ptr(), ports.as_ptr(), ports.len()) != 0 }
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for CModelInstance {
+    fn drop(&mut self) {
+        if let (Some(free), Some(_)) = (self.model.free, self.model.instantiate) {
+            unsafe { free(self.state) };
+        }
+    }
+}
+
+/// The integers of values, with the elements of vectors in order
+fn to_logic(values: &[Value]) -> Vec<Logic> {
+    fn add(value: &Value, logic: &mut Vec<Logic>) {
+        match value {
+            Value::Int { bits, unknown } => logic.push(Logic {
+                bits: [*bits as u64, (*bits >> 64) as u64],
+                unknown: [*unknown as u64, (*unknown >> 64) as u64],
+            }),
+            Value::Vector(elems) => elems.iter().for_each(|elem| add(elem, logic)),
+        }
+    }
+    let mut logic = vec![];
+    values.iter().for_each(|value| add(value, &mut logic));
+    logic
+}
+
+fn from_logic(values: &mut [Value], logic: &mut impl Iterator<Item = Logic>) {
+    for value in values.iter_mut() {
+        match value {
+            Value::Int { bits, unknown } => {
+                let next = logic.next().unwrap_or_default();
+                *bits = u128::from(next.bits[0]) | (u128::from(next.bits[1]) << 64);
+                *unknown = u128::from(next.unknown[0]) | (u128::from(next.unknown[1]) << 64);
+            }
+            Value::Vector(elems) => from_logic(elems, logic),
+        }
+    }
+}
+
+/// Simulates the black boxes of the entity named `entity` with the callbacks of `model`
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_options_add_model(
+    options: *mut Options,
+    entity: *const c_char,
+    model: *const CModel,
+) -> c_int {
+    match (options.as_mut(), string(entity), model.as_ref()) {
+        (Some(options), Some(entity), Some(model)) if !entity.is_empty() => {
+            options.models.register(&entity, *model);
+            set(true)
+        }
+        _ => set(false),
+    }
+}
+
+/// Compiles the design whose root file is at `path` like `rhdlc` does, with the default options
+/// if `options` is null. A panic is caught and reported as an internal compiler error, since it
+/// can't unwind into C.
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_compile(
+    path: *const c_char,
+    options: *const Options,
+) -> *mut Output {
+    let default_options;
+    let options = match options.as_ref() {
+        Some(options) => options,
+        None => {
+            default_options = self::default_options();
+            &default_options
+        }
+    };
+    let (diagnostics, status) = match string(path) {
+        Some(path) => panic::catch_unwind(AssertUnwindSafe(|| {
+            crate::entry_with_status(FileContentProvider::File(PathBuf::from(path)), options)
+        }))
+        .unwrap_or_else(|_| {
+            (
+                "error: internal compiler error: rhdlc panicked\n".to_string(),
+                Status::InternalError,
+            )
+        }),
+        None => (
+            "error: the path of the root file isn't UTF-8\n".to_string(),
+            Status::Usage,
+        ),
+    };
+    Box::into_raw(Box::new(Output {
+        status,
+        // a NUL in a file of the design can end up in a diagnostic
+        diagnostics: CString::new(diagnostics.replace('\0', "\u{fffd}")).unwrap_or_default(),
+    }))
+}
+
+/// The status `rhdlc` would exit with, as in the README
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_output_status(output: *const Output) -> c_int {
+    output
+        .as_ref()
+        .map_or(Status::Usage.code(), |output| output.status.code())
+}
+
+/// The diagnostics as `rhdlc` writes them to standard error, which live as long as `output`
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_output_diagnostics(output: *const Output) -> *const c_char {
+    output
+        .as_ref()
+        .map_or(std::ptr::null(), |output| output.diagnostics.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rhdlc_output_free(output: *mut Output) {
+    if !output.is_null() {
+        drop(Box::from_raw(output));
+    }
+}
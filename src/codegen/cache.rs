@@ -0,0 +1,115 @@
+//! Content-addressed caching of the outputs emitted to a directory. Each output is listed in the
+//! manifest with the fingerprint of the IR it was generated from, its input, and isn't generated
+//! or written again while its input and its file are unchanged, so iterative workflows only
+//! rewrite the modules that changed and downstream tools don't rebuild the others.
+use std::fs;
+use std::path::PathBuf;
+
+use fxhash::FxHashMap as HashMap;
+use serde_json::Value as Json;
+
+use super::interface::fingerprint;
+use super::{Emit, EmitKind, Output, MANIFEST};
+use crate::ir::{Ir, Module};
+
+/// The outputs of a kind listed in the manifest of its directory by a previous run
+#[derive(Default)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+    kind: Option<EmitKind>,
+    /// By path, the input and fingerprint of each output
+    artifacts: HashMap<String, (String, String)>,
+}
+
+impl Cache {
+    /// Reads the manifest of the directory of `emit`, which is empty for standard output
+    pub fn load(emit: &Emit) -> Self {
+        let dir = match &emit.dir {
+            Some(dir) => dir,
+            None => return Self::default(),
+        };
+        let manifest = fs::read_to_string(dir.join(MANIFEST))
+            .ok()
+            .and_then(|manifest| serde_json::from_str::<Json>(&manifest).ok())
+            .unwrap_or_default();
+        let artifacts = manifest["artifacts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|artifact| artifact["kind"] == emit.kind.name())
+            .filter_map(|artifact| {
+                Some((
+                    artifact["path"].as_str()?.to_string(),
+                    (
+                        artifact["input"].as_str()?.to_string(),
+                        artifact["hash"].as_str()?.to_string(),
+                    ),
+                ))
+            })
+            .collect();
+        Self {
+            dir: Some(dir.clone()),
+            kind: Some(emit.kind),
+            artifacts,
+        }
+    }
+
+    /// The content of the output named `name` if it was generated from `input` before and its
+    /// file hasn't changed since
+    pub fn get(&self, name: &str, input: &str) -> Option<String> {
+        let (dir, kind) = (self.dir.as_ref()?, self.kind?);
+        let path = format!("{}.{}", name, kind.extension());
+        let (previous_input, hash) = self.artifacts.get(&path)?;
+        if previous_input != input {
+            return None;
+        }
+        let content = fs::read_to_string(dir.join(&path)).ok()?;
+        Some(content).filter(|content| fingerprint(content) == *hash)
+    }
+
+    /// The output of a backend that generates one from the whole design, which is only
+    /// generated if the design changed
+    pub fn design(&self, ir: &Ir, generate: impl FnOnce() -> Vec<Output>) -> Vec<Output> {
+        let top = match ir.top() {
+            Some(top) => top,
+            None => return generate(),
+        };
+        let input = design_input(ir);
+        match self.get(&top.name, &input) {
+            Some(content) => vec![Output {
+                name: top.name.clone(),
+                content,
+                entity: Some(top.entity),
+                input: Some(input),
+            }],
+            None => generate()
+                .into_iter()
+                .map(|output| Output {
+                    input: Some(input.clone()),
+                    ..output
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The fingerprint of what the output of `module` is generated from: the module and the ports and
+/// names of the modules it instantiates, and the version of rhdlc that generates it
+pub fn module_input(ir: &Ir, module: &Module) -> String {
+    let instantiated = module
+        .instances
+        .iter()
+        .map(|instance| &ir[instance.module])
+        .collect::<Vec<_>>();
+    fingerprint(&format!(
+        "{} {:?} {:?}",
+        env!("CARGO_PKG_VERSION"),
+        module,
+        instantiated
+    ))
+}
+
+/// The fingerprint of the whole design and the version of rhdlc that generates its output
+pub fn design_input(ir: &Ir) -> String {
+    fingerprint(&format!("{} {:?}", env!("CARGO_PKG_VERSION"), ir))
+}
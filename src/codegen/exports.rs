@@ -0,0 +1,55 @@
+//! Lists the public API of the design, so library authors can diff it between versions.
+//! `--emit exports` writes a line per item with its kind and path, and `--emit exports-json` an
+//! array of objects.
+
+use serde_json::json;
+
+use super::Output;
+use crate::resolution::Resolver;
+
+pub struct ExportsEmitter<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub json: bool,
+}
+
+impl<'a, 'ast> ExportsEmitter<'a, 'ast> {
+    /// A single report named `exports`, ordered by path
+    pub fn emit(&self) -> Vec<Output> {
+        let exports = self.resolver.exports();
+        let content = if self.json {
+            let exports = exports
+                .iter()
+                .map(|export| {
+                    json!({
+                        "path": export.path,
+                        "kind": export.kind.name(),
+                        "canonical": export.canonical,
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_string_pretty(&exports).unwrap() + "\n"
+        } else {
+            exports
+                .iter()
+                .map(|export| {
+                    if export.path == export.canonical {
+                        format!("{} {}\n", export.kind.name(), export.path)
+                    } else {
+                        format!(
+                            "{} {} (from {})\n",
+                            export.kind.name(),
+                            export.path,
+                            export.canonical
+                        )
+                    }
+                })
+                .collect()
+        };
+        vec![Output {
+            name: "exports".to_string(),
+            content,
+            entity: None,
+            input: None,
+        }]
+    }
+}
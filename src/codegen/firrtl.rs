@@ -0,0 +1,487 @@
+//! Lowers an elaborated design to FIRRTL, for optimization and further lowering
+//! with the FIRRTL/CIRCT toolchain.
+//!
+//! FIRRTL has no parameters, so modules are named after their generic values, like `Counter_WIDTH_8`.
+//! Black boxes become external modules that pass their generic values on as parameters.
+//! Registers are declared with their clock and asynchronous reset, and assignments become
+//! last-connect-wins connections inside `when` blocks. Arithmetic wraps around like it does in RHDL,
+//! so results are truncated to the width of the operands.
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{Span, Spanned};
+
+use super::Output;
+use crate::analysis::signal_graph::{Edge, SignalKind};
+use crate::elaboration::const_eval::ConstValue;
+use crate::error::*;
+use crate::ir::{
+    clock_signals, ident_name, BinaryOp, Clocking, Connection, Expr, ExprKind, Ir, Module,
+    ModuleIndex, SignalIndex, Stmt, Ty, UnaryOp,
+};
+
+const BACKEND: &str = "FIRRTL";
+const INDENT: &str = "  ";
+
+/// Words of FIRRTL that can't be used as names
+const KEYWORDS: &[&str] = &[
+    "Analog",
+    "AsyncReset",
+    "Clock",
+    "Reset",
+    "SInt",
+    "UInt",
+    "attach",
+    "circuit",
+    "cmem",
+    "defname",
+    "else",
+    "extmodule",
+    "flip",
+    "infer",
+    "inst",
+    "invalid",
+    "is",
+    "mem",
+    "module",
+    "mport",
+    "mux",
+    "node",
+    "of",
+    "old",
+    "parameter",
+    "printf",
+    "read",
+    "reg",
+    "reset",
+    "skip",
+    "smem",
+    "stop",
+    "undefined",
+    "validif",
+    "when",
+    "wire",
+    "with",
+    "write",
+];
+
+/// RHDL names are legal FIRRTL names, except for its keywords which get a trailing `_`
+pub fn escape(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+pub struct FirrtlEmitter<'a, 'ast> {
+    pub ir: &'a Ir<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> FirrtlEmitter<'a, 'ast> {
+    /// A single circuit named after the top module
+    pub fn emit(&mut self) -> Vec<Output> {
+        let ir = self.ir;
+        let top = match ir.top() {
+            Some(top) => top,
+            None => return vec![],
+        };
+        let clocks = clock_signals(ir);
+        let modules = ir
+            .module_indices()
+            .map(|module| {
+                let mut module_writer = ModuleWriter {
+                    ir,
+                    index: module,
+                    module: &ir[module],
+                    errors: &mut *self.errors,
+                    clocks: &clocks,
+                    out: String::new(),
+                };
+                module_writer.write_module();
+                module_writer.out
+            })
+            .collect::<Vec<_>>();
+        let mut content = format!("circuit {} :\n", top.specialized_name());
+        content.push_str(&modules.join("\n"));
+        vec![Output {
+            name: top.name.clone(),
+            content,
+            entity: Some(top.entity),
+            input: None,
+        }]
+    }
+}
+
+struct ModuleWriter<'a, 'ast> {
+    ir: &'a Ir<'ast>,
+    index: ModuleIndex,
+    module: &'a Module<'ast>,
+    errors: &'a mut Vec<Diagnostic>,
+    clocks: &'a HashSet<(ModuleIndex, SignalIndex)>,
+    out: String,
+}
+
+impl<'a, 'ast> ModuleWriter<'a, 'ast> {
+    fn line(&mut self, depth: usize, line: &str) {
+        for _ in 0..depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn unsupported(&mut self, span: Span) {
+        self.errors
+            .push(unsupported_by_backend(self.module.file, span, BACKEND));
+    }
+
+    fn is_clock(&self, signal: SignalIndex) -> bool {
+        self.clocks.contains(&(self.index, signal))
+    }
+
+    /// Registers that are outputs are declared separately as `<name>_reg`,
+    /// since ports can't be registers in FIRRTL
+    fn is_output_register(&self, signal: SignalIndex) -> bool {
+        self.module[signal].kind == SignalKind::Output && self.module.is_register(signal)
+    }
+
+    /// The name signals are read from and connected to
+    fn value_name(&self, signal: SignalIndex) -> String {
+        let name = escape(&self.module[signal].name);
+        if self.is_output_register(signal) {
+            format!("{}_reg", name)
+        } else {
+            name
+        }
+    }
+
+    fn write_module(&mut self) {
+        let module = self.module;
+        let keyword = if module.is_black_box() {
+            "extmodule"
+        } else {
+            "module"
+        };
+        self.line(1, &format!("{} {} :", keyword, module.specialized_name()));
+        let mut declarations = vec![];
+        let mut output_registers = vec![];
+        for signal in module.signal_indices() {
+            let name = escape(&module[signal].name);
+            let ty = if self.is_clock(signal) {
+                "Clock".to_string()
+            } else {
+                type_name(&module[signal].ty)
+            };
+            match module[signal].kind {
+                SignalKind::Input => self.line(2, &format!("input {} : {}", name, ty)),
+                SignalKind::Output => {
+                    self.line(2, &format!("output {} : {}", name, ty));
+                    if module.is_register(signal) {
+                        output_registers.push(signal);
+                        declarations.push(self.register_declaration(signal, &ty));
+                    }
+                }
+                SignalKind::InOut => self.errors.push(unsupported_by_backend(
+                    module[signal].file,
+                    module[signal].ident.span(),
+                    BACKEND,
+                )),
+                SignalKind::Local if module.is_register(signal) => {
+                    declarations.push(self.register_declaration(signal, &ty))
+                }
+                SignalKind::Local => declarations.push(format!("wire {} : {}", name, ty)),
+            }
+        }
+        if module.is_black_box() {
+            self.line(2, &format!("defname = {}", module.name));
+            for (ident, value) in module.generics.iter() {
+                let value = match value {
+                    ConstValue::Int(value) => *value,
+                    ConstValue::Bool(value) => i128::from(*value),
+                };
+                self.line(2, &format!("parameter {} = {}", ident_name(ident), value));
+            }
+            return;
+        }
+        self.out.push('\n');
+
+        let body_start = self.out.len();
+        for declaration in declarations {
+            self.line(2, &declaration);
+        }
+        for instance in module.instances.iter() {
+            let child = &self.ir[instance.module];
+            let instance_name = escape(&instance.name);
+            self.line(
+                2,
+                &format!("inst {} of {}", instance_name, child.specialized_name()),
+            );
+            for connection in instance.connections.iter() {
+                match connection {
+                    Connection::Input { port, value } => {
+                        let port_is_clock = self.clocks.contains(&(instance.module, *port));
+                        let source = match value.kind {
+                            ExprKind::Signal(signal) if port_is_clock && self.is_clock(signal) => {
+                                escape(&module[signal].name)
+                            }
+                            _ if port_is_clock => format!("asClock({})", self.lower_expr(value)),
+                            _ => self.lower_expr(value),
+                        };
+                        let line = format!(
+                            "{}.{} <= {}",
+                            instance_name,
+                            escape(&child[*port].name),
+                            source
+                        );
+                        self.line(2, &line);
+                    }
+                    Connection::Output { port, signal } => {
+                        let port_is_clock = self.clocks.contains(&(instance.module, *port));
+                        let port_name = format!("{}.{}", instance_name, escape(&child[*port].name));
+                        let source = match (port_is_clock, self.is_clock(*signal)) {
+                            (true, false) => format!("asUInt({})", port_name),
+                            (false, true) => format!("asClock({})", port_name),
+                            _ => port_name,
+                        };
+                        let line = format!("{} <= {}", self.value_name(*signal), source);
+                        self.line(2, &line);
+                    }
+                    Connection::InOut { .. } => self.unsupported(instance.span),
+                }
+            }
+        }
+        // clocked processes only differ from combinational ones in what they connect to,
+        // and asynchronous reset values are part of the register declarations
+        for process in module.processes.iter() {
+            self.write_stmts(&process.body, 2);
+        }
+        for signal in output_registers {
+            let line = format!(
+                "{} <= {}",
+                escape(&module[signal].name),
+                self.value_name(signal)
+            );
+            self.line(2, &line);
+        }
+        if self.out.len() == body_start {
+            self.line(2, "skip");
+        }
+    }
+
+    /// `reg <name> : <type>, <clock>`, with an asynchronous reset if it has one.
+    /// Synchronous resets are ordinary logic in the process.
+    fn register_declaration(&mut self, signal: SignalIndex, ty: &str) -> String {
+        let clocking = self.module.register_clocking(signal).unwrap();
+        let clock = self.clock_expr(clocking);
+        let name = self.value_name(signal);
+        let reset = clocking
+            .reset
+            .as_ref()
+            .and_then(|reset| Some((reset, reset.value_of(signal)?)));
+        match reset {
+            None => format!("reg {} : {}, {}", name, ty, clock),
+            Some((reset, init)) => {
+                let reset_name = self.read(reset.signal);
+                let condition = if reset.active_high {
+                    reset_name
+                } else {
+                    format!("not({})", reset_name)
+                };
+                format!(
+                    "reg {} : {}, {} with : (reset => (asAsyncReset({}), {}))",
+                    name,
+                    ty,
+                    clock,
+                    condition,
+                    self.lower_expr(init)
+                )
+            }
+        }
+    }
+
+    fn clock_expr(&self, clocking: &Clocking) -> String {
+        let clock = escape(&self.module[clocking.clock].name);
+        let clock = if self.is_clock(clocking.clock) {
+            clock
+        } else {
+            format!("asClock({})", clock)
+        };
+        match clocking.edge {
+            Edge::Rising => clock,
+            Edge::Falling => format!("asClock(not(asUInt({})))", clock),
+        }
+    }
+
+    fn write_stmts(&mut self, stmts: &[Stmt], depth: usize) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Assign {
+                    target,
+                    index,
+                    value,
+                    span,
+                } => {
+                    let mut sink = self.value_name(*target);
+                    if let Some(index) = index {
+                        // only elements of vectors can be connected to
+                        if !self.module[*target].ty.is_vector() {
+                            self.unsupported(*span);
+                            continue;
+                        }
+                        sink = format!("{}[{}]", sink, self.lower_index(index));
+                    }
+                    let line = format!("{} <= {}", sink, self.lower_expr(value));
+                    self.line(depth, &line);
+                }
+                Stmt::If { .. } => self.write_when(stmt, depth, false),
+                Stmt::Property { .. } => {}
+            }
+        }
+    }
+
+    fn write_when(&mut self, stmt: &Stmt, depth: usize, is_else_when: bool) {
+        let (cond, then_branch, else_branch) = match stmt {
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => (cond, then_branch, else_branch),
+            Stmt::Assign { .. } | Stmt::Property { .. } => unreachable!(),
+        };
+        let cond = self.lower_expr(cond);
+        if is_else_when {
+            // continues the `else` of the previous line
+            self.out.push_str(&format!(" when {} :\n", cond));
+        } else {
+            self.line(depth, &format!("when {} :", cond));
+        }
+        self.write_branch(then_branch, depth + 1);
+        match else_branch.as_slice() {
+            [] => {}
+            [else_when @ Stmt::If { .. }] => {
+                for _ in 0..depth {
+                    self.out.push_str(INDENT);
+                }
+                self.out.push_str("else");
+                self.write_when(else_when, depth, true);
+            }
+            _ => {
+                self.line(depth, "else :");
+                self.write_branch(else_branch, depth + 1);
+            }
+        }
+    }
+
+    /// Branches can't be empty in FIRRTL
+    fn write_branch(&mut self, stmts: &[Stmt], depth: usize) {
+        let start = self.out.len();
+        self.write_stmts(stmts, depth);
+        if self.out.len() == start {
+            self.line(depth, "skip");
+        }
+    }
+
+    fn read(&self, signal: SignalIndex) -> String {
+        if self.is_clock(signal) {
+            format!("asUInt({})", self.value_name(signal))
+        } else {
+            self.value_name(signal)
+        }
+    }
+
+    /// Constant indices are written as plain numbers
+    fn lower_index(&self, index: &Expr) -> String {
+        match index.as_const() {
+            Some(index) => index.to_string(),
+            None => self.lower_expr(index),
+        }
+    }
+
+    fn lower_expr(&self, expr: &Expr) -> String {
+        let signed = expr.ty.is_signed();
+        // FIRRTL operators mostly return unsigned values
+        let wrap = |text: String| {
+            if signed {
+                format!("asSInt({})", text)
+            } else {
+                text
+            }
+        };
+        match &expr.kind {
+            ExprKind::Const(value) => format!("{}({})", type_name(&expr.ty), value),
+            ExprKind::Signal(signal) => self.read(*signal),
+            ExprKind::Unary(op, operand) => {
+                let operand = self.lower_expr(operand);
+                match op {
+                    UnaryOp::Not => wrap(format!("not({})", operand)),
+                    UnaryOp::Neg => wrap(format!("tail(neg({}), 1)", operand)),
+                    UnaryOp::ReduceOr => format!("orr({})", operand),
+                }
+            }
+            ExprKind::Binary(op, left, right) => {
+                let width = left.ty.width();
+                let (a, b) = (self.lower_expr(left), self.lower_expr(right));
+                match op {
+                    BinaryOp::Add => wrap(format!("tail(add({}, {}), 1)", a, b)),
+                    BinaryOp::Sub => wrap(format!("tail(sub({}, {}), 1)", a, b)),
+                    BinaryOp::Mul => wrap(format!("bits(mul({}, {}), {}, 0)", a, b, width - 1)),
+                    BinaryOp::Div if signed => wrap(format!("tail(div({}, {}), 1)", a, b)),
+                    BinaryOp::Div => format!("div({}, {})", a, b),
+                    BinaryOp::Rem => format!("rem({}, {})", a, b),
+                    BinaryOp::BitAnd | BinaryOp::And => wrap(format!("and({}, {})", a, b)),
+                    BinaryOp::BitOr | BinaryOp::Or => wrap(format!("or({}, {})", a, b)),
+                    BinaryOp::BitXor => wrap(format!("xor({}, {})", a, b)),
+                    BinaryOp::Shl => wrap(format!("bits(dshl({}, {}), {}, 0)", a, b, width - 1)),
+                    BinaryOp::Shr => format!("dshr({}, {})", a, b),
+                    BinaryOp::Eq => format!("eq({}, {})", a, b),
+                    BinaryOp::Ne => format!("neq({}, {})", a, b),
+                    BinaryOp::Lt => format!("lt({}, {})", a, b),
+                    BinaryOp::Le => format!("leq({}, {})", a, b),
+                    BinaryOp::Gt => format!("gt({}, {})", a, b),
+                    BinaryOp::Ge => format!("geq({}, {})", a, b),
+                }
+            }
+            ExprKind::Index(base, index) => {
+                let index_text = self.lower_index(index);
+                let base_text = self.lower_expr(base);
+                match (&base.ty, index.as_const()) {
+                    (Ty::Vector(..), _) => format!("{}[{}]", base_text, index_text),
+                    (_, Some(_)) => format!("bits({}, {}, {})", base_text, index_text, index_text),
+                    (_, None) => format!("bits(dshr({}, {}), 0, 0)", base_text, index_text),
+                }
+            }
+            ExprKind::Resize(inner) => {
+                let (from, to) = (inner.ty.width(), expr.ty.width());
+                let mut text = self.lower_expr(inner);
+                let mut is_signed = inner.ty.is_signed();
+                if from > to {
+                    text = format!("bits({}, {}, 0)", text, to - 1);
+                    is_signed = false;
+                } else if from < to {
+                    text = format!("pad({}, {})", text, to);
+                }
+                match (is_signed, signed) {
+                    (false, true) => format!("asSInt({})", text),
+                    (true, false) => format!("asUInt({})", text),
+                    _ => text,
+                }
+            }
+            ExprKind::Mux(cond, then_value, else_value) => format!(
+                "mux({}, {}, {})",
+                self.lower_expr(cond),
+                self.lower_expr(then_value),
+                self.lower_expr(else_value)
+            ),
+        }
+    }
+}
+
+/// i.e. `UInt<8>` or `SInt<4>[2]`
+fn type_name(ty: &Ty) -> String {
+    match ty {
+        Ty::UInt(width) => format!("UInt<{}>", width),
+        Ty::SInt(width) => format!("SInt<{}>", width),
+        Ty::Vector(elem, len) => format!("{}[{}]", type_name(elem), len),
+    }
+}
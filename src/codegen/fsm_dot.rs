@@ -0,0 +1,71 @@
+//! Draws the state machines of a design as Graphviz digraphs.
+//!
+//! Each state machine is a graph of its own, named after its module and register like
+//! `Controller_state`. An arrow from a point marks the initial state.
+use std::fmt::Write;
+
+use super::Output;
+use crate::ir::{Ir, StateMachine};
+
+const INDENT: &str = "    ";
+
+pub struct FsmDotEmitter<'a, 'ast> {
+    pub ir: &'a Ir<'ast>,
+}
+
+impl<'a, 'ast> FsmDotEmitter<'a, 'ast> {
+    /// One graph per state machine of every module
+    pub fn emit(&self) -> Vec<Output> {
+        let mut outputs = vec![];
+        for module in self.ir.modules.iter() {
+            for state_machine in module.state_machines.iter() {
+                let name = format!(
+                    "{}_{}",
+                    module.specialized_name(),
+                    module[state_machine.signal].name
+                );
+                outputs.push(Output {
+                    content: digraph(&name, state_machine),
+                    name,
+                    entity: Some(module.entity),
+                    input: None,
+                });
+            }
+        }
+        outputs
+    }
+}
+
+fn digraph(name: &str, state_machine: &StateMachine) -> String {
+    let states = &state_machine.states;
+    let mut out = String::new();
+    writeln!(out, "digraph {} {{", quote(name)).unwrap();
+    writeln!(out, "{}_start [shape=point];", INDENT).unwrap();
+    for state in states.iter() {
+        writeln!(out, "{}{};", INDENT, quote(state)).unwrap();
+    }
+    writeln!(
+        out,
+        "{}_start -> {};",
+        INDENT,
+        quote(&states[state_machine.initial])
+    )
+    .unwrap();
+    for (from, to) in state_machine.transitions.iter() {
+        writeln!(
+            out,
+            "{}{} -> {};",
+            INDENT,
+            quote(&states[*from]),
+            quote(&states[*to])
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Identifiers can't contain quotes, so they only need surrounding
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name)
+}
@@ -0,0 +1,185 @@
+//! Reports the tree of instances of the elaborated design for design reviews: the name of each
+//! instance, its entity, architecture, and generic values, and where it's instantiated.
+//! `--emit hierarchy` writes it as an indented tree and `--emit hierarchy-json` as nested objects.
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use rhdl::ast::{Span, Spanned};
+use serde_json::{json, Value as Json};
+
+use super::Output;
+use crate::elaboration::{
+    const_eval::ConstValue, item_entity, top::entity_path, Design, InstanceIndex,
+};
+use crate::find_file::{FileGraph, FileId};
+use crate::ir::ident_name;
+use crate::resolution::{architecture_name, black_box, Resolver};
+
+/// An instance and the instances below it
+#[derive(Debug)]
+pub struct Hierarchy {
+    pub name: String,
+    /// The path of the entity from its root file
+    pub entity: String,
+    /// `None` for black boxes and architectures without a `#[name]`
+    pub architecture: Option<String>,
+    pub black_box: bool,
+    pub generics: Vec<(String, ConstValue)>,
+    /// Where the instance is instantiated, or where the top entity is declared
+    pub location: String,
+    pub children: Vec<Hierarchy>,
+}
+
+impl Hierarchy {
+    pub fn build(resolver: &Resolver, design: &Design, file_graph: &FileGraph) -> Self {
+        Self::build_instance(resolver, design, file_graph, design.top)
+    }
+
+    fn build_instance(
+        resolver: &Resolver,
+        design: &Design,
+        file_graph: &FileGraph,
+        idx: InstanceIndex,
+    ) -> Self {
+        let resolution_graph = &resolver.resolution_graph;
+        let instance = &design[idx];
+        let (file, span) = match instance.instantiation {
+            Some(instantiation) => (instantiation.file, instantiation.span()),
+            None => (
+                resolution_graph.file(instance.entity),
+                item_entity(resolver, instance.entity).ident.span(),
+            ),
+        };
+        Hierarchy {
+            name: instance.name.clone(),
+            entity: entity_path(resolver, instance.entity),
+            architecture: instance
+                .arch
+                .and_then(|arch| architecture_name(resolution_graph, arch)),
+            black_box: black_box(resolution_graph, instance.entity).is_some(),
+            generics: instance
+                .generics
+                .iter()
+                .map(|(ident, value)| (ident_name(ident), *value))
+                .collect(),
+            location: location(file_graph, file, span),
+            children: instance
+                .children
+                .iter()
+                .map(|child| Self::build_instance(resolver, design, file_graph, *child))
+                .collect(),
+        }
+    }
+
+    /// The entity with its generic values and architecture, i.e. `Counter<WIDTH = 8> [fast]`
+    fn describe(&self) -> String {
+        let mut description = self.entity.clone();
+        if !self.generics.is_empty() {
+            description += &format!(
+                "<{}>",
+                self.generics
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        if self.black_box {
+            description += " [black box]";
+        } else if let Some(architecture) = &self.architecture {
+            description += &format!(" [{}]", architecture);
+        }
+        description
+    }
+
+    fn add_rows(&self, label: String, prefix: &str, rows: &mut Vec<(String, String)>) {
+        rows.push((label, self.location.clone()));
+        for (i, child) in self.children.iter().enumerate() {
+            let is_last = i + 1 == self.children.len();
+            let label = format!(
+                "{}{}{}: {}",
+                prefix,
+                if is_last { "└─ " } else { "├─ " },
+                child.name,
+                child.describe()
+            );
+            let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+            child.add_rows(label, &child_prefix, rows);
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        json!({
+            "name": self.name,
+            "entity": self.entity,
+            "architecture": self.architecture,
+            "black_box": self.black_box,
+            "generics": self
+                .generics
+                .iter()
+                .map(|(name, value)| json!({ "name": name, "value": value_json(*value) }))
+                .collect::<Vec<_>>(),
+            "location": self.location,
+            "children": self.children.iter().map(Hierarchy::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// `path:line:column`
+pub fn location(file_graph: &FileGraph, file: FileId, span: Span) -> String {
+    let name = file_graph.inner.name(file).to_string_lossy();
+    let range: Range<usize> = span.into();
+    match file_graph.line_column(file, range.start) {
+        Some(location) => format!("{}:{}:{}", name, location.line, location.column),
+        None => name.to_string(),
+    }
+}
+
+/// Integers too wide for JSON numbers are written as strings
+fn value_json(value: ConstValue) -> Json {
+    match value {
+        ConstValue::Int(value) => match i64::try_from(value) {
+            Ok(value) => json!(value),
+            Err(_) => json!(value.to_string()),
+        },
+        ConstValue::Bool(value) => json!(value),
+    }
+}
+
+pub struct HierarchyEmitter<'a> {
+    pub hierarchy: &'a Hierarchy,
+    pub json: bool,
+}
+
+impl<'a> HierarchyEmitter<'a> {
+    /// A single report named `hierarchy`, with the locations lined up in a column
+    pub fn emit(&self) -> Vec<Output> {
+        let content = if self.json {
+            serde_json::to_string_pretty(&self.hierarchy.to_json()).unwrap() + "\n"
+        } else {
+            let mut rows = vec![];
+            self.hierarchy.add_rows(self.hierarchy.describe(), "", &mut rows);
+            let label_width = rows
+                .iter()
+                .map(|(label, _)| label.chars().count())
+                .max()
+                .unwrap_or_default();
+            rows.iter()
+                .map(|(label, location)| {
+                    format!(
+                        "{}{}  {}\n",
+                        label,
+                        " ".repeat(label_width - label.chars().count()),
+                        location
+                    )
+                })
+                .collect()
+        };
+        vec![Output {
+            name: "hierarchy".to_string(),
+            content,
+            entity: None,
+            input: None,
+        }]
+    }
+}
@@ -0,0 +1,121 @@
+//! Draws which entities of the elaborated design instantiate which others, so teams can spot
+//! unexpected dependencies and estimate how much logic is replicated before synthesis. Unlike the
+//! hierarchy, an entity is one node however often it's instantiated: it's labeled with how many
+//! instances of it there are in the design, and each edge with how many of those are instantiated
+//! by instances of the other entity. `--emit instance-graph` writes a Graphviz digraph and
+//! `--emit instance-graph-json` an object.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde_json::json;
+
+use super::hierarchy::Hierarchy;
+use super::Output;
+
+const INDENT: &str = "    ";
+
+pub struct InstanceGraphEmitter<'a> {
+    pub hierarchy: &'a Hierarchy,
+    pub json: bool,
+}
+
+/// The instances of an entity in the design
+#[derive(Default)]
+struct Entity {
+    instances: usize,
+    black_box: bool,
+}
+
+impl<'a> InstanceGraphEmitter<'a> {
+    /// A single graph named `instance-graph`, with the entities and edges ordered by path
+    pub fn emit(&self) -> Vec<Output> {
+        let mut entities = BTreeMap::new();
+        let mut instantiations = BTreeMap::new();
+        count(self.hierarchy, &mut entities, &mut instantiations);
+        let content = if self.json {
+            let graph = json!({
+                "top": self.hierarchy.entity,
+                "entities": entities
+                    .iter()
+                    .map(|(path, entity)| {
+                        json!({
+                            "entity": path,
+                            "instances": entity.instances,
+                            "black_box": entity.black_box,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+                "instantiations": instantiations
+                    .iter()
+                    .map(|((from, to), count)| json!({ "from": from, "to": to, "count": count }))
+                    .collect::<Vec<_>>(),
+            });
+            serde_json::to_string_pretty(&graph).unwrap() + "\n"
+        } else {
+            let mut out = String::new();
+            out.push_str("digraph \"instances\" {\n");
+            for (path, entity) in entities.iter() {
+                let instances = match entity.instances {
+                    1 => "1 instance".to_string(),
+                    instances => format!("{} instances", instances),
+                };
+                let style = if entity.black_box {
+                    ", style=dashed"
+                } else {
+                    ""
+                };
+                writeln!(
+                    out,
+                    "{}{} [label={}{}];",
+                    INDENT,
+                    quote(path),
+                    quote(&format!("{}\\n{}", path, instances)),
+                    style
+                )
+                .unwrap();
+            }
+            for ((from, to), count) in instantiations.iter() {
+                writeln!(
+                    out,
+                    "{}{} -> {} [label={}];",
+                    INDENT,
+                    quote(from),
+                    quote(to),
+                    quote(&count.to_string())
+                )
+                .unwrap();
+            }
+            out.push_str("}\n");
+            out
+        };
+        vec![Output {
+            name: "instance-graph".to_string(),
+            content,
+            entity: None,
+            input: None,
+        }]
+    }
+}
+
+/// Counts `instance` and the instances below it, by entity and by the entities that instantiate
+/// them
+fn count<'h>(
+    instance: &'h Hierarchy,
+    entities: &mut BTreeMap<&'h str, Entity>,
+    instantiations: &mut BTreeMap<(&'h str, &'h str), usize>,
+) {
+    let entity = entities.entry(instance.entity.as_str()).or_default();
+    entity.instances += 1;
+    entity.black_box = instance.black_box;
+    for child in instance.children.iter() {
+        *instantiations
+            .entry((instance.entity.as_str(), child.entity.as_str()))
+            .or_default() += 1;
+        count(child, entities, instantiations);
+    }
+}
+
+/// Paths can't contain quotes, so they only need surrounding
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name)
+}
@@ -0,0 +1,191 @@
+//! `--emit interface`: the interface of a library, which designs using it compile against instead
+//! of its sources. It's RHDL: the files of the library in one, with the modules of other files
+//! inlined, the bodies of fns and the architectures left out, and the entities made black boxes,
+//! so everything its exported items are declared with still resolves. It's written as `lib.rhdli`
+//! for a `lib.rhdl`, next to which the library finder looks for it.
+//!
+//! The interface ends with the path and fingerprint of each source it was generated from. It's
+//! used while they're unchanged, which is checked by reading them without parsing them.
+use std::ops::Range;
+use std::path::Path;
+
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::Spanned;
+
+use super::Output;
+use crate::find_file::{FileGraph, FileId};
+use crate::resolution::{black_box, Branch, ResolutionNode, Resolver};
+
+/// The extension of an interface, in place of that of the library's root file
+pub const EXTENSION: &str = "rhdli";
+
+const SOURCE_PREFIX: &str = "// source ";
+
+pub struct InterfaceEmitter<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub file_graph: &'a FileGraph,
+}
+
+/// A change to the source of a file of the library
+enum Edit {
+    Replace(Range<usize>, String),
+    /// The module whose `;` is at the range is inlined from its file
+    Inline(Range<usize>, FileId),
+}
+
+impl Edit {
+    fn range(&self) -> &Range<usize> {
+        match self {
+            Edit::Replace(range, _) | Edit::Inline(range, _) => range,
+        }
+    }
+}
+
+impl<'a, 'ast> InterfaceEmitter<'a, 'ast> {
+    /// A single interface named by the root file of the design, i.e. `lib`
+    pub fn emit(&self) -> Vec<Output> {
+        let file_graph = self.file_graph;
+        let root = match file_graph
+            .roots
+            .iter()
+            .find(|root| !file_graph.externs.contains_key(root))
+        {
+            Some(root) => *root,
+            None => return vec![],
+        };
+        let root_name = Path::new(file_graph.inner.name(root)).to_owned();
+        let edits = self.edits();
+        let mut content = self.render(root, &edits).trim_end().to_string();
+        content.push_str("\n\n// rhdlc interface, generated from these sources by path\n");
+        let dir = root_name.parent().unwrap_or_else(|| Path::new(""));
+        for file in file_graph
+            .iter()
+            .filter(|file| file_graph.root_of(**file) == root)
+        {
+            let name = Path::new(file_graph.inner.name(*file));
+            let path = name.strip_prefix(dir).unwrap_or(name);
+            content.push_str(&format!(
+                "{}{} {}\n",
+                SOURCE_PREFIX,
+                path.to_string_lossy(),
+                fingerprint(file_graph[*file].as_ref())
+            ));
+        }
+        vec![Output {
+            name: root_name
+                .file_stem()
+                .map_or("lib".to_string(), |stem| stem.to_string_lossy().to_string()),
+            content,
+            entity: None,
+            input: None,
+        }]
+    }
+
+    /// The changes to each file of the library, ordered by where they are
+    fn edits(&self) -> HashMap<FileId, Vec<Edit>> {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let mut edits: HashMap<FileId, Vec<Edit>> = HashMap::default();
+        for node in resolution_graph.node_indices() {
+            if resolution_graph.is_extern(node) {
+                continue;
+            }
+            let file = resolution_graph.file(node);
+            let source = self.file_graph[file].as_ref();
+            let edit = match &resolution_graph[node] {
+                ResolutionNode::Branch {
+                    branch: Branch::Fn(item_fn),
+                    ..
+                } => Edit::Replace(item_fn.block.span().into(), "{}".to_string()),
+                ResolutionNode::Branch {
+                    branch: Branch::Arch(item_arch),
+                    ..
+                } => Edit::Replace(item_arch.span().into(), String::new()),
+                ResolutionNode::Branch {
+                    branch: Branch::Mod(item_mod),
+                    ..
+                } => match resolution_graph.content_files.get(&node) {
+                    Some(content_file) => {
+                        let span: Range<usize> = item_mod.span().into();
+                        Edit::Inline(span.end - 1..span.end, *content_file)
+                    }
+                    None => continue,
+                },
+                ResolutionNode::Branch {
+                    branch: Branch::Entity(item_entity),
+                    ..
+                } if black_box(resolution_graph, node).is_none() => {
+                    let ident: Range<usize> = item_entity.ident.span().into();
+                    let keyword = match source[..ident.start].rfind("entity") {
+                        Some(keyword) => keyword,
+                        None => continue,
+                    };
+                    // on a line of its own, indented like the entity
+                    let line = source[..keyword]
+                        .rfind('\n')
+                        .map_or(0, |newline| newline + 1);
+                    let indent = &source[line..keyword];
+                    let indent = if indent.trim().is_empty() {
+                        indent
+                    } else {
+                        " "
+                    };
+                    Edit::Replace(keyword..keyword, format!("#[black_box]\n{}", indent))
+                }
+                _ => continue,
+            };
+            edits.entry(file).or_default().push(edit);
+        }
+        for file_edits in edits.values_mut() {
+            file_edits.sort_by_key(|edit| (edit.range().start, edit.range().end));
+        }
+        edits
+    }
+
+    /// The source of `file` with its edits, leaving out those within a body that was removed
+    fn render(&self, file: FileId, edits: &HashMap<FileId, Vec<Edit>>) -> String {
+        let source = self.file_graph[file].as_ref();
+        let mut rendered = String::new();
+        let mut end = 0;
+        for edit in edits.get(&file).into_iter().flatten() {
+            let range = edit.range();
+            if range.start < end {
+                continue;
+            }
+            rendered.push_str(&source[end..range.start]);
+            match edit {
+                Edit::Replace(_, replacement) => rendered.push_str(replacement),
+                Edit::Inline(_, content_file) => {
+                    rendered.push_str(" {\n");
+                    for line in self.render(*content_file, edits).trim_end().lines() {
+                        if !line.is_empty() {
+                            rendered.push_str("    ");
+                            rendered.push_str(line);
+                        }
+                        rendered.push('\n');
+                    }
+                    rendered.push('}');
+                }
+            }
+            end = range.end;
+        }
+        rendered.push_str(&source[end..]);
+        rendered
+    }
+}
+
+/// The fingerprint of the contents of a source
+pub fn fingerprint(content: &str) -> String {
+    format!("{:016x}", fxhash::hash64(content))
+}
+
+/// The sources an interface was generated from, by path from it, with their fingerprints
+pub fn sources(interface: &str) -> Vec<(&str, &str)> {
+    interface
+        .lines()
+        .filter_map(|line| line.strip_prefix(SOURCE_PREFIX))
+        .filter_map(|source| {
+            let space = source.rfind(' ')?;
+            Some((&source[..space], &source[space + 1..]))
+        })
+        .collect()
+}
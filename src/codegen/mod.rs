@@ -0,0 +1,372 @@
+//! Backends that turn the intermediate representation of a design into the input of other
+//! hardware tools, and reports about its source, which don't need a design.
+//! Each is selected with `--emit <kind>`, written to standard output or, with `--emit <kind>=<dir>`,
+//! to files in `dir`, along with a manifest of them.
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use codespan_reporting::diagnostic::Severity;
+use serde_json::{json, Value as Json};
+
+use crate::error::*;
+use crate::events;
+use crate::find_file::FileGraph;
+use crate::ir::Ir;
+use crate::resolution::{ResolutionIndex, Resolver};
+use cache::Cache;
+use hierarchy::Hierarchy;
+
+pub mod cache;
+pub mod exports;
+pub mod firrtl;
+pub mod fsm_dot;
+pub mod hierarchy;
+pub mod instance_graph;
+pub mod interface;
+pub mod module_graph;
+pub mod netlist_json;
+pub mod resources;
+pub mod sdc;
+pub mod symbols;
+pub mod timing_report;
+pub mod verilog;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emit {
+    pub kind: EmitKind,
+    /// `None` for standard output
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitKind {
+    Verilog,
+    NetlistJson,
+    Firrtl,
+    FsmDot,
+    Resources,
+    TimingReport,
+    Sdc,
+    Hierarchy,
+    HierarchyJson,
+    InstanceGraph,
+    InstanceGraphJson,
+    Symbols,
+    Exports,
+    ExportsJson,
+    Interface,
+    ModuleGraph,
+    ModuleGraphJson,
+}
+
+impl EmitKind {
+    const ALL: &'static [EmitKind] = &[
+        EmitKind::Verilog,
+        EmitKind::NetlistJson,
+        EmitKind::Firrtl,
+        EmitKind::FsmDot,
+        EmitKind::Resources,
+        EmitKind::TimingReport,
+        EmitKind::Sdc,
+        EmitKind::Hierarchy,
+        EmitKind::HierarchyJson,
+        EmitKind::InstanceGraph,
+        EmitKind::InstanceGraphJson,
+        EmitKind::Symbols,
+        EmitKind::Exports,
+        EmitKind::ExportsJson,
+        EmitKind::Interface,
+        EmitKind::ModuleGraph,
+        EmitKind::ModuleGraphJson,
+    ];
+
+    /// Whether the output is generated from an elaborated design rather than its source
+    pub fn needs_design(&self) -> bool {
+        !matches!(
+            self,
+            EmitKind::Symbols
+                | EmitKind::Exports
+                | EmitKind::ExportsJson
+                | EmitKind::Interface
+                | EmitKind::ModuleGraph
+                | EmitKind::ModuleGraphJson
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            EmitKind::Verilog => "verilog",
+            EmitKind::NetlistJson => "netlist-json",
+            EmitKind::Firrtl => "firrtl",
+            EmitKind::FsmDot => "fsm-dot",
+            EmitKind::Resources => "resources",
+            EmitKind::TimingReport => "timing-report",
+            EmitKind::Sdc => "sdc",
+            EmitKind::Hierarchy => "hierarchy",
+            EmitKind::HierarchyJson => "hierarchy-json",
+            EmitKind::InstanceGraph => "instance-graph",
+            EmitKind::InstanceGraphJson => "instance-graph-json",
+            EmitKind::Symbols => "symbols",
+            EmitKind::Exports => "exports",
+            EmitKind::ExportsJson => "exports-json",
+            EmitKind::Interface => "interface",
+            EmitKind::ModuleGraph => "module-graph",
+            EmitKind::ModuleGraphJson => "module-graph-json",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            EmitKind::Verilog => "v",
+            EmitKind::NetlistJson => "json",
+            EmitKind::Firrtl => "fir",
+            EmitKind::FsmDot => "dot",
+            EmitKind::Resources => "txt",
+            EmitKind::TimingReport => "txt",
+            EmitKind::Sdc => "sdc",
+            EmitKind::Hierarchy => "txt",
+            EmitKind::HierarchyJson => "json",
+            EmitKind::InstanceGraph => "dot",
+            EmitKind::InstanceGraphJson => "json",
+            EmitKind::Symbols => "json",
+            EmitKind::Exports => "txt",
+            EmitKind::ExportsJson => "json",
+            EmitKind::Interface => interface::EXTENSION,
+            EmitKind::ModuleGraph => "dot",
+            EmitKind::ModuleGraphJson => "json",
+        }
+    }
+}
+
+impl Display for EmitKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    /// Parses `kind` or `kind=dir`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, dir) = match s.find('=') {
+            Some(eq) => (&s[..eq], Some(PathBuf::from(&s[eq + 1..]))),
+            None => (s, None),
+        };
+        let kind = EmitKind::ALL
+            .iter()
+            .copied()
+            .find(|emit_kind| emit_kind.name() == kind)
+            .ok_or_else(|| {
+                format!(
+                    "unknown emit kind `{}`, expected one of: {}",
+                    kind,
+                    EmitKind::ALL
+                        .iter()
+                        .map(EmitKind::name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+        Ok(Emit { kind, dir })
+    }
+}
+
+/// The file in the directory of an emit that lists the outputs written to it, so that downstream
+/// tools can find them without globbing
+pub const MANIFEST: &str = "manifest.json";
+
+/// A unit of output, like a Verilog module or a whole netlist or circuit
+pub struct Output {
+    pub name: String,
+    pub content: String,
+    /// The entity it was generated from, if it was generated from one
+    pub entity: Option<ResolutionIndex>,
+    /// The fingerprint of the IR it was generated from, if it's cached, see [`cache`]
+    pub input: Option<String>,
+}
+
+pub fn emit(
+    resolver: &Resolver,
+    ir: &Ir,
+    hierarchy: &Hierarchy,
+    emit: &Emit,
+    errors: &mut Vec<Diagnostic>,
+) {
+    let error_count = errors.len();
+    let cache = &Cache::load(emit);
+    let outputs = match emit.kind {
+        EmitKind::Verilog => verilog::VerilogEmitter { ir, cache, errors }.emit(),
+        EmitKind::NetlistJson => {
+            cache.design(ir, || netlist_json::NetlistEmitter { ir, errors }.emit())
+        }
+        EmitKind::Firrtl => cache.design(ir, || firrtl::FirrtlEmitter { ir, errors }.emit()),
+        EmitKind::FsmDot => fsm_dot::FsmDotEmitter { ir }.emit(),
+        EmitKind::Resources => resources::ResourcesEmitter { ir }.emit(),
+        EmitKind::TimingReport => timing_report::TimingReportEmitter { ir }.emit(),
+        EmitKind::Sdc => sdc::SdcEmitter { ir, errors }.emit(),
+        EmitKind::Hierarchy => hierarchy::HierarchyEmitter {
+            hierarchy,
+            json: false,
+        }
+        .emit(),
+        EmitKind::HierarchyJson => hierarchy::HierarchyEmitter {
+            hierarchy,
+            json: true,
+        }
+        .emit(),
+        EmitKind::InstanceGraph => instance_graph::InstanceGraphEmitter {
+            hierarchy,
+            json: false,
+        }
+        .emit(),
+        EmitKind::InstanceGraphJson => instance_graph::InstanceGraphEmitter {
+            hierarchy,
+            json: true,
+        }
+        .emit(),
+        // emitted from the source by `emit_source`
+        EmitKind::Symbols
+        | EmitKind::Exports
+        | EmitKind::ExportsJson
+        | EmitKind::Interface
+        | EmitKind::ModuleGraph
+        | EmitKind::ModuleGraphJson => return,
+    };
+    // don't write partial output
+    if errors[error_count..]
+        .iter()
+        .any(|diagnostic| diagnostic.severity >= Severity::Error)
+    {
+        return;
+    }
+    write(resolver, outputs, emit, errors);
+}
+
+/// Emits the outputs that are generated from the resolved source rather than a design
+pub fn emit_source(
+    resolver: &Resolver,
+    file_graph: &FileGraph,
+    emit: &Emit,
+    errors: &mut Vec<Diagnostic>,
+) {
+    let outputs = match emit.kind {
+        EmitKind::Symbols => symbols::SymbolsEmitter {
+            resolver,
+            file_graph,
+        }
+        .emit(),
+        EmitKind::Exports => exports::ExportsEmitter {
+            resolver,
+            json: false,
+        }
+        .emit(),
+        EmitKind::ExportsJson => exports::ExportsEmitter {
+            resolver,
+            json: true,
+        }
+        .emit(),
+        EmitKind::Interface => interface::InterfaceEmitter {
+            resolver,
+            file_graph,
+        }
+        .emit(),
+        EmitKind::ModuleGraph => module_graph::ModuleGraphEmitter {
+            resolver,
+            json: false,
+        }
+        .emit(),
+        EmitKind::ModuleGraphJson => module_graph::ModuleGraphEmitter {
+            resolver,
+            json: true,
+        }
+        .emit(),
+        _ => return,
+    };
+    write(resolver, outputs, emit, errors);
+}
+
+fn write(resolver: &Resolver, outputs: Vec<Output>, emit: &Emit, errors: &mut Vec<Diagnostic>) {
+    match &emit.dir {
+        None => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            for output in outputs {
+                if let Err(err) = stdout.write_all(output.content.as_bytes()) {
+                    errors.push(write_output("stdout", err));
+                    return;
+                }
+            }
+        }
+        Some(dir) => {
+            if let Err(err) = fs::create_dir_all(dir) {
+                errors.push(write_output(&dir.to_string_lossy(), err));
+                return;
+            }
+            let mut artifacts = vec![];
+            for output in outputs {
+                let name = format!("{}.{}", output.name, emit.kind.extension());
+                let path = dir.join(&name);
+                let artifact = json!({
+                    "kind": emit.kind.name(),
+                    "path": name,
+                    "entity": output
+                        .entity
+                        .map(|entity| resolver.resolution_graph.path(entity)),
+                    "hash": interface::fingerprint(&output.content),
+                    "input": output.input,
+                });
+                match write_if_changed(&path, &output.content) {
+                    Ok(()) => artifacts.push(artifact),
+                    Err(err) => errors.push(write_output(&path.to_string_lossy(), err)),
+                }
+            }
+            write_manifest(dir.join(MANIFEST), emit.kind, artifacts, errors);
+        }
+    }
+}
+
+/// Replaces the artifacts of `kind` in the manifest at `path` with `artifacts`, keeping those
+/// of the other kinds emitted to the same directory
+fn write_manifest(
+    path: PathBuf,
+    kind: EmitKind,
+    mut artifacts: Vec<Json>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    let previous = fs::read_to_string(&path)
+        .ok()
+        .and_then(|manifest| serde_json::from_str::<Json>(&manifest).ok());
+    artifacts.extend(
+        previous
+            .as_ref()
+            .and_then(|manifest| manifest["artifacts"].as_array())
+            .into_iter()
+            .flatten()
+            .filter(|artifact| artifact["kind"] != kind.name())
+            .cloned(),
+    );
+    artifacts.sort_by(|a, b| a["path"].as_str().cmp(&b["path"].as_str()));
+    let manifest = json!({
+        "creator": "rhdlc",
+        "artifacts": artifacts,
+    });
+    let content = serde_json::to_string_pretty(&manifest).unwrap() + "\n";
+    if let Err(err) = write_if_changed(&path, &content) {
+        errors.push(write_output(&path.to_string_lossy(), err));
+    }
+}
+
+/// Writes `content` to the file at `path` unless it's already there, so that tools that rebuild
+/// when a file is modified don't rebuild from an output that didn't change
+fn write_if_changed(path: &Path, content: &str) -> io::Result<()> {
+    if fs::read_to_string(path).map_or(false, |previous| previous == content) {
+        return Ok(());
+    }
+    fs::write(path, content)?;
+    events::artifact_written(path);
+    Ok(())
+}
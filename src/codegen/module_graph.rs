@@ -0,0 +1,71 @@
+//! Draws which modules of the design import items from which other modules, for a quick view of
+//! how coupled they are. `--emit module-graph` writes a Graphviz digraph with an edge per pair of
+//! modules labeled with the names imported, and `--emit module-graph-json` the same as an object.
+use std::fmt::Write;
+
+use serde_json::json;
+
+use super::Output;
+use crate::resolution::Resolver;
+
+const INDENT: &str = "    ";
+
+pub struct ModuleGraphEmitter<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub json: bool,
+}
+
+impl<'a, 'ast> ModuleGraphEmitter<'a, 'ast> {
+    /// A single graph named `module-graph`
+    pub fn emit(&self) -> Vec<Output> {
+        let graph = self.resolver.module_graph();
+        let content = if self.json {
+            let dependencies = graph
+                .dependencies
+                .iter()
+                .map(|dependency| {
+                    json!({
+                        "from": dependency.from,
+                        "to": dependency.to,
+                        "names": dependency.names,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let graph = json!({
+                "modules": graph.modules,
+                "dependencies": dependencies,
+            });
+            serde_json::to_string_pretty(&graph).unwrap() + "\n"
+        } else {
+            let mut out = String::new();
+            out.push_str("digraph \"modules\" {\n");
+            for module in graph.modules.iter() {
+                writeln!(out, "{}{};", INDENT, quote(module)).unwrap();
+            }
+            for dependency in graph.dependencies.iter() {
+                writeln!(
+                    out,
+                    "{}{} -> {} [label={}];",
+                    INDENT,
+                    quote(&dependency.from),
+                    quote(&dependency.to),
+                    quote(&dependency.names.join(", "))
+                )
+                .unwrap();
+            }
+            out.push_str("}\n");
+            out
+        };
+        vec![Output {
+            name: "module-graph".to_string(),
+            content,
+            entity: None,
+            input: None,
+        }]
+    }
+}
+
+/// Paths and names can't contain quotes, so they only need surrounding
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name)
+}
@@ -0,0 +1,767 @@
+//! Emits the elaborated design as a Yosys JSON netlist, as read by `read_json` in Yosys
+//! and by nextpnr.
+//!
+//! Each module of the intermediate representation becomes one module of word-level cells like `$add`, `$mux`, and `$adff` and instances of other modules.
+//! Black boxes are cells of their entity's name with their generic values as parameters,
+//! like the primitives of a vendor library.
+//! Bits are numbered from 2 within a module, since the format reserves `0` and `1` for constants.
+//! The nets of state registers have the `fsm_encoding` attribute of their enum's encoding.
+//! Synthesis pragmas are attributes of the nets, modules, and black box cells they're applied to.
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::Span;
+use serde_json::{json, Map, Value as Json};
+
+use super::Output;
+use crate::analysis::signal_graph::{Edge, SignalKind};
+use crate::attribute::Pragma;
+use crate::elaboration::{const_eval::ConstValue, enums::Encoding};
+use crate::error::*;
+use crate::ir::{
+    ident_name, BinaryOp, Clocking, Connection, Expr, ExprKind, Ir, Module, ProcessKind,
+    SignalIndex, Stmt, UnaryOp,
+};
+
+const BACKEND: &str = "netlist JSON";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bit {
+    Net(usize),
+    Zero,
+    One,
+}
+
+impl Bit {
+    fn to_json(self) -> Json {
+        match self {
+            Bit::Net(net) => json!(net),
+            Bit::Zero => json!("0"),
+            Bit::One => json!("1"),
+        }
+    }
+}
+
+/// A word of bits, least significant first
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Word {
+    bits: Vec<Bit>,
+    signed: bool,
+}
+
+impl Word {
+    fn constant(value: i128, width: usize, signed: bool) -> Self {
+        Word {
+            bits: (0..width)
+                .map(|i| {
+                    if (value >> i.min(127)) & 1 == 1 {
+                        Bit::One
+                    } else {
+                        Bit::Zero
+                    }
+                })
+                .collect(),
+            signed,
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Truncate or extend, with sign extension for signed words
+    fn resize(&self, width: usize) -> Word {
+        let fill = if self.signed {
+            self.bits.last().copied().unwrap_or(Bit::Zero)
+        } else {
+            Bit::Zero
+        };
+        let mut bits = self.bits.clone();
+        bits.resize(width, fill);
+        Word {
+            bits,
+            signed: self.signed,
+        }
+    }
+
+    /// A constant as a binary string, most significant bit first
+    fn const_string(&self) -> Option<String> {
+        self.bits
+            .iter()
+            .rev()
+            .map(|bit| match bit {
+                Bit::Zero => Some('0'),
+                Bit::One => Some('1'),
+                Bit::Net(_) => None,
+            })
+            .collect()
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        usize::from_str_radix(&self.const_string()?, 2).ok()
+    }
+}
+
+/// Integer parameters and attributes are written as 32-bit binary strings
+fn param(value: usize) -> Json {
+    json!(format!("{:032b}", value))
+}
+
+/// Flags are attributes set to 1
+fn pragma_attributes(pragmas: &[Pragma]) -> Map<String, Json> {
+    pragmas
+        .iter()
+        .map(|pragma| {
+            let value = pragma.value.map(|value| value as usize).unwrap_or(1);
+            (pragma.name.to_string(), param(value))
+        })
+        .collect()
+}
+
+fn parameters(values: &[(&str, usize)]) -> Map<String, Json> {
+    values
+        .iter()
+        .map(|(name, value)| (name.to_string(), param(*value)))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Input,
+    Output,
+    InOut,
+}
+
+impl Direction {
+    fn name(self) -> &'static str {
+        match self {
+            Direction::Input => "input",
+            Direction::Output => "output",
+            Direction::InOut => "inout",
+        }
+    }
+}
+
+/// A primitive cell like `$add`, or an instance of another module
+struct Cell {
+    name: String,
+    ty: String,
+    hide_name: bool,
+    parameters: Map<String, Json>,
+    attributes: Map<String, Json>,
+    ports: Vec<(String, Direction, Vec<Bit>)>,
+}
+
+/// The values assigned to signals so far in a process
+type Env = BTreeMap<SignalIndex, Word>;
+
+/// Modules with generics are named the way Yosys names specialized modules,
+/// i.e. `$paramod\Counter\WIDTH=8`
+fn netlist_module_name(module: &Module) -> String {
+    if module.generics.is_empty() {
+        return module.name.clone();
+    }
+    format!(
+        "$paramod\\{}{}",
+        module.name,
+        module
+            .generics
+            .iter()
+            .map(|(ident, value)| format!("\\{}={}", ident_name(ident), value))
+            .collect::<String>()
+    )
+}
+
+/// The `fsm_encoding` attribute value for an encoding, as Yosys reads it.
+/// Yosys can't gray code a state machine, so it's told to keep the codes it has.
+fn fsm_encoding(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Binary => "binary",
+        Encoding::OneHot => "one-hot",
+        Encoding::Gray => "user",
+    }
+}
+
+pub struct NetlistEmitter<'a, 'ast> {
+    pub ir: &'a Ir<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> NetlistEmitter<'a, 'ast> {
+    /// A single netlist named after the top module
+    pub fn emit(&mut self) -> Vec<Output> {
+        let ir = self.ir;
+        let mut modules = Map::new();
+        for (i, module) in ir.modules.iter().enumerate() {
+            // cells of black boxes are left for the tools reading the netlist to define
+            if module.is_black_box() {
+                continue;
+            }
+            let mut module_builder = ModuleBuilder {
+                ir,
+                module,
+                errors: &mut *self.errors,
+                next_net: 2,
+                nets: vec![],
+                aliases: HashMap::default(),
+                cells: vec![],
+            };
+            module_builder.build();
+            modules.insert(netlist_module_name(module), module_builder.to_json(i == 0));
+        }
+        let netlist = json!({
+            "creator": "rhdlc",
+            "modules": modules,
+        });
+        ir.top()
+            .map(|top| Output {
+                name: top.name.clone(),
+                content: serde_json::to_string_pretty(&netlist).unwrap() + "\n",
+                entity: Some(top.entity),
+                input: None,
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+struct ModuleBuilder<'a, 'ast> {
+    ir: &'a Ir<'ast>,
+    module: &'a Module<'ast>,
+    errors: &'a mut Vec<Diagnostic>,
+    next_net: usize,
+    /// The nets of each signal
+    nets: Vec<Word>,
+    /// Nets of combinational signals are another name for the bits of their final value
+    aliases: HashMap<usize, Bit>,
+    cells: Vec<Cell>,
+}
+
+impl<'a, 'ast> ModuleBuilder<'a, 'ast> {
+    fn unsupported(&mut self, span: Span) {
+        self.errors
+            .push(unsupported_by_backend(self.module.file, span, BACKEND));
+    }
+
+    fn new_word(&mut self, width: usize, signed: bool) -> Word {
+        let bits = (self.next_net..self.next_net + width)
+            .map(Bit::Net)
+            .collect();
+        self.next_net += width;
+        Word { bits, signed }
+    }
+
+    fn add_cell(
+        &mut self,
+        ty: &str,
+        parameters: Map<String, Json>,
+        ports: Vec<(&str, Direction, Vec<Bit>)>,
+    ) {
+        self.cells.push(Cell {
+            name: format!("{}${}", ty, self.cells.len() + 1),
+            ty: ty.to_string(),
+            hide_name: true,
+            parameters,
+            attributes: Map::new(),
+            ports: ports
+                .into_iter()
+                .map(|(name, direction, bits)| (name.to_string(), direction, bits))
+                .collect(),
+        });
+    }
+
+    /// Adds a cell with a single output `Y`
+    fn add_operator(
+        &mut self,
+        ty: &str,
+        parameters: Map<String, Json>,
+        inputs: Vec<(&str, Vec<Bit>)>,
+        width: usize,
+        signed: bool,
+    ) -> Word {
+        let y = self.new_word(width, signed);
+        let mut ports = inputs
+            .into_iter()
+            .map(|(name, bits)| (name, Direction::Input, bits))
+            .collect::<Vec<_>>();
+        ports.push(("Y", Direction::Output, y.bits.clone()));
+        self.add_cell(ty, parameters, ports);
+        y
+    }
+
+    /// `a` when `s` is low, `b` when it is high
+    fn mux(&mut self, s: Bit, a: Word, b: Word) -> Word {
+        let width = a.width();
+        let signed = a.signed;
+        self.add_operator(
+            "$mux",
+            parameters(&[("WIDTH", width)]),
+            vec![("A", a.bits), ("B", b.bits), ("S", vec![s])],
+            width,
+            signed,
+        )
+    }
+
+    fn build(&mut self) {
+        let module = self.module;
+        for signal in module.signal_indices() {
+            let width = match usize::try_from(module[signal].ty.width()) {
+                Ok(width) => width,
+                Err(_) => {
+                    self.unsupported(module[signal].ident.span());
+                    return;
+                }
+            };
+            let word = self.new_word(width, module[signal].ty.is_signed());
+            self.nets.push(word);
+        }
+
+        for process in module.processes.iter() {
+            match &process.kind {
+                ProcessKind::Comb => {
+                    let mut env = Env::default();
+                    self.exec(&mut env, &process.body, false);
+                    for (signal, value) in env {
+                        let nets = self.nets[signal.index()].clone();
+                        for (net, bit) in nets.bits.iter().zip(value.bits) {
+                            if let Bit::Net(net) = net {
+                                if Bit::Net(*net) != bit {
+                                    self.aliases.insert(*net, bit);
+                                }
+                            }
+                        }
+                    }
+                }
+                ProcessKind::Clocked(clocking) => {
+                    self.build_registers(clocking, &process.body, process.span)
+                }
+            }
+        }
+
+        for instance in module.instances.iter() {
+            let child = &self.ir[instance.module];
+            let mut ports = vec![];
+            for connection in instance.connections.iter() {
+                let (port, direction, word) = match connection {
+                    Connection::Input { port, value } => (
+                        *port,
+                        Direction::Input,
+                        self.lower_expr(&Env::default(), value, false),
+                    ),
+                    Connection::Output { port, signal } => {
+                        (*port, Direction::Output, self.nets[signal.index()].clone())
+                    }
+                    Connection::InOut { port, signal } => {
+                        (*port, Direction::InOut, self.nets[signal.index()].clone())
+                    }
+                };
+                ports.push((child[port].name.clone(), direction, word.bits));
+            }
+            let (ty, parameters, attributes) = if child.is_black_box() {
+                let parameters = child
+                    .generics
+                    .iter()
+                    .map(|(ident, value)| {
+                        let value = match value {
+                            ConstValue::Int(value) => *value as usize,
+                            ConstValue::Bool(value) => usize::from(*value),
+                        };
+                        (ident_name(ident), param(value))
+                    })
+                    .collect();
+                (
+                    child.name.clone(),
+                    parameters,
+                    pragma_attributes(&child.pragmas),
+                )
+            } else {
+                (netlist_module_name(child), Map::new(), Map::new())
+            };
+            self.cells.push(Cell {
+                name: instance.name.clone(),
+                ty,
+                hide_name: false,
+                parameters,
+                attributes,
+                ports,
+            });
+        }
+    }
+
+    /// One `$dff` or `$adff` for each signal assigned by a clocked process
+    fn build_registers(&mut self, clocking: &Clocking, body: &[Stmt], span: Span) {
+        let clock = self.nets[clocking.clock.index()].bits[0];
+        let mut env = Env::default();
+        self.exec(&mut env, body, true);
+        let reset = match &clocking.reset {
+            Some(reset) => {
+                let mut reset_env = Env::default();
+                self.exec(&mut reset_env, &reset.body, true);
+                Some((
+                    self.nets[reset.signal.index()].bits[0],
+                    reset.active_high,
+                    reset_env,
+                ))
+            }
+            None => None,
+        };
+
+        let mut signals = env.keys().copied().collect::<Vec<_>>();
+        if let Some((_, _, reset_env)) = &reset {
+            signals.extend(reset_env.keys().copied());
+        }
+        signals.sort();
+        signals.dedup();
+        for signal in signals {
+            let q = self.nets[signal.index()].clone();
+            let mut d = env.get(&signal).cloned().unwrap_or_else(|| q.clone());
+            let mut cell_parameters = parameters(&[
+                ("CLK_POLARITY", (clocking.edge == Edge::Rising) as usize),
+                ("WIDTH", q.width()),
+            ]);
+            let mut ports = vec![("CLK", Direction::Input, vec![clock])];
+            let ty = match &reset {
+                Some((reset, active_high, reset_env)) => {
+                    match reset_env.get(&signal).map(Word::const_string) {
+                        Some(Some(reset_value)) => {
+                            cell_parameters
+                                .insert("ARST_POLARITY".to_string(), param(*active_high as usize));
+                            cell_parameters.insert("ARST_VALUE".to_string(), json!(reset_value));
+                            ports.push(("ARST", Direction::Input, vec![*reset]));
+                            "$adff"
+                        }
+                        Some(None) => {
+                            // only constants can be loaded asynchronously
+                            self.unsupported(span);
+                            continue;
+                        }
+                        None => {
+                            // registers without a reset value hold while in reset
+                            d = if *active_high {
+                                self.mux(*reset, d, q.clone())
+                            } else {
+                                self.mux(*reset, q.clone(), d)
+                            };
+                            "$dff"
+                        }
+                    }
+                }
+                None => "$dff",
+            };
+            ports.push(("D", Direction::Input, d.bits));
+            ports.push(("Q", Direction::Output, q.bits));
+            self.add_cell(ty, cell_parameters, ports);
+        }
+    }
+
+    /// The value of a signal, which is the value of its nets for nonblocking assignments
+    fn read(&self, env: &Env, signal: SignalIndex, nonblocking: bool) -> Word {
+        let nets = &self.nets[signal.index()];
+        if nonblocking {
+            nets.clone()
+        } else {
+            env.get(&signal).unwrap_or(nets).clone()
+        }
+    }
+
+    fn exec(&mut self, env: &mut Env, stmts: &[Stmt], nonblocking: bool) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Assign {
+                    target,
+                    index,
+                    value,
+                    span,
+                } => {
+                    let value = self.lower_expr(env, value, nonblocking);
+                    let mut current = env
+                        .get(target)
+                        .unwrap_or(&self.nets[target.index()])
+                        .clone();
+                    match index {
+                        None => current.bits = value.bits,
+                        // assignments are to a constant element or bit
+                        Some(index) => match self.lower_expr(env, index, nonblocking).to_usize() {
+                            Some(index) => {
+                                let elem_width = value.width();
+                                current.bits[index * elem_width..(index + 1) * elem_width]
+                                    .copy_from_slice(&value.bits);
+                            }
+                            None => {
+                                self.unsupported(*span);
+                                continue;
+                            }
+                        },
+                    }
+                    env.insert(*target, current);
+                }
+                Stmt::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    // both branches are executed and signals they assign differently are multiplexed
+                    let cond = self.lower_expr(env, cond, nonblocking).bits[0];
+                    let mut then_env = env.clone();
+                    self.exec(&mut then_env, then_branch, nonblocking);
+                    let mut else_env = env.clone();
+                    self.exec(&mut else_env, else_branch, nonblocking);
+
+                    let mut signals = then_env
+                        .keys()
+                        .chain(else_env.keys())
+                        .copied()
+                        .collect::<Vec<_>>();
+                    signals.sort();
+                    signals.dedup();
+                    for signal in signals {
+                        let nets = &self.nets[signal.index()];
+                        let then_value = then_env.get(&signal).unwrap_or(nets).clone();
+                        let else_value = else_env.get(&signal).unwrap_or(nets).clone();
+                        let value = if then_value == else_value {
+                            then_value
+                        } else {
+                            self.mux(cond, else_value, then_value)
+                        };
+                        env.insert(signal, value);
+                    }
+                }
+                Stmt::Property { .. } => {}
+            }
+        }
+    }
+
+    fn lower_expr(&mut self, env: &Env, expr: &Expr, nonblocking: bool) -> Word {
+        let width = usize::try_from(expr.ty.width()).unwrap();
+        let signed = expr.ty.is_signed();
+        match &expr.kind {
+            ExprKind::Const(value) => Word::constant(*value, width, signed),
+            ExprKind::Signal(signal) => self.read(env, *signal, nonblocking),
+            ExprKind::Unary(op, operand) => {
+                let operand = self.lower_expr(env, operand, nonblocking);
+                let ty = match op {
+                    UnaryOp::Not => "$not",
+                    UnaryOp::Neg => "$neg",
+                    UnaryOp::ReduceOr => "$reduce_bool",
+                };
+                let a_width = operand.width();
+                self.add_operator(
+                    ty,
+                    parameters(&[
+                        ("A_SIGNED", operand.signed as usize),
+                        ("A_WIDTH", a_width),
+                        ("Y_WIDTH", width),
+                    ]),
+                    vec![("A", operand.bits)],
+                    width,
+                    signed,
+                )
+            }
+            ExprKind::Binary(op, left, right) => {
+                let left = self.lower_expr(env, left, nonblocking);
+                let right = self.lower_expr(env, right, nonblocking);
+                let ty = match op {
+                    BinaryOp::Add => "$add",
+                    BinaryOp::Sub => "$sub",
+                    BinaryOp::Mul => "$mul",
+                    BinaryOp::Div => "$div",
+                    BinaryOp::Rem => "$mod",
+                    BinaryOp::BitAnd => "$and",
+                    BinaryOp::BitOr => "$or",
+                    BinaryOp::BitXor => "$xor",
+                    BinaryOp::Shl => "$shl",
+                    BinaryOp::Shr if left.signed => "$sshr",
+                    BinaryOp::Shr => "$shr",
+                    BinaryOp::And => "$logic_and",
+                    BinaryOp::Or => "$logic_or",
+                    BinaryOp::Eq => "$eq",
+                    BinaryOp::Ne => "$ne",
+                    BinaryOp::Lt => "$lt",
+                    BinaryOp::Le => "$le",
+                    BinaryOp::Gt => "$gt",
+                    BinaryOp::Ge => "$ge",
+                };
+                let (a_width, b_width) = (left.width(), right.width());
+                self.add_operator(
+                    ty,
+                    parameters(&[
+                        ("A_SIGNED", left.signed as usize),
+                        ("A_WIDTH", a_width),
+                        ("B_SIGNED", right.signed as usize),
+                        ("B_WIDTH", b_width),
+                        ("Y_WIDTH", width),
+                    ]),
+                    vec![("A", left.bits), ("B", right.bits)],
+                    width,
+                    signed,
+                )
+            }
+            ExprKind::Index(base, index) => {
+                let base = self.lower_expr(env, base, nonblocking);
+                let index = self.lower_expr(env, index, nonblocking);
+                if let Some(index) = index.to_usize() {
+                    return Word {
+                        bits: base.bits[index * width..(index + 1) * width].to_vec(),
+                        signed,
+                    };
+                }
+                // elements are shifted into place by their offset in bits
+                let offset = if width == 1 {
+                    index
+                } else {
+                    let stride = Word::constant(
+                        width as i128,
+                        128 - (width as u128).leading_zeros() as usize,
+                        false,
+                    );
+                    let (index_width, stride_width) = (index.width(), stride.width());
+                    self.add_operator(
+                        "$mul",
+                        parameters(&[
+                            ("A_SIGNED", 0),
+                            ("A_WIDTH", index_width),
+                            ("B_SIGNED", 0),
+                            ("B_WIDTH", stride_width),
+                            ("Y_WIDTH", index_width + stride_width),
+                        ]),
+                        vec![("A", index.bits), ("B", stride.bits)],
+                        index_width + stride_width,
+                        false,
+                    )
+                };
+                let (base_width, offset_width) = (base.width(), offset.width());
+                self.add_operator(
+                    "$shiftx",
+                    parameters(&[
+                        ("A_SIGNED", 0),
+                        ("A_WIDTH", base_width),
+                        ("B_SIGNED", 0),
+                        ("B_WIDTH", offset_width),
+                        ("Y_WIDTH", width),
+                    ]),
+                    vec![("A", base.bits), ("B", offset.bits)],
+                    width,
+                    signed,
+                )
+            }
+            ExprKind::Resize(inner) => {
+                let mut word = self.lower_expr(env, inner, nonblocking).resize(width);
+                word.signed = signed;
+                word
+            }
+            ExprKind::Mux(cond, then_value, else_value) => {
+                let cond = self.lower_expr(env, cond, nonblocking).bits[0];
+                let then_value = self.lower_expr(env, then_value, nonblocking);
+                let else_value = self.lower_expr(env, else_value, nonblocking);
+                self.mux(cond, else_value, then_value)
+            }
+        }
+    }
+
+    /// Follows aliases to the bit that drives a net
+    fn resolve(&self, mut bit: Bit) -> Bit {
+        // a combinational loop has no driver
+        for _ in 0..=self.aliases.len() {
+            match bit {
+                Bit::Net(net) => match self.aliases.get(&net) {
+                    Some(alias) => bit = *alias,
+                    None => return bit,
+                },
+                _ => return bit,
+            }
+        }
+        bit
+    }
+
+    fn bits_json(&self, bits: &[Bit]) -> Json {
+        Json::Array(
+            bits.iter()
+                .map(|bit| self.resolve(*bit).to_json())
+                .collect(),
+        )
+    }
+
+    fn to_json(&self, is_top: bool) -> Json {
+        let module = self.module;
+        let mut attributes = pragma_attributes(&module.pragmas);
+        if is_top {
+            attributes.insert("top".to_string(), param(1));
+        }
+
+        let mut ports = Map::new();
+        let mut netnames = Map::new();
+        for (signal, nets) in module.signal_indices().zip(self.nets.iter()) {
+            let direction = match module[signal].kind {
+                SignalKind::Input => Some(Direction::Input),
+                SignalKind::Output => Some(Direction::Output),
+                SignalKind::InOut => Some(Direction::InOut),
+                SignalKind::Local => None,
+            };
+            if let Some(direction) = direction {
+                ports.insert(
+                    module[signal].name.clone(),
+                    json!({
+                        "direction": direction.name(),
+                        "bits": self.bits_json(&nets.bits),
+                    }),
+                );
+            }
+            let mut net_attributes = pragma_attributes(&module[signal].pragmas);
+            if let Some(state_machine) = module
+                .state_machines
+                .iter()
+                .find(|state_machine| state_machine.signal == signal)
+            {
+                net_attributes.insert(
+                    "fsm_encoding".to_string(),
+                    json!(fsm_encoding(state_machine.encoding)),
+                );
+            }
+            netnames.insert(
+                module[signal].name.clone(),
+                json!({
+                    "hide_name": 0,
+                    "bits": self.bits_json(&nets.bits),
+                    "attributes": net_attributes,
+                }),
+            );
+        }
+
+        let cells = self
+            .cells
+            .iter()
+            .map(|cell| {
+                let port_directions = cell
+                    .ports
+                    .iter()
+                    .map(|(name, direction, _)| (name.clone(), json!(direction.name())))
+                    .collect::<Map<_, _>>();
+                let connections = cell
+                    .ports
+                    .iter()
+                    .map(|(name, _, bits)| (name.clone(), self.bits_json(bits)))
+                    .collect::<Map<_, _>>();
+                (
+                    cell.name.clone(),
+                    json!({
+                        "hide_name": cell.hide_name as usize,
+                        "type": cell.ty,
+                        "parameters": cell.parameters,
+                        "attributes": cell.attributes,
+                        "port_directions": port_directions,
+                        "connections": connections,
+                    }),
+                )
+            })
+            .collect::<Map<_, _>>();
+
+        json!({
+            "attributes": attributes,
+            "ports": ports,
+            "cells": cells,
+            "netnames": netnames,
+        })
+    }
+}
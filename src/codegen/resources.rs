@@ -0,0 +1,350 @@
+//! Estimates the flip-flops, LUTs, and memory bits of each instance of a design,
+//! so unexpectedly large submodules stand out before running vendor tools.
+//!
+//! The estimate is a tree of instances, each counting itself and everything below it,
+//! so the first row is the whole design. It assumes 4-input LUTs:
+//! * a flip-flop for each bit of a register, unless it's a vector written at a computed index,
+//!   which is a memory of as many bits that selects the words it reads and writes itself
+//! * a LUT for each bit of a bitwise operation, sum, difference, ordering, or 2:1 multiplexer,
+//!   including those that select the values assigned under a condition
+//! * a tree of LUTs for equality and reductions, a multiplexer for each bit and stage of a shift
+//!   by a computed amount, and a LUT for each pair of bits of a product or quotient
+//!
+//! Constants and resizing are free, and operations of constants are assumed to be folded.
+//! Black boxes are defined outside of the design, so they aren't counted.
+use std::ops::AddAssign;
+
+use fxhash::FxHashMap as HashMap;
+
+use super::Output;
+use crate::ir::{
+    BinaryOp, Connection, Expr, ExprKind, Ir, Module, ModuleIndex, Process, ProcessKind,
+    SignalIndex, Stmt, Ty, UnaryOp,
+};
+
+const COLUMNS: &[&str] = &["flip-flops", "LUTs", "memory bits"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resources {
+    pub flip_flops: u128,
+    pub luts: u128,
+    pub memory_bits: u128,
+}
+
+impl AddAssign for Resources {
+    fn add_assign(&mut self, other: Resources) {
+        self.flip_flops += other.flip_flops;
+        self.luts += other.luts;
+        self.memory_bits += other.memory_bits;
+    }
+}
+
+pub struct ResourcesEmitter<'a, 'ast> {
+    pub ir: &'a Ir<'ast>,
+}
+
+impl<'a, 'ast> ResourcesEmitter<'a, 'ast> {
+    /// A single report named after the top module
+    pub fn emit(&self) -> Vec<Output> {
+        let ir = self.ir;
+        let top = match ir.module_indices().next() {
+            Some(top) => top,
+            None => return vec![],
+        };
+        let own = ir
+            .module_indices()
+            .map(|module| (module, module_resources(&ir[module])))
+            .collect::<HashMap<_, _>>();
+        let mut rows = vec![];
+        self.add_rows(&own, top, ir[top].specialized_name(), "", &mut rows);
+
+        let label_width = rows
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .chain(Some("instance".len()))
+            .max()
+            .unwrap_or_default();
+        let column_widths = COLUMNS
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                rows.iter()
+                    .map(|(_, counts)| counts[i].to_string().len())
+                    .chain(Some(column.len()))
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let mut content = format!("{:<1$}", "instance", label_width);
+        for (column, width) in COLUMNS.iter().zip(column_widths.iter()) {
+            content += &format!("  {:>1$}", column, width);
+        }
+        content.push('\n');
+        for (label, counts) in rows {
+            content += &label;
+            content += &" ".repeat(label_width - label.chars().count());
+            for (count, width) in counts.iter().zip(column_widths.iter()) {
+                content += &format!("  {:>1$}", count, width);
+            }
+            content.push('\n');
+        }
+        vec![Output {
+            name: ir[top].specialized_name(),
+            content,
+            entity: Some(ir[top].entity),
+            input: None,
+        }]
+    }
+
+    /// Adds the row of an instance of `module` and those of the instances below it,
+    /// returning their total
+    fn add_rows(
+        &self,
+        own: &HashMap<ModuleIndex, Resources>,
+        module: ModuleIndex,
+        label: String,
+        prefix: &str,
+        rows: &mut Vec<(String, [u128; 3])>,
+    ) -> Resources {
+        let row = rows.len();
+        rows.push((label, [0; 3]));
+        let mut total = own[&module];
+        let instances = &self.ir[module].instances;
+        for (i, instance) in instances.iter().enumerate() {
+            let is_last = i + 1 == instances.len();
+            let child = &self.ir[instance.module];
+            let label = format!(
+                "{}{}{}: {}{}",
+                prefix,
+                if is_last { "└─ " } else { "├─ " },
+                instance.name,
+                child.specialized_name(),
+                if child.is_black_box() {
+                    " (black box)"
+                } else {
+                    ""
+                }
+            );
+            let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+            total += self.add_rows(own, instance.module, label, &child_prefix, rows);
+        }
+        rows[row].1 = [total.flip_flops, total.luts, total.memory_bits];
+        total
+    }
+}
+
+/// The resources of a module itself, leaving out its instances
+fn module_resources(module: &Module) -> Resources {
+    let mut resources = Resources::default();
+    let memories = memories(module);
+    for signal in module.signal_indices() {
+        if !module.is_register(signal) {
+            continue;
+        }
+        let bits = module[signal].ty.width();
+        if memories.contains(&signal) {
+            resources.memory_bits += bits;
+        } else {
+            resources.flip_flops += bits;
+        }
+    }
+    for process in module.processes.iter() {
+        resources.luts += bodies(process)
+            .map(|body| stmts_luts(module, &memories, body))
+            .sum::<u128>();
+    }
+    for instance in module.instances.iter() {
+        for connection in instance.connections.iter() {
+            if let Connection::Input { value, .. } = connection {
+                resources.luts += expr_luts(&memories, value);
+            }
+        }
+    }
+    resources
+}
+
+/// The registers that are vectors written at a computed index, which are memories
+pub fn memories(module: &Module) -> Vec<SignalIndex> {
+    module
+        .signal_indices()
+        .filter(|signal| {
+            module.is_register(*signal)
+                && module[*signal].ty.is_vector()
+                && module
+                    .processes
+                    .iter()
+                    .any(|process| bodies(process).any(|body| writes_computed_index(body, *signal)))
+        })
+        .collect()
+}
+
+/// The statements of a process, including those of its reset
+pub fn bodies(process: &Process) -> impl Iterator<Item = &[Stmt]> {
+    let reset = match &process.kind {
+        ProcessKind::Clocked(clocking) => clocking.reset.as_ref(),
+        ProcessKind::Comb => None,
+    };
+    reset
+        .map(|reset| reset.body.as_slice())
+        .into_iter()
+        .chain(Some(process.body.as_slice()))
+}
+
+/// Whether any statement assigns to an element of a signal at an index that isn't constant
+fn writes_computed_index(stmts: &[Stmt], signal: SignalIndex) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::Assign {
+            target,
+            index: Some(index),
+            ..
+        } => *target == signal && index.as_const().is_none(),
+        Stmt::Assign { .. } | Stmt::Property { .. } => false,
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            writes_computed_index(then_branch, signal) || writes_computed_index(else_branch, signal)
+        }
+    })
+}
+
+/// Memories select the words they read and write themselves
+fn stmts_luts(module: &Module, memories: &[SignalIndex], stmts: &[Stmt]) -> u128 {
+    stmts
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::Assign { index, value, .. } => {
+                index
+                    .as_ref()
+                    .map(|index| expr_luts(memories, index))
+                    .unwrap_or_default()
+                    + expr_luts(memories, value)
+            }
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                // a multiplexer for each signal assigned in either branch
+                let mut targets = vec![];
+                assigned(then_branch, &mut targets);
+                assigned(else_branch, &mut targets);
+                let muxes = targets
+                    .iter()
+                    .filter(|(target, _)| !memories.contains(target))
+                    .map(|(target, partial)| {
+                        let ty = &module[*target].ty;
+                        if *partial {
+                            ty.elem().width()
+                        } else {
+                            ty.width()
+                        }
+                    })
+                    .sum::<u128>();
+                expr_luts(memories, cond)
+                    + stmts_luts(module, memories, then_branch)
+                    + stmts_luts(module, memories, else_branch)
+                    + muxes
+            }
+            Stmt::Property { .. } => 0,
+        })
+        .sum()
+}
+
+/// The signals statements assign to, and whether only elements of them are
+fn assigned(stmts: &[Stmt], targets: &mut Vec<(SignalIndex, bool)>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign { target, index, .. } => {
+                let partial = index.is_some();
+                if !targets.contains(&(*target, partial)) {
+                    targets.push((*target, partial));
+                }
+            }
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                assigned(then_branch, targets);
+                assigned(else_branch, targets);
+            }
+            Stmt::Property { .. } => {}
+        }
+    }
+}
+
+/// LUTs for a single output of `inputs` bits
+fn lut_tree(inputs: u128) -> u128 {
+    (inputs.saturating_sub(1) + 2) / 3
+}
+
+/// The LUTs of an expression, including its operands
+pub fn expr_luts(memories: &[SignalIndex], expr: &Expr) -> u128 {
+    if expr.as_const().is_some() {
+        return 0;
+    }
+    let width = expr.ty.width();
+    match &expr.kind {
+        ExprKind::Const(_) | ExprKind::Signal(_) => 0,
+        ExprKind::Resize(inner) => expr_luts(memories, inner),
+        ExprKind::Unary(op, operand) => {
+            expr_luts(memories, operand)
+                + match op {
+                    // absorbed by whatever reads it
+                    UnaryOp::Not => 0,
+                    UnaryOp::Neg => width,
+                    UnaryOp::ReduceOr => lut_tree(operand.ty.width()),
+                }
+        }
+        ExprKind::Binary(op, left, right) => {
+            let operand_width = left.ty.width();
+            let variable_bits = [left, right]
+                .iter()
+                .filter(|operand| operand.as_const().is_none())
+                .map(|operand| operand.ty.width())
+                .sum::<u128>();
+            expr_luts(memories, left)
+                + expr_luts(memories, right)
+                + match op {
+                    BinaryOp::BitAnd
+                    | BinaryOp::BitOr
+                    | BinaryOp::BitXor
+                    | BinaryOp::Add
+                    | BinaryOp::Sub
+                    | BinaryOp::Lt
+                    | BinaryOp::Le
+                    | BinaryOp::Gt
+                    | BinaryOp::Ge => operand_width,
+                    BinaryOp::And | BinaryOp::Or => 1,
+                    BinaryOp::Eq | BinaryOp::Ne => lut_tree(variable_bits),
+                    BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => operand_width * operand_width,
+                    BinaryOp::Shl | BinaryOp::Shr if right.as_const().is_some() => 0,
+                    BinaryOp::Shl | BinaryOp::Shr => {
+                        let stages =
+                            u128::from(128 - operand_width.saturating_sub(1).leading_zeros());
+                        operand_width * stages.min(right.ty.width())
+                    }
+                }
+        }
+        ExprKind::Index(base, index) => {
+            let is_memory =
+                matches!(base.kind, ExprKind::Signal(signal) if memories.contains(&signal));
+            let muxes = match (&base.ty, index.as_const()) {
+                _ if is_memory => 0,
+                (_, Some(_)) => 0,
+                (Ty::Vector(elem, len), None) => elem.width() * len.saturating_sub(1),
+                (ty, None) => ty.width().saturating_sub(1),
+            };
+            expr_luts(memories, base) + expr_luts(memories, index) + muxes
+        }
+        ExprKind::Mux(cond, then_value, else_value) => {
+            expr_luts(memories, cond)
+                + expr_luts(memories, then_value)
+                + expr_luts(memories, else_value)
+                + width
+        }
+    }
+}
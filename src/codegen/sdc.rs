@@ -0,0 +1,278 @@
+//! Emits timing constraints for the clocks of a design as a single SDC file,
+//! as read by Vivado, Quartus, and OpenSTA.
+//!
+//! The clock of each register is traced through connections and plain copies to what drives it:
+//! a top level input, an output of a black box like a PLL, or logic of the design.
+//! Each becomes a clock named after the signal it drives in the emitted HDL, with the period of
+//! the frequency declared with `#[clock(freq = "100MHz")]` on a signal along the way.
+//! Clocks driven from different places are asynchronous to each other,
+//! as the clock domain crossings between them are already synchronized.
+//!
+//! Timing paths are ignored for signals marked `#[false_path]`: the paths to registers and
+//! top level outputs, from top level inputs, and through everything else.
+//! Signals below the top are named by their path of instance names, like `sync/meta`.
+use super::Output;
+use crate::analysis::signal_graph::SignalKind;
+use crate::attribute;
+use crate::error::*;
+use crate::ir::{Connection, ExprKind, Ir, Module, ModuleIndex, ProcessKind, SignalIndex, Stmt};
+
+pub struct SdcEmitter<'a, 'ast> {
+    pub ir: &'a Ir<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+/// An instance in the hierarchy of the design
+struct Node {
+    module: ModuleIndex,
+    /// The instance names from the top, joined by `/`
+    path: String,
+    /// The node of the parent and the position of the instance among its instances
+    parent: Option<(usize, usize)>,
+    /// In the order of the instances of the module
+    children: Vec<usize>,
+}
+
+/// A signal of an instance in the hierarchy
+type NodeSignal = (usize, SignalIndex);
+
+struct Clock {
+    /// What drives the clock
+    root: NodeSignal,
+    /// The signal the clock is named after
+    named: NodeSignal,
+    /// An SDC object query, like `[get_ports clk]`
+    target: String,
+    /// In hertz
+    frequency: Option<f64>,
+}
+
+impl<'a, 'ast> SdcEmitter<'a, 'ast> {
+    /// A single constraints file named after the top module
+    pub fn emit(&mut self) -> Vec<Output> {
+        let ir = self.ir;
+        let top = match ir.module_indices().next() {
+            Some(top) => top,
+            None => return vec![],
+        };
+        let nodes = hierarchy(ir, top);
+
+        let mut clocks: Vec<Clock> = vec![];
+        for (node, Node { module, .. }) in nodes.iter().enumerate() {
+            for process in ir[*module].processes.iter() {
+                let clocking = match &process.kind {
+                    ProcessKind::Clocked(clocking) => clocking,
+                    ProcessKind::Comb => continue,
+                };
+                let trace = trace(ir, &nodes, (node, clocking.clock));
+                let frequency = trace.iter().find_map(|(node, signal)| {
+                    attribute::clock_frequency(ir[nodes[*node].module][*signal].attrs)
+                        .and_then(|(_, frequency)| frequency)
+                });
+                let root = trace[trace.len() - 1];
+                if let Some(clock) = clocks.iter_mut().find(|clock| clock.root == root) {
+                    clock.frequency = clock.frequency.or(frequency);
+                    continue;
+                }
+                let (root_node, root_signal) = root;
+                let (named, target) = if ir[nodes[root_node].module].is_black_box() {
+                    // named after the signal of the parent the output drives
+                    let pin = format!(
+                        "{}/{}",
+                        nodes[root_node].path, ir[nodes[root_node].module][root_signal].name
+                    );
+                    (trace[trace.len() - 2], format!("[get_pins {}]", pin))
+                } else if root_node == 0 && ir[top][root_signal].kind == SignalKind::Input {
+                    (root, format!("[get_ports {}]", ir[top][root_signal].name))
+                } else {
+                    (
+                        root,
+                        format!("[get_nets {}]", hierarchical_name(ir, &nodes, root)),
+                    )
+                };
+                clocks.push(Clock {
+                    root,
+                    named,
+                    target,
+                    frequency,
+                });
+            }
+        }
+
+        let mut content = String::new();
+        let mut constrained = vec![];
+        for clock in clocks.iter() {
+            let name = hierarchical_name(ir, &nodes, clock.named);
+            match clock.frequency {
+                Some(frequency) => {
+                    content += &format!(
+                        "create_clock -name {} -period {:.3} {}\n",
+                        name,
+                        1e9 / frequency,
+                        clock.target
+                    );
+                    constrained.push(name);
+                }
+                None => {
+                    let (node, signal) = clock.named;
+                    let signal = &ir[nodes[node].module][signal];
+                    self.errors
+                        .push(unconstrained_clock(signal.file, signal.ident));
+                }
+            }
+        }
+        if constrained.len() > 1 {
+            content += "\nset_clock_groups -asynchronous";
+            for name in constrained {
+                content += &format!(" -group [get_clocks {}]", name);
+            }
+            content.push('\n');
+        }
+
+        let mut false_paths = vec![];
+        for (node, Node { module, .. }) in nodes.iter().enumerate() {
+            let module = &ir[*module];
+            for signal in module.signal_indices() {
+                if attribute::find_flag(module[signal].attrs, "false_path").is_none() {
+                    continue;
+                }
+                let name = hierarchical_name(ir, &nodes, (node, signal));
+                false_paths.push(match (node, module[signal].kind) {
+                    (0, SignalKind::Input) => format!("set_false_path -from [get_ports {}]", name),
+                    (0, SignalKind::Output) => format!("set_false_path -to [get_ports {}]", name),
+                    _ if module.is_register(signal) => {
+                        format!("set_false_path -to [get_cells {}]", name)
+                    }
+                    _ => format!("set_false_path -through [get_nets {}]", name),
+                });
+            }
+        }
+        if !false_paths.is_empty() {
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            for false_path in false_paths {
+                content += &false_path;
+                content.push('\n');
+            }
+        }
+
+        vec![Output {
+            name: ir[top].specialized_name(),
+            content,
+            entity: Some(ir[top].entity),
+            input: None,
+        }]
+    }
+}
+
+/// Every instance of the design, breadth first from the top
+fn hierarchy(ir: &Ir, top: ModuleIndex) -> Vec<Node> {
+    let mut nodes = vec![Node {
+        module: top,
+        path: String::new(),
+        parent: None,
+        children: vec![],
+    }];
+    let mut node = 0;
+    while node < nodes.len() {
+        for (i, instance) in ir[nodes[node].module].instances.iter().enumerate() {
+            let path = if node == 0 {
+                instance.name.clone()
+            } else {
+                format!("{}/{}", nodes[node].path, instance.name)
+            };
+            let child = nodes.len();
+            nodes.push(Node {
+                module: instance.module,
+                path,
+                parent: Some((node, i)),
+                children: vec![],
+            });
+            nodes[node].children.push(child);
+        }
+        node += 1;
+    }
+    nodes
+}
+
+/// The name of a signal in the emitted HDL, prefixed with the path of its instance
+fn hierarchical_name(ir: &Ir, nodes: &[Node], (node, signal): NodeSignal) -> String {
+    let name = &ir[nodes[node].module][signal].name;
+    if node == 0 {
+        name.clone()
+    } else {
+        format!("{}/{}", nodes[node].path, name)
+    }
+}
+
+/// The signals a clock passes through, from `start` back to what drives it
+fn trace(ir: &Ir, nodes: &[Node], start: NodeSignal) -> Vec<NodeSignal> {
+    let mut trace = vec![start];
+    loop {
+        let (node, signal) = trace[trace.len() - 1];
+        let module = &ir[nodes[node].module];
+        let next = match (module[signal].kind, nodes[node].parent) {
+            // up to the signal of the parent connected to the input
+            (SignalKind::Input, Some((parent, instance))) => ir[nodes[parent].module].instances
+                [instance]
+                .connections
+                .iter()
+                .find_map(|connection| match connection {
+                    Connection::Input { port, value } if *port == signal => match &value.kind {
+                        ExprKind::Signal(source) => Some((parent, *source)),
+                        _ => None,
+                    },
+                    _ => None,
+                }),
+            (SignalKind::Input, None) => None,
+            // down to the output of an instance driving it
+            _ => module
+                .instances
+                .iter()
+                .zip(nodes[node].children.iter())
+                .find_map(|(instance, child)| {
+                    instance
+                        .connections
+                        .iter()
+                        .find_map(|connection| match connection {
+                            Connection::Output {
+                                port,
+                                signal: driven,
+                            } if *driven == signal => Some((*child, *port)),
+                            _ => None,
+                        })
+                })
+                .or_else(|| copied_from(module, signal).map(|source| (node, source))),
+        };
+        match next {
+            Some(next) if !trace.contains(&next) => trace.push(next),
+            _ => break trace,
+        }
+    }
+}
+
+/// The signal that a signal is a plain copy of, like `clk_b = clk_a;`
+fn copied_from(module: &Module, signal: SignalIndex) -> Option<SignalIndex> {
+    let mut values = module
+        .processes
+        .iter()
+        .filter(|process| matches!(process.kind, ProcessKind::Comb))
+        .flat_map(|process| process.body.iter())
+        .filter_map(|stmt| match stmt {
+            Stmt::Assign {
+                target,
+                index: None,
+                value,
+                ..
+            } if *target == signal => Some(value),
+            _ => None,
+        });
+    match (values.next(), values.next()) {
+        (Some(value), None) => match &value.kind {
+            ExprKind::Signal(source) => Some(*source),
+            _ => None,
+        },
+        _ => None,
+    }
+}
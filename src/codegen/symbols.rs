@@ -0,0 +1,67 @@
+//! Lists the items declared in each file of the design, nested by scope, with their spans.
+//! `--emit symbols` writes them as JSON, one object per file in the order the files were found.
+
+use rhdl::ast::Span;
+use serde_json::{json, Value as Json};
+
+use super::Output;
+use crate::find_file::{FileGraph, FileId};
+use crate::resolution::{Resolver, Symbol};
+
+pub struct SymbolsEmitter<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub file_graph: &'a FileGraph,
+}
+
+impl<'a, 'ast> SymbolsEmitter<'a, 'ast> {
+    /// A single report named `symbols`
+    pub fn emit(&self) -> Vec<Output> {
+        let files = self
+            .file_graph
+            .iter()
+            .map(|file| {
+                json!({
+                    "file": self.file_graph.inner.name(*file).to_string_lossy(),
+                    "symbols": self
+                        .resolver
+                        .symbols(*file)
+                        .iter()
+                        .map(|symbol| self.symbol_json(*file, symbol))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+        vec![Output {
+            name: "symbols".to_string(),
+            content: serde_json::to_string_pretty(&files).unwrap() + "\n",
+            entity: None,
+            input: None,
+        }]
+    }
+
+    fn symbol_json(&self, file: FileId, symbol: &Symbol) -> Json {
+        json!({
+            "name": symbol.name,
+            "kind": symbol.kind.name(),
+            "detail": symbol.detail,
+            "range": self.range(file, symbol.span),
+            "name_range": self.range(file, symbol.name_span),
+            "children": symbol
+                .children
+                .iter()
+                .map(|child| self.symbol_json(file, child))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// `line:column-line:column`
+    fn range(&self, file: FileId, span: Span) -> String {
+        match self.file_graph.line_columns(file, span.into()) {
+            Some((start, end)) => format!(
+                "{}:{}-{}:{}",
+                start.line, start.column, end.line, end.column
+            ),
+            None => String::new(),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Reports the pipeline latency and logic depth of the outputs of each module of a design,
+//! so long combinational paths can be found before running vendor timing analysis.
+//!
+//! Each module has a table with a row per output:
+//! * `stages` is the fewest registers between an input of the module and the output, the cycles
+//!   it takes a change of an input to reach it, or `-` if no input reaches it
+//! * `depth` is the most operators on a combinational path between registers or ports that leads
+//!   to the output, counting each operator, multiplexer, and element selected by a computed index
+//!   as a level, along with a multiplexer for each condition a value is assigned under
+//!
+//! Paths are followed through the instances below a module, except those through black boxes,
+//! which are defined outside of the design. Constants and resizing are free.
+use std::collections::VecDeque;
+
+use fxhash::FxHashMap as HashMap;
+
+use super::Output;
+use crate::analysis::signal_graph::SignalKind;
+use crate::ir::{Connection, Expr, ExprKind, Ir, ModuleIndex, ProcessKind, SignalIndex, Stmt};
+
+const COLUMNS: &[&str] = &["stages", "depth"];
+
+pub struct TimingReportEmitter<'a, 'ast> {
+    pub ir: &'a Ir<'ast>,
+}
+
+impl<'a, 'ast> TimingReportEmitter<'a, 'ast> {
+    /// A single report named after the top module, with a table for each module
+    pub fn emit(&self) -> Vec<Output> {
+        let ir = self.ir;
+        let top = match ir.module_indices().next() {
+            Some(top) => top,
+            None => return vec![],
+        };
+        let mut tables = vec![];
+        for module in ir.module_indices() {
+            if ir[module].is_black_box() {
+                continue;
+            }
+            let mut graph = Graph::default();
+            graph.add_module(ir, module);
+            let stages = graph.stages();
+            let arrivals = graph.arrivals();
+            let incoming = graph.incoming(true);
+            let rows = ir[module]
+                .signal_indices()
+                .filter(|signal| ir[module][*signal].kind == SignalKind::Output)
+                .map(|signal| {
+                    let node = signal.index();
+                    (
+                        ir[module][signal].name.clone(),
+                        [
+                            stages[node]
+                                .map_or_else(|| "-".to_string(), |stages| stages.to_string()),
+                            depth(&incoming, &arrivals, node).to_string(),
+                        ],
+                    )
+                })
+                .collect::<Vec<_>>();
+            tables.push(table(&ir[module].specialized_name(), rows));
+        }
+        vec![Output {
+            name: ir[top].specialized_name(),
+            content: tables.join("\n"),
+            entity: Some(ir[top].entity),
+            input: None,
+        }]
+    }
+}
+
+fn table(name: &str, rows: Vec<(String, [String; 2])>) -> String {
+    let label_width = rows
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .chain(Some("output".len()))
+        .max()
+        .unwrap_or_default();
+    let column_widths = COLUMNS
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|(_, cells)| cells[i].len())
+                .chain(Some(column.len()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut content = format!("{}\n{:<2$}", name, "output", label_width);
+    for (column, width) in COLUMNS.iter().zip(column_widths.iter()) {
+        content += &format!("  {:>1$}", column, width);
+    }
+    content.push('\n');
+    for (label, cells) in rows {
+        content += &format!("{:<1$}", label, label_width);
+        for (cell, width) in cells.iter().zip(column_widths.iter()) {
+            content += &format!("  {:>1$}", cell, width);
+        }
+        content.push('\n');
+    }
+    content
+}
+
+/// A signal drives another through `depth` levels of logic, and a register if `registered`
+struct Edge {
+    from: usize,
+    to: usize,
+    depth: u128,
+    registered: bool,
+}
+
+/// The signals of a module and the instances below it, with the signals of the module first
+#[derive(Default)]
+struct Graph {
+    node_count: usize,
+    inputs: Vec<usize>,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    /// Adds the signals of `module` and its instances, returning the node of its first signal
+    fn add_module(&mut self, ir: &Ir, module: ModuleIndex) -> usize {
+        let base = self.node_count;
+        let module = &ir[module];
+        self.node_count += module.signals.len();
+        if base == 0 {
+            self.inputs = module
+                .signal_indices()
+                .filter(|signal| module[*signal].kind == SignalKind::Input)
+                .map(SignalIndex::index)
+                .collect();
+        }
+        for process in module.processes.iter() {
+            // the values of an asynchronous reset are constants
+            let registered = matches!(process.kind, ProcessKind::Clocked(_));
+            self.add_stmts(base, &process.body, &mut vec![], registered);
+        }
+        for instance in module.instances.iter() {
+            let child = self.add_module(ir, instance.module);
+            for connection in instance.connections.iter() {
+                match connection {
+                    Connection::Input { port, value } => {
+                        for (from, depth) in sources(value) {
+                            self.edges.push(Edge {
+                                from: base + from.index(),
+                                to: child + port.index(),
+                                depth,
+                                registered: false,
+                            });
+                        }
+                    }
+                    Connection::Output { port, signal } => self.edges.push(Edge {
+                        from: child + port.index(),
+                        to: base + signal.index(),
+                        depth: 0,
+                        registered: false,
+                    }),
+                    // bidirectional pins are driven outside of the design
+                    Connection::InOut { .. } => {}
+                }
+            }
+        }
+        base
+    }
+
+    /// Adds the edges of assignments under the conditions of the `if`s around them
+    fn add_stmts(
+        &mut self,
+        base: usize,
+        stmts: &[Stmt],
+        conds: &mut Vec<HashMap<SignalIndex, u128>>,
+        registered: bool,
+    ) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Assign {
+                    target,
+                    index,
+                    value,
+                    ..
+                } => {
+                    let muxes = conds.len() as u128;
+                    let mut drivers = HashMap::default();
+                    let mut add = |from: SignalIndex, depth: u128| {
+                        let max = drivers.entry(from).or_insert(depth);
+                        *max = (*max).max(depth);
+                    };
+                    for (from, depth) in sources(value) {
+                        add(from, depth + muxes);
+                    }
+                    // the element written is selected by a decoder
+                    for (from, depth) in index.iter().flat_map(sources) {
+                        add(from, depth + 1 + muxes);
+                    }
+                    for (i, cond) in conds.iter().enumerate() {
+                        for (from, depth) in cond.iter() {
+                            add(*from, depth + muxes - i as u128);
+                        }
+                    }
+                    for (from, depth) in drivers {
+                        self.edges.push(Edge {
+                            from: base + from.index(),
+                            to: base + target.index(),
+                            depth,
+                            registered,
+                        });
+                    }
+                }
+                Stmt::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => {
+                    conds.push(sources(cond));
+                    self.add_stmts(base, then_branch, conds, registered);
+                    self.add_stmts(base, else_branch, conds, registered);
+                    conds.pop();
+                }
+                Stmt::Property { .. } => {}
+            }
+        }
+    }
+
+    /// The edges into each node, leaving out those through registers unless `registered`
+    fn incoming(&self, registered: bool) -> Vec<Vec<&Edge>> {
+        let mut incoming = vec![vec![]; self.node_count];
+        for edge in self.edges.iter() {
+            if registered || !edge.registered {
+                incoming[edge.to].push(edge);
+            }
+        }
+        incoming
+    }
+
+    /// The fewest registers between an input of the module and each node
+    fn stages(&self) -> Vec<Option<u128>> {
+        let mut outgoing = vec![vec![]; self.node_count];
+        for edge in self.edges.iter() {
+            outgoing[edge.from].push(edge);
+        }
+        let mut stages = vec![None; self.node_count];
+        let mut queue = VecDeque::new();
+        for input in self.inputs.iter() {
+            stages[*input] = Some(0);
+            queue.push_back(*input);
+        }
+        // a breadth-first search that visits the nodes reached without a register first
+        while let Some(node) = queue.pop_front() {
+            let node_stages = stages[node].unwrap();
+            for edge in outgoing[node].iter() {
+                let to_stages = node_stages + edge.registered as u128;
+                if stages[edge.to].map_or(true, |stages| to_stages < stages) {
+                    stages[edge.to] = Some(to_stages);
+                    if edge.registered {
+                        queue.push_back(edge.to);
+                    } else {
+                        queue.push_front(edge.to);
+                    }
+                }
+            }
+        }
+        stages
+    }
+
+    /// The most levels of logic between a register or port and each node
+    fn arrivals(&self) -> Vec<u128> {
+        let incoming = self.incoming(false);
+        let mut arrivals = vec![None; self.node_count];
+        for node in 0..self.node_count {
+            arrival(&incoming, &mut arrivals, node);
+        }
+        arrivals
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect()
+    }
+}
+
+/// The deepest path between registers or ports that leads to `node`,
+/// including the paths to the registers it depends on
+fn depth(incoming: &[Vec<&Edge>], arrivals: &[u128], node: usize) -> u128 {
+    let mut visited = vec![false; incoming.len()];
+    visited[node] = true;
+    let mut stack = vec![node];
+    let mut depth = arrivals[node];
+    while let Some(node) = stack.pop() {
+        for edge in incoming[node].iter() {
+            if edge.registered {
+                depth = depth.max(arrivals[edge.from] + edge.depth);
+            }
+            if !visited[edge.from] {
+                visited[edge.from] = true;
+                stack.push(edge.from);
+            }
+        }
+    }
+    depth
+}
+
+/// Memoized, counting a combinational loop back to a node as a path of its own
+fn arrival(incoming: &[Vec<&Edge>], arrivals: &mut Vec<Option<u128>>, node: usize) -> u128 {
+    if let Some(arrival) = arrivals[node] {
+        return arrival;
+    }
+    arrivals[node] = Some(0);
+    let arrival = incoming[node]
+        .iter()
+        .map(|edge| arrival(incoming, arrivals, edge.from) + edge.depth)
+        .max()
+        .unwrap_or_default();
+    arrivals[node] = Some(arrival);
+    arrival
+}
+
+/// The signals an expression reads, with the most levels of logic between each and its value
+fn sources(expr: &Expr) -> HashMap<SignalIndex, u128> {
+    let mut sources = HashMap::default();
+    add_sources(expr, 0, &mut sources);
+    sources
+}
+
+fn add_sources(expr: &Expr, depth: u128, sources: &mut HashMap<SignalIndex, u128>) {
+    match &expr.kind {
+        ExprKind::Const(_) => {}
+        ExprKind::Signal(signal) => {
+            let max = sources.entry(*signal).or_insert(depth);
+            *max = (*max).max(depth);
+        }
+        ExprKind::Resize(operand) => add_sources(operand, depth, sources),
+        // a constant index selects wires
+        ExprKind::Index(base, index) if index.as_const().is_some() => {
+            add_sources(base, depth, sources)
+        }
+        ExprKind::Unary(_, operand) => add_sources(operand, depth + 1, sources),
+        ExprKind::Binary(_, left, right) | ExprKind::Index(left, right) => {
+            add_sources(left, depth + 1, sources);
+            add_sources(right, depth + 1, sources);
+        }
+        ExprKind::Mux(cond, then_value, else_value) => {
+            add_sources(cond, depth + 1, sources);
+            add_sources(then_value, depth + 1, sources);
+            add_sources(else_value, depth + 1, sources);
+        }
+    }
+}
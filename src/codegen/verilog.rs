@@ -0,0 +1,675 @@
+//! Lowers the intermediate representation of a design to synthesizable Verilog-2001.
+//!
+//! Each module of the representation becomes one Verilog module, named after its generic values
+//! like `Counter_WIDTH_8`. Processes are lowered as follows:
+//! * combinational processes become an `always @*` block with blocking assignments
+//! * clocked processes become an `always @(posedge clk)` block with nonblocking assignments
+//! * an asynchronous reset adds the reset signal to the sensitivity list
+//!
+//! Black boxes are instantiated with their generic values as parameters, but not defined.
+//! Synthesis pragmas become attributes of the declarations of signals and modules,
+//! or of the instances of black boxes.
+//!
+//! State registers are declared with the `fsm_encoding` attribute of their enum's encoding,
+//! so synthesis tools keep it.
+use rhdl::ast::Spanned;
+
+use super::cache::{self, Cache};
+use super::Output;
+use crate::analysis::signal_graph::{Edge, SignalKind};
+use crate::attribute::Pragma;
+use crate::elaboration::{const_eval::ConstValue, enums::Encoding};
+use crate::error::*;
+use crate::ir::{
+    ident_name, BinaryOp, Connection, Expr, ExprKind, Ir, Module, ProcessKind, SignalIndex, Stmt,
+    Ty, UnaryOp,
+};
+
+const BACKEND: &str = "Verilog";
+const INDENT: &str = "    ";
+
+/// Reserved words of Verilog-2001, which can only be used as escaped identifiers
+const KEYWORDS: &[&str] = &[
+    "always",
+    "and",
+    "assign",
+    "automatic",
+    "begin",
+    "buf",
+    "bufif0",
+    "bufif1",
+    "case",
+    "casex",
+    "casez",
+    "cell",
+    "cmos",
+    "config",
+    "deassign",
+    "default",
+    "defparam",
+    "design",
+    "disable",
+    "edge",
+    "else",
+    "end",
+    "endcase",
+    "endconfig",
+    "endfunction",
+    "endgenerate",
+    "endmodule",
+    "endprimitive",
+    "endspecify",
+    "endtable",
+    "endtask",
+    "event",
+    "for",
+    "force",
+    "forever",
+    "fork",
+    "function",
+    "generate",
+    "genvar",
+    "highz0",
+    "highz1",
+    "if",
+    "ifnone",
+    "incdir",
+    "include",
+    "initial",
+    "inout",
+    "input",
+    "instance",
+    "integer",
+    "join",
+    "large",
+    "liblist",
+    "library",
+    "localparam",
+    "macromodule",
+    "medium",
+    "module",
+    "nand",
+    "negedge",
+    "nmos",
+    "nor",
+    "noshowcancelled",
+    "not",
+    "notif0",
+    "notif1",
+    "or",
+    "output",
+    "parameter",
+    "pmos",
+    "posedge",
+    "primitive",
+    "pull0",
+    "pull1",
+    "pulldown",
+    "pullup",
+    "pulsestyle_onevent",
+    "pulsestyle_ondetect",
+    "rcmos",
+    "real",
+    "realtime",
+    "reg",
+    "release",
+    "repeat",
+    "rnmos",
+    "rpmos",
+    "rtran",
+    "rtranif0",
+    "rtranif1",
+    "scalared",
+    "showcancelled",
+    "signed",
+    "small",
+    "specify",
+    "specparam",
+    "strong0",
+    "strong1",
+    "supply0",
+    "supply1",
+    "table",
+    "task",
+    "time",
+    "tran",
+    "tranif0",
+    "tranif1",
+    "tri",
+    "tri0",
+    "tri1",
+    "triand",
+    "trior",
+    "trireg",
+    "unsigned",
+    "use",
+    "vectored",
+    "wait",
+    "wand",
+    "weak0",
+    "weak1",
+    "while",
+    "wire",
+    "wor",
+    "xnor",
+    "xor",
+];
+
+/// Escape a name that isn't a legal Verilog identifier, i.e. `reg` becomes `\reg `
+pub fn escape(name: &str) -> String {
+    let is_legal = name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or_default()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        && !KEYWORDS.contains(&name);
+    if is_legal {
+        name.to_string()
+    } else {
+        format!("\\{} ", name)
+    }
+}
+
+/// A generic value as the value of a parameter
+fn parameter(value: &ConstValue) -> String {
+    match value {
+        ConstValue::Int(value) => value.to_string(),
+        ConstValue::Bool(value) => u8::from(*value).to_string(),
+    }
+}
+
+/// A synthesis pragma as a Verilog attribute, like `max_fanout = 8` or `keep = "true"`
+fn attribute(pragma: &Pragma) -> String {
+    match pragma.value {
+        Some(value) => format!("{} = {}", pragma.name, value),
+        None => format!("{} = \"true\"", pragma.name),
+    }
+}
+
+/// Synthesis pragmas as an attribute instance, like `(* keep_hierarchy = "true" *)`
+fn attributes(pragmas: &[Pragma]) -> Option<String> {
+    if pragmas.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "(* {} *)",
+        pragmas.iter().map(attribute).collect::<Vec<_>>().join(", ")
+    ))
+}
+
+/// The `fsm_encoding` attribute value for an encoding, as Vivado reads it
+fn fsm_encoding(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Binary => "sequential",
+        Encoding::OneHot => "one_hot",
+        Encoding::Gray => "gray",
+    }
+}
+
+pub struct VerilogEmitter<'a, 'ast> {
+    pub ir: &'a Ir<'ast>,
+    pub cache: &'a Cache,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> VerilogEmitter<'a, 'ast> {
+    /// One module for each module of the design, from the top down,
+    /// leaving out black boxes which are defined elsewhere. Modules that haven't changed since
+    /// they were last emitted aren't written again.
+    pub fn emit(&mut self) -> Vec<Output> {
+        let ir = self.ir;
+        ir.modules
+            .iter()
+            .filter(|module| !module.is_black_box())
+            .map(|module| {
+                let name = module.specialized_name();
+                let input = cache::module_input(ir, module);
+                if let Some(content) = self.cache.get(&name, &input) {
+                    return Output {
+                        name,
+                        content,
+                        entity: Some(module.entity),
+                        input: Some(input),
+                    };
+                }
+                let mut module_writer = ModuleWriter {
+                    ir,
+                    module,
+                    errors: &mut *self.errors,
+                    out: String::new(),
+                };
+                module_writer.write_module();
+                Output {
+                    name,
+                    content: module_writer.out,
+                    entity: Some(module.entity),
+                    input: Some(input),
+                }
+            })
+            .collect()
+    }
+}
+
+struct ModuleWriter<'a, 'ast> {
+    ir: &'a Ir<'ast>,
+    module: &'a Module<'ast>,
+    errors: &'a mut Vec<Diagnostic>,
+    out: String,
+}
+
+impl<'a, 'ast> ModuleWriter<'a, 'ast> {
+    fn line(&mut self, depth: usize, line: &str) {
+        for _ in 0..depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn name(&self, signal: SignalIndex) -> String {
+        escape(&self.module[signal].name)
+    }
+
+    fn write_module(&mut self) {
+        let module = self.module;
+        if let Some(attributes) = attributes(&module.pragmas) {
+            self.out.push_str(&format!("{}\n", attributes));
+        }
+        self.out
+            .push_str(&format!("module {}", escape(&module.specialized_name())));
+
+        let mut ports = vec![];
+        let mut declarations = vec![];
+        for signal in module.signal_indices() {
+            let kind = module[signal].kind;
+            let (elem, unpacked) = match &module[signal].ty {
+                Ty::Vector(elem, len) if !elem.is_vector() && kind == SignalKind::Local => {
+                    (&**elem, Some(format!("[0:{}]", len - 1)))
+                }
+                // ports can't be memories in Verilog-2001
+                Ty::Vector(..) => {
+                    self.errors.push(unsupported_by_backend(
+                        module[signal].file,
+                        module[signal].ident.span(),
+                        BACKEND,
+                    ));
+                    continue;
+                }
+                ty => (ty, None),
+            };
+            let mut parts = vec![];
+            let mut attributes = module[signal]
+                .pragmas
+                .iter()
+                .map(attribute)
+                .collect::<Vec<_>>();
+            if let Some(state_machine) = module
+                .state_machines
+                .iter()
+                .find(|state_machine| state_machine.signal == signal)
+            {
+                attributes.insert(
+                    0,
+                    format!(
+                        "fsm_encoding = \"{}\"",
+                        fsm_encoding(state_machine.encoding)
+                    ),
+                );
+            }
+            if !attributes.is_empty() {
+                parts.push(format!("(* {} *)", attributes.join(", ")));
+            }
+            match kind {
+                SignalKind::Input => parts.push("input".to_string()),
+                SignalKind::Output => parts.push("output".to_string()),
+                SignalKind::InOut => parts.push("inout".to_string()),
+                SignalKind::Local => {}
+            }
+            match (kind, module.is_assigned(signal)) {
+                (SignalKind::Local, true) | (SignalKind::Output, true) => {
+                    parts.push("reg".to_string())
+                }
+                (SignalKind::Local, false) => parts.push("wire".to_string()),
+                _ => {}
+            }
+            if elem.is_signed() {
+                parts.push("signed".to_string());
+            }
+            if elem.width() > 1 {
+                parts.push(format!("[{}:0]", elem.width() - 1));
+            }
+            parts.push(self.name(signal));
+            parts.extend(unpacked);
+            let declaration = parts.join(" ");
+            if kind == SignalKind::Local {
+                declarations.push(declaration);
+            } else {
+                ports.push(declaration);
+            }
+        }
+        if ports.is_empty() {
+            self.out.push_str(" ();\n");
+        } else {
+            self.out.push_str(" (\n");
+            let ports = ports
+                .iter()
+                .map(|port| format!("{}{}", INDENT, port))
+                .collect::<Vec<String>>();
+            self.out.push_str(&ports.join(",\n"));
+            self.out.push_str("\n);\n");
+        }
+        for declaration in declarations {
+            self.line(1, &format!("{};", declaration));
+        }
+
+        for process in module.processes.iter() {
+            self.out.push('\n');
+            match &process.kind {
+                ProcessKind::Comb => {
+                    self.line(1, "always @* begin");
+                    self.write_stmts(&process.body, false, 2);
+                }
+                ProcessKind::Clocked(clocking) => {
+                    let clock = format!("{} {}", edge(clocking.edge), self.name(clocking.clock));
+                    match &clocking.reset {
+                        Some(reset) => {
+                            let reset_name = self.name(reset.signal);
+                            self.line(
+                                1,
+                                &format!(
+                                    "always @({} or {} {}) begin",
+                                    clock,
+                                    if reset.active_high {
+                                        "posedge"
+                                    } else {
+                                        "negedge"
+                                    },
+                                    reset_name
+                                ),
+                            );
+                            self.line(
+                                2,
+                                &format!(
+                                    "if ({}{}) begin",
+                                    if reset.active_high { "" } else { "!" },
+                                    reset_name
+                                ),
+                            );
+                            self.write_stmts(&reset.body, true, 3);
+                            self.line(2, "end else begin");
+                            self.write_stmts(&process.body, true, 3);
+                            self.line(2, "end");
+                        }
+                        None => {
+                            self.line(1, &format!("always @({}) begin", clock));
+                            self.write_stmts(&process.body, true, 2);
+                        }
+                    }
+                }
+            }
+            self.line(1, "end");
+        }
+
+        for instance in module.instances.iter() {
+            let child = &self.ir[instance.module];
+            let connections = instance
+                .connections
+                .iter()
+                .map(|connection| {
+                    let (port, value) = match connection {
+                        Connection::Input { port, value } => (*port, self.lower_expr(value)),
+                        Connection::Output { port, signal }
+                        | Connection::InOut { port, signal } => (*port, self.name(*signal)),
+                    };
+                    format!(
+                        "{}{}.{}({})",
+                        INDENT,
+                        INDENT,
+                        escape(&child[port].name),
+                        value
+                    )
+                })
+                .collect::<Vec<String>>();
+            // black boxes take their generic values as parameters
+            let line = if child.is_black_box() {
+                let parameters = child
+                    .generics
+                    .iter()
+                    .map(|(ident, value)| {
+                        format!(".{}({})", escape(&ident_name(ident)), parameter(value))
+                    })
+                    .collect::<Vec<_>>();
+                if parameters.is_empty() {
+                    format!("{} {} (", escape(&child.name), escape(&instance.name))
+                } else {
+                    format!(
+                        "{} #({}) {} (",
+                        escape(&child.name),
+                        parameters.join(", "),
+                        escape(&instance.name)
+                    )
+                }
+            } else {
+                format!(
+                    "{} {} (",
+                    escape(&child.specialized_name()),
+                    escape(&instance.name)
+                )
+            };
+            self.out.push('\n');
+            // the pragmas of modules that are defined elsewhere go on their instances
+            if let Some(attributes) = attributes(&child.pragmas).filter(|_| child.is_black_box()) {
+                self.line(1, &attributes);
+            }
+            if connections.is_empty() {
+                self.line(1, &format!("{});", line));
+            } else {
+                self.line(1, &line);
+                self.out.push_str(&connections.join(",\n"));
+                self.out.push('\n');
+                self.line(1, ");");
+            }
+        }
+        self.out.push_str("endmodule\n");
+    }
+
+    fn write_stmts(&mut self, stmts: &[Stmt], nonblocking: bool, depth: usize) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Assign {
+                    target,
+                    index,
+                    value,
+                    ..
+                } => {
+                    let mut target = self.name(*target);
+                    if let Some(index) = index {
+                        target = format!("{}[{}]", target, self.lower_expr(index));
+                    }
+                    let line = format!(
+                        "{} {} {};",
+                        target,
+                        if nonblocking { "<=" } else { "=" },
+                        self.lower_expr(value)
+                    );
+                    self.line(depth, &line);
+                }
+                Stmt::If { .. } => self.write_if(stmt, nonblocking, depth, false),
+                Stmt::Property { .. } => {}
+            }
+        }
+    }
+
+    fn write_if(&mut self, stmt: &Stmt, nonblocking: bool, depth: usize, is_else_if: bool) {
+        let (cond, then_branch, else_branch) = match stmt {
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => (cond, then_branch, else_branch),
+            Stmt::Assign { .. } | Stmt::Property { .. } => unreachable!(),
+        };
+        let cond = self.lower_expr(cond);
+        if is_else_if {
+            // continues the `end else` of the previous line
+            self.out.push_str(&format!(" if ({}) begin\n", cond));
+        } else {
+            self.line(depth, &format!("if ({}) begin", cond));
+        }
+        self.write_stmts(then_branch, nonblocking, depth + 1);
+        match else_branch.as_slice() {
+            [] => self.line(depth, "end"),
+            [else_if @ Stmt::If { .. }] => {
+                for _ in 0..depth {
+                    self.out.push_str(INDENT);
+                }
+                self.out.push_str("end else");
+                self.write_if(else_if, nonblocking, depth, true);
+            }
+            _ => {
+                self.line(depth, "end else begin");
+                self.write_stmts(else_branch, nonblocking, depth + 1);
+                self.line(depth, "end");
+            }
+        }
+    }
+
+    fn lower_expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Const(value) => literal(*value, &expr.ty),
+            ExprKind::Signal(signal) => self.name(*signal),
+            ExprKind::Unary(op, operand) => {
+                let operand = self.lower_operand(operand);
+                match op {
+                    UnaryOp::Not => format!("~{}", operand),
+                    UnaryOp::Neg => format!("-{}", operand),
+                    UnaryOp::ReduceOr => format!("|{}", operand),
+                }
+            }
+            ExprKind::Binary(op, left, right) => format!(
+                "{} {} {}",
+                self.lower_operand(left),
+                match op {
+                    BinaryOp::Shr if left.ty.is_signed() => ">>>",
+                    op => binary_operator(*op),
+                },
+                self.lower_operand(right)
+            ),
+            ExprKind::Index(base, index) => match base.kind {
+                ExprKind::Signal(_) | ExprKind::Index(..) => {
+                    format!("{}[{}]", self.lower_expr(base), self.lower_expr(index))
+                }
+                // only signals can be indexed in Verilog-2001
+                _ => format!(
+                    "({} >> {}) & 1'b1",
+                    self.lower_operand(base),
+                    self.lower_operand(index)
+                ),
+            },
+            ExprKind::Resize(inner) => self.lower_resize(inner, &expr.ty),
+            ExprKind::Mux(cond, then_value, else_value) => format!(
+                "{} ? {} : {}",
+                self.lower_operand(cond),
+                self.lower_operand(then_value),
+                self.lower_operand(else_value)
+            ),
+        }
+    }
+
+    /// Operands of operators are parenthesized so that Verilog precedence doesn't matter
+    fn lower_operand(&self, expr: &Expr) -> String {
+        let lowered = self.lower_expr(expr);
+        match expr.kind {
+            ExprKind::Unary(..) | ExprKind::Binary(..) | ExprKind::Mux(..) => {
+                format!("({})", lowered)
+            }
+            ExprKind::Index(ref base, _)
+                if !matches!(base.kind, ExprKind::Signal(_) | ExprKind::Index(..)) =>
+            {
+                format!("({})", lowered)
+            }
+            _ => lowered,
+        }
+    }
+
+    /// Verilog extends operands to the width of the whole expression before evaluating them,
+    /// so operands that are extended are made self-determined with a concatenation first,
+    /// which keeps arithmetic wrapping around at the width of the operands
+    fn lower_resize(&self, inner: &Expr, ty: &Ty) -> String {
+        let (from, to) = (inner.ty.width(), ty.width());
+        let is_leaf = matches!(inner.kind, ExprKind::Signal(_) | ExprKind::Const(_));
+        let self_determined = match (is_leaf, inner.ty.is_signed()) {
+            (true, _) => self.lower_expr(inner),
+            (false, false) => format!("{{{}}}", self.lower_expr(inner)),
+            (false, true) => format!("$signed({{{}}})", self.lower_expr(inner)),
+        };
+        if to > from {
+            return match (inner.ty.is_signed(), ty.is_signed()) {
+                (false, false) | (true, true) => self_determined,
+                (false, true) => format!("$signed({{1'b0, {}}})", self.lower_expr(inner)),
+                (true, false) => format!("$unsigned({} + {}'sd0)", self_determined, to),
+            };
+        }
+        // part-selects and masks are unsigned
+        let (value, is_signed) = if to < from {
+            let truncated = match inner.kind {
+                ExprKind::Signal(_) if to == 1 => format!("{}[0]", self.lower_expr(inner)),
+                ExprKind::Signal(_) => format!("{}[{}:0]", self.lower_expr(inner), to - 1),
+                _ => format!("({} & {{{}{{1'b1}}}})", self_determined, to),
+            };
+            (truncated, false)
+        } else {
+            (self_determined, inner.ty.is_signed())
+        };
+        match (is_signed, ty.is_signed()) {
+            (false, true) => format!("$signed({})", value),
+            (true, false) => format!("$unsigned({})", value),
+            _ => value,
+        }
+    }
+}
+
+fn edge(edge: Edge) -> &'static str {
+    match edge {
+        Edge::Rising => "posedge",
+        Edge::Falling => "negedge",
+    }
+}
+
+/// A sized literal, i.e. `8'd1` or `-4'sd2`
+fn literal(value: i128, ty: &Ty) -> String {
+    match (ty, value) {
+        (Ty::UInt(1), 0) => "1'b0".to_string(),
+        (Ty::UInt(1), 1) => "1'b1".to_string(),
+        (Ty::SInt(width), value) if value < 0 => format!("-{}'sd{}", width, value.unsigned_abs()),
+        (Ty::SInt(width), value) => format!("{}'sd{}", width, value),
+        (ty, value) => format!("{}'d{}", ty.width(), value),
+    }
+}
+
+fn binary_operator(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Rem => "%",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+        BinaryOp::BitXor => "^",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitOr => "|",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::Eq => "==",
+        BinaryOp::Lt => "<",
+        BinaryOp::Le => "<=",
+        BinaryOp::Ne => "!=",
+        BinaryOp::Ge => ">=",
+        BinaryOp::Gt => ">",
+    }
+}
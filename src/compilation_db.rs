@@ -0,0 +1,82 @@
+//! `--json-compilation-db <file>`: a compilation database like clang's `compile_commands.json`,
+//! with an entry for each file found for the design, so indexers and editor plugins can find how a
+//! file is compiled inside a larger build.
+//!
+//! Each file is compiled by the command for the root file of the design, with the flags that
+//! change which files are found and how they're read. Libraries found in `RHDL_PATH` are found by
+//! that command too, as long as it's run with the same environment. Files that aren't on disk,
+//! like those of the standard library, aren't listed.
+use std::fs;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::error::{write_output, Diagnostic};
+use crate::events;
+use crate::find_file::{absolute, FileGraph};
+use crate::Options;
+
+/// Writes the compilation database of the design in `file_graph` to `path`
+pub fn write(file_graph: &FileGraph, options: &Options, path: &Path, errors: &mut Vec<Diagnostic>) {
+    let directory = absolute(Path::new("."));
+    let arguments = arguments(file_graph, options);
+    let entries = file_graph
+        .iter()
+        .filter_map(|file| file_graph.absolute_path(*file))
+        .map(|file| {
+            json!({
+                "directory": directory.to_string_lossy(),
+                "file": file.to_string_lossy(),
+                "arguments": arguments,
+            })
+        })
+        .collect::<Vec<_>>();
+    let content = serde_json::to_string_pretty(&entries).unwrap() + "\n";
+    match fs::write(path, content) {
+        Ok(()) => events::artifact_written(path),
+        Err(err) => errors.push(write_output(&path.to_string_lossy(), err)),
+    }
+}
+
+/// The command the design is compiled by, with absolute paths so it can be run from anywhere. A
+/// root read from standard input is `-`.
+fn arguments(file_graph: &FileGraph, options: &Options) -> Vec<String> {
+    let root = file_graph
+        .roots
+        .first()
+        .and_then(|root| file_graph.absolute_path(*root))
+        .map_or("-".to_string(), |root| root.to_string_lossy().to_string());
+    let mut arguments = vec!["rhdlc".to_string(), root];
+    let mut flag = |name: &str, value: Option<String>| {
+        arguments.push(name.to_string());
+        arguments.extend(value);
+    };
+    if let Some(top) = &options.top {
+        flag("--top", Some(top.clone()));
+    }
+    for (name, path) in options.libraries.iter() {
+        flag(
+            "--lib",
+            Some(format!("{}={}", name, absolute(path).to_string_lossy())),
+        );
+    }
+    if let Some(src_dir) = &options.src_dir {
+        flag(
+            "--src-dir",
+            Some(absolute(src_dir).to_string_lossy().to_string()),
+        );
+    }
+    if let Some(src_extension) = &options.src_extension {
+        flag("--src-ext", Some(src_extension.clone()));
+    }
+    if options.lossy_utf8 {
+        flag("--lossy-utf8", None);
+    }
+    if let Some(max_file_size) = options.max_file_size {
+        flag("--max-file-size", Some(max_file_size.to_string()));
+    }
+    if let Some(max_files) = options.max_files {
+        flag("--max-files", Some(max_files.to_string()));
+    }
+    arguments
+}
@@ -0,0 +1,183 @@
+//! `.rhdlc.toml`: the defaults of a project, read from the directory of the root file or the
+//! closest of its ancestors with one, unless `--no-config` is given. The command line overrides
+//! them. Paths in it are relative to it.
+//!
+//! ```toml
+//! emit = ["verilog=build", "sdc"]
+//! color = "auto"
+//! error_format = "human"
+//! library_path = ["../libraries"]
+//!
+//! [lib]
+//! uart = "../uart"
+//!
+//! [lints]
+//! unused_pub = "allow"
+//! synthesizability = "deny"
+//!
+//! [fmt]
+//! width = 80
+//!
+//! [diagnostics]
+//! width = 100
+//! context_lines = 2
+//! ascii = true
+//! anonymize_paths = true
+//! ```
+//!
+//! `[diagnostics]` sets how they're rendered, see [`crate::report::Render`].
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::Value as Toml;
+
+use crate::attribute::{LintLevel, LintLevels};
+use crate::codegen::Emit;
+use crate::find_file::parse_library;
+use crate::report::{ErrorFormat, Render};
+
+/// The name of the config file
+pub const CONFIG_FILE: &str = ".rhdlc.toml";
+
+/// The defaults read from the config file
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// Output to generate when there's no `--emit`
+    pub emit: Vec<Emit>,
+    /// `auto`, `always`, or `never`, when there's no `--color`
+    pub color: Option<String>,
+    /// The format of diagnostics when there's no `--error-format`
+    pub error_format: Option<ErrorFormat>,
+    /// Libraries registered by name, after those of `--lib`
+    pub libraries: Vec<(String, PathBuf)>,
+    /// Directories to find libraries in, after those in `RHDL_PATH`
+    pub library_path: Vec<PathBuf>,
+    /// The levels of lints, which attributes on items override
+    pub lints: LintLevels,
+    /// The width `rhdlc fmt` breaks lines at when there's no `--width`
+    pub fmt_width: Option<usize>,
+    /// How diagnostics are rendered, which their flags override
+    pub render: Render,
+}
+
+impl Config {
+    /// Reads the config file in `dir` or the closest of its ancestors, or the default if none of
+    /// them has one
+    pub fn find(dir: &Path) -> Result<Self, String> {
+        let dir = crate::find_file::absolute(dir);
+        for ancestor in dir.ancestors() {
+            let path = ancestor.join(CONFIG_FILE);
+            if let Ok(content) = fs::read_to_string(&path) {
+                return Self::parse(&content, ancestor)
+                    .map_err(|err| format!("invalid {}: {}", path.display(), err));
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Parses a config file in `dir`
+    pub fn parse(content: &str, dir: &Path) -> Result<Self, String> {
+        let config = content.parse::<Toml>().map_err(|err| err.to_string())?;
+        let string = |value: &Toml, key: &str| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("`{}` isn't a string", key))
+        };
+        let strings = |key: &str| match config.get(key) {
+            None => Ok(vec![]),
+            Some(Toml::Array(values)) => values.iter().map(|value| string(value, key)).collect(),
+            Some(_) => Err(format!("`{}` isn't an array of strings", key)),
+        };
+        let one_of = |key: &str, values: &[&str]| match config.get(key) {
+            None => Ok(None),
+            Some(value) => match value.as_str() {
+                Some(value) if values.contains(&value) => Ok(Some(value.to_string())),
+                _ => Err(format!("`{}` isn't one of: {}", key, values.join(", "))),
+            },
+        };
+        let table = |key: &str| match config.get(key) {
+            None => Ok(vec![]),
+            Some(Toml::Table(table)) => Ok(table.iter().collect::<Vec<_>>()),
+            Some(_) => Err(format!("`{}` isn't a table", key)),
+        };
+
+        let emit = strings("emit")?
+            .iter()
+            .map(|emit| emit.parse::<Emit>())
+            .map(|emit| {
+                emit.map(|mut emit| {
+                    emit.dir = emit.dir.map(|emit_dir| dir.join(emit_dir));
+                    emit
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let error_format = one_of("error_format", &["human", "sarif"])?.map(|format| {
+            if format == "sarif" {
+                ErrorFormat::Sarif
+            } else {
+                ErrorFormat::Human
+            }
+        });
+        let mut libraries = vec![];
+        for (name, path) in table("lib")? {
+            let path = string(path, &format!("lib.{}", name))?;
+            let (name, path) = parse_library(&format!("{}={}", name, path))?;
+            libraries.push((name, dir.join(path)));
+        }
+        let mut lints = LintLevels::default();
+        for (lint, level) in table("lints")? {
+            let level = match level.as_str() {
+                Some("allow") => LintLevel::Allow,
+                Some("warn") => LintLevel::Warn,
+                Some("deny") => LintLevel::Deny,
+                _ => return Err(format!("`lints.{}` isn't one of: allow, warn, deny", lint)),
+            };
+            lints.insert(lint.clone(), level);
+        }
+        let fmt_width = match config.get("fmt").and_then(|fmt| fmt.get("width")) {
+            None => None,
+            Some(width) => Some(
+                width
+                    .as_integer()
+                    .filter(|width| *width > 0)
+                    .ok_or_else(|| "`fmt.width` isn't a positive number".to_string())?
+                    as usize,
+            ),
+        };
+        let diagnostics = config.get("diagnostics");
+        let count = |key: &str| match diagnostics.and_then(|diagnostics| diagnostics.get(key)) {
+            None => Ok(None),
+            Some(value) => value
+                .as_integer()
+                .filter(|count| *count >= 0)
+                .map(|count| Some(count as usize))
+                .ok_or_else(|| format!("`diagnostics.{}` isn't a number", key)),
+        };
+        let flag = |key: &str| match diagnostics.and_then(|diagnostics| diagnostics.get(key)) {
+            None => Ok(false),
+            Some(value) => value
+                .as_bool()
+                .ok_or_else(|| format!("`diagnostics.{}` isn't true or false", key)),
+        };
+        let render = Render {
+            width: count("width")?,
+            context_lines: count("context_lines")?,
+            ascii: flag("ascii")?,
+            anonymize_paths: flag("anonymize_paths")?,
+        };
+        Ok(Self {
+            emit,
+            color: one_of("color", &["auto", "always", "never"])?,
+            error_format,
+            libraries,
+            library_path: strings("library_path")?
+                .into_iter()
+                .map(|path| dir.join(path))
+                .collect(),
+            lints,
+            fmt_width,
+            render,
+        })
+    }
+}
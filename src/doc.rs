@@ -0,0 +1,509 @@
+//! `rhdlc doc`: static HTML documentation for a design.
+//!
+//! Each module gets a page listing the items declared in it: entities with a table of their ports
+//! and a list of their architectures, structs with a table of their fields, enums with their
+//! variants, and the signatures of everything else. Doc comments are rendered as Markdown, and the
+//! paths in the types of ports and fields link to the items they resolve to. The page of the root
+//! module is `index.html`, and the pages of other modules are named by their path, like
+//! `cpu.alu.html`.
+use std::fmt::Write;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{html, Parser};
+use rhdl::ast::{PortDir, Spanned, Type};
+
+use crate::error::{write_output, Diagnostic};
+use crate::events;
+use crate::find_file::FileGraph;
+use crate::resolution::{
+    architecture_name, doc_comment, Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver,
+    SymbolKind,
+};
+
+/// The sections of a module's page after its submodules, in order
+const SECTIONS: &[(SymbolKind, &str)] = &[
+    (SymbolKind::Entity, "Entities"),
+    (SymbolKind::Struct, "Structs"),
+    (SymbolKind::Enum, "Enums"),
+    (SymbolKind::Trait, "Traits"),
+    (SymbolKind::Type, "Types"),
+    (SymbolKind::Const, "Constants"),
+    (SymbolKind::Fn, "Functions"),
+];
+
+const STYLE: &str = "\
+body { display: flex; margin: 0; font-family: sans-serif; line-height: 1.5; }
+nav { min-width: 14em; padding: 1em; background: #f4f4f4; }
+nav ul { padding-left: 1em; list-style: none; }
+main { flex: 1; max-width: 60em; padding: 1em 2em; }
+section { margin-bottom: 2em; }
+h3 a { color: inherit; text-decoration: none; }
+pre { padding: 0.5em; background: #f4f4f4; overflow-x: auto; }
+table { border-collapse: collapse; }
+td, th { padding: 0.25em 1em 0.25em 0; text-align: left; vertical-align: top; }
+td p, li p { margin: 0; }
+";
+
+/// `rhdlc doc`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Doc {
+    /// The directory the pages are written to
+    pub output: PathBuf,
+}
+
+/// Writes a page for each module of the design to the output directory, along with their
+/// stylesheet
+pub fn document(
+    resolver: &Resolver,
+    file_graph: &FileGraph,
+    doc: &Doc,
+    errors: &mut Vec<Diagnostic>,
+) {
+    if let Err(err) = fs::create_dir_all(&doc.output) {
+        errors.push(write_output(&doc.output.to_string_lossy(), err));
+        return;
+    }
+    let documenter = Documenter {
+        resolver,
+        file_graph,
+    };
+    let mut pages = documenter.pages();
+    pages.push(("style.css".to_string(), STYLE.to_string()));
+    for (name, content) in pages {
+        let path = doc.output.join(name);
+        match fs::write(&path, content) {
+            Ok(()) => events::artifact_written(&path),
+            Err(err) => errors.push(write_output(&path.to_string_lossy(), err)),
+        }
+    }
+}
+
+struct Documenter<'a, 'ast> {
+    resolver: &'a Resolver<'ast>,
+    file_graph: &'a FileGraph,
+}
+
+impl<'a, 'ast> Documenter<'a, 'ast> {
+    /// The file name and content of each module's page
+    fn pages(&self) -> Vec<(String, String)> {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let mut nav = String::new();
+        for root in resolution_graph.roots.iter() {
+            nav += "<ul>";
+            self.module_tree(*root, &mut nav);
+            nav += "</ul>";
+        }
+        resolution_graph
+            .node_indices()
+            .filter(|node| is_module(&resolution_graph[*node]))
+            .map(|module| (self.page_name(module), self.page(module, &nav)))
+            .collect()
+    }
+
+    /// A module and the modules nested in it as a list item of the navigation
+    fn module_tree(&self, module: ResolutionIndex, html: &mut String) {
+        write!(
+            html,
+            "<li><a href=\"{}\">{}</a>",
+            escape(&self.page_name(module)),
+            escape(&self.module_name(module))
+        )
+        .unwrap();
+        let submodules = self.submodules(module);
+        if !submodules.is_empty() {
+            *html += "<ul>";
+            for submodule in submodules {
+                self.module_tree(submodule, html);
+            }
+            *html += "</ul>";
+        }
+        *html += "</li>";
+    }
+
+    fn page(&self, module: ResolutionIndex, nav: &str) -> String {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let title = match &resolution_graph[module] {
+            ResolutionNode::Root { .. } => self.module_name(module),
+            _ => format!("mod {}", resolution_graph.path(module)),
+        };
+        let mut html = String::new();
+        writeln!(html, "<!DOCTYPE html>").unwrap();
+        writeln!(html, "<html>").unwrap();
+        writeln!(html, "<head>").unwrap();
+        writeln!(html, "<meta charset=\"utf-8\">").unwrap();
+        writeln!(html, "<title>{}</title>", escape(&title)).unwrap();
+        writeln!(html, "<link rel=\"stylesheet\" href=\"style.css\">").unwrap();
+        writeln!(html, "</head>").unwrap();
+        writeln!(html, "<body>").unwrap();
+        writeln!(html, "<nav>{}</nav>", nav).unwrap();
+        writeln!(html, "<main>").unwrap();
+        writeln!(html, "<h1>{}</h1>", escape(&title)).unwrap();
+        if let Some(docs) = self.module_docs(module) {
+            html += &markdown(&docs);
+        }
+
+        let submodules = self.submodules(module);
+        if !submodules.is_empty() {
+            writeln!(html, "<h2>Modules</h2>").unwrap();
+            writeln!(html, "<ul>").unwrap();
+            for submodule in submodules {
+                writeln!(
+                    html,
+                    "<li><a href=\"{}\"><code>{}</code></a>{}</li>",
+                    escape(&self.page_name(submodule)),
+                    escape(&self.module_name(submodule)),
+                    self.module_docs(submodule)
+                        .map(|docs| markdown(&docs))
+                        .unwrap_or_default()
+                )
+                .unwrap();
+            }
+            writeln!(html, "</ul>").unwrap();
+        }
+
+        for (kind, heading) in SECTIONS {
+            let mut items = self
+                .children(module)
+                .into_iter()
+                .filter(|child| self.kind(*child) == Some(*kind))
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                continue;
+            }
+            items.sort_by_key(|item| resolution_graph[*item].name().map(ToString::to_string));
+            writeln!(html, "<h2>{}</h2>", heading).unwrap();
+            for item in items {
+                html += &self.item(item);
+            }
+        }
+        writeln!(html, "</main>").unwrap();
+        writeln!(html, "</body>").unwrap();
+        writeln!(html, "</html>").unwrap();
+        html
+    }
+
+    /// A section describing an item declared in a module
+    fn item(&self, item: ResolutionIndex) -> String {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let hover = match self.resolver.describe(item) {
+            Some(hover) => hover,
+            None => return String::new(),
+        };
+        let name = resolution_graph[item]
+            .name()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let anchor = anchor(hover.kind, &name);
+        let mut html = String::new();
+        writeln!(html, "<section id=\"{}\">", escape(&anchor)).unwrap();
+        writeln!(
+            html,
+            "<h3><a href=\"#{}\">{} <code>{}</code></a></h3>",
+            escape(&anchor),
+            hover.kind.name(),
+            escape(&name)
+        )
+        .unwrap();
+        // ports, fields, and variants are listed separately
+        let signature = match hover.kind {
+            SymbolKind::Entity | SymbolKind::Struct | SymbolKind::Enum => hover
+                .signature
+                .split('{')
+                .next()
+                .unwrap_or_default()
+                .trim_end(),
+            _ => hover.signature.as_str(),
+        };
+        writeln!(html, "<pre><code>{}</code></pre>", escape(signature)).unwrap();
+        if let Some(docs) = &hover.docs {
+            html += &markdown(docs);
+        }
+
+        match &resolution_graph[item] {
+            ResolutionNode::Branch {
+                branch: Branch::Entity(item_entity),
+                ..
+            } => {
+                if !item_entity.ports.is_empty() {
+                    writeln!(html, "<h4>Ports</h4>").unwrap();
+                    writeln!(html, "<table>").unwrap();
+                    for port in item_entity.ports.iter() {
+                        let span: Range<usize> = port.span().into();
+                        writeln!(
+                            html,
+                            "<tr><td>{}</td><td><code>{}</code></td><td><code>{}</code></td><td>{}</td></tr>",
+                            match port.dir {
+                                PortDir::In(_) => "in",
+                                PortDir::Out(_) => "out",
+                                PortDir::InOut(_) => "inout",
+                            },
+                            escape(&port.ident.to_string()),
+                            self.linked_type(item, &port.ty),
+                            doc_comment(self.source(item), span.start)
+                                .map(|docs| markdown(&docs))
+                                .unwrap_or_default()
+                        )
+                        .unwrap();
+                    }
+                    writeln!(html, "</table>").unwrap();
+                }
+
+                let arches = resolution_graph.architectures(item);
+                if !arches.is_empty() {
+                    writeln!(html, "<h4>Architectures</h4>").unwrap();
+                    writeln!(html, "<ul>").unwrap();
+                    for arch in arches.iter().copied() {
+                        writeln!(
+                            html,
+                            "<li>{}{}</li>",
+                            architecture_name(resolution_graph, arch)
+                                .map(|name| format!("<code>{}</code>", escape(&name)))
+                                .unwrap_or_else(|| "unnamed".to_string()),
+                            resolution_graph
+                                .docs(arch)
+                                .map(markdown)
+                                .unwrap_or_default()
+                        )
+                        .unwrap();
+                    }
+                    writeln!(html, "</ul>").unwrap();
+                }
+            }
+            ResolutionNode::Branch {
+                branch: Branch::Struct(_),
+                ..
+            } => {
+                let fields = self.children(item);
+                if !fields.is_empty() {
+                    writeln!(html, "<h4>Fields</h4>").unwrap();
+                    writeln!(html, "<table>").unwrap();
+                    for (i, field) in fields.into_iter().enumerate() {
+                        let (name, ty): (String, &'ast Type) = match &resolution_graph[field] {
+                            ResolutionNode::Leaf {
+                                leaf: Leaf::NamedField(named),
+                                ..
+                            } => (named.ident.to_string(), &named.ty),
+                            ResolutionNode::Leaf {
+                                leaf: Leaf::UnnamedField(unnamed),
+                                ..
+                            } => (i.to_string(), &unnamed.ty),
+                            _ => continue,
+                        };
+                        writeln!(
+                            html,
+                            "<tr><td><code>{}</code></td><td><code>{}</code></td><td>{}</td></tr>",
+                            escape(&name),
+                            self.linked_type(field, ty),
+                            resolution_graph
+                                .docs(field)
+                                .map(markdown)
+                                .unwrap_or_default()
+                        )
+                        .unwrap();
+                    }
+                    writeln!(html, "</table>").unwrap();
+                }
+            }
+            ResolutionNode::Branch {
+                branch: Branch::Enum(_),
+                ..
+            } => {
+                let variants = self
+                    .children(item)
+                    .into_iter()
+                    .filter_map(|variant| self.resolver.describe(variant))
+                    .collect::<Vec<_>>();
+                if !variants.is_empty() {
+                    writeln!(html, "<h4>Variants</h4>").unwrap();
+                    writeln!(html, "<ul>").unwrap();
+                    for variant in variants {
+                        writeln!(
+                            html,
+                            "<li><code>{}</code>{}</li>",
+                            escape(&variant.signature),
+                            variant.docs.map(|docs| markdown(&docs)).unwrap_or_default()
+                        )
+                        .unwrap();
+                    }
+                    writeln!(html, "</ul>").unwrap();
+                }
+            }
+            _ => {}
+        }
+        writeln!(html, "</section>").unwrap();
+        html
+    }
+
+    /// The source of `ty`, a type in `node`, with the paths in it linked to what they resolve to
+    fn linked_type(&self, node: ResolutionIndex, ty: &'ast Type) -> String {
+        let source = self.source(node);
+        let span: Range<usize> = ty.span().into();
+        let mut html = String::new();
+        let mut last = span.start;
+        for (range, target) in self.resolver.type_references(node, ty) {
+            if range.start < last {
+                continue;
+            }
+            html += &escape(&source[last..range.start]);
+            match self.href(target) {
+                Some(href) => write!(
+                    html,
+                    "<a href=\"{}\">{}</a>",
+                    escape(&href),
+                    escape(&source[range.clone()])
+                )
+                .unwrap(),
+                None => html += &escape(&source[range.clone()]),
+            }
+            last = range.end;
+        }
+        html += &escape(&source[last..span.end]);
+        html
+    }
+
+    /// Where `target` is documented: the page of a module, or the section of the item in a
+    /// module that declares it
+    fn href(&self, target: ResolutionIndex) -> Option<String> {
+        let resolution_graph = &self.resolver.resolution_graph;
+        if is_module(&resolution_graph[target]) {
+            return Some(self.page_name(target));
+        }
+        let mut item = target;
+        loop {
+            let parent = resolution_graph[item].parent()?;
+            if is_module(&resolution_graph[parent]) {
+                let kind = self.kind(item)?;
+                let name = resolution_graph[item].name()?;
+                return Some(format!(
+                    "{}#{}",
+                    self.page_name(parent),
+                    anchor(kind, &name.to_string())
+                ));
+            }
+            item = parent;
+        }
+    }
+
+    /// The section a node is listed in, `None` for nodes that aren't listed in a section
+    fn kind(&self, node: ResolutionIndex) -> Option<SymbolKind> {
+        let kind = self.resolver.describe(node)?.kind;
+        if SECTIONS.iter().any(|(section, _)| *section == kind) {
+            Some(kind)
+        } else {
+            None
+        }
+    }
+
+    /// The `///` comments above a module's declaration followed by the `//!` comments at the top
+    /// of its file
+    fn module_docs(&self, module: ResolutionIndex) -> Option<String> {
+        self.resolver
+            .resolution_graph
+            .docs(module)
+            .map(str::to_string)
+    }
+
+    /// The name of a module, where roots are named by their file without its extension
+    fn module_name(&self, module: ResolutionIndex) -> String {
+        let resolution_graph = &self.resolver.resolution_graph;
+        match resolution_graph[module].name() {
+            Some(name) => name.to_string(),
+            None => {
+                let file = self.file_graph.inner.name(resolution_graph.file(module));
+                Path::new(file)
+                    .file_stem()
+                    .unwrap_or(file)
+                    .to_string_lossy()
+                    .to_string()
+            }
+        }
+    }
+
+    /// The file name of a module's page. The pages of roots other than the first are prefixed by
+    /// the name of the root so they don't collide.
+    fn page_name(&self, module: ResolutionIndex) -> String {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let mut root = module;
+        while let Some(parent) = resolution_graph[root].parent() {
+            root = parent;
+        }
+        let mut segments = vec![];
+        if resolution_graph.roots.first() != Some(&root) {
+            segments.push(self.module_name(root));
+        }
+        if root != module {
+            segments.push(resolution_graph.path(module).replace("::", "."));
+        }
+        if segments.is_empty() {
+            "index.html".to_string()
+        } else {
+            format!("{}.html", segments.join("."))
+        }
+    }
+
+    fn submodules(&self, module: ResolutionIndex) -> Vec<ResolutionIndex> {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let mut submodules = self
+            .children(module)
+            .into_iter()
+            .filter(|child| is_module(&resolution_graph[*child]))
+            .collect::<Vec<_>>();
+        submodules
+            .sort_by_key(|submodule| resolution_graph[*submodule].name().map(ToString::to_string));
+        submodules
+    }
+
+    /// The children of a node in declaration order
+    fn children(&self, node: ResolutionIndex) -> Vec<ResolutionIndex> {
+        let mut children = self.resolver.resolution_graph[node]
+            .children()
+            .into_iter()
+            .flat_map(|children| children.values().flatten())
+            .copied()
+            .collect::<Vec<_>>();
+        children.sort();
+        children
+    }
+
+    fn source(&self, node: ResolutionIndex) -> &'a str {
+        self.file_graph[self.resolver.resolution_graph.file(node)].as_ref()
+    }
+}
+
+fn is_module(node: &ResolutionNode) -> bool {
+    matches!(
+        node,
+        ResolutionNode::Root { .. }
+            | ResolutionNode::Branch {
+                branch: Branch::Mod(_),
+                ..
+            }
+    )
+}
+
+/// The id of an item's section, i.e. `entity.Core`
+fn anchor(kind: SymbolKind, name: &str) -> String {
+    format!("{}.{}", kind.name(), name)
+}
+
+fn markdown(text: &str) -> String {
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new(text));
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
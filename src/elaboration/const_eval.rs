@@ -0,0 +1,290 @@
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+use rhdl::ast::{
+    BinOp, Expr, GenericArg, Ident, ItemConst, Lit, Span, Spanned, Stmt, Type, TypePath, UnOp,
+};
+
+use crate::error::*;
+use crate::find_file::FileId;
+use crate::resolution::{Leaf, ResolutionIndex, ResolutionNode, Resolver};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ConstValue {
+    Int(i128),
+    Bool(bool),
+}
+
+impl Display for ConstValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Int(i) => write!(f, "{}", i),
+            ConstValue::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// Evaluates expressions that must be known during elaboration,
+/// like generic arguments and loop bounds
+pub struct ConstEvaluator<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    /// Generic parameters bound by the instance being elaborated
+    pub bindings: &'a [(&'ast Ident, ConstValue)],
+    /// Where names in the expression are resolved from
+    pub scope: ResolutionIndex,
+    /// Consts that are currently being evaluated, to catch cycles
+    pub evaluating: Vec<ResolutionIndex>,
+}
+
+impl<'a, 'ast> ConstEvaluator<'a, 'ast> {
+    pub fn eval(&mut self, expr: &'ast Expr) -> Result<ConstValue, Diagnostic> {
+        let file = self.resolver.resolution_graph.file(self.scope);
+        match expr {
+            Expr::Lit(expr_lit) => match &expr_lit.lit {
+                Lit::Int(lit_int) => parse_int(&lit_int.inner)
+                    .map(ConstValue::Int)
+                    .ok_or_else(|| const_overflow(file, expr.span())),
+                Lit::Bool(lit_bool) => Ok(ConstValue::Bool(lit_bool.value)),
+                _ => Err(not_const_evaluable(file, expr.span())),
+            },
+            Expr::Paren(expr_paren) => self.eval(&expr_paren.expr),
+            // const generic arguments are wrapped in a block, i.e. `Counter::<{ WIDTH * 2 }>`
+            Expr::Block(expr_block) => match expr_block.block.statements.as_slice() {
+                [Stmt::Expr(inner)] => self.eval(inner),
+                _ => Err(not_const_evaluable(file, expr.span())),
+            },
+            Expr::Path(expr_path) => self.eval_path(&expr_path.path),
+            Expr::Unary(expr_unary) => match (&expr_unary.op, self.eval(&expr_unary.expr)?) {
+                (UnOp::Neg(_), ConstValue::Int(i)) => i
+                    .checked_neg()
+                    .map(ConstValue::Int)
+                    .ok_or_else(|| const_overflow(file, expr.span())),
+                (UnOp::Not(_), ConstValue::Int(i)) => {
+                    Ok(ConstValue::Int(not(i, self.operand_type(&expr_unary.expr))))
+                }
+                (UnOp::Not(_), ConstValue::Bool(b)) => Ok(ConstValue::Bool(!b)),
+                _ => Err(const_operand_mismatch(file, expr.span())),
+            },
+            Expr::Binary(expr_binary) => {
+                let left = self.eval(&expr_binary.left)?;
+                let right = self.eval(&expr_binary.right)?;
+                eval_binary(&expr_binary.op, left, right)
+                    .map_err(|cause| cause.diagnostic(file, expr.span()))
+            }
+            _ => Err(not_const_evaluable(file, expr.span())),
+        }
+    }
+
+    /// Generic arguments are parsed as types when they are a bare path, i.e. `Counter<WIDTH>`
+    pub fn eval_generic_arg(&mut self, arg: &'ast GenericArg) -> Result<ConstValue, Diagnostic> {
+        match arg {
+            GenericArg::Const(expr) => self.eval(expr),
+            GenericArg::Type(Type::Path(type_path)) => self.eval_path(type_path),
+            other => Err(not_const_evaluable(
+                self.resolver.resolution_graph.file(self.scope),
+                other.span(),
+            )),
+        }
+    }
+
+    pub fn eval_path(&mut self, path: &'ast TypePath) -> Result<ConstValue, Diagnostic> {
+        let file = self.resolver.resolution_graph.file(self.scope);
+        if path.leading_sep.is_none() && path.segments.len() == 1 {
+            let ident = &path.segments.first().unwrap().ident;
+            if let Some((_, value)) = self
+                .bindings
+                .iter()
+                .rev()
+                .find(|(bound, _)| *bound == ident)
+            {
+                return Ok(*value);
+            }
+        }
+        let (const_idx, item_const) = self
+            .find_const(path)?
+            .ok_or_else(|| not_const_evaluable(file, path.span()))?;
+        if self.evaluating.contains(&const_idx) {
+            return Err(recursive_const(
+                self.resolver.resolution_graph.file(const_idx),
+                &item_const.ident,
+            ));
+        }
+        // consts can't see the generic parameters of the instance using them
+        let mut const_evaluator = ConstEvaluator {
+            resolver: self.resolver,
+            bindings: &[],
+            scope: self.resolver.resolution_graph[const_idx].parent().unwrap(),
+            evaluating: self.evaluating.clone(),
+        };
+        const_evaluator.evaluating.push(const_idx);
+        const_evaluator.eval(&item_const.expr)
+    }
+
+    /// The const at `path`, unless it's ambiguous or isn't a const
+    fn find_const(
+        &self,
+        path: &'ast TypePath,
+    ) -> Result<Option<(ResolutionIndex, &'ast ItemConst)>, Diagnostic> {
+        let consts: Vec<(ResolutionIndex, &'ast ItemConst)> = self
+            .resolver
+            .find_at_type_path(self.scope, path)?
+            .iter()
+            .copied()
+            .filter_map(|i| match &self.resolver.resolution_graph[i] {
+                ResolutionNode::Leaf {
+                    leaf: Leaf::Const(item_const),
+                    ..
+                } => Some((i, *item_const)),
+                _ => None,
+            })
+            .collect();
+        match consts.as_slice() {
+            [found] => Ok(Some(*found)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The primitive type of an integer operand, if it's known from the suffix of a literal or
+    /// the type of a const
+    fn operand_type(&self, expr: &'ast Expr) -> Option<String> {
+        match expr {
+            Expr::Lit(expr_lit) => match &expr_lit.lit {
+                Lit::Int(lit_int) => int_suffix(&lit_int.inner).map(str::to_string),
+                _ => None,
+            },
+            Expr::Paren(expr_paren) => self.operand_type(&expr_paren.expr),
+            Expr::Block(expr_block) => match expr_block.block.statements.as_slice() {
+                [Stmt::Expr(inner)] => self.operand_type(inner),
+                _ => None,
+            },
+            Expr::Unary(expr_unary) => self.operand_type(&expr_unary.expr),
+            // a shift has the type of what's shifted
+            Expr::Binary(expr_binary) => match expr_binary.op {
+                BinOp::Shl(_) | BinOp::Shr(_) => self.operand_type(&expr_binary.left),
+                _ => self
+                    .operand_type(&expr_binary.left)
+                    .or_else(|| self.operand_type(&expr_binary.right)),
+            },
+            Expr::Path(expr_path) => {
+                let path = &expr_path.path;
+                let bound = path.leading_sep.is_none()
+                    && path.segments.len() == 1
+                    && self
+                        .bindings
+                        .iter()
+                        .any(|(bound, _)| *bound == &path.segments.first().unwrap().ident);
+                if bound {
+                    return None;
+                }
+                match &*self.find_const(path).ok()??.1.ty {
+                    Type::Path(type_path) if type_path.segments.len() == 1 => {
+                        Some(type_path.segments.first().unwrap().ident.to_string())
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+enum EvalError {
+    Overflow,
+    DivisionByZero,
+    Mismatch,
+}
+
+impl EvalError {
+    fn diagnostic(self, file_id: FileId, span: Span) -> Diagnostic {
+        match self {
+            EvalError::Overflow => const_overflow(file_id, span),
+            EvalError::DivisionByZero => const_division_by_zero(file_id, span),
+            EvalError::Mismatch => const_operand_mismatch(file_id, span),
+        }
+    }
+}
+
+fn eval_binary(op: &BinOp, left: ConstValue, right: ConstValue) -> Result<ConstValue, EvalError> {
+    use BinOp::*;
+    use ConstValue::*;
+    match (left, right) {
+        (Int(l), Int(r)) => match op {
+            Add(_) => l.checked_add(r).map(Int).ok_or(EvalError::Overflow),
+            Sub(_) => l.checked_sub(r).map(Int).ok_or(EvalError::Overflow),
+            Mul(_) => l.checked_mul(r).map(Int).ok_or(EvalError::Overflow),
+            Div(_) | Rem(_) if r == 0 => Err(EvalError::DivisionByZero),
+            Div(_) => l.checked_div(r).map(Int).ok_or(EvalError::Overflow),
+            Rem(_) => l.checked_rem(r).map(Int).ok_or(EvalError::Overflow),
+            Shl(_) => u32::try_from(r)
+                .ok()
+                .and_then(|r| l.checked_shl(r))
+                .map(Int)
+                .ok_or(EvalError::Overflow),
+            Shr(_) => u32::try_from(r)
+                .ok()
+                .and_then(|r| l.checked_shr(r))
+                .map(Int)
+                .ok_or(EvalError::Overflow),
+            BitAnd(_) => Ok(Int(l & r)),
+            BitOr(_) => Ok(Int(l | r)),
+            BitXor(_) => Ok(Int(l ^ r)),
+            Eq(_) => Ok(Bool(l == r)),
+            Ne(_) => Ok(Bool(l != r)),
+            Lt(_) => Ok(Bool(l < r)),
+            Le(_) => Ok(Bool(l <= r)),
+            Gt(_) => Ok(Bool(l > r)),
+            Ge(_) => Ok(Bool(l >= r)),
+            And(_) | Or(_) => Err(EvalError::Mismatch),
+        },
+        (Bool(l), Bool(r)) => match op {
+            And(_) => Ok(Bool(l && r)),
+            Or(_) => Ok(Bool(l || r)),
+            BitAnd(_) => Ok(Bool(l & r)),
+            BitOr(_) => Ok(Bool(l | r)),
+            BitXor(_) => Ok(Bool(l ^ r)),
+            Eq(_) => Ok(Bool(l == r)),
+            Ne(_) => Ok(Bool(l != r)),
+            _ => Err(EvalError::Mismatch),
+        },
+        _ => Err(EvalError::Mismatch),
+    }
+}
+
+/// Parses integer literals like `42`, `0xff_u8`, and `0b1010`
+pub fn parse_int(text: &str) -> Option<i128> {
+    let text = text.replace('_', "");
+    let (radix, digits) = if let Some(hex) = text.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(octal) = text.strip_prefix("0o") {
+        (8, octal)
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        (2, binary)
+    } else {
+        (10, text.as_str())
+    };
+    // strip a type suffix like `u8` or `i32`
+    let digits = match int_suffix(digits) {
+        Some(suffix) => &digits[..digits.len() - suffix.len()],
+        None => digits,
+    };
+    i128::from_str_radix(digits, radix).ok()
+}
+
+/// The type suffix of an integer literal, like `u8` in `0xff_u8`
+fn int_suffix(text: &str) -> Option<&str> {
+    text.find(|c| c == 'u' || c == 'i')
+        .map(|suffix_start| &text[suffix_start..])
+}
+
+/// `!` of an integer, within the width of its type if it's unsigned, i.e. `!0u8` is 255 rather
+/// than -1
+fn not(value: i128, ty: Option<String>) -> i128 {
+    match ty
+        .as_deref()
+        .and_then(|ty| ty.strip_prefix('u'))
+        .and_then(|bits| bits.parse::<u32>().ok())
+    {
+        Some(bits) if bits < 128 => !value & ((1 << bits) - 1),
+        _ => !value,
+    }
+}
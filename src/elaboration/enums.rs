@@ -0,0 +1,199 @@
+//! Enums with only unit variants can be the types of signals, like the states of a state machine.
+//! Their variants are named like `State::Idle`, and encoded by their position in declaration order
+//! as set with `#[encoding = "binary"]` (the default), `#[encoding = "one-hot"]`,
+//! or `#[encoding = "gray"]`.
+use rhdl::ast::{ItemEnum, Spanned, TypePath, VariantType};
+
+use crate::attribute;
+use crate::error::*;
+use crate::resolution::{Branch, ResolutionIndex, ResolutionNode, Resolver};
+
+/// How the variants of an enum are represented in hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// The position of the variant
+    Binary,
+    /// A single bit per variant
+    OneHot,
+    /// The position of the variant in gray code, so consecutive variants differ by a bit
+    Gray,
+}
+
+impl Encoding {
+    pub const ALL: &'static [Encoding] = &[Encoding::Binary, Encoding::OneHot, Encoding::Gray];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Binary => "binary",
+            Encoding::OneHot => "one-hot",
+            Encoding::Gray => "gray",
+        }
+    }
+
+    /// Enough bits to encode every one of `variants`
+    pub fn width(self, variants: usize) -> u128 {
+        match self {
+            Encoding::Binary | Encoding::Gray => {
+                let max = (variants as u128).saturating_sub(1);
+                u128::from(128 - max.leading_zeros()).max(1)
+            }
+            Encoding::OneHot => (variants as u128).max(1),
+        }
+    }
+
+    /// The code of the variant at `position`
+    pub fn code(self, position: usize) -> u128 {
+        match self {
+            Encoding::Binary => position as u128,
+            Encoding::OneHot => 1u128.checked_shl(position as u32).unwrap_or_default(),
+            Encoding::Gray => (position ^ (position >> 1)) as u128,
+        }
+    }
+}
+
+/// An enum that can be the type of a signal
+#[derive(Debug, Clone, Copy)]
+pub struct Enum<'ast> {
+    pub index: ResolutionIndex,
+    pub item_enum: &'ast ItemEnum,
+}
+
+impl<'ast> Enum<'ast> {
+    fn from_node(resolver: &Resolver<'ast>, index: ResolutionIndex) -> Option<Self> {
+        match &resolver.resolution_graph[index] {
+            ResolutionNode::Branch {
+                branch: Branch::Enum(item_enum),
+                ..
+            } if item_enum
+                .variants
+                .iter()
+                .all(|variant| matches!(variant.variant_type, VariantType::Unit(_))) =>
+            {
+                Some(Enum { index, item_enum })
+            }
+            _ => None,
+        }
+    }
+
+    /// Binary unless declared otherwise, including with an invalid `#[encoding]`
+    pub fn encoding(&self) -> Encoding {
+        attribute::find_str(&self.item_enum.attrs, "encoding")
+            .and_then(|lit_str| {
+                Encoding::ALL
+                    .iter()
+                    .copied()
+                    .find(|encoding| lit_str.inner == encoding.name())
+            })
+            .unwrap_or(Encoding::Binary)
+    }
+
+    pub fn width(&self) -> u128 {
+        self.encoding().width(self.item_enum.variants.len())
+    }
+
+    /// The code of the variant at `position`
+    pub fn code(&self, position: usize) -> u128 {
+        self.encoding().code(position)
+    }
+}
+
+/// The enum a type names
+pub fn find_enum<'ast>(
+    resolver: &Resolver<'ast>,
+    scope: ResolutionIndex,
+    type_path: &'ast TypePath,
+) -> Option<Enum<'ast>> {
+    match resolver
+        .find_at_type_path(scope, type_path)
+        .ok()?
+        .as_slice()
+    {
+        [index] => Enum::from_node(resolver, *index),
+        _ => None,
+    }
+}
+
+/// The enum and number of the variant that a path like `State::Idle` names,
+/// with the enum declared in `scope` or one of its ancestors
+pub fn find_variant<'ast>(
+    resolver: &Resolver<'ast>,
+    scope: ResolutionIndex,
+    path: &TypePath,
+) -> Option<(Enum<'ast>, usize)> {
+    if path.leading_sep.is_some() || path.segments.len() != 2 {
+        return None;
+    }
+    let mut segments = path.segments.iter();
+    let (enum_segment, variant_segment) = (segments.next()?, segments.next()?);
+    let resolution_graph = &resolver.resolution_graph;
+    let mut scope = Some(scope);
+    while let Some(current) = scope {
+        let found = resolution_graph[current]
+            .children()
+            .zip(resolution_graph.symbol(&enum_segment.ident))
+            .and_then(|(children, name)| children.get(&Some(name)))
+            .into_iter()
+            .flatten()
+            .find_map(|child| Enum::from_node(resolver, *child));
+        if let Some(found) = found {
+            let variant = found
+                .item_enum
+                .variants
+                .iter()
+                .position(|variant| variant.ident == variant_segment.ident)?;
+            return Some((found, variant));
+        }
+        scope = resolution_graph[current].parent();
+    }
+    None
+}
+
+/// Checks the `#[encoding]` of every enum that can be the type of a signal:
+/// * it is one of `"binary"`, `"one-hot"`, or `"gray"`
+/// * gray coded enums have a power of two variants, so the last variant differs from the first
+///   by a single bit as well
+pub struct EncodingChecker<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> EncodingChecker<'a, 'ast> {
+    pub fn check(&mut self) {
+        let resolution_graph = &self.resolver.resolution_graph;
+        for index in resolution_graph.node_indices() {
+            let found = match Enum::from_node(self.resolver, index) {
+                Some(found) => found,
+                None => continue,
+            };
+            let lit_str = match attribute::find_str(&found.item_enum.attrs, "encoding") {
+                Some(lit_str) => lit_str,
+                None => continue,
+            };
+            let file = resolution_graph.file(index);
+            let variants = found.item_enum.variants.len();
+            match Encoding::ALL
+                .iter()
+                .find(|encoding| lit_str.inner == encoding.name())
+            {
+                None => self.errors.push(invalid_attribute_value(
+                    file,
+                    lit_str.span(),
+                    "encoding",
+                    &Encoding::ALL
+                        .iter()
+                        .map(|encoding| encoding.name())
+                        .collect::<Vec<_>>(),
+                )),
+                Some(Encoding::Gray) if !variants.is_power_of_two() => {
+                    self.errors.push(gray_encoding_not_power_of_two(
+                        file,
+                        lit_str.span(),
+                        &found.item_enum.ident,
+                        variants,
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,586 @@
+//! Elaboration turns the resolved design into a tree of instances, starting from a top entity.
+//! Each instance binds an entity to one of its architectures and to concrete generic values.
+//!
+//! An entity is instantiated inside an architecture with a struct expression, optionally
+//! selecting an architecture by name:
+//! ```text
+//! #[arch = "rtl"]
+//! let counter = Counter::<8> { clk: clk, count: count };
+//! ```
+//!
+//! Instantiations in a `for` loop are replicated once per iteration, their names suffixed with
+//! the value of each loop variable, i.e. `cell_0`, `cell_1`, and so on.
+//!
+//! Generic arguments are evaluated when the entity is instantiated, and must fit the types of
+//! their parameters. Instances with the same entity, architecture, and generic values share a
+//! [`Specialization`], whose architecture is only searched for instantiations once.
+//!
+//! Entities marked `#[black_box]` have no architecture, like vendor primitives or pre-compiled IP.
+//! Their instances are leaves of the tree that backends instantiate without defining.
+use fxhash::FxHashMap as HashMap;
+use rhdl::{
+    ast::{
+        ExprForLoop, ExprStruct, GenericParam, GenericParamConst, Ident, ItemEntity, Local, Pat,
+        Span, Spanned, Type,
+    },
+    visit::Visit,
+};
+
+use crate::attribute;
+use crate::error::*;
+use crate::find_file::FileId;
+use crate::resolution::{
+    black_box, primitive_width, select_architecture, Branch, ResolutionIndex, ResolutionNode,
+    Resolver,
+};
+
+pub mod const_eval;
+pub mod enums;
+mod ports;
+pub mod top;
+pub mod unroll;
+pub mod width;
+
+use const_eval::{ConstEvaluator, ConstValue};
+
+/// Instantiations found in an architecture, with the values of the loop variables around them
+type Body<'ast> = Vec<(Instantiation<'ast>, Vec<(&'ast Ident, ConstValue)>)>;
+
+#[derive(Debug)]
+pub struct Design<'ast> {
+    pub instances: Vec<Instance<'ast>>,
+    pub top: InstanceIndex,
+    pub specializations: Vec<Specialization>,
+    specialization_indices: HashMap<Specialization, SpecializationIndex>,
+    /// The instantiations of each specialization's architecture
+    bodies: HashMap<SpecializationIndex, Body<'ast>>,
+}
+
+impl<'ast> Design<'ast> {
+    fn specialize(&mut self, specialization: Specialization) -> SpecializationIndex {
+        if let Some(existing) = self.specialization_indices.get(&specialization) {
+            return *existing;
+        }
+        let idx = SpecializationIndex(self.specializations.len());
+        self.specialization_indices.insert(specialization.clone(), idx);
+        self.specializations.push(specialization);
+        idx
+    }
+
+    fn add_instance(&mut self, instance: Instance<'ast>) -> InstanceIndex {
+        let idx = InstanceIndex(self.instances.len());
+        if let Some(parent) = instance.parent {
+            self[parent].children.push(idx);
+        }
+        self.instances.push(instance);
+        idx
+    }
+
+    pub fn instance_indices(&self) -> impl Iterator<Item = InstanceIndex> {
+        (0..self.instances.len()).map(InstanceIndex)
+    }
+
+    /// The instance names from the top down to `instance`
+    pub fn hierarchical_name(&self, instance: InstanceIndex) -> Vec<&str> {
+        let mut names = vec![self[instance].name.as_str()];
+        let mut current = instance;
+        while let Some(parent) = self[current].parent {
+            names.push(self[parent].name.as_str());
+            current = parent;
+        }
+        names.reverse();
+        names
+    }
+}
+
+impl<'ast> std::ops::Index<InstanceIndex> for Design<'ast> {
+    type Output = Instance<'ast>;
+    fn index(&self, index: InstanceIndex) -> &<Self as std::ops::Index<InstanceIndex>>::Output {
+        &self.instances[index.0]
+    }
+}
+
+impl<'ast> std::ops::IndexMut<InstanceIndex> for Design<'ast> {
+    fn index_mut(
+        &mut self,
+        index: InstanceIndex,
+    ) -> &mut <Self as std::ops::Index<InstanceIndex>>::Output {
+        &mut self.instances[index.0]
+    }
+}
+
+impl<'ast> std::ops::Index<SpecializationIndex> for Design<'ast> {
+    type Output = Specialization;
+    fn index(
+        &self,
+        index: SpecializationIndex,
+    ) -> &<Self as std::ops::Index<SpecializationIndex>>::Output {
+        &self.specializations[index.0]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InstanceIndex(usize);
+
+/// An entity monomorphized with an architecture and generic values
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Specialization {
+    pub entity: ResolutionIndex,
+    pub arch: Option<ResolutionIndex>,
+    pub generics: Vec<ConstValue>,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpecializationIndex(usize);
+
+impl std::fmt::Display for InstanceIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance<'ast> {
+    /// `None` for the top instance
+    pub parent: Option<InstanceIndex>,
+    pub children: Vec<InstanceIndex>,
+    pub name: String,
+    pub entity: ResolutionIndex,
+    /// `None` for black boxes, and when no architecture could be selected
+    pub arch: Option<ResolutionIndex>,
+    /// Values of the entity's const generic parameters, in declaration order
+    pub generics: Vec<(&'ast Ident, ConstValue)>,
+    pub specialization: SpecializationIndex,
+    /// Whether an earlier instance has the same specialization, so the instantiations in its
+    /// body were already checked
+    pub repeated: bool,
+    /// Where this instance was created, `None` for the top instance
+    pub instantiation: Option<Instantiation<'ast>>,
+    /// Values of the variables of the loops around the instantiation, outermost first
+    pub loop_vars: Vec<(&'ast Ident, ConstValue)>,
+}
+
+/// A struct expression in an architecture that creates an instance of an entity
+#[derive(Debug, Clone, Copy)]
+pub struct Instantiation<'ast> {
+    pub file: FileId,
+    /// The `let` binding that names the instance, if any
+    pub local: Option<&'ast Local>,
+    pub expr: &'ast ExprStruct,
+}
+
+impl<'ast> Instantiation<'ast> {
+    pub fn name(&self) -> Option<&'ast Ident> {
+        self.local.and_then(|local| match &local.pat {
+            Pat::Ident(pat_ident) => Some(pat_ident),
+            _ => None,
+        })
+    }
+
+    pub fn span(&self) -> Span {
+        self.expr.path.span()
+    }
+}
+
+pub struct Elaborator<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+    /// The name of the architecture to elaborate the top entity with, instead of its only or
+    /// default one
+    pub top_arch: Option<&'a str>,
+}
+
+impl<'a, 'ast> Elaborator<'a, 'ast> {
+    /// Elaborates the design from the entity at the path `top`, or the detected top entity
+    pub fn elaborate(&mut self, top: Option<&str>) -> Option<Design<'ast>> {
+        let resolver = self.resolver;
+        let found = match top {
+            Some(path) => top::find_top(resolver, path),
+            None => top::detect_top(resolver),
+        };
+        let top_entity = match found {
+            Ok(top_entity) => top_entity,
+            Err(err) => {
+                self.errors.push(err);
+                return None;
+            }
+        };
+        let top_name = item_entity(resolver, top_entity).ident.to_string();
+
+        let mut design = Design {
+            instances: vec![],
+            top: InstanceIndex(0),
+            specializations: vec![],
+            specialization_indices: HashMap::default(),
+            bodies: HashMap::default(),
+        };
+        let generics = self.bind_generics(None, top_entity, None);
+        design.top = self.instantiate(
+            &mut design,
+            None,
+            top_name,
+            top_entity,
+            generics,
+            None,
+            vec![],
+        );
+        Some(design)
+    }
+
+    fn instantiate(
+        &mut self,
+        design: &mut Design<'ast>,
+        parent: Option<InstanceIndex>,
+        name: String,
+        entity: ResolutionIndex,
+        generics: Vec<(&'ast Ident, ConstValue)>,
+        instantiation: Option<Instantiation<'ast>>,
+        loop_vars: Vec<(&'ast Ident, ConstValue)>,
+    ) -> InstanceIndex {
+        let resolver = self.resolver;
+        let resolution_graph = &resolver.resolution_graph;
+        let site = instantiation.map(|instantiation| (instantiation.file, instantiation.span()));
+        // the instantiations in the body of a repeated specialization were checked with its
+        // first instance
+        let checked = parent.map_or(false, |parent| design[parent].repeated);
+        let requested = match instantiation {
+            Some(instantiation) => instantiation
+                .local
+                .and_then(|local| attribute::find_str(&local.attrs, "arch"))
+                .map(|name| (name.inner.as_str(), Some((instantiation.file, name.span())))),
+            None => self.top_arch.map(|name| (name, None)),
+        };
+        let arch = if black_box(resolution_graph, entity).is_some() {
+            None
+        } else {
+            match select_architecture(resolution_graph, entity, requested, site) {
+                Ok(arch) => Some(arch),
+                Err(err) => {
+                    if !checked {
+                        self.errors.push(err);
+                    }
+                    None
+                }
+            }
+        };
+        let specialization = Specialization {
+            entity,
+            arch,
+            generics: generics.iter().map(|(_, value)| *value).collect(),
+        };
+        let repeated = design.specialization_indices.contains_key(&specialization);
+        let specialization = design.specialize(specialization);
+        let idx = design.add_instance(Instance {
+            parent,
+            children: vec![],
+            name,
+            entity,
+            arch,
+            generics,
+            specialization,
+            repeated,
+            instantiation,
+            loop_vars,
+        });
+        if !checked {
+            self.check_ports(design, idx);
+        }
+
+        let arch = match arch {
+            Some(arch) => arch,
+            None => return idx,
+        };
+        let body = match design.bodies.get(&specialization) {
+            Some(body) => body.clone(),
+            None => {
+                let mut instantiation_finder = InstantiationFinder {
+                    resolver,
+                    file: resolution_graph.file(arch),
+                    arch,
+                    generics: design[idx].generics.clone(),
+                    loop_vars: vec![],
+                    local: None,
+                    instantiations: vec![],
+                    errors: vec![],
+                };
+                resolution_graph[arch].visit(&mut instantiation_finder);
+                self.errors.extend(instantiation_finder.errors);
+                design
+                    .bodies
+                    .insert(specialization, instantiation_finder.instantiations.clone());
+                instantiation_finder.instantiations
+            }
+        };
+
+        let mut unnamed_count = 0;
+        for (instantiation, loop_vars) in body {
+            let child_entity = match resolver
+                .find_at_type_path(arch, &instantiation.expr.path)
+                .unwrap_or_default()
+                .iter()
+                .copied()
+                .filter(|i| resolution_graph[*i].is_entity())
+                .collect::<Vec<ResolutionIndex>>()
+                .as_slice()
+            {
+                [child_entity] => *child_entity,
+                // plain struct expressions and unresolved paths are not instances
+                _ => continue,
+            };
+
+            let mut ancestor = Some(idx);
+            let mut is_recursive = false;
+            while let Some(current) = ancestor {
+                is_recursive |= design[current].entity == child_entity;
+                ancestor = design[current].parent;
+            }
+            if is_recursive {
+                // whether an instantiation is recursive depends on the instances above it, so
+                // it's checked for every instance, but reported once
+                let error = recursive_instantiation(
+                    instantiation.file,
+                    instantiation.span(),
+                    resolution_graph[child_entity].name().unwrap(),
+                );
+                if !self.errors.contains(&error) {
+                    self.errors.push(error);
+                }
+                continue;
+            }
+
+            let mut child_name = match instantiation.name() {
+                Some(ident) => ident.to_string(),
+                None => {
+                    unnamed_count += 1;
+                    format!(
+                        "{}_{}",
+                        resolution_graph[child_entity].name().unwrap(),
+                        unnamed_count
+                    )
+                }
+            };
+            for (_, value) in loop_vars.iter() {
+                child_name += &format!("_{}", value);
+            }
+            // generic arguments can refer to loop variables
+            let mut outer_bindings = design[idx].generics.clone();
+            outer_bindings.extend(loop_vars.iter().copied());
+            let error_count = self.errors.len();
+            let child_generics = self.bind_generics(
+                Some((&outer_bindings, arch)),
+                child_entity,
+                Some(instantiation),
+            );
+            if design[idx].repeated {
+                self.errors.truncate(error_count);
+            }
+            self.instantiate(
+                design,
+                Some(idx),
+                child_name,
+                child_entity,
+                child_generics,
+                Some(instantiation),
+                loop_vars,
+            );
+        }
+        idx
+    }
+
+    /// Generic arguments are positional, falling back on the parameter's default value,
+    /// and each value must fit the type of its parameter.
+    /// `outer` is the bindings and architecture of the instance that is instantiating `entity`.
+    fn bind_generics(
+        &mut self,
+        outer: Option<(&[(&'ast Ident, ConstValue)], ResolutionIndex)>,
+        entity: ResolutionIndex,
+        instantiation: Option<Instantiation<'ast>>,
+    ) -> Vec<(&'ast Ident, ConstValue)> {
+        let resolver = self.resolver;
+        let item_entity = item_entity(resolver, entity);
+        let params = match &item_entity.generics {
+            Some(generics) => generics
+                .params
+                .iter()
+                .filter_map(|param| match param {
+                    GenericParam::Const(param_const) => Some(param_const),
+                    _ => None,
+                })
+                .collect::<Vec<&'ast GenericParamConst>>(),
+            None => return vec![],
+        };
+        let args = instantiation
+            .and_then(|instantiation| instantiation.expr.path.segments.last())
+            .and_then(|segment| segment.generic_args.as_ref())
+            .map(|generic_args| generic_args.args.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        if let (Some(extra), Some(instantiation)) = (args.get(params.len()), instantiation) {
+            self.errors.push(too_many_generic_arguments(
+                instantiation.file,
+                extra.span(),
+                &item_entity.ident,
+                params.len(),
+                args.len(),
+            ));
+        }
+
+        let mut bindings = vec![];
+        let entity_file = resolver.resolution_graph.file(entity);
+        for (i, param) in params.iter().copied().enumerate() {
+            // where the value comes from, for reporting values that don't fit the parameter
+            let mut source = None;
+            let value =
+                if let (Some(arg), Some((outer_bindings, outer_arch))) = (args.get(i), outer) {
+                    source = Some((instantiation.unwrap().file, arg.span()));
+                    ConstEvaluator {
+                        resolver,
+                        bindings: outer_bindings,
+                        scope: outer_arch,
+                        evaluating: vec![],
+                    }
+                    .eval_generic_arg(arg)
+                } else if let Some((_, default)) = &param.default {
+                    source = Some((entity_file, default.span()));
+                    // defaults can refer to the parameters before them
+                    ConstEvaluator {
+                        resolver,
+                        bindings: &bindings,
+                        scope: entity,
+                        evaluating: vec![],
+                    }
+                    .eval(default)
+                } else {
+                    Err(missing_generic_argument(
+                        instantiation
+                            .map(|instantiation| instantiation.file)
+                            .unwrap_or_else(|| resolver.resolution_graph.file(entity)),
+                        instantiation
+                            .map(|instantiation| instantiation.span())
+                            .unwrap_or_else(|| item_entity.ident.span()),
+                        &item_entity.ident,
+                        &param.ident,
+                    ))
+                };
+            match value {
+                Ok(value) => {
+                    if let (Some(ty), Some((file, span))) =
+                        (mismatched_type(&param.ty, value), source)
+                    {
+                        self.errors.push(mismatched_generic_argument(
+                            file,
+                            span,
+                            value,
+                            entity_file,
+                            &param.ident,
+                            &ty,
+                        ));
+                    }
+                    bindings.push((&param.ident, value))
+                }
+                Err(err) => self.errors.push(err),
+            }
+        }
+        bindings
+    }
+}
+
+/// The name of a parameter's type if `value` doesn't fit in it.
+/// Only `bool` and primitive integers are checked.
+fn mismatched_type(ty: &Type, value: ConstValue) -> Option<String> {
+    let name = match ty {
+        Type::Path(type_path) if type_path.segments.len() == 1 => {
+            type_path.segments.first().unwrap().ident.to_string()
+        }
+        _ => return None,
+    };
+    let width = primitive_width(&name)?;
+    let fits = match value {
+        ConstValue::Bool(_) => name == "bool",
+        ConstValue::Int(_) if name == "bool" => false,
+        ConstValue::Int(value) if name.starts_with('i') => {
+            width >= 128 || (-(1i128 << (width - 1)) <= value && value < 1i128 << (width - 1))
+        }
+        ConstValue::Int(value) => value >= 0 && (width >= 127 || value < 1i128 << width),
+    };
+    if fits {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+pub fn item_entity<'ast>(resolver: &Resolver<'ast>, entity: ResolutionIndex) -> &'ast ItemEntity {
+    match &resolver.resolution_graph[entity] {
+        ResolutionNode::Branch {
+            branch: Branch::Entity(item_entity),
+            ..
+        } => item_entity,
+        _ => unreachable!(),
+    }
+}
+
+/// Collects the struct expressions in an architecture that might be instantiations,
+/// unrolling the loops around them
+struct InstantiationFinder<'a, 'ast> {
+    resolver: &'a Resolver<'ast>,
+    file: FileId,
+    arch: ResolutionIndex,
+    /// Generic parameters bound by the instance of the architecture
+    generics: Vec<(&'ast Ident, ConstValue)>,
+    /// Variables of the loops being unrolled, outermost first
+    loop_vars: Vec<(&'ast Ident, ConstValue)>,
+    local: Option<&'ast Local>,
+    instantiations: Vec<(Instantiation<'ast>, Vec<(&'ast Ident, ConstValue)>)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> Visit<'ast> for InstantiationFinder<'a, 'ast> {
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let Some((_, init)) = &local.init {
+            self.local = Some(local);
+            self.visit_expr(init);
+            self.local = None;
+        }
+    }
+
+    fn visit_expr_struct(&mut self, expr_struct: &'ast ExprStruct) {
+        self.instantiations.push((
+            Instantiation {
+                file: self.file,
+                local: self.local.take(),
+                expr: expr_struct,
+            },
+            self.loop_vars.clone(),
+        ));
+    }
+
+    fn visit_expr_for_loop(&mut self, expr_for_loop: &'ast ExprForLoop) {
+        let mut bindings = self.generics.clone();
+        bindings.extend(self.loop_vars.iter().copied());
+        let unrolled = unroll::unroll(
+            &mut ConstEvaluator {
+                resolver: self.resolver,
+                bindings: &bindings,
+                scope: self.arch,
+                evaluating: vec![],
+            },
+            expr_for_loop,
+        );
+        let unrolled = match unrolled {
+            Ok(unrolled) => unrolled,
+            Err(err) => {
+                self.errors.push(err);
+                return;
+            }
+        };
+        for value in unrolled.values {
+            if let Some(var) = unrolled.var {
+                self.loop_vars.push((var, value));
+            }
+            self.visit_block(&expr_for_loop.body);
+            if unrolled.var.is_some() {
+                self.loop_vars.pop();
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use rhdl::{
+    ast::{Expr, FieldValue, Ident, Local, Pat, PortDir, Span, Spanned, Type},
+    visit::Visit,
+};
+
+use super::{item_entity, width::type_width, Design, Elaborator, InstanceIndex};
+use crate::error::*;
+use crate::resolution::black_box;
+
+impl<'a, 'ast> Elaborator<'a, 'ast> {
+    /// Check the port connections of an instance against the declaration of its entity:
+    /// * every connection names a port
+    /// * inputs without a default value are connected
+    /// * connected signals have the same width as the port, when both are known
+    /// * outputs are connected, otherwise a warning is emitted,
+    ///   except for black boxes whose primitives often have outputs that go unused
+    pub(super) fn check_ports(&mut self, design: &Design<'ast>, instance: InstanceIndex) {
+        let resolver = self.resolver;
+        let (instantiation, parent) =
+            match (design[instance].instantiation, design[instance].parent) {
+                (Some(instantiation), Some(parent)) => (instantiation, parent),
+                _ => return,
+            };
+        let entity = design[instance].entity;
+        let entity_file = resolver.resolution_graph.file(entity);
+        let item_entity = item_entity(resolver, entity);
+        let is_black_box = black_box(&resolver.resolution_graph, entity).is_some();
+
+        let mut connected: Vec<&'ast Ident> = vec![];
+        for field_value in instantiation.expr.fields.iter() {
+            let port = match item_entity
+                .ports
+                .iter()
+                .find(|port| port.ident == field_value.ident)
+            {
+                Some(port) => port,
+                None => {
+                    self.errors.push(unknown_port(
+                        instantiation.file,
+                        &field_value.ident,
+                        entity_file,
+                        &item_entity.ident,
+                    ));
+                    continue;
+                }
+            };
+            connected.push(&port.ident);
+
+            let port_width = type_width(resolver, entity, &design[instance].generics, &port.ty);
+            let signal_width = self.signal_width(design, parent, field_value);
+            if let (Some(port_width), Some((signal_width, signal_span))) =
+                (port_width, signal_width)
+            {
+                if port_width != signal_width {
+                    self.errors.push(port_width_mismatch(
+                        instantiation.file,
+                        signal_span,
+                        signal_width,
+                        entity_file,
+                        &port.ident,
+                        port_width,
+                    ));
+                }
+            }
+        }
+
+        for port in item_entity.ports.iter() {
+            if connected.contains(&&port.ident) {
+                continue;
+            }
+            match port.dir {
+                PortDir::Out(_) if !is_black_box => self.errors.push(unconnected_output(
+                    instantiation.file,
+                    instantiation.span(),
+                    entity_file,
+                    &item_entity.ident,
+                    &port.ident,
+                )),
+                PortDir::In(_) | PortDir::InOut(_) if port.default.is_none() => {
+                    self.errors.push(missing_port_connection(
+                        instantiation.file,
+                        instantiation.span(),
+                        entity_file,
+                        &item_entity.ident,
+                        &port.ident,
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The width of the signal connected to a port, if it is a port of the enclosing entity
+    /// or a local with a declared type in the enclosing architecture
+    fn signal_width(
+        &self,
+        design: &Design<'ast>,
+        parent: InstanceIndex,
+        field_value: &'ast FieldValue,
+    ) -> Option<(u128, Span)> {
+        let resolver = self.resolver;
+        let (ident, span) = match &field_value.expr {
+            None => (&field_value.ident, field_value.ident.span()),
+            Some((_, Expr::Path(expr_path)))
+                if expr_path.path.leading_sep.is_none() && expr_path.path.segments.len() == 1 =>
+            {
+                let segment = expr_path.path.segments.first().unwrap();
+                if segment.generic_args.is_some() {
+                    return None;
+                }
+                (&segment.ident, expr_path.span())
+            }
+            Some(_) => return None,
+        };
+        let bindings = &design[parent].generics;
+
+        let parent_arch = design[parent].arch?;
+        let mut local_finder = TypedLocalFinder { ident, ty: None };
+        resolver.resolution_graph[parent_arch].visit(&mut local_finder);
+        if let Some(ty) = local_finder.ty {
+            return type_width(resolver, parent_arch, bindings, ty).map(|width| (width, span));
+        }
+
+        let parent_entity = design[parent].entity;
+        item_entity(resolver, parent_entity)
+            .ports
+            .iter()
+            .find(|port| port.ident == *ident)
+            .and_then(|port| type_width(resolver, parent_entity, bindings, &port.ty))
+            .map(|width| (width, span))
+    }
+}
+
+/// Finds the declared type of a local in an architecture.
+/// Locals shadow each other, so the last one wins.
+struct TypedLocalFinder<'i, 'ast> {
+    ident: &'i Ident,
+    ty: Option<&'ast Type>,
+}
+
+impl<'i, 'ast> Visit<'ast> for TypedLocalFinder<'i, 'ast> {
+    fn visit_local(&mut self, local: &'ast Local) {
+        if let (Pat::Ident(pat_ident), Some((_, ty))) = (&local.pat, &local.ty) {
+            if pat_ident == self.ident {
+                self.ty = Some(ty);
+            }
+        }
+        if let Some((_, init)) = &local.init {
+            self.visit_expr(init);
+        }
+    }
+}
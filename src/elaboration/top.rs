@@ -0,0 +1,118 @@
+//! The top entity of a design is named with `--top`, by a path from the root files like
+//! `cpu::Core`. When it isn't, the top is the only entity that no other entity instantiates,
+//! leaving out black boxes, testbenches, and the instantiations in testbenches.
+use rhdl::{ast::ExprStruct, visit::Visit};
+
+use crate::error::*;
+use crate::resolution::{black_box, ResolutionIndex, Resolver};
+use crate::sim::test::is_testbench;
+
+/// Finds an entity by its path, optionally starting with `crate::`
+pub fn find_top(resolver: &Resolver, path: &str) -> Result<ResolutionIndex, Diagnostic> {
+    let resolution_graph = &resolver.resolution_graph;
+    let segments = path
+        .strip_prefix("crate::")
+        .unwrap_or(path)
+        .split("::")
+        .collect::<Vec<_>>();
+    let mut current = resolution_graph
+        .roots
+        .iter()
+        .copied()
+        .filter(|root| !resolution_graph.is_extern(*root))
+        .collect::<Vec<_>>();
+    for (i, segment) in segments.iter().enumerate() {
+        let is_last = i + 1 == segments.len();
+        current = current
+            .iter()
+            .filter_map(|scope| resolution_graph[*scope].children())
+            .flat_map(|children| children.iter())
+            .filter(|(name, _)| {
+                name.map(|name| resolution_graph.names.as_str(name) == *segment)
+                    .unwrap_or_default()
+            })
+            .flat_map(|(_, indices)| indices.iter().copied())
+            .filter(|child| {
+                if is_last {
+                    resolution_graph[*child].is_entity()
+                } else {
+                    resolution_graph[*child].children().is_some()
+                }
+            })
+            .collect();
+    }
+    current
+        .first()
+        .copied()
+        .ok_or_else(|| unknown_top_entity(path))
+}
+
+/// Finds the only entity that isn't instantiated by another
+pub fn detect_top(resolver: &Resolver) -> Result<ResolutionIndex, Diagnostic> {
+    let resolution_graph = &resolver.resolution_graph;
+    let entities = resolution_graph
+        .node_indices()
+        .filter(|i| resolution_graph[*i].is_entity())
+        .filter(|entity| !resolution_graph.is_extern(*entity))
+        .filter(|entity| black_box(resolution_graph, *entity).is_none())
+        .filter(|entity| !is_testbench(resolver, *entity))
+        .collect::<Vec<_>>();
+
+    let mut instantiated = vec![];
+    for entity in entities.iter() {
+        for arch in resolution_graph.architectures(*entity) {
+            let mut struct_collector = StructCollector { exprs: vec![] };
+            resolution_graph[*arch].visit(&mut struct_collector);
+            for expr_struct in struct_collector.exprs {
+                instantiated.extend(
+                    resolver
+                        .find_at_type_path(*arch, &expr_struct.path)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|i| resolution_graph[*i].is_entity()),
+                );
+            }
+        }
+    }
+
+    let candidates = entities
+        .into_iter()
+        .filter(|entity| !instantiated.contains(entity))
+        .collect::<Vec<_>>();
+    match candidates.as_slice() {
+        [top] => Ok(*top),
+        [] => Err(no_top_entity()),
+        _ => Err(ambiguous_top_entity(
+            &candidates
+                .iter()
+                .map(|candidate| {
+                    (
+                        resolution_graph.file(*candidate),
+                        resolution_graph[*candidate].name().unwrap(),
+                        entity_path(resolver, *candidate),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )),
+    }
+}
+
+/// The path of an entity from its root file, i.e. `cpu::Core`
+pub fn entity_path(resolver: &Resolver, entity: ResolutionIndex) -> String {
+    resolver.resolution_graph.path(entity)
+}
+
+struct StructCollector<'ast> {
+    exprs: Vec<&'ast ExprStruct>,
+}
+
+impl<'ast> Visit<'ast> for StructCollector<'ast> {
+    fn visit_expr_struct(&mut self, expr_struct: &'ast ExprStruct) {
+        self.exprs.push(expr_struct);
+        for field_value in expr_struct.fields.iter() {
+            if let Some((_, expr)) = &field_value.expr {
+                self.visit_expr(expr);
+            }
+        }
+    }
+}
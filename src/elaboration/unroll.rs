@@ -0,0 +1,73 @@
+//! `for` loops in architectures describe replicated hardware, like generate loops in VHDL.
+//! They are unrolled during elaboration, so they must be over a range with constant bounds:
+//! ```text
+//! for i in 0..WIDTH {
+//!     q[i] = d[WIDTH - 1 - i];
+//! }
+//! ```
+use rhdl::ast::{Expr, ExprForLoop, ExprRange, Ident, Pat, RangeLimits, Spanned};
+
+use super::const_eval::{ConstEvaluator, ConstValue};
+use crate::error::*;
+use crate::find_file::FileId;
+
+/// The variable of a `for` loop and its value in each iteration
+pub struct Unrolled<'ast> {
+    /// `None` for `_`
+    pub var: Option<&'ast Ident>,
+    pub values: Vec<ConstValue>,
+}
+
+/// Evaluates the bounds of a loop over `a..b` or `a..=b`, resolving names in them with `evaluator`
+pub fn unroll<'ast>(
+    evaluator: &mut ConstEvaluator<'_, 'ast>,
+    expr_for_loop: &'ast ExprForLoop,
+) -> Result<Unrolled<'ast>, Diagnostic> {
+    let file = evaluator.resolver.resolution_graph.file(evaluator.scope);
+    let var = match &expr_for_loop.pat {
+        Pat::Ident(pat_ident) => Some(pat_ident),
+        Pat::Wild(_) => None,
+        other => return Err(not_synthesizable(file, other.span())),
+    };
+    let expr_range = match range(&expr_for_loop.expr) {
+        Some(expr_range) => expr_range,
+        None => return Err(non_const_loop_bound(file, expr_for_loop.expr.span())),
+    };
+    let (from, to) = match (&expr_range.from, &expr_range.to) {
+        (Some(from), Some(to)) => (
+            bound(evaluator, file, from)?,
+            bound(evaluator, file, to)?,
+        ),
+        _ => return Err(non_const_loop_bound(file, expr_range.span())),
+    };
+    let end = match expr_range.limits {
+        RangeLimits::Closed(_) => to
+            .checked_add(1)
+            .ok_or_else(|| const_overflow(file, expr_range.span()))?,
+        RangeLimits::HalfOpen(_) => to,
+    };
+    Ok(Unrolled {
+        var,
+        values: (from..end).map(ConstValue::Int).collect(),
+    })
+}
+
+fn range(expr: &Expr) -> Option<&ExprRange> {
+    match expr {
+        Expr::Range(expr_range) => Some(expr_range),
+        Expr::Paren(expr_paren) => range(&expr_paren.expr),
+        _ => None,
+    }
+}
+
+fn bound<'ast>(
+    evaluator: &mut ConstEvaluator<'_, 'ast>,
+    file: FileId,
+    expr: &'ast Expr,
+) -> Result<i128, Diagnostic> {
+    match evaluator.eval(expr) {
+        Ok(ConstValue::Int(value)) => Ok(value),
+        Ok(ConstValue::Bool(_)) => Err(const_operand_mismatch(file, expr.span())),
+        Err(_) => Err(non_const_loop_bound(file, expr.span())),
+    }
+}
@@ -0,0 +1,55 @@
+use rhdl::ast::{Ident, Type};
+
+use super::const_eval::{ConstEvaluator, ConstValue};
+use super::enums::find_enum;
+use crate::resolution::{primitive_width, ResolutionIndex, Resolver};
+
+/// The width in bits of a signal of type `ty`, if it is known during elaboration.
+/// Names in `ty` are resolved from `scope` with the generic parameters in `bindings`.
+pub fn type_width<'ast>(
+    resolver: &Resolver<'ast>,
+    scope: ResolutionIndex,
+    bindings: &[(&'ast Ident, ConstValue)],
+    ty: &'ast Type,
+) -> Option<u128> {
+    match ty {
+        Type::Path(type_path) => {
+            if type_path.leading_sep.is_some() || type_path.segments.len() != 1 {
+                return None;
+            }
+            let segment = type_path.segments.first().unwrap();
+            if segment.generic_args.is_some() {
+                return None;
+            }
+            // items and generic parameters shadow primitives
+            if bindings.iter().any(|(ident, _)| **ident == segment.ident) {
+                return None;
+            }
+            let is_item = !resolver
+                .find_at_type_path(scope, type_path)
+                .unwrap_or_default()
+                .is_empty();
+            if is_item {
+                return find_enum(resolver, scope, type_path).map(|found| found.width());
+            }
+            primitive_width(&segment.ident.to_string())
+        }
+        Type::Array(type_array) => {
+            let elem_width = type_width(resolver, scope, bindings, &type_array.elem)?;
+            let len = ConstEvaluator {
+                resolver,
+                bindings,
+                scope,
+                evaluating: vec![],
+            }
+            .eval(&type_array.len)
+            .ok()?;
+            match len {
+                ConstValue::Int(len) if len >= 0 => elem_width.checked_mul(len as u128),
+                _ => None,
+            }
+        }
+        Type::Paren(type_paren) => type_width(resolver, scope, bindings, &type_paren.elem),
+        _ => None,
+    }
+}
@@ -0,0 +1,512 @@
+use codespan_reporting::diagnostic::Severity;
+
+/// The codes that diagnostics are reported with, the severity they have unless a lint level
+/// changes it, and what each means. Mistakes that rustc also reports have its code, and the others
+/// are named after the diagnostic, which for a lint is the name it's allowed by.
+pub const CODES: &[(&str, Severity, &str)] = &[
+    (
+        "E0063",
+        Severity::Error,
+        "An input port of an instantiated entity isn't connected",
+    ),
+    (
+        "E0107",
+        Severity::Error,
+        "An entity was given the wrong number of generic arguments",
+    ),
+    (
+        "E0308",
+        Severity::Error,
+        "A generic argument doesn't have the type of its parameter",
+    ),
+    ("E0391", Severity::Error, "A const depends on its own value"),
+    (
+        "E0425",
+        Severity::Error,
+        "A path doesn't name any item in scope",
+    ),
+    (
+        "E0428",
+        Severity::Error,
+        "A name is defined more than once in the same namespace of a scope",
+    ),
+    (
+        "E0429",
+        Severity::Error,
+        "`self` is imported outside of a braced list",
+    ),
+    (
+        "E0431",
+        Severity::Error,
+        "`self` is imported in a braced list without a prefix",
+    ),
+    (
+        "E0449",
+        Severity::Error,
+        "A visibility qualifier is written where items can't have one",
+    ),
+    (
+        "E0560",
+        Severity::Error,
+        "An entity is instantiated with a port it doesn't have",
+    ),
+    (
+        "E0742",
+        Severity::Error,
+        "A visibility is restricted to a module that isn't an ancestor",
+    ),
+    (
+        "always_driven",
+        Severity::Error,
+        "A tri-state bus is driven by something that can't be disabled",
+    ),
+    (
+        "ambiguous_architecture",
+        Severity::Error,
+        "An entity has several architectures and none is selected",
+    ),
+    (
+        "ambiguous_rename",
+        Severity::Error,
+        "The name to rename refers to more than one item",
+    ),
+    (
+        "ambiguous_top_entity",
+        Severity::Error,
+        "More than one entity could be the top",
+    ),
+    (
+        "architectures_differ",
+        Severity::Error,
+        "Two architectures aren't equivalent",
+    ),
+    (
+        "assertion_can_fail",
+        Severity::Error,
+        "An assertion can fail",
+    ),
+    (
+        "assertion_failed",
+        Severity::Error,
+        "An assertion doesn't hold in simulation",
+    ),
+    (
+        "assertion_not_proven",
+        Severity::Warning,
+        "An assertion holds for every cycle checked but couldn't be proven",
+    ),
+    (
+        "assumption_violated",
+        Severity::Error,
+        "The stimulus of a simulation violates an assumption",
+    ),
+    (
+        "black_box_not_simulated",
+        Severity::Error,
+        "A black box without a model is simulated",
+    ),
+    (
+        "black_box_with_architecture",
+        Severity::Error,
+        "A black box entity has an architecture",
+    ),
+    (
+        "cannot_rename",
+        Severity::Error,
+        "The name to rename isn't an item",
+    ),
+    (
+        "cannot_rename_extern",
+        Severity::Error,
+        "The name to rename is declared in a library rhdlc ships with",
+    ),
+    (
+        "circular_module",
+        Severity::Error,
+        "A module is loaded from the file of one of its ancestors",
+    ),
+    (
+        "clock_domain_crossing",
+        Severity::Warning,
+        "A signal crosses into another clock domain without a synchronizer",
+    ),
+    (
+        "compile_simulation",
+        Severity::Error,
+        "rustc couldn't compile the simulation",
+    ),
+    (
+        "conflicting_clock_frequencies",
+        Severity::Error,
+        "A clock is declared with different frequencies",
+    ),
+    (
+        "const_division_by_zero",
+        Severity::Error,
+        "A constant expression divides by zero",
+    ),
+    (
+        "const_operand_mismatch",
+        Severity::Error,
+        "A constant expression mixes integers and booleans",
+    ),
+    (
+        "const_overflow",
+        Severity::Error,
+        "A constant expression overflows",
+    ),
+    (
+        "cover_not_reached",
+        Severity::Warning,
+        "A cover never holds in simulation",
+    ),
+    (
+        "coverpoint_not_sampled",
+        Severity::Error,
+        "A signal that isn't sampled is a coverpoint",
+    ),
+    (
+        "dead_end_state",
+        Severity::Warning,
+        "A state of a state machine has no exit transitions",
+    ),
+    (
+        "deep_inout",
+        Severity::Error,
+        "A bidirectional port is below the instances of the top",
+    ),
+    ("delay", Severity::Warning, "A function waits for a delay"),
+    (
+        "distributed_memory",
+        Severity::Warning,
+        "A memory will be built from registers instead of block RAM",
+    ),
+    (
+        "equivalence_not_proven",
+        Severity::Warning,
+        "Two architectures are the same for every cycle checked but couldn't be proven equivalent",
+    ),
+    (
+        "file_already_loaded",
+        Severity::Error,
+        "A file is declared as the file of more than one module",
+    ),
+    (
+        "file_too_large",
+        Severity::Error,
+        "A file is larger than the limit",
+    ),
+    (
+        "floating_point",
+        Severity::Warning,
+        "A floating-point type is used",
+    ),
+    (
+        "formal_without_z3",
+        Severity::Error,
+        "Model checking needs rhdlc built with z3",
+    ),
+    (
+        "gray_encoding_not_power_of_two",
+        Severity::Error,
+        "A gray coded enum doesn't have a power of two variants",
+    ),
+    (
+        "heap_allocation",
+        Severity::Warning,
+        "An expression allocates on the heap",
+    ),
+    (
+        "implicit_extension",
+        Severity::Warning,
+        "A value is extended to a wider type without a cast",
+    ),
+    (
+        "implicit_truncation",
+        Severity::Warning,
+        "A value is truncated to a narrower type without a cast",
+    ),
+    (
+        "invalid_attribute_value",
+        Severity::Error,
+        "An attribute has a value it doesn't take",
+    ),
+    (
+        "invalid_clock_frequency",
+        Severity::Error,
+        "The frequency of a clock can't be read",
+    ),
+    (
+        "invalid_coverpoint",
+        Severity::Error,
+        "The bins of a coverpoint can't be read",
+    ),
+    (
+        "invalid_identifier",
+        Severity::Error,
+        "A new name isn't an identifier",
+    ),
+    (
+        "invalid_message",
+        Severity::Error,
+        "The format string of a message is invalid",
+    ),
+    (
+        "invalid_random",
+        Severity::Error,
+        "The constraints of random stimulus can't be read",
+    ),
+    (
+        "invalid_synthesis_pragma_value",
+        Severity::Error,
+        "A synthesis pragma has a value it doesn't take",
+    ),
+    ("invalid_utf8", Severity::Error, "A file isn't UTF-8"),
+    (
+        "io",
+        Severity::Error,
+        "A file couldn't be read or written, or a program couldn't be run",
+    ),
+    (
+        "misplaced_synthesis_pragma",
+        Severity::Error,
+        "A synthesis pragma is on an item it doesn't apply to",
+    ),
+    (
+        "missing_architecture",
+        Severity::Error,
+        "An elaborated entity has no architecture",
+    ),
+    (
+        "missing_reset",
+        Severity::Warning,
+        "A register has no reset",
+    ),
+    (
+        "mixed_reset_styles",
+        Severity::Warning,
+        "The registers of an architecture are reset in different ways",
+    ),
+    (
+        "mixed_signedness",
+        Severity::Warning,
+        "An operation mixes signed and unsigned operands",
+    ),
+    (
+        "model_not_compiled",
+        Severity::Error,
+        "A black box with a model is simulated with the compiled backend",
+    ),
+    (
+        "module_file_casing",
+        Severity::Warning,
+        "The file of a module differs in case from its name",
+    ),
+    (
+        "multiple_default_architectures",
+        Severity::Error,
+        "More than one architecture of an entity is marked `#[default]`",
+    ),
+    (
+        "multiple_drivers",
+        Severity::Error,
+        "A signal is driven by more than one function or instance",
+    ),
+    (
+        "no_top_entity",
+        Severity::Error,
+        "Every entity is instantiated by another, so none is the top",
+    ),
+    (
+        "non_const_loop_bound",
+        Severity::Error,
+        "The bound of a loop isn't constant",
+    ),
+    (
+        "non_gray_transition",
+        Severity::Warning,
+        "A gray coded state machine changes more than one bit in a transition",
+    ),
+    (
+        "not_a_bit_vector",
+        Severity::Error,
+        "A signal is too wide or narrow to be model checked",
+    ),
+    (
+        "not_const_evaluable",
+        Severity::Error,
+        "An expression that has to be constant can't be evaluated",
+    ),
+    (
+        "not_synthesizable",
+        Severity::Error,
+        "Something in an architecture can't be lowered to hardware",
+    ),
+    (
+        "nothing_to_rename",
+        Severity::Error,
+        "There's no name at the location to rename",
+    ),
+    (
+        "port_width_mismatch",
+        Severity::Error,
+        "A port is connected to a signal of another width",
+    ),
+    (
+        "random_not_compiled",
+        Severity::Error,
+        "Random stimulus is simulated with the compiled backend",
+    ),
+    (
+        "random_not_stimulus",
+        Severity::Error,
+        "A signal that isn't stimulus is drawn at random",
+    ),
+    (
+        "random_seed",
+        Severity::Note,
+        "The seed random stimulus was drawn with",
+    ),
+    ("recursion", Severity::Warning, "A function calls itself"),
+    (
+        "recursive_instantiation",
+        Severity::Error,
+        "An entity instantiates itself",
+    ),
+    (
+        "redundant_import",
+        Severity::Warning,
+        "A name is imported again where it's already imported",
+    ),
+    (
+        "repeated_logic",
+        Severity::Warning,
+        "The same logic is computed more than once",
+    ),
+    (
+        "replaced_invalid_utf8",
+        Severity::Warning,
+        "Invalid UTF-8 in a file was replaced",
+    ),
+    (
+        "run_simulation",
+        Severity::Error,
+        "The compiled simulation failed",
+    ),
+    (
+        "shadowed_import",
+        Severity::Warning,
+        "A name imported by a glob is shadowed by a local item",
+    ),
+    (
+        "stale_interface",
+        Severity::Warning,
+        "An interface file is older than the sources it was generated from",
+    ),
+    (
+        "too_many_errors",
+        Severity::Error,
+        "More errors were found than the limit",
+    ),
+    (
+        "too_many_files",
+        Severity::Error,
+        "More files were found for the modules of the design than the limit",
+    ),
+    (
+        "too_many_memory_ports",
+        Severity::Error,
+        "A memory needs more ports than a block RAM has",
+    ),
+    (
+        "too_wide_to_simulate",
+        Severity::Error,
+        "A signal is wider than the simulator supports",
+    ),
+    (
+        "tristate_contention",
+        Severity::Error,
+        "A tri-state bus can be driven twice at the same time",
+    ),
+    ("unbounded_loop", Severity::Warning, "A loop has no bound"),
+    (
+        "unconnected_output",
+        Severity::Warning,
+        "An output port of an instance isn't connected",
+    ),
+    (
+        "unconstrained_clock",
+        Severity::Warning,
+        "A clock has no frequency",
+    ),
+    (
+        "unexpected_reset_style",
+        Severity::Warning,
+        "A register has a kind of reset that `#[reset_style]` doesn't allow",
+    ),
+    ("unformatted", Severity::Error, "A file isn't formatted"),
+    (
+        "unknown_architecture",
+        Severity::Error,
+        "An instantiation selects an architecture its entity doesn't have",
+    ),
+    (
+        "unknown_output",
+        Severity::Warning,
+        "An output is unknown in simulation after its reset",
+    ),
+    (
+        "unknown_synthesis_pragma",
+        Severity::Error,
+        "A synthesis pragma isn't one that rhdlc knows",
+    ),
+    (
+        "unknown_top_entity",
+        Severity::Error,
+        "The top entity given with `--top` doesn't exist",
+    ),
+    (
+        "unknown_width",
+        Severity::Error,
+        "The width of a signal can't be determined during elaboration",
+    ),
+    (
+        "unreachable_branch",
+        Severity::Warning,
+        "A branch can never be taken",
+    ),
+    (
+        "unreachable_state",
+        Severity::Warning,
+        "No transition enters a state of a state machine",
+    ),
+    (
+        "unread_port",
+        Severity::Warning,
+        "An input port is never read",
+    ),
+    (
+        "unread_signal",
+        Severity::Warning,
+        "A signal is assigned but never read",
+    ),
+    (
+        "unsettled_simulation",
+        Severity::Error,
+        "The design didn't settle in a cycle of the simulation",
+    ),
+    (
+        "unsupported_by_backend",
+        Severity::Error,
+        "Something can't be emitted by the backend it's emitted with",
+    ),
+    (
+        "unused_pub",
+        Severity::Warning,
+        "An item is more visible than its uses need",
+    ),
+];
+
+/// The code of every failure to read or write a file or to run a program, which rhdlc exits with
+/// its own status for
+pub const IO_CODE: &str = "io";
@@ -1,46 +1,131 @@
 use rhdl::ast::SimplePath;
 use std::ffi::OsString;
 use std::fmt::{self, Display, Formatter};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use codespan::FileId;
 use codespan_reporting::diagnostic::{Diagnostic as CodespanDiagnostic, Label};
 use lalrpop_util::{lexer::Token, ParseError};
 use rhdl::ast::{Ident, ItemMod, PathSep, Span, Spanned, UseTreeGlob, Vis};
 
+use crate::elaboration::const_eval::ConstValue;
+use crate::find_file::Declaration;
+use crate::ir::Level;
+
+mod codes;
+pub use codes::{CODES, IO_CODE};
+
 pub type Diagnostic = CodespanDiagnostic<FileId>;
 
 pub enum FileFindingError {
     Parse(Diagnostic),
     Io(std::io::Error),
+    /// The file was already loaded for another module
+    AlreadyLoaded(Diagnostic),
+    /// The file is that of a module the declared one is in
+    Circular(Diagnostic),
+    /// The file isn't UTF-8, from the sequence at this byte offset
+    InvalidUtf8(usize),
+    /// The file is larger than this limit in bytes
+    TooLarge(u64),
 }
 
 impl FileFindingError {
     pub fn is_io_not_found(&self) -> bool {
         match self {
             Self::Io(err) => err.kind() == std::io::ErrorKind::NotFound,
-            Self::Parse(_) => false,
+            Self::Parse(_)
+            | Self::AlreadyLoaded(_)
+            | Self::Circular(_)
+            | Self::InvalidUtf8(_)
+            | Self::TooLarge(_) => false,
         }
     }
     pub fn diagnostic(self, name: OsString, parent: Option<(FileId, &ItemMod)>) -> Diagnostic {
         match self {
-            Self::Parse(diag) => diag,
+            Self::Parse(diag) | Self::AlreadyLoaded(diag) | Self::Circular(diag) => diag,
             Self::Io(err) => Diagnostic::error()
+                .with_code(IO_CODE)
                 .with_message(format!("couldn't read {}: {}", name.to_string_lossy(), err,))
-                .with_labels({
-                    let mut labels = vec![];
-                    if let Some((parent_file_id, this_item_mod_decl)) = parent {
-                        labels.push(
-                            Label::primary(parent_file_id, this_item_mod_decl.span())
-                                .with_message("declared here"),
-                        );
-                    }
-                    labels
-                }),
+                .with_labels(declared_here(parent)),
+            Self::InvalidUtf8(offset) => Diagnostic::error()
+                .with_code("invalid_utf8")
+                .with_message(format!(
+                    "{} isn't valid UTF-8: invalid byte sequence at byte {}",
+                    name.to_string_lossy(),
+                    offset
+                ))
+                .with_labels(declared_here(parent))
+                .with_notes(vec![
+                    "save it as UTF-8, or compile with --lossy-utf8 to replace invalid sequences"
+                        .to_string(),
+                ]),
+            Self::TooLarge(max_size) => Diagnostic::error()
+                .with_code("file_too_large")
+                .with_message(format!(
+                    "{} is larger than the limit of {} bytes",
+                    name.to_string_lossy(),
+                    max_size
+                ))
+                .with_labels(declared_here(parent))
+                .with_notes(vec!["raise the limit with --max-file-size".to_string()]),
         }
     }
 }
 
+/// The label on the declaration of the module a file was read for, if it wasn't a root
+fn declared_here(parent: Option<(FileId, &ItemMod)>) -> Vec<Label<FileId>> {
+    parent
+        .map(|(parent_file_id, item_mod)| {
+            Label::primary(parent_file_id, item_mod.span()).with_message("declared here")
+        })
+        .into_iter()
+        .collect()
+}
+
+pub fn too_many_files(max_files: usize, parent: Option<(FileId, &ItemMod)>) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("too_many_files")
+        .with_message(format!(
+            "more than the limit of {} files were found for the modules of the design",
+            max_files
+        ))
+        .with_labels(declared_here(parent))
+        .with_notes(vec![
+            "this module and those declared after it aren't loaded".to_string(),
+            "raise the limit with --max-files".to_string(),
+        ])
+}
+
+/// An interface of a library whose sources changed after it was generated
+pub fn stale_interface(path: &Path, name: &str) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("stale_interface")
+        .with_message(format!(
+            "the interface {} of `{}` is out of date",
+            path.display(),
+            name
+        ))
+        .with_notes(vec![
+            "its sources are compiled instead".to_string(),
+            "generate it again with --emit interface".to_string(),
+        ])
+}
+
+/// Invalid UTF-8 in a file read with `--lossy-utf8`, from the first sequence that was replaced
+pub fn replaced_invalid_utf8(name: OsString, file_id: FileId, offset: usize) -> Diagnostic {
+    let replacement = offset..offset + char::REPLACEMENT_CHARACTER.len_utf8();
+    Diagnostic::warning()
+        .with_code("replaced_invalid_utf8")
+        .with_message(format!(
+            "{} isn't valid UTF-8, invalid byte sequences were replaced",
+            name.to_string_lossy()
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, replacement).with_message("first replaced here")
+        ])
+}
+
 pub fn parse<'input>(
     name: OsString,
     file_id: FileId,
@@ -117,8 +202,97 @@ pub fn conflicting_mod_files(
         })
 }
 
+pub fn file_already_loaded(
+    name: OsString,
+    first: Option<&Declaration>,
+    again: Option<(FileId, &ItemMod)>,
+) -> Diagnostic {
+    let mut labels = vec![];
+    if let Some((file_id, item_mod)) = again {
+        labels.push(Label::primary(file_id, item_mod.span()).with_message("declared again here"));
+    }
+    if let Some(first) = first {
+        labels.push(
+            Label::secondary(first.file, first.span.clone()).with_message("first declared here"),
+        );
+    }
+    Diagnostic::error()
+        .with_code("file_already_loaded")
+        .with_message(format!(
+            "the file {} is already loaded for module `{}`",
+            name.to_string_lossy(),
+            first.map_or("crate", |first| first.module.as_str())
+        ))
+        .with_labels(labels)
+        .with_notes(vec!["a file can only be loaded for one module".to_string()])
+}
+
+pub fn circular_module(
+    name: OsString,
+    ident_path: &[Ident],
+    ancestor: Option<&Declaration>,
+    again: Option<(FileId, &ItemMod)>,
+) -> Diagnostic {
+    let mut labels = vec![];
+    if let Some((file_id, item_mod)) = again {
+        labels.push(Label::primary(file_id, item_mod.span()).with_message("declared here"));
+    }
+    if let Some(ancestor) = ancestor {
+        labels.push(
+            Label::secondary(ancestor.file, ancestor.span.clone())
+                .with_message("ancestor declared here"),
+        );
+    }
+    Diagnostic::error()
+        .with_code("circular_module")
+        .with_message(format!(
+            "circular module dependency: module `{}` would be loaded from the file of its ancestor `{}`",
+            ident_path
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("::"),
+            ancestor.map_or("crate", |ancestor| ancestor.module.as_str())
+        ))
+        .with_labels(labels)
+        .with_notes(vec![format!(
+            "both are loaded from {}",
+            name.to_string_lossy()
+        )])
+}
+
+pub fn module_file_casing(
+    module: &str,
+    file_id: FileId,
+    item_mod: &ItemMod,
+    declared: OsString,
+    on_disk: OsString,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("module_file_casing")
+        .with_message(format!(
+            "module `{}` is loaded from `{}`, which differs in case from `{}`",
+            module,
+            on_disk.to_string_lossy(),
+            declared.to_string_lossy()
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, item_mod.span()).with_message("declared here")
+        ])
+        .with_notes(vec![format!(
+            "it won't be found on case-sensitive file systems, rename it to `{}`",
+            declared.to_string_lossy()
+        )])
+}
+
+/// Whether `diagnostic` is about reading or writing a file or running a program instead of the
+/// design, which the exit status tells apart
+pub fn is_io(diagnostic: &Diagnostic) -> bool {
+    diagnostic.code.as_deref() == Some(IO_CODE)
+}
+
 pub fn working_directory(cause: std::io::Error) -> Diagnostic {
-    Diagnostic::error().with_message(format!(
+    Diagnostic::error().with_code(IO_CODE).with_message(format!(
         "couldn't get the current working directory: {}",
         cause,
     ))
@@ -189,6 +363,58 @@ pub fn reimport(
         ])
 }
 
+pub fn redundant_import(
+    file_id: FileId,
+    ident: &Ident,
+    previous_file_id: FileId,
+    previous: Span,
+    declaration_file_id: FileId,
+    declaration_ident: &Ident,
+    declaration_hint: Option<ItemHint>,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("redundant_import")
+        .with_message(&format!(
+            "the {} `{}` is already imported",
+            declaration_hint
+                .map(|x| x.to_string())
+                .unwrap_or("name".to_string()),
+            ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, ident.span()).with_message("reimported here"),
+            Label::secondary(previous_file_id, previous).with_message("imported here"),
+            Label::secondary(declaration_file_id, declaration_ident.span())
+                .with_message("declared here"),
+        ])
+}
+
+pub fn shadowed_import(
+    file_id: FileId,
+    glob: &UseTreeGlob,
+    local_file_id: FileId,
+    local: &Ident,
+    declaration_file_id: FileId,
+    declaration_ident: &Ident,
+    declaration_hint: Option<ItemHint>,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("shadowed_import")
+        .with_message(&format!(
+            "the {} `{}` imported by the glob is shadowed by a local item",
+            declaration_hint
+                .map(|x| x.to_string())
+                .unwrap_or("name".to_string()),
+            local
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, glob.span()).with_message("imported here"),
+            Label::secondary(local_file_id, local.span()).with_message("shadowed here"),
+            Label::secondary(declaration_file_id, declaration_ident.span())
+                .with_message("declared here"),
+        ])
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum DuplicateHint {
     Variant,
@@ -290,6 +516,17 @@ pub fn unresolved_item(
         .with_notes(notes)
 }
 
+/// Notes that an item wasn't found in a scope from a file with syntax errors, so it might be
+/// declared in an item that didn't parse
+pub fn in_incomplete_scope(mut diagnostic: Diagnostic) -> Diagnostic {
+    diagnostic.notes.push(
+        "it was looked for in a file with syntax errors, \
+         so it might be declared in an item that couldn't be parsed"
+            .to_string(),
+    );
+    diagnostic
+}
+
 #[derive(Debug)]
 pub enum ItemHint {
     /// mod
@@ -314,6 +551,8 @@ pub enum ItemHint {
     Field,
     /// a variant in an enum
     Variant,
+    /// an entity in particular
+    Entity,
 }
 
 impl Display for ItemHint {
@@ -331,6 +570,7 @@ impl Display for ItemHint {
             Fn => write!(f, "function"),
             Field => write!(f, "field"),
             Variant => write!(f, "variant"),
+            Entity => write!(f, "entity"),
         }
     }
 }
@@ -508,3 +748,1433 @@ pub fn non_ancestral_visibility(
             "visibility can only be restricted to an ancestral path".to_string(),
         ])
 }
+
+pub fn multiple_architecture_definition(
+    entity_ident: &Ident,
+    name: &str,
+    original: (FileId, Span),
+    duplicate: (FileId, Span),
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0428")
+        .with_message(format!(
+            "the architecture `{}` of `{}` is defined multiple times",
+            name, entity_ident
+        ))
+        .with_labels(vec![
+            Label::primary(duplicate.0, duplicate.1)
+                .with_message(format!("`{}` redefined here", name)),
+            Label::secondary(original.0, original.1).with_message(format!(
+                "previous definition of the architecture `{}` here",
+                name
+            )),
+        ])
+}
+
+pub fn multiple_default_architectures(
+    entity_ident: &Ident,
+    original: (FileId, Span),
+    duplicate: (FileId, Span),
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("multiple_default_architectures")
+        .with_message(format!(
+            "entity `{}` has multiple default architectures",
+            entity_ident
+        ))
+        .with_labels(vec![
+            Label::primary(duplicate.0, duplicate.1).with_message("also marked as default here"),
+            Label::secondary(original.0, original.1).with_message("first marked as default here"),
+        ])
+        .with_notes(vec![
+            "only one architecture of an entity can be marked `#[default]`".to_string(),
+        ])
+}
+
+pub fn ambiguous_architecture(
+    site: Option<(FileId, Span)>,
+    entity_ident: &Ident,
+    candidates: Vec<(FileId, Span)>,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("ambiguous_architecture")
+        .with_message(format!(
+            "multiple architectures are available for entity `{}`",
+            entity_ident
+        ))
+        .with_labels({
+            let mut labels = vec![];
+            if let Some((file_id, span)) = site {
+                labels.push(Label::primary(file_id, span).with_message("ambiguous architecture"));
+            }
+            labels.extend(candidates.into_iter().map(|(file_id, span)| {
+                Label::secondary(file_id, span).with_message("candidate architecture")
+            }));
+            labels
+        })
+        .with_notes(vec![
+            "select one with `#[arch = \"...\"]` or mark one of them `#[default]`".to_string(),
+        ])
+}
+
+pub fn unknown_architecture(
+    site: Option<(FileId, Span)>,
+    entity_ident: &Ident,
+    requested: &str,
+    available: Vec<String>,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("unknown_architecture")
+        .with_message(format!(
+            "no architecture named `{}` for entity `{}`",
+            requested, entity_ident
+        ))
+        .with_labels(
+            site.into_iter()
+                .map(|(file_id, span)| {
+                    Label::primary(file_id, span).with_message("unknown architecture")
+                })
+                .collect(),
+        )
+        .with_notes(if available.is_empty() {
+            vec!["none of its architectures are named".to_string()]
+        } else {
+            vec![format!("available architectures: {}", available.join(", "))]
+        })
+}
+
+pub fn missing_architecture(
+    site: Option<(FileId, Span)>,
+    entity_file_id: FileId,
+    entity_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("missing_architecture")
+        .with_message(format!("entity `{}` has no architecture", entity_ident))
+        .with_labels({
+            let mut labels = vec![];
+            if let Some((file_id, span)) = site {
+                labels.push(Label::primary(file_id, span).with_message("elaborated here"));
+            }
+            labels.push(
+                Label::secondary(entity_file_id, entity_ident.span()).with_message("declared here"),
+            );
+            labels
+        })
+}
+
+pub fn black_box_with_architecture(
+    entity_ident: &Ident,
+    black_box: (FileId, Span),
+    arch: (FileId, Span),
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("black_box_with_architecture")
+        .with_message(format!(
+            "black box entity `{}` has an architecture",
+            entity_ident
+        ))
+        .with_labels(vec![
+            Label::primary(arch.0, arch.1).with_message("architecture defined here"),
+            Label::secondary(black_box.0, black_box.1).with_message("declared a black box here"),
+        ])
+        .with_notes(vec![
+            "black boxes are defined outside of the design, like vendor primitives".to_string(),
+        ])
+}
+
+pub fn not_const_evaluable(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("not_const_evaluable")
+        .with_message("expression cannot be evaluated at compile time")
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("not a constant")
+        ])
+        .with_notes(vec![
+            "constant expressions can only use literals, consts, and generic parameters"
+                .to_string(),
+        ])
+}
+
+pub fn const_overflow(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("const_overflow")
+        .with_message("constant expression overflows")
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("this overflows during evaluation")
+        ])
+}
+
+pub fn const_division_by_zero(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("const_division_by_zero")
+        .with_message("attempt to divide by zero in a constant expression")
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("divides by zero")
+        ])
+}
+
+pub fn const_operand_mismatch(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("const_operand_mismatch")
+        .with_message("mismatched operands in constant expression")
+        .with_labels(vec![Label::primary(file_id, span)
+            .with_message("operands must both be integers or both be booleans")])
+}
+
+pub fn recursive_const(file_id: FileId, ident: &Ident) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0391")
+        .with_message(format!("cycle detected when evaluating const `{}`", ident))
+        .with_labels(vec![
+            Label::primary(file_id, ident.span()).with_message("depends on itself")
+        ])
+}
+
+pub fn unknown_top_entity(name: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("unknown_top_entity")
+        .with_message(format!("no entity named `{}` was found", name))
+}
+
+pub fn no_top_entity() -> Diagnostic {
+    Diagnostic::error()
+        .with_code("no_top_entity")
+        .with_message("couldn't find a top entity")
+        .with_notes(vec![
+            "every entity is instantiated by another, or is a black box or testbench".to_string(),
+        ])
+}
+
+pub fn ambiguous_top_entity(candidates: &[(FileId, &Ident, String)]) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("ambiguous_top_entity")
+        .with_message("couldn't detect the top entity")
+        .with_labels(
+            candidates
+                .iter()
+                .map(|(file_id, ident, _)| {
+                    Label::primary(*file_id, ident.span())
+                        .with_message("not instantiated by any other entity")
+                })
+                .collect(),
+        )
+        .with_notes(vec![format!(
+            "choose one of {} with `--top`",
+            candidates
+                .iter()
+                .map(|(_, _, path)| format!("`{}`", path))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )])
+}
+
+pub fn recursive_instantiation(file_id: FileId, span: Span, entity_ident: &Ident) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("recursive_instantiation")
+        .with_message(format!("entity `{}` instantiates itself", entity_ident))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("recursive instantiation")
+        ])
+        .with_notes(vec![
+            "an entity cannot contain an instance of itself, directly or indirectly".to_string(),
+        ])
+}
+
+pub fn non_const_loop_bound(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("non_const_loop_bound")
+        .with_message("loop bound cannot be evaluated at compile time")
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("not a constant")
+        ])
+        .with_notes(vec![
+            "loops are unrolled during elaboration, so they must be over a range of constants"
+                .to_string(),
+        ])
+}
+
+pub fn missing_generic_argument(
+    file_id: FileId,
+    span: Span,
+    entity_ident: &Ident,
+    param_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0107")
+        .with_message(format!(
+            "missing generic argument `{}` for entity `{}`",
+            param_ident, entity_ident
+        ))
+        .with_labels(vec![Label::primary(file_id, span)
+            .with_message(format!("`{}` is not bound here", param_ident))])
+}
+
+pub fn too_many_generic_arguments(
+    file_id: FileId,
+    span: Span,
+    entity_ident: &Ident,
+    expected: usize,
+    found: usize,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0107")
+        .with_message(format!(
+            "entity `{}` takes {} generic argument{} but {} were supplied",
+            entity_ident,
+            expected,
+            if expected == 1 { "" } else { "s" },
+            found
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("unexpected generic argument")
+        ])
+}
+
+pub fn mismatched_generic_argument(
+    file_id: FileId,
+    span: Span,
+    value: ConstValue,
+    param_file_id: FileId,
+    param_ident: &Ident,
+    param_ty: &str,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0308")
+        .with_message(format!(
+            "generic argument `{}` doesn't fit in `{}`",
+            value, param_ty
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("evaluates to `{}`", value)),
+            Label::secondary(param_file_id, param_ident.span())
+                .with_message(format!("`{}` is a `{}`", param_ident, param_ty)),
+        ])
+}
+
+pub fn unknown_port(
+    file_id: FileId,
+    port_ident: &Ident,
+    entity_file_id: FileId,
+    entity_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0560")
+        .with_message(format!(
+            "entity `{}` has no port named `{}`",
+            entity_ident, port_ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, port_ident.span()).with_message("unknown port"),
+            Label::secondary(entity_file_id, entity_ident.span())
+                .with_message("entity declared here"),
+        ])
+}
+
+pub fn missing_port_connection(
+    file_id: FileId,
+    span: Span,
+    entity_file_id: FileId,
+    entity_ident: &Ident,
+    port_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0063")
+        .with_message(format!(
+            "input port `{}` of entity `{}` is not connected",
+            port_ident, entity_ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("missing `{}`", port_ident)),
+            Label::secondary(entity_file_id, port_ident.span()).with_message("port declared here"),
+        ])
+}
+
+pub fn unconnected_output(
+    file_id: FileId,
+    span: Span,
+    entity_file_id: FileId,
+    entity_ident: &Ident,
+    port_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unconnected_output")
+        .with_message(format!(
+            "output port `{}` of entity `{}` is not connected",
+            port_ident, entity_ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("`{}` is unused", port_ident)),
+            Label::secondary(entity_file_id, port_ident.span()).with_message("port declared here"),
+        ])
+}
+
+pub fn port_width_mismatch(
+    file_id: FileId,
+    signal_span: Span,
+    signal_width: u128,
+    entity_file_id: FileId,
+    port_ident: &Ident,
+    port_width: u128,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("port_width_mismatch")
+        .with_message(format!(
+            "mismatched widths connecting port `{}`",
+            port_ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, signal_span)
+                .with_message(format!("this signal is {} bits wide", signal_width)),
+            Label::secondary(entity_file_id, port_ident.span())
+                .with_message(format!("`{}` is {} bits wide", port_ident, port_width)),
+        ])
+}
+
+pub fn clock_domain_crossing(
+    file_id: FileId,
+    read_span: Span,
+    source_ident: &Ident,
+    source_clock_ident: &Ident,
+    clocking_span: Span,
+    target_ident: &Ident,
+    target_clock_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("clock_domain_crossing")
+        .with_message(format!(
+            "unsynchronized clock domain crossing from `{}` to `{}`",
+            source_clock_ident, target_clock_ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, read_span).with_message(format!(
+                "`{}` is in the `{}` domain",
+                source_ident, source_clock_ident
+            )),
+            Label::secondary(file_id, clocking_span).with_message(format!(
+                "`{}` is clocked by `{}` here",
+                target_ident, target_clock_ident
+            )),
+        ])
+        .with_notes(vec![format!(
+            "if `{}` is the first register of a synchronizer, mark it with `#[synchronizer]`",
+            target_ident
+        )])
+}
+
+pub fn invalid_attribute_value(
+    file_id: FileId,
+    span: Span,
+    attribute: &str,
+    expected: &[&str],
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("invalid_attribute_value")
+        .with_message(format!("invalid value for `#[{}]`", attribute))
+        .with_labels(vec![Label::primary(file_id, span).with_message(format!(
+            "expected one of {}",
+            expected
+                .iter()
+                .map(|value| format!("`\"{}\"`", value))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))])
+}
+
+pub fn unknown_synthesis_pragma(file_id: FileId, span: Span, known: &[&str]) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("unknown_synthesis_pragma")
+        .with_message("unknown synthesis pragma")
+        .with_labels(vec![Label::primary(file_id, span).with_message(format!(
+            "expected one of {}",
+            known
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))])
+}
+
+pub fn invalid_synthesis_pragma_value(
+    file_id: FileId,
+    span: Span,
+    pragma: &str,
+    takes_value: bool,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("invalid_synthesis_pragma_value")
+        .with_message(format!("invalid synthesis pragma `{}`", pragma))
+        .with_labels(vec![Label::primary(file_id, span).with_message(
+            if takes_value {
+                format!("expected a positive integer, like `{} = 8`", pragma)
+            } else {
+                format!("`{}` doesn't take a value", pragma)
+            },
+        )])
+}
+
+pub fn misplaced_synthesis_pragma(
+    file_id: FileId,
+    span: Span,
+    pragma: &str,
+    targets: &str,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("misplaced_synthesis_pragma")
+        .with_message(format!(
+            "synthesis pragma `{}` can't be applied here",
+            pragma
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("only applies to {}", targets))
+        ])
+}
+
+pub fn invalid_clock_frequency(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("invalid_clock_frequency")
+        .with_message("invalid clock frequency")
+        .with_labels(vec![Label::primary(file_id, span)
+            .with_message("expected a frequency like `freq = \"100MHz\"`")])
+        .with_notes(vec![
+            "frequencies are in `Hz`, `kHz`, `MHz`, or `GHz`".to_string()
+        ])
+}
+
+pub fn conflicting_clock_frequencies(
+    file_id: FileId,
+    span: Span,
+    first_file_id: FileId,
+    first_span: Span,
+    clock_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("conflicting_clock_frequencies")
+        .with_message(format!(
+            "conflicting frequencies for clock `{}`",
+            clock_ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("declared with a different frequency here"),
+            Label::secondary(first_file_id, first_span).with_message("first declared here"),
+        ])
+}
+
+pub fn unconstrained_clock(file_id: FileId, clock_ident: &Ident) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unconstrained_clock")
+        .with_message(format!("clock `{}` has no frequency", clock_ident))
+        .with_labels(vec![Label::primary(file_id, clock_ident.span())
+            .with_message("paths clocked by this aren't constrained")])
+        .with_notes(vec![
+            "declare its frequency with `#[clock(freq = \"100MHz\")]`".to_string(),
+        ])
+}
+
+pub fn missing_reset(file_id: FileId, clocking_span: Span, register_ident: &Ident) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("missing_reset")
+        .with_message(format!("register `{}` has no reset", register_ident))
+        .with_labels(vec![Label::primary(file_id, clocking_span)
+            .with_message(format!("`{}` is clocked here", register_ident))])
+        .with_notes(vec![
+            "registers without a reset can be allowed with `#[allow(missing_reset)]`".to_string(),
+        ])
+}
+
+pub fn unexpected_reset_style(
+    file_id: FileId,
+    reset_span: Span,
+    register_ident: &Ident,
+    reset_description: &str,
+    required_kind: &str,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unexpected_reset_style")
+        .with_message(format!(
+            "register `{}` has {}",
+            register_ident, reset_description
+        ))
+        .with_labels(vec![Label::primary(file_id, reset_span).with_message(
+            format!("`#[reset_style]` requires {} resets", required_kind),
+        )])
+}
+
+pub fn mixed_reset_styles(
+    file_id: FileId,
+    first: (Span, &Ident, &str),
+    second: (Span, &Ident, &str),
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("mixed_reset_styles")
+        .with_message("architecture mixes reset styles")
+        .with_labels(vec![
+            Label::primary(file_id, second.0)
+                .with_message(format!("`{}` has {}", second.1, second.2)),
+            Label::secondary(file_id, first.0)
+                .with_message(format!("`{}` has {}", first.1, first.2)),
+        ])
+        .with_notes(vec![
+            "mixed reset styles can be allowed with `#[allow(mixed_reset_styles)]`".to_string(),
+        ])
+}
+
+pub fn gray_encoding_not_power_of_two(
+    file_id: FileId,
+    span: Span,
+    enum_ident: &Ident,
+    variants: usize,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("gray_encoding_not_power_of_two")
+        .with_message(format!(
+            "`{}` has {} variants, which can't be gray coded",
+            enum_ident, variants
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("gray coding needs a power of two variants")
+        ])
+        .with_notes(vec![format!(
+            "the codes of the last and first variants of `{}` would differ by more than one bit",
+            enum_ident
+        )])
+}
+
+pub fn non_gray_transition(
+    file_id: FileId,
+    from_ident: &Ident,
+    to_ident: &Ident,
+    register_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("non_gray_transition")
+        .with_message(format!(
+            "`{}` goes from `{}` to `{}`, which differ by more than one bit",
+            register_ident, from_ident, to_ident
+        ))
+        .with_labels(vec![Label::primary(file_id, to_ident.span()).with_message(
+            format!("isn't next to `{}` in gray code", from_ident),
+        )])
+        .with_notes(vec![
+            "transitions that change more bits can be allowed with `#[allow(non_gray_transition)]`"
+                .to_string(),
+        ])
+}
+
+pub fn unreachable_state(
+    file_id: FileId,
+    state_ident: &Ident,
+    register_ident: &Ident,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unreachable_state")
+        .with_message(format!(
+            "state `{}` of `{}` is unreachable",
+            state_ident, register_ident
+        ))
+        .with_labels(vec![Label::primary(file_id, state_ident.span())
+            .with_message("no transition enters this state")])
+        .with_notes(vec![
+            "unreachable states can be allowed with `#[allow(unreachable_state)]`".to_string(),
+        ])
+}
+
+pub fn dead_end_state(file_id: FileId, state_ident: &Ident, register_ident: &Ident) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("dead_end_state")
+        .with_message(format!(
+            "state `{}` of `{}` has no exit transitions",
+            state_ident, register_ident
+        ))
+        .with_labels(vec![Label::primary(file_id, state_ident.span())
+            .with_message("the state machine never leaves this state")])
+        .with_notes(vec![
+            "states without exits can be allowed with `#[allow(dead_end_state)]`".to_string(),
+        ])
+}
+
+pub fn distributed_memory(
+    file_id: FileId,
+    span: Span,
+    memory_ident: &Ident,
+    reason: &str,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("distributed_memory")
+        .with_message(format!(
+            "memory `{}` will be built from registers instead of block RAM",
+            memory_ident
+        ))
+        .with_labels(vec![Label::primary(file_id, span).with_message(reason)])
+        .with_notes(vec![
+            "memories built from registers can be allowed with `#[allow(distributed_memory)]`"
+                .to_string(),
+        ])
+}
+
+pub fn too_many_memory_ports(
+    file_id: FileId,
+    access_span: Span,
+    memory_ident: &Ident,
+    access_kind: &str,
+    target_span: Span,
+    target: &str,
+    ports: &str,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("too_many_memory_ports")
+        .with_message(format!(
+            "memory `{}` needs more ports than a `{}` block RAM has",
+            memory_ident, target
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, access_span)
+                .with_message(format!("{} at another address here", access_kind)),
+            Label::secondary(file_id, target_span)
+                .with_message(format!("`{}` block RAMs have {}", target, ports)),
+        ])
+}
+
+pub fn unbounded_loop(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unbounded_loop")
+        .with_message("loop has no bound")
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("can't be unrolled into hardware")
+        ])
+        .with_notes(vec![
+            "unbounded loops can be allowed with `#[allow(unbounded_loop)]`".to_string(),
+        ])
+}
+
+pub fn recursion(file_id: FileId, call_span: Span, callee: &Ident) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("recursion")
+        .with_message(format!("`{}` is called recursively", callee))
+        .with_labels(vec![Label::primary(file_id, call_span)
+            .with_message("recursion can't be unrolled into hardware")])
+        .with_notes(vec![
+            "recursion can be allowed with `#[allow(recursion)]`".to_string()
+        ])
+}
+
+pub fn heap_allocation(file_id: FileId, span: Span, name: &str) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("heap_allocation")
+        .with_message(format!("`{}` allocates on the heap", name))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("hardware has no heap to allocate from")
+        ])
+        .with_notes(vec![
+            "heap allocation can be allowed with `#[allow(heap_allocation)]`".to_string(),
+        ])
+}
+
+pub fn floating_point(file_id: FileId, span: Span, ty: &str) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("floating_point")
+        .with_message(format!("`{}` is a floating-point type", ty))
+        .with_labels(vec![Label::primary(file_id, span).with_message(
+            "floating-point arithmetic isn't supported in hardware",
+        )])
+        .with_notes(vec![
+            "floating-point types can be allowed with `#[allow(floating_point)]`".to_string(),
+        ])
+}
+
+pub fn delay(file_id: FileId, span: Span, name: &str) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("delay")
+        .with_message(format!("`{}` waits for a delay", name))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("hardware only waits for clock edges")
+        ])
+        .with_notes(vec![
+            "delays can be allowed with `#[allow(delay)]`".to_string()
+        ])
+}
+
+pub fn unused_pub(
+    file_id: FileId,
+    vis: &Vis,
+    written: &str,
+    item_ident: &Ident,
+    module: &str,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unused_pub")
+        .with_message(format!(
+            "`{}` is `{}` but isn't used outside of `{}`",
+            item_ident, written, module
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, vis.span()).with_message("remove this to make it private")
+        ])
+        .with_notes(vec![
+            "unused visibility can be allowed with `#[allow(unused_pub)]`".to_string(),
+        ])
+}
+
+pub fn unread_signal(file_id: FileId, ident: &Ident) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unread_signal")
+        .with_message(format!("`{}` is assigned but never read", ident))
+        .with_labels(vec![
+            Label::primary(file_id, ident.span()).with_message("declared here")
+        ])
+        .with_notes(vec![
+            format!(
+                "remove it, or prefix its name with an underscore: `_{}`",
+                ident
+            ),
+            "unread signals can be allowed with `#[allow(unread_signal)]`".to_string(),
+        ])
+}
+
+pub fn unread_port(file_id: FileId, ident: &Ident, entity_ident: &Ident) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unread_port")
+        .with_message(format!("input `{}` is never read", ident))
+        .with_labels(vec![Label::primary(file_id, ident.span()).with_message(
+            format!("not read by any architecture of `{}`", entity_ident),
+        )])
+        .with_notes(vec![
+            format!(
+                "remove it, or prefix its name with an underscore: `_{}`",
+                ident
+            ),
+            "unread ports can be allowed with `#[allow(unread_port)]`".to_string(),
+        ])
+}
+
+pub fn implicit_truncation(file_id: FileId, span: Span, from: &str, to: &str) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("implicit_truncation")
+        .with_message(format!("implicit truncation from `{}` to `{}`", from, to))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("truncated to `{}`", to))
+        ])
+        .with_notes(vec![
+            format!("make it explicit with a cast: `as {}`", to),
+            "implicit truncations can be allowed with `#[allow(implicit_truncation)]`".to_string(),
+        ])
+}
+
+pub fn implicit_extension(
+    file_id: FileId,
+    span: Span,
+    from: &str,
+    to: &str,
+    signed: bool,
+) -> Diagnostic {
+    let extension = if signed { "sign" } else { "zero" };
+    Diagnostic::warning()
+        .with_code("implicit_extension")
+        .with_message(format!("implicit extension from `{}` to `{}`", from, to))
+        .with_labels(vec![Label::primary(file_id, span)
+            .with_message(format!("{}-extended to `{}`", extension, to))])
+        .with_notes(vec![
+            format!("make it explicit with a cast: `as {}`", to),
+            "implicit extensions can be allowed with `#[allow(implicit_extension)]`".to_string(),
+        ])
+}
+
+pub fn unreachable_branch(file_id: FileId, span: Span, constants: &[String]) -> Diagnostic {
+    let label = if constants.is_empty() {
+        "never true".to_string()
+    } else {
+        format!("never true because {}", constants.join(" and "))
+    };
+    Diagnostic::warning()
+        .with_code("unreachable_branch")
+        .with_message("unreachable branch")
+        .with_labels(vec![Label::primary(file_id, span).with_message(label)])
+        .with_notes(vec![
+            "it's left out of the generated hardware".to_string(),
+            "unreachable branches can be allowed with `#[allow(unreachable_branch)]`".to_string(),
+        ])
+}
+
+pub fn repeated_logic(file_id: FileId, spans: &[Span], duplicated_luts: u128) -> Diagnostic {
+    let mut labels = vec![Label::primary(file_id, spans[0]).with_message("computed here")];
+    labels.extend(
+        spans[1..]
+            .iter()
+            .map(|span| Label::secondary(file_id, *span).with_message("and again here")),
+    );
+    Diagnostic::warning()
+        .with_code("repeated_logic")
+        .with_message(format!("the same logic is computed {} times", spans.len()))
+        .with_labels(labels)
+        .with_notes(vec![
+            format!(
+                "about {} LUTs are duplicated, estimated like `--emit resources`",
+                duplicated_luts
+            ),
+            "assign it to a local once and read the local instead".to_string(),
+            "repeated logic can be allowed with `#[allow(repeated_logic)]`".to_string(),
+        ])
+}
+
+pub fn multiple_drivers(file_id: FileId, ident: &Ident, spans: &[Span]) -> Diagnostic {
+    let mut labels =
+        vec![Label::primary(file_id, spans[0]).with_message(format!("`{}` is driven here", ident))];
+    labels.extend(
+        spans[1..]
+            .iter()
+            .map(|span| Label::secondary(file_id, *span).with_message("and also here")),
+    );
+    Diagnostic::error()
+        .with_code("multiple_drivers")
+        .with_message(format!("`{}` has {} drivers", ident, spans.len()))
+        .with_labels(labels)
+        .with_notes(vec![
+            "a signal can only be driven by one function or instance".to_string(),
+            "if it's a bus that each of them only drives while enabled, mark it `#[tristate]`"
+                .to_string(),
+        ])
+}
+
+pub fn always_driven(file_id: FileId, ident: &Ident, span: Span, instance: bool) -> Diagnostic {
+    let (label, note) = if instance {
+        (
+            "connected to a port that isn't tri-state",
+            "mark the port of the entity `#[tristate]` if its architecture only assigns it under a condition",
+        )
+    } else {
+        (
+            "assigned unconditionally",
+            "assign it only under a condition, the enable of this driver, and never in a clocked block",
+        )
+    };
+    Diagnostic::error()
+        .with_code("always_driven")
+        .with_message(format!(
+            "the tri-state bus `{}` is driven by something that can't be disabled",
+            ident
+        ))
+        .with_labels(vec![Label::primary(file_id, span).with_message(label)])
+        .with_notes(vec![
+            "every driver of a tri-state bus has to leave it undriven while it's disabled"
+                .to_string(),
+            note.to_string(),
+        ])
+}
+
+pub fn tristate_contention(
+    file_id: FileId,
+    ident: &Ident,
+    first: Span,
+    second: Span,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("tristate_contention")
+        .with_message(format!(
+            "the tri-state bus `{}` can be driven twice at the same time",
+            ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, first).with_message("driven here"),
+            Label::secondary(file_id, second).with_message("while also driven here"),
+        ])
+        .with_notes(vec![
+            "the conditions of both assignments can hold together".to_string(),
+            "make the enables of the drivers exclusive, like `sel == 0` and `sel == 1`".to_string(),
+        ])
+}
+
+pub fn deep_inout(
+    file_id: FileId,
+    span: Span,
+    ident: &Ident,
+    entity_ident: &Ident,
+    depth: usize,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("deep_inout")
+        .with_message(format!(
+            "bidirectional port `{}` of `{}` is {} levels below the top",
+            ident, entity_ident, depth
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("connected below the instances of the top")
+        ])
+        .with_notes(vec![
+            "a bidirectional port needs an I/O buffer, so it has to reach a pin through every instance above it".to_string(),
+            "drive it from the top, or allow it with `#[allow(deep_inout)]` on the port or its entity".to_string(),
+        ])
+}
+
+pub fn mixed_signedness(
+    file_id: FileId,
+    span: Span,
+    signed: Span,
+    unsigned: Span,
+    comparison: bool,
+    unsigned_width: Option<u128>,
+) -> Diagnostic {
+    let (what, consequence) = if comparison {
+        (
+            "comparison",
+            "the comparison is unsigned, so negative values of the signed operand compare greater than every value of the unsigned one",
+        )
+    } else {
+        (
+            "arithmetic",
+            "the operation is unsigned, so negative values of the signed operand are read as large positive numbers",
+        )
+    };
+    let signed_type = match unsigned_width {
+        Some(width) => format!("`i{}`", width + 1),
+        None => "a signed type one bit wider".to_string(),
+    };
+    Diagnostic::warning()
+        .with_code("mixed_signedness")
+        .with_message(format!("{} mixes signed and unsigned operands", what))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("treated as unsigned"),
+            Label::secondary(file_id, signed).with_message("signed"),
+            Label::secondary(file_id, unsigned).with_message("unsigned"),
+        ])
+        .with_notes(vec![
+            consequence.to_string(),
+            format!(
+                "convert explicitly with `as`: the unsigned operand to {} for signed semantics, or the signed one to an unsigned type for unsigned semantics",
+                signed_type
+            ),
+            "mixed signedness can be allowed with `#[allow(mixed_signedness)]`".to_string(),
+        ])
+}
+
+pub fn not_synthesizable(file_id: FileId, span: Span) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("not_synthesizable")
+        .with_message("can't be lowered to hardware")
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("not supported in an architecture")
+        ])
+}
+
+pub fn unknown_width(file_id: FileId, ident: &Ident) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("unknown_width")
+        .with_message(format!("can't determine the width of `{}`", ident))
+        .with_labels(vec![Label::primary(file_id, ident.span())
+            .with_message("declare a type whose width is known during elaboration")])
+}
+
+pub fn unsupported_by_backend(file_id: FileId, span: Span, backend: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("unsupported_by_backend")
+        .with_message(format!("not supported by the {} backend", backend))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("cannot be lowered to {}", backend))
+        ])
+}
+
+pub fn too_wide_to_simulate(file_id: FileId, ident: &Ident, width: u128) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("too_wide_to_simulate")
+        .with_message(format!("`{}` is too wide to simulate", ident))
+        .with_labels(vec![Label::primary(file_id, ident.span()).with_message(format!(
+            "has a {} bit integer, but integers of at most 128 bits can be simulated",
+            width
+        ))])
+}
+
+pub fn black_box_not_simulated(file_id: FileId, span: Span, name: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("black_box_not_simulated")
+        .with_message(format!("black box `{}` can't be simulated", name))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("instantiated here")
+        ])
+        .with_notes(vec![
+            "black boxes have no architecture describing their behavior".to_string(),
+            format!(
+                "a program embedding rhdlc can register a model of `{}` to simulate it with",
+                name
+            ),
+        ])
+}
+
+pub fn model_not_compiled(file_id: FileId, span: Span, name: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("model_not_compiled")
+        .with_message(format!("the model of `{}` can't be compiled", name))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("instantiated here")
+        ])
+        .with_notes(vec![
+            "models registered by a program embedding rhdlc only run in its process".to_string(),
+            "simulate the design with `--backend interpreted` to use them".to_string(),
+        ])
+}
+
+pub fn random_not_compiled(file_id: FileId, ident: &Ident) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("random_not_compiled")
+        .with_message(format!(
+            "the random stimulus of `{}` can't be compiled",
+            ident
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, ident.span()).with_message("drawn at random")
+        ])
+        .with_notes(vec![
+            "simulate the design with `--backend interpreted` to draw it".to_string(),
+        ])
+}
+
+pub fn random_not_stimulus(file_id: FileId, ident: &Ident) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("random_not_stimulus")
+        .with_message(format!("`{}` can't be drawn at random", ident))
+        .with_labels(vec![
+            Label::primary(file_id, ident.span()).with_message("marked `#[random]`")
+        ])
+        .with_notes(vec![
+            "only the inputs of the testbench that don't clock registers are stimulus".to_string(),
+        ])
+}
+
+pub fn invalid_random(file_id: FileId, span: Span, msg: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("invalid_random")
+        .with_message("invalid random stimulus")
+        .with_labels(vec![Label::primary(file_id, span).with_message(msg)])
+        .with_notes(vec![
+            "constrain it with `min = <int>` and `max = <int>`, or with `weights = \"0: 3, 1..=7: 1\"`"
+                .to_string(),
+        ])
+}
+
+pub fn invalid_message(file_id: FileId, span: Span, msg: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("invalid_message")
+        .with_message("invalid message")
+        .with_labels(vec![Label::primary(file_id, span).with_message(msg)])
+        .with_notes(vec![
+            "signals are named like `\"count is {count}\"`, and `{}` is the next argument"
+                .to_string(),
+        ])
+}
+
+pub fn random_seed(testbench: &str, seed: u64) -> Diagnostic {
+    Diagnostic::note()
+        .with_code("random_seed")
+        .with_message(format!(
+            "the random stimulus of `{}` was drawn with seed {}",
+            testbench, seed
+        ))
+        .with_notes(vec![format!(
+            "rerun with `--seed {}` to draw it again",
+            seed
+        )])
+}
+
+pub fn coverpoint_not_sampled(file_id: FileId, ident: &Ident) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("coverpoint_not_sampled")
+        .with_message(format!("`{}` can't be a coverpoint", ident))
+        .with_labels(vec![
+            Label::primary(file_id, ident.span()).with_message("marked `#[coverpoint]`")
+        ])
+        .with_notes(vec![
+            "only the integers of the testbench that aren't clocks are sampled".to_string(),
+        ])
+}
+
+pub fn invalid_coverpoint(file_id: FileId, span: Span, msg: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("invalid_coverpoint")
+        .with_message("invalid coverpoint")
+        .with_labels(vec![Label::primary(file_id, span).with_message(msg)])
+        .with_notes(vec![
+            "name its bins with `bins = \"idle: 0, busy: 1..=14, done: 15\"`".to_string(),
+        ])
+}
+
+pub fn compile_simulation(err: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("compile_simulation")
+        .with_message(format!("couldn't compile the simulation: {}", err))
+        .with_notes(vec![
+            "the compiled backend builds the design with `rustc`, from `PATH` or `RUSTC`"
+                .to_string(),
+        ])
+}
+
+pub fn run_rustc(rustc: &str, err: std::io::Error) -> Diagnostic {
+    Diagnostic::error()
+        .with_code(IO_CODE)
+        .with_message(format!("couldn't run {}: {}", rustc, err))
+        .with_notes(vec![
+            "the compiled backend builds the design with `rustc`, from `PATH` or `RUSTC`"
+                .to_string(),
+        ])
+}
+
+pub fn start_simulation(err: std::io::Error) -> Diagnostic {
+    Diagnostic::error()
+        .with_code(IO_CODE)
+        .with_message(format!("couldn't start the compiled simulation: {}", err))
+}
+
+pub fn run_simulation(err: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("run_simulation")
+        .with_message(format!("couldn't run the compiled simulation: {}", err))
+}
+
+pub fn unsettled_simulation(cycle: u64) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("unsettled_simulation")
+        .with_message(format!("the design didn't settle in cycle {}", cycle))
+        .with_notes(vec![
+            "a combinational loop keeps changing, or registers keep clocking each other"
+                .to_string(),
+        ])
+}
+
+/// An assertion at a level, reported with its message when it has one like a panic in Rust
+pub fn assertion_failed(
+    file_id: FileId,
+    span: Span,
+    cycle: u64,
+    level: Level,
+    message: Option<&str>,
+) -> Diagnostic {
+    let diagnostic = match level {
+        Level::Warning => Diagnostic::warning(),
+        Level::Error | Level::Fatal => Diagnostic::error(),
+    };
+    let (message, label) = match message {
+        Some(message) => (message.to_string(), format!("reported in cycle {}", cycle)),
+        None => (
+            format!("assertion failed in cycle {}", cycle),
+            "doesn't hold in simulation".to_string(),
+        ),
+    };
+    diagnostic
+        .with_code("assertion_failed")
+        .with_message(message)
+        .with_labels(vec![Label::primary(file_id, span).with_message(label)])
+        .with_notes(if level == Level::Fatal {
+            vec!["the simulation stopped after this cycle".to_string()]
+        } else {
+            vec![]
+        })
+}
+
+pub fn assumption_violated(
+    file_id: FileId,
+    span: Span,
+    cycle: u64,
+    message: Option<&str>,
+) -> Diagnostic {
+    let (message, label) = match message {
+        Some(message) => (message.to_string(), format!("violated in cycle {}", cycle)),
+        None => (
+            format!("assumption violated in cycle {}", cycle),
+            "the stimulus doesn't satisfy this in simulation".to_string(),
+        ),
+    };
+    Diagnostic::error()
+        .with_code("assumption_violated")
+        .with_message(message)
+        .with_labels(vec![Label::primary(file_id, span).with_message(label)])
+}
+
+pub fn cover_not_reached(file_id: FileId, span: Span, cycles: u64) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("cover_not_reached")
+        .with_message(format!("cover not reached in {} cycles", cycles))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("never holds in simulation")
+        ])
+}
+
+pub fn unknown_output(
+    file_id: FileId,
+    span: Span,
+    instance: &str,
+    port: &str,
+    cycle: u64,
+) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("unknown_output")
+        .with_message(format!(
+            "output `{}` of `{}` is unknown in cycle {}",
+            port, instance, cycle
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("has an X or Z after it was reset")
+        ])
+        .with_notes(vec![
+            "it depends on a register without a reset or an input that isn't connected".to_string(),
+            "unknown outputs can be allowed with `#[allow(unknown_output)]`".to_string(),
+        ])
+}
+
+pub fn write_output(path: &str, err: std::io::Error) -> Diagnostic {
+    Diagnostic::error()
+        .with_code(IO_CODE)
+        .with_message(format!("couldn't write {}: {}", path, err))
+}
+
+pub fn formal_without_z3(option: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("formal_without_z3")
+        .with_message(format!(
+            "`{}` isn't supported by this build of rhdlc",
+            option
+        ))
+        .with_notes(vec![
+            "model checking a design needs rhdlc built with the `z3` feature".to_string(),
+        ])
+}
+
+pub fn not_a_bit_vector(file_id: FileId, ident: &Ident, width: u128) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("not_a_bit_vector")
+        .with_message(format!("`{}` can't be model checked", ident))
+        .with_labels(vec![Label::primary(file_id, ident.span()).with_message(
+            format!("has {} bits, a bit vector has 1 to {}", width, u32::MAX),
+        )])
+}
+
+pub fn assertion_can_fail(file_id: FileId, span: Span, cycle: u64) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("assertion_can_fail")
+        .with_message(format!("assertion can fail in cycle {}", cycle))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message("a counterexample violates this")
+        ])
+}
+
+pub fn assertion_not_proven(file_id: FileId, span: Span, depth: u64) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("assertion_not_proven")
+        .with_message(format!(
+            "assertion holds for {} cycles but couldn't be proven",
+            depth
+        ))
+        .with_labels(vec![
+            Label::primary(file_id, span).with_message(format!("isn't {}-inductive", depth))
+        ])
+        .with_notes(vec![
+            "it can fail after holding for that many cycles from a state that might not be reachable".to_string(),
+            "prove it with a greater `--depth`, or with assertions about the registers it depends on".to_string(),
+        ])
+}
+
+pub fn architectures_differ(names: &str, outputs: Vec<(FileId, Span)>, cycle: u64) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("architectures_differ")
+        .with_message(format!("{} aren't equivalent", names))
+        .with_labels(
+            outputs
+                .into_iter()
+                .map(|(file_id, span)| {
+                    Label::primary(file_id, span)
+                        .with_message(format!("differs in cycle {}", cycle))
+                })
+                .collect(),
+        )
+        .with_notes(vec![
+            "the inputs that tell them apart are in the report".to_string()
+        ])
+}
+
+pub fn equivalence_not_proven(names: &str, depth: u64) -> Diagnostic {
+    Diagnostic::warning()
+        .with_code("equivalence_not_proven")
+        .with_message(format!(
+            "{} are the same for {} cycles but couldn't be proven equivalent",
+            names, depth
+        ))
+        .with_notes(vec![
+            "their outputs can differ after being the same for that many cycles from states that might not be reachable".to_string(),
+            "prove it with a greater `--depth`".to_string(),
+        ])
+}
+
+pub fn too_many_errors(limit: usize) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("too_many_errors")
+        .with_message("too many errors emitted, stopping now")
+        .with_notes(vec![format!(
+            "only the first {} errors are reported; raise the limit with `--error-limit`",
+            limit
+        )])
+}
+
+pub fn unformatted(file_id: FileId, line: std::ops::Range<usize>) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("unformatted")
+        .with_message("this file isn't formatted")
+        .with_labels(vec![
+            Label::primary(file_id, line).with_message("formatted differently from here")
+        ])
+        .with_notes(vec!["run `rhdlc fmt` to format it".to_string()])
+}
+
+pub fn invalid_identifier(name: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("invalid_identifier")
+        .with_message(format!("`{}` isn't a valid identifier", name))
+}
+
+pub fn nothing_to_rename(location: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("nothing_to_rename")
+        .with_message(format!("there's no name to rename at {}", location))
+}
+
+pub fn ambiguous_rename(location: &str) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("ambiguous_rename")
+        .with_message(format!("the name at {} refers to more than one item", location))
+        .with_notes(vec!["rename one of them at its declaration".to_string()])
+}
+
+pub fn cannot_rename(location: Option<(FileId, Span)>) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("cannot_rename")
+        .with_message("this can't be renamed")
+        .with_labels(
+            location
+                .map(|(file_id, span)| vec![Label::primary(file_id, span)])
+                .unwrap_or_default(),
+        )
+        .with_notes(vec![
+            "only items that paths refer to, like types and fns, can be renamed".to_string(),
+        ])
+}
+
+pub fn cannot_rename_extern(file_id: FileId, ident: &Ident) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("cannot_rename_extern")
+        .with_message(format!("`{}` can't be renamed", ident))
+        .with_labels(vec![Label::primary(file_id, ident.span())
+            .with_message("declared in a library rhdlc ships with")])
+}
+
+pub fn rename_conflict(
+    file_id: FileId,
+    ident: &Ident,
+    name: &str,
+    existing_file_id: FileId,
+    existing: &Ident,
+) -> Diagnostic {
+    Diagnostic::error()
+        .with_code("E0428")
+        .with_message(format!("the name `{}` is already defined", name))
+        .with_labels(vec![
+            Label::primary(file_id, ident.span())
+                .with_message(format!("renaming `{}` to `{}` would redefine it", ident, name)),
+            Label::secondary(existing_file_id, existing.span())
+                .with_message(format!("`{}` is defined here", name)),
+        ])
+}
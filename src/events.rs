@@ -0,0 +1,141 @@
+//! `--message-format json-stream`: events written to standard output as the design is compiled,
+//! one JSON object a line, for build tools and editors to show progress before it's done. Like
+//! the messages of cargo, each is named by its `reason`:
+//!
+//! * `file-discovered`: a file of the design at `path` was found
+//! * `stage-started`: a `stage` of compilation, like `resolution`, started
+//! * `diagnostic`: a diagnostic was reported, with its `level`, `code`, `message`, `notes`, the
+//!   `spans` of its labels, and how it's `rendered` otherwise
+//! * `artifact-written`: an output was written to the file at `path`
+//! * `finished`: compilation finished, with the `status` rhdlc exits with
+//!
+//! Events are emitted to the stream started on the thread compiling the design, so the stages and
+//! outputs don't need to be handed it.
+use std::cell::RefCell;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use serde_json::{json, Value as Json};
+
+use crate::error::Diagnostic;
+use crate::find_file::FileGraph;
+use crate::status::Status;
+
+/// Where events are written, which is shared by clones of the options it's in
+#[derive(Clone)]
+pub struct Events(Arc<Mutex<dyn Write + Send>>);
+
+impl Events {
+    pub fn stdout() -> Self {
+        Self::to(io::stdout())
+    }
+
+    pub fn to(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(writer)))
+    }
+}
+
+impl Debug for Events {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("Events")
+    }
+}
+
+thread_local! {
+    static STREAM: RefCell<Option<Events>> = RefCell::new(None);
+}
+
+/// Streams the events emitted on this thread to `events`, if there are any, until the stream is
+/// dropped
+pub fn stream(events: Option<&Events>) -> Stream {
+    Stream(STREAM.with(|stream| stream.replace(events.cloned())))
+}
+
+/// Restores the stream it replaced when dropped
+pub struct Stream(Option<Events>);
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        STREAM.with(|stream| stream.replace(previous));
+    }
+}
+
+fn emit(reason: &str, mut event: Json) {
+    STREAM.with(|stream| {
+        if let Some(Events(writer)) = &*stream.borrow() {
+            event["reason"] = json!(reason);
+            let mut writer = writer.lock().unwrap();
+            // a build tool that stopped reading doesn't stop compilation
+            let _ = writeln!(writer, "{}", event).and_then(|()| writer.flush());
+        }
+    });
+}
+
+pub fn file_discovered(path: &str) {
+    emit("file-discovered", json!({ "path": path }));
+}
+
+pub fn stage_started(stage: &str) {
+    emit("stage-started", json!({ "stage": stage }));
+}
+
+pub fn diagnostic(diagnostic: &Diagnostic, file_graph: &FileGraph, rendered: &str) {
+    let spans = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let (start, end) = file_graph
+                .line_columns(label.file_id, label.range.clone())
+                .unzip();
+            json!({
+                "file": file_graph.inner.name(label.file_id).to_string_lossy(),
+                "byte_start": label.range.start,
+                "byte_end": label.range.end,
+                "line_start": start.map(|start| start.line),
+                "column_start": start.map(|start| start.column),
+                "line_end": end.map(|end| end.line),
+                "column_end": end.map(|end| end.column),
+                "is_primary": label.style == LabelStyle::Primary,
+                "label": Some(&label.message).filter(|message| !message.is_empty()),
+            })
+        })
+        .collect::<Vec<_>>();
+    emit(
+        "diagnostic",
+        json!({
+            "level": match diagnostic.severity {
+                Severity::Bug => "bug",
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "note",
+                Severity::Help => "help",
+            },
+            "code": diagnostic.code,
+            "message": diagnostic.message,
+            "notes": diagnostic.notes,
+            "spans": spans,
+            "rendered": rendered,
+        }),
+    );
+}
+
+pub fn artifact_written(path: &Path) {
+    emit(
+        "artifact-written",
+        json!({ "path": path.to_string_lossy() }),
+    );
+}
+
+pub fn finished(status: Status) {
+    emit(
+        "finished",
+        json!({
+            "success": status == Status::Success,
+            "status": status.code(),
+        }),
+    );
+}
@@ -1,25 +1,36 @@
 use crate::error::FileFindingError;
+use crate::query::Database;
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::fs;
 use std::io::Read;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use codespan::Files;
 use codespan_reporting::diagnostic::Diagnostic;
-use fxhash::FxHashMap as HashMap;
-use rhdl::ast::{File as RhdlFile, Ident, Item, ItemMod, ModContent};
+use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use rayon::prelude::*;
+use rhdl::ast::{File as RhdlFile, Ident, Item, ItemMod, ModContent, ToTokens, Tok};
 use rhdl::parser::FileParser;
 
 pub use codespan::FileId;
 
+use crate::codegen::interface;
 use crate::error;
+use crate::recover;
+use crate::stdlib;
+use crate::timing::{Instant, Timings};
 
 #[derive(Debug)]
 pub struct File {
     pub provider: FileContentProvider,
     content: String,
     pub parsed: Option<RhdlFile>,
+    /// Whether the file has syntax errors and was found tolerantly, so names that aren't found in
+    /// it note that `parsed` only has the items that did parse
+    pub incomplete: bool,
     pub parent: Option<FileId>,
 }
 
@@ -35,6 +46,22 @@ pub struct FileGraph {
     indices: Vec<FileId>,
     pub roots: Vec<FileId>,
     pub children: HashMap<FileId, Vec<(Vec<Ident>, FileId)>>,
+    /// The declarations of the modules that files other than the roots were loaded for
+    pub declarations: HashMap<FileId, Declaration>,
+    /// The files loaded by their absolute paths, so a file isn't loaded for two modules
+    loaded: HashMap<PathBuf, FileId>,
+    /// The roots of libraries the design uses, like `std`, by the name they're imported by
+    pub externs: HashMap<FileId, String>,
+}
+
+/// The `mod` item that a file was loaded for
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    /// The file the item is in
+    pub file: FileId,
+    pub span: Range<usize>,
+    /// The path of the module from its root, i.e. `cpu::alu`
+    pub module: String,
 }
 
 impl std::ops::Index<FileId> for FileGraph {
@@ -64,10 +91,108 @@ impl FileGraph {
     pub fn iter(&self) -> impl Iterator<Item = &FileId> {
         self.indices.iter()
     }
+
+    /// The root that `file` was found from
+    pub fn root_of(&self, file: FileId) -> FileId {
+        let mut root = file;
+        while let Some(parent) = self[root].parent {
+            root = parent;
+        }
+        root
+    }
+
+    /// The one-based line and column of byte `offset` in `file`, or `None` if it's past the end
+    pub fn line_column(&self, file: FileId, offset: usize) -> Option<LineColumn> {
+        let location = self.inner.location(file, offset as u32).ok()?;
+        Some(LineColumn {
+            line: location.line.to_usize() + 1,
+            column: location.column.to_usize() + 1,
+        })
+    }
+
+    /// The one-based lines and columns of the start and end of `range` in `file`
+    pub fn line_columns(
+        &self,
+        file: FileId,
+        range: Range<usize>,
+    ) -> Option<(LineColumn, LineColumn)> {
+        Some((
+            self.line_column(file, range.start)?,
+            self.line_column(file, range.end)?,
+        ))
+    }
+
+    /// The canonical absolute path of `file`, or `None` if it was read from a reader
+    pub fn absolute_path(&self, file: FileId) -> Option<PathBuf> {
+        match &self[file].provider {
+            FileContentProvider::File(path) => Some(absolute(path)),
+            FileContentProvider::Reader(..) | FileContentProvider::Embedded(_) => None,
+        }
+    }
+}
+
+/// The canonical absolute form of `path`. Paths that aren't on disk, like the unsaved buffers of
+/// an editor, are made absolute without resolving links.
+pub fn absolute(path: &Path) -> PathBuf {
+    match path.canonicalize() {
+        Ok(canonical) => canonical,
+        Err(_) => match std::env::current_dir() {
+            Ok(cwd) => cwd.join(path),
+            Err(_) => path.to_owned(),
+        },
+    }
+}
+
+/// A position in a file, as editors and other tools count them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineColumn {
+    /// The line, starting at 1
+    pub line: usize,
+    /// The character in the line, starting at 1
+    pub column: usize,
 }
 
 const STDIN_FALLBACK_EXTENSION: &str = "rhdl";
 
+/// The name of the root file of a library in its directory
+const LIBRARY_ROOT: &str = "lib.rhdl";
+
+/// The environment variable listing the directories libraries are found in, like `PATH`
+pub const RHDL_PATH: &str = "RHDL_PATH";
+
+/// A library registered as `<name>=<path>`, named by an identifier
+pub fn parse_library(value: &str) -> Result<(String, PathBuf), String> {
+    let mut parts = value.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(path)) if !path.is_empty() && is_library_name(name) => {
+            Ok((name.to_string(), path.into()))
+        }
+        _ => Err(format!(
+            "invalid library `{}`: expected <name>=<path>, where the name is an identifier",
+            value
+        )),
+    }
+}
+
+pub fn is_library_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_"
+}
+
+/// The directories in [`RHDL_PATH`], in order
+pub fn rhdl_path() -> Vec<PathBuf> {
+    std::env::var_os(RHDL_PATH)
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
+
+/// The size in bytes of the largest file read when no other is given, far past any real design
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 16 << 20;
+/// The number of files found when no other is given, past which modules are left out
+pub const DEFAULT_MAX_FILES: usize = 10_000;
+
 /// Finds source code for modules from their files recursively
 /// Errors are related to file-reading issues, missing content, or conflicting files
 /// Does not care about naming conflicts, as those are handled downstream.
@@ -75,6 +200,43 @@ const STDIN_FALLBACK_EXTENSION: &str = "rhdl";
 pub struct FileFinder {
     pub file_graph: FileGraph,
     pub errors: Vec<Diagnostic<FileId>>,
+    /// Contents read instead of the files at these paths, like the unsaved buffers of an editor
+    pub overlay: HashMap<PathBuf, String>,
+    /// Memoizes parsing the files found, if given
+    pub database: Option<Database>,
+    /// How long finding and parsing the files took
+    pub timings: Timings,
+    /// The directory to find the modules of a root read from a reader in, instead of the
+    /// current directory
+    pub src_dir: Option<PathBuf>,
+    /// The extension of the files of those modules, instead of `rhdl`
+    pub src_extension: Option<String>,
+    /// Mark files with syntax errors incomplete, so a name that isn't found in one notes that it
+    /// might be declared in an item that didn't parse. The items that did parse are kept either
+    /// way; an editor reports a design that's mid-edit with this.
+    pub tolerant: bool,
+    /// Replace invalid UTF-8 in files with U+FFFD and warn, instead of leaving the files out
+    pub lossy_utf8: bool,
+    /// The size in bytes of the largest file read, or [`DEFAULT_MAX_FILE_SIZE`] if `None`
+    pub max_file_size: Option<u64>,
+    /// The number of files found past which modules are left out, or [`DEFAULT_MAX_FILES`] if
+    /// `None`
+    pub max_files: Option<usize>,
+    /// Libraries registered by name, as the directory with their `lib.rhdl` or their root file.
+    /// They take precedence over the libraries found in `library_path`.
+    pub libraries: Vec<(String, PathBuf)>,
+    /// Directories whose subdirectories with a `lib.rhdl` are libraries named by the
+    /// subdirectory, like those in [`RHDL_PATH`]
+    pub library_path: Vec<PathBuf>,
+    /// Find libraries from their up-to-date interfaces instead of their sources. Their entities
+    /// are black boxes there, without their architectures, so this is only for checking a design.
+    pub interfaces: bool,
+    /// Whether reaching that number was reported, which is only done once
+    files_exhausted: bool,
+    /// Whether the root being found is the standard library's, so its modules are embedded too
+    embedded: bool,
+    /// Files read and parsed ahead of the search, by path
+    prefetched: HashMap<PathBuf, (String, Option<RhdlFile>)>,
     cwd: PathBuf,
     extension: String,
     ancestry: Vec<FileId>,
@@ -84,12 +246,14 @@ pub struct FileFinder {
 pub enum FileContentProvider {
     File(PathBuf),
     Reader(String, Box<dyn Read>),
+    /// A file of the standard library embedded in rhdlc, by its path in the library
+    Embedded(PathBuf),
 }
 
 impl FileContentProvider {
     fn name(&self) -> OsString {
         match self {
-            Self::File(path) => path.as_os_str().to_os_string(),
+            Self::File(path) | Self::Embedded(path) => path.as_os_str().to_os_string(),
             Self::Reader(name, _) => name.clone().into(),
         }
     }
@@ -99,26 +263,178 @@ impl std::fmt::Debug for FileContentProvider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use FileContentProvider::*;
         match self {
-            File(path) => f.debug_tuple("FileContentProvider").field(&path).finish(),
+            File(path) | Embedded(path) => {
+                f.debug_tuple("FileContentProvider").field(&path).finish()
+            }
             Reader(name, _) => f.debug_tuple("FileContentProvider").field(&name).finish(),
         }
     }
 }
 
 impl FileFinder {
-    /// A top level entry point
-    /// TODO: handle a top level file named `a.rhdl` with `mod a;` declared.
+    /// A top level entry point. The libraries the design names are found after it.
     pub fn find_tree(&mut self, root_provider: FileContentProvider) {
+        let start = Instant::now();
+        let parsing = self.timings.time("parse");
+        // listed before parsing, which happens while the files are found
+        self.timings.add("find files", Duration::default());
+        self.find_root(root_provider);
+        self.find_libraries();
+        let parsed = self.timings.time("parse") - parsing;
+        self.timings
+            .add("find files", start.elapsed().saturating_sub(parsed));
+        log::info!(
+            "found {} files in {} roots",
+            self.file_graph.indices.len(),
+            self.file_graph.roots.len()
+        );
+    }
+
+    /// The root files of the libraries that can be used, by name: those registered, then those in
+    /// the library path in order, then the standard library, whose root is `None` since it's
+    /// embedded. A library is shadowed by the ones before it with the same name.
+    fn registry(&self) -> HashMap<String, Option<PathBuf>> {
+        let mut registry = HashMap::default();
+        for (name, path) in self.libraries.iter() {
+            let root = if path.is_dir() {
+                path.join(LIBRARY_ROOT)
+            } else {
+                path.clone()
+            };
+            registry.entry(name.clone()).or_insert(Some(root));
+        }
+        for dir in self.library_path.iter() {
+            // like PATH, directories that can't be read are skipped
+            let mut entries = match fs::read_dir(dir) {
+                Ok(entries) => entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .collect::<Vec<_>>(),
+                Err(_) => continue,
+            };
+            entries.sort();
+            for entry in entries {
+                let root = entry.join(LIBRARY_ROOT);
+                if let (Some(name), true) =
+                    (entry.file_name().and_then(OsStr::to_str), root.is_file())
+                {
+                    registry.entry(name.to_string()).or_insert(Some(root));
+                }
+            }
+        }
+        registry.entry(stdlib::NAME.to_string()).or_insert(None);
+        registry
+    }
+
+    /// Finds the libraries named by the files of the design as extern roots named by the library,
+    /// then those named by their files in turn. A library isn't found if the root of the design
+    /// declares a module by its name, which shadows it.
+    fn find_libraries(&mut self) {
+        let registry = self.registry();
+        let declared = self
+            .file_graph
+            .roots
+            .first()
+            .and_then(|root| self.file_graph[*root].parsed.as_ref())
+            .map(|parsed| {
+                parsed
+                    .items
+                    .iter()
+                    .filter_map(|item| match item {
+                        Item::Mod(item_mod) => Some(item_mod.ident.inner.clone()),
+                        _ => None,
+                    })
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        let mut found = HashSet::default();
+        let mut scanned = 0;
+        loop {
+            let mut named = vec![];
+            for file in self.file_graph.indices[scanned..].iter() {
+                let tokens = self.file_graph[*file]
+                    .parsed
+                    .as_ref()
+                    .map(ToTokens::to_tokens)
+                    .unwrap_or_default();
+                for token in tokens {
+                    if let Tok::Ident(ident) = token {
+                        if registry.contains_key(&ident.inner)
+                            && !declared.contains(&ident.inner)
+                            && found.insert(ident.inner.clone())
+                        {
+                            named.push(ident.inner.clone());
+                        }
+                    }
+                }
+            }
+            scanned = self.file_graph.indices.len();
+            if named.is_empty() {
+                break;
+            }
+            for name in named {
+                let provider = match &registry[&name] {
+                    Some(root) if self.interfaces => match self.interface(&name, root) {
+                        Some(interface) => FileContentProvider::File(interface),
+                        None => FileContentProvider::File(root.clone()),
+                    },
+                    Some(root) => FileContentProvider::File(root.clone()),
+                    None => FileContentProvider::Embedded(stdlib::ROOT.into()),
+                };
+                log::info!(
+                    "using library `{}` from {}",
+                    name,
+                    provider.name().to_string_lossy()
+                );
+                if let Some(root) = self.find_root(provider) {
+                    self.file_graph.externs.insert(root, name);
+                }
+            }
+        }
+    }
+
+    /// The interface generated for the library `name` next to its `root`, if the sources it was
+    /// generated from haven't changed since. They're read but not parsed. A stale interface is
+    /// warned about and the sources are found instead.
+    fn interface(&mut self, name: &str, root: &Path) -> Option<PathBuf> {
+        let path = root.with_extension(interface::EXTENSION);
+        let max_file_size = self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE);
+        let content = read(&self.overlay, &path, max_file_size).ok()?;
+        let content = decode(content, self.lossy_utf8).ok()?.0;
+        let dir = root.parent().unwrap_or_else(|| Path::new(""));
+        let sources = interface::sources(&content);
+        let unchanged = !sources.is_empty()
+            && sources.iter().all(|(source, fingerprint)| {
+                read(&self.overlay, &dir.join(source), max_file_size)
+                    .ok()
+                    .and_then(|bytes| decode(bytes, self.lossy_utf8).ok())
+                    .map_or(false, |(source, _)| {
+                        interface::fingerprint(&source) == *fingerprint
+                    })
+            });
+        if unchanged {
+            Some(path)
+        } else {
+            self.errors.push(error::stale_interface(&path, name));
+            None
+        }
+    }
+
+    /// Finds a root and its modules, returning the root if it was found
+    fn find_root(&mut self, root_provider: FileContentProvider) -> Option<FileId> {
         let root_name = root_provider.name();
         let root_path = match &root_provider {
-            FileContentProvider::File(path) => Some(path.clone()),
+            FileContentProvider::File(path) | FileContentProvider::Embedded(path) => {
+                Some(path.clone())
+            }
             _ => None,
         };
+        self.embedded = matches!(root_provider, FileContentProvider::Embedded(_));
         let root_file_id = match self.find(root_provider, None) {
             Ok(root_file_id) => root_file_id,
             Err(err) => {
                 self.errors.push(err.diagnostic(root_name, None));
-                return;
+                return None;
             }
         };
         let mods: Vec<ItemMod> = self.file_graph[root_file_id]
@@ -143,12 +459,14 @@ impl FileFinder {
             .map(Path::to_owned)
         {
             cwd
+        } else if let Some(src_dir) = &self.src_dir {
+            src_dir.clone()
         } else {
             match std::env::current_dir() {
                 Ok(cwd) => cwd,
                 Err(cause) => {
                     self.errors.push(error::working_directory(cause));
-                    return;
+                    return Some(root_file_id);
                 }
             }
         };
@@ -159,8 +477,13 @@ impl FileFinder {
                     .map(OsStr::to_string_lossy)
                     .map(|cow| cow.to_string())
             })
+            .or_else(|| self.src_extension.clone())
             .unwrap_or_else(|| STDIN_FALLBACK_EXTENSION.to_owned());
 
+        // the files of the standard library are already in memory
+        if !self.embedded {
+            self.prefetch(&mods);
+        }
         self.ancestry.push(root_file_id);
         for child in mods {
             if child.content.is_file() {
@@ -170,26 +493,29 @@ impl FileFinder {
             }
         }
         self.ancestry.pop();
+        Some(root_file_id)
     }
 
     /// If the code is in a mod file, there could be more modules that need to be recursively found.
     fn find_mod(&mut self, item_mod: &ItemMod) {
+        let max_files = self.max_files.unwrap_or(DEFAULT_MAX_FILES);
+        if self.file_graph.indices.len() >= max_files {
+            if !self.files_exhausted {
+                self.files_exhausted = true;
+                self.errors.push(error::too_many_files(
+                    max_files,
+                    self.ancestry.last().cloned().map(|id| (id, item_mod)),
+                ));
+            }
+            return;
+        }
         self.ident_path.push(item_mod.ident.clone());
-        let mut mod_base_path = self.cwd.clone();
-        self.ident_path.iter().for_each(|ident| {
-            let ident = ident.to_string();
-            mod_base_path.push(ident.strip_prefix("r#").unwrap_or(&ident));
-        });
-        let mod_folder_file_path = mod_base_path.join("mod").with_extension(&self.extension);
-        let mod_file_path = mod_base_path.with_extension(&self.extension);
+        let (mod_file_path, mod_folder_file_path) = self.mod_file_paths(&self.ident_path);
         let parent = self.ancestry.last().cloned().map(|id| (id, item_mod));
 
         let found_file_id = match (
-            self.find(FileContentProvider::File(mod_file_path.clone()), parent),
-            self.find(
-                FileContentProvider::File(mod_folder_file_path.clone()),
-                parent,
-            ),
+            self.find(self.provider(mod_file_path.clone()), parent),
+            self.find(self.provider(mod_folder_file_path.clone()), parent),
         ) {
             (Ok(found_file_id), Err(err)) => {
                 if !err.is_io_not_found() {
@@ -303,53 +629,348 @@ impl FileFinder {
         }
     }
 
+    /// The file at `path` of the root being found, which is in the standard library if the root is
+    fn provider(&self, path: PathBuf) -> FileContentProvider {
+        if self.embedded {
+            FileContentProvider::Embedded(path)
+        } else {
+            FileContentProvider::File(path)
+        }
+    }
+
+    /// The paths of the two files a module at `ident_path` could be in: `a/b.rhdl` and
+    /// `a/b/mod.rhdl`
+    fn mod_file_paths(&self, ident_path: &[Ident]) -> (PathBuf, PathBuf) {
+        let mut mod_base_path = self.cwd.clone();
+        ident_path.iter().for_each(|ident| {
+            let ident = ident.to_string();
+            mod_base_path.push(ident.strip_prefix("r#").unwrap_or(&ident));
+        });
+        (
+            mod_base_path.with_extension(&self.extension),
+            mod_base_path.join("mod").with_extension(&self.extension),
+        )
+    }
+
+    /// Reads and parses the files that the modules declared in `mods` could be in, then the files
+    /// that the modules declared in those could be in, and so on, a level at a time with the
+    /// files of each level parsed in parallel. They're added to the graph as they're found by the
+    /// depth-first search afterwards, so the graph doesn't depend on which parse finishes first.
+    /// Files that don't parse are parsed again then to report why.
+    /// A file reached again through links is only read the first time, so cyclic links end.
+    fn prefetch(&mut self, mods: &[ItemMod]) {
+        let mut level = vec![];
+        self.mod_file_candidates(mods.iter(), &mut vec![], &mut level);
+        let mut visited = self
+            .file_graph
+            .loaded
+            .keys()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let max_file_size = self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE);
+        let max_files = self.max_files.unwrap_or(DEFAULT_MAX_FILES);
+        while !level.is_empty() {
+            level.retain(|(path, _)| visited.insert(absolute(path)));
+            // the search reads what's left if it's under the limit
+            level.truncate(max_files.saturating_sub(self.prefetched.len()));
+            let start = Instant::now();
+            let overlay = &self.overlay;
+            let database = &self.database;
+            let found = level
+                .into_par_iter()
+                .filter_map(|(path, ident_path)| {
+                    let bytes = read(overlay, &path, max_file_size).ok()?;
+                    if bytes.len() as u64 > max_file_size {
+                        return None;
+                    }
+                    // invalid UTF-8 is read again by the search to report or replace it
+                    let (content, _) = decode(bytes, false).ok()?;
+                    let parsed = match database
+                        .as_ref()
+                        .and_then(|database| database.memoized_parse(&content))
+                    {
+                        Some(_) => None,
+                        None => FileParser::new().parse(&content).ok(),
+                    };
+                    Some((path, ident_path, content, parsed))
+                })
+                .collect::<Vec<_>>();
+            self.timings.record("parse", start);
+
+            level = vec![];
+            for (path, mut ident_path, content, parsed) in found {
+                let items = parsed.as_ref().or_else(|| {
+                    self.database
+                        .as_ref()
+                        .and_then(|database| database.memoized_parse(&content))
+                });
+                if let Some(items) = items {
+                    let mods = items.items.iter().filter_map(|item| match item {
+                        Item::Mod(m) => Some(m),
+                        _ => None,
+                    });
+                    self.mod_file_candidates(mods, &mut ident_path, &mut level);
+                }
+                self.prefetched.insert(path, (content, parsed));
+            }
+        }
+    }
+
+    /// The files that the modules declared in `mods`, at `ident_path`, could be in, with the
+    /// paths of the modules
+    fn mod_file_candidates<'a>(
+        &self,
+        mods: impl Iterator<Item = &'a ItemMod>,
+        ident_path: &mut Vec<Ident>,
+        candidates: &mut Vec<(PathBuf, Vec<Ident>)>,
+    ) {
+        for item_mod in mods {
+            ident_path.push(item_mod.ident.clone());
+            match &item_mod.content {
+                ModContent::Here(here) => {
+                    let mods = here.items.iter().filter_map(|item| match item {
+                        Item::Mod(m) => Some(m),
+                        _ => None,
+                    });
+                    self.mod_file_candidates(mods, ident_path, candidates);
+                }
+                _ => {
+                    let (mod_file_path, mod_folder_file_path) = self.mod_file_paths(ident_path);
+                    candidates.push((mod_file_path, ident_path.clone()));
+                    candidates.push((mod_folder_file_path, ident_path.clone()));
+                }
+            }
+            ident_path.pop();
+        }
+    }
+
     fn find(
         &mut self,
         mut provider: FileContentProvider,
         parent: Option<(FileId, &ItemMod)>,
     ) -> Result<FileId, FileFindingError> {
-        let content = match &mut provider {
-            FileContentProvider::File(path) => fs::File::open(&path).and_then(|mut f| {
-                let mut content = String::new();
-                f.read_to_string(&mut content)?;
-                Ok(content)
-            }),
+        let absolute_path = match &provider {
+            FileContentProvider::File(path) => Some(absolute(path)),
+            FileContentProvider::Reader(..) | FileContentProvider::Embedded(_) => None,
+        };
+        if let Some(loaded) = absolute_path
+            .as_ref()
+            .and_then(|path| self.file_graph.loaded.get(path))
+        {
+            // through links, a module's file can be one of the files the module is in
+            if self.ancestry.contains(loaded) {
+                return Err(FileFindingError::Circular(error::circular_module(
+                    self.file_graph.inner.name(*loaded).to_os_string(),
+                    &self.ident_path,
+                    self.file_graph.declarations.get(loaded),
+                    parent,
+                )));
+            }
+            return Err(FileFindingError::AlreadyLoaded(error::file_already_loaded(
+                provider.name(),
+                self.file_graph.declarations.get(loaded),
+                parent,
+            )));
+        }
+        let casing = match (&provider, &absolute_path, parent) {
+            (FileContentProvider::File(path), Some(absolute_path), Some(_)) => path
+                .strip_prefix(&self.cwd)
+                .ok()
+                .and_then(|relative| case_mismatch(relative, absolute_path)),
+            _ => None,
+        };
+        let max_file_size = self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE);
+        let (content, prefetched) = match &mut provider {
+            FileContentProvider::File(path) => match self.prefetched.remove(path.as_path()) {
+                Some((content, prefetched)) => (Ok((content, None)), prefetched),
+                None => (read(&self.overlay, path, max_file_size), None),
+            },
             FileContentProvider::Reader(_, reader) => {
-                let mut content = String::new();
-                reader.read_to_string(&mut content).map(|_| content)
+                let mut bytes = vec![];
+                let read = reader
+                    .by_ref()
+                    .take(max_file_size + 1)
+                    .read_to_end(&mut bytes);
+                (read.map(|_| bytes), None)
+            }
+            FileContentProvider::Embedded(path) => {
+                let source = stdlib::source(path)
+                    .map(|source| source.as_bytes().to_vec())
+                    .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound));
+                (source, None)
             }
         };
+        let content = match content {
+            Ok(bytes) if bytes.len() as u64 > max_file_size => {
+                Err(FileFindingError::TooLarge(max_file_size))
+            }
+            Ok(bytes) => decode(bytes, self.lossy_utf8),
+            Err(err) => Err(FileFindingError::Io(err)),
+        };
         match content {
-            Ok(content) => {
-                let res = FileParser::new().parse(&content);
+            Ok((content, replaced)) => {
+                let res = match self
+                    .database
+                    .as_mut()
+                    .and_then(|database| database.parsed(&content))
+                {
+                    Some(parsed) => {
+                        log::debug!(
+                            "{} is unchanged, reusing its parse",
+                            provider.name().to_string_lossy()
+                        );
+                        Ok(parsed)
+                    }
+                    None => {
+                        log::debug!("parsing {}", provider.name().to_string_lossy());
+                        let res = match prefetched {
+                            Some(parsed) => Ok(parsed),
+                            None => {
+                                let start = Instant::now();
+                                let res = recover::parse(&content);
+                                self.timings.record("parse", start);
+                                res
+                            }
+                        };
+                        if let (Some(database), Ok(parsed)) = (&mut self.database, &res) {
+                            database.memoize_parse(&content, parsed);
+                        }
+                        res
+                    }
+                };
                 // todo: see if this clone can be avoided, this could be a large file
                 let mut file = File {
                     provider,
                     content: content.clone(),
                     parsed: None,
+                    incomplete: false,
                     parent: parent.map(|(id, _)| id),
                 };
-                let err = match res {
+                let errs = match res {
                     Ok(parsed) => {
                         file.parsed = Some(parsed);
-                        None
+                        vec![]
+                    }
+                    Err(recovered) => {
+                        file.incomplete = self.tolerant && recovered.partial.is_some();
+                        file.parsed = recovered.partial;
+                        recovered.errors
                     }
-                    Err(err) => Some(err),
                 };
                 let file_id = self.file_graph.add_node(file);
-                let file_ref = &self.file_graph[file_id];
-                if let Some(err) = err {
-                    Err(FileFindingError::Parse(error::parse(
-                        file_ref.provider.name(),
+                if let Some(offset) = replaced {
+                    self.errors.push(error::replaced_invalid_utf8(
+                        self.file_graph.inner.name(file_id).to_os_string(),
                         file_id,
-                        parent,
-                        err,
-                    )))
-                } else {
+                        offset,
+                    ));
+                }
+                if let Some(absolute_path) = absolute_path {
+                    self.file_graph.loaded.insert(absolute_path, file_id);
+                }
+                if let Some((parent_file_id, item_mod)) = parent {
+                    let declaration = Declaration {
+                        file: parent_file_id,
+                        span: item_mod.span().into(),
+                        module: self
+                            .ident_path
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join("::"),
+                    };
+                    if let Some((declared, on_disk)) = casing {
+                        self.errors.push(error::module_file_casing(
+                            &declaration.module,
+                            parent_file_id,
+                            item_mod,
+                            declared,
+                            on_disk,
+                        ));
+                    }
+                    self.file_graph.declarations.insert(file_id, declaration);
+                }
+                let file_ref = &self.file_graph[file_id];
+                let mut diagnostics = errs
+                    .into_iter()
+                    .map(|err| error::parse(file_ref.provider.name(), file_id, parent, err))
+                    .collect::<Vec<_>>();
+                if diagnostics.is_empty() || file_ref.parsed.is_some() {
+                    // the items that did parse are still used
+                    self.errors.append(&mut diagnostics);
                     Ok(file_id)
+                } else {
+                    let first = diagnostics.remove(0);
+                    self.errors.append(&mut diagnostics);
+                    Err(FileFindingError::Parse(first))
                 }
             }
-            Err(err) => Err(FileFindingError::Io(err)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The last component of `relative`, the path a module's file was found at, that differs only by
+/// case from the one in its place at the end of `absolute_path`, with that one. On
+/// case-insensitive file systems both name the same file, and canonical paths have the casing on
+/// disk.
+fn case_mismatch(relative: &Path, absolute_path: &Path) -> Option<(OsString, OsString)> {
+    relative
+        .components()
+        .rev()
+        .zip(absolute_path.components().rev())
+        .map(|(declared, on_disk)| (declared.as_os_str(), on_disk.as_os_str()))
+        .find(|(declared, on_disk)| {
+            declared != on_disk
+                && declared
+                    .to_string_lossy()
+                    .eq_ignore_ascii_case(&on_disk.to_string_lossy())
+        })
+        .map(|(declared, on_disk)| (declared.to_os_string(), on_disk.to_os_string()))
+}
+
+/// The contents of the file at `path`, or of the `overlay` in its place, up to a byte past
+/// `max_size` so a file that's too large isn't read whole
+fn read(
+    overlay: &HashMap<PathBuf, String>,
+    path: &Path,
+    max_size: u64,
+) -> std::io::Result<Vec<u8>> {
+    match overlay.get(path) {
+        Some(content) => Ok(content.clone().into_bytes()),
+        None => {
+            let mut bytes = vec![];
+            fs::File::open(path)?
+                .take(max_size + 1)
+                .read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+    }
+}
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The text of a file from its `bytes`, without the byte order mark some editors start it with.
+/// Invalid UTF-8 is replaced with U+FFFD when `lossy`, along with the offset of the first
+/// sequence replaced.
+fn decode(mut bytes: Vec<u8>, lossy: bool) -> Result<(String, Option<usize>), FileFindingError> {
+    let bom = if bytes.starts_with(BOM) {
+        bytes.drain(..BOM.len());
+        BOM.len()
+    } else {
+        0
+    };
+    match String::from_utf8(bytes) {
+        Ok(content) => Ok((content, None)),
+        Err(err) => {
+            let offset = err.utf8_error().valid_up_to();
+            if lossy {
+                let content = String::from_utf8_lossy(err.as_bytes()).into_owned();
+                Ok((content, Some(offset)))
+            } else {
+                // the offset in the file as it is on disk
+                Err(FileFindingError::InvalidUtf8(bom + offset))
+            }
         }
     }
 }
@@ -0,0 +1,891 @@
+//! `rhdlc fmt`: formats the files of a design in a canonical style.
+//!
+//! Files are formatted from their tokens rather than their syntax trees, since the parser drops
+//! comments, but only once the whole design has been found and parsed. Items, statements, fields,
+//! and match arms each go on a line of their own, indented by four spaces for each block they're
+//! in, with a trailing comma after the last field or variant. Lines longer than the width are
+//! broken at their first parenthesized, bracketed, or generic list, with each element of the
+//! list on a line of its own.
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::error::{unformatted, write_output, Diagnostic};
+use crate::find_file::{FileContentProvider, FileGraph};
+
+/// `rhdlc fmt`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    /// The width to break lines at
+    pub width: usize,
+    /// Report the files that aren't formatted instead of formatting them
+    pub check: bool,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            width: 100,
+            check: false,
+        }
+    }
+}
+
+/// Formats every file of the design in place, or with `check`, reports the first line of each
+/// file that would change. A design read from standard input is written to standard output.
+pub fn format_files(file_graph: &FileGraph, format: &Format, errors: &mut Vec<Diagnostic>) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for file in file_graph.iter().copied() {
+        // libraries and their interfaces aren't the design's to format
+        if file_graph.externs.contains_key(&file_graph.root_of(file)) {
+            continue;
+        }
+        let source = file_graph[file].as_ref();
+        let formatted = self::format(source, format.width);
+        let path = match &file_graph[file].provider {
+            FileContentProvider::File(path) => Some(path),
+            FileContentProvider::Reader(..) => None,
+            // the standard library isn't the design's to format
+            FileContentProvider::Embedded(_) => continue,
+        };
+        if formatted == source && path.is_some() {
+            continue;
+        }
+        if format.check {
+            errors.push(unformatted(file, first_difference(source, &formatted)));
+            continue;
+        }
+        let written = match path {
+            Some(path) => {
+                fs::write(path, formatted).map_err(|err| write_output(&path.to_string_lossy(), err))
+            }
+            None => stdout
+                .write_all(formatted.as_bytes())
+                .map_err(|err| write_output("stdout", err)),
+        };
+        if let Err(err) = written {
+            errors.push(err);
+        }
+    }
+}
+
+/// The byte range of the first line of `source` that's different in `formatted`
+fn first_difference(source: &str, formatted: &str) -> Range<usize> {
+    let line = source
+        .lines()
+        .zip(formatted.lines())
+        .position(|(line, formatted_line)| line != formatted_line)
+        .unwrap_or_else(|| source.lines().count().min(formatted.lines().count()));
+    let start = match line {
+        0 => 0,
+        line => source
+            .match_indices('\n')
+            .nth(line - 1)
+            .map_or(source.len(), |(newline, _)| newline + 1),
+    };
+    let end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |newline| start + newline);
+    start..end
+}
+
+/// Keywords that are followed by a space even before an opening delimiter, and after which an
+/// operator is unary
+const SPACED_KEYWORDS: &[&str] = &[
+    "as", "break", "const", "else", "for", "if", "in", "inout", "let", "loop", "match", "mut",
+    "out", "ref", "return", "static", "use", "where", "while",
+];
+
+/// Punctuation that's more than one character, longest first
+const MULTI_CHAR_PUNCT: &[&str] = &[
+    "<<=", ">>=", "..=", "...", "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "..", "+=",
+    "-=", "*=", "/=", "%=", "^=", "&=", "|=",
+];
+
+/// Tokens that stay on the line of a `}` before them, like `} else {`
+const AFTER_CLOSE_BRACE: &[&str] = &["else", ",", ";", ")", "]", ".", "?", "=>", "="];
+
+/// Formats the source of a file in the canonical style, breaking lines longer than `width`
+/// where it can. Comments are kept where they are, and so are single blank lines between items
+/// and statements.
+pub fn format(source: &str, width: usize) -> String {
+    let tokens = tokenize(source);
+    let mut formatter = Formatter {
+        generic_closes: vec![false; tokens.len()],
+        tokens,
+        lines: vec![],
+        line: Line::default(),
+        indent: 0,
+        groups: vec![],
+        pending_newline: false,
+        after_close_brace: false,
+        space_after: false,
+        last_code: None,
+        in_use: false,
+        where_depth: None,
+    };
+    formatter.run();
+    let mut output = String::new();
+    for line in formatter.lines.iter() {
+        let mut wrapped = vec![];
+        wrap(line, width, &mut wrapped);
+        for text in wrapped {
+            output += &text;
+            output.push('\n');
+        }
+    }
+    output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Ident,
+    Literal,
+    Punct,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    kind: TokenKind,
+    /// How many line breaks are between this and the previous token
+    newlines_before: usize,
+    /// Whether this directly follows the previous token without whitespace
+    joined: bool,
+}
+
+impl<'a> Token<'a> {
+    fn is_comment(&self) -> bool {
+        matches!(self.kind, TokenKind::LineComment | TokenKind::BlockComment)
+    }
+}
+
+/// Splits source into tokens, keeping comments. Anything that isn't recognized becomes a
+/// token of its own, since the file has already been parsed.
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut rest = source;
+    loop {
+        let trimmed = rest.trim_start();
+        let whitespace = &rest[..rest.len() - trimmed.len()];
+        rest = trimmed;
+        let first = match rest.chars().next() {
+            Some(first) => first,
+            None => break,
+        };
+        let after_dot = tokens.last().map_or(false, |token| token.text == ".");
+        let (kind, len) = if rest.starts_with("//") {
+            (
+                TokenKind::LineComment,
+                rest.find('\n').unwrap_or(rest.len()),
+            )
+        } else if rest.starts_with("/*") {
+            (TokenKind::BlockComment, block_comment_len(rest))
+        } else if rest.starts_with("r\"") || rest.starts_with("r#\"") || rest.starts_with("r##") {
+            (TokenKind::Literal, raw_string_len(rest))
+        } else if first.is_alphabetic() || first == '_' {
+            let len = match rest.strip_prefix("r#") {
+                Some(raw) => 2 + ident_len(raw),
+                None => ident_len(rest),
+            };
+            (TokenKind::Ident, len)
+        } else if first.is_ascii_digit() {
+            let mut len = ident_len(rest);
+            // fields of tuples are indexed like `pair.0.1`
+            if !after_dot
+                && rest[len..].starts_with('.')
+                && rest[len + 1..].starts_with(|c: char| c.is_ascii_digit())
+            {
+                len += 1 + ident_len(&rest[len + 1..]);
+            }
+            (TokenKind::Literal, len)
+        } else if first == '"' {
+            (TokenKind::Literal, quoted_len(rest, '"'))
+        } else if first == '\'' {
+            let mut chars = rest.char_indices().skip(1);
+            match (chars.next(), chars.next()) {
+                (Some((_, '\\')), _) => (TokenKind::Literal, quoted_len(rest, '\'')),
+                (Some(_), Some((end, '\''))) => (TokenKind::Literal, end + 1),
+                // lifetimes
+                _ => (TokenKind::Ident, 1 + ident_len(&rest[1..])),
+            }
+        } else {
+            let len = MULTI_CHAR_PUNCT
+                .iter()
+                .find(|punct| rest.starts_with(*punct))
+                .map_or_else(|| first.len_utf8(), |punct| punct.len());
+            (TokenKind::Punct, len)
+        };
+        let text = &rest[..len];
+        tokens.push(Token {
+            text: if kind == TokenKind::LineComment {
+                text.trim_end()
+            } else {
+                text
+            },
+            kind,
+            newlines_before: whitespace.matches('\n').count(),
+            joined: whitespace.is_empty(),
+        });
+        rest = &rest[len..];
+    }
+    tokens
+}
+
+fn ident_len(source: &str) -> usize {
+    source
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(source.len())
+}
+
+/// The length of a literal from an opening quote to the closing one, skipping escapes
+fn quoted_len(source: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in source.char_indices().skip(1) {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c if c == quote && !escaped => return i + 1,
+            _ => escaped = false,
+        }
+    }
+    source.len()
+}
+
+fn raw_string_len(source: &str) -> usize {
+    let hashes = source[1..].len() - source[1..].trim_start_matches('#').len();
+    let terminator = format!("\"{}", "#".repeat(hashes));
+    let start = 1 + hashes + 1;
+    source
+        .get(start..)
+        .and_then(|content| content.find(&terminator))
+        .map_or_else(|| source.len(), |end| start + end + terminator.len())
+}
+
+/// Block comments nest
+fn block_comment_len(source: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < source.len() {
+        if source[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if source[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += source[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    source.len()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delimiter {
+    None,
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Piece {
+    text: String,
+    /// Whether there's a space between this and the previous piece
+    space: bool,
+    delimiter: Delimiter,
+    comment: bool,
+}
+
+/// A line of output before it's broken to fit, empty for blank lines
+#[derive(Debug, Clone, Default)]
+struct Line {
+    indent: usize,
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum List {
+    /// Fields or variants, the last of which gets a trailing comma too
+    Fields,
+    /// Match arms, where arms with blocks don't need commas
+    Arms,
+}
+
+/// A delimiter that's open at the current token
+struct Group {
+    open: &'static str,
+    /// Braces that are laid out on one line, like those of use trees and const generics
+    inline: bool,
+    /// Braces around fields, variants, or match arms, which are separated by commas
+    list: Option<List>,
+    attribute: bool,
+    /// Whether the last element of the group is a rest pattern or base expression like
+    /// `..base`, which can't be followed by a comma
+    has_rest: bool,
+}
+
+impl Group {
+    fn new(open: &'static str, inline: bool) -> Self {
+        Self {
+            open,
+            inline,
+            list: None,
+            attribute: false,
+            has_rest: false,
+        }
+    }
+}
+
+struct Formatter<'a> {
+    tokens: Vec<Token<'a>>,
+    /// Which `>` tokens close generics
+    generic_closes: Vec<bool>,
+    lines: Vec<Line>,
+    line: Line,
+    indent: usize,
+    groups: Vec<Group>,
+    /// The next token starts a new line, unless it's a comment on the same line
+    pending_newline: bool,
+    /// The last token was a `}`, which some tokens stay on the line of
+    after_close_brace: bool,
+    /// Whether the last piece can be followed by a space
+    space_after: bool,
+    /// Where the last piece that isn't a comment is, as the index of its line and the piece
+    last_code: Option<(usize, usize)>,
+    in_use: bool,
+    /// How many groups were open at the `where` of the clause being formatted
+    where_depth: Option<usize>,
+}
+
+impl<'a> Formatter<'a> {
+    fn run(&mut self) {
+        let mut i = 0;
+        while i < self.tokens.len() {
+            i += self.token(i);
+        }
+        self.end_line();
+    }
+
+    /// Whether lines at the current token are statements, items, or fields, which each go on a
+    /// line of their own
+    fn at_block_level(&self) -> bool {
+        self.groups
+            .last()
+            .map_or(true, |group| group.open == "{" && !group.inline)
+    }
+
+    fn in_where_clause(&self) -> bool {
+        self.where_depth == Some(self.groups.len())
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.pieces.is_empty() {
+            self.lines.push(std::mem::take(&mut self.line));
+        }
+    }
+
+    fn push(&mut self, text: &str, space_before: bool, space_after: bool, delimiter: Delimiter) {
+        if self.line.pieces.is_empty() {
+            self.line.indent = self.indent;
+        }
+        let space = !self.line.pieces.is_empty() && self.space_after && space_before;
+        self.line.pieces.push(Piece {
+            text: text.to_string(),
+            space,
+            delimiter,
+            comment: false,
+        });
+        self.space_after = space_after;
+        self.last_code = Some((self.lines.len(), self.line.pieces.len() - 1));
+    }
+
+    fn push_comment(&mut self, text: &str) {
+        if self.line.pieces.is_empty() {
+            self.line.indent = self.indent;
+        }
+        let space = !self.line.pieces.is_empty();
+        self.line.pieces.push(Piece {
+            text: text.to_string(),
+            space,
+            delimiter: Delimiter::None,
+            comment: true,
+        });
+        self.space_after = true;
+    }
+
+    /// Keeps a blank line before a token that had at least one above it
+    fn blank_line(&mut self, token: &Token) {
+        let after_open = self
+            .lines
+            .last()
+            .and_then(|line| line.pieces.last())
+            .map_or(true, |piece| piece.delimiter == Delimiter::Open);
+        if token.newlines_before >= 2
+            && self.line.pieces.is_empty()
+            && !after_open
+            && token.text != "}"
+            && self.at_block_level()
+        {
+            self.lines.push(Line::default());
+        }
+    }
+
+    fn previous_code(&self, i: usize) -> Option<(usize, Token<'a>)> {
+        (0..i)
+            .rev()
+            .map(|j| (j, self.tokens[j]))
+            .find(|(_, token)| !token.is_comment())
+    }
+
+    fn next_code(&self, i: usize) -> Option<Token<'a>> {
+        self.tokens[i + 1..]
+            .iter()
+            .find(|token| !token.is_comment())
+            .copied()
+    }
+
+    /// Formats the token at `i`, returning how many tokens were consumed
+    fn token(&mut self, i: usize) -> usize {
+        let token = self.tokens[i];
+        if token.kind == TokenKind::LineComment {
+            if token.newlines_before == 0 && !self.line.pieces.is_empty() {
+                self.push_comment(token.text);
+            } else {
+                self.end_line();
+                self.blank_line(&token);
+                self.push_comment(token.text);
+            }
+            self.end_line();
+            self.pending_newline = false;
+            self.after_close_brace = false;
+            return 1;
+        }
+
+        if self.pending_newline {
+            let continues = self.after_close_brace && AFTER_CLOSE_BRACE.contains(&token.text);
+            let same_line_comment =
+                token.kind == TokenKind::BlockComment && token.newlines_before == 0;
+            if !continues && !same_line_comment {
+                self.end_line();
+                self.pending_newline = false;
+            }
+        }
+        self.blank_line(&token);
+        if token.kind == TokenKind::BlockComment {
+            self.push_comment(token.text);
+            return 1;
+        }
+        self.pending_newline = false;
+        self.after_close_brace = false;
+
+        let previous = self.previous_code(i);
+        let previous_text = previous.map(|(_, previous)| previous.text);
+        let previous_is_keyword = previous.map_or(false, |(_, previous)| {
+            previous.kind == TokenKind::Ident && SPACED_KEYWORDS.contains(&previous.text)
+        });
+        // whether an opening delimiter here would be a call, an index, or generic arguments
+        let follows_operand = previous.map_or(false, |(j, previous)| match previous.kind {
+            TokenKind::Ident => !previous_is_keyword,
+            TokenKind::Punct => matches!(previous.text, ")" | "]" | "?") || self.generic_closes[j],
+            _ => false,
+        });
+        let text = token.text;
+        match (token.kind, text) {
+            (TokenKind::Ident, "use") if self.at_block_level() => {
+                self.in_use = true;
+                self.push(text, true, true, Delimiter::None);
+            }
+            (TokenKind::Ident, "where") if self.at_block_level() => {
+                self.end_line();
+                self.push(text, true, true, Delimiter::None);
+                self.indent += 1;
+                self.where_depth = Some(self.groups.len());
+                self.pending_newline = true;
+            }
+            (TokenKind::Ident, _) | (TokenKind::Literal, _) => {
+                self.push(text, true, true, Delimiter::None)
+            }
+            (_, "{") if self.in_use || !self.at_block_level() => {
+                self.groups.push(Group::new("{", true));
+                self.push(text, true, !self.in_use, Delimiter::Open);
+            }
+            (_, "{") => {
+                if self.in_where_clause() {
+                    self.end_where_clause();
+                }
+                // even after an open range, like `for i in 0.. {`
+                self.space_after = true;
+                if self.tokens.get(i + 1).map(|next| next.text) == Some("}") {
+                    self.push("{}", true, true, Delimiter::None);
+                    self.pending_newline = true;
+                    self.after_close_brace = true;
+                    return 2;
+                }
+                let mut group = Group::new("{", false);
+                group.list = self.list(i);
+                self.groups.push(group);
+                self.push(text, true, true, Delimiter::Open);
+                self.indent += 1;
+                self.pending_newline = true;
+            }
+            (_, "}") => {
+                let group = self.close("{");
+                if group.as_ref().map_or(false, |group| group.inline) {
+                    self.push(text, !self.in_use, true, Delimiter::Close);
+                    return 1;
+                }
+                if let (Some(group), Some((line, piece))) = (group, self.last_code) {
+                    let last = self.piece(line, piece).map(|piece| piece.text.as_str());
+                    let needs_comma = match group.list {
+                        Some(List::Fields) => !group.has_rest,
+                        Some(List::Arms) => last != Some("}"),
+                        None => false,
+                    };
+                    if needs_comma && last != Some(",") && last != Some("{") {
+                        self.insert_comma(line, piece);
+                    }
+                }
+                self.end_line();
+                self.indent = self.indent.saturating_sub(1);
+                self.push(text, true, true, Delimiter::Close);
+                self.pending_newline = self.at_block_level();
+                self.after_close_brace = true;
+            }
+            (_, "(") | (_, "[") => {
+                let attribute = text == "["
+                    && (previous_text == Some("#")
+                        || previous_text == Some("!")
+                            && self.previous_code(i - 1).map(|(_, token)| token.text) == Some("#"));
+                let mut group = Group::new(if text == "(" { "(" } else { "[" }, true);
+                group.attribute = attribute;
+                self.groups.push(group);
+                let space_before = !follows_operand && previous_text != Some("#");
+                self.push(text, space_before, false, Delimiter::Open);
+            }
+            (_, ")") | (_, "]") => {
+                let group = self.close(if text == ")" { "(" } else { "[" });
+                self.push(text, false, true, Delimiter::Close);
+                if group.map_or(false, |group| group.attribute) && self.at_block_level() {
+                    self.pending_newline = true;
+                }
+            }
+            // generic arguments, or a qualified path like `<T as Trait>::f` when not after an
+            // operand
+            (_, "<") if previous.map_or(true, |(_, p)| p.kind != TokenKind::Literal) => {
+                match self.generic_close(i) {
+                    Some(close) => {
+                        self.generic_closes[close] = true;
+                        self.groups.push(Group::new("<", true));
+                        let space_before = !follows_operand && previous_text != Some("::");
+                        self.push(text, space_before, false, Delimiter::Open);
+                    }
+                    None => return self.operator(i),
+                }
+            }
+            (_, ">") if self.generic_closes[i] => {
+                self.close("<");
+                self.push(text, false, true, Delimiter::Close);
+            }
+            (_, ",") => {
+                let next = self.next_code(i).map(|next| next.text);
+                let closes_inline = self.tokens.get(i + 1).map_or(false, |next| {
+                    next.text == ")"
+                        || next.text == "]"
+                        || next.text == "}" && !self.at_block_level()
+                        || next.text == ">" && self.generic_closes[i + 1]
+                        || next.text == "{" && self.in_where_clause()
+                });
+                if closes_inline {
+                    // trailing commas are only kept in blocks and where clauses
+                    return 1;
+                }
+                self.push(text, false, true, Delimiter::None);
+                if (self.at_block_level() || self.in_where_clause()) && next.is_some() {
+                    self.pending_newline = true;
+                }
+            }
+            (_, ";") => {
+                if self.in_where_clause() {
+                    self.where_depth = None;
+                    self.indent = self.indent.saturating_sub(1);
+                }
+                self.push(text, false, true, Delimiter::None);
+                if self.at_block_level() {
+                    self.in_use = false;
+                    self.pending_newline = true;
+                }
+            }
+            (_, ":") | (_, "?") => self.push(text, false, true, Delimiter::None),
+            (_, "::") => self.push(text, !follows_operand, false, Delimiter::None),
+            (_, ".") => self.push(text, false, false, Delimiter::None),
+            (_, "..") | (_, "..=") | (_, "...") => {
+                if matches!(previous_text, Some(",") | Some("{")) {
+                    if let Some(group) = self.groups.last_mut() {
+                        group.has_rest = true;
+                    }
+                }
+                self.push(text, false, false, Delimiter::None)
+            }
+            (_, "#") => self.push(text, true, false, Delimiter::None),
+            (_, "!")
+                if previous_text == Some("#")
+                    || token.joined
+                        && follows_operand
+                        && previous.map_or(false, |(_, p)| p.kind == TokenKind::Ident)
+                        && matches!(
+                            self.tokens.get(i + 1).map(|next| next.text),
+                            Some("(") | Some("[") | Some("{")
+                        ) =>
+            {
+                // macros and inner attributes
+                self.push(text, false, false, Delimiter::None)
+            }
+            _ => return self.operator(i),
+        }
+        1
+    }
+
+    /// Ends a where clause before the `{` after it, which goes on a line of its own
+    fn end_where_clause(&mut self) {
+        if let Some((line, piece)) = self.last_code {
+            if self.piece(line, piece).map(|piece| piece.text.as_str()) != Some(",") {
+                self.insert_comma(line, piece);
+            }
+        }
+        self.where_depth = None;
+        self.indent = self.indent.saturating_sub(1);
+        self.end_line();
+    }
+
+    /// What the braces opened at `i` hold when they hold fields, variants, or match arms rather
+    /// than statements or items, judging by the keywords since the end of the last statement
+    fn list(&self, i: usize) -> Option<List> {
+        let mut depth = 0;
+        let mut list = None;
+        for j in (0..i).rev() {
+            let token = self.tokens[j];
+            match (token.kind, token.text) {
+                (TokenKind::Punct, ")") | (TokenKind::Punct, "]") => depth += 1,
+                (TokenKind::Punct, ">") if self.generic_closes[j] => depth += 1,
+                (TokenKind::Punct, "(") | (TokenKind::Punct, "[") | (TokenKind::Punct, "<")
+                    if depth > 0 =>
+                {
+                    depth -= 1
+                }
+                (TokenKind::Punct, ";") | (TokenKind::Punct, "{") | (TokenKind::Punct, "}")
+                    if depth == 0 =>
+                {
+                    break
+                }
+                (TokenKind::Ident, keyword) if depth == 0 => match keyword {
+                    "arch" | "else" | "fn" | "for" | "if" | "impl" | "loop" | "mod" | "trait"
+                    | "unsafe" | "while" => return None,
+                    "entity" | "enum" | "struct" | "union" => list = list.or(Some(List::Fields)),
+                    "match" => list = list.or(Some(List::Arms)),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        // otherwise braces after a path are a struct expression or pattern
+        list.or_else(|| {
+            self.previous_code(i)
+                .filter(|(j, previous)| {
+                    previous.kind == TokenKind::Ident && !SPACED_KEYWORDS.contains(&previous.text)
+                        || self.generic_closes[*j]
+                })
+                .map(|_| List::Fields)
+        })
+    }
+
+    /// Formats a unary or binary operator, joining shifts that were split in case they closed
+    /// generics
+    fn operator(&mut self, i: usize) -> usize {
+        let token = self.tokens[i];
+        let previous = self.previous_code(i);
+        let unary = matches!(token.text, "-" | "!" | "*" | "&")
+            && previous.map_or(true, |(j, previous)| match previous.kind {
+                TokenKind::Ident => SPACED_KEYWORDS.contains(&previous.text),
+                TokenKind::Punct => {
+                    !matches!(previous.text, ")" | "]" | "?") && !self.generic_closes[j]
+                }
+                _ => false,
+            });
+        if unary {
+            self.push(token.text, true, false, Delimiter::None);
+            return 1;
+        }
+        let joined_shift = matches!(token.text, "<" | ">")
+            && self.tokens.get(i + 1).map_or(false, |next| {
+                next.text == token.text && next.joined && !self.generic_closes[i + 1]
+            });
+        if joined_shift {
+            self.push(&token.text.repeat(2), true, true, Delimiter::None);
+            2
+        } else {
+            self.push(token.text, true, true, Delimiter::None);
+            1
+        }
+    }
+
+    /// The index of the `>` that closes the generics opened at `i`, if they are generics
+    fn generic_close(&self, i: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut nesting = 0;
+        for (j, token) in self.tokens.iter().enumerate().skip(i) {
+            if token.kind != TokenKind::Punct {
+                continue;
+            }
+            match token.text {
+                "<" if nesting == 0 => depth += 1,
+                ">" if nesting == 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(j);
+                    }
+                }
+                "(" | "[" => nesting += 1,
+                // const generic arguments are in braces
+                "{" if nesting > 0
+                    || matches!(
+                        self.previous_code(j).map(|(_, previous)| previous.text),
+                        Some("<") | Some(",") | Some("=")
+                    ) =>
+                {
+                    nesting += 1
+                }
+                ")" | "]" | "}" if nesting > 0 => nesting -= 1,
+                _ if nesting > 0 => {}
+                "::" | "," | ":" | "&" | "=" | "+" | "-" | "->" | "*" => {}
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Closes the innermost group opened by `open`, along with any left open inside it
+    fn close(&mut self, open: &str) -> Option<Group> {
+        let position = self.groups.iter().rposition(|group| group.open == open)?;
+        self.groups.drain(position..).next()
+    }
+
+    fn piece(&self, line: usize, piece: usize) -> Option<&Piece> {
+        if line == self.lines.len() {
+            self.line.pieces.get(piece)
+        } else {
+            self.lines.get(line).and_then(|line| line.pieces.get(piece))
+        }
+    }
+
+    fn insert_comma(&mut self, line: usize, piece: usize) {
+        let comma = Piece {
+            text: ",".to_string(),
+            space: false,
+            delimiter: Delimiter::None,
+            comment: false,
+        };
+        if line == self.lines.len() {
+            self.line.pieces.insert(piece + 1, comma);
+        } else {
+            self.lines[line].pieces.insert(piece + 1, comma);
+        }
+    }
+}
+
+fn render(indent: usize, pieces: &[Piece]) -> String {
+    let mut text = " ".repeat(indent * 4);
+    for (i, piece) in pieces.iter().enumerate() {
+        if i > 0 && piece.space {
+            text.push(' ');
+        }
+        text += &piece.text;
+    }
+    text
+}
+
+/// Breaks a line that's longer than `width` at the first delimited list in it that's closed on
+/// the same line, with each element on a line of its own
+fn wrap(line: &Line, width: usize, output: &mut Vec<String>) {
+    let code = line
+        .pieces
+        .iter()
+        .position(|piece| piece.comment)
+        .unwrap_or(line.pieces.len());
+    if render(line.indent, &line.pieces[..code]).chars().count() <= width {
+        output.push(render(line.indent, &line.pieces));
+        return;
+    }
+    let list = (0..code)
+        .filter(|open| line.pieces[*open].delimiter == Delimiter::Open)
+        .find_map(|open| {
+            let mut depth = 0;
+            for close in open..code {
+                match line.pieces[close].delimiter {
+                    Delimiter::Open => depth += 1,
+                    Delimiter::Close => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some((open, close)).filter(|_| close > open + 1);
+                        }
+                    }
+                    Delimiter::None => {}
+                }
+            }
+            None
+        });
+    let (open, close) = match list {
+        Some(list) => list,
+        None => {
+            output.push(render(line.indent, &line.pieces));
+            return;
+        }
+    };
+    let head = Line {
+        indent: line.indent,
+        pieces: line.pieces[..=open].to_vec(),
+    };
+    wrap(&head, width, output);
+    let mut element = vec![];
+    let mut depth = 0;
+    for piece in line.pieces[open + 1..close].iter() {
+        match piece.delimiter {
+            Delimiter::Open => depth += 1,
+            Delimiter::Close => depth -= 1,
+            Delimiter::None => {}
+        }
+        element.push(piece.clone());
+        if depth == 0 && piece.text == "," && !piece.comment {
+            wrap_element(&mut element, line.indent + 1, width, output);
+        }
+    }
+    if !element.is_empty() {
+        element.push(Piece {
+            text: ",".to_string(),
+            space: false,
+            delimiter: Delimiter::None,
+            comment: false,
+        });
+        wrap_element(&mut element, line.indent + 1, width, output);
+    }
+    let mut tail = line.pieces[close..].to_vec();
+    tail[0].space = false;
+    wrap(
+        &Line {
+            indent: line.indent,
+            pieces: tail,
+        },
+        width,
+        output,
+    );
+}
+
+fn wrap_element(element: &mut Vec<Piece>, indent: usize, width: usize, output: &mut Vec<String>) {
+    let mut pieces = std::mem::take(element);
+    pieces[0].space = false;
+    wrap(&Line { indent, pieces }, width, output);
+}
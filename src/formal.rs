@@ -0,0 +1,945 @@
+//! Exports a design and its properties to SMT-LIB for bounded model checking.
+//!
+//! The design is flattened and unrolled for a number of cycles, with a bit vector for each signal
+//! of each instance in each cycle, named like `counter.count@3`. Registers start out as zero like
+//! they do in simulation. Every clocked process is clocked by the same global clock, and
+//! asynchronous resets are taken at the clock edge. Inputs of the top entity are unconstrained
+//! except by assumptions, and so are outputs of black boxes.
+//!
+//! The script first checks whether any assertion can fail, and then whether each cover can hold,
+//! so `sat` means a counterexample or a trace reaching the cover was found.
+//!
+//! `rhdlc prove` solves the same unrolling with z3 instead, checking each assertion cycle by
+//! cycle. An assertion that can fail is reported with the first cycle it can fail in, and the
+//! values of the counterexample can be written as a value change dump with `--vcd`.
+//! One that can't is proven by k-induction if it also holds after holding for as many cycles
+//! from any state of the registers, and is unknown otherwise, since a state it fails in might
+//! only be reachable in more cycles than were unrolled.
+//!
+//! `rhdlc equiv` unrolls two architectures of the top entity side by side, named like
+//! `a.count@3` and `b.count@3`, giving them the same inputs in every cycle. They're different if
+//! an output of one can differ from that of the other in some cycle, and equivalent if the
+//! outputs being the same for as many cycles from any state of both also makes them the same in
+//! the next.
+#[cfg(feature = "z3")]
+use std::fs;
+#[cfg(feature = "z3")]
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(feature = "z3")]
+use rhdl::ast::{Span, Spanned};
+#[cfg(feature = "z3")]
+use z3::{ast::*, Config, Context, Model, SatResult, Solver};
+
+#[cfg(feature = "z3")]
+use crate::analysis::signal_graph::SignalKind;
+#[cfg(feature = "z3")]
+use crate::codegen::hierarchy::location;
+use crate::error::*;
+#[cfg(feature = "z3")]
+use crate::events;
+#[cfg(feature = "z3")]
+use crate::find_file::FileId;
+use crate::ir::Ir;
+#[cfg(feature = "z3")]
+use crate::ir::{
+    assigns, BinaryOp, Connection, Expr, ExprKind, Level, Module, ModuleIndex, ProcessKind,
+    PropertyKind, SignalIndex, Stmt, Ty, UnaryOp,
+};
+use crate::resolution::Resolver;
+#[cfg(feature = "z3")]
+use crate::sim::{table, testbench_clocks, write_report, Value, Vcd};
+
+/// `--formal`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formal {
+    /// How many clock cycles to unroll the design for
+    pub depth: u64,
+    /// `None` for standard output
+    pub output: Option<PathBuf>,
+}
+
+/// `rhdlc prove`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prove {
+    /// How many clock cycles to look for counterexamples in, and for an assertion to hold in
+    /// before the cycle an induction step checks it in
+    pub depth: u64,
+    /// `None` for standard output
+    pub output: Option<PathBuf>,
+    /// The directory to write the value changes of counterexamples to
+    pub vcd: Option<PathBuf>,
+}
+
+/// `rhdlc equiv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equiv {
+    /// The names of the two architectures of the top entity to compare
+    pub arch_a: String,
+    pub arch_b: String,
+    /// How many clock cycles to look for inputs that tell them apart in, and for their outputs to
+    /// be the same in before the cycle an induction step compares them in
+    pub depth: u64,
+    /// `None` for standard output
+    pub output: Option<PathBuf>,
+    /// The directory to write the value changes of each architecture given those inputs to
+    pub vcd: Option<PathBuf>,
+}
+
+/// An instance of a module in the flattened hierarchy
+#[cfg(feature = "z3")]
+struct FlatInstance {
+    module: ModuleIndex,
+    /// Prefixes the names of its signals, like `counter.`
+    path: String,
+    /// The index of each instance of the module
+    children: Vec<usize>,
+}
+
+#[cfg(feature = "z3")]
+fn flatten(ir: &Ir, module: ModuleIndex, path: String, instances: &mut Vec<FlatInstance>) -> usize {
+    let index = instances.len();
+    instances.push(FlatInstance {
+        module,
+        path: path.clone(),
+        children: vec![],
+    });
+    let children = ir[module]
+        .instances
+        .iter()
+        .map(|instance| {
+            let path = format!("{}{}.", path, instance.name);
+            flatten(ir, instance.module, path, instances)
+        })
+        .collect();
+    instances[index].children = children;
+    index
+}
+
+/// Reports the signals that can't be bit vectors, which have between 1 and `u32::MAX` bits,
+/// returning whether there were none
+#[cfg(feature = "z3")]
+fn can_unroll(ir: &Ir, errors: &mut Vec<Diagnostic>) -> bool {
+    let error_count = errors.len();
+    for module in ir.modules.iter() {
+        for signal in module.signals.iter() {
+            let width = signal.ty.width();
+            if width == 0 || width > u128::from(u32::MAX) {
+                errors.push(not_a_bit_vector(signal.file, signal.ident, width));
+            }
+        }
+    }
+    errors.len() == error_count
+}
+
+/// The design unrolled for a number of cycles into a solver, with its assumptions asserted
+#[cfg(feature = "z3")]
+struct Unrolled<'ctx> {
+    ctx: &'ctx Context,
+    /// The top first, and every instance before its children
+    instances: Vec<FlatInstance>,
+    /// The bit vector of each signal of each instance in each cycle
+    steps: Vec<Vec<Vec<BV<'ctx>>>>,
+    /// Each assertion as holding whenever it's reached in a cycle, with where it is and the cycle
+    asserts: Vec<(FileId, Span, usize, Bool<'ctx>)>,
+    /// Each cover as being reached with its condition holding, in any cycle or instance
+    covers: Vec<Vec<Bool<'ctx>>>,
+}
+
+/// Registers start out as zero when `from_zero` is set, or in any state for an induction step.
+/// The names of the signals start with `prefix`, so two designs can be unrolled into a solver.
+#[cfg(feature = "z3")]
+fn unroll<'ctx>(
+    ctx: &'ctx Context,
+    solver: &Solver<'ctx>,
+    ir: &Ir,
+    top: ModuleIndex,
+    depth: u64,
+    from_zero: bool,
+    prefix: &str,
+) -> Unrolled<'ctx> {
+    let mut instances = vec![];
+    flatten(ir, top, prefix.to_string(), &mut instances);
+
+    let steps = (0..=depth)
+        .map(|step| {
+            instances
+                .iter()
+                .map(|instance| {
+                    ir[instance.module]
+                        .signals
+                        .iter()
+                        .map(|signal| {
+                            let name = format!("{}{}@{}", instance.path, signal.name, step);
+                            BV::new_const(ctx, name, signal.ty.width() as u32)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    // registers start out as zero
+    let initial = steps[0].iter().filter(|_| from_zero);
+    for (instance, values) in instances.iter().zip(initial) {
+        let module = &ir[instance.module];
+        for signal in module.signal_indices() {
+            if module.is_register(signal) {
+                let value = &values[signal.index()];
+                solver.assert(&value._eq(&BV::from_u64(ctx, 0, value.get_size())));
+            }
+        }
+    }
+
+    let mut asserts = vec![];
+    let mut covers: Vec<Vec<Bool>> = vec![];
+    let mut cover_spans = vec![];
+    for (step, values) in steps.iter().enumerate() {
+        let next_values = steps.get(step + 1);
+        for (index, instance) in instances.iter().enumerate() {
+            let module = &ir[instance.module];
+            let mut unroller = Unroller {
+                ctx,
+                module,
+                properties: vec![],
+            };
+            for process in module.processes.iter() {
+                match &process.kind {
+                    ProcessKind::Comb => {
+                        let mut env = values[index].clone();
+                        let always = Bool::from_bool(ctx, true);
+                        unroller.exec(&process.body, &values[index], &mut env, true, &always);
+                        for signal in module.signal_indices() {
+                            if assigns(&process.body, signal) {
+                                let i = signal.index();
+                                solver.assert(&values[index][i]._eq(&env[i]));
+                            }
+                        }
+                    }
+                    ProcessKind::Clocked(clocking) => {
+                        // the values after the last cycle aren't constrained
+                        let next_values = match next_values {
+                            Some(next_values) => &next_values[index],
+                            None => continue,
+                        };
+                        let current = &values[index];
+                        let mut env = current.clone();
+                        match &clocking.reset {
+                            Some(reset) => {
+                                let active = unroller.is_true(&current[reset.signal.index()]);
+                                let active = if reset.active_high {
+                                    active
+                                } else {
+                                    active.not()
+                                };
+                                let mut reset_env = current.clone();
+                                unroller.exec(&reset.body, current, &mut reset_env, false, &active);
+                                unroller.exec(
+                                    &process.body,
+                                    current,
+                                    &mut env,
+                                    false,
+                                    &active.not(),
+                                );
+                                for signal in module.signal_indices() {
+                                    let i = signal.index();
+                                    env[i] = active.ite(&reset_env[i], &env[i]);
+                                }
+                            }
+                            None => {
+                                let always = Bool::from_bool(ctx, true);
+                                unroller.exec(&process.body, current, &mut env, false, &always);
+                            }
+                        }
+                        for signal in module.signal_indices() {
+                            let reset_assigns = clocking
+                                .reset
+                                .as_ref()
+                                .map(|reset| assigns(&reset.body, signal))
+                                .unwrap_or_default();
+                            if assigns(&process.body, signal) || reset_assigns {
+                                let i = signal.index();
+                                solver.assert(&next_values[i]._eq(&env[i]));
+                            }
+                        }
+                    }
+                }
+            }
+
+            for (instance, child) in module.instances.iter().zip(instance.children.iter()) {
+                for connection in instance.connections.iter() {
+                    let (port, value) = match connection {
+                        Connection::Input { port, value } => {
+                            (port, unroller.eval(value, &values[index]))
+                        }
+                        // whichever side drives the signal, both sides have the same value
+                        Connection::Output { port, signal }
+                        | Connection::InOut { port, signal } => {
+                            (port, values[index][signal.index()].clone())
+                        }
+                    };
+                    solver.assert(&values[*child][port.index()]._eq(&value));
+                }
+            }
+
+            for (kind, property, span) in unroller.properties {
+                match kind {
+                    PropertyKind::Assert => asserts.push((module.file, span, step, property)),
+                    PropertyKind::Assume => solver.assert(&property),
+                    PropertyKind::Cover => {
+                        let cover = (module.file, span);
+                        match cover_spans.iter().position(|existing| *existing == cover) {
+                            Some(existing) => covers[existing].push(property),
+                            None => {
+                                cover_spans.push(cover);
+                                covers.push(vec![property]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Unrolled {
+        ctx,
+        instances,
+        steps,
+        asserts,
+        covers,
+    }
+}
+
+#[cfg(feature = "z3")]
+pub fn export(ir: &Ir, formal: &Formal, errors: &mut Vec<Diagnostic>) {
+    let top = match ir.module_indices().next() {
+        Some(top) if can_unroll(ir, errors) => top,
+        _ => return,
+    };
+    let ctx = Context::new(&Config::new());
+    let solver = Solver::new(&ctx);
+    let unrolled = unroll(&ctx, &solver, ir, top, formal.depth, true, "");
+    let mut script = format!(
+        "; {} unrolled for {} cycles\n{}",
+        ir[top].specialized_name(),
+        formal.depth,
+        solver
+    );
+    if !unrolled.asserts.is_empty() {
+        let asserts = unrolled.asserts.iter().map(|(.., holds)| holds);
+        let all = Bool::and(&ctx, &asserts.collect::<Vec<_>>());
+        script += &format!(
+            "; can an assertion fail?\n(push 1)\n(assert {})\n(check-sat)\n(pop 1)\n",
+            all.not()
+        );
+    }
+    for (i, reached) in unrolled.covers.iter().enumerate() {
+        let any = Bool::or(&ctx, &reached.iter().collect::<Vec<_>>());
+        script += &format!(
+            "; can cover {} hold?\n(push 1)\n(assert {})\n(check-sat)\n(pop 1)\n",
+            i, any
+        );
+    }
+
+    write_report(formal.output.as_deref(), &script, errors);
+}
+
+/// Checks whether each assertion of the design can fail within the depth, and proves those that
+/// can't by induction, writing the outcome of each and a summary
+#[cfg(feature = "z3")]
+pub fn prove(resolver: &Resolver, ir: &Ir, prove: &Prove, errors: &mut Vec<Diagnostic>) {
+    let top = match ir.module_indices().next() {
+        Some(top) if can_unroll(ir, errors) => top,
+        _ => return,
+    };
+    let ctx = Context::new(&Config::new());
+    let (solver, induction_solver) = (Solver::new(&ctx), Solver::new(&ctx));
+    let unrolled = unroll(&ctx, &solver, ir, top, prove.depth, true, "");
+    let induction = unroll(&ctx, &induction_solver, ir, top, prove.depth, false, "");
+    let file_graph = resolver.file_graph();
+    // each assertion once, in the order of the source
+    let mut assertions = unrolled
+        .asserts
+        .iter()
+        .map(|(file, span, ..)| (*file, *span))
+        .collect::<Vec<_>>();
+    assertions.sort_by_key(|(file, span)| (*file, span.start, span.end));
+    assertions.dedup();
+
+    let mut report = format!(
+        "proving {} assertions of {} for {} cycles\n",
+        assertions.len(),
+        ir[top].specialized_name(),
+        prove.depth
+    );
+    let (mut proven, mut failed, mut unknown) = (0, 0, 0);
+    for (file, span) in assertions {
+        let at = location(file_graph, file, span);
+        let (cycle, trace) = match counterexample(&unrolled, &solver, ir, file, span) {
+            Some(counterexample) => counterexample,
+            None if is_inductive(&induction, &induction_solver, file, span) => {
+                proven += 1;
+                report += &format!("assert {} ... proven\n", at);
+                continue;
+            }
+            None => {
+                unknown += 1;
+                report += &format!("assert {} ... unknown\n", at);
+                errors.push(assertion_not_proven(file, span, prove.depth));
+                continue;
+            }
+        };
+        failed += 1;
+        report += &format!("assert {} ... FAILED in cycle {}\n", at, cycle);
+        errors.push(assertion_can_fail(file, span, cycle as u64));
+
+        if let Some(dir) = &prove.vcd {
+            // named after the file and line of the assertion, like `top-12.vcd`
+            let name = Path::new(file_graph.inner.name(file)).file_stem();
+            let line = file_graph.line_column(file, span.start);
+            let name = format!(
+                "{}-{}",
+                name.unwrap_or_default().to_string_lossy(),
+                line.map_or(0, |at| at.line)
+            );
+            write_vcd(dir, &name, ir, &unrolled, &trace, errors);
+        }
+    }
+    report += &format!(
+        "\nprove result: {}. {} proven; {} failed; {} unknown\n",
+        if failed == 0 { "ok" } else { "FAILED" },
+        proven,
+        failed,
+        unknown
+    );
+    write_report(prove.output.as_deref(), &report, errors);
+}
+
+/// Writes the values of an unrolling in each cycle of a trace to `<dir>/<name>.vcd`
+#[cfg(feature = "z3")]
+fn write_vcd(
+    dir: &Path,
+    name: &str,
+    ir: &Ir,
+    unrolled: &Unrolled,
+    trace: &[Vec<Vec<Value>>],
+    errors: &mut Vec<Diagnostic>,
+) {
+    let hierarchy = unrolled
+        .instances
+        .iter()
+        .map(|instance| (instance.module, instance.children.clone()))
+        .collect::<Vec<_>>();
+    let mut vcd = Vcd::with_hierarchy(ir, &hierarchy, trace[0].iter().map(Vec::as_slice));
+    for (step, values) in trace.iter().enumerate().skip(1) {
+        vcd.sample_values(values.iter().map(Vec::as_slice), step as u64 * 2);
+    }
+    let path = dir.join(format!("{}.vcd", name));
+    let written = fs::create_dir_all(dir).and_then(|_| fs::write(&path, vcd.finish()));
+    match written {
+        Ok(()) => events::artifact_written(&path),
+        Err(err) => errors.push(write_output(&path.to_string_lossy(), err)),
+    }
+}
+
+/// Compares the outputs of two architectures of the top entity, lowered to `a` and `b`, given
+/// the same inputs in each cycle up to the depth, and proves they're the same in every cycle by
+/// induction if they are, writing the outcome and any inputs that tell them apart
+#[cfg(feature = "z3")]
+pub fn equiv(a: &Ir, b: &Ir, equiv: &Equiv, errors: &mut Vec<Diagnostic>) {
+    let (top_a, top_b) = match (a.module_indices().next(), b.module_indices().next()) {
+        (Some(top_a), Some(top_b)) if can_unroll(a, errors) && can_unroll(b, errors) => {
+            (top_a, top_b)
+        }
+        _ => return,
+    };
+    let ctx = Context::new(&Config::new());
+    let (solver, induction_solver) = (Solver::new(&ctx), Solver::new(&ctx));
+    let (unrolled_a, unrolled_b, same) = miter(&ctx, &solver, (a, top_a), (b, top_b), equiv, true);
+    let (.., induction_same) = miter(
+        &ctx,
+        &induction_solver,
+        (a, top_a),
+        (b, top_b),
+        equiv,
+        false,
+    );
+    let names = format!("`{}` and `{}`", equiv.arch_a, equiv.arch_b);
+
+    let distinguished = (0..same.len()).find_map(|cycle| {
+        solver.push();
+        solver.assert(&same[cycle].not());
+        let traces = model(&solver).map(|model| {
+            (
+                trace(&model, a, &unrolled_a, cycle),
+                trace(&model, b, &unrolled_b, cycle),
+            )
+        });
+        solver.pop(1);
+        traces.map(|traces| (cycle, traces))
+    });
+    let (cycle, (trace_a, trace_b)) = match distinguished {
+        Some(distinguished) => distinguished,
+        None => {
+            // the outputs are the same whenever they were in the cycles before
+            let last = induction_same.len() - 1;
+            for same in induction_same[..last].iter() {
+                induction_solver.assert(same);
+            }
+            induction_solver.assert(&induction_same[last].not());
+            let report = if matches!(induction_solver.check(), SatResult::Unsat) {
+                format!("{} are equivalent\n", names)
+            } else {
+                errors.push(equivalence_not_proven(&names, equiv.depth));
+                format!("{} are equivalent for {} cycles\n", names, equiv.depth)
+            };
+            return write_report(equiv.output.as_deref(), &report, errors);
+        }
+    };
+
+    // the inputs in each cycle but the clocks, which are all the same global clock, and each
+    // output that differs in the last as both have it
+    let module = &a[top_a];
+    let clocks = testbench_clocks(a);
+    let inputs = module
+        .signal_indices()
+        .filter(|signal| module[*signal].kind == SignalKind::Input && !clocks.contains(signal))
+        .collect::<Vec<_>>();
+    let differing = module
+        .signal_indices()
+        .filter(|signal| module[*signal].kind == SignalKind::Output)
+        .filter(|signal| trace_a[cycle][0][signal.index()] != trace_b[cycle][0][signal.index()])
+        .collect::<Vec<_>>();
+    let mut header = vec!["cycle".to_string()];
+    header.extend(inputs.iter().map(|input| module[*input].name.clone()));
+    for output in differing.iter() {
+        header.push(format!("{} ({})", module[*output].name, equiv.arch_a));
+        header.push(format!("{} ({})", module[*output].name, equiv.arch_b));
+    }
+    let mut rows = vec![header];
+    for (step, (values_a, values_b)) in trace_a.iter().zip(trace_b.iter()).enumerate() {
+        let mut row = vec![step.to_string()];
+        let cell = |values: &[Vec<Value>], signal: SignalIndex| {
+            values[0][signal.index()]
+                .display(&module[signal].ty)
+                .to_string()
+        };
+        row.extend(inputs.iter().map(|input| cell(values_a, *input)));
+        for output in differing.iter() {
+            row.push(cell(values_a, *output));
+            row.push(cell(values_b, *output));
+        }
+        rows.push(row);
+    }
+    let report = format!("{} differ in cycle {}\n\n{}", names, cycle, table(rows));
+    write_report(equiv.output.as_deref(), &report, errors);
+    errors.push(architectures_differ(
+        &names,
+        differing
+            .iter()
+            .map(|output| (module[*output].file, module[*output].ident.span()))
+            .collect(),
+        cycle as u64,
+    ));
+
+    if let Some(dir) = &equiv.vcd {
+        write_vcd(dir, &equiv.arch_a, a, &unrolled_a, &trace_a, errors);
+        write_vcd(dir, &equiv.arch_b, b, &unrolled_b, &trace_b, errors);
+    }
+}
+
+/// Unrolls two designs with the same ports into a solver, giving them the same inputs in each
+/// cycle, with whether their outputs are the same in each cycle
+#[cfg(feature = "z3")]
+fn miter<'ctx>(
+    ctx: &'ctx Context,
+    solver: &Solver<'ctx>,
+    (a, top_a): (&Ir, ModuleIndex),
+    (b, top_b): (&Ir, ModuleIndex),
+    equiv: &Equiv,
+    from_zero: bool,
+) -> (Unrolled<'ctx>, Unrolled<'ctx>, Vec<Bool<'ctx>>) {
+    let unrolled_a = unroll(ctx, solver, a, top_a, equiv.depth, from_zero, "a.");
+    let unrolled_b = unroll(ctx, solver, b, top_b, equiv.depth, from_zero, "b.");
+    let module = &a[top_a];
+    let same = unrolled_a
+        .steps
+        .iter()
+        .zip(unrolled_b.steps.iter())
+        .map(|(values_a, values_b)| {
+            let mut outputs = vec![];
+            for signal in module.signal_indices() {
+                let i = signal.index();
+                let equal = values_a[0][i]._eq(&values_b[0][i]);
+                match module[signal].kind {
+                    SignalKind::Input => solver.assert(&equal),
+                    SignalKind::Output => outputs.push(equal),
+                    _ => {}
+                }
+            }
+            Bool::and(ctx, &outputs.iter().collect::<Vec<_>>())
+        })
+        .collect();
+    (unrolled_a, unrolled_b, same)
+}
+
+/// The first cycle an assertion can fail in and the values of each signal of each instance in
+/// each cycle up to it, or `None` if it holds in every cycle of the unrolling
+#[cfg(feature = "z3")]
+fn counterexample<'ctx>(
+    unrolled: &Unrolled<'ctx>,
+    solver: &Solver<'ctx>,
+    ir: &Ir,
+    file: FileId,
+    span: Span,
+) -> Option<(usize, Vec<Vec<Vec<Value>>>)> {
+    for cycle in 0..unrolled.steps.len() {
+        let holds = unrolled
+            .asserts
+            .iter()
+            .filter(|(assert_file, assert_span, step, _)| {
+                (*assert_file, *assert_span, *step) == (file, span, cycle)
+            })
+            .map(|(.., holds)| holds)
+            .collect::<Vec<_>>();
+        if holds.is_empty() {
+            continue;
+        }
+        solver.push();
+        solver.assert(&Bool::and(unrolled.ctx, &holds).not());
+        let values = model(solver).map(|model| trace(&model, ir, unrolled, cycle));
+        solver.pop(1);
+        if let Some(values) = values {
+            return Some((cycle, values));
+        }
+    }
+    None
+}
+
+/// A model of the assertions of a solver, if they can hold
+#[cfg(feature = "z3")]
+fn model<'ctx>(solver: &Solver<'ctx>) -> Option<Model<'ctx>> {
+    match solver.check() {
+        SatResult::Sat => solver.get_model(),
+        SatResult::Unsat | SatResult::Unknown => None,
+    }
+}
+
+/// The values of each signal of each instance of an unrolling in a model, in each cycle up to
+/// `last`
+#[cfg(feature = "z3")]
+fn trace<'ctx>(
+    model: &Model<'ctx>,
+    ir: &Ir,
+    unrolled: &Unrolled<'ctx>,
+    last: usize,
+) -> Vec<Vec<Vec<Value>>> {
+    unrolled.steps[..=last]
+        .iter()
+        .map(|step| {
+            step.iter()
+                .zip(unrolled.instances.iter())
+                .map(|(values, instance)| {
+                    values
+                        .iter()
+                        .zip(ir[instance.module].signals.iter())
+                        .map(|(value, signal)| model_value(model, value, &signal.ty))
+                        .collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether an assertion holds in the last cycle of an unrolling from any state whenever it held
+/// in the cycles before
+#[cfg(feature = "z3")]
+fn is_inductive<'ctx>(
+    induction: &Unrolled<'ctx>,
+    solver: &Solver<'ctx>,
+    file: FileId,
+    span: Span,
+) -> bool {
+    let last = induction.steps.len() - 1;
+    solver.push();
+    let mut holds_last = vec![];
+    for (assert_file, assert_span, step, holds) in induction.asserts.iter() {
+        if (*assert_file, *assert_span) != (file, span) {
+            continue;
+        }
+        if *step == last {
+            holds_last.push(holds);
+        } else {
+            solver.assert(holds);
+        }
+    }
+    solver.assert(&Bool::and(induction.ctx, &holds_last).not());
+    let inductive = matches!(solver.check(), SatResult::Unsat);
+    solver.pop(1);
+    inductive
+}
+
+/// The value of a signal in a model, with the elements of a vector from its lowest bits
+#[cfg(feature = "z3")]
+fn model_value<'ctx>(model: &Model<'ctx>, value: &BV<'ctx>, ty: &Ty) -> Value {
+    match ty {
+        Ty::Vector(elem, len) => {
+            let elem_width = elem.width() as u32;
+            Value::Vector(
+                (0..*len as u32)
+                    .map(|i| {
+                        let elem_value = value.extract((i + 1) * elem_width - 1, i * elem_width);
+                        model_value(model, &elem_value, elem)
+                    })
+                    .collect(),
+            )
+        }
+        _ => {
+            // numerals are read 64 bits at a time, and values the model leaves free are zero
+            let width = value.get_size();
+            let bits = |high: u32, low: u32| {
+                model
+                    .eval(&value.extract(high, low))
+                    .and_then(|bits| bits.as_u64())
+                    .unwrap_or_default()
+            };
+            let low = bits(width.min(64) - 1, 0);
+            let high = if width > 64 { bits(width - 1, 64) } else { 0 };
+            Value::known(u128::from(high) << 64 | u128::from(low))
+        }
+    }
+}
+
+/// Symbolically executes the processes of a module in one cycle
+#[cfg(feature = "z3")]
+struct Unroller<'a, 'ctx> {
+    ctx: &'ctx Context,
+    module: &'a Module<'a>,
+    /// Asserts and assumes as the condition holding whenever they're reached,
+    /// and covers as being reached with the condition holding
+    properties: Vec<(PropertyKind, Bool<'ctx>, Span)>,
+}
+
+#[cfg(feature = "z3")]
+impl<'a, 'ctx> Unroller<'a, 'ctx> {
+    fn is_true(&self, bit: &BV<'ctx>) -> Bool<'ctx> {
+        bit._eq(&BV::from_u64(self.ctx, 1, 1))
+    }
+
+    /// Like [`crate::sim`], blocking assignments read from `env`, and others from `current`
+    fn exec(
+        &mut self,
+        stmts: &[Stmt],
+        current: &[BV<'ctx>],
+        env: &mut Vec<BV<'ctx>>,
+        blocking: bool,
+        reached: &Bool<'ctx>,
+    ) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Assign {
+                    target,
+                    index,
+                    value,
+                    ..
+                } => {
+                    let read = if blocking { &env[..] } else { current };
+                    let value = self.eval(value, read);
+                    let value = match index {
+                        None => value,
+                        Some(index) => {
+                            let index = self.eval(index, read);
+                            let ty = &self.module[*target].ty;
+                            self.splice(&env[target.index()], ty, &index, &value)
+                        }
+                    };
+                    env[target.index()] = value;
+                }
+                Stmt::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => {
+                    let read = if blocking { &env[..] } else { current };
+                    let cond = self.is_true(&self.eval(cond, read));
+                    let mut then_env = env.clone();
+                    let then_reached = Bool::and(self.ctx, &[reached, &cond]);
+                    self.exec(then_branch, current, &mut then_env, blocking, &then_reached);
+                    let mut else_env = env.clone();
+                    let else_reached = Bool::and(self.ctx, &[reached, &cond.not()]);
+                    self.exec(else_branch, current, &mut else_env, blocking, &else_reached);
+                    for signal in self.module.signal_indices() {
+                        if assigns(then_branch, signal) || assigns(else_branch, signal) {
+                            let i = signal.index();
+                            env[i] = cond.ite(&then_env[i], &else_env[i]);
+                        }
+                    }
+                }
+                // a warning doesn't fail a simulation, so there's nothing to prove about it
+                Stmt::Property {
+                    level: Level::Warning,
+                    kind: PropertyKind::Assert,
+                    ..
+                } => {}
+                Stmt::Property {
+                    kind, cond, span, ..
+                } => {
+                    let read = if blocking { &env[..] } else { current };
+                    let holds = self.is_true(&self.eval(cond, read));
+                    let property = match kind {
+                        PropertyKind::Cover => Bool::and(self.ctx, &[reached, &holds]),
+                        PropertyKind::Assert | PropertyKind::Assume => reached.implies(&holds),
+                    };
+                    self.properties.push((*kind, property, *span));
+                }
+            }
+        }
+    }
+
+    /// Replaces an element of a vector, or a bit of an integer
+    fn splice(&self, target: &BV<'ctx>, ty: &Ty, index: &BV<'ctx>, value: &BV<'ctx>) -> BV<'ctx> {
+        let elem_width = ty.elem().width() as u32;
+        let len = ty.width() as u32 / elem_width;
+        let mut spliced: Option<BV<'ctx>> = None;
+        for i in 0..len {
+            let elem = target.extract((i + 1) * elem_width - 1, i * elem_width);
+            let is_index = index._eq(&self.constant(i as i128, index.get_size()));
+            let elem = is_index.ite(value, &elem);
+            // the last element is the most significant
+            spliced = Some(match spliced {
+                Some(lower) => elem.concat(&lower),
+                None => elem,
+            });
+        }
+        spliced.unwrap()
+    }
+
+    fn constant(&self, value: i128, width: u32) -> BV<'ctx> {
+        let bits = value as u128;
+        if width <= 64 {
+            BV::from_u64(self.ctx, bits as u64, width)
+        } else {
+            let high = BV::from_u64(self.ctx, (bits >> 64) as u64, width - 64);
+            high.concat(&BV::from_u64(self.ctx, bits as u64, 64))
+        }
+    }
+
+    /// Resizes like [`ExprKind::Resize`]
+    fn resize(&self, value: BV<'ctx>, signed: bool, width: u32) -> BV<'ctx> {
+        let from = value.get_size();
+        if width < from {
+            value.extract(width - 1, 0)
+        } else if width == from {
+            value
+        } else if signed {
+            value.sign_ext(width - from)
+        } else {
+            value.zero_ext(width - from)
+        }
+    }
+
+    fn eval(&self, expr: &Expr, values: &[BV<'ctx>]) -> BV<'ctx> {
+        let width = expr.ty.width() as u32;
+        let bit = |b: Bool<'ctx>| b.ite(&self.constant(1, 1), &self.constant(0, 1));
+        match &expr.kind {
+            ExprKind::Const(value) => self.constant(*value, width),
+            ExprKind::Signal(signal) => values[signal.index()].clone(),
+            ExprKind::Unary(op, operand) => {
+                let operand = self.eval(operand, values);
+                match op {
+                    UnaryOp::Not => operand.bvnot(),
+                    UnaryOp::Neg => operand.bvneg(),
+                    UnaryOp::ReduceOr => {
+                        let zero = self.constant(0, operand.get_size());
+                        bit(operand._eq(&zero).not())
+                    }
+                }
+            }
+            ExprKind::Binary(op, lhs_expr, rhs_expr) => {
+                let signed = lhs_expr.ty.is_signed();
+                let lhs = self.eval(lhs_expr, values);
+                let rhs = self.eval(rhs_expr, values);
+                let rhs_is_zero = || rhs._eq(&self.constant(0, rhs.get_size()));
+                match op {
+                    BinaryOp::Add => lhs.bvadd(&rhs),
+                    BinaryOp::Sub => lhs.bvsub(&rhs),
+                    BinaryOp::Mul => lhs.bvmul(&rhs),
+                    // dividing by zero behaves like it does in simulation
+                    BinaryOp::Div if signed => {
+                        rhs_is_zero().ite(&self.constant(-1, width), &lhs.bvsdiv(&rhs))
+                    }
+                    BinaryOp::Rem if signed => rhs_is_zero().ite(&lhs, &lhs.bvsrem(&rhs)),
+                    BinaryOp::Div => lhs.bvudiv(&rhs),
+                    BinaryOp::Rem => lhs.bvurem(&rhs),
+                    BinaryOp::BitAnd | BinaryOp::And => lhs.bvand(&rhs),
+                    BinaryOp::BitOr | BinaryOp::Or => lhs.bvor(&rhs),
+                    BinaryOp::BitXor => lhs.bvxor(&rhs),
+                    BinaryOp::Shl | BinaryOp::Shr => {
+                        // shift at the wider of the two widths, so large amounts shift everything out
+                        let shift_width = width.max(rhs.get_size());
+                        let value = self.resize(lhs, signed, shift_width);
+                        let amount = self.resize(rhs, false, shift_width);
+                        let shifted = match op {
+                            BinaryOp::Shl => value.bvshl(&amount),
+                            _ if signed => value.bvashr(&amount),
+                            _ => value.bvlshr(&amount),
+                        };
+                        shifted.extract(width - 1, 0)
+                    }
+                    BinaryOp::Eq => bit(lhs._eq(&rhs)),
+                    BinaryOp::Ne => bit(lhs._eq(&rhs).not()),
+                    BinaryOp::Lt if signed => bit(lhs.bvslt(&rhs)),
+                    BinaryOp::Le if signed => bit(lhs.bvsle(&rhs)),
+                    BinaryOp::Gt if signed => bit(lhs.bvsgt(&rhs)),
+                    BinaryOp::Ge if signed => bit(lhs.bvsge(&rhs)),
+                    BinaryOp::Lt => bit(lhs.bvult(&rhs)),
+                    BinaryOp::Le => bit(lhs.bvule(&rhs)),
+                    BinaryOp::Gt => bit(lhs.bvugt(&rhs)),
+                    BinaryOp::Ge => bit(lhs.bvuge(&rhs)),
+                }
+            }
+            ExprKind::Index(base, index) => {
+                let base_value = self.eval(base, values);
+                let index = self.eval(index, values);
+                let elem_width = base.ty.elem().width() as u32;
+                let len = base.ty.width() as u32 / elem_width;
+                // out of range elements read as zero, like in simulation
+                let mut elem = self.constant(0, elem_width);
+                for i in 0..len {
+                    let is_index = index._eq(&self.constant(i as i128, index.get_size()));
+                    let candidate = base_value.extract((i + 1) * elem_width - 1, i * elem_width);
+                    elem = is_index.ite(&candidate, &elem);
+                }
+                elem
+            }
+            ExprKind::Resize(operand) => {
+                let value = self.eval(operand, values);
+                self.resize(value, operand.ty.is_signed(), width)
+            }
+            ExprKind::Mux(cond, then_value, else_value) => {
+                let cond = self.is_true(&self.eval(cond, values));
+                cond.ite(
+                    &self.eval(then_value, values),
+                    &self.eval(else_value, values),
+                )
+            }
+        }
+    }
+}
+
+/// Without z3 there's nothing to build the script with, which the browser doesn't have
+#[cfg(not(feature = "z3"))]
+pub fn export(_ir: &Ir, _formal: &Formal, errors: &mut Vec<Diagnostic>) {
+    errors.push(formal_without_z3("--formal"));
+}
+
+#[cfg(not(feature = "z3"))]
+pub fn prove(_resolver: &Resolver, _ir: &Ir, _prove: &Prove, errors: &mut Vec<Diagnostic>) {
+    errors.push(formal_without_z3("rhdlc prove"));
+}
+
+#[cfg(not(feature = "z3"))]
+pub fn equiv(_a: &Ir, _b: &Ir, _equiv: &Equiv, errors: &mut Vec<Diagnostic>) {
+    errors.push(formal_without_z3("rhdlc equiv"));
+}
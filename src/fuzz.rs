@@ -0,0 +1,299 @@
+//! `RHDLC_FUZZ=visibility`: a fuzz target that builds a tree of modules with random visibilities
+//! from the input, imports the struct of every module from every module, and checks the resolver
+//! against a model of Rust's privacy rules. A use must fail exactly when a segment of its path
+//! isn't visible from the module it's in. The visibility solver must also agree that an item
+//! visible from a module is visible from the modules inside it, and that a `pub` item is visible
+//! wherever its module is.
+use std::fmt::Write;
+
+use codespan_reporting::diagnostic::Severity;
+
+use crate::find_file::{FileContentProvider, FileFinder};
+use crate::resolution::{context, ResolutionIndex, Resolver};
+
+/// The most modules generated besides the root
+const MAX_MODULES: usize = 8;
+
+/// Who an item can be named by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Vis {
+    Priv,
+    Pub,
+    Crate,
+    LowerSelf,
+    Super,
+    /// `pub(in crate::...)` the module this many levels above the one it's declared in, written
+    /// `pub(crate)` for the root
+    In(usize),
+}
+
+/// A module with a struct `S`, declared in `parent` unless it's the root
+#[derive(Debug)]
+struct Module {
+    parent: Option<usize>,
+    /// Of the module itself, and of its `S`
+    vis: Vis,
+    struct_vis: Vis,
+}
+
+/// The bytes the design is generated from, which are zero once they run out
+struct Input<'a>(std::slice::Iter<'a, u8>);
+
+impl Input<'_> {
+    fn next(&mut self, below: usize) -> usize {
+        self.0.next().copied().unwrap_or_default() as usize % below
+    }
+}
+
+/// Generates a design from `data` and panics if the resolver disagrees with the model
+pub fn visibility(data: &[u8]) {
+    let modules = generate(&mut Input(data.iter()));
+    let (source, uses) = render(&modules);
+
+    let mut finder = FileFinder::default();
+    finder.find_tree(FileContentProvider::Reader(
+        "fuzz".to_string(),
+        Box::new(std::io::Cursor::new(source.clone().into_bytes())),
+    ));
+    assert!(finder.errors.is_empty(), "{}\n{:#?}", source, finder.errors);
+    let file_graph = &finder.file_graph;
+    let ctx = context();
+    let mut resolver = Resolver::build(file_graph, &ctx);
+    resolver.build_graph();
+    resolver.check_graph();
+
+    // the lines of the uses that failed
+    let mut failed = vec![];
+    for error in resolver
+        .errors
+        .iter()
+        .filter(|error| error.severity >= Severity::Error)
+    {
+        let lines = error
+            .labels
+            .iter()
+            .filter_map(|label| file_graph.line_column(label.file_id, label.range.start))
+            .map(|start| start.line)
+            .filter(|line| uses.iter().any(|(use_line, ..)| use_line == line))
+            .collect::<Vec<_>>();
+        assert!(
+            !lines.is_empty(),
+            "{}\nunexpected error {:#?}",
+            source,
+            error
+        );
+        failed.extend(lines);
+    }
+    for (line, from, to) in uses.iter() {
+        assert_eq!(
+            !failed.contains(line),
+            is_accessible(&modules, *from, *to),
+            "{}\nthe use on line {} in {} of the struct in {}",
+            source,
+            line,
+            path(&modules, *from),
+            path(&modules, *to)
+        );
+    }
+
+    // the items by the index of the module they're in or are
+    let graph = &resolver.resolution_graph;
+    let node = |path: String| {
+        graph
+            .node_indices()
+            .find(|node| {
+                graph[*node].name().is_some()
+                    && !graph.is_extern(*node)
+                    && graph.path(*node) == path
+            })
+            .unwrap()
+    };
+    let module_nodes = (0..modules.len())
+        .map(|module| match module {
+            0 => graph.roots[0],
+            _ => node(path(&modules, module)),
+        })
+        .collect::<Vec<ResolutionIndex>>();
+    let struct_nodes = (0..modules.len())
+        .map(|module| match module {
+            0 => node("S".to_string()),
+            _ => node(format!("{}::S", path(&modules, module))),
+        })
+        .collect::<Vec<ResolutionIndex>>();
+    for (module, parent) in modules
+        .iter()
+        .enumerate()
+        .filter_map(|(module, m)| Some((module, m.parent?)))
+    {
+        for target in module_nodes.iter().skip(1).chain(struct_nodes.iter()) {
+            assert!(
+                !resolver.is_visible(module_nodes[parent], *target)
+                    || resolver.is_visible(module_nodes[module], *target),
+                "{}\n{} is visible from {} but not from {} inside it",
+                source,
+                graph.path(*target),
+                path(&modules, parent),
+                path(&modules, module)
+            );
+        }
+    }
+    for (module, m) in modules.iter().enumerate().skip(1) {
+        if m.struct_vis != Vis::Pub {
+            continue;
+        }
+        for dest in module_nodes.iter() {
+            assert!(
+                !resolver.is_visible(*dest, module_nodes[module])
+                    || resolver.is_visible(*dest, struct_nodes[module]),
+                "{}\nthe pub struct in {} isn't visible from {}, where the module is",
+                source,
+                path(&modules, module),
+                graph.path(*dest)
+            );
+        }
+    }
+}
+
+/// A root and up to `MAX_MODULES` modules, each declared in one before it
+fn generate(input: &mut Input) -> Vec<Module> {
+    let mut modules = vec![Module {
+        parent: None,
+        vis: Vis::Priv,
+        struct_vis: vis(input, 0),
+    }];
+    for module in 1..=1 + input.next(MAX_MODULES) {
+        let parent = input.next(module);
+        modules.push(Module {
+            parent: Some(parent),
+            vis: vis(input, depth(&modules, parent)),
+            struct_vis: Vis::Priv,
+        });
+        modules[module].struct_vis = vis(input, depth(&modules, module));
+    }
+    modules
+}
+
+/// A visibility for an item in a module `depth` levels below the root, which can't be
+/// `pub(super)` in the root
+fn vis(input: &mut Input, depth: usize) -> Vis {
+    match input.next(6) {
+        0 => Vis::Priv,
+        1 => Vis::Pub,
+        2 => Vis::Crate,
+        3 => Vis::LowerSelf,
+        4 if depth > 0 => Vis::Super,
+        4 => Vis::Priv,
+        _ => Vis::In(input.next(depth + 1)),
+    }
+}
+
+/// The design, and the line of each use with the module it's in and the one it imports from
+fn render(modules: &[Module]) -> (String, Vec<(usize, usize, usize)>) {
+    let mut source = String::new();
+    let mut uses = vec![];
+    render_module(modules, 0, &mut source, &mut uses);
+    (source, uses)
+}
+
+fn render_module(
+    modules: &[Module],
+    module: usize,
+    source: &mut String,
+    uses: &mut Vec<(usize, usize, usize)>,
+) {
+    let indent = "    ".repeat(depth(modules, module));
+    let line = |source: &String| source.lines().count() + 1;
+    writeln!(
+        source,
+        "{}{}struct S {{}}",
+        indent,
+        vis_keyword(modules, module, modules[module].struct_vis)
+    )
+    .unwrap();
+    for target in 0..modules.len() {
+        uses.push((line(source), module, target));
+        let target_path = match target {
+            0 => "crate::S".to_string(),
+            _ => format!("crate::{}::S", path(modules, target)),
+        };
+        writeln!(source, "{}use {} as U{};", indent, target_path, target).unwrap();
+    }
+    for (child, m) in modules.iter().enumerate() {
+        if m.parent != Some(module) {
+            continue;
+        }
+        writeln!(
+            source,
+            "{}{}mod m{} {{",
+            indent,
+            vis_keyword(modules, module, m.vis),
+            child
+        )
+        .unwrap();
+        render_module(modules, child, source, uses);
+        writeln!(source, "{}}}", indent).unwrap();
+    }
+}
+
+/// How `vis` is written for an item declared in `module`
+fn vis_keyword(modules: &[Module], module: usize, vis: Vis) -> String {
+    match vis {
+        Vis::Priv => String::new(),
+        Vis::Pub => "pub ".to_string(),
+        Vis::Crate => "pub(crate) ".to_string(),
+        Vis::LowerSelf => "pub(self) ".to_string(),
+        Vis::Super => "pub(super) ".to_string(),
+        Vis::In(up) => match ancestors(modules, module)[up] {
+            0 => "pub(crate) ".to_string(),
+            scope => format!("pub(in crate::{}) ", path(modules, scope)),
+        },
+    }
+}
+
+/// Whether `use crate::<to>::S` resolves in `from`, which needs each module on the way and the
+/// struct to be visible from it
+fn is_accessible(modules: &[Module], from: usize, to: usize) -> bool {
+    let mut ancestors = ancestors(modules, to);
+    ancestors.pop();
+    ancestors.into_iter().all(|module| {
+        let parent = modules[module].parent.unwrap();
+        is_visible(modules, from, parent, modules[module].vis)
+    }) && is_visible(modules, from, to, modules[to].struct_vis)
+}
+
+/// Whether an item declared in `module` with `vis` is visible from `from`: it's visible in the
+/// module it's restricted to and the modules inside it
+fn is_visible(modules: &[Module], from: usize, module: usize, vis: Vis) -> bool {
+    let scope = match vis {
+        Vis::Pub => return true,
+        Vis::Priv | Vis::LowerSelf => module,
+        Vis::Super => modules[module].parent.unwrap(),
+        Vis::Crate => 0,
+        Vis::In(up) => ancestors(modules, module)[up],
+    };
+    ancestors(modules, from).contains(&scope)
+}
+
+/// `module` and the modules it's in, up to the root
+fn ancestors(modules: &[Module], module: usize) -> Vec<usize> {
+    let mut ancestors = vec![module];
+    while let Some(parent) = modules[*ancestors.last().unwrap()].parent {
+        ancestors.push(parent);
+    }
+    ancestors
+}
+
+fn depth(modules: &[Module], module: usize) -> usize {
+    ancestors(modules, module).len() - 1
+}
+
+/// The path of a module from the root, i.e. `m1::m3`, which is empty for the root
+fn path(modules: &[Module], module: usize) -> String {
+    let mut names = ancestors(modules, module)
+        .into_iter()
+        .filter(|module| *module != 0)
+        .map(|module| format!("m{}", module))
+        .collect::<Vec<_>>();
+    names.reverse();
+    names.join("::")
+}
@@ -0,0 +1,349 @@
+//! `--incremental <dir>`: the diagnostics from resolving and checking a design are saved to a
+//! cache in `dir`, along with a fingerprint of each of its files, the files each one depends on,
+//! and a key of the options that change them. The next run of the same design with the same
+//! options that only checks it reuses them:
+//!
+//! * If no file changed, all of the diagnostics are replayed instead of resolving the design.
+//! * Otherwise, a file whose fingerprint and transitive dependencies are unchanged isn't checked
+//!   again, and the diagnostics resolving it reported are replayed. A file with errors is always
+//!   checked again, since a change elsewhere can add what it couldn't find.
+//!
+//! A file depends on the files of the items it refers to, and a module is checked with the file
+//! of its items. Uses are still traced over the whole design, since a path in a changed file can
+//! go through any of them, and the checks of items run again if there are no errors, since
+//! they're of the design as a whole.
+//!
+//! The resolution graph itself isn't saved: it borrows the syntax trees of the files, which are
+//! parsed on every run anyway to find the modules of the design and fingerprint them.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use codespan_reporting::diagnostic::{Label, LabelStyle, Severity};
+use fxhash::FxHashSet as HashSet;
+use serde_json::{json, Value as Json};
+
+use crate::error::{write_output, Diagnostic};
+use crate::find_file::{FileGraph, FileId};
+use crate::resolution::Resolver;
+use crate::Options;
+
+/// A design's entry in the cache
+pub struct Cache {
+    path: PathBuf,
+    /// A hash of the options that change the diagnostics of the design
+    options: String,
+    /// The name and fingerprint of each file of the design, in the order they were found
+    fingerprints: Vec<(String, String)>,
+}
+
+/// What a run reuses from the last one
+pub enum Reuse {
+    /// No file changed, so these are all of the diagnostics of the design, from resolving it and
+    /// then from checking its items
+    All(Vec<Diagnostic>, Vec<Diagnostic>),
+    /// The files that aren't checked again, and the diagnostics resolving them reported
+    Files(HashSet<FileId>, Vec<Diagnostic>),
+}
+
+impl Cache {
+    /// The entry of the design in `file_graph` in the cache in `dir`, which is named by the
+    /// design's root file
+    pub fn new(dir: &Path, file_graph: &FileGraph, options: &Options) -> Self {
+        let fingerprints = file_graph
+            .iter()
+            .map(|file| {
+                (
+                    file_graph.inner.name(*file).to_string_lossy().to_string(),
+                    format!("{:016x}", fxhash::hash64(file_graph[*file].as_ref())),
+                )
+            })
+            .collect::<Vec<(String, String)>>();
+        let root = fingerprints
+            .first()
+            .map(|(name, _)| name.as_str())
+            .unwrap_or_default();
+        Self {
+            path: dir.join(format!("{:016x}.json", fxhash::hash64(root))),
+            options: options_key(options),
+            fingerprints,
+        }
+    }
+
+    /// What can be reused from the last run, or `None` if there wasn't one, it had different
+    /// options, or no file is unchanged along with its dependencies
+    pub fn load(&self, file_graph: &FileGraph) -> Option<Reuse> {
+        let saved: Json = serde_json::from_str(&fs::read_to_string(&self.path).ok()?).ok()?;
+        if saved["version"] != env!("CARGO_PKG_VERSION") || saved["options"] != self.options {
+            return None;
+        }
+        let files = saved["files"].as_array()?;
+        // the files of this run by their index in the last one, if they're unchanged
+        let ids = files
+            .iter()
+            .map(|file| {
+                let name = file["name"].as_str()?;
+                let i = self
+                    .fingerprints
+                    .iter()
+                    .position(|(other, _)| other == name)?;
+                if file["fingerprint"].as_str() == Some(self.fingerprints[i].1.as_str()) {
+                    file_graph.iter().nth(i).copied()
+                } else {
+                    log::debug!("{} changed since the last run", name);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let stage = |stage: &'static str| move |diagnostic: &&Json| diagnostic["stage"] == stage;
+        let diagnostics = |file: &Json| file["diagnostics"].as_array().cloned().unwrap_or_default();
+        if files.len() == self.fingerprints.len() && ids.iter().all(Option::is_some) {
+            let all = files.iter().flat_map(diagnostics).collect::<Vec<_>>();
+            let restore = |stage_name| {
+                all.iter()
+                    .filter(stage(stage_name))
+                    .map(|diagnostic| diagnostic_from_json(diagnostic, &ids))
+                    .collect::<Option<Vec<_>>>()
+            };
+            return Some(Reuse::All(restore("resolution")?, restore("items")?));
+        }
+
+        let mut reused = HashSet::default();
+        let mut restored = vec![];
+        for (i, reusable) in reusable_files(files, &ids).into_iter().enumerate() {
+            if !reusable {
+                continue;
+            }
+            reused.insert(ids[i]?);
+            for diagnostic in diagnostics(&files[i]).iter().filter(stage("resolution")) {
+                restored.push(diagnostic_from_json(diagnostic, &ids)?);
+            }
+        }
+        if reused.is_empty() {
+            return None;
+        }
+        log::info!("reusing the diagnostics of {} files", reused.len());
+        Some(Reuse::Files(reused, restored))
+    }
+
+    /// Saves the diagnostics of this run, from `resolver` and then from checking the items of the
+    /// design, by the file of their primary label, with the dependencies of the files
+    pub fn save(
+        &self,
+        file_graph: &FileGraph,
+        resolver: &Resolver,
+        items: &[Diagnostic],
+        errors: &mut Vec<Diagnostic>,
+    ) {
+        let ids = file_graph.iter().copied().collect::<Vec<_>>();
+        let dependencies = dependencies(resolver, &ids);
+        let mut diagnostics = vec![vec![]; ids.len()];
+        let stages = resolver
+            .errors
+            .iter()
+            .map(|diagnostic| (diagnostic, "resolution"))
+            .chain(items.iter().map(|diagnostic| (diagnostic, "items")));
+        for (diagnostic, stage) in stages {
+            // a diagnostic without a location is of the whole design, like its root file
+            let i = primary_file(diagnostic)
+                .and_then(|file| ids.iter().position(|id| *id == file))
+                .unwrap_or_default();
+            if let Some(file_diagnostics) = diagnostics.get_mut(i) {
+                let mut json = diagnostic_to_json(diagnostic, &ids);
+                json["stage"] = json!(stage);
+                file_diagnostics.push(json);
+            }
+        }
+        let saved = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "options": self.options,
+            "files": self
+                .fingerprints
+                .iter()
+                .zip(dependencies)
+                .zip(diagnostics)
+                .map(|(((name, fingerprint), dependencies), diagnostics)| json!({
+                    "name": name,
+                    "fingerprint": fingerprint,
+                    "dependencies": dependencies,
+                    "diagnostics": diagnostics,
+                }))
+                .collect::<Vec<_>>(),
+        });
+        let result = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&self.path, saved.to_string()));
+        if let Err(err) = result {
+            errors.push(write_output(&self.path.to_string_lossy(), err));
+        }
+    }
+}
+
+/// The file of the primary label of a diagnostic
+pub fn primary_file(diagnostic: &Diagnostic) -> Option<FileId> {
+    diagnostic
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary)
+        .map(|label| label.file_id)
+}
+
+/// A hash of the options that change the diagnostics of a design: the lint levels, where
+/// libraries are found, the top entity, the passes registered, and how files are read
+fn options_key(options: &Options) -> String {
+    let lint_levels = options.lint_levels.iter().collect::<BTreeMap<_, _>>();
+    let key = format!(
+        "{:?}",
+        (
+            lint_levels,
+            &options.libraries,
+            &options.library_path,
+            &options.top,
+            &options.passes,
+            (&options.src_dir, &options.src_extension),
+            (options.lossy_utf8, options.max_file_size, options.max_files)
+        )
+    );
+    format!("{:016x}", fxhash::hash64(&key))
+}
+
+/// Whether each file of the last run can be reused, which it can if it and the files it depends
+/// on are unchanged and it didn't have any errors
+fn reusable_files(files: &[Json], ids: &[Option<FileId>]) -> Vec<bool> {
+    let dependencies = files
+        .iter()
+        .map(|file| {
+            file["dependencies"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|dependency| dependency.as_u64().map_or(usize::MAX, |i| i as usize))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut reusable = files
+        .iter()
+        .zip(ids)
+        .map(|(file, id)| {
+            id.is_some()
+                && !file["diagnostics"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|diagnostic| {
+                        matches!(diagnostic["severity"].as_str(), Some("bug") | Some("error"))
+                    })
+        })
+        .collect::<Vec<_>>();
+    // a file that isn't reusable rules out those that depend on it, until none are left
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, dependencies) in dependencies.iter().enumerate() {
+            if reusable[i]
+                && dependencies
+                    .iter()
+                    .any(|dependency| !reusable.get(*dependency).unwrap_or(&false))
+            {
+                reusable[i] = false;
+                changed = true;
+            }
+        }
+    }
+    reusable
+}
+
+/// The files each file depends on, by their index in `ids`
+fn dependencies(resolver: &Resolver, ids: &[FileId]) -> Vec<Vec<usize>> {
+    let resolution_graph = &resolver.resolution_graph;
+    let index = |file: FileId| ids.iter().position(|id| *id == file);
+    let mut dependencies = vec![HashSet::default(); ids.len()];
+    for (target, referrers) in resolver.references() {
+        let target = index(resolution_graph.file(target));
+        for referrer in referrers {
+            if let (Some(referrer), Some(target)) = (index(resolution_graph.file(referrer)), target)
+            {
+                dependencies[referrer].insert(target);
+            }
+        }
+    }
+    dependencies
+        .into_iter()
+        .enumerate()
+        .map(|(i, dependencies)| {
+            let mut dependencies = dependencies
+                .into_iter()
+                .filter(|dependency| *dependency != i)
+                .collect::<Vec<_>>();
+            dependencies.sort_unstable();
+            dependencies
+        })
+        .collect()
+}
+
+/// Files are saved by their index in `ids`, since file ids are only meaningful within a run
+fn diagnostic_to_json(diagnostic: &Diagnostic, ids: &[FileId]) -> Json {
+    json!({
+        "severity": match diagnostic.severity {
+            Severity::Bug => "bug",
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        },
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+        "labels": diagnostic
+            .labels
+            .iter()
+            .map(|label| json!({
+                "primary": label.style == LabelStyle::Primary,
+                "file": ids.iter().position(|id| *id == label.file_id),
+                "start": label.range.start,
+                "end": label.range.end,
+                "message": label.message,
+            }))
+            .collect::<Vec<_>>(),
+        "notes": diagnostic.notes,
+    })
+}
+
+/// `ids` are the files of this run by their index in the last one, which are `None` if they
+/// changed
+fn diagnostic_from_json(diagnostic: &Json, ids: &[Option<FileId>]) -> Option<Diagnostic> {
+    let severity = match diagnostic["severity"].as_str()? {
+        "bug" => Severity::Bug,
+        "error" => Severity::Error,
+        "warning" => Severity::Warning,
+        "note" => Severity::Note,
+        "help" => Severity::Help,
+        _ => return None,
+    };
+    let mut labels = vec![];
+    for label in diagnostic["labels"].as_array()? {
+        let style = if label["primary"].as_bool()? {
+            LabelStyle::Primary
+        } else {
+            LabelStyle::Secondary
+        };
+        let file = (*ids.get(label["file"].as_u64()? as usize)?)?;
+        let range = label["start"].as_u64()? as usize..label["end"].as_u64()? as usize;
+        labels.push(Label::new(style, file, range).with_message(label["message"].as_str()?));
+    }
+    let mut restored = Diagnostic::new(severity)
+        .with_message(diagnostic["message"].as_str()?)
+        .with_labels(labels)
+        .with_notes(
+            diagnostic["notes"]
+                .as_array()?
+                .iter()
+                .map(|note| note.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()?,
+        );
+    if let Some(code) = diagnostic["code"].as_str() {
+        restored = restored.with_code(code);
+    }
+    Some(restored)
+}
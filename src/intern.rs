@@ -0,0 +1,40 @@
+//! Interned names: each distinct name is stored once, and the `Symbol`s naming it compare and hash
+//! as integers. The children of a scope are keyed by them, so looking up a name in a scope hashes
+//! the name once to find its symbol instead of once per lookup.
+//!
+//! The interner belongs to the resolution graph, so its names are freed with the graph instead of
+//! living as long as the compiler, like the language server does. Names are only interned while
+//! the graph is built, and looking them up only reads it, so the graph can be shared by threads.
+use fxhash::FxBuildHasher;
+use indexmap::IndexSet;
+
+/// The names of a resolution graph
+#[derive(Default, Debug)]
+pub struct Interner {
+    names: IndexSet<Box<str>, FxBuildHasher>,
+}
+
+/// A name in an [`Interner`]. Symbols of different interners can't be compared.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Symbol(u32);
+
+impl Interner {
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.get(name) {
+            return symbol;
+        }
+        let (index, _) = self.names.insert_full(name.into());
+        Symbol(index as u32)
+    }
+
+    /// The symbol of `name`, or `None` if it was never interned, in which case nothing is named it
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.names
+            .get_index_of(name)
+            .map(|index| Symbol(index as u32))
+    }
+
+    pub fn as_str(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
@@ -0,0 +1,1276 @@
+//! Lowers an elaborated design and its signal graph to the intermediate representation.
+//!
+//! Processes are split into a combinational process with the statements outside of any clock edge,
+//! and a clocked process for each `if clk.rising_edge() { ... }`. Constants take the type of the
+//! expression they are used in when they fit in it, and are otherwise as wide as they need to be.
+//! Variants of enums are numbered in declaration order, and a `match` is a chain of `if`s.
+//! `for` loops are unrolled, the loop variable a constant in each copy of the body.
+//!
+//! Since widths are only known here, this is also where implicit width changes of assignments
+//! and operators are linted, with `implicit_truncation` and `implicit_extension`. Both are
+//! allowed unless turned on for the design in the config file, or with `#[warn(lint)]` or
+//! `#[deny(lint)]` on an architecture or one of its functions, the function taking precedence.
+//! A cast with `as` or a constant that fits is explicit.
+use codespan_reporting::diagnostic::Severity;
+use fxhash::FxHashMap as HashMap;
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{
+    Attribute, BinOp, Block, Expr as AstExpr, ExprIf, ExprMatch, Ident, Lit, Pat, PortDir, Span,
+    Spanned, Stmt as AstStmt, Type, TypePath, UnOp,
+};
+use rhdl::visit::Visit;
+
+use super::process::{clocked_if, is_instantiation, single_ident, FnCollector};
+use super::{
+    ident_name, module_name, wrap, AsyncReset, BinaryOp, Clocking, Connection, Expr, ExprKind,
+    Instance, Ir, Level, MessagePart, Module, ModuleIndex, Process, ProcessKind, PropertyKind,
+    Signal, SignalIndex, StateMachine, Stmt, Ty, UnaryOp,
+};
+use crate::analysis::fsm::find_fsms;
+use crate::analysis::signal_graph::{SignalGraph, SignalIndex as GraphSignalIndex, SignalKind};
+use crate::attribute::{self, synthesis_pragmas, LintLevel, Pragma, PragmaTarget};
+use crate::elaboration::{
+    const_eval::{parse_int, ConstEvaluator, ConstValue},
+    enums::find_variant,
+    item_entity,
+    unroll::unroll,
+    width::type_width,
+    Design, InstanceIndex,
+};
+use crate::error::*;
+use crate::find_file::FileId;
+use crate::resolution::{black_box, item_arch, primitive_width, ResolutionIndex, Resolver};
+
+/// The lints of implicit width changes, see the module documentation
+const WIDTH_LINTS: &[&str] = &["implicit_truncation", "implicit_extension"];
+
+/// Whether implicit width changes are linted anywhere in the design, which are only found when
+/// it's lowered
+pub fn lints_widths(resolver: &Resolver) -> bool {
+    let resolution_graph = &resolver.resolution_graph;
+    let mut attrs: Vec<&[Attribute]> = vec![];
+    let arches = resolution_graph
+        .node_indices()
+        .filter(|node| resolution_graph[*node].is_entity())
+        .flat_map(|entity| resolution_graph.architectures(entity));
+    for arch in arches {
+        let mut fn_collector = FnCollector { fns: vec![] };
+        resolution_graph[*arch].visit(&mut fn_collector);
+        attrs.push(&item_arch(resolution_graph, *arch).attrs);
+        attrs.extend(
+            fn_collector
+                .fns
+                .into_iter()
+                .map(|item_fn| item_fn.attrs.as_slice()),
+        );
+    }
+    WIDTH_LINTS.iter().any(|lint| {
+        let levels = attrs
+            .iter()
+            .filter_map(|attrs| attribute::lint_level(attrs, lint));
+        resolver
+            .lint_levels
+            .get(*lint)
+            .copied()
+            .into_iter()
+            .chain(levels)
+            .any(|level| level != LintLevel::Allow)
+    })
+}
+
+/// Whether a type is one of the signed primitives, `iN`
+pub fn is_signed(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) if type_path.segments.len() == 1 => {
+            let name = type_path.segments.first().unwrap().ident.to_string();
+            name.starts_with('i') && primitive_width(&name).is_some()
+        }
+        Type::Paren(type_paren) => is_signed(&type_paren.elem),
+        _ => false,
+    }
+}
+
+/// Whether the result of an operator is as wide as its operands
+fn is_arithmetic(op: &BinOp) -> bool {
+    !matches!(
+        op,
+        BinOp::And(_)
+            | BinOp::Or(_)
+            | BinOp::Eq(_)
+            | BinOp::Ne(_)
+            | BinOp::Lt(_)
+            | BinOp::Le(_)
+            | BinOp::Gt(_)
+            | BinOp::Ge(_)
+    )
+}
+
+fn is_literal(expr: &AstExpr) -> bool {
+    match expr {
+        AstExpr::Lit(_) => true,
+        AstExpr::Paren(expr_paren) => is_literal(&expr_paren.expr),
+        AstExpr::Unary(expr_unary) => is_literal(&expr_unary.expr),
+        _ => false,
+    }
+}
+
+fn binary_op(op: &BinOp) -> BinaryOp {
+    match op {
+        BinOp::Add(_) => BinaryOp::Add,
+        BinOp::Sub(_) => BinaryOp::Sub,
+        BinOp::Mul(_) => BinaryOp::Mul,
+        BinOp::Div(_) => BinaryOp::Div,
+        BinOp::Rem(_) => BinaryOp::Rem,
+        BinOp::And(_) => BinaryOp::And,
+        BinOp::Or(_) => BinaryOp::Or,
+        BinOp::BitXor(_) => BinaryOp::BitXor,
+        BinOp::BitAnd(_) => BinaryOp::BitAnd,
+        BinOp::BitOr(_) => BinaryOp::BitOr,
+        BinOp::Shl(_) => BinaryOp::Shl,
+        BinOp::Shr(_) => BinaryOp::Shr,
+        BinOp::Eq(_) => BinaryOp::Eq,
+        BinOp::Lt(_) => BinaryOp::Lt,
+        BinOp::Le(_) => BinaryOp::Le,
+        BinOp::Ne(_) => BinaryOp::Ne,
+        BinOp::Ge(_) => BinaryOp::Ge,
+        BinOp::Gt(_) => BinaryOp::Gt,
+    }
+}
+
+/// Invalid pragmas have already been reported
+fn valid_pragmas(attrs: &[Attribute], target: PragmaTarget) -> Vec<Pragma> {
+    synthesis_pragmas(attrs, target)
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect()
+}
+
+/// Arrays of anything but bits are vectors, everything else is a single integer
+fn lower_type(
+    resolver: &Resolver,
+    scope: ResolutionIndex,
+    bindings: &[(&Ident, ConstValue)],
+    ty: &Type,
+) -> Option<Ty> {
+    let width = type_width(resolver, scope, bindings, ty)?;
+    Some(match ty {
+        Type::Array(type_array) => match type_width(resolver, scope, bindings, &type_array.elem) {
+            Some(elem_width) if elem_width > 1 => Ty::Vector(
+                Box::new(lower_type(resolver, scope, bindings, &type_array.elem)?),
+                width / elem_width,
+            ),
+            _ => Ty::UInt(width),
+        },
+        Type::Paren(type_paren) => return lower_type(resolver, scope, bindings, &type_paren.elem),
+        other if is_signed(other) => Ty::SInt(width),
+        _ => Ty::UInt(width),
+    })
+}
+
+/// The number of elements of a vector, or bits of an integer
+fn len(ty: &Ty) -> u128 {
+    match ty {
+        Ty::Vector(_, len) => *len,
+        other => other.width(),
+    }
+}
+
+/// A part of the format string of a message
+#[derive(Debug, PartialEq, Eq)]
+pub enum Piece<'a> {
+    Text(String),
+    /// `{name}`, the value of a signal or loop variable
+    Named(&'a str),
+    /// `{}`, the value of the next argument
+    Next,
+}
+
+/// Parses the format string of a message, where `{{` and `}}` are braces, or describes why it's
+/// invalid
+pub fn parse_message(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut rest = format;
+    while let Some(at) = rest.find(|c| c == '{' || c == '}') {
+        text += &rest[..at];
+        let (brace, after) = (&rest[at..at + 1], &rest[at + 1..]);
+        if after.starts_with(brace) {
+            text += brace;
+            rest = &after[1..];
+            continue;
+        }
+        let end = match (brace, after.find('}')) {
+            ("{", Some(end)) => end,
+            ("{", None) => return Err("`{` isn't closed; write `{{` for a brace".to_string()),
+            _ => return Err("`}` isn't opened; write `}}` for a brace".to_string()),
+        };
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        let name = after[..end].trim();
+        let is_name = name
+            .chars()
+            .enumerate()
+            .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+        pieces.push(match name {
+            "" => Piece::Next,
+            name if is_name => Piece::Named(name),
+            name => return Err(format!("`{}` isn't the name of a signal", name)),
+        });
+        rest = &after[end + 1..];
+    }
+    text += rest;
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// A constant of the type of `hint` if it fits in it
+fn constant(value: ConstValue, hint: Option<&Ty>, span: Span) -> Expr {
+    let value = match value {
+        ConstValue::Bool(value) => {
+            return Expr {
+                kind: ExprKind::Const(i128::from(value)),
+                ty: Ty::UInt(1),
+                span,
+            }
+        }
+        ConstValue::Int(value) => value,
+    };
+    let hint = hint.filter(|hint| !hint.is_vector());
+    let signed = hint.map(Ty::is_signed).unwrap_or_default() || value < 0;
+    let min_width = if value < 0 {
+        u128::from(129 - (!value).leading_zeros())
+    } else {
+        u128::from(128 - value.leading_zeros()).max(1) + signed as u128
+    };
+    let width = hint.map(Ty::width).unwrap_or_default().max(min_width);
+    Expr {
+        kind: ExprKind::Const(value),
+        ty: Ty::int(width, signed),
+        span,
+    }
+}
+
+pub struct Lowerer<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub design: &'a Design<'ast>,
+    pub graph: &'a SignalGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> Lowerer<'a, 'ast> {
+    pub fn lower(&mut self) -> Ir<'ast> {
+        let design = self.design;
+        let graph = self.graph;
+        let mut modules: Vec<Module<'ast>> = vec![];
+        let mut module_of = HashMap::default();
+        // instances of the same specialization share a module
+        let mut module_of_specialization = HashMap::default();
+        // the instance each module is lowered from, and how its signals map to the module's
+        let mut lowered_from: Vec<(InstanceIndex, HashMap<GraphSignalIndex, SignalIndex>)> = vec![];
+        for instance in design.instance_indices() {
+            let entity = design[instance].entity;
+            let arch = design[instance].arch;
+            if arch.is_none() && black_box(&self.resolver.resolution_graph, entity).is_none() {
+                continue;
+            }
+            let generics = &design[instance].generics;
+            let specialization = design[instance].specialization;
+            if let Some(existing) = module_of_specialization.get(&specialization) {
+                module_of.insert(instance, *existing);
+                continue;
+            }
+
+            let mut signals = vec![];
+            let mut signal_map = HashMap::default();
+            for graph_signal in graph
+                .instance_signals
+                .get(&instance)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .copied()
+            {
+                let signal = &graph[graph_signal];
+                let scope = match (signal.kind, arch) {
+                    (SignalKind::Local, Some(arch)) => arch,
+                    _ => entity,
+                };
+                let ty = match signal
+                    .ty
+                    .and_then(|ty| lower_type(self.resolver, scope, generics, ty))
+                {
+                    Some(ty) => ty,
+                    None => {
+                        self.errors.push(unknown_width(signal.file, signal.ident));
+                        continue;
+                    }
+                };
+                signal_map.insert(graph_signal, SignalIndex(signals.len()));
+                signals.push(Signal {
+                    name: ident_name(signal.ident),
+                    ident: signal.ident,
+                    file: signal.file,
+                    kind: signal.kind,
+                    ty,
+                    pragmas: valid_pragmas(signal.attrs, PragmaTarget::Signal),
+                    attrs: signal.attrs,
+                });
+            }
+            let state_machines = find_fsms(self.resolver, design, graph, instance)
+                .into_iter()
+                .filter_map(|fsm| {
+                    Some(StateMachine {
+                        signal: *signal_map.get(&fsm.signal)?,
+                        states: fsm
+                            .states
+                            .item_enum
+                            .variants
+                            .iter()
+                            .map(|variant| ident_name(&variant.ident))
+                            .collect(),
+                        initial: fsm.initial,
+                        transitions: fsm.transitions,
+                        encoding: fsm.states.encoding(),
+                    })
+                })
+                .collect();
+            module_of.insert(instance, ModuleIndex(modules.len()));
+            module_of_specialization.insert(specialization, ModuleIndex(modules.len()));
+            modules.push(Module {
+                name: match arch {
+                    Some(arch) => module_name(&self.resolver.resolution_graph, entity, arch),
+                    None => ident_name(&item_entity(self.resolver, entity).ident),
+                },
+                entity,
+                arch,
+                generics: generics.clone(),
+                file: self.resolver.resolution_graph.file(arch.unwrap_or(entity)),
+                signals,
+                processes: vec![],
+                instances: vec![],
+                state_machines,
+                pragmas: valid_pragmas(
+                    &item_entity(self.resolver, entity).attrs,
+                    PragmaTarget::Entity,
+                ),
+            });
+            lowered_from.push((instance, signal_map));
+        }
+
+        let mut bodies = vec![];
+        // specializations of an architecture and unrolled loops lower the same expressions again
+        let mut reported = HashSet::default();
+        for (module, (instance, signal_map)) in lowered_from.iter().enumerate() {
+            let arch = match modules[module].arch {
+                Some(arch) => arch,
+                // black boxes are defined outside of the design
+                None => {
+                    bodies.push((vec![], vec![]));
+                    continue;
+                }
+            };
+            let mut module_lowerer = ModuleLowerer {
+                resolver: self.resolver,
+                design,
+                graph,
+                errors: &mut *self.errors,
+                modules: &modules,
+                module_of: &module_of,
+                module: ModuleIndex(module),
+                arch,
+                instance: *instance,
+                signals: signal_map,
+                loop_vars: vec![],
+                fn_attrs: &[],
+                reported: &mut reported,
+            };
+            let processes = module_lowerer.lower_processes();
+            let instances = module_lowerer.lower_instances();
+            bodies.push((processes, instances));
+        }
+        for (module, (processes, instances)) in modules.iter_mut().zip(bodies) {
+            module.processes = processes;
+            module.instances = instances;
+        }
+        Ir { modules }
+    }
+}
+
+/// Lowers the processes and instances of the module of `instance`
+struct ModuleLowerer<'a, 'ast> {
+    resolver: &'a Resolver<'ast>,
+    design: &'a Design<'ast>,
+    graph: &'a SignalGraph<'ast>,
+    errors: &'a mut Vec<Diagnostic>,
+    modules: &'a [Module<'ast>],
+    module_of: &'a HashMap<InstanceIndex, ModuleIndex>,
+    module: ModuleIndex,
+    arch: ResolutionIndex,
+    instance: InstanceIndex,
+    signals: &'a HashMap<GraphSignalIndex, SignalIndex>,
+    /// Variables of the loops being unrolled, outermost first
+    loop_vars: Vec<(&'ast Ident, ConstValue)>,
+    /// The attributes of the function being lowered
+    fn_attrs: &'ast [Attribute],
+    /// The spans implicit width changes have been reported at
+    reported: &'a mut HashSet<(FileId, usize, usize)>,
+}
+
+impl<'a, 'ast> ModuleLowerer<'a, 'ast> {
+    fn module(&self) -> &'a Module<'ast> {
+        &self.modules[self.module.0]
+    }
+
+    /// The generic parameters of the instance, followed by the variables of the loops being unrolled
+    fn bindings(&self) -> Vec<(&'ast Ident, ConstValue)> {
+        let mut bindings = self.design[self.instance].generics.clone();
+        bindings.extend(self.loop_vars.iter().copied());
+        bindings
+    }
+
+    fn unsupported(&mut self, span: Span) {
+        let file = self.module().file;
+        self.errors.push(not_synthesizable(file, span));
+    }
+
+    /// A signal of the module. Signals of unknown width have already been reported.
+    fn lookup_signal(&mut self, ident: &Ident) -> Option<SignalIndex> {
+        match self.graph.lookup(self.instance, ident) {
+            Some(signal) => self.signals.get(&signal).copied(),
+            None => {
+                self.unsupported(ident.span());
+                None
+            }
+        }
+    }
+
+    fn read(&self, signal: SignalIndex, span: Span) -> Expr {
+        Expr {
+            kind: ExprKind::Signal(signal),
+            ty: self.module()[signal].ty.clone(),
+            span,
+        }
+    }
+
+    /// Resize an integer to another type, which vectors can't be
+    fn fit(&mut self, expr: Expr, ty: &Ty) -> Option<Expr> {
+        if expr.ty == *ty {
+            Some(expr)
+        } else if expr.ty.is_vector() || ty.is_vector() {
+            self.unsupported(expr.span);
+            None
+        } else {
+            Some(expr.resize(ty))
+        }
+    }
+
+    /// Lints `expr` being implicitly truncated or extended to `ty`, unless it's a constant that
+    /// fits in it. Changing only the signedness isn't a change of width.
+    fn check_width(&mut self, expr: &Expr, ty: &Ty) {
+        let (from, to) = (expr.ty.width(), ty.width());
+        if from == to || expr.ty.is_vector() || ty.is_vector() {
+            return;
+        }
+        if let Some(value) = expr.as_const() {
+            if wrap(value, ty) == value {
+                return;
+            }
+        }
+        let file = self.module().file;
+        let (lint, mut diagnostic) = if from > to {
+            (
+                "implicit_truncation",
+                implicit_truncation(file, expr.span, &expr.ty.name(), &ty.name()),
+            )
+        } else {
+            (
+                "implicit_extension",
+                implicit_extension(
+                    file,
+                    expr.span,
+                    &expr.ty.name(),
+                    &ty.name(),
+                    expr.ty.is_signed(),
+                ),
+            )
+        };
+        let arch_attrs: &[Attribute] = &item_arch(&self.resolver.resolution_graph, self.arch).attrs;
+        let level = attribute::lint_level(self.fn_attrs, lint)
+            .or_else(|| attribute::lint_level(arch_attrs, lint))
+            .or_else(|| self.resolver.lint_levels.get(lint).copied())
+            .unwrap_or(LintLevel::Allow);
+        match level {
+            LintLevel::Allow => return,
+            LintLevel::Warn => {}
+            LintLevel::Deny => diagnostic.severity = Severity::Error,
+        }
+        if self.reported.insert((file, expr.span.start, expr.span.end)) {
+            self.errors.push(diagnostic);
+        }
+    }
+
+    /// A single bit, reducing wider integers with [`UnaryOp::ReduceOr`]
+    fn to_bool(&mut self, expr: Expr) -> Option<Expr> {
+        if expr.ty == Ty::UInt(1) {
+            return Some(expr);
+        }
+        if expr.ty.is_vector() {
+            self.unsupported(expr.span);
+            return None;
+        }
+        let span = expr.span;
+        Some(Expr {
+            kind: ExprKind::Unary(UnaryOp::ReduceOr, Box::new(expr)),
+            ty: Ty::UInt(1),
+            span,
+        })
+    }
+
+    fn lower_processes(&mut self) -> Vec<Process> {
+        let arch = self.arch;
+        let mut fn_collector = FnCollector { fns: vec![] };
+        self.resolver.resolution_graph[arch].visit(&mut fn_collector);
+        let mut processes = vec![];
+        for item_fn in fn_collector.fns {
+            self.fn_attrs = &item_fn.attrs;
+            let test =
+                attribute::find_flag(&item_fn.attrs, "test").map(|_| ident_name(&item_fn.ident));
+            let mut comb = vec![];
+            let mut clocked = vec![];
+            for stmt in item_fn.block.statements.iter() {
+                match stmt {
+                    AstStmt::Expr(AstExpr::If(expr_if))
+                    | AstStmt::Semi(AstExpr::If(expr_if), _)
+                        if clocked_if(expr_if).is_some() =>
+                    {
+                        clocked.push(expr_if)
+                    }
+                    _ => self.lower_stmt(stmt, &mut comb),
+                }
+            }
+            if !comb.is_empty() {
+                processes.push(Process {
+                    kind: ProcessKind::Comb,
+                    body: comb,
+                    span: item_fn.span(),
+                    test: test.clone(),
+                });
+            }
+            for expr_if in clocked {
+                processes.extend(self.lower_clocked(expr_if).map(|process| Process {
+                    test: test.clone(),
+                    ..process
+                }));
+            }
+        }
+        processes
+    }
+
+    fn lower_clocked(&mut self, expr_if: &'ast ExprIf) -> Option<Process> {
+        let clocked_if = clocked_if(expr_if).unwrap();
+        let clock = self.lookup_signal(clocked_if.clock)?;
+        let reset = match clocked_if.reset {
+            Some((reset, active_high, reset_block)) => {
+                let signal = self.lookup_signal(reset)?;
+                let mut body = vec![];
+                self.lower_block(reset_block, &mut body);
+                Some(AsyncReset {
+                    signal,
+                    active_high,
+                    body,
+                })
+            }
+            None => None,
+        };
+        let mut body = vec![];
+        self.lower_block(clocked_if.body, &mut body);
+        Some(Process {
+            kind: ProcessKind::Clocked(Clocking {
+                clock,
+                edge: clocked_if.edge,
+                reset,
+            }),
+            body,
+            span: expr_if.span(),
+            test: None,
+        })
+    }
+
+    fn lower_block(&mut self, block: &'ast Block, out: &mut Vec<Stmt>) {
+        for stmt in block.statements.iter() {
+            self.lower_stmt(stmt, out);
+        }
+    }
+
+    fn lower_stmt(&mut self, stmt: &'ast AstStmt, out: &mut Vec<Stmt>) {
+        match stmt {
+            AstStmt::Local(local) => {
+                let init = match &local.init {
+                    Some((_, init)) if !is_instantiation(self.design, self.instance, local) => init,
+                    _ => return,
+                };
+                match &local.pat {
+                    Pat::Ident(pat_ident) => {
+                        if let Some(signal) = self.lookup_signal(pat_ident) {
+                            self.lower_assign(signal, None, init, None, local.span(), out);
+                        }
+                    }
+                    other => self.unsupported(other.span()),
+                }
+            }
+            AstStmt::Expr(expr) | AstStmt::Semi(expr, _) => self.lower_expr_stmt(expr, out),
+            AstStmt::Item(_) => {}
+        }
+    }
+
+    fn lower_expr_stmt(&mut self, expr: &'ast AstExpr, out: &mut Vec<Stmt>) {
+        match expr {
+            AstExpr::Assign(expr_assign) => self.lower_target(
+                &expr_assign.left,
+                &expr_assign.right,
+                None,
+                expr.span(),
+                out,
+            ),
+            AstExpr::AssignOp(expr_assign_op) => self.lower_target(
+                &expr_assign_op.left,
+                &expr_assign_op.right,
+                Some(&expr_assign_op.op),
+                expr.span(),
+                out,
+            ),
+            AstExpr::If(expr_if) => self.lower_if(expr_if, out),
+            AstExpr::Match(expr_match) => self.lower_match(expr_match, out),
+            AstExpr::ForLoop(expr_for_loop) => {
+                let bindings = self.bindings();
+                let unrolled = match unroll(
+                    &mut ConstEvaluator {
+                        resolver: self.resolver,
+                        bindings: &bindings,
+                        scope: self.arch,
+                        evaluating: vec![],
+                    },
+                    expr_for_loop,
+                ) {
+                    Ok(unrolled) => unrolled,
+                    // reported during elaboration
+                    Err(_) => return,
+                };
+                for value in unrolled.values {
+                    if let Some(var) = unrolled.var {
+                        self.loop_vars.push((var, value));
+                    }
+                    self.lower_block(&expr_for_loop.body, out);
+                    if unrolled.var.is_some() {
+                        self.loop_vars.pop();
+                    }
+                }
+            }
+            AstExpr::Block(expr_block) => self.lower_block(&expr_block.block, out),
+            AstExpr::Paren(expr_paren) => self.lower_expr_stmt(&expr_paren.expr, out),
+            AstExpr::Macro(expr_macro) => {
+                let kind = PropertyKind::ALL
+                    .iter()
+                    .copied()
+                    .find(|kind| attribute::is_named(&expr_macro.path, kind.name()));
+                let level = Level::ALL
+                    .iter()
+                    .copied()
+                    .find(|level| attribute::is_named(&expr_macro.path, level.name()));
+                let args = expr_macro.args.iter().collect::<Vec<_>>();
+                let (kind, level, cond, message) = match (kind, level, args.as_slice()) {
+                    (Some(kind), _, [cond]) => (kind, Level::Error, Some(*cond), &[][..]),
+                    (Some(kind), _, [cond, message @ ..]) if kind != PropertyKind::Cover => {
+                        (kind, Level::Error, Some(*cond), message)
+                    }
+                    (None, Some(level), [_, ..]) => (PropertyKind::Assert, level, None, &args[..]),
+                    _ => {
+                        self.unsupported(expr.span());
+                        return;
+                    }
+                };
+                let cond = match cond {
+                    Some(cond) => self.lower_cond(cond),
+                    // they fail whenever they're reached
+                    None => Some(constant(ConstValue::Bool(false), None, expr.span())),
+                };
+                let message = self.lower_message(message);
+                if let (Some(cond), Some(message)) = (cond, message) {
+                    out.push(Stmt::Property {
+                        kind,
+                        level,
+                        cond,
+                        message,
+                        span: expr.span(),
+                    });
+                }
+            }
+            other => self.unsupported(other.span()),
+        }
+    }
+
+    /// Assignments are to a whole signal or an element or bit of it
+    fn lower_target(
+        &mut self,
+        left: &'ast AstExpr,
+        right: &'ast AstExpr,
+        op: Option<&'ast BinOp>,
+        span: Span,
+        out: &mut Vec<Stmt>,
+    ) {
+        let (target, index) = match left {
+            AstExpr::Index(expr_index) => {
+                (single_ident(&expr_index.expr), Some(&*expr_index.index))
+            }
+            _ => (single_ident(left), None),
+        };
+        let signal = match target {
+            Some(ident) => match self.lookup_signal(ident) {
+                Some(signal) => signal,
+                None => return,
+            },
+            None => {
+                self.unsupported(left.span());
+                return;
+            }
+        };
+        let index = match index {
+            Some(index) => match self.lower_index(index, &self.module()[signal].ty) {
+                Some(index) => Some(index),
+                None => return,
+            },
+            None => None,
+        };
+        self.lower_assign(signal, index, right, op, span, out);
+    }
+
+    fn lower_assign(
+        &mut self,
+        signal: SignalIndex,
+        index: Option<Expr>,
+        right: &'ast AstExpr,
+        op: Option<&'ast BinOp>,
+        span: Span,
+        out: &mut Vec<Stmt>,
+    ) {
+        let signal_ty = &self.module()[signal].ty;
+        let ty = match index {
+            Some(_) => signal_ty.elem(),
+            None => signal_ty.clone(),
+        };
+        let mut value = match self.lower_expr(right, Some(&ty)) {
+            Some(value) => value,
+            None => return,
+        };
+        if let Some(op) = op {
+            let mut old = self.read(signal, span);
+            if let Some(index) = &index {
+                old = Expr {
+                    kind: ExprKind::Index(Box::new(old), Box::new(index.clone())),
+                    ty: ty.clone(),
+                    span,
+                };
+            }
+            value = match self.binary(op, old, value, right.span()) {
+                Some(value) => value,
+                None => return,
+            };
+        }
+        self.check_width(&value, &ty);
+        if let Some(value) = self.fit(value, &ty) {
+            out.push(Stmt::Assign {
+                target: signal,
+                index,
+                value,
+                span,
+            });
+        }
+    }
+
+    fn lower_if(&mut self, expr_if: &'ast ExprIf, out: &mut Vec<Stmt>) {
+        let cond = match self.lower_cond(&expr_if.cond) {
+            Some(cond) => cond,
+            None => return,
+        };
+        let mut then_branch = vec![];
+        self.lower_block(&expr_if.then_branch, &mut then_branch);
+        let mut else_branch = vec![];
+        if let Some((_, else_expr)) = &expr_if.else_branch {
+            self.lower_expr_stmt(else_expr, &mut else_branch);
+        }
+        out.push(Stmt::If {
+            cond,
+            then_branch,
+            else_branch,
+        });
+    }
+
+    /// Arms without a guard are compared to the value matched in order, `_` matching anything
+    fn lower_match(&mut self, expr_match: &'ast ExprMatch, out: &mut Vec<Stmt>) {
+        let scrutinee = match self.lower_expr(&expr_match.expr, None) {
+            Some(scrutinee) if !scrutinee.ty.is_vector() => scrutinee,
+            Some(scrutinee) => return self.unsupported(scrutinee.span),
+            None => return,
+        };
+        // built from the last arm up, each arm in the else branch of the one before it
+        let mut chain = vec![];
+        for arm in expr_match.arms.iter().rev() {
+            if let Some((_, guard)) = &arm.guard {
+                return self.unsupported(guard.span());
+            }
+            let mut body = vec![];
+            self.lower_expr_stmt(&arm.body, &mut body);
+            let pattern = match &arm.pat {
+                Pat::Wild(_) => {
+                    chain = body;
+                    continue;
+                }
+                Pat::Path(pat_path) => {
+                    match self.lower_path(&pat_path.path, Some(&scrutinee.ty), pat_path.span()) {
+                        Some(pattern) if !pattern.ty.is_vector() => pattern,
+                        Some(pattern) => return self.unsupported(pattern.span),
+                        None => return,
+                    }
+                }
+                other => return self.unsupported(other.span()),
+            };
+            let ty = Ty::int(
+                scrutinee.ty.width().max(pattern.ty.width()),
+                scrutinee.ty.is_signed() && pattern.ty.is_signed(),
+            );
+            let span = pattern.span;
+            let cond = Expr {
+                kind: ExprKind::Binary(
+                    BinaryOp::Eq,
+                    Box::new(scrutinee.clone().resize(&ty)),
+                    Box::new(pattern.resize(&ty)),
+                ),
+                ty: Ty::UInt(1),
+                span,
+            };
+            chain = vec![Stmt::If {
+                cond,
+                then_branch: body,
+                else_branch: chain,
+            }];
+        }
+        out.extend(chain);
+    }
+
+    /// The message of a property from a format string and the arguments of its `{}`s
+    fn lower_message(&mut self, args: &[&'ast AstExpr]) -> Option<Vec<MessagePart>> {
+        let file = self.module().file;
+        let (format, mut args) = match args {
+            [] => return Some(vec![]),
+            [format, args @ ..] => (*format, args.iter()),
+        };
+        let span = format.span();
+        let lit_str = match format {
+            AstExpr::Lit(expr_lit) => match &expr_lit.lit {
+                Lit::Str(lit_str) => Some(lit_str),
+                _ => None,
+            },
+            _ => None,
+        };
+        let lit_str = match lit_str {
+            Some(lit_str) => lit_str,
+            None => {
+                let msg = "expected a format string";
+                self.errors.push(invalid_message(file, span, msg));
+                return None;
+            }
+        };
+        let pieces = match parse_message(&lit_str.inner) {
+            Ok(pieces) => pieces,
+            Err(msg) => {
+                self.errors.push(invalid_message(file, span, &msg));
+                return None;
+            }
+        };
+        let mut message = vec![];
+        for piece in pieces {
+            message.push(match piece {
+                Piece::Text(text) => MessagePart::Text(text),
+                Piece::Next => match args.next() {
+                    Some(arg) => MessagePart::Value(self.lower_expr(arg, None)?),
+                    None => {
+                        let msg = "there are more `{}` than arguments";
+                        self.errors.push(invalid_message(file, span, msg));
+                        return None;
+                    }
+                },
+                Piece::Named(name) => MessagePart::Value(self.lower_name(name, span)?),
+            });
+        }
+        if let Some(arg) = args.next() {
+            let msg = "there isn't a `{}` for this";
+            self.errors.push(invalid_message(file, arg.span(), msg));
+            return None;
+        }
+        Some(message)
+    }
+
+    /// The value of a loop variable or signal named in the format string of a message
+    fn lower_name(&mut self, name: &str, span: Span) -> Option<Expr> {
+        let mut vars = self.loop_vars.iter().rev();
+        if let Some((_, value)) = vars.find(|(var, _)| ident_name(var) == name) {
+            return Some(constant(*value, None, span));
+        }
+        let module = self.module();
+        match module
+            .signal_indices()
+            .filter(|signal| module[*signal].name == name)
+            .last()
+        {
+            Some(signal) => Some(self.read(signal, span)),
+            None => {
+                let msg = format!("`{}` isn't a signal", name);
+                self.errors.push(invalid_message(module.file, span, &msg));
+                None
+            }
+        }
+    }
+
+    fn lower_cond(&mut self, expr: &'ast AstExpr) -> Option<Expr> {
+        let cond = self.lower_expr(expr, None)?;
+        self.to_bool(cond)
+    }
+
+    /// An unsigned index into a value of type `ty`, which must be in range when it is constant
+    fn lower_index(&mut self, index: &'ast AstExpr, ty: &Ty) -> Option<Expr> {
+        let lowered = self.lower_expr(index, None)?;
+        if let Some(value) = lowered.as_const() {
+            if value < 0 || value as u128 >= len(ty) {
+                self.unsupported(index.span());
+                return None;
+            }
+        }
+        let unsigned = lowered.ty.with_signedness(false);
+        self.fit(lowered, &unsigned)
+    }
+
+    /// Lower an expression, reporting anything that can't be represented in hardware.
+    /// Constants take the type of `hint` when they fit in it.
+    fn lower_expr(&mut self, expr: &'ast AstExpr, hint: Option<&Ty>) -> Option<Expr> {
+        let span = expr.span();
+        match expr {
+            AstExpr::Lit(expr_lit) => match &expr_lit.lit {
+                Lit::Int(lit_int) => match parse_int(&lit_int.inner) {
+                    Some(value) => Some(constant(ConstValue::Int(value), hint, span)),
+                    None => {
+                        self.errors.push(const_overflow(self.module().file, span));
+                        None
+                    }
+                },
+                Lit::Bool(lit_bool) => Some(constant(ConstValue::Bool(lit_bool.value), hint, span)),
+                _ => {
+                    self.unsupported(span);
+                    None
+                }
+            },
+            AstExpr::Path(expr_path) => {
+                if let Some((_, value)) = single_ident(expr).and_then(|ident| {
+                    self.loop_vars.iter().rev().find(|(var, _)| *var == ident)
+                }) {
+                    return Some(constant(*value, hint, span));
+                }
+                if let Some(signal) =
+                    single_ident(expr).and_then(|ident| self.graph.lookup(self.instance, ident))
+                {
+                    return Some(self.read(*self.signals.get(&signal)?, span));
+                }
+                self.lower_path(&expr_path.path, hint, span)
+            }
+            AstExpr::Paren(expr_paren) => self.lower_expr(&expr_paren.expr, hint),
+            AstExpr::Unary(expr_unary) => {
+                let operand = self.lower_expr(&expr_unary.expr, hint)?;
+                if operand.ty.is_vector() {
+                    self.unsupported(span);
+                    return None;
+                }
+                let op = match expr_unary.op {
+                    UnOp::Not(_) => UnaryOp::Not,
+                    UnOp::Neg(_) => UnaryOp::Neg,
+                };
+                Some(Expr {
+                    ty: operand.ty.clone(),
+                    kind: ExprKind::Unary(op, Box::new(operand)),
+                    span,
+                })
+            }
+            AstExpr::Binary(expr_binary) => {
+                // constants take the type of the other operand
+                let operand_hint = if is_arithmetic(&expr_binary.op) {
+                    hint
+                } else {
+                    None
+                };
+                let (left, right) =
+                    if is_literal(&expr_binary.left) && !is_literal(&expr_binary.right) {
+                        let right = self.lower_expr(&expr_binary.right, operand_hint)?;
+                        let left = self.lower_expr(&expr_binary.left, Some(&right.ty))?;
+                        (left, right)
+                    } else {
+                        let left = self.lower_expr(&expr_binary.left, operand_hint)?;
+                        let right = self.lower_expr(&expr_binary.right, Some(&left.ty))?;
+                        (left, right)
+                    };
+                self.binary(&expr_binary.op, left, right, span)
+            }
+            AstExpr::Index(expr_index) => {
+                let base = self.lower_expr(&expr_index.expr, None)?;
+                let index = self.lower_index(&expr_index.index, &base.ty)?;
+                Some(Expr {
+                    ty: base.ty.elem(),
+                    kind: ExprKind::Index(Box::new(base), Box::new(index)),
+                    span,
+                })
+            }
+            AstExpr::Cast(expr_cast) => {
+                let ty = match lower_type(self.resolver, self.arch, &self.bindings(), &expr_cast.ty)
+                {
+                    Some(ty) => ty,
+                    None => {
+                        self.unsupported(expr_cast.ty.span());
+                        return None;
+                    }
+                };
+                let inner = self.lower_expr(&expr_cast.expr, Some(&ty))?;
+                let mut cast = self.fit(inner, &ty)?;
+                cast.span = span;
+                Some(cast)
+            }
+            AstExpr::Block(expr_block) => match expr_block.block.statements.as_slice() {
+                [AstStmt::Expr(inner)] => self.lower_expr(inner, hint),
+                _ => {
+                    self.unsupported(span);
+                    None
+                }
+            },
+            AstExpr::If(expr_if) => {
+                let (then_value, else_value) = match (
+                    expr_if.then_branch.statements.as_slice(),
+                    expr_if.else_branch.as_ref(),
+                ) {
+                    ([AstStmt::Expr(then_value)], Some((_, else_value))) => {
+                        (then_value, else_value)
+                    }
+                    _ => {
+                        self.unsupported(span);
+                        return None;
+                    }
+                };
+                let cond = self.lower_cond(&expr_if.cond)?;
+                let then_value = self.lower_expr(then_value, hint)?;
+                let else_value = self.lower_expr(else_value, Some(&then_value.ty))?;
+                let ty = if then_value.ty == else_value.ty {
+                    then_value.ty.clone()
+                } else if then_value.ty.is_vector() || else_value.ty.is_vector() {
+                    self.unsupported(span);
+                    return None;
+                } else {
+                    Ty::int(
+                        then_value.ty.width().max(else_value.ty.width()),
+                        then_value.ty.is_signed() && else_value.ty.is_signed(),
+                    )
+                };
+                self.check_width(&then_value, &ty);
+                self.check_width(&else_value, &ty);
+                Some(Expr {
+                    kind: ExprKind::Mux(
+                        Box::new(cond),
+                        Box::new(then_value.resize(&ty)),
+                        Box::new(else_value.resize(&ty)),
+                    ),
+                    ty,
+                    span,
+                })
+            }
+            _ => {
+                self.unsupported(span);
+                None
+            }
+        }
+    }
+
+    /// A variant of an enum, or a constant
+    fn lower_path(&mut self, path: &'ast TypePath, hint: Option<&Ty>, span: Span) -> Option<Expr> {
+        let arch = self.arch;
+        if let Some((found, variant)) = find_variant(self.resolver, arch, path) {
+            return Some(Expr {
+                kind: ExprKind::Const(found.code(variant) as i128),
+                ty: Ty::UInt(found.width()),
+                span,
+            });
+        }
+        match (ConstEvaluator {
+            resolver: self.resolver,
+            bindings: &self.bindings(),
+            scope: arch,
+            evaluating: vec![],
+        }
+        .eval_path(path))
+        {
+            Ok(value) => Some(constant(value, hint, span)),
+            Err(_) => {
+                self.unsupported(span);
+                None
+            }
+        }
+    }
+
+    /// Operands are resized to a common type, which is unsigned unless both are signed
+    fn binary(&mut self, op: &BinOp, left: Expr, right: Expr, span: Span) -> Option<Expr> {
+        if left.ty.is_vector() || right.ty.is_vector() {
+            self.unsupported(span);
+            return None;
+        }
+        let op = binary_op(op);
+        let (left, right, ty) = match op {
+            BinaryOp::Shl | BinaryOp::Shr => {
+                let amount_ty = right.ty.with_signedness(false);
+                let ty = left.ty.clone();
+                (left, right.resize(&amount_ty), ty)
+            }
+            BinaryOp::And | BinaryOp::Or => {
+                (self.to_bool(left)?, self.to_bool(right)?, Ty::UInt(1))
+            }
+            _ => {
+                let operand_ty = Ty::int(
+                    left.ty.width().max(right.ty.width()),
+                    left.ty.is_signed() && right.ty.is_signed(),
+                );
+                let ty = if op.is_comparison() {
+                    Ty::UInt(1)
+                } else {
+                    operand_ty.clone()
+                };
+                self.check_width(&left, &operand_ty);
+                self.check_width(&right, &operand_ty);
+                (left.resize(&operand_ty), right.resize(&operand_ty), ty)
+            }
+        };
+        Some(Expr {
+            kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+            ty,
+            span,
+        })
+    }
+
+    fn lower_instances(&mut self) -> Vec<Instance> {
+        let design = self.design;
+        let mut instances = vec![];
+        for child in design[self.instance].children.iter().copied() {
+            let (module, instantiation) =
+                match (self.module_of.get(&child), design[child].instantiation) {
+                    (Some(module), Some(instantiation)) => (*module, instantiation),
+                    _ => continue,
+                };
+            let child_module = &self.modules[module.0];
+            let child_entity = design[child].entity;
+            // connections of an instance in a loop can refer to the loop variables
+            self.loop_vars = design[child].loop_vars.clone();
+            let mut connections = vec![];
+            for port in item_entity(self.resolver, child_entity).ports.iter() {
+                // ports of unknown width are reported by the child's module
+                let port_signal = match child_module.signal_indices().find(|signal| {
+                    child_module[*signal].kind != SignalKind::Local
+                        && *child_module[*signal].ident == port.ident
+                }) {
+                    Some(port_signal) => port_signal,
+                    None => continue,
+                };
+                let port_ty = &child_module[port_signal].ty;
+                let field_value = instantiation
+                    .expr
+                    .fields
+                    .iter()
+                    .find(|field_value| field_value.ident == port.ident);
+                match (&port.dir, field_value) {
+                    (PortDir::In(_), Some(field_value)) => {
+                        let value = match &field_value.expr {
+                            Some((_, expr)) => self.lower_expr(expr, Some(port_ty)),
+                            None => self
+                                .lookup_signal(&field_value.ident)
+                                .map(|signal| self.read(signal, field_value.span())),
+                        };
+                        if let Some(value) = value.and_then(|value| self.fit(value, port_ty)) {
+                            connections.push(Connection::Input {
+                                port: port_signal,
+                                value,
+                            });
+                        }
+                    }
+                    (PortDir::In(_), None) => {
+                        let default = match &port.default {
+                            Some((_, default)) => default,
+                            None => continue,
+                        };
+                        let value = ConstEvaluator {
+                            resolver: self.resolver,
+                            bindings: &design[child].generics,
+                            scope: child_entity,
+                            evaluating: vec![],
+                        }
+                        .eval(default);
+                        match value {
+                            Ok(value) => {
+                                let value = constant(value, Some(port_ty), default.span());
+                                if let Some(value) = self.fit(value, port_ty) {
+                                    connections.push(Connection::Input {
+                                        port: port_signal,
+                                        value,
+                                    });
+                                }
+                            }
+                            Err(err) => self.errors.push(err),
+                        }
+                    }
+                    // outputs and inouts connect to a signal of the same type
+                    (_, Some(field_value)) => {
+                        let ident = match &field_value.expr {
+                            Some((_, expr)) => single_ident(expr),
+                            None => Some(&field_value.ident),
+                        };
+                        let signal = match ident {
+                            Some(ident) => match self.lookup_signal(ident) {
+                                Some(signal) => signal,
+                                None => continue,
+                            },
+                            None => {
+                                self.unsupported(field_value.span());
+                                continue;
+                            }
+                        };
+                        if self.module()[signal].ty != *port_ty {
+                            self.unsupported(field_value.span());
+                            continue;
+                        }
+                        connections.push(match port.dir {
+                            PortDir::InOut(_) => Connection::InOut {
+                                port: port_signal,
+                                signal,
+                            },
+                            _ => Connection::Output {
+                                port: port_signal,
+                                signal,
+                            },
+                        });
+                    }
+                    (_, None) => {}
+                }
+            }
+            self.loop_vars.clear();
+            instances.push(Instance {
+                name: design[child].name.clone(),
+                module,
+                connections,
+                span: instantiation.expr.span(),
+            });
+        }
+        instances
+    }
+}
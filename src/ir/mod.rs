@@ -0,0 +1,611 @@
+//! A typed intermediate representation of an elaborated design that the backends share,
+//! rather than each of them walking the syntax tree of every architecture.
+//!
+//! Each entity/architecture pair becomes one module per distinct set of generic values,
+//! so every signal and expression has a known width. Operands are truncated or extended
+//! explicitly with [`ExprKind::Resize`]: both operands of a binary operator have the same type,
+//! except for shift amounts, and arithmetic wraps around at the width of its operands.
+//!
+//! The design is lowered from elaboration and the signal graph rather than from a type checker,
+//! since `type_checker` is an unfinished sketch over `syn`'s syntax tree that isn't compiled.
+//! The widths elaboration already works out with [`type_width`] are the types here, so lowering
+//! can move to typed expressions once there is a type checker of the rhdl syntax tree, without
+//! changing the representation.
+//!
+//! [`type_width`]: crate::elaboration::width::type_width
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{Attribute, Ident, Span};
+
+use crate::analysis::signal_graph::{Edge, SignalKind};
+use crate::attribute::Pragma;
+use crate::elaboration::{const_eval::ConstValue, enums::Encoding};
+use crate::find_file::FileId;
+use crate::resolution::{architecture_name, ResolutionGraph, ResolutionIndex};
+
+pub mod lower;
+pub mod optimize;
+pub mod process;
+
+/// The modules of a design, the top module first and every module before its children
+#[derive(Debug)]
+pub struct Ir<'ast> {
+    pub modules: Vec<Module<'ast>>,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ModuleIndex(usize);
+
+impl ModuleIndex {
+    /// The position of the module in [`Ir::modules`]
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl<'ast> std::ops::Index<ModuleIndex> for Ir<'ast> {
+    type Output = Module<'ast>;
+    fn index(&self, index: ModuleIndex) -> &<Self as std::ops::Index<ModuleIndex>>::Output {
+        &self.modules[index.0]
+    }
+}
+
+impl<'ast> Ir<'ast> {
+    pub fn module_indices(&self) -> impl Iterator<Item = ModuleIndex> {
+        (0..self.modules.len()).map(ModuleIndex)
+    }
+
+    pub fn top(&self) -> Option<&Module<'ast>> {
+        self.modules.first()
+    }
+}
+
+#[derive(Debug)]
+pub struct Module<'ast> {
+    /// The name of the entity/architecture pair, see [`module_name`]
+    pub name: String,
+    pub entity: ResolutionIndex,
+    /// `None` for black boxes, which only have ports
+    pub arch: Option<ResolutionIndex>,
+    /// The generic values this module is specialized for
+    pub generics: Vec<(&'ast Ident, ConstValue)>,
+    /// The file of the architecture, or of the entity of a black box
+    pub file: FileId,
+    /// Ports first and then locals in declaration order
+    pub signals: Vec<Signal<'ast>>,
+    pub processes: Vec<Process>,
+    pub instances: Vec<Instance>,
+    pub state_machines: Vec<StateMachine>,
+    /// From `#[synthesis(...)]` on the entity
+    pub pragmas: Vec<Pragma>,
+}
+
+/// A register of an enum type whose next state is decided by matching on it,
+/// see [`crate::analysis::fsm`]
+#[derive(Debug)]
+pub struct StateMachine {
+    pub signal: SignalIndex,
+    /// The names of the variants, numbered by their position
+    pub states: Vec<String>,
+    pub initial: usize,
+    /// Sorted pairs of states the register can go from and to
+    pub transitions: Vec<(usize, usize)>,
+    /// How the states are encoded in the register, which its values already are
+    pub encoding: Encoding,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SignalIndex(usize);
+
+impl SignalIndex {
+    /// The position of the signal in [`Module::signals`]
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl<'ast> std::ops::Index<SignalIndex> for Module<'ast> {
+    type Output = Signal<'ast>;
+    fn index(&self, index: SignalIndex) -> &<Self as std::ops::Index<SignalIndex>>::Output {
+        &self.signals[index.0]
+    }
+}
+
+impl<'ast> Module<'ast> {
+    pub fn signal_indices(&self) -> impl Iterator<Item = SignalIndex> {
+        (0..self.signals.len()).map(SignalIndex)
+    }
+
+    /// Defined outside of the design, like a vendor primitive
+    pub fn is_black_box(&self) -> bool {
+        self.arch.is_none()
+    }
+
+    /// The name qualified by the generic values, for backends without parameters,
+    /// i.e. `Counter_WIDTH_8`
+    pub fn specialized_name(&self) -> String {
+        self.generics
+            .iter()
+            .fold(self.name.clone(), |name, (ident, value)| {
+                format!(
+                    "{}_{}_{}",
+                    name,
+                    ident_name(ident),
+                    value.to_string().replace('-', "neg")
+                )
+            })
+    }
+
+    /// How a signal is clocked, if it is assigned by a clocked process
+    pub fn register_clocking(&self, signal: SignalIndex) -> Option<&Clocking> {
+        self.processes
+            .iter()
+            .filter_map(|process| match &process.kind {
+                ProcessKind::Clocked(clocking) => Some((clocking, &process.body)),
+                ProcessKind::Comb => None,
+            })
+            .find(|(clocking, body)| {
+                assigns(body, signal)
+                    || clocking
+                        .reset
+                        .as_ref()
+                        .map(|reset| assigns(&reset.body, signal))
+                        .unwrap_or_default()
+            })
+            .map(|(clocking, _)| clocking)
+    }
+
+    pub fn is_register(&self, signal: SignalIndex) -> bool {
+        self.register_clocking(signal).is_some()
+    }
+
+    /// Whether a signal is assigned by a process, rather than only driven by an instance
+    pub fn is_assigned(&self, signal: SignalIndex) -> bool {
+        self.processes.iter().any(|process| {
+            assigns(&process.body, signal)
+                || match &process.kind {
+                    ProcessKind::Clocked(Clocking {
+                        reset: Some(reset), ..
+                    }) => assigns(&reset.body, signal),
+                    _ => false,
+                }
+        })
+    }
+}
+
+/// Whether any statement assigns to a signal, possibly only to part of it
+pub fn assigns(stmts: &[Stmt], signal: SignalIndex) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::Assign { target, .. } => *target == signal,
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => assigns(then_branch, signal) || assigns(else_branch, signal),
+        Stmt::Property { .. } => false,
+    })
+}
+
+#[derive(Debug)]
+pub struct Signal<'ast> {
+    /// The name without the `r#` of a raw identifier
+    pub name: String,
+    pub ident: &'ast Ident,
+    /// The file the signal is declared in
+    pub file: FileId,
+    pub kind: SignalKind,
+    pub ty: Ty,
+    /// From `#[synthesis(...)]`
+    pub pragmas: Vec<Pragma>,
+    /// For backends that read other attributes, like `#[clock(freq = "100MHz")]`
+    pub attrs: &'ast [Attribute],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Ty {
+    /// Unsigned integers, including `bool` and arrays of `bool`
+    UInt(u128),
+    /// Signed integers, `iN`
+    SInt(u128),
+    /// Arrays of anything but bits
+    Vector(Box<Ty>, u128),
+}
+
+impl Ty {
+    pub fn int(width: u128, signed: bool) -> Ty {
+        if signed {
+            Ty::SInt(width)
+        } else {
+            Ty::UInt(width)
+        }
+    }
+
+    /// The number of bits, or `u128::MAX` for a vector with more, which is too wide for any backend
+    pub fn width(&self) -> u128 {
+        match self {
+            Ty::UInt(width) | Ty::SInt(width) => *width,
+            Ty::Vector(elem, len) => elem.width().checked_mul(*len).unwrap_or(u128::MAX),
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Ty::SInt(_))
+    }
+
+    pub fn is_vector(&self) -> bool {
+        matches!(self, Ty::Vector(..))
+    }
+
+    /// An integer of the same width and the given signedness
+    pub fn with_signedness(&self, signed: bool) -> Ty {
+        Ty::int(self.width(), signed)
+    }
+
+    /// The type of one element of a vector, or one bit of an integer
+    pub fn elem(&self) -> Ty {
+        match self {
+            Ty::Vector(elem, _) => (**elem).clone(),
+            _ => Ty::UInt(1),
+        }
+    }
+
+    /// How the type is written in a design, i.e. `bool`, `u8`, or `[i4; 2]`
+    pub fn name(&self) -> String {
+        match self {
+            Ty::UInt(1) => "bool".to_string(),
+            Ty::UInt(width) => format!("u{}", width),
+            Ty::SInt(width) => format!("i{}", width),
+            Ty::Vector(elem, len) => format!("[{}; {}]", elem.name(), len),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub ty: Ty,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    /// A constant, already wrapped to the width of its type
+    Const(i128),
+    /// The current value of a signal, which for registers is the value before the clock edge
+    Signal(SignalIndex),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// An element of a vector or a bit of an integer, by an unsigned index
+    Index(Box<Expr>, Box<Expr>),
+    /// Truncates or extends an integer to the width of the expression's type,
+    /// sign-extending signed operands, and reinterprets it with the type's signedness
+    Resize(Box<Expr>),
+    /// `cond ? then : else`, with a single bit condition
+    Mux(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Truncate, extend, or reinterpret an integer expression to another type.
+    /// Constants are folded.
+    pub fn resize(self, ty: &Ty) -> Expr {
+        if self.ty == *ty {
+            return self;
+        }
+        match self.kind {
+            ExprKind::Const(value) => Expr {
+                kind: ExprKind::Const(wrap(value, ty)),
+                ty: ty.clone(),
+                span: self.span,
+            },
+            kind => {
+                let span = self.span;
+                Expr {
+                    kind: ExprKind::Resize(Box::new(Expr { kind, ..self })),
+                    ty: ty.clone(),
+                    span,
+                }
+            }
+        }
+    }
+
+    pub fn as_const(&self) -> Option<i128> {
+        match self.kind {
+            ExprKind::Const(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Wrap a value around to the range of an integer type
+pub fn wrap(value: i128, ty: &Ty) -> i128 {
+    let width = ty.width();
+    if width >= 128 {
+        return value;
+    }
+    let truncated = value & ((1 << width) - 1);
+    if ty.is_signed() && width > 0 && truncated >> (width - 1) == 1 {
+        truncated - (1 << width)
+    } else {
+        truncated
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    /// Whether any bit is set, as a single bit
+    ReduceOr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    /// Arithmetic for signed operands
+    Shr,
+    /// Of single bits
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinaryOp {
+    /// Whether the result is a single bit rather than the type of the operands
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    /// Assigns to a signal, or to an element or bit of it when there is an index.
+    /// The value has the type of the target.
+    Assign {
+        target: SignalIndex,
+        index: Option<Expr>,
+        value: Expr,
+        span: Span,
+    },
+    If {
+        cond: Expr,
+        then_branch: Vec<Stmt>,
+        else_branch: Vec<Stmt>,
+    },
+    /// `assert!(cond)`, `assume!(cond)`, or `cover!(cond)`, checked in simulation and formal
+    /// verification and left out of the backends' output. `warning!`, `error!`, and `fatal!` are
+    /// assertions of false at their level.
+    Property {
+        kind: PropertyKind,
+        level: Level,
+        cond: Expr,
+        /// Formatted with the values when an assertion or assumption fails, empty for none
+        message: Vec<MessagePart>,
+        span: Span,
+    },
+}
+
+/// How a failing assertion is reported in simulation, like `$warning`, `$error`, and `$fatal` in
+/// SystemVerilog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// A warning, which doesn't fail the simulation
+    Warning,
+    /// An error, which is what `assert!` and `assume!` report
+    Error,
+    /// An error that ends the simulation after the cycle it fails in
+    Fatal,
+}
+
+impl Level {
+    pub const ALL: &'static [Level] = &[Level::Warning, Level::Error, Level::Fatal];
+
+    /// The name of the macro that fails at the level whenever it's reached
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Warning => "warning",
+            Level::Error => "error",
+            Level::Fatal => "fatal",
+        }
+    }
+}
+
+/// Part of the message of a property
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessagePart {
+    Text(String),
+    /// Displayed like the values of simulation reports
+    Value(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyKind {
+    /// Holds whenever it is reached
+    Assert,
+    /// A constraint on the inputs, which only stimulus that satisfies it is considered for
+    Assume,
+    /// Should hold at some point
+    Cover,
+}
+
+impl PropertyKind {
+    pub const ALL: &'static [PropertyKind] =
+        &[PropertyKind::Assert, PropertyKind::Assume, PropertyKind::Cover];
+
+    /// The name of the macro
+    pub fn name(self) -> &'static str {
+        match self {
+            PropertyKind::Assert => "assert",
+            PropertyKind::Assume => "assume",
+            PropertyKind::Cover => "cover",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Process {
+    pub kind: ProcessKind,
+    pub body: Vec<Stmt>,
+    /// The function, or the clocked `if` of a clocked process
+    pub span: Span,
+    /// The name of the function if it is a `#[test]`
+    pub test: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ProcessKind {
+    /// Assignments take effect immediately, so later statements read the assigned values
+    Comb,
+    /// Assignments take effect at the clock edge, so statements read the values before it
+    Clocked(Clocking),
+}
+
+#[derive(Debug)]
+pub struct Clocking {
+    pub clock: SignalIndex,
+    pub edge: Edge,
+    pub reset: Option<AsyncReset>,
+}
+
+/// `if rst { ... } else if clk.rising_edge() { ... }`.
+/// Synchronous resets are ordinary statements of the clocked process.
+#[derive(Debug)]
+pub struct AsyncReset {
+    pub signal: SignalIndex,
+    pub active_high: bool,
+    /// Assigns the reset values
+    pub body: Vec<Stmt>,
+}
+
+impl AsyncReset {
+    /// The value a register is reset to, if it is unconditionally reset as a whole
+    pub fn value_of(&self, signal: SignalIndex) -> Option<&Expr> {
+        self.body.iter().rev().find_map(|stmt| match stmt {
+            Stmt::Assign {
+                target,
+                index: None,
+                value,
+                ..
+            } if *target == signal => Some(value),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub name: String,
+    pub module: ModuleIndex,
+    /// In port order, leaving out unconnected ports
+    pub connections: Vec<Connection>,
+    /// The instantiation
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum Connection {
+    /// An input of the instance, driven by an expression of the parent of the port's type
+    Input { port: SignalIndex, value: Expr },
+    /// A signal of the parent driven by an output of the instance
+    Output {
+        port: SignalIndex,
+        signal: SignalIndex,
+    },
+    /// A signal of the parent connected to an inout of the instance
+    InOut {
+        port: SignalIndex,
+        signal: SignalIndex,
+    },
+}
+
+/// Signals that clock registers, and the signals connected to them through the hierarchy
+pub fn clock_signals(ir: &Ir) -> HashSet<(ModuleIndex, SignalIndex)> {
+    let mut clocks = ir
+        .module_indices()
+        .flat_map(|module| {
+            ir[module]
+                .processes
+                .iter()
+                .filter_map(move |process| match &process.kind {
+                    ProcessKind::Clocked(clocking) => Some((module, clocking.clock)),
+                    ProcessKind::Comb => None,
+                })
+        })
+        .collect::<HashSet<_>>();
+    loop {
+        let clock_count = clocks.len();
+        for module in ir.module_indices() {
+            for instance in ir[module].instances.iter() {
+                for connection in instance.connections.iter() {
+                    let (port, signal) = match connection {
+                        Connection::Input {
+                            port,
+                            value:
+                                Expr {
+                                    kind: ExprKind::Signal(signal),
+                                    ..
+                                },
+                        }
+                        | Connection::Output { port, signal }
+                        | Connection::InOut { port, signal } => (*port, *signal),
+                        Connection::Input { .. } => continue,
+                    };
+                    if clocks.contains(&(module, signal))
+                        || clocks.contains(&(instance.module, port))
+                    {
+                        clocks.insert((module, signal));
+                        clocks.insert((instance.module, port));
+                    }
+                }
+            }
+        }
+        if clocks.len() == clock_count {
+            return clocks;
+        }
+    }
+}
+
+/// The name of an identifier without the `r#` of a raw identifier
+pub fn ident_name(ident: &Ident) -> String {
+    let name = ident.to_string();
+    match name.strip_prefix("r#") {
+        Some(raw) => raw.to_string(),
+        None => name,
+    }
+}
+
+/// Modules are named after their entity, qualified by the architecture name
+/// when the entity has more than one architecture
+pub fn module_name(
+    resolution_graph: &ResolutionGraph,
+    entity: ResolutionIndex,
+    arch: ResolutionIndex,
+) -> String {
+    let entity_name = ident_name(resolution_graph[entity].name().unwrap());
+    let arches = resolution_graph.architectures(entity);
+    if arches.len() == 1 {
+        return entity_name;
+    }
+    match architecture_name(resolution_graph, arch) {
+        Some(arch_name) => format!("{}_{}", entity_name, arch_name),
+        None => format!(
+            "{}_{}",
+            entity_name,
+            arches.iter().position(|other| *other == arch).unwrap()
+        ),
+    }
+}
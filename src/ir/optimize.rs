@@ -0,0 +1,443 @@
+//! Constant propagation and dead-branch elimination, run on the intermediate representation
+//! after lowering so the backends and simulation see the simplest form of each module.
+//!
+//! Operators of constants are folded with the semantics of simulation, and locals whose only
+//! assignment gives them a constant are replaced by it where they're read after it. Branches whose
+//! condition is always false are removed, as are the else branches of conditions that are always
+//! true. A branch that's removed from every module and unrolled copy of a loop it's lowered into
+//! is reported with `unreachable_branch`, along with the constants in its condition, i.e.
+//! "never true because `WIDTH` is 8".
+use std::collections::BTreeMap;
+
+use codespan_reporting::diagnostic::Severity;
+use fxhash::FxHashMap as HashMap;
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{ExprPath, Span, Spanned, TypePath};
+use rhdl::visit::Visit;
+
+use super::{
+    wrap, Clocking, Connection, Expr, ExprKind, Ir, MessagePart, Module, ModuleIndex, ProcessKind,
+    SignalIndex, Stmt,
+};
+use crate::analysis::signal_graph::SignalKind;
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::const_eval::ConstEvaluator;
+use crate::error::*;
+use crate::find_file::FileId;
+use crate::resolution::{item_arch, Resolver};
+use crate::sim::eval::{eval, Value};
+
+const LINT: &str = "unreachable_branch";
+
+/// The condition of a branch, by its file and span
+type Branch = (FileId, usize, usize);
+
+fn branch(file: FileId, span: Span) -> Branch {
+    (file, span.start, span.end)
+}
+
+pub struct Optimizer<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> Optimizer<'a, 'ast> {
+    pub fn optimize(&mut self, ir: &mut Ir<'ast>) {
+        let mut live = HashSet::default();
+        // the conditions of removed branches in the order they're found, and the modules
+        let mut dead: Vec<(Branch, Span, Vec<ModuleIndex>)> = vec![];
+        for (index, module) in ir.modules.iter_mut().enumerate() {
+            let constants = constants(module);
+            let mut pruner = Pruner {
+                folder: Folder {
+                    constants: &constants,
+                    process: 0,
+                    stmt: 0,
+                },
+                file: module.file,
+                live: &mut live,
+                dead: vec![],
+            };
+            for (process_index, process) in module.processes.iter_mut().enumerate() {
+                pruner.folder.process = process_index;
+                pruner.folder.stmt = 0;
+                if let ProcessKind::Clocked(Clocking {
+                    reset: Some(reset), ..
+                }) = &mut process.kind
+                {
+                    reset.body = pruner.prune(std::mem::take(&mut reset.body));
+                }
+                let mut body = vec![];
+                for (stmt_index, stmt) in std::mem::take(&mut process.body).into_iter().enumerate()
+                {
+                    pruner.folder.stmt = stmt_index;
+                    pruner.prune_stmt(stmt, &mut body);
+                }
+                process.body = body;
+            }
+            // connections are read continuously, after every assignment
+            pruner.folder.process = usize::MAX;
+            for instance in module.instances.iter_mut() {
+                for connection in instance.connections.iter_mut() {
+                    if let Connection::Input { value, .. } = connection {
+                        let folded = pruner.folder.fold(value.clone());
+                        *value = folded;
+                    }
+                }
+            }
+            for span in pruner.dead {
+                let removed = branch(module.file, span);
+                match dead.iter_mut().find(|(other, _, _)| *other == removed) {
+                    Some((_, _, modules)) => modules.push(ModuleIndex(index)),
+                    None => dead.push((removed, span, vec![ModuleIndex(index)])),
+                }
+            }
+        }
+        for (removed, span, modules) in dead {
+            if live.contains(&removed) {
+                continue;
+            }
+            self.report(ir, removed.0, span, &modules);
+        }
+    }
+
+    /// Reports a branch that's never taken, at the level set on the architecture of the first
+    /// module it's removed from or else for the design
+    fn report(&mut self, ir: &Ir<'ast>, file: FileId, span: Span, modules: &[ModuleIndex]) {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let arch = match ir[modules[0]].arch {
+            Some(arch) => arch,
+            None => return,
+        };
+        let level = attribute::lint_level(&item_arch(resolution_graph, arch).attrs, LINT)
+            .or_else(|| self.resolver.lint_levels.get(LINT).copied())
+            .unwrap_or(LintLevel::Warn);
+        let mut diagnostic = match level {
+            LintLevel::Allow => return,
+            LintLevel::Warn | LintLevel::Deny => {
+                unreachable_branch(file, span, &self.constants_in(ir, span, modules))
+            }
+        };
+        if level == LintLevel::Deny {
+            diagnostic.severity = Severity::Error;
+        }
+        self.errors.push(diagnostic);
+    }
+
+    /// The constants and generic parameters named in the condition at `span`, with their values
+    /// in each module, i.e. "`WIDTH` is 8 or 16"
+    fn constants_in(&self, ir: &Ir<'ast>, span: Span, modules: &[ModuleIndex]) -> Vec<String> {
+        let mut values: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for module in modules.iter().map(|module| &ir[*module]) {
+            let arch = match module.arch {
+                Some(arch) => arch,
+                None => continue,
+            };
+            let mut collector = PathCollector {
+                span,
+                paths: vec![],
+            };
+            self.resolver.resolution_graph[arch].visit(&mut collector);
+            for path in collector.paths {
+                let mut evaluator = ConstEvaluator {
+                    resolver: self.resolver,
+                    bindings: &module.generics,
+                    scope: arch,
+                    evaluating: vec![],
+                };
+                if let Ok(value) = evaluator.eval_path(path) {
+                    let name = path.segments.last().unwrap().ident.to_string();
+                    let module_values = values.entry(name).or_default();
+                    let value = value.to_string();
+                    if !module_values.contains(&value) {
+                        module_values.push(value);
+                    }
+                }
+            }
+        }
+        values
+            .into_iter()
+            .map(|(name, values)| format!("`{}` is {}", name, values.join(" or ")))
+            .collect()
+    }
+}
+
+/// Where a local that's only assigned a constant is assigned, by the index of the combinational
+/// process and of the statement in its body
+struct Constant {
+    value: i128,
+    process: usize,
+    stmt: usize,
+}
+
+/// The locals of a module that are assigned once, as a whole, at the top of a combinational
+/// process, to a value that folds to a constant
+fn constants(module: &Module) -> HashMap<SignalIndex, Constant> {
+    let mut assignments: HashMap<SignalIndex, usize> = HashMap::default();
+    for process in module.processes.iter() {
+        count_assignments(&process.body, &mut assignments);
+        if let ProcessKind::Clocked(Clocking {
+            reset: Some(reset), ..
+        }) = &process.kind
+        {
+            count_assignments(&reset.body, &mut assignments);
+        }
+    }
+    for instance in module.instances.iter() {
+        for connection in instance.connections.iter() {
+            if let Connection::Output { signal, .. } | Connection::InOut { signal, .. } = connection
+            {
+                *assignments.entry(*signal).or_default() += 1;
+            }
+        }
+    }
+    let mut candidates = vec![];
+    for (process_index, process) in module.processes.iter().enumerate() {
+        if !matches!(process.kind, ProcessKind::Comb) {
+            continue;
+        }
+        for (stmt_index, stmt) in process.body.iter().enumerate() {
+            if let Stmt::Assign {
+                target,
+                index: None,
+                value,
+                ..
+            } = stmt
+            {
+                if module[*target].kind == SignalKind::Local && assignments[target] == 1 {
+                    candidates.push((*target, value, process_index, stmt_index));
+                }
+            }
+        }
+    }
+    // values can read locals that are found to be constant later on
+    let mut constants = HashMap::default();
+    loop {
+        let count = constants.len();
+        for (target, value, process, stmt) in candidates.iter() {
+            if constants.contains_key(target) {
+                continue;
+            }
+            let folder = Folder {
+                constants: &constants,
+                process: *process,
+                stmt: *stmt,
+            };
+            if let Some(value) = folder.fold((*value).clone()).as_const() {
+                constants.insert(
+                    *target,
+                    Constant {
+                        value,
+                        process: *process,
+                        stmt: *stmt,
+                    },
+                );
+            }
+        }
+        if constants.len() == count {
+            return constants;
+        }
+    }
+}
+
+fn count_assignments(stmts: &[Stmt], assignments: &mut HashMap<SignalIndex, usize>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign { target, .. } => *assignments.entry(*target).or_default() += 1,
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                count_assignments(then_branch, assignments);
+                count_assignments(else_branch, assignments);
+            }
+            Stmt::Property { .. } => {}
+        }
+    }
+}
+
+/// Folds the expressions of a statement at the top of the body of a process
+struct Folder<'c> {
+    constants: &'c HashMap<SignalIndex, Constant>,
+    process: usize,
+    stmt: usize,
+}
+
+impl<'c> Folder<'c> {
+    /// The constant a local is replaced by, if it's been assigned by this point of a process
+    fn constant(&self, signal: SignalIndex) -> Option<i128> {
+        let constant = self.constants.get(&signal)?;
+        if constant.process == self.process && self.stmt <= constant.stmt {
+            None
+        } else {
+            Some(constant.value)
+        }
+    }
+
+    fn fold(&self, expr: Expr) -> Expr {
+        let Expr { kind, ty, span } = expr;
+        let kind = match kind {
+            ExprKind::Const(value) => {
+                return Expr {
+                    kind: ExprKind::Const(value),
+                    ty,
+                    span,
+                }
+            }
+            ExprKind::Signal(signal) => {
+                return Expr {
+                    kind: match self.constant(signal) {
+                        Some(value) => ExprKind::Const(value),
+                        None => ExprKind::Signal(signal),
+                    },
+                    ty,
+                    span,
+                }
+            }
+            ExprKind::Unary(op, operand) => ExprKind::Unary(op, Box::new(self.fold(*operand))),
+            ExprKind::Binary(op, left, right) => {
+                ExprKind::Binary(op, Box::new(self.fold(*left)), Box::new(self.fold(*right)))
+            }
+            ExprKind::Index(base, index) => {
+                ExprKind::Index(Box::new(self.fold(*base)), Box::new(self.fold(*index)))
+            }
+            ExprKind::Resize(operand) => ExprKind::Resize(Box::new(self.fold(*operand))),
+            ExprKind::Mux(cond, then_value, else_value) => {
+                let cond = self.fold(*cond);
+                match cond.as_const() {
+                    Some(0) => return self.fold(*else_value),
+                    Some(_) => return self.fold(*then_value),
+                    None => ExprKind::Mux(
+                        Box::new(cond),
+                        Box::new(self.fold(*then_value)),
+                        Box::new(self.fold(*else_value)),
+                    ),
+                }
+            }
+        };
+        let expr = Expr { kind, ty, span };
+        let operands_are_const = match &expr.kind {
+            ExprKind::Unary(_, operand) | ExprKind::Resize(operand) => operand.as_const().is_some(),
+            ExprKind::Binary(_, left, right) | ExprKind::Index(left, right) => {
+                left.as_const().is_some() && right.as_const().is_some()
+            }
+            _ => false,
+        };
+        if !operands_are_const {
+            return expr;
+        }
+        match eval(&expr, &[]) {
+            Value::Int { bits, .. } => Expr {
+                kind: ExprKind::Const(wrap(bits as i128, &expr.ty)),
+                ..expr
+            },
+            Value::Vector(_) => expr,
+        }
+    }
+}
+
+/// Folds the statements of a module, removing the branches that are never taken
+struct Pruner<'c, 'l> {
+    folder: Folder<'c>,
+    file: FileId,
+    /// The conditions of the branches that are kept in any module
+    live: &'l mut HashSet<Branch>,
+    /// The conditions of the branches removed from this module
+    dead: Vec<Span>,
+}
+
+impl<'c, 'l> Pruner<'c, 'l> {
+    fn prune(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut out = vec![];
+        for stmt in stmts {
+            self.prune_stmt(stmt, &mut out);
+        }
+        out
+    }
+
+    fn prune_stmt(&mut self, stmt: Stmt, out: &mut Vec<Stmt>) {
+        match stmt {
+            Stmt::Assign {
+                target,
+                index,
+                value,
+                span,
+            } => out.push(Stmt::Assign {
+                target,
+                index: index.map(|index| self.folder.fold(index)),
+                value: self.folder.fold(value),
+                span,
+            }),
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let cond = self.folder.fold(cond);
+                match cond.as_const() {
+                    Some(0) => {
+                        if !then_branch.is_empty() {
+                            self.dead.push(cond.span);
+                        }
+                        out.extend(self.prune(else_branch));
+                    }
+                    Some(_) => {
+                        self.live.insert(branch(self.file, cond.span));
+                        out.extend(self.prune(then_branch));
+                    }
+                    None => {
+                        self.live.insert(branch(self.file, cond.span));
+                        let then_branch = self.prune(then_branch);
+                        let else_branch = self.prune(else_branch);
+                        if !then_branch.is_empty() || !else_branch.is_empty() {
+                            out.push(Stmt::If {
+                                cond,
+                                then_branch,
+                                else_branch,
+                            });
+                        }
+                    }
+                }
+            }
+            Stmt::Property {
+                kind,
+                level,
+                cond,
+                message,
+                span,
+            } => out.push(Stmt::Property {
+                kind,
+                level,
+                cond: self.folder.fold(cond),
+                message: message
+                    .into_iter()
+                    .map(|part| match part {
+                        MessagePart::Value(expr) => MessagePart::Value(self.folder.fold(expr)),
+                        text => text,
+                    })
+                    .collect(),
+                span,
+            }),
+        }
+    }
+}
+
+/// The paths within a span of an architecture
+struct PathCollector<'ast> {
+    span: Span,
+    paths: Vec<&'ast TypePath>,
+}
+
+impl<'ast> Visit<'ast> for PathCollector<'ast> {
+    fn visit_expr_path(&mut self, expr_path: &'ast ExprPath) {
+        self.visit_type_path(&expr_path.path);
+    }
+
+    fn visit_type_path(&mut self, type_path: &'ast TypePath) {
+        let span = type_path.span();
+        if self.span.start <= span.start && span.end <= self.span.end {
+            self.paths.push(type_path);
+        }
+    }
+}
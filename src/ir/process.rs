@@ -0,0 +1,107 @@
+//! How the statements of an architecture's processes map to hardware
+use rhdl::ast::{Block, Expr, ExprIf, Ident, ItemFn, Local, UnOp};
+use rhdl::visit::Visit;
+
+use crate::analysis::signal_graph::Edge;
+use crate::elaboration::{Design, InstanceIndex};
+
+/// `if clk.rising_edge() { ... }`, optionally preceded by an asynchronous reset
+pub struct ClockedIf<'ast> {
+    pub clock: &'ast Ident,
+    pub edge: Edge,
+    /// The reset signal, whether it is active-high, and the block that assigns reset values
+    pub reset: Option<(&'ast Ident, bool, &'ast Block)>,
+    pub body: &'ast Block,
+}
+
+pub fn clocked_if(expr_if: &ExprIf) -> Option<ClockedIf> {
+    if let Some((clock, edge)) = edge(&expr_if.cond) {
+        return Some(ClockedIf {
+            clock,
+            edge,
+            reset: None,
+            body: &expr_if.then_branch,
+        });
+    }
+    let else_if = match expr_if
+        .else_branch
+        .as_ref()
+        .map(|(_, else_branch)| &**else_branch)
+    {
+        Some(Expr::If(else_if)) => else_if,
+        _ => return None,
+    };
+    let (clock, edge) = edge(&else_if.cond)?;
+    let (reset, active_high) = reset(&expr_if.cond)?;
+    Some(ClockedIf {
+        clock,
+        edge,
+        reset: Some((reset, active_high, &expr_if.then_branch)),
+        body: &else_if.then_branch,
+    })
+}
+
+fn edge(cond: &Expr) -> Option<(&Ident, Edge)> {
+    match cond {
+        Expr::MethodCall(method_call) if method_call.args.is_empty() => {
+            let edge = if method_call.method == "rising_edge" {
+                Edge::Rising
+            } else if method_call.method == "falling_edge" {
+                Edge::Falling
+            } else {
+                return None;
+            };
+            Some((single_ident(&method_call.receiver)?, edge))
+        }
+        Expr::Paren(expr_paren) => edge(&expr_paren.expr),
+        _ => None,
+    }
+}
+
+fn reset(cond: &Expr) -> Option<(&Ident, bool)> {
+    match cond {
+        Expr::Path(_) => single_ident(cond).map(|ident| (ident, true)),
+        Expr::Unary(expr_unary) if matches!(expr_unary.op, UnOp::Not(_)) => {
+            reset(&expr_unary.expr).map(|(ident, active_high)| (ident, !active_high))
+        }
+        Expr::Paren(expr_paren) => reset(&expr_paren.expr),
+        _ => None,
+    }
+}
+
+pub fn single_ident(expr: &Expr) -> Option<&Ident> {
+    match expr {
+        Expr::Path(expr_path)
+            if expr_path.path.leading_sep.is_none() && expr_path.path.segments.len() == 1 =>
+        {
+            let segment = expr_path.path.segments.first().unwrap();
+            if segment.generic_args.is_none() {
+                Some(&segment.ident)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Collects the processes of an architecture
+pub struct FnCollector<'ast> {
+    pub fns: Vec<&'ast ItemFn>,
+}
+
+impl<'ast> Visit<'ast> for FnCollector<'ast> {
+    fn visit_item_fn(&mut self, item_fn: &'ast ItemFn) {
+        self.fns.push(item_fn);
+    }
+}
+
+/// Whether `local` names an instance of `instance`, rather than a signal
+pub fn is_instantiation(design: &Design, instance: InstanceIndex, local: &Local) -> bool {
+    design[instance]
+        .children
+        .iter()
+        .filter_map(|child| design[*child].instantiation)
+        .filter_map(|instantiation| instantiation.local)
+        .any(|instantiation_local| std::ptr::eq(instantiation_local, local))
+}
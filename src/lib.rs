@@ -0,0 +1,3734 @@
+//! The RHDL compiler as a library. The `rhdlc` executable runs [`main`], and tools that embed the
+//! compiler use its modules directly, like to register custom passes in [`Options`].
+//!
+//! With the `capi` feature, it's also built as `librhdlc` for C and C++ programs to link to, with
+//! the functions of `capi.rs` declared in `include/rhdlc.h`, and with the `python` feature, as the
+//! Python module of `python.rs`.
+// the C API and the browser playground allow it where they need it
+#![deny(unsafe_code)]
+
+use clap::{clap_app, crate_authors, crate_description, crate_version};
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+
+use std::env;
+
+pub mod analysis;
+pub mod api_diff;
+pub mod attribute;
+#[cfg(feature = "capi")]
+#[allow(unsafe_code)]
+pub mod capi;
+pub mod codegen;
+pub mod compilation_db;
+pub mod config;
+pub mod doc;
+pub mod elaboration;
+pub mod error;
+pub mod events;
+pub mod find_file;
+pub mod fmt;
+pub mod formal;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod incremental;
+pub mod intern;
+pub mod ir;
+pub mod logging;
+pub mod lsp;
+pub mod minimize;
+pub mod pass;
+#[cfg(any(test, target_arch = "wasm32"))]
+// the binding is generated with unsafe code
+#[cfg_attr(target_arch = "wasm32", allow(unsafe_code))]
+pub mod playground;
+#[cfg(feature = "python")]
+// the bindings are generated with unsafe code
+#[allow(unsafe_code)]
+pub mod python;
+pub mod query;
+pub mod recover;
+pub mod report;
+pub mod resolution;
+pub mod sarif;
+pub mod sim;
+pub mod status;
+pub mod stdlib;
+pub mod timing;
+// mod type_checker;
+
+use analysis::signal_graph::SignalGraph;
+use elaboration::{Design, Elaborator};
+use find_file::{FileContentProvider, FileFinder};
+use resolution::Resolver;
+use timing::Instant;
+
+/// Options that affect compilation beyond the choice of root file
+#[derive(Default, Debug, Clone)]
+pub struct Options {
+    /// The path of the entity to elaborate the design from, detected when `None`
+    pub top: Option<String>,
+    /// Output to generate from the elaborated design
+    pub emit: Vec<codegen::Emit>,
+    /// Simulate the elaborated design, with `top` as the testbench
+    pub simulate: Option<sim::Simulate>,
+    /// Run the testbenches of the design instead of elaborating `top`
+    pub test: Option<sim::test::Test>,
+    /// Export the elaborated design and its properties for bounded model checking
+    pub formal: Option<formal::Formal>,
+    /// Check the assertions of the elaborated design with bounded model checking
+    pub prove: Option<formal::Prove>,
+    /// Check that two architectures of `top` are equivalent instead of elaborating it once
+    pub equiv: Option<formal::Equiv>,
+    /// Rename the item at a position instead of compiling the design
+    pub rename: Option<resolution::Rename>,
+    /// Format the files of the design instead of compiling it
+    pub format: Option<fmt::Format>,
+    /// Generate HTML documentation for the design instead of compiling it
+    pub doc: Option<doc::Doc>,
+    /// Compare the public API of the design with that of an old revision instead of compiling it
+    pub api_diff: Option<api_diff::ApiDiff>,
+    /// The directory to cache the diagnostics of checking the design in between runs
+    pub incremental: Option<std::path::PathBuf>,
+    /// The file to write a compilation database for the files of the design to
+    pub json_compilation_db: Option<std::path::PathBuf>,
+    /// Report how long each stage of compilation took after the output
+    pub time_passes: bool,
+    /// Only report errors
+    pub quiet: bool,
+    /// End the diagnostics with a count of them and the time since compilation started here
+    pub summary: Option<Instant>,
+    /// Color the diagnostics with ANSI escape codes
+    pub color: bool,
+    /// The number of errors to report before stopping, or all of them if `None`
+    pub error_limit: Option<usize>,
+    /// Resolve the design a second time and panic if anything about it differs, to catch output
+    /// that depends on the order of a hash map or of threads
+    pub verify_determinism: bool,
+    /// How diagnostics are rendered
+    pub render: report::Render,
+    /// Whether diagnostics are rendered or written as a SARIF log or events
+    pub error_format: report::ErrorFormat,
+    /// Where the events of `--message-format json-stream` are written
+    pub events: Option<events::Events>,
+    /// The directory to find the modules of a root read from standard input in
+    pub src_dir: Option<std::path::PathBuf>,
+    /// The extension of the files of those modules
+    pub src_extension: Option<String>,
+    /// Replace invalid UTF-8 in the files of the design instead of reporting it
+    pub lossy_utf8: bool,
+    /// The size in bytes of the largest file read, or the default if `None`
+    pub max_file_size: Option<u64>,
+    /// The number of files found before the rest of the modules are left out, or the default if
+    /// `None`
+    pub max_files: Option<usize>,
+    /// Libraries registered by name with `--lib` and then the config file, as a directory or root
+    /// file
+    pub libraries: Vec<(String, std::path::PathBuf)>,
+    /// The directories libraries are found in, from `RHDL_PATH` and then the config file
+    pub library_path: Vec<std::path::PathBuf>,
+    /// The levels lints are set to for the whole design by the config file
+    pub lint_levels: attribute::LintLevels,
+    /// Checks registered by an embedder to run over each file of the design after resolution
+    pub passes: pass::Passes,
+    /// Behavioral models registered by an embedder to simulate black boxes with
+    pub models: sim::model::Models,
+}
+
+impl Options {
+    /// Whether an output is generated from the elaborated design, which needs a top entity
+    fn needs_design(&self) -> bool {
+        self.emit.iter().any(|emit| emit.kind.needs_design())
+            || self.simulate.is_some()
+            || self.formal.is_some()
+            || self.prove.is_some()
+    }
+
+    /// Whether the design is only checked, so the diagnostics are the only output
+    fn only_checks(&self) -> bool {
+        self.top.is_none()
+            && self.emit.is_empty()
+            && self.simulate.is_none()
+            && self.test.is_none()
+            && self.formal.is_none()
+            && self.prove.is_none()
+            && self.equiv.is_none()
+            && self.rename.is_none()
+            && self.format.is_none()
+            && self.doc.is_none()
+            && self.api_diff.is_none()
+    }
+}
+
+/// The command line interface of `rhdlc`, which its executable runs
+#[cfg(not(any(feature = "fuzz", target_arch = "wasm32")))]
+pub fn main() {
+    let start = Instant::now();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        eprintln!("error: internal compiler error, please report it along with the design");
+        status::Status::InternalError.exit()
+    }));
+    let app_matches = clap_app!(rhdlc =>
+        (version: crate_version!())
+        (author: crate_authors!())
+        (about: crate_description!())
+        (@arg FILE: "The top level RHDL file")
+        (@arg TOP: --top +takes_value "The path of the entity to elaborate the design from, like cpu::Core; detected when omitted")
+        (@arg EMIT: --emit +takes_value +multiple number_of_values(1) "Output to generate, as <kind> or <kind>=<dir>: verilog, netlist-json, firrtl, fsm-dot, resources, timing-report, sdc, hierarchy, hierarchy-json, instance-graph, instance-graph-json, symbols, exports, exports-json, interface, module-graph, module-graph-json")
+        (@arg FORMAL: --formal +takes_value "The SMT-LIB file to export the design and its properties to for bounded model checking, or - for standard output")
+        (@arg DEPTH: --depth +takes_value "The number of clock cycles to unroll the design for with --formal, 20 by default")
+        (@arg INCREMENTAL: --incremental +takes_value "The directory to cache the results of checking the design in, which are reused for the files that are unchanged along with those they depend on, while the options are the same")
+        (@arg JSON_COMPILATION_DB: --("json-compilation-db") +takes_value "The file to write a clang-style compilation database to, with an entry for each file of the design and the command it's compiled by")
+        (@arg TIME_PASSES: --("time-passes") "Report how long each stage of compilation took and the peak memory after it")
+        (@arg COLOR: --color +global +takes_value possible_values(&["auto", "always", "never"]) "Whether to color the diagnostics: auto colors them when standard error is a terminal and NO_COLOR isn't set")
+        (@arg ERROR_LIMIT: --("error-limit") +takes_value "The number of errors to report before stopping, 50 by default; 0 reports all of them")
+        (@arg DIAGNOSTIC_WIDTH: --("diagnostic-width") +global +takes_value "The width to wrap the notes of diagnostics at; overrides diagnostics.width in .rhdlc.toml")
+        (@arg CONTEXT_LINES: --("context-lines") +global +takes_value "The number of lines to show around the start and end of labels spanning lines; overrides diagnostics.context_lines")
+        (@arg ASCII: --ascii +global "Draw diagnostics with ASCII characters only, for CI logs; overrides diagnostics.ascii")
+        (@arg ANONYMIZE_PATHS: --("anonymize-paths") +global "Name files relative to the directory of the root file so output can be shared; overrides diagnostics.anonymize_paths")
+        (@arg ERROR_FORMAT: --("error-format") +global +takes_value possible_values(&["human", "sarif"]) "The format of the diagnostics: human renders them with their source, sarif writes a SARIF 2.1.0 log for code scanning")
+        (@arg MESSAGE_FORMAT: --("message-format") +global +takes_value possible_values(&["human", "json-stream"]) conflicts_with[ERROR_FORMAT] "The format of the messages of compilation: human renders diagnostics with their source, json-stream writes a JSON event a line to standard output as each file is found, stage starts, diagnostic is reported and output is written")
+        (@arg SRC_DIR: --("src-dir") +global +takes_value "The directory to find the files of modules in when the root file is read from standard input, instead of the current directory")
+        (@arg SRC_EXT: --("src-ext") +global +takes_value "The extension of the files of modules when the root file is read from standard input, rhdl by default")
+        (@arg LOSSY_UTF8: --("lossy-utf8") +global "Replace invalid UTF-8 in files with U+FFFD and warn, instead of reporting it as an error")
+        (@arg MAX_FILE_SIZE: --("max-file-size") +global +takes_value "The size in bytes of the largest file read, 16 MiB by default")
+        (@arg MAX_FILES: --("max-files") +global +takes_value "The number of files found before the rest of the modules are left out, 10000 by default")
+        (@arg LIB: --lib +global +takes_value +multiple number_of_values(1) "A library the design can use by name, as <name>=<path> to its directory or root file; these take precedence over the libraries in the subdirectories of the directories in RHDL_PATH")
+        (@arg VERBOSE: -v --verbose +global +multiple "Log what the compiler is doing to standard error: -v for each stage with counts of files, nodes and solver queries, -vv for each file, -vvv for the time of each pass")
+        (@arg QUIET: -q --quiet +global conflicts_with[VERBOSE] "Only report errors, leaving out warnings, notes and the summary after them")
+        (@arg NO_CONFIG: --("no-config") +global "Ignore the .rhdlc.toml of the project, in the directory of the root file or one of its ancestors")
+        (@arg VERIFY_DETERMINISM: --("verify-determinism") +hidden "Resolve the design twice and panic if the results differ")
+        (@subcommand sim =>
+            (about: "Simulates a testbench cycle by cycle and reports its signals after each cycle")
+            (@arg FILE: "The top level RHDL file")
+            (@arg TOP: --top +takes_value "The path of the testbench entity; detected when omitted")
+            (@arg CYCLES: --cycles +takes_value "The number of clock cycles to simulate, 10 by default")
+            (@arg OUTPUT: -o --output +takes_value "The file to write the report to instead of standard output")
+            (@arg BACKEND: --backend +takes_value possible_values(&["interpreted", "compiled"]) "How to simulate the design: interpreted executes it directly, compiled builds a program from it with rustc first, which takes a while but runs long simulations faster")
+            (@arg SEED: --seed +takes_value "The seed to draw the testbench's #[random] inputs with; one is picked and reported when omitted")
+            (@arg COVERAGE: --coverage +takes_value "The directory to collect the hits of covers and coverpoints in, adding them to those of earlier runs in coverage.json and listing them in coverage.txt")
+        )
+        (@subcommand test =>
+            (about: "Simulates the testbenches marked #[test] and checks their assertions")
+            (@arg FILE: "The top level RHDL file")
+            (@arg CYCLES: --cycles +takes_value "The number of clock cycles to simulate each testbench for, 100 by default")
+            (@arg VCD: --vcd +takes_value "The directory to write value change dumps of failing testbenches to")
+            (@arg OUTPUT: -o --output +takes_value "The file to write the results to instead of standard output")
+            (@arg SEED: --seed +takes_value "The seed to draw the #[random] inputs of each testbench with; one is picked and reported when omitted")
+            (@arg COVERAGE: --coverage +takes_value "The directory to collect the hits of the covers and coverpoints of every testbench in, adding them to those of earlier runs in coverage.json and listing them in coverage.txt")
+            (@arg LCOV: --lcov +takes_value "The file to write the lines executed and the signal bits toggled by the testbenches to as an lcov tracefile")
+        )
+        (@subcommand prove =>
+            (about: "Proves the assertions of a design with bounded model checking and k-induction, reporting a counterexample for each that can fail")
+            (@arg FILE: "The top level RHDL file")
+            (@arg TOP: --top +takes_value "The path of the entity to check; detected when omitted")
+            (@arg DEPTH: --depth +takes_value "The number of clock cycles to look for counterexamples in and to assume an assertion held for in its induction step, 20 by default")
+            (@arg VCD: --vcd +takes_value "The directory to write value change dumps of the counterexamples to")
+            (@arg OUTPUT: -o --output +takes_value "The file to write the results to instead of standard output")
+        )
+        (@subcommand equiv =>
+            (about: "Checks that two architectures of an entity have the same outputs given the same inputs, reporting inputs that tell them apart if they don't")
+            (@arg FILE: "The top level RHDL file")
+            (@arg TOP: --top +takes_value +required "The path of the entity")
+            (@arg IMPL_A: --("impl-a") +takes_value +required "The name of one architecture")
+            (@arg IMPL_B: --("impl-b") +takes_value +required "The name of the other architecture")
+            (@arg DEPTH: --depth +takes_value "The number of clock cycles to compare their outputs in and to assume they were the same for in the induction step, 20 by default")
+            (@arg VCD: --vcd +takes_value "The directory to write value change dumps of both architectures given inputs that tell them apart to")
+            (@arg OUTPUT: -o --output +takes_value "The file to write the results to instead of standard output")
+        )
+        (@subcommand rename =>
+            (about: "Renames an item along with the uses and paths that refer to it")
+            (@arg FILE: +required "The top level RHDL file")
+            (@arg AT: +required "The position of a name of the item, as <file>:<line>:<column>")
+            (@arg NAME: +required "The new name")
+            (@arg WRITE: --write "Rewrite the files in place instead of listing the edits")
+        )
+        (@subcommand fmt =>
+            (about: "Formats the files of a design in place")
+            (@arg FILE: "The top level RHDL file")
+            (@arg WIDTH: --width +takes_value "The width to break lines at, 100 by default")
+            (@arg CHECK: --check "Report the files that aren't formatted instead of formatting them, for CI")
+        )
+        (@subcommand doc =>
+            (about: "Generates HTML documentation for the modules and items of a design")
+            (@arg FILE: "The top level RHDL file")
+            (@arg OUTPUT: -o --output +takes_value "The directory to write the pages to, doc by default")
+        )
+        (@subcommand minimize =>
+            (about: "Shrinks a design that makes rhdlc panic or report a diagnostic to a test case, removing items for as long as it still does")
+            (@arg FILE: +required "The top level RHDL file")
+            (@arg ERROR: --error +takes_value "A code or part of the message of the diagnostic to keep reporting, like E0428; the design has to make rhdlc panic when omitted")
+            (@arg OUTPUT: -o --output +takes_value "The directory to write the test case to, test/minimized/<name of the root file> by default")
+            (@arg TOP: --top +takes_value "The path of the entity to elaborate the design from")
+            (@arg EMIT: --emit +takes_value +multiple number_of_values(1) "Output to generate while compiling the design")
+        )
+        (@subcommand lsp =>
+            (about: "Runs a Language Server Protocol server over standard input and output for editors")
+            (@arg FILE: "The top level RHDL file; each open file is a root when omitted")
+        )
+    )
+    // clap_app! only names subcommands with identifiers
+    .subcommand(
+        clap::SubCommand::with_name("api-diff")
+            .about("Reports how the public API of a design changed since an old revision, classifying each change as breaking or additive")
+            .arg(clap::Arg::with_name("OLD").required(true).help("The top level RHDL file of the old revision"))
+            .arg(clap::Arg::with_name("FILE").required(true).help("The top level RHDL file of the new revision")),
+    )
+    .get_matches_safe()
+    .unwrap_or_else(|err| status::usage_error(err));
+    // -v counts before the subcommand as well as after it
+    let verbosity = app_matches
+        .subcommand()
+        .1
+        .map_or(0, |matches| matches.occurrences_of("VERBOSE"))
+        .max(app_matches.occurrences_of("VERBOSE"));
+    let quiet = app_matches.is_present("QUIET")
+        || app_matches
+            .subcommand()
+            .1
+            .map_or(false, |matches| matches.is_present("QUIET"));
+    logging::init(verbosity, quiet);
+
+    let cycles = |matches: &clap::ArgMatches, default: u64| {
+        matches.value_of("CYCLES").map_or(default, |cycles| {
+            cycles.parse::<u64>().unwrap_or_else(|err| {
+                status::invalid_value(&format!("invalid number of cycles `{}`: {}", cycles, err))
+            })
+        })
+    };
+    let depth = |matches: &clap::ArgMatches| {
+        matches.value_of("DEPTH").map_or(20, |depth| {
+            depth.parse::<u64>().unwrap_or_else(|err| {
+                status::invalid_value(&format!("invalid depth `{}`: {}", depth, err))
+            })
+        })
+    };
+    let seed = |matches: &clap::ArgMatches| {
+        matches.value_of("SEED").map(|seed| {
+            seed.parse::<u64>().unwrap_or_else(|err| {
+                status::invalid_value(&format!("invalid seed `{}`: {}", seed, err))
+            })
+        })
+    };
+    // the language server reads the config of each design it's asked about
+    let config = match app_matches.subcommand() {
+        ("lsp", _) => config::Config::default(),
+        (_, matches) => {
+            let matches = matches.unwrap_or(&app_matches);
+            if matches.is_present("NO_CONFIG") {
+                config::Config::default()
+            } else {
+                config::Config::find(&root_dir(matches))
+                    .unwrap_or_else(|msg| status::invalid_value(&msg))
+            }
+        }
+    };
+    let (matches, simulate, test, rename, format, doc, api_diff) = match app_matches.subcommand() {
+        ("sim", Some(matches)) => {
+            let simulate = sim::Simulate {
+                cycles: cycles(matches, 10),
+                output: matches.value_of("OUTPUT").map(Into::into),
+                backend: match matches.value_of("BACKEND") {
+                    Some("compiled") => sim::Backend::Compiled,
+                    _ => sim::Backend::Interpreted,
+                },
+                seed: seed(matches),
+                coverage: matches.value_of("COVERAGE").map(Into::into),
+            };
+            (matches, Some(simulate), None, None, None, None, None)
+        }
+        ("test", Some(matches)) => {
+            let test = sim::test::Test {
+                cycles: cycles(matches, 100),
+                output: matches.value_of("OUTPUT").map(Into::into),
+                vcd: matches.value_of("VCD").map(Into::into),
+                seed: seed(matches),
+                coverage: matches.value_of("COVERAGE").map(Into::into),
+                lcov: matches.value_of("LCOV").map(Into::into),
+            };
+            (matches, None, Some(test), None, None, None, None)
+        }
+        ("rename", Some(matches)) => {
+            let rename = resolution::Rename::new(
+                matches.value_of("AT").unwrap(),
+                matches.value_of("NAME").unwrap(),
+                matches.is_present("WRITE"),
+            )
+            .unwrap_or_else(|msg| status::invalid_value(&msg));
+            (matches, None, None, Some(rename), None, None, None)
+        }
+        ("fmt", Some(matches)) => {
+            let default_width = config.fmt_width.unwrap_or(100);
+            let format = fmt::Format {
+                width: matches.value_of("WIDTH").map_or(default_width, |width| {
+                    width.parse::<usize>().unwrap_or_else(|err| {
+                        status::invalid_value(&format!("invalid width `{}`: {}", width, err))
+                    })
+                }),
+                check: matches.is_present("CHECK"),
+            };
+            (matches, None, None, None, Some(format), None, None)
+        }
+        ("doc", Some(matches)) => {
+            let doc = doc::Doc {
+                output: matches.value_of("OUTPUT").unwrap_or("doc").into(),
+            };
+            (matches, None, None, None, None, Some(doc), None)
+        }
+        ("api-diff", Some(matches)) => {
+            let api_diff = api_diff::ApiDiff {
+                old: matches.value_of("OLD").unwrap().into(),
+            };
+            (matches, None, None, None, None, None, Some(api_diff))
+        }
+        ("prove", Some(matches)) | ("equiv", Some(matches)) | ("minimize", Some(matches)) => {
+            (matches, None, None, None, None, None, None)
+        }
+        ("lsp", Some(matches)) => {
+            if let Err(err) = lsp::serve(matches.value_of("FILE").map(Into::into)) {
+                eprintln!("error: {}", err);
+                status::Status::Io.exit();
+            }
+            return;
+        }
+        _ => (&app_matches, None, None, None, None, None, None),
+    };
+    let emit = match matches.values_of("EMIT") {
+        Some(values) => values
+            .map(|value| {
+                value
+                    .parse::<codegen::Emit>()
+                    .unwrap_or_else(|msg| status::invalid_value(&msg))
+            })
+            .collect(),
+        None => config.emit.clone(),
+    };
+    let formal = matches.value_of("FORMAL").map(|output| formal::Formal {
+        depth: depth(matches),
+        output: match output {
+            "-" => None,
+            path => Some(path.into()),
+        },
+    });
+    let prove = match app_matches.subcommand() {
+        ("prove", Some(matches)) => Some(formal::Prove {
+            depth: depth(matches),
+            output: matches.value_of("OUTPUT").map(Into::into),
+            vcd: matches.value_of("VCD").map(Into::into),
+        }),
+        _ => None,
+    };
+    let equiv = match app_matches.subcommand() {
+        ("equiv", Some(matches)) => Some(formal::Equiv {
+            arch_a: matches.value_of("IMPL_A").unwrap().to_string(),
+            arch_b: matches.value_of("IMPL_B").unwrap().to_string(),
+            depth: depth(matches),
+            output: matches.value_of("OUTPUT").map(Into::into),
+            vcd: matches.value_of("VCD").map(Into::into),
+        }),
+        _ => None,
+    };
+    let options = Options {
+        top: matches.value_of("TOP").map(str::to_string),
+        emit,
+        simulate,
+        test,
+        formal,
+        prove,
+        equiv,
+        rename,
+        format,
+        doc,
+        api_diff,
+        incremental: matches.value_of("INCREMENTAL").map(Into::into),
+        json_compilation_db: matches.value_of("JSON_COMPILATION_DB").map(Into::into),
+        time_passes: matches.is_present("TIME_PASSES"),
+        quiet,
+        summary: Some(start).filter(|_| !quiet),
+        color: !cfg!(feature = "no-color")
+            && match matches.value_of("COLOR").or_else(|| config.color.as_deref()) {
+                Some("always") => true,
+                Some("never") => false,
+                _ => env::var_os("NO_COLOR").is_none() && atty::is(atty::Stream::Stderr),
+            },
+        error_limit: matches.value_of("ERROR_LIMIT").map_or(Some(50), |limit| {
+            match limit.parse::<usize>() {
+                Ok(0) => None,
+                Ok(limit) => Some(limit),
+                Err(err) => {
+                    status::invalid_value(&format!("invalid error limit `{}`: {}", limit, err))
+                }
+            }
+        }),
+        verify_determinism: matches.is_present("VERIFY_DETERMINISM"),
+        render: render(matches, &config),
+        error_format: match (
+            matches.value_of("ERROR_FORMAT"),
+            matches.value_of("MESSAGE_FORMAT"),
+        ) {
+            (_, Some("json-stream")) => report::ErrorFormat::JsonStream,
+            (Some("sarif"), _) => report::ErrorFormat::Sarif,
+            (Some(_), _) | (_, Some(_)) => report::ErrorFormat::Human,
+            (None, None) => config.error_format.unwrap_or_default(),
+        },
+        events: Some(events::Events::stdout())
+            .filter(|_| matches.value_of("MESSAGE_FORMAT") == Some("json-stream")),
+        src_dir: matches.value_of("SRC_DIR").map(Into::into),
+        src_extension: matches
+            .value_of("SRC_EXT")
+            .map(|ext| ext.trim_start_matches('.').to_string()),
+        lossy_utf8: matches.is_present("LOSSY_UTF8"),
+        max_file_size: matches.value_of("MAX_FILE_SIZE").map(|size| {
+            size.parse().unwrap_or_else(|err| {
+                status::invalid_value(&format!("invalid file size `{}`: {}", size, err))
+            })
+        }),
+        max_files: matches.value_of("MAX_FILES").map(|count| {
+            count.parse().unwrap_or_else(|err| {
+                status::invalid_value(&format!("invalid number of files `{}`: {}", count, err))
+            })
+        }),
+        libraries: matches
+            .values_of("LIB")
+            .into_iter()
+            .flatten()
+            .map(|value| {
+                find_file::parse_library(value).unwrap_or_else(|msg| status::invalid_value(&msg))
+            })
+            .chain(config.libraries.iter().cloned())
+            .collect(),
+        library_path: find_file::rhdl_path()
+            .into_iter()
+            .chain(config.library_path.iter().cloned())
+            .collect(),
+        lint_levels: config.lints.clone(),
+        passes: Default::default(),
+        models: Default::default(),
+    };
+
+    if let ("minimize", Some(matches)) = app_matches.subcommand() {
+        let root = std::path::Path::new(matches.value_of("FILE").unwrap());
+        let minimize = minimize::Minimize {
+            condition: match matches.value_of("ERROR") {
+                Some(error) => minimize::Condition::Diagnostic(error.to_string()),
+                None => minimize::Condition::Panic,
+            },
+            output: matches.value_of("OUTPUT").map_or_else(
+                || {
+                    let name = root.file_stem().unwrap_or_default();
+                    std::path::Path::new("test").join("minimized").join(name)
+                },
+                Into::into,
+            ),
+        };
+        match minimize::minimize(root, &minimize, &options) {
+            Ok(report) => eprint!("{}", report),
+            Err((status, msg)) => {
+                eprintln!("error: {}", msg);
+                status.exit();
+            }
+        }
+        return;
+    }
+
+    let src = match matches.value_of("FILE") {
+        Some("-") | None => {
+            FileContentProvider::Reader("stdin".to_string(), Box::new(std::io::stdin()))
+        }
+        Some(path) => FileContentProvider::File(path.into()),
+    };
+    // CI checks formatting by the exit status, which unformatted files are errors for
+    let (output, status) = entry_with_status(src, &options);
+    eprint!("{}", output);
+    status.exit()
+}
+
+/// The directory of the root file, or the one its modules are found in when it's read from
+/// standard input
+#[cfg(not(any(feature = "fuzz", target_arch = "wasm32")))]
+fn root_dir(matches: &clap::ArgMatches) -> std::path::PathBuf {
+    match matches.value_of("FILE") {
+        Some("-") | None => matches.value_of("SRC_DIR").unwrap_or(".").into(),
+        Some(path) => std::path::Path::new(path)
+            .parent()
+            .map_or_else(|| ".".into(), Into::into),
+    }
+}
+
+/// The rendering of diagnostics in the config file, overridden by the command line
+#[cfg(not(any(feature = "fuzz", target_arch = "wasm32")))]
+fn render(matches: &clap::ArgMatches, config: &config::Config) -> report::Render {
+    let mut render = config.render.clone();
+    let count = |name: &str, what: &str| {
+        matches.value_of(name).map(|count| {
+            count.parse::<usize>().unwrap_or_else(|err| {
+                status::invalid_value(&format!("invalid {} `{}`: {}", what, count, err))
+            })
+        })
+    };
+    if let Some(width) = count("DIAGNOSTIC_WIDTH", "diagnostic width") {
+        render.width = Some(width);
+    }
+    if let Some(context_lines) = count("CONTEXT_LINES", "number of context lines") {
+        render.context_lines = Some(context_lines);
+    }
+    render.ascii |= matches.is_present("ASCII");
+    render.anonymize_paths |= matches.is_present("ANONYMIZE_PATHS");
+    render
+}
+
+/// The browser calls `playground::compile_str` instead
+#[cfg(all(target_arch = "wasm32", not(feature = "fuzz")))]
+pub fn main() {}
+
+#[cfg(feature = "fuzz")]
+#[macro_use]
+extern crate afl;
+
+#[cfg(feature = "fuzz")]
+pub fn main() {
+    if env::var_os("RHDLC_FUZZ").map_or(false, |target| target == "visibility") {
+        fuzz!(|data: &[u8]| fuzz::visibility(data));
+        return;
+    }
+    fuzz! {
+        |data: &[u8] | {
+            eprint!("{}", entry(FileContentProvider::Reader("fuzz".to_string(), Box::new(std::io::Cursor::new(Vec::from(data)))), &Options::default()))
+        }
+    }
+}
+
+fn entry(src: FileContentProvider, options: &Options) -> String {
+    entry_with_status(src, options).0
+}
+
+/// Like `entry`, along with the status to exit with
+fn entry_with_status(src: FileContentProvider, options: &Options) -> (String, status::Status) {
+    let _stream = events::stream(options.events.as_ref());
+    let mut timings = timing::Timings::default();
+    let (mut output, status) = run(src, options, &mut timings);
+    events::finished(status);
+    if options.time_passes {
+        output.push_str(&timings.report());
+    }
+    (output, status)
+}
+
+/// Compiles the design from `src` as `options` ask, returning the diagnostics and reports
+fn run(
+    src: FileContentProvider,
+    options: &Options,
+    timings: &mut timing::Timings,
+) -> (String, status::Status) {
+    let mut finder = FileFinder::default();
+    finder.src_dir = options.src_dir.clone();
+    finder.src_extension = options.src_extension.clone();
+    finder.lossy_utf8 = options.lossy_utf8;
+    finder.max_file_size = options.max_file_size;
+    finder.max_files = options.max_files;
+    finder.libraries = options.libraries.clone();
+    finder.library_path = options.library_path.clone();
+    // anything but checking needs the architectures that interfaces leave out
+    finder.interfaces = options.only_checks();
+    events::stage_started("find files");
+    finder.find_tree(src);
+    timings.append(&mut finder.timings);
+    for file in finder.file_graph.iter() {
+        if !matches!(
+            finder.file_graph[*file].provider,
+            FileContentProvider::Embedded(_)
+        ) {
+            events::file_discovered(&finder.file_graph.inner.name(*file).to_string_lossy());
+        }
+    }
+    sort_diagnostics(&mut finder.errors);
+
+    let mut reporter = report::Reporter::new(&finder.file_graph, options);
+    reporter.report(&finder.errors);
+
+    if let Some(path) = &options.json_compilation_db {
+        let mut errors = vec![];
+        compilation_db::write(&finder.file_graph, options, path, &mut errors);
+        reporter.report(&errors);
+    }
+
+    if let Some(format) = &options.format {
+        if !has_error_severity(&finder.errors) {
+            let mut errors = vec![];
+            events::stage_started("format");
+            fmt::format_files(&finder.file_graph, format, &mut errors);
+            reporter.report(&errors);
+        }
+        return reporter.finish();
+    }
+
+    let cache = options
+        .incremental
+        .as_ref()
+        .filter(|_| options.only_checks())
+        .map(|dir| incremental::Cache::new(dir, &finder.file_graph, options));
+    let mut reused = None;
+    match cache
+        .as_ref()
+        .and_then(|cache| cache.load(&finder.file_graph))
+    {
+        Some(incremental::Reuse::All(resolution, items)) => {
+            reporter.report(&resolution);
+            reporter.report(&items);
+            return reporter.finish();
+        }
+        Some(incremental::Reuse::Files(files, diagnostics)) => reused = Some((files, diagnostics)),
+        None => {}
+    }
+
+    events::stage_started("resolution");
+    let ctx = resolution::context();
+    let mut scope_builder = Resolver::build(&finder.file_graph, &ctx);
+    scope_builder.lint_levels = options.lint_levels.clone();
+    if let Some((files, _)) = &reused {
+        scope_builder.resolution_graph.reused_files = files.clone();
+    }
+    scope_builder.build_graph();
+    scope_builder.check_graph();
+    timings.append(&mut scope_builder.timings);
+    sort_diagnostics(&mut scope_builder.errors);
+    if options.verify_determinism {
+        verify_determinism(&scope_builder, &finder.file_graph, &ctx);
+    }
+    if let Some((files, mut diagnostics)) = reused {
+        // what's reported in the reused files is what was reported in them last time
+        scope_builder.errors.retain(|diagnostic| {
+            !incremental::primary_file(diagnostic).map_or(false, |file| files.contains(&file))
+        });
+        scope_builder.errors.append(&mut diagnostics);
+        sort_diagnostics(&mut scope_builder.errors);
+    }
+    reporter.report(&scope_builder.errors);
+
+    let has_errors =
+        has_error_severity(&finder.errors) || has_error_severity(&scope_builder.errors);
+    if let Some(doc) = &options.doc {
+        if !has_errors {
+            let mut errors = vec![];
+            events::stage_started("doc");
+            doc::document(&scope_builder, &finder.file_graph, doc, &mut errors);
+            reporter.report(&errors);
+        }
+        return reporter.finish();
+    }
+    if let Some(api_diff) = &options.api_diff {
+        if !has_errors {
+            match api_diff::api_at(&api_diff.old, options) {
+                Ok(old) => {
+                    let new = api_diff::Api::of(&scope_builder, &finder.file_graph);
+                    let mut errors = vec![];
+                    api_diff::report(&old, &new, &mut errors);
+                    reporter.report(&errors);
+                }
+                Err((old_output, old_status)) => {
+                    let (output, status) = reporter.finish();
+                    return (old_output + &output, old_status.max(status));
+                }
+            }
+        }
+        return reporter.finish();
+    }
+    let mut item_errors = vec![];
+    if !has_errors {
+        events::stage_started("item checks");
+        let start = Instant::now();
+        elaboration::enums::EncodingChecker {
+            resolver: &scope_builder,
+            errors: &mut item_errors,
+        }
+        .check();
+        analysis::synth::SynthesisLinter {
+            resolver: &scope_builder,
+            errors: &mut item_errors,
+        }
+        .check();
+        resolution::UnusedPubLinter {
+            resolver: &scope_builder,
+            errors: &mut item_errors,
+        }
+        .check();
+        timings.record("item checks", start);
+        options.passes.run(
+            &scope_builder,
+            &finder.file_graph,
+            timings,
+            &mut item_errors,
+        );
+        if !has_error_severity(&item_errors) {
+            for emit in options.emit.iter() {
+                codegen::emit_source(&scope_builder, &finder.file_graph, emit, &mut item_errors);
+            }
+        }
+        sort_diagnostics(&mut item_errors);
+        reporter.report(&item_errors);
+    }
+    let has_errors = has_errors || has_error_severity(&item_errors);
+    if let Some(cache) = &cache {
+        let mut errors = vec![];
+        cache.save(
+            &finder.file_graph,
+            &scope_builder,
+            &item_errors,
+            &mut errors,
+        );
+        reporter.report(&errors);
+    }
+    if let (Some(rename), false) = (&options.rename, has_errors) {
+        let mut errors = vec![];
+        scope_builder.rename_at(rename, &mut errors);
+        reporter.report(&errors);
+    } else if let (Some(test), false) = (&options.test, has_errors) {
+        let mut errors = vec![];
+        let mut report = sim::test::TestReport::default();
+        for testbench in sim::test::find_testbenches(&scope_builder) {
+            events::stage_started("elaboration");
+            let start = Instant::now();
+            let top = Some(testbench.name.as_str());
+            let compiled = compile(&scope_builder, top, None, true, &mut errors);
+            timings.record("elaboration", start);
+            events::stage_started("outputs");
+            let start = Instant::now();
+            match compiled {
+                Some((_, ir)) => report.run(
+                    &scope_builder,
+                    &ir,
+                    &options.models,
+                    &testbench,
+                    test,
+                    &mut errors,
+                ),
+                None => report.compile_failed(&testbench),
+            }
+            timings.record("outputs", start);
+        }
+        report.finish(test, &mut errors);
+        reporter.report(&errors);
+    } else if let (Some(equiv), false) = (&options.equiv, has_errors) {
+        let mut errors = vec![];
+        let top = options.top.as_deref();
+        events::stage_started("elaboration");
+        let start = Instant::now();
+        let (arch_a, arch_b) = (Some(equiv.arch_a.as_str()), Some(equiv.arch_b.as_str()));
+        let a = compile(&scope_builder, top, arch_a, true, &mut errors);
+        // what the architectures share is only reported once
+        let mut b_errors = vec![];
+        let b = compile(&scope_builder, top, arch_b, true, &mut b_errors);
+        for error in b_errors {
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+        timings.record("elaboration", start);
+        if let (Some((_, a)), Some((_, b))) = (a, b) {
+            events::stage_started("outputs");
+            let start = Instant::now();
+            formal::equiv(&a, &b, equiv, &mut errors);
+            timings.record("outputs", start);
+        }
+        sort_diagnostics(&mut errors);
+        reporter.report(&errors);
+    } else if !has_errors && (options.top.is_some() || options.needs_design()) {
+        let mut errors = vec![];
+        let top = options.top.as_deref();
+        events::stage_started("elaboration");
+        let start = Instant::now();
+        // implicit width changes are only found when the design is lowered
+        let lower = options.needs_design() || ir::lower::lints_widths(&scope_builder);
+        let compiled = compile(&scope_builder, top, None, lower, &mut errors);
+        timings.record("elaboration", start);
+        if let Some((design, ir)) = compiled {
+            events::stage_started("outputs");
+            let start = Instant::now();
+            let hierarchy =
+                codegen::hierarchy::Hierarchy::build(&scope_builder, &design, &finder.file_graph);
+            for emit in options.emit.iter() {
+                codegen::emit(&scope_builder, &ir, &hierarchy, emit, &mut errors);
+            }
+            if let Some(simulate) = &options.simulate {
+                sim::simulate(&scope_builder, &ir, &options.models, simulate, &mut errors);
+            }
+            if let Some(formal) = &options.formal {
+                formal::export(&ir, formal, &mut errors);
+            }
+            if let Some(prove) = &options.prove {
+                formal::prove(&scope_builder, &ir, prove, &mut errors);
+            }
+            timings.record("outputs", start);
+        }
+        sort_diagnostics(&mut errors);
+        reporter.report(&errors);
+    }
+
+    // #[cfg(not(test))]
+    // println!("{}", Dot::new(&scope_builder.resolution_graph));
+    reporter.finish()
+}
+
+/// Resolves the design again and asserts that the graph and diagnostics are the same as those of
+/// `resolver`
+fn verify_determinism(
+    resolver: &Resolver,
+    file_graph: &find_file::FileGraph,
+    ctx: &resolution::Context,
+) {
+    let mut again = Resolver::build(file_graph, ctx);
+    again.resolution_graph.reused_files = resolver.resolution_graph.reused_files.clone();
+    again.build_graph();
+    again.check_graph();
+    sort_diagnostics(&mut again.errors);
+    assert_eq!(
+        format!("{:#?}", resolver.resolution_graph),
+        format!("{:#?}", again.resolution_graph),
+        "the resolution graph differs between runs"
+    );
+    assert_eq!(
+        format!("{:#?}", resolver.errors),
+        format!("{:#?}", again.errors),
+        "the diagnostics differ between runs"
+    );
+}
+
+/// Elaborates and checks the design from `top`, with the architecture named `top_arch` if any,
+/// and if there are no errors and `lower` is set, lowers it to the intermediate representation,
+/// optimizes it and checks it for repeated logic, returned with the design it was lowered from
+fn compile<'ast>(
+    resolver: &Resolver<'ast>,
+    top: Option<&str>,
+    top_arch: Option<&str>,
+    lower: bool,
+    errors: &mut Vec<error::Diagnostic>,
+) -> Option<(Design<'ast>, ir::Ir<'ast>)> {
+    let error_count = errors.len();
+    let design = Elaborator {
+        resolver,
+        errors: &mut *errors,
+        top_arch,
+    }
+    .elaborate(top)?;
+    if has_error_severity(&errors[error_count..]) {
+        return None;
+    }
+    let graph = SignalGraph::build(resolver, &design);
+    analysis::check_design(resolver, &design, &graph, errors);
+    if !lower || has_error_severity(&errors[error_count..]) {
+        return None;
+    }
+    let mut ir = ir::lower::Lowerer {
+        resolver,
+        design: &design,
+        graph: &graph,
+        errors: &mut *errors,
+    }
+    .lower();
+    if !has_error_severity(&errors[error_count..]) {
+        ir::optimize::Optimizer {
+            resolver,
+            errors: &mut *errors,
+        }
+        .optimize(&mut ir);
+        analysis::sharing::SharingChecker {
+            resolver,
+            ir: &ir,
+            errors: &mut *errors,
+        }
+        .check();
+    }
+    if has_error_severity(&errors[error_count..]) {
+        None
+    } else {
+        Some((design, ir))
+    }
+}
+
+fn has_error_severity(diagnostics: &[error::Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity >= Severity::Error)
+}
+
+/// Sorts diagnostics by the file and span of their primary label, so they're reported in the
+/// order of the source instead of the order they were found in. Those without one come first.
+fn sort_diagnostics(diagnostics: &mut [error::Diagnostic]) {
+    diagnostics.sort_by_key(|diagnostic| {
+        diagnostic
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .map(|label| (label.file_id, label.range.start, label.range.end))
+    });
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn compile_fail_find_file() {
+        fail_test_looper("./test/compile-fail/find-file")
+    }
+
+    #[test]
+    fn compile_fail_resolution_use() {
+        fail_test_looper("./test/compile-fail/resolution/use")
+    }
+
+    #[test]
+    fn compile_fail_resolution_pub() {
+        fail_test_looper("./test/compile-fail/resolution/pub")
+    }
+
+    #[test]
+    fn compile_fail_resolution_conflicts() {
+        fail_test_looper("./test/compile-fail/resolution/conflicts")
+    }
+
+    /// Cases checked against the `//~` annotations in their files
+    #[test]
+    fn compile_fail_annotations() {
+        fail_test_looper("./test/compile-fail/annotations")
+    }
+
+    #[test]
+    fn compile_fail_resolution_type_existence() {
+        fail_test_looper("./test/compile-fail/resolution/type-existence")
+    }
+
+    #[test]
+    fn compile_fail_resolution_arch() {
+        fail_test_looper("./test/compile-fail/resolution/arch")
+    }
+
+    #[test]
+    fn compile_fail_resolution_pragma() {
+        fail_test_looper("./test/compile-fail/resolution/pragma")
+    }
+
+    /// TODO: consider allowing these identifiers at the parser level and blocking them during resolution
+    #[test]
+    fn compile_fail_identifier() {
+        fail_test_looper("./test/compile-fail/identifier")
+    }
+
+    #[test]
+    fn compile_fail_parse() {
+        fail_test_looper("./test/compile-fail/parse")
+    }
+
+    /// Splits a file into items without ending them at brackets in char literals
+    #[test]
+    fn recover_items() {
+        let content = "fn a() { let b = '}'; }\nconst C: u8 = '\\'';\nstruct D<'e> {}\n";
+        let items = crate::recover::items(content)
+            .into_iter()
+            .map(|item| content[item].trim())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                "fn a() { let b = '}'; }",
+                "const C: u8 = '\\'';",
+                "struct D<'e> {}"
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_fail_unsupported() {
+        fail_test_looper("./test/compile-fail/unsupported")
+    }
+
+    #[test]
+    fn compile_fail_elaboration() {
+        fail_test_looper_with_options("./test/compile-fail/elaboration", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_top() {
+        fail_test_looper_with_options(
+            "./test/compile-fail/top",
+            &crate::Options {
+                emit: vec![crate::codegen::Emit {
+                    kind: crate::codegen::EmitKind::Verilog,
+                    dir: Some(out_dir()),
+                }],
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn compile_fail_analysis_cdc() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/cdc", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_reset() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/reset", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_fsm() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/fsm", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_memory() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/memory", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_timing() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/timing", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_unread() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/unread", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_signedness() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/signedness", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_tristate() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/tristate", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_width() {
+        fail_test_looper_with_options("./test/compile-fail/analysis/width", &top_options())
+    }
+
+    #[test]
+    fn compile_fail_analysis_synthesizability() {
+        fail_test_looper("./test/compile-fail/analysis/synthesizability")
+    }
+
+    /// Options that emit Verilog, so the design is lowered to the intermediate representation
+    fn lowering_options() -> crate::Options {
+        crate::Options {
+            emit: vec![crate::codegen::Emit {
+                kind: crate::codegen::EmitKind::Verilog,
+                dir: Some(out_dir()),
+            }],
+            ..top_options()
+        }
+    }
+
+    /// Unreachable branches are found by optimizing the lowered design, which is only lowered for
+    /// an output
+    #[test]
+    fn compile_fail_optimize() {
+        fail_test_looper_with_options("./test/compile-fail/optimize", &lowering_options())
+    }
+
+    /// Repeated logic is found in the optimized design
+    #[test]
+    fn compile_fail_sharing() {
+        fail_test_looper_with_options("./test/compile-fail/sharing", &lowering_options())
+    }
+
+    /// A registered pass runs over each file of the design and reports to the diagnostics
+    #[test]
+    fn compile_fail_pass() {
+        use crate::pass::{FileContext, Pass};
+        use codespan_reporting::diagnostic::{Diagnostic, Label};
+        use rhdl::ast::{File, ItemFn, Spanned};
+        use rhdl::visit::Visit;
+
+        struct FnFinder<'ast> {
+            fns: Vec<&'ast ItemFn>,
+        }
+
+        impl<'ast> Visit<'ast> for FnFinder<'ast> {
+            fn visit_item_fn(&mut self, item_fn: &'ast ItemFn) {
+                self.fns.push(item_fn);
+            }
+        }
+
+        struct Fns;
+
+        impl Pass for Fns {
+            fn name(&self) -> &'static str {
+                "fns"
+            }
+
+            fn check_file<'ast>(&self, cx: &mut FileContext<'_, 'ast>, file: &'ast File) {
+                let mut finder = FnFinder { fns: vec![] };
+                finder.visit_file(file);
+                let module = cx.resolver.resolution_graph.path(cx.scope);
+                for item_fn in finder.fns {
+                    let ident = &item_fn.sig.ident;
+                    let message = match module.as_str() {
+                        "" => format!("`{}` is a fn", ident),
+                        module => format!("`{}` is a fn in `{}`", ident, module),
+                    };
+                    let file = cx.file;
+                    cx.report(
+                        Diagnostic::warning()
+                            .with_message(message)
+                            .with_labels(vec![Label::primary(file, ident.span())]),
+                    );
+                }
+            }
+        }
+
+        let mut options = crate::Options::default();
+        options.passes.register(Fns);
+        fail_test_looper_with_options("./test/compile-fail/pass", &options)
+    }
+
+    #[test]
+    fn codegen_verilog() {
+        codegen_test_looper("./test/codegen/verilog", crate::codegen::EmitKind::Verilog)
+    }
+
+    #[test]
+    fn codegen_netlist_json() {
+        codegen_test_looper(
+            "./test/codegen/netlist-json",
+            crate::codegen::EmitKind::NetlistJson,
+        )
+    }
+
+    #[test]
+    fn codegen_firrtl() {
+        codegen_test_looper("./test/codegen/firrtl", crate::codegen::EmitKind::Firrtl)
+    }
+
+    #[test]
+    fn codegen_fsm_dot() {
+        codegen_test_looper("./test/codegen/fsm-dot", crate::codegen::EmitKind::FsmDot)
+    }
+
+    #[test]
+    fn codegen_resources() {
+        codegen_test_looper("./test/codegen/resources", crate::codegen::EmitKind::Resources)
+    }
+
+    #[test]
+    fn codegen_timing_report() {
+        codegen_test_looper(
+            "./test/codegen/timing-report",
+            crate::codegen::EmitKind::TimingReport,
+        )
+    }
+
+    #[test]
+    fn codegen_sdc() {
+        codegen_test_looper("./test/codegen/sdc", crate::codegen::EmitKind::Sdc)
+    }
+
+    #[test]
+    fn codegen_hierarchy() {
+        codegen_test_looper("./test/codegen/hierarchy", crate::codegen::EmitKind::Hierarchy)
+    }
+
+    #[test]
+    fn codegen_hierarchy_json() {
+        codegen_test_looper(
+            "./test/codegen/hierarchy-json",
+            crate::codegen::EmitKind::HierarchyJson,
+        )
+    }
+
+    #[test]
+    fn codegen_instance_graph() {
+        codegen_test_looper(
+            "./test/codegen/instance-graph",
+            crate::codegen::EmitKind::InstanceGraph,
+        )
+    }
+
+    #[test]
+    fn codegen_instance_graph_json() {
+        codegen_test_looper(
+            "./test/codegen/instance-graph-json",
+            crate::codegen::EmitKind::InstanceGraphJson,
+        )
+    }
+
+    #[test]
+    fn codegen_symbols() {
+        codegen_test_looper("./test/codegen/symbols", crate::codegen::EmitKind::Symbols)
+    }
+
+    #[test]
+    fn codegen_exports() {
+        codegen_test_looper("./test/codegen/exports", crate::codegen::EmitKind::Exports)
+    }
+
+    #[test]
+    fn codegen_exports_json() {
+        codegen_test_looper(
+            "./test/codegen/exports-json",
+            crate::codegen::EmitKind::ExportsJson,
+        )
+    }
+
+    #[test]
+    fn codegen_interface() {
+        codegen_test_looper("./test/codegen/interface", crate::codegen::EmitKind::Interface)
+    }
+
+    #[test]
+    fn codegen_module_graph() {
+        codegen_test_looper(
+            "./test/codegen/module-graph",
+            crate::codegen::EmitKind::ModuleGraph,
+        )
+    }
+
+    #[test]
+    fn codegen_module_graph_json() {
+        codegen_test_looper(
+            "./test/codegen/module-graph-json",
+            crate::codegen::EmitKind::ModuleGraphJson,
+        )
+    }
+
+    /// Lists the outputs of each kind emitted to a directory in its manifest, with the entities
+    /// they were generated from and their fingerprints, and replaces those emitted before
+    #[test]
+    fn codegen_manifest() {
+        use crate::codegen::{interface::fingerprint, Emit, EmitKind, MANIFEST};
+        use std::fs;
+        let out_dir = out_dir();
+        let options = crate::Options {
+            top: Some("Top".to_string()),
+            emit: vec![
+                Emit {
+                    kind: EmitKind::Verilog,
+                    dir: Some(out_dir.clone()),
+                },
+                Emit {
+                    kind: EmitKind::Resources,
+                    dir: Some(out_dir.clone()),
+                },
+            ],
+            ..Default::default()
+        };
+        let root = "./test/codegen/verilog/counter/top.rhdl";
+        for _ in 0..2 {
+            let output = super::entry(
+                crate::find_file::FileContentProvider::File(root.into()),
+                &options,
+            );
+            assert_eq!("", output);
+        }
+        let manifest = fs::read_to_string(out_dir.join(MANIFEST)).unwrap();
+        let manifest = serde_json::from_str::<serde_json::Value>(&manifest).unwrap();
+        let artifacts = manifest["artifacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|artifact| {
+                let path = artifact["path"].as_str().unwrap();
+                let content = fs::read_to_string(out_dir.join(path)).unwrap();
+                assert_eq!(artifact["hash"], fingerprint(&content));
+                (
+                    artifact["kind"].as_str().unwrap(),
+                    path,
+                    artifact["entity"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("verilog", "Counter_WIDTH_8.v", "Counter"),
+                ("verilog", "Top.v", "Top"),
+                ("resources", "Top.txt", "Top"),
+            ],
+            artifacts
+        );
+    }
+
+    /// Only writes the outputs whose input or file changed since they were last emitted
+    #[test]
+    fn codegen_cache() {
+        use crate::codegen::{Emit, EmitKind};
+        use std::fs;
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let out_dir = out_dir();
+        let root = "./test/codegen/verilog/counter/top.rhdl";
+        let written = |emit: Vec<EmitKind>| {
+            let shared = Shared::default();
+            let options = crate::Options {
+                top: Some("Top".to_string()),
+                emit: emit
+                    .into_iter()
+                    .map(|kind| Emit {
+                        kind,
+                        dir: Some(out_dir.clone()),
+                    })
+                    .collect(),
+                events: Some(crate::events::Events::to(shared.clone())),
+                ..Default::default()
+            };
+            let output = super::entry(
+                crate::find_file::FileContentProvider::File(root.into()),
+                &options,
+            );
+            assert_eq!("", output);
+            let events = String::from_utf8(shared.0.lock().unwrap().clone()).unwrap();
+            events
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .filter(|event| event["reason"] == "artifact-written")
+                .map(|event| {
+                    let path = std::path::PathBuf::from(event["path"].as_str().unwrap());
+                    path.file_name().unwrap().to_string_lossy().to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["Counter_WIDTH_8.v", "Top.v", "manifest.json"],
+            written(vec![EmitKind::Verilog])
+        );
+        assert!(written(vec![EmitKind::Verilog]).is_empty());
+        fs::write(out_dir.join("Counter_WIDTH_8.v"), "// edited\n").unwrap();
+        assert_eq!(vec!["Counter_WIDTH_8.v"], written(vec![EmitKind::Verilog]));
+        assert_eq!(
+            vec!["Top.json", "manifest.json"],
+            written(vec![EmitKind::NetlistJson])
+        );
+        assert!(written(vec![EmitKind::Verilog, EmitKind::NetlistJson]).is_empty());
+    }
+
+    #[test]
+    fn sim() {
+        sim_test_looper("./test/sim")
+    }
+
+    /// Draws the random inputs of each case with the same seed
+    #[test]
+    fn sim_random() {
+        sim_test_looper("./test/sim-random")
+    }
+
+    /// Simulates each case twice, collecting coverage in the same directory, and compares the
+    /// report of both runs with `coverage.txt`
+    #[test]
+    fn sim_coverage() {
+        use std::fs;
+        run_cases("./test/sim-coverage", |case, out| {
+            let options = crate::Options {
+                top: Some("TestBench".to_string()),
+                simulate: Some(crate::sim::Simulate {
+                    cycles: 8,
+                    output: Some(out.join("report.txt")),
+                    backend: crate::sim::Backend::Interpreted,
+                    seed: Some(1),
+                    coverage: Some(out.to_path_buf()),
+                }),
+                ..Default::default()
+            };
+            for _ in 0..2 {
+                assert_expected(&case.join("expected.txt"), &compile(case, &options));
+            }
+            assert_expected(
+                &case.join("coverage.txt"),
+                &fs::read_to_string(out.join("coverage.txt")).unwrap(),
+            );
+            let json = fs::read_to_string(out.join("coverage.json")).unwrap();
+            let json = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+            assert_eq!(2, json["runs"]);
+        })
+    }
+
+    /// Tests the counter's testbenches with lcov and checks the hits of a clocked statement, a
+    /// combinational one, and the toggles of a clock
+    #[test]
+    fn test_lcov() {
+        use std::fs;
+        let lcov = out_dir().join("counter.info");
+        let options = crate::Options {
+            test: Some(crate::sim::test::Test {
+                cycles: 8,
+                output: Some(lcov.with_extension("txt")),
+                vcd: None,
+                seed: Some(1),
+                coverage: None,
+                lcov: Some(lcov.clone()),
+            }),
+            ..Default::default()
+        };
+        super::entry(
+            crate::find_file::FileContentProvider::File("./test/testbench/counter/top.rhdl".into()),
+            &options,
+        );
+        let lcov = fs::read_to_string(&lcov).unwrap();
+        let lines = lcov.lines().collect::<Vec<_>>();
+        assert_eq!(Some(&"SF:./test/testbench/counter/top.rhdl"), lines.first());
+        assert_eq!(Some(&"end_of_record"), lines.last());
+        // `step += 1` on each rising edge, and `rst_n = step != 0` at each check
+        assert!(lines.contains(&"DA:10,8"));
+        assert!(lines.contains(&"DA:12,9"));
+        // the rises and falls of `clk`
+        assert!(lines.contains(&"BRDA:1,26,0,8"));
+        assert!(lines.contains(&"BRDA:1,26,1,8"));
+    }
+
+    /// Simulates the same cases by compiling them, which has to report the same
+    #[test]
+    fn sim_compiled() {
+        sim_test_looper_with(
+            "./test/sim",
+            &Default::default(),
+            crate::sim::Backend::Compiled,
+        )
+    }
+
+    /// Simulates a black box with a model that multiplies its inputs and accumulates the products
+    /// on rising edges of its clock
+    #[test]
+    fn sim_models() {
+        use crate::sim::model::{Model, ModelContext, ModelInstance};
+        use crate::sim::Value;
+
+        struct Mac;
+
+        struct MacInstance {
+            sum: u128,
+        }
+
+        impl Model for Mac {
+            fn instantiate(&self, cx: &ModelContext) -> Box<dyn ModelInstance> {
+                assert_eq!("mac", cx.path);
+                assert_eq!(5, cx.ports.len());
+                Box::new(MacInstance { sum: 0 })
+            }
+        }
+
+        /// The product of the inputs `a` and `b`, if they're known
+        fn product(ports: &[Value]) -> Option<u128> {
+            let known = |value: &Value| match value {
+                Value::Int { bits, unknown: 0 } => Some(*bits),
+                _ => None,
+            };
+            Some((known(&ports[1])? * known(&ports[2])?) & 0xffff)
+        }
+
+        impl ModelInstance for MacInstance {
+            fn eval(&self, ports: &mut [Value]) {
+                ports[3] = product(ports).map_or(
+                    Value::Int {
+                        bits: 0,
+                        unknown: 0xffff,
+                    },
+                    Value::known,
+                );
+                ports[4] = Value::known(self.sum);
+            }
+
+            fn update(&mut self, before: &[Value], ports: &[Value]) -> bool {
+                let rising = before[0] == Value::known(0) && ports[0] == Value::known(1);
+                match product(before) {
+                    Some(product) if rising => {
+                        self.sum = (self.sum + product) & 0xffff;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+        }
+
+        let mut models = crate::sim::model::Models::default();
+        models.register("Mac", Mac);
+        sim_test_looper_with(
+            "./test/sim-models",
+            &models,
+            crate::sim::Backend::Interpreted,
+        )
+    }
+
+    #[test]
+    fn testbench() {
+        testbench_test_looper("./test/testbench")
+    }
+
+    #[test]
+    fn formal() {
+        formal_test_looper("./test/formal")
+    }
+
+    #[test]
+    fn prove() {
+        prove_test_looper("./test/prove")
+    }
+
+    #[test]
+    fn equiv() {
+        equiv_test_looper("./test/equiv")
+    }
+
+    #[test]
+    fn rename() {
+        rename_test_looper("./test/rename")
+    }
+
+    #[test]
+    fn fmt() {
+        fmt_test_looper("./test/fmt")
+    }
+
+    /// Documents a design and checks the pages for its modules, ports, and links
+    /// Converts offsets to one-based lines and columns, and names files by their absolute paths
+    #[test]
+    fn line_column() {
+        use crate::find_file::{FileContentProvider, FileFinder, LineColumn};
+        let mut finder = FileFinder::default();
+        finder.find_tree(FileContentProvider::File("./test/doc/top.rhdl".into()));
+        let file_graph = &finder.file_graph;
+        let top = file_graph.roots[0];
+        let offset = file_graph[top].as_ref().find("mod alu;").unwrap();
+        assert_eq!(
+            Some((
+                LineColumn { line: 3, column: 1 },
+                LineColumn { line: 3, column: 9 }
+            )),
+            file_graph.line_columns(top, offset..offset + "mod alu;".len())
+        );
+        assert_eq!(None, file_graph.line_column(top, usize::MAX));
+        assert_eq!(
+            Some(
+                std::env::current_dir()
+                    .unwrap()
+                    .join("test/doc/top.rhdl")
+                    .canonicalize()
+                    .unwrap()
+            ),
+            file_graph.absolute_path(top)
+        );
+    }
+
+    /// Attaches the doc comments of modules, items and fields to their nodes
+    #[test]
+    fn docs() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let mut finder = FileFinder::default();
+        finder.find_tree(FileContentProvider::File("./test/doc/top.rhdl".into()));
+        let ctx = crate::resolution::context();
+        let mut resolver = super::Resolver::build(&finder.file_graph, &ctx);
+        resolver.build_graph();
+        let graph = &resolver.resolution_graph;
+        let docs = |path: &str| {
+            graph
+                .node_indices()
+                .find(|node| graph[*node].name().is_some() && graph.path(*node) == path)
+                .and_then(|node| graph.docs(node))
+        };
+        assert_eq!(
+            Some("A small CPU for testing `rhdlc doc`."),
+            graph.docs(graph.roots[0])
+        );
+        assert_eq!(Some("Arithmetic and *logic*."), docs("alu"));
+        assert_eq!(Some("An operation of the ALU"), docs("alu::Op"));
+        assert_eq!(Some("Adds the operands"), docs("alu::Op::Add"));
+        assert_eq!(None, docs("alu::Op::Sub"));
+        assert_eq!(Some("The value stored"), docs("Register::value"));
+    }
+
+    /// Counts the edges logged with -v: from scopes to their children, then from uses to what
+    /// they import once they're traced
+    #[test]
+    fn edge_count() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let mut finder = FileFinder::default();
+        finder.find_tree(FileContentProvider::Reader(
+            "stdin".to_string(),
+            Box::new("mod a { pub struct B {} }\nuse a::B;\n".as_bytes()),
+        ));
+        let ctx = crate::resolution::context();
+        let mut resolver = super::Resolver::build(&finder.file_graph, &ctx);
+        let graph = &resolver.resolution_graph;
+        let children = graph.inner.len() - graph.roots.len();
+        assert_eq!(children, graph.edge_count());
+        resolver.build_graph();
+        assert_eq!(children + 1, resolver.resolution_graph.edge_count());
+    }
+
+    proptest::proptest! {
+        /// Importing an item by its canonical path, from the module it's declared in, imports
+        /// only that item
+        #[test]
+        fn canonical_paths_resolve_to_their_items(design in design()) {
+            let (items, _) = resolutions(&design.render(&|_| vec![]));
+            let probes = |module: Option<usize>| -> Vec<String> {
+                let prefix = design.canonical_path(module);
+                items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, item)| item.rsplitn(2, "::").nth(1) == Some(prefix.as_str()))
+                    .map(|(probe, item)| format!("use {} as Probe{};", item, probe))
+                    .collect()
+            };
+            let (_, uses) = resolutions(&design.render(&probes));
+            for (probe, item) in items.iter().enumerate() {
+                let module = item.rsplitn(2, "::").nth(1).unwrap();
+                let probe = format!("{}::Probe{}", module, probe)
+                    .trim_start_matches("crate::")
+                    .to_string();
+                proptest::prop_assert_eq!(Some(&vec![item.clone()]), uses.get(&probe));
+            }
+        }
+
+        /// Declaring an item with a new name doesn't change what any import resolves to
+        #[test]
+        fn unrelated_items_keep_resolutions(
+            design in design(),
+            imports in proptest::collection::vec((index(), index()), 0..8),
+            unrelated in index(),
+        ) {
+            let modules = design.modules();
+            let imports = imports
+                .iter()
+                .enumerate()
+                .map(|(import, (from, to))| {
+                    let to = to.index(design.items.len() + 1);
+                    let path = match to {
+                        0 => "crate::Missing".to_string(),
+                        _ => design.canonical_path(Some(to - 1)),
+                    };
+                    (*from.get(&modules), format!("use {} as Import{};", path, import))
+                })
+                .collect::<Vec<_>>();
+            let unrelated = *unrelated.get(&modules);
+            let render = |with_unrelated: bool| {
+                design.render(&|module| {
+                    imports
+                        .iter()
+                        .filter(|(from, _)| *from == module)
+                        .map(|(_, import)| import.clone())
+                        .chain(
+                            Some("struct Unrelated {}".to_string())
+                                .filter(|_| with_unrelated && unrelated == module),
+                        )
+                        .collect()
+                })
+            };
+            let (_, before) = resolutions(&render(false));
+            let (_, after) = resolutions(&render(true));
+            proptest::prop_assert_eq!(before, after);
+        }
+    }
+
+    /// Nested modules with structs, enums, functions, and traits in them, each declared in the
+    /// root or a module before it
+    #[derive(Debug, Clone)]
+    struct Design {
+        /// The module each item is in, or `None` for the root, and what kind of item it is
+        items: Vec<(Option<usize>, u8)>,
+    }
+
+    const MOD: u8 = 0;
+
+    fn index() -> impl proptest::strategy::Strategy<Value = proptest::sample::Index> {
+        proptest::prelude::any::<proptest::sample::Index>()
+    }
+
+    fn design() -> impl proptest::strategy::Strategy<Value = Design> {
+        use proptest::strategy::Strategy;
+        proptest::collection::vec((index(), 0..5u8), 0..12).prop_map(|raw| {
+            let mut design = Design { items: vec![] };
+            for (parent, kind) in raw {
+                let parent = *parent.get(&design.modules());
+                design.items.push((parent, kind));
+            }
+            design
+        })
+    }
+
+    impl Design {
+        /// The root and the modules, which are the places an item can be declared in
+        fn modules(&self) -> Vec<Option<usize>> {
+            std::iter::once(None)
+                .chain(
+                    self.items
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (_, kind))| *kind == MOD)
+                        .map(|(item, _)| Some(item)),
+                )
+                .collect()
+        }
+
+        fn name(&self, item: usize) -> String {
+            match self.items[item].1 {
+                MOD => format!("m{}", item),
+                _ => format!("I{}", item),
+            }
+        }
+
+        /// `crate` for the root, or the canonical path of an item
+        fn canonical_path(&self, item: Option<usize>) -> String {
+            match item {
+                None => "crate".to_string(),
+                Some(item) => format!(
+                    "{}::{}",
+                    self.canonical_path(self.items[item].0),
+                    self.name(item)
+                ),
+            }
+        }
+
+        /// The design, with the lines `extra` gives for each module at the end of it
+        fn render(&self, extra: &dyn Fn(Option<usize>) -> Vec<String>) -> String {
+            let mut source = String::new();
+            self.render_module(None, extra, &mut source);
+            source
+        }
+
+        fn render_module(
+            &self,
+            module: Option<usize>,
+            extra: &dyn Fn(Option<usize>) -> Vec<String>,
+            source: &mut String,
+        ) {
+            for (item, (parent, kind)) in self.items.iter().enumerate() {
+                if *parent != module {
+                    continue;
+                }
+                let name = self.name(item);
+                match *kind {
+                    MOD => {
+                        source.push_str(&format!("mod {} {{\n", name));
+                        self.render_module(Some(item), extra, source);
+                        source.push_str("}\n");
+                    }
+                    1 => source.push_str(&format!("struct {} {{}}\n", name)),
+                    2 => source.push_str(&format!("enum {} {{ A }}\n", name)),
+                    3 => source.push_str(&format!("fn {}() {{}}\n", name)),
+                    _ => source.push_str(&format!("trait {} {{}}\n", name)),
+                }
+            }
+            for line in extra(module) {
+                source.push_str(&line);
+                source.push('\n');
+            }
+        }
+    }
+
+    /// The canonical paths of the items of the design in `source`, and the canonical paths of
+    /// what each of its imports resolved to, by the path of the import
+    fn resolutions(source: &str) -> (Vec<String>, std::collections::BTreeMap<String, Vec<String>>) {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let mut finder = FileFinder::default();
+        finder.find_tree(FileContentProvider::Reader(
+            "stdin".to_string(),
+            Box::new(std::io::Cursor::new(source.to_string().into_bytes())),
+        ));
+        let ctx = crate::resolution::context();
+        let mut resolver = super::Resolver::build(&finder.file_graph, &ctx);
+        resolver.build_graph();
+        let graph = &resolver.resolution_graph;
+        let items = graph
+            .node_indices()
+            .filter(|node| !graph.is_extern(*node))
+            .filter_map(|node| graph.canonical_path(node))
+            .collect();
+        let uses = graph
+            .node_indices()
+            .filter(|node| !graph.is_extern(*node))
+            .filter_map(|node| {
+                let targets = graph.use_targets(node)?;
+                Some((
+                    graph.path(node),
+                    targets
+                        .iter()
+                        .map(|target| {
+                            graph
+                                .canonical_path(*target)
+                                .unwrap_or_else(|| graph.path(*target))
+                        })
+                        .collect(),
+                ))
+            })
+            .collect();
+        (items, uses)
+    }
+
+    /// Removes the items and files the diagnostic doesn't need, and refuses a design that doesn't
+    /// report it
+    #[test]
+    fn minimize() {
+        use crate::minimize::{minimize, Condition, Minimize};
+        use std::fs;
+        use std::path::Path;
+        let output = out_dir().join("minimize");
+        let root = Path::new("./test/minimize/top.rhdl");
+        let mut minimize_to = Minimize {
+            condition: Condition::Diagnostic("E0428".to_string()),
+            output: output.clone(),
+        };
+        let report = minimize(root, &minimize_to, &Default::default()).unwrap();
+        assert!(
+            report.starts_with("removed 3 items and 1 file, "),
+            "{}",
+            report
+        );
+        assert_eq!(
+            "fn a() {}\nfn a() {}\n",
+            fs::read_to_string(output.join("top.rhdl")).unwrap()
+        );
+        assert!(!output.join("b.rhdl").exists());
+
+        minimize_to.condition = Condition::Diagnostic("E0432".to_string());
+        minimize_to.output = output.join("again");
+        assert_eq!(
+            Err((
+                crate::status::Status::Usage,
+                "compiling ./test/minimize/top.rhdl doesn't report `E0432`".to_string()
+            )),
+            minimize(root, &minimize_to, &Default::default())
+        );
+    }
+
+    /// Checks a design that isn't in a file, like the browser does
+    #[test]
+    fn playground() {
+        let diagnostics: serde_json::Value =
+            serde_json::from_str(&crate::playground::compile_str("fn a() {}\nfn a() {}\n"))
+                .unwrap();
+        let diagnostics = diagnostics.as_array().unwrap();
+        assert_eq!(1, diagnostics.len(), "{:#?}", diagnostics);
+        assert_eq!("E0428", diagnostics[0]["code"]);
+        assert_eq!(1, diagnostics[0]["range"]["start"]["line"]);
+        assert_eq!("[]", crate::playground::compile_str("fn a() {}\n"));
+    }
+
+    /// Compiles a design through the C API, which reports like the command line
+    #[cfg(feature = "capi")]
+    #[test]
+    #[allow(unsafe_code)]
+    fn capi() {
+        use crate::capi::*;
+        use std::ffi::{CStr, CString};
+        let path = CString::new("test/compile-fail/resolution/conflicts/fn/top.rhdl").unwrap();
+        let name = CString::new("not a name").unwrap();
+        unsafe {
+            let options = rhdlc_options_new();
+            assert_eq!(0, rhdlc_options_set_error_limit(options, 0));
+            assert_eq!(
+                2,
+                rhdlc_options_add_library(options, name.as_ptr(), path.as_ptr())
+            );
+            let output = rhdlc_compile(path.as_ptr(), options);
+            rhdlc_options_free(options);
+            assert_eq!(1, rhdlc_output_status(output));
+            let diagnostics = CStr::from_ptr(rhdlc_output_diagnostics(output));
+            let diagnostics = diagnostics.to_str().unwrap();
+            assert!(diagnostics.contains("error[E0428]"), "{}", diagnostics);
+            rhdlc_output_free(output);
+        }
+    }
+
+    #[test]
+    fn doc() {
+        use std::fs;
+        let output = out_dir().join("doc");
+        let options = crate::Options {
+            doc: Some(crate::doc::Doc {
+                output: output.clone(),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            "",
+            super::entry(
+                crate::find_file::FileContentProvider::File("./test/doc/top.rhdl".into()),
+                &options,
+            )
+        );
+        let index = fs::read_to_string(output.join("index.html")).unwrap();
+        let alu = fs::read_to_string(output.join("alu.html")).unwrap();
+        assert!(output.join("style.css").exists());
+        for fragment in &[
+            "<p>A small CPU for testing <code>rhdlc doc</code>.</p>",
+            "<li><a href=\"alu.html\"><code>alu</code></a><p>Arithmetic and <em>logic</em>.</p>",
+            "<section id=\"entity.Core\">",
+            "<tr><td>in</td><td><code>op</code></td><td><code><a href=\"alu.html#enum.Op\">alu::Op</a></code></td><td><p>The operation to perform</p>\n</td></tr>",
+            "<tr><td>out</td><td><code>result</code></td><td><code><a href=\"index.html#struct.Register\">Register</a></code></td><td></td></tr>",
+            "<li><code>simple</code><p>The simplest implementation</p>\n</li>",
+            "<tr><td><code>op</code></td><td><code><a href=\"alu.html#enum.Op\">Op</a></code></td>",
+            "<pre><code>const WIDTH: u32 = 8",
+        ] {
+            assert!(index.contains(fragment), "{}\n{}", fragment, index);
+        }
+        for fragment in &[
+            "<title>mod alu</title>",
+            "<li><code>Add</code><p>Adds the operands</p>\n</li>",
+            "<section id=\"fn.add\">",
+        ] {
+            assert!(alu.contains(fragment), "{}\n{}", fragment, alu);
+        }
+    }
+
+    #[test]
+    fn compile_pass_resolution_use() {
+        success_test_looper("./test/compile-pass/resolution/use")
+    }
+
+    #[test]
+    fn compile_pass_resolution_graph() {
+        success_test_looper("./test/compile-pass/resolution/graph")
+    }
+
+    #[test]
+    fn compile_pass_resolution_type_existence() {
+        success_test_looper("./test/compile-pass/resolution/type-existence")
+    }
+
+    #[test]
+    fn compile_pass_elaboration() {
+        success_test_looper_with_options("./test/compile-pass/elaboration", &top_options())
+    }
+
+    #[test]
+    fn compile_pass_analysis() {
+        success_test_looper_with_options("./test/compile-pass/analysis", &top_options())
+    }
+
+    /// Elaborates a design made of the entities of the standard library
+    #[test]
+    fn compile_pass_std() {
+        success_test_looper_with_options("./test/compile-pass/std", &top_options())
+    }
+
+    /// Loads the standard library only for designs that name it and don't have their own
+    #[test]
+    fn std_on_demand() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let roots = |source: &'static str| {
+            let mut finder = FileFinder::default();
+            finder.find_tree(FileContentProvider::Reader(
+                "stdin".to_string(),
+                Box::new(source.as_bytes()),
+            ));
+            assert!(finder.errors.is_empty());
+            finder
+                .file_graph
+                .roots
+                .iter()
+                .map(|root| finder.file_graph.externs.get(root).cloned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![None], roots("struct a {}\n"));
+        assert_eq!(
+            vec![None, Some("std".to_string())],
+            roots("use std::width::BYTE;\n")
+        );
+        assert_eq!(vec![None], roots("mod std {}\nuse self::std;\n"));
+    }
+
+    /// Elaborates a design using a library in the library path, which uses the standard library,
+    /// and one registered by another name
+    #[test]
+    fn compile_pass_libraries() {
+        success_test_looper_with_options(
+            "./test/compile-pass/libraries",
+            &crate::Options {
+                libraries: vec![("codes".to_string(), "./test/libraries/gray".into())],
+                library_path: vec!["./test/libraries".into()],
+                ..top_options()
+            },
+        )
+    }
+
+    /// Registered libraries shadow those in the library path by the same name, and are compiled
+    /// from their interface while it's up to date if interfaces are used
+    #[test]
+    fn library_precedence() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        for (interfaces, root) in [(true, "lib.rhdli"), (false, "lib.rhdl")].iter() {
+            let mut finder = FileFinder::default();
+            finder.libraries = vec![("uart".to_string(), "./test/libraries/gray".into())];
+            finder.library_path = vec!["./test/libraries".into()];
+            finder.interfaces = *interfaces;
+            finder.find_tree(FileContentProvider::Reader(
+                "stdin".to_string(),
+                Box::new("use uart::POINTER_WIDTH;\n".as_bytes()),
+            ));
+            assert!(finder.errors.is_empty());
+            let file_graph = &finder.file_graph;
+            assert_eq!(2, file_graph.roots.len());
+            let library = file_graph.roots[1];
+            assert_eq!(Some(&"uart".to_string()), file_graph.externs.get(&library));
+            assert_eq!(
+                std::path::Path::new("./test/libraries/gray").join(root).as_os_str(),
+                file_graph.inner.name(library)
+            );
+        }
+        assert_eq!(
+            Ok(("uart".to_string(), "lib/uart".into())),
+            crate::find_file::parse_library("uart=lib/uart")
+        );
+        assert!(crate::find_file::parse_library("lib/uart").is_err());
+        assert!(crate::find_file::parse_library("1uart=lib/uart").is_err());
+    }
+
+    /// Compiles the sources of a library instead of its interface once they've changed
+    #[test]
+    fn stale_interface() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let mut finder = FileFinder::default();
+        finder.libraries = vec![("stale".to_string(), "./test/interface/stale".into())];
+        finder.interfaces = true;
+        finder.find_tree(FileContentProvider::Reader(
+            "stdin".to_string(),
+            Box::new("use stale::A;\n".as_bytes()),
+        ));
+        assert_eq!(1, finder.errors.len());
+        assert_eq!(
+            "the interface ./test/interface/stale/lib.rhdli of `stale` is out of date",
+            finder.errors[0].message
+        );
+        let file_graph = &finder.file_graph;
+        assert_eq!(
+            std::path::Path::new("./test/interface/stale/lib.rhdl").as_os_str(),
+            file_graph.inner.name(file_graph.roots[1])
+        );
+    }
+
+    #[test]
+    fn compile_pass_stdin() {
+        let output = super::entry(
+            crate::find_file::FileContentProvider::Reader(
+                "string".to_string(),
+                Box::new("struct a {}".as_bytes()),
+            ),
+            &Default::default(),
+        );
+        assert_eq!("", output);
+    }
+
+    /// Reads the config of the project with its paths relative to it, and sets the levels of
+    /// lints from it
+    #[test]
+    fn config() {
+        use crate::attribute::LintLevel;
+        use crate::config::Config;
+        use std::path::Path;
+        let config = Config::find(Path::new("test/config")).unwrap();
+        let dir = crate::find_file::absolute(Path::new("test/config"));
+        assert_eq!(
+            vec![crate::codegen::Emit {
+                kind: crate::codegen::EmitKind::Verilog,
+                dir: Some(dir.join("build")),
+            }],
+            config.emit
+        );
+        assert_eq!(Some("never".to_string()), config.color);
+        assert_eq!(Some(crate::report::ErrorFormat::Sarif), config.error_format);
+        assert_eq!(
+            vec![("codes".to_string(), dir.join("../libraries/gray"))],
+            config.libraries
+        );
+        assert_eq!(vec![dir.join("../libraries")], config.library_path);
+        assert_eq!(Some(&LintLevel::Deny), config.lints.get("unused_pub"));
+        assert_eq!(Some(80), config.fmt_width);
+        assert_eq!(
+            crate::report::Render {
+                width: Some(100),
+                ascii: true,
+                ..Default::default()
+            },
+            config.render
+        );
+
+        let lint = |options: &super::Options| {
+            super::entry(
+                crate::find_file::FileContentProvider::File("test/config/top.rhdl".into()),
+                options,
+            )
+        };
+        assert!(lint(&Default::default())
+            .starts_with("warning[unused_pub]: `B` is `pub` but isn't used outside of `a`"));
+        let options = super::Options {
+            lint_levels: config.lints,
+            ..Default::default()
+        };
+        assert!(lint(&options)
+            .starts_with("error[unused_pub]: `B` is `pub` but isn't used outside of `a`"));
+
+        let invalid = |content: &str| Config::parse(content, Path::new(".")).unwrap_err();
+        assert_eq!(
+            "`color` isn't one of: auto, always, never",
+            invalid("color = \"sometimes\"")
+        );
+        assert_eq!(
+            "`lints.unused_pub` isn't one of: allow, warn, deny",
+            invalid("[lints]\nunused_pub = \"forbid\"")
+        );
+        assert_eq!("`fmt.width` isn't a positive number", invalid("[fmt]\nwidth = 0"));
+        assert_eq!(
+            "`diagnostics.ascii` isn't true or false",
+            invalid("[diagnostics]\nascii = \"yes\"")
+        );
+    }
+
+    /// Lists each file of the design with the command for its root and the flags that find them
+    #[test]
+    fn json_compilation_db() {
+        use std::fs;
+        let db = out_dir().join("compile_commands.json");
+        let options = super::Options {
+            json_compilation_db: Some(db.clone()),
+            max_files: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            "",
+            super::entry(
+                crate::find_file::FileContentProvider::File("test/lsp/modules/top.rhdl".into()),
+                &options,
+            )
+        );
+        let entries: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&db).unwrap()).unwrap();
+        let entries = entries.as_array().unwrap();
+        let top = crate::find_file::absolute("test/lsp/modules/top.rhdl".as_ref());
+        let files = entries
+            .iter()
+            .map(|entry| entry["file"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                top.to_string_lossy().to_string(),
+                crate::find_file::absolute("test/lsp/modules/a.rhdl".as_ref())
+                    .to_string_lossy()
+                    .to_string(),
+            ],
+            files
+        );
+        for entry in entries {
+            assert_eq!(
+                serde_json::json!(["rhdlc", top.to_string_lossy(), "--max-files", "5"]),
+                entry["arguments"]
+            );
+        }
+    }
+
+    /// Finds the modules of a root read from a reader in the source directory, which they aren't
+    /// found without
+    #[test]
+    fn compile_pass_stdin_src_dir() {
+        let dir = std::path::Path::new("test/lsp/modules");
+        let stdin = |options: &super::Options| {
+            super::entry(
+                crate::find_file::FileContentProvider::Reader(
+                    "stdin".to_string(),
+                    Box::new(std::fs::File::open(dir.join("top.rhdl")).unwrap()),
+                ),
+                options,
+            )
+        };
+        assert_ne!("", stdin(&Default::default()));
+        let options = super::Options {
+            src_dir: Some(dir.to_path_buf()),
+            ..Default::default()
+        };
+        assert_eq!("", stdin(&options));
+    }
+
+    /// Keeps the items of a file with a syntax error that did parse, and marks the scope they're in
+    /// incomplete only when tolerant
+    #[test]
+    fn tolerant() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let find = |tolerant: bool| {
+            let mut finder = FileFinder::default();
+            finder.tolerant = tolerant;
+            finder.find_tree(FileContentProvider::Reader(
+                "stdin".to_string(),
+                Box::new("struct a {}\nksldjflk;\nstruct b {}\n".as_bytes()),
+            ));
+            assert_eq!(1, finder.errors.len());
+            finder.file_graph
+        };
+        for tolerant in [false, true].iter() {
+            let file_graph = find(*tolerant);
+            let ctx = crate::resolution::context();
+            let mut resolver = super::Resolver::build(&file_graph, &ctx);
+            resolver.build_graph();
+            let graph = &resolver.resolution_graph;
+            let root = graph.roots[0];
+            assert_eq!(*tolerant, graph.is_incomplete(root));
+            for name in ["a", "b"].iter() {
+                let name = graph.names.get(name).unwrap();
+                assert!(graph[root].children().unwrap().contains_key(&Some(name)));
+            }
+        }
+    }
+
+    /// Skips a byte order mark, and replaces invalid UTF-8 only when lossy
+    #[test]
+    fn bom_and_lossy_utf8() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let find = |content: &'static [u8], lossy_utf8: bool| {
+            let mut finder = FileFinder::default();
+            finder.lossy_utf8 = lossy_utf8;
+            finder.find_tree(FileContentProvider::Reader(
+                "stdin".to_string(),
+                Box::new(content),
+            ));
+            finder
+        };
+        let finder = find(b"\xEF\xBB\xBFstruct a {}\n", false);
+        assert!(finder.errors.is_empty());
+        let root = finder.file_graph.roots[0];
+        assert_eq!("struct a {}\n", finder.file_graph[root].as_ref());
+
+        let invalid = b"struct a {}\n// \xFF\n";
+        let finder = find(invalid, false);
+        assert_eq!(1, finder.errors.len());
+        assert!(finder.errors[0]
+            .message
+            .ends_with("invalid byte sequence at byte 15"));
+
+        let finder = find(invalid, true);
+        assert_eq!(1, finder.errors.len());
+        assert_eq!(super::Severity::Warning, finder.errors[0].severity);
+        let root = finder.file_graph.roots[0];
+        assert!(finder.file_graph[root].parsed.is_some());
+    }
+
+    /// Leaves out files larger than the limit, and the modules past the limit on files
+    #[test]
+    fn file_limits() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let mut finder = FileFinder::default();
+        finder.max_file_size = Some(4);
+        finder.find_tree(FileContentProvider::Reader(
+            "stdin".to_string(),
+            Box::new("struct a {}\n".as_bytes()),
+        ));
+        assert_eq!(1, finder.errors.len());
+        assert!(finder.errors[0]
+            .message
+            .ends_with("is larger than the limit of 4 bytes"));
+
+        let mut finder = FileFinder::default();
+        finder.max_files = Some(1);
+        finder.find_tree(FileContentProvider::File(
+            "test/lsp/modules/top.rhdl".into(),
+        ));
+        assert_eq!(1, finder.file_graph.iter().count());
+        assert_eq!(1, finder.errors.len());
+    }
+
+    /// Compares the public API of `old` with `new` in each directory with `expected.txt`
+    #[test]
+    fn api_diff() {
+        use std::fs;
+        for test in fs::read_dir("./test/api-diff").unwrap() {
+            let dir = test.unwrap().path();
+            let api = |revision: &str| {
+                crate::api_diff::api_at(&dir.join(revision).join("top.rhdl"), &Default::default())
+                    .unwrap_or_else(|(output, _)| panic!("{}", output))
+            };
+            let changes = crate::api_diff::diff(&api("old"), &api("new"))
+                .iter()
+                .map(|change| format!("{}\n", change))
+                .collect::<String>();
+            eprintln!("{}", dir.to_string_lossy());
+            assert_expected(&dir.join("expected.txt"), &changes);
+        }
+    }
+
+    /// Breaks a design by opening one of its modules with different contents, then fixes it,
+    /// and checks the diagnostics published for its root
+    #[test]
+    fn lsp_diagnostics() {
+        use pretty_assertions::assert_eq;
+        use serde_json::json;
+        let dir = std::env::current_dir().unwrap().join("test/lsp/modules");
+        let top = crate::lsp::position::path_to_uri(&dir.join("top.rhdl"));
+        let a = crate::lsp::position::path_to_uri(&dir.join("a.rhdl"));
+        let messages = lsp_session(
+            dir.join("top.rhdl"),
+            vec![
+                json!({
+                    "method": "textDocument/didOpen",
+                    "params": {
+                        "textDocument": { "uri": a, "version": 1, "text": "pub struct c {}\n" },
+                    },
+                }),
+                json!({
+                    "method": "textDocument/didChange",
+                    "params": {
+                        "textDocument": { "uri": a, "version": 2 },
+                        "contentChanges": [{ "text": "pub struct b {}\n" }],
+                    },
+                }),
+            ],
+        );
+        assert_eq!(4, messages.len());
+        assert_eq!(
+            json!({
+                "uri": top,
+                "diagnostics": [{
+                    "range": {
+                        "start": { "line": 1, "character": 7 },
+                        "end": { "line": 1, "character": 8 },
+                    },
+                    "severity": 1,
+                    "code": "E0425",
+                    "source": "rhdlc",
+                    "message": "unresolved item `b`",
+                    "relatedInformation": [{
+                        "location": {
+                            "uri": top,
+                            "range": {
+                                "start": { "line": 1, "character": 7 },
+                                "end": { "line": 1, "character": 8 },
+                            },
+                        },
+                        "message": "no `b` item in `a`",
+                    }],
+                }],
+            }),
+            messages[1]["params"]
+        );
+        assert_eq!(json!({ "uri": top, "diagnostics": [] }), messages[2]["params"]);
+    }
+
+    /// Goes to the definition of an imported struct in another file
+    #[test]
+    fn lsp_definition() {
+        use pretty_assertions::assert_eq;
+        use serde_json::json;
+        let dir = std::env::current_dir().unwrap().join("test/lsp/modules");
+        let top = crate::lsp::position::path_to_uri(&dir.join("top.rhdl"));
+        let a = crate::lsp::position::path_to_uri(&dir.join("a.rhdl"));
+        let messages = lsp_session(
+            dir.join("top.rhdl"),
+            vec![
+                json!({
+                    "method": "textDocument/didOpen",
+                    "params": {
+                        "textDocument": {
+                            "uri": top,
+                            "version": 1,
+                            "text": "mod a;\nuse a::b;\n",
+                        },
+                    },
+                }),
+                json!({
+                    "id": 3,
+                    "method": "textDocument/definition",
+                    "params": {
+                        "textDocument": { "uri": top },
+                        "position": { "line": 1, "character": 7 },
+                    },
+                }),
+            ],
+        );
+        assert_eq!(
+            json!([{
+                "uri": a,
+                "range": {
+                    "start": { "line": 0, "character": 11 },
+                    "end": { "line": 0, "character": 12 },
+                },
+            }]),
+            messages[messages.len() - 2]["result"]
+        );
+    }
+
+    /// Renames an item from a use of it in another file
+    #[test]
+    fn lsp_rename() {
+        use pretty_assertions::assert_eq;
+        use serde_json::json;
+        let dir = std::env::current_dir().unwrap().join("test/lsp/modules");
+        let top = crate::lsp::position::path_to_uri(&dir.join("top.rhdl"));
+        let a = crate::lsp::position::path_to_uri(&dir.join("a.rhdl"));
+        let messages = lsp_session(
+            dir.join("top.rhdl"),
+            vec![
+                json!({
+                    "method": "textDocument/didOpen",
+                    "params": {
+                        "textDocument": {
+                            "uri": top,
+                            "version": 1,
+                            "text": "mod a;\nuse a::b;\n",
+                        },
+                    },
+                }),
+                json!({
+                    "id": 3,
+                    "method": "textDocument/rename",
+                    "params": {
+                        "textDocument": { "uri": top },
+                        "position": { "line": 1, "character": 7 },
+                        "newName": "c",
+                    },
+                }),
+            ],
+        );
+        let range = |line, character| {
+            json!({
+                "start": { "line": line, "character": character },
+                "end": { "line": line, "character": character + 1 },
+            })
+        };
+        assert_eq!(
+            json!({
+                "changes": {
+                    a: [{ "range": range(0, 11), "newText": "c" }],
+                    top: [{ "range": range(1, 7), "newText": "c" }],
+                },
+            }),
+            messages[messages.len() - 2]["result"]
+        );
+    }
+
+    /// Describes an entity from where it's instantiated, with its doc comments
+    #[test]
+    fn lsp_hover() {
+        use pretty_assertions::assert_eq;
+        use serde_json::json;
+        let top = std::env::current_dir().unwrap().join("test/lsp/hover/top.rhdl");
+        let uri = crate::lsp::position::path_to_uri(&top);
+        let messages = lsp_session(
+            top.clone(),
+            vec![
+                json!({
+                    "method": "textDocument/didOpen",
+                    "params": {
+                        "textDocument": {
+                            "uri": uri,
+                            "version": 1,
+                            "text": std::fs::read_to_string(&top).unwrap(),
+                        },
+                    },
+                }),
+                json!({
+                    "id": 3,
+                    "method": "textDocument/hover",
+                    "params": {
+                        "textDocument": { "uri": uri },
+                        "position": { "line": 14, "character": 22 },
+                    },
+                }),
+            ],
+        );
+        assert_eq!(
+            json!({
+                "contents": {
+                    "kind": "markdown",
+                    "value": "```rhdl\nentity Counter { in clk: bool, out count: u8 }\n```\n\n\
+                        entity `Counter`\n\n---\n\n\
+                        Counts the rising edges of `clk`,\nwrapping around when it overflows.",
+                },
+            }),
+            messages[messages.len() - 2]["result"]
+        );
+    }
+
+    /// Finds a design again after an edit to one of its files, which is the only one parsed again,
+    /// and then again without one, which reuses its diagnostics
+    #[test]
+    fn incremental() {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        use crate::query::{Database, Stats};
+        use pretty_assertions::assert_eq;
+        let top = std::env::current_dir().unwrap().join("test/doc/top.rhdl");
+        let mut database = Database::default();
+        let mut run = |overlay: &[(&std::path::Path, String)]| {
+            database.new_revision();
+            let mut finder = FileFinder::default();
+            finder.overlay = overlay
+                .iter()
+                .map(|(path, content)| (path.to_path_buf(), content.clone()))
+                .collect();
+            finder.database = Some(std::mem::take(&mut database));
+            finder.find_tree(FileContentProvider::File(top.clone()));
+            database = finder.database.take().unwrap();
+            assert!(finder.errors.is_empty());
+            database.check(&finder.file_graph, Vec::new);
+            database.stats
+        };
+        let edited = std::fs::read_to_string(&top).unwrap() + "\nconst DEPTH: u32 = 4;\n";
+        assert_eq!(
+            Stats {
+                parsed: 2,
+                checked: 1,
+                ..Default::default()
+            },
+            run(&[])
+        );
+        assert_eq!(
+            Stats {
+                parsed: 1,
+                parses_reused: 1,
+                checked: 1,
+                ..Default::default()
+            },
+            run(&[(&top, edited.clone())])
+        );
+        assert_eq!(
+            Stats {
+                parses_reused: 2,
+                checks_reused: 1,
+                ..Default::default()
+            },
+            run(&[(&top, edited)])
+        );
+    }
+
+    /// Checks a design with `--incremental`, replays the saved diagnostics while its file doesn't
+    /// change, and checks it again once it does
+    #[test]
+    fn incremental_cache() {
+        use pretty_assertions::assert_eq;
+        use std::fs;
+        let dir = out_dir();
+        let top = dir.join("top.rhdl");
+        fs::copy("./test/compile-fail/resolution/arch/duplicate-name/top.rhdl", &top).unwrap();
+        let options = crate::Options {
+            incremental: Some(dir.join("cache")),
+            ..Default::default()
+        };
+        let run = || {
+            super::entry(
+                crate::find_file::FileContentProvider::File(top.clone()),
+                &options,
+            )
+        };
+        let checked = run();
+        assert!(checked.contains("is defined multiple times"), "{}", checked);
+
+        // mark the saved diagnostic to tell that it's replayed
+        let cached = fs::read_dir(dir.join("cache"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let saved = fs::read_to_string(&cached).unwrap();
+        fs::write(&cached, saved.replace("multiple times", "multiple times again")).unwrap();
+        assert_eq!(checked.replace("multiple times", "multiple times again"), run());
+
+        fs::write(&top, "entity a {}\n#[name = \"rtl\"] arch a {}\n").unwrap();
+        assert_eq!("", run());
+    }
+
+    /// Doesn't replay the diagnostics saved by a run with other lint levels
+    #[test]
+    fn incremental_cache_options() {
+        use crate::attribute::LintLevel;
+        use pretty_assertions::assert_eq;
+        use std::fs;
+        let dir = out_dir();
+        let top = dir.join("top.rhdl");
+        fs::copy(
+            "./test/compile-fail/resolution/arch/duplicate-name/top.rhdl",
+            &top,
+        )
+        .unwrap();
+        let mut options = crate::Options {
+            incremental: Some(dir.join("cache")),
+            ..Default::default()
+        };
+        let run = |options: &crate::Options| {
+            super::entry(
+                crate::find_file::FileContentProvider::File(top.clone()),
+                options,
+            )
+        };
+        let checked = run(&options);
+        let cached = fs::read_dir(dir.join("cache"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let saved = fs::read_to_string(&cached).unwrap();
+        fs::write(
+            &cached,
+            saved.replace("multiple times", "multiple times again"),
+        )
+        .unwrap();
+        options
+            .lint_levels
+            .insert("unused_pub".to_string(), LintLevel::Deny);
+        assert_eq!(checked, run(&options));
+    }
+
+    /// Replays the diagnostics of the files of a design that are unchanged along with the files
+    /// they depend on, and checks the others again
+    #[test]
+    fn incremental_cache_files() {
+        use std::fs;
+        let dir = out_dir();
+        fs::write(dir.join("top.rhdl"), "mod a;\nmod c;\nmod e;\n").unwrap();
+        fs::write(dir.join("a.rhdl"), "pub struct b {}\n").unwrap();
+        fs::write(dir.join("c.rhdl"), "use crate::a::{b, b};\n").unwrap();
+        fs::write(dir.join("e.rhdl"), "pub struct f {}\n").unwrap();
+        let options = crate::Options {
+            incremental: Some(dir.join("cache")),
+            ..Default::default()
+        };
+        let run = || {
+            super::entry(
+                crate::find_file::FileContentProvider::File(dir.join("top.rhdl")),
+                &options,
+            )
+        };
+        let checked = run();
+        assert!(checked.contains("imported multiple times"), "{}", checked);
+
+        // mark the saved diagnostic to tell that it's replayed
+        let cached = fs::read_dir(dir.join("cache"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let saved = fs::read_to_string(&cached).unwrap();
+        fs::write(
+            &cached,
+            saved.replace("multiple times", "multiple times again"),
+        )
+        .unwrap();
+
+        // `c` doesn't depend on `e`, but it does on `a`
+        fs::write(dir.join("e.rhdl"), "pub struct f {}\npub struct g {}\n").unwrap();
+        let replayed = run();
+        assert!(
+            replayed.contains("imported multiple times again"),
+            "{}",
+            replayed
+        );
+        fs::write(dir.join("a.rhdl"), "pub struct b {}\npub struct d {}\n").unwrap();
+        let checked = run();
+        assert!(!checked.contains("again"), "{}", checked);
+        assert!(checked.contains("imported multiple times"), "{}", checked);
+    }
+
+    #[test]
+    fn verify_determinism() {
+        let options = crate::Options {
+            verify_determinism: true,
+            ..Default::default()
+        };
+        fail_test_looper_with_options("./test/compile-fail/resolution/conflicts", &options);
+        success_test_looper_with_options("./test/compile-pass/resolution/use", &options);
+    }
+
+    #[test]
+    fn color() {
+        let options = crate::Options {
+            color: true,
+            ..Default::default()
+        };
+        let output = super::entry(
+            crate::find_file::FileContentProvider::File(
+                "./test/compile-fail/resolution/use/no-name/top.rhdl".into(),
+            ),
+            &options,
+        );
+        assert!(output.contains("\u{1b}["), "{}", output);
+        // without the escape codes, it's the same as without color
+        let mut uncolored = String::new();
+        let mut chars = output.chars();
+        while let Some(c) = chars.next() {
+            if c == '\u{1b}' {
+                chars.by_ref().find(|c| *c == 'm');
+            } else {
+                uncolored.push(c);
+            }
+        }
+        assert_eq!(
+            std::fs::read_to_string("./test/compile-fail/resolution/use/no-name/expected.txt")
+                .unwrap(),
+            uncolored
+        );
+    }
+
+    #[test]
+    fn render() {
+        let options = crate::Options {
+            render: crate::report::Render {
+                ascii: true,
+                anonymize_paths: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let output = super::entry(
+            crate::find_file::FileContentProvider::File(
+                "./test/compile-fail/resolution/use/no-name/top.rhdl".into(),
+            ),
+            &options,
+        );
+        assert!(output.is_ascii(), "{}", output);
+        assert!(output.contains(" top.rhdl:3:8"), "{}", output);
+        assert!(!output.contains("./test"), "{}", output);
+    }
+
+    #[test]
+    fn sarif() {
+        use pretty_assertions::assert_eq;
+        use serde_json::json;
+        let options = crate::Options {
+            error_format: crate::report::ErrorFormat::Sarif,
+            ..Default::default()
+        };
+        let output = super::entry(
+            crate::find_file::FileContentProvider::File(
+                "./test/compile-fail/resolution/use/no-name/top.rhdl".into(),
+            ),
+            &options,
+        );
+        let log: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(json!("2.1.0"), log["version"]);
+        let run = &log["runs"][0];
+        let rule_index = run["results"][0]["ruleIndex"].as_u64().unwrap() as usize;
+        assert_eq!(
+            json!("E0425"),
+            run["tool"]["driver"]["rules"][rule_index]["id"]
+        );
+        assert_eq!(
+            json!([{
+                "ruleId": "E0425",
+                "ruleIndex": rule_index,
+                "level": "error",
+                "message": { "text": "unresolved item `b`" },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": "test/compile-fail/resolution/use/no-name/top.rhdl"
+                        },
+                        "region": {
+                            "startLine": 3,
+                            "startColumn": 8,
+                            "endLine": 3,
+                            "endColumn": 9
+                        }
+                    },
+                    "message": { "text": "no `b` item in `a`" }
+                }],
+                "relatedLocations": []
+            }]),
+            run["results"]
+        );
+    }
+
+    #[test]
+    fn exit_status() {
+        use crate::status::Status;
+        let status = |path: &str| {
+            super::entry_with_status(
+                crate::find_file::FileContentProvider::File(path.into()),
+                &crate::Options::default(),
+            )
+            .1
+        };
+        assert_eq!(
+            Status::Success,
+            status("./test/compile-pass/resolution/use/everything.rhdl")
+        );
+        assert_eq!(
+            Status::CompileError,
+            status("./test/compile-fail/resolution/use/no-name/top.rhdl")
+        );
+        assert_eq!(Status::Io, status("./test/does-not-exist.rhdl"));
+    }
+
+    #[test]
+    fn error_limit() {
+        let options = crate::Options {
+            error_limit: Some(2),
+            ..Default::default()
+        };
+        let output = super::entry(
+            crate::find_file::FileContentProvider::File(
+                "./test/compile-fail/resolution/conflicts/many/top.rhdl".into(),
+            ),
+            &options,
+        );
+        assert_eq!(
+            output.matches("is defined multiple times").count(),
+            2,
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("error[too_many_errors]: too many errors emitted, stopping now"),
+            "{}",
+            output
+        );
+    }
+
+    /// Streams an event as each file is found, stage starts and diagnostic is reported, instead
+    /// of rendering them, and ends with the status
+    #[test]
+    fn json_stream() {
+        use serde_json::Value as Json;
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let shared = Shared::default();
+        let options = crate::Options {
+            error_format: crate::report::ErrorFormat::JsonStream,
+            events: Some(crate::events::Events::to(shared.clone())),
+            ..Default::default()
+        };
+        let root = "test/compile-fail/resolution/conflicts/fn/top.rhdl";
+        let (output, status) = super::entry_with_status(
+            crate::find_file::FileContentProvider::File(root.into()),
+            &options,
+        );
+        assert_eq!("", output);
+        assert_eq!(crate::status::Status::CompileError, status);
+        let events = String::from_utf8(shared.0.lock().unwrap().clone()).unwrap();
+        let events = events
+            .lines()
+            .map(|line| serde_json::from_str::<Json>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "stage-started",
+                "file-discovered",
+                "stage-started",
+                "diagnostic",
+                "finished"
+            ],
+            events
+                .iter()
+                .map(|event| event["reason"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("find files", events[0]["stage"]);
+        assert_eq!(root, events[1]["path"]);
+        assert_eq!("resolution", events[2]["stage"]);
+        assert_eq!("E0428", events[3]["code"]);
+        assert_eq!(2, events[3]["spans"][0]["line_start"]);
+        assert_eq!(true, events[3]["spans"][0]["is_primary"]);
+        assert!(events[3]["rendered"]
+            .as_str()
+            .unwrap()
+            .starts_with("error[E0428]"));
+        assert_eq!(1, events[4]["status"]);
+    }
+
+    /// Counts the diagnostics after them, and leaves out everything but errors when quiet
+    #[test]
+    fn quiet_and_summary() {
+        use crate::attribute::LintLevel;
+        let lint = |options: &crate::Options| {
+            super::entry(
+                crate::find_file::FileContentProvider::File("test/config/top.rhdl".into()),
+                options,
+            )
+        };
+        let options = crate::Options {
+            summary: Some(crate::timing::Instant::now()),
+            ..Default::default()
+        };
+        let output = lint(&options);
+        let summary = output.lines().last().unwrap();
+        assert!(
+            summary.starts_with("0 errors, 1 warning in 1 file, ") && summary.ends_with('s'),
+            "{}",
+            output
+        );
+
+        let options = crate::Options {
+            quiet: true,
+            ..Default::default()
+        };
+        assert_eq!("", lint(&options));
+        let mut lint_levels = crate::attribute::LintLevels::default();
+        lint_levels.insert("unused_pub".to_string(), LintLevel::Deny);
+        let options = crate::Options {
+            quiet: true,
+            lint_levels,
+            ..Default::default()
+        };
+        assert!(lint(&options)
+            .starts_with("error[unused_pub]: `B` is `pub` but isn't used outside of `a`"));
+    }
+
+    #[test]
+    fn time_passes() {
+        let options = crate::Options {
+            time_passes: true,
+            ..top_options()
+        };
+        let output = super::entry(
+            crate::find_file::FileContentProvider::File(
+                "./test/compile-pass/elaboration/everything.rhdl".into(),
+            ),
+            &options,
+        );
+        let passes = output
+            .lines()
+            .skip_while(|line| !line.starts_with("pass"))
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            passes,
+            [
+                "find",
+                "parse",
+                "graph",
+                "visibility",
+                "use",
+                "invalid",
+                "conflicts",
+                "type",
+                "architectures",
+                "pragmas",
+                "item",
+                "elaboration",
+                "total"
+            ],
+            "{}",
+            output
+        );
+    }
+
+    /// Suggests what's visible in the fn of an architecture, but not private items through a glob
+    /// or locals declared after the cursor
+    #[test]
+    fn lsp_completion() {
+        use pretty_assertions::assert_eq;
+        use serde_json::json;
+        let top = std::env::current_dir()
+            .unwrap()
+            .join("test/lsp/completion/top.rhdl");
+        let uri = crate::lsp::position::path_to_uri(&top);
+        let messages = lsp_session(
+            top.clone(),
+            vec![
+                json!({
+                    "method": "textDocument/didOpen",
+                    "params": {
+                        "textDocument": {
+                            "uri": uri,
+                            "version": 1,
+                            "text": std::fs::read_to_string(&top).unwrap(),
+                        },
+                    },
+                }),
+                json!({
+                    "id": 3,
+                    "method": "textDocument/completion",
+                    "params": {
+                        "textDocument": { "uri": uri },
+                        "position": { "line": 17, "character": 12 },
+                    },
+                }),
+            ],
+        );
+        let labels = messages[messages.len() - 2]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "DEPTH", "Point", "Top", "WIDTH", "bool", "clk", "delayed", "i128", "i16", "i32",
+                "i64", "i8", "q", "run", "shapes", "u128", "u16", "u32", "u64", "u8",
+            ],
+            labels
+        );
+    }
+
+    /// Classifies the entity, its generic parameter, its ports, and the locals of its architecture
+    #[test]
+    fn lsp_semantic_tokens() {
+        use pretty_assertions::assert_eq;
+        use serde_json::json;
+        let top = std::env::current_dir().unwrap().join("test/lsp/highlight/top.rhdl");
+        let uri = crate::lsp::position::path_to_uri(&top);
+        let messages = lsp_session(
+            top.clone(),
+            vec![
+                json!({
+                    "method": "textDocument/didOpen",
+                    "params": {
+                        "textDocument": {
+                            "uri": uri,
+                            "version": 1,
+                            "text": std::fs::read_to_string(&top).unwrap(),
+                        },
+                    },
+                }),
+                json!({
+                    "id": 3,
+                    "method": "textDocument/semanticTokens/full",
+                    "params": { "textDocument": { "uri": uri } },
+                }),
+            ],
+        );
+        #[rustfmt::skip]
+        let data = json!([
+            0, 7, 3, 2, 1,
+            0, 10, 5, 7, 1,
+            0, 17, 1, 6, 1,
+            0, 10, 5, 7, 0,
+            0, 12, 1, 6, 1,
+            0, 10, 5, 7, 0,
+            1, 5, 3, 2, 0,
+            1, 7, 3, 3, 1,
+            1, 12, 8, 6, 1,
+            0, 11, 1, 6, 0,
+            1, 8, 1, 6, 0,
+            0, 4, 8, 6, 0,
+        ]);
+        assert_eq!(json!({ "data": data }), messages[messages.len() - 2]["result"]);
+    }
+
+    /// Initializes a language server for the design at `root`, sends it `messages`, shuts it
+    /// down, and returns everything it sent back, starting with the response to `initialize`
+    /// and ending with the one to `shutdown`
+    fn lsp_session(
+        root: std::path::PathBuf,
+        messages: Vec<serde_json::Value>,
+    ) -> Vec<serde_json::Value> {
+        use serde_json::{json, Value};
+        let mut all = vec![
+            json!({ "id": 1, "method": "initialize", "params": {} }),
+            json!({ "method": "initialized", "params": {} }),
+        ];
+        all.extend(messages);
+        all.push(json!({ "id": 2, "method": "shutdown" }));
+        all.push(json!({ "method": "exit" }));
+        let input = all
+            .into_iter()
+            .map(|mut message| {
+                message["jsonrpc"] = json!("2.0");
+                let content = message.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+            })
+            .collect::<String>();
+        let mut output = vec![];
+        crate::lsp::Server::new(Some(root))
+            .run(&mut input.as_bytes(), &mut output)
+            .unwrap();
+        let messages = String::from_utf8(output)
+            .unwrap()
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|message| {
+                serde_json::from_str::<Value>(&message[message.find("\r\n\r\n").unwrap() + 4..])
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(json!(1), messages[0]["id"]);
+        assert_eq!(
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null }),
+            messages[messages.len() - 1]
+        );
+        messages
+    }
+
+    fn top_options() -> crate::Options {
+        crate::Options {
+            top: Some("Top".to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// A new empty directory of its own for a test to write outputs to, so tests and concurrent
+    /// runs of them don't overwrite each other's
+    fn out_dir() -> std::path::PathBuf {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join("rhdlc-test").join(format!(
+            "{}-{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Runs `test` on each case in `dir` with the path of the case and an output directory from
+    /// [`out_dir`], which is removed once the case passes
+    fn run_cases(dir: &str, test: impl Fn(&std::path::Path, &std::path::Path)) {
+        for case in std::fs::read_dir(dir).unwrap() {
+            let case = case.unwrap().path();
+            let out = out_dir();
+            eprintln!("{}", case.to_string_lossy());
+            test(&case, &out);
+            let _ = std::fs::remove_dir_all(&out);
+        }
+    }
+
+    /// The diagnostics of compiling the `top.rhdl` in `dir`
+    fn compile(dir: &std::path::Path, options: &crate::Options) -> String {
+        super::entry(
+            crate::find_file::FileContentProvider::File(dir.join("top.rhdl")),
+            options,
+        )
+    }
+
+    /// Copies the files of a case to `to`, for commands that rewrite them
+    fn copy_sources(case: &std::path::Path, to: &std::path::Path) {
+        for file in std::fs::read_dir(case).unwrap() {
+            let file = file.unwrap();
+            if file.path().extension().map_or(false, |ext| ext == "rhdl") {
+                std::fs::copy(file.path(), to.join(file.file_name())).unwrap();
+            }
+        }
+    }
+
+    /// Compares each file in the `expected` directory of a case with the one of the same name
+    /// in `actual`
+    fn assert_expected_files(case: &std::path::Path, actual: &std::path::Path) {
+        for expected in std::fs::read_dir(case.join("expected")).unwrap() {
+            let expected = expected.unwrap();
+            let actual = std::fs::read_to_string(actual.join(expected.file_name()))
+                .expect(&expected.path().to_string_lossy());
+            assert_expected(&expected.path(), &actual);
+        }
+    }
+
+    /// Emits each case's `top.rhdl` to a temporary directory and compares it with `expected/`
+    fn codegen_test_looper(dir: &str, kind: crate::codegen::EmitKind) {
+        use pretty_assertions::assert_eq;
+        run_cases(dir, |case, out| {
+            let options = crate::Options {
+                top: Some("Top".to_string()),
+                emit: vec![crate::codegen::Emit {
+                    kind,
+                    dir: Some(out.to_path_buf()),
+                }],
+                ..Default::default()
+            };
+            assert_eq!("", compile(case, &options));
+            assert_expected_files(case, out);
+        })
+    }
+
+    /// Simulates each case's `TestBench` for 8 cycles and compares the report with `report.txt`
+    /// and the diagnostics with `expected.txt`
+    fn sim_test_looper(dir: &str) {
+        sim_test_looper_with(dir, &Default::default(), crate::sim::Backend::Interpreted)
+    }
+
+    fn sim_test_looper_with(
+        dir: &str,
+        models: &crate::sim::model::Models,
+        backend: crate::sim::Backend,
+    ) {
+        use std::fs;
+        run_cases(dir, |case, out| {
+            let report = out.join("report.txt");
+            let options = crate::Options {
+                top: Some("TestBench".to_string()),
+                simulate: Some(crate::sim::Simulate {
+                    cycles: 8,
+                    output: Some(report.clone()),
+                    backend,
+                    seed: Some(1),
+                    coverage: None,
+                }),
+                models: models.clone(),
+                ..Default::default()
+            };
+            assert_expected(&case.join("expected.txt"), &compile(case, &options));
+            assert_expected(
+                &case.join("report.txt"),
+                &fs::read_to_string(&report).unwrap(),
+            );
+        })
+    }
+
+    /// Proves each case's assertions with a depth of 4 and compares the results with `report.txt`
+    /// and the diagnostics with `expected.txt`. Cases with a failing assertion have to write a
+    /// counterexample.
+    fn prove_test_looper(dir: &str) {
+        use std::fs;
+        run_cases(dir, |case, out| {
+            let options = crate::Options {
+                top: Some("TestBench".to_string()),
+                prove: Some(crate::formal::Prove {
+                    depth: 4,
+                    output: Some(out.join("report.txt")),
+                    vcd: Some(out.join("vcd")),
+                }),
+                ..Default::default()
+            };
+            assert_expected(&case.join("expected.txt"), &compile(case, &options));
+            let results = fs::read_to_string(out.join("report.txt")).unwrap();
+            assert_expected(&case.join("report.txt"), &results);
+            assert_eq!(
+                results.contains("FAILED in cycle"),
+                out.join("vcd").exists()
+            );
+        })
+    }
+
+    /// Compares the architectures `a` and `b` of each case's `Top` for 4 cycles and compares the
+    /// results with `report.txt` and the diagnostics with `expected.txt`. Cases where they differ
+    /// have to write the value changes of both.
+    fn equiv_test_looper(dir: &str) {
+        use std::fs;
+        run_cases(dir, |case, out| {
+            let options = crate::Options {
+                top: Some("Top".to_string()),
+                equiv: Some(crate::formal::Equiv {
+                    arch_a: "a".to_string(),
+                    arch_b: "b".to_string(),
+                    depth: 4,
+                    output: Some(out.join("report.txt")),
+                    vcd: Some(out.join("vcd")),
+                }),
+                ..Default::default()
+            };
+            assert_expected(&case.join("expected.txt"), &compile(case, &options));
+            let results = fs::read_to_string(out.join("report.txt")).unwrap();
+            assert_expected(&case.join("report.txt"), &results);
+            assert_eq!(
+                results.contains("differ in cycle"),
+                out.join("vcd").join("b.vcd").exists()
+            );
+        })
+    }
+
+    /// Exports each case's `TestBench` unrolled for 4 cycles and checks that the script has
+    /// no diagnostics and checks its properties
+    fn formal_test_looper(dir: &str) {
+        use pretty_assertions::assert_eq;
+        use std::fs;
+        run_cases(dir, |case, out| {
+            let script = out.join("top.smt2");
+            let options = crate::Options {
+                top: Some("TestBench".to_string()),
+                formal: Some(crate::formal::Formal {
+                    depth: 4,
+                    output: Some(script.clone()),
+                }),
+                ..Default::default()
+            };
+            assert_eq!("", compile(case, &options));
+            let script = fs::read_to_string(&script).unwrap();
+            assert!(script.contains("(check-sat)"), "{}", script);
+        })
+    }
+
+    /// Runs each case's testbenches for 8 cycles and compares the results with `report.txt`
+    /// and the diagnostics with `expected.txt`
+    fn testbench_test_looper(dir: &str) {
+        use std::fs;
+        run_cases(dir, |case, out| {
+            let report = out.join("report.txt");
+            let options = crate::Options {
+                test: Some(crate::sim::test::Test {
+                    cycles: 8,
+                    output: Some(report.clone()),
+                    vcd: None,
+                    seed: Some(1),
+                    coverage: None,
+                    lcov: None,
+                }),
+                ..Default::default()
+            };
+            assert_expected(&case.join("expected.txt"), &compile(case, &options));
+            assert_expected(
+                &case.join("report.txt"),
+                &fs::read_to_string(&report).unwrap(),
+            );
+        })
+    }
+
+    /// Renames the item at the position in each case's `rename.txt`, written as
+    /// `<file>:<line>:<column> <name>`. Cases with an `expected.txt` compare the diagnostics with
+    /// it, and the rest rewrite a copy of their files and compare them with `expected/`.
+    fn rename_test_looper(dir: &str) {
+        use pretty_assertions::assert_eq;
+        use std::fs;
+        run_cases(dir, |case, out| {
+            let rename = fs::read_to_string(case.join("rename.txt")).unwrap();
+            let (at, name) = rename.trim().split_at(rename.trim().find(' ').unwrap());
+            let in_place = case.join("expected.txt").exists();
+            let root = if in_place {
+                case
+            } else {
+                copy_sources(case, out);
+                out
+            };
+            let options = crate::Options {
+                rename: Some(
+                    crate::resolution::Rename::new(
+                        &root.join(at).to_string_lossy(),
+                        name.trim(),
+                        true,
+                    )
+                    .unwrap(),
+                ),
+                ..Default::default()
+            };
+            let output = compile(root, &options);
+            if in_place {
+                assert_expected(&case.join("expected.txt"), &output);
+            } else {
+                assert_eq!("", output);
+                assert_expected_files(case, out);
+            }
+        })
+    }
+
+    /// Formats a copy of each test and compares it to the files in its `expected` directory, then
+    /// checks that formatting them again changes nothing. Tests with an `expected.txt` are checked
+    /// in place instead, and compared to the diagnostics.
+    fn fmt_test_looper(dir: &str) {
+        use pretty_assertions::assert_eq;
+        use std::fs;
+        run_cases(dir, |case, out| {
+            let width = fs::read_to_string(case.join("width.txt"))
+                .map_or(100, |width| width.trim().parse().unwrap());
+            let format = |root: &std::path::Path, check| {
+                let options = crate::Options {
+                    format: Some(crate::fmt::Format { width, check }),
+                    ..Default::default()
+                };
+                compile(root, &options)
+            };
+            if case.join("expected.txt").exists() {
+                assert_expected(&case.join("expected.txt"), &format(case, true));
+                return;
+            }
+            copy_sources(case, out);
+            assert_eq!("", format(out, false));
+            assert_expected_files(case, out);
+            assert_eq!("", format(out, true));
+        })
+    }
+
+    /// Shows only the lines that changed, numbered in the expected output and the actual one
+    #[test]
+    fn expected_output_diff() {
+        assert_eq!(
+            "    2 -b\n    2 +c\n    4 +e\n",
+            line_diff("a\nb\nd\n", "a\nc\nd\ne\n")
+        );
+        assert_eq!(
+            "only the newline at the end differs\n",
+            line_diff("a\n", "a")
+        );
+    }
+
+    fn fail_test_looper(dir: &str) {
+        fail_test_looper_with_options(dir, &Default::default())
+    }
+
+    /// Compares the diagnostics of each case with its `expected.txt`, or with the annotations in
+    /// its files if it doesn't have one
+    fn fail_test_looper_with_options(dir: &str, options: &crate::Options) {
+        use std::fs;
+        use std::io::Write;
+        for test in fs::read_dir(dir).unwrap() {
+            let test = test.unwrap();
+            let input = test.path().join("top.rhdl");
+            if !test.path().join("expected.txt").exists() {
+                eprintln!("{}", test.path().to_string_lossy());
+                let options = crate::Options {
+                    error_format: crate::report::ErrorFormat::Sarif,
+                    render: crate::report::Render {
+                        anonymize_paths: true,
+                        ..options.render.clone()
+                    },
+                    ..options.clone()
+                };
+                let output =
+                    super::entry(crate::find_file::FileContentProvider::File(input), &options);
+                check_annotations(&test.path(), &output);
+                continue;
+            }
+            let output = super::entry(crate::find_file::FileContentProvider::File(input), options);
+            eprintln!("{}", test.path().to_string_lossy());
+            std::io::stderr()
+                .flush()
+                .ok()
+                .expect("Could not flush stderr");
+            std::io::stdout()
+                .flush()
+                .ok()
+                .expect("Could not flush stdout");
+            assert_expected(&test.path().join("expected.txt"), &output);
+        }
+    }
+
+    fn success_test_looper(dir: &str) {
+        success_test_looper_with_options(dir, &Default::default())
+    }
+
+    /// Compares the diagnostics of the case in `dir` with its `expected.txt`, and the snapshot of
+    /// its resolution graph with its `graph.json` if it has one
+    fn success_test_looper_with_options(dir: &str, options: &crate::Options) {
+        use std::io::Write;
+        let dir = std::path::PathBuf::from(dir);
+        let input_path = dir.join("everything.rhdl");
+        let output = super::entry(
+            crate::find_file::FileContentProvider::File(input_path.clone()),
+            options,
+        );
+        eprintln!("{}", dir.to_string_lossy());
+        std::io::stderr()
+            .flush()
+            .ok()
+            .expect("Could not flush stderr");
+        std::io::stdout()
+            .flush()
+            .ok()
+            .expect("Could not flush stdout");
+        assert_expected(&dir.join("expected.txt"), &output);
+        if dir.join("graph.json").exists() {
+            assert_expected(
+                &dir.join("graph.json"),
+                &graph_snapshot(&input_path, options),
+            );
+        }
+    }
+
+    /// The resolution graph of the design at `root` as pretty JSON, with files named from the
+    /// directory of `root`
+    fn graph_snapshot(root: &std::path::Path, options: &crate::Options) -> String {
+        use crate::find_file::{FileContentProvider, FileFinder};
+        let mut finder = FileFinder::default();
+        finder.libraries = options.libraries.clone();
+        finder.find_tree(FileContentProvider::File(root.to_owned()));
+        let ctx = crate::resolution::context();
+        let mut resolver = super::Resolver::build(&finder.file_graph, &ctx);
+        resolver.build_graph();
+        resolver.check_graph();
+        let dir = root.parent().unwrap();
+        let file_graph = &finder.file_graph;
+        let name = |file: crate::find_file::FileId| {
+            let name = std::path::Path::new(file_graph.inner.name(file));
+            name.strip_prefix(dir)
+                .unwrap_or(name)
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        serde_json::to_string_pretty(&resolver.snapshot(file_graph, &name)).unwrap() + "\n"
+    }
+
+    /// A diagnostic expected by a comment in the files of a case, like `//~ ERROR E0428` on the line
+    /// of its primary label. The text after the level is its code or part of its message. `//~^`
+    /// is for the line above, with another `^` for each line further up, `//~|` for the line of
+    /// the annotation before it, and `//~?` for a diagnostic without a label.
+    #[derive(Debug)]
+    struct Annotation {
+        /// The path of the file from the case
+        file: String,
+        line: Option<usize>,
+        /// `error`, `warning`, or `note`, as in SARIF
+        level: String,
+        text: String,
+    }
+
+    /// The annotations in the `.rhdl` files of the case in `dir` and its subdirectories
+    fn annotations(dir: &std::path::Path) -> Vec<Annotation> {
+        use std::fs;
+        let mut files = vec![dir.to_owned()];
+        let mut annotations = vec![];
+        while let Some(path) = files.pop() {
+            if path.is_dir() {
+                let mut entries = fs::read_dir(&path)
+                    .unwrap()
+                    .map(|entry| entry.unwrap().path())
+                    .collect::<Vec<_>>();
+                entries.sort();
+                files.extend(entries.into_iter().rev());
+                continue;
+            }
+            if path.extension().map_or(true, |ext| ext != "rhdl") {
+                continue;
+            }
+            let file = path
+                .strip_prefix(dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            let mut previous = None;
+            for (i, line) in fs::read_to_string(&path).unwrap().lines().enumerate() {
+                let annotation = match line.find("//~") {
+                    Some(start) => &line[start + 3..],
+                    None => continue,
+                };
+                let (line, annotation) = if let Some(annotation) = annotation.strip_prefix('?') {
+                    (None, annotation)
+                } else if let Some(annotation) = annotation.strip_prefix('|') {
+                    (previous, annotation)
+                } else {
+                    let up = annotation.len() - annotation.trim_start_matches('^').len();
+                    (Some(i + 1 - up), &annotation[up..])
+                };
+                previous = line;
+                let annotation = annotation.trim();
+                let (level, text) =
+                    annotation.split_at(annotation.find(' ').unwrap_or(annotation.len()));
+                let level = match level {
+                    "ERROR" => "error",
+                    "WARNING" => "warning",
+                    "NOTE" => "note",
+                    _ => panic!("`{}` isn't ERROR, WARNING or NOTE in {}", level, file),
+                };
+                annotations.push(Annotation {
+                    file: file.clone(),
+                    line,
+                    level: level.to_string(),
+                    text: text.trim().to_string(),
+                });
+            }
+        }
+        annotations
+    }
+
+    /// Checks that each error and warning in the SARIF log `output` is annotated in the case in
+    /// `dir` and each annotation matches one of its diagnostics. Notes only need to match when
+    /// they're annotated.
+    fn check_annotations(dir: &std::path::Path, output: &str) {
+        let log: serde_json::Value = serde_json::from_str(output).expect(output);
+        let mut unmatched = annotations(dir);
+        let mut unexpected = vec![];
+        for result in log["runs"][0]["results"].as_array().unwrap() {
+            let level = result["level"].as_str().unwrap();
+            let message = result["message"]["text"].as_str().unwrap();
+            let code = result["ruleId"].as_str();
+            let locations = result["locations"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|location| {
+                    let location = &location["physicalLocation"];
+                    (
+                        location["artifactLocation"]["uri"].as_str().unwrap(),
+                        location["region"]["startLine"]
+                            .as_u64()
+                            .map(|line| line as usize),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let matched = unmatched.iter().position(|annotation| {
+                annotation.level == level
+                    && (code == Some(annotation.text.as_str())
+                        || message.contains(&annotation.text))
+                    && match annotation.line {
+                        Some(line) => locations.contains(&(annotation.file.as_str(), Some(line))),
+                        None => locations.is_empty(),
+                    }
+            });
+            match matched {
+                Some(matched) => {
+                    unmatched.remove(matched);
+                }
+                None if level == "note" => {}
+                None => unexpected.push(format!("{:?} {}: {}", locations, level, message)),
+            }
+        }
+        assert!(
+            unmatched.is_empty() && unexpected.is_empty(),
+            "{}\nannotations without a diagnostic: {:#?}\ndiagnostics without an annotation: {:#?}",
+            dir.to_string_lossy(),
+            unmatched,
+            unexpected
+        );
+    }
+
+    /// The variable that makes the test loopers overwrite their expected output with the actual
+    /// output instead of comparing them, i.e. `RHDLC_BLESS=1 cargo test`
+    const BLESS: &str = "RHDLC_BLESS";
+
+    /// Compares `actual` with the expected output in `path`, which is empty if there's no such
+    /// file, showing the lines that differ. When blessing, overwrites it instead, or removes it
+    /// for empty output.
+    fn assert_expected(path: &std::path::Path, actual: &str) {
+        use std::fs;
+        let expected = fs::read_to_string(path).unwrap_or_default();
+        if expected == actual {
+            return;
+        }
+        if std::env::var_os(BLESS).is_some() {
+            if actual.is_empty() {
+                fs::remove_file(path).unwrap();
+            } else {
+                fs::write(path, actual).unwrap();
+            }
+            return;
+        }
+        panic!(
+            "the output differs from {}:\n{}rerun with {}=1 to overwrite it",
+            path.to_string_lossy(),
+            line_diff(&expected, actual),
+            BLESS
+        );
+    }
+
+    /// The lines only in `expected`, marked with `-`, and those only in `actual`, marked with `+`,
+    /// numbered by where they are in each
+    fn line_diff(expected: &str, actual: &str) -> String {
+        let expected = expected.lines().collect::<Vec<_>>();
+        let actual = actual.lines().collect::<Vec<_>>();
+        // the length of the longest common subsequence of the lines from i and j on
+        let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+        for i in (0..expected.len()).rev() {
+            for j in (0..actual.len()).rev() {
+                common[i][j] = if expected[i] == actual[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+        let mut diff = String::new();
+        let (mut i, mut j) = (0, 0);
+        while i < expected.len() || j < actual.len() {
+            if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+                i += 1;
+                j += 1;
+            } else if j == actual.len()
+                || (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+            {
+                diff.push_str(&format!("{:>5} -{}\n", i + 1, expected[i]));
+                i += 1;
+            } else {
+                diff.push_str(&format!("{:>5} +{}\n", j + 1, actual[j]));
+                j += 1;
+            }
+        }
+        if diff.is_empty() {
+            diff.push_str("only the newline at the end differs\n");
+        }
+        diff
+    }
+}
@@ -0,0 +1,36 @@
+//! `-v`: what the compiler is doing, logged to standard error as it does it. `-v` logs each stage
+//! with the number of files, nodes, edges and solver queries it dealt with, `-vv` each file parsed
+//! and resolved, and `-vvv` how long each pass took, while `-q` only logs errors. `RUST_LOG`
+//! overrides the level, for debugging the compiler itself.
+use std::io::Write;
+
+use log::LevelFilter;
+
+/// Logs from the compiler at the level `verbosity` asks for, the number of times `-v` is given,
+/// or only errors when `quiet`
+pub fn init(verbosity: u64, quiet: bool) {
+    let level = match verbosity {
+        _ if quiet => LevelFilter::Error,
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let filter = format!("{}={}", env!("CARGO_PKG_NAME"), level);
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter))
+        .format(|buf, record| {
+            // the module that logged it names the stage, i.e. `find_file` or `resolution::pub`
+            let stage = record
+                .target()
+                .strip_prefix(concat!(env!("CARGO_PKG_NAME"), "::"))
+                .unwrap_or_else(|| record.target());
+            writeln!(
+                buf,
+                "{} {}: {}",
+                record.level().to_string().to_lowercase(),
+                stage,
+                record.args()
+            )
+        })
+        .init();
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use fxhash::FxHashMap as HashMap;
+use serde_json::{json, Value as Json};
+
+use super::position::{offset, path_to_uri, position, range};
+use crate::analysis::synth::SynthesisLinter;
+use crate::config::Config;
+use crate::elaboration::enums::EncodingChecker;
+use crate::error::{ambiguous_rename, nothing_to_rename, Diagnostic};
+use crate::find_file::{FileContentProvider, FileFinder, FileGraph, FileId};
+use crate::query::Database;
+use crate::resolution::{context, Resolver, Symbol, SymbolKind, TokenKind, UnusedPubLinter};
+
+/// The token types and modifiers of semantic tokens, which are encoded by their index
+pub const TOKEN_TYPES: &[&str] = &[
+    "namespace",
+    "type",
+    "class",
+    "function",
+    "enumMember",
+    "property",
+    "variable",
+    "typeParameter",
+];
+pub const TOKEN_MODIFIERS: &[&str] = &["declaration", "readonly"];
+
+/// The files of a design found from one root and the diagnostics from checking them
+pub struct Analysis {
+    pub root: PathBuf,
+    pub file_graph: FileGraph,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// Finds, resolves, and checks the items of the design from `root`, reading the open
+    /// `documents` instead of the files on disk. Parsing and checking are memoized in `database`.
+    pub fn run(
+        root: &Path,
+        documents: &HashMap<PathBuf, String>,
+        database: &mut Database,
+    ) -> Self {
+        // a config that doesn't parse is reported when the design is compiled
+        let config = root
+            .parent()
+            .and_then(|dir| Config::find(dir).ok())
+            .unwrap_or_default();
+        let mut finder = FileFinder::default();
+        finder.overlay = documents.clone();
+        // keep navigating the files being edited, which often don't parse
+        finder.tolerant = true;
+        finder.libraries = config.libraries;
+        finder.library_path = crate::find_file::rhdl_path();
+        finder.library_path.extend(config.library_path);
+        finder.database = Some(std::mem::take(database));
+        finder.find_tree(FileContentProvider::File(root.to_owned()));
+        *database = finder.database.take().unwrap_or_default();
+        let file_graph = finder.file_graph;
+        let mut diagnostics = finder.errors;
+
+        // the files found are the key, so whether they had errors is the same for each check
+        let found_errors = crate::has_error_severity(&diagnostics);
+        diagnostics.append(&mut database.check(&file_graph, || {
+            let mut diagnostics = vec![];
+            let ctx = context();
+            let mut resolver = Resolver::build(&file_graph, &ctx);
+            resolver.lint_levels = config.lints;
+            resolver.build_graph();
+            resolver.check_graph();
+            diagnostics.append(&mut resolver.errors);
+            if !found_errors && !crate::has_error_severity(&diagnostics) {
+                EncodingChecker {
+                    resolver: &resolver,
+                    errors: &mut diagnostics,
+                }
+                .check();
+                SynthesisLinter {
+                    resolver: &resolver,
+                    errors: &mut diagnostics,
+                }
+                .check();
+                UnusedPubLinter {
+                    resolver: &resolver,
+                    errors: &mut diagnostics,
+                }
+                .check();
+            }
+            diagnostics
+        }));
+        crate::sort_diagnostics(&mut diagnostics);
+
+        Analysis {
+            root: root.to_owned(),
+            file_graph,
+            diagnostics,
+        }
+    }
+
+    /// Resolves the design again, for requests that need the resolution graph
+    pub fn resolve<T>(&self, f: impl FnOnce(&Resolver) -> T) -> T {
+        let ctx = context();
+        let mut resolver = Resolver::build(&self.file_graph, &ctx);
+        resolver.build_graph();
+        resolver.check_graph();
+        f(&resolver)
+    }
+
+    pub fn file(&self, path: &Path) -> Option<FileId> {
+        self.file_graph
+            .iter()
+            .copied()
+            .find(|file| self.path(*file) == path)
+    }
+
+    pub fn path(&self, file: FileId) -> PathBuf {
+        match &self.file_graph[file].provider {
+            FileContentProvider::File(path) | FileContentProvider::Embedded(path) => path.clone(),
+            FileContentProvider::Reader(name, _) => name.into(),
+        }
+    }
+
+    /// Whether `path` is one of the files of the design other than its root
+    pub fn includes(&self, path: &Path) -> bool {
+        path != self.root && self.file(path).is_some()
+    }
+
+    /// The locations of the declarations of the name at `position` in `path`, or `None` if
+    /// `path` isn't part of this design
+    pub fn definition(&self, path: &Path, position: &Json) -> Option<Vec<Json>> {
+        let file = self.file(path)?;
+        let offset = offset(self.file_graph[file].as_ref(), position)?;
+        let definitions = self.resolve(|resolver| resolver.find_definition(file, offset));
+        Some(
+            definitions
+                .into_iter()
+                .map(|definition| {
+                    let range: Range<usize> = definition.span.into();
+                    json!({
+                        "uri": path_to_uri(&self.path(definition.file)),
+                        "range": self.range(definition.file, range.start, range.end),
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// A description of the item that the name at `position` in `path` refers to as Markdown, or
+    /// `None` if there isn't one or `path` isn't part of this design
+    pub fn hover(&self, path: &Path, position: &Json) -> Option<Json> {
+        let file = self.file(path)?;
+        let offset = offset(self.file_graph[file].as_ref(), position)?;
+        let hover = self.resolve(|resolver| resolver.hover(file, offset))?;
+        let mut value = format!(
+            "```rhdl\n{}\n```\n\n{} `{}`",
+            hover.signature,
+            hover.kind.name(),
+            hover.path
+        );
+        if let Some(docs) = &hover.docs {
+            value += "\n\n---\n\n";
+            value += docs;
+        }
+        Some(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": value,
+            },
+        }))
+    }
+
+    /// The names visible at `position` in `path` as LSP completion items, or `None` if `path`
+    /// isn't part of this design
+    pub fn completions(&self, path: &Path, position: &Json) -> Option<Vec<Json>> {
+        let file = self.file(path)?;
+        let offset = offset(self.file_graph[file].as_ref(), position)?;
+        let completions = self.resolve(|resolver| resolver.completions(file, offset));
+        Some(
+            completions
+                .into_iter()
+                .map(|completion| {
+                    json!({
+                        "label": completion.name,
+                        // https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#completionItemKind
+                        "kind": match completion.kind {
+                            TokenKind::Function => 3,
+                            TokenKind::Field => 5,
+                            TokenKind::Variable => 6,
+                            TokenKind::Entity => 7,
+                            TokenKind::Module => 9,
+                            TokenKind::Variant => 20,
+                            TokenKind::Const => 21,
+                            TokenKind::Type => 22,
+                            TokenKind::GenericParam => 25,
+                        },
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// The outline of `path` as LSP document symbols, or `None` if `path` isn't part of this design
+    pub fn symbols(&self, path: &Path) -> Option<Vec<Json>> {
+        let file = self.file(path)?;
+        let symbols = self.resolve(|resolver| resolver.symbols(file));
+        Some(
+            symbols
+                .iter()
+                .map(|symbol| self.symbol_json(file, symbol))
+                .collect(),
+        )
+    }
+
+    fn symbol_json(&self, file: FileId, symbol: &Symbol) -> Json {
+        let span: Range<usize> = symbol.span.into();
+        let name_span: Range<usize> = symbol.name_span.into();
+        let mut document_symbol = json!({
+            "name": symbol.name,
+            // https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#symbolKind
+            "kind": match symbol.kind {
+                SymbolKind::Mod => 2,
+                SymbolKind::Arch => 5,
+                SymbolKind::Field => 8,
+                SymbolKind::Enum => 10,
+                SymbolKind::Entity | SymbolKind::Trait => 11,
+                SymbolKind::Fn => 12,
+                SymbolKind::Const => 14,
+                SymbolKind::Variant => 22,
+                SymbolKind::Struct => 23,
+                SymbolKind::Type => 26,
+            },
+            "range": self.range(file, span.start, span.end),
+            "selectionRange": self.range(file, name_span.start, name_span.end),
+            "children": symbol
+                .children
+                .iter()
+                .map(|child| self.symbol_json(file, child))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(detail) = &symbol.detail {
+            document_symbol["detail"] = json!(detail);
+        }
+        document_symbol
+    }
+
+    /// The semantic tokens of `path` in the relative encoding of LSP, five numbers per token:
+    /// the line and start character relative to the previous token, the length, the type, and
+    /// the modifiers. `None` if `path` isn't part of this design.
+    pub fn semantic_tokens(&self, path: &Path) -> Option<Vec<u64>> {
+        let file = self.file(path)?;
+        let source = self.file_graph[file].as_ref();
+        let tokens = self.resolve(|resolver| resolver.semantic_tokens(file));
+        let mut data = vec![];
+        let (mut previous_line, mut previous_character) = (0, 0);
+        for token in tokens {
+            let span: Range<usize> = token.span.into();
+            let start = position(source, span.start);
+            let line = start["line"].as_u64().unwrap_or_default();
+            let character = start["character"].as_u64().unwrap_or_default();
+            let length = source[span].encode_utf16().count() as u64;
+            let token_type = match token.kind {
+                TokenKind::Module => 0,
+                TokenKind::Type => 1,
+                TokenKind::Entity => 2,
+                TokenKind::Function => 3,
+                TokenKind::Variant => 4,
+                TokenKind::Field => 5,
+                TokenKind::Const | TokenKind::Variable => 6,
+                TokenKind::GenericParam => 7,
+            };
+            let mut modifiers = 0;
+            if token.declaration {
+                modifiers |= 1;
+            }
+            if token.kind == TokenKind::Const {
+                modifiers |= 2;
+            }
+            data.extend_from_slice(&[
+                line - previous_line,
+                if line == previous_line {
+                    character - previous_character
+                } else {
+                    character
+                },
+                length,
+                token_type,
+                modifiers,
+            ]);
+            previous_line = line;
+            previous_character = character;
+        }
+        Some(data)
+    }
+
+    /// The edits to each file that rename the item named at `position` in `path`, or the message
+    /// of the diagnostic explaining why it can't be, or `None` if `path` isn't part of this design
+    pub fn rename(&self, path: &Path, position: &Json, name: &str) -> Option<Result<Json, String>> {
+        let file = self.file(path)?;
+        let offset = offset(self.file_graph[file].as_ref(), position)?;
+        let location = format!(
+            "{}:{}:{}",
+            path.display(),
+            position["line"].as_u64().unwrap_or_default() + 1,
+            position["character"].as_u64().unwrap_or_default() + 1
+        );
+        let edits = self.resolve(|resolver| {
+            match resolver.find_definition(file, offset).as_slice() {
+                [] => Err(nothing_to_rename(&location)),
+                [definition] => resolver.rename(definition.node, name),
+                _ => Err(ambiguous_rename(&location)),
+            }
+        });
+        let mut changes = BTreeMap::<String, Vec<Json>>::new();
+        for edit in edits.map_err(|diagnostic| diagnostic.message)? {
+            changes
+                .entry(path_to_uri(&self.path(edit.file)))
+                .or_default()
+                .push(json!({
+                    "range": self.range(edit.file, edit.range.start, edit.range.end),
+                    "newText": edit.text,
+                }));
+        }
+        Some(Ok(json!({ "changes": changes })))
+    }
+
+    /// The diagnostics of each file as LSP diagnostics, located by their primary label.
+    /// Diagnostics without labels, like a root that couldn't be read, go to the root.
+    pub fn lsp_diagnostics(&self) -> BTreeMap<PathBuf, Vec<Json>> {
+        let mut by_path = BTreeMap::<PathBuf, Vec<Json>>::new();
+        for diagnostic in self.diagnostics.iter() {
+            let primary = diagnostic
+                .labels
+                .iter()
+                .find(|label| label.style == LabelStyle::Primary)
+                .or_else(|| diagnostic.labels.first());
+            let (path, range) = match primary {
+                Some(label) => (
+                    self.path(label.file_id),
+                    self.range(label.file_id, label.range.start, label.range.end),
+                ),
+                None => (
+                    self.root.clone(),
+                    json!({
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": 0, "character": 0 },
+                    }),
+                ),
+            };
+            let mut message = diagnostic.message.clone();
+            for note in diagnostic.notes.iter() {
+                message += "\n";
+                message += note;
+            }
+            let related = diagnostic
+                .labels
+                .iter()
+                .filter(|label| !label.message.is_empty())
+                .map(|label| {
+                    json!({
+                        "location": {
+                            "uri": path_to_uri(&self.path(label.file_id)),
+                            "range": self.range(label.file_id, label.range.start, label.range.end),
+                        },
+                        "message": label.message,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut lsp_diagnostic = json!({
+                "range": range,
+                "severity": match diagnostic.severity {
+                    Severity::Bug | Severity::Error => 1,
+                    Severity::Warning => 2,
+                    Severity::Note => 3,
+                    Severity::Help => 4,
+                },
+                "source": "rhdlc",
+                "message": message,
+                "relatedInformation": related,
+            });
+            if let Some(code) = &diagnostic.code {
+                lsp_diagnostic["code"] = json!(code);
+            }
+            by_path.entry(path).or_default().push(lsp_diagnostic);
+        }
+        by_path
+    }
+
+    fn range(&self, file: FileId, start: usize, end: usize) -> Json {
+        range(self.file_graph[file].as_ref(), start, end)
+    }
+}
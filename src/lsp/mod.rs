@@ -0,0 +1,329 @@
+//! `rhdlc lsp`: a Language Server Protocol server over standard input and output.
+//!
+//! Editors send the contents of open files as they change. After each change, the design is found,
+//! parsed, and resolved again, reading the open files instead of the files on disk, and the
+//! diagnostics of each of its files are published. Files that didn't change aren't parsed again,
+//! and designs whose files didn't change aren't checked again. Requests about a file, like go-to-definition,
+//! hover, completion, rename, its outline, and semantic highlighting, resolve the design of the
+//! file they're in again.
+//!
+//! Designs are found from the top level file given on the command line or as the `root`
+//! initialization option. Without one, each open file is a root, unless it's a module of another
+//! open file.
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use serde_json::{json, Value as Json};
+
+use crate::query::Database;
+
+pub(crate) mod analysis;
+pub mod position;
+mod transport;
+
+use analysis::{Analysis, TOKEN_MODIFIERS, TOKEN_TYPES};
+use position::{path_to_uri, uri_to_path};
+use transport::{read_message, write_message};
+
+/// JSON-RPC error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+const REQUEST_FAILED: i64 = -32803;
+
+/// Serves an editor over standard input and output until it exits
+pub fn serve(root: Option<PathBuf>) -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    Server::new(root).run(&mut stdin.lock(), &mut stdout.lock())
+}
+
+pub struct Server {
+    /// The top level file of the design, if given
+    root: Option<PathBuf>,
+    /// The contents of the open files
+    documents: HashMap<PathBuf, String>,
+    analyses: Vec<Analysis>,
+    /// Memoizes parsing and checking across changes
+    database: Database,
+    /// Files with diagnostics published, which are cleared once they have none
+    published: HashSet<PathBuf>,
+    initialized: bool,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new(root: Option<PathBuf>) -> Self {
+        Self {
+            root: root.map(|root| match std::env::current_dir() {
+                Ok(cwd) => cwd.join(root),
+                Err(_) => root,
+            }),
+            documents: HashMap::default(),
+            analyses: vec![],
+            database: Database::default(),
+            published: HashSet::default(),
+            initialized: false,
+            shutdown: false,
+        }
+    }
+
+    /// Handles messages until `exit`, which is an error unless it follows `shutdown`
+    pub fn run(&mut self, reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(reader)? {
+            let params = message.get("params").cloned().unwrap_or(Json::Null);
+            match (message.get("method").and_then(Json::as_str), message.get("id")) {
+                (Some("exit"), _) => {
+                    return if self.shutdown {
+                        Ok(())
+                    } else {
+                        Err(io::Error::new(io::ErrorKind::Other, "exited without shutting down"))
+                    };
+                }
+                (Some(method), Some(id)) => {
+                    let response = match self.request(method, &params) {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        Err((code, message)) => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": { "code": code, "message": message },
+                        }),
+                    };
+                    write_message(writer, &response)?;
+                }
+                (Some(method), None) => {
+                    for notification in self.notify(method, &params) {
+                        write_message(writer, &notification)?;
+                    }
+                }
+                // responses, but the server doesn't make requests
+                (None, _) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        if !self.initialized && method != "initialize" {
+            return Err((SERVER_NOT_INITIALIZED, "the server hasn't been initialized".to_string()));
+        }
+        match method {
+            "initialize" => {
+                self.initialized = true;
+                if self.root.is_none() {
+                    self.root = params
+                        .pointer("/initializationOptions/root")
+                        .and_then(Json::as_str)
+                        .map(PathBuf::from);
+                }
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": {
+                            "openClose": true,
+                            // the whole document is sent with each change
+                            "change": 1,
+                            "save": true,
+                        },
+                        "definitionProvider": true,
+                        "hoverProvider": true,
+                        "completionProvider": {},
+                        "renameProvider": true,
+                        "documentSymbolProvider": true,
+                        "semanticTokensProvider": {
+                            "legend": {
+                                "tokenTypes": TOKEN_TYPES,
+                                "tokenModifiers": TOKEN_MODIFIERS,
+                            },
+                            "full": true,
+                        },
+                    },
+                    "serverInfo": {
+                        "name": "rhdlc",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => {
+                let (path, position) = text_document_position(params)?;
+                Ok(self
+                    .analyses
+                    .iter()
+                    .find_map(|analysis| analysis.definition(&path, position))
+                    .map_or(Json::Null, Json::from))
+            }
+            "textDocument/hover" => {
+                let (path, position) = text_document_position(params)?;
+                Ok(self
+                    .analyses
+                    .iter()
+                    .find_map(|analysis| analysis.hover(&path, position))
+                    .unwrap_or(Json::Null))
+            }
+            "textDocument/completion" => {
+                let (path, position) = text_document_position(params)?;
+                Ok(self
+                    .analyses
+                    .iter()
+                    .find_map(|analysis| analysis.completions(&path, position))
+                    .map_or(Json::Null, Json::from))
+            }
+            "textDocument/documentSymbol" => {
+                let path = text_document(params)?;
+                Ok(self
+                    .analyses
+                    .iter()
+                    .find_map(|analysis| analysis.symbols(&path))
+                    .map_or(Json::Null, Json::from))
+            }
+            "textDocument/semanticTokens/full" => {
+                let path = text_document(params)?;
+                Ok(self
+                    .analyses
+                    .iter()
+                    .find_map(|analysis| analysis.semantic_tokens(&path))
+                    .map_or(Json::Null, |data| json!({ "data": data })))
+            }
+            "textDocument/rename" => {
+                let (path, position) = text_document_position(params)?;
+                let name = params.get("newName").and_then(Json::as_str).ok_or((
+                    INVALID_PARAMS,
+                    "expected a new name".to_string(),
+                ))?;
+                self.analyses
+                    .iter()
+                    .find_map(|analysis| analysis.rename(&path, position, name))
+                    .unwrap_or(Ok(Json::Null))
+                    .map_err(|message| (REQUEST_FAILED, message))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method `{}`", method))),
+        }
+    }
+
+    /// Returns the notifications to send in response, i.e. diagnostics
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let path = params
+            .pointer("/textDocument/uri")
+            .and_then(Json::as_str)
+            .and_then(uri_to_path);
+        match (method, path) {
+            ("textDocument/didOpen", Some(path)) => {
+                if let Some(text) = params.pointer("/textDocument/text").and_then(Json::as_str) {
+                    self.documents.insert(path, text.to_string());
+                }
+            }
+            ("textDocument/didChange", Some(path)) => {
+                if let Some(text) = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str)
+                {
+                    self.documents.insert(path, text.to_string());
+                }
+            }
+            ("textDocument/didClose", Some(path)) => {
+                self.documents.remove(&path);
+            }
+            // modules that aren't open are read from disk
+            ("textDocument/didSave", Some(_)) => {}
+            _ => return vec![],
+        }
+        self.analyze()
+    }
+
+    /// Analyzes every design again and publishes the diagnostics of files that have any, or had
+    /// some before
+    fn analyze(&mut self) -> Vec<Json> {
+        let roots = match &self.root {
+            Some(root) => vec![root.clone()],
+            None => {
+                let mut open = self.documents.keys().cloned().collect::<Vec<_>>();
+                open.sort();
+                open
+            }
+        };
+        self.database.new_revision();
+        let mut analyses = roots
+            .iter()
+            .map(|root| Analysis::run(root, &self.documents, &mut self.database))
+            .collect::<Vec<_>>();
+        // an open module is analyzed as part of the design that declares it
+        let modules = analyses
+            .iter()
+            .filter(|analysis| analyses.iter().any(|other| other.includes(&analysis.root)))
+            .map(|analysis| analysis.root.clone())
+            .collect::<Vec<_>>();
+        analyses.retain(|analysis| !modules.contains(&analysis.root));
+        self.analyses = analyses;
+
+        let mut diagnostics = BTreeMap::<PathBuf, Vec<Json>>::new();
+        for analysis in self.analyses.iter() {
+            for (path, mut lsp_diagnostics) in analysis.lsp_diagnostics() {
+                diagnostics
+                    .entry(path)
+                    .or_default()
+                    .append(&mut lsp_diagnostics);
+            }
+        }
+        let mut cleared = self
+            .published
+            .iter()
+            .filter(|path| !diagnostics.contains_key(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+        cleared.sort();
+        for path in cleared {
+            diagnostics.insert(path, vec![]);
+        }
+        self.published = diagnostics
+            .iter()
+            .filter(|(_, lsp_diagnostics)| !lsp_diagnostics.is_empty())
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        diagnostics
+            .into_iter()
+            .map(|(path, lsp_diagnostics)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": {
+                        "uri": path_to_uri(&path),
+                        "diagnostics": lsp_diagnostics,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+/// The file of a request about a whole document
+fn text_document(params: &Json) -> Result<PathBuf, (i64, String)> {
+    params
+        .pointer("/textDocument/uri")
+        .and_then(Json::as_str)
+        .and_then(uri_to_path)
+        .ok_or((INVALID_PARAMS, "expected a `file://` text document".to_string()))
+}
+
+/// The file and position of a request about a position in a document
+fn text_document_position(params: &Json) -> Result<(PathBuf, &Json), (i64, String)> {
+    let path = params
+        .pointer("/textDocument/uri")
+        .and_then(Json::as_str)
+        .and_then(uri_to_path);
+    match (path, params.get("position")) {
+        (Some(path), Some(position)) => Ok((path, position)),
+        _ => Err((
+            INVALID_PARAMS,
+            "expected a `file://` text document and a position".to_string(),
+        )),
+    }
+}
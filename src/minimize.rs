@@ -0,0 +1,245 @@
+//! `rhdlc minimize`: shrinks a design that makes rhdlc panic, or report a diagnostic, to a test
+//! case. The files of the design next to its root are copied to the output directory, and items
+//! are removed from the copy one at a time, outer ones before those inside them, for as long as
+//! compiling it still does. The files left out of the design by then are deleted.
+use std::fs;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use rhdl::ast::{Item, ModContent, Spanned};
+use rhdl::parser::FileParser;
+
+use crate::find_file::{absolute, FileContentProvider, FileFinder};
+use crate::report::ErrorFormat;
+use crate::status::Status;
+use crate::Options;
+
+pub struct Minimize {
+    /// What compiling the design has to keep doing
+    pub condition: Condition,
+    /// The directory to write the test case to, which mustn't exist yet
+    pub output: PathBuf,
+}
+
+pub enum Condition {
+    Panic,
+    /// Report a diagnostic with this in its code or message
+    Diagnostic(String),
+}
+
+impl Condition {
+    fn describe(&self) -> String {
+        match self {
+            Condition::Panic => "panic".to_string(),
+            Condition::Diagnostic(text) => format!("report `{}`", text),
+        }
+    }
+}
+
+/// Minimizes the design of `root` into `minimize.output`, returning what was removed
+pub fn minimize(
+    root: &Path,
+    minimize: &Minimize,
+    options: &Options,
+) -> Result<String, (Status, String)> {
+    let options = Options {
+        color: false,
+        quiet: false,
+        summary: None,
+        time_passes: false,
+        error_limit: None,
+        error_format: ErrorFormat::Human,
+        events: None,
+        incremental: None,
+        json_compilation_db: None,
+        ..options.clone()
+    };
+    if minimize.output.exists() {
+        return Err((
+            Status::Usage,
+            format!("{} already exists", minimize.output.display()),
+        ));
+    }
+    if !reproduces(root, &minimize.condition, &options) {
+        return Err((
+            Status::Usage,
+            format!(
+                "compiling {} doesn't {}",
+                root.display(),
+                minimize.condition.describe()
+            ),
+        ));
+    }
+
+    let dir = absolute(root)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let files = design_files(root, &options, &dir);
+    let io =
+        |path: &Path, err: std::io::Error| (Status::Io, format!("{}: {}", path.display(), err));
+    for file in files.iter() {
+        let copy = minimize.output.join(file);
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent).map_err(|err| io(parent, err))?;
+        }
+        fs::copy(dir.join(file), &copy).map_err(|err| io(&copy, err))?;
+    }
+    let root = minimize.output.join(root.file_name().unwrap_or_default());
+    let size = |files: &[PathBuf]| {
+        files
+            .iter()
+            .filter_map(|file| fs::metadata(minimize.output.join(file)).ok())
+            .map(|metadata| metadata.len())
+            .sum::<u64>()
+    };
+    let original_size = size(&files);
+
+    let mut removed = 0;
+    loop {
+        let mut changed = false;
+        for file in files.iter().map(|file| minimize.output.join(file)) {
+            loop {
+                let content = fs::read_to_string(&file).map_err(|err| io(&file, err))?;
+                if !remove_an_item(&file, &content, &root, &minimize.condition, &options)
+                    .map_err(|err| io(&file, err))?
+                {
+                    break;
+                }
+                removed += 1;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let left = design_files(&root, &options, &minimize.output);
+    for file in files.iter() {
+        let path = minimize.output.join(file);
+        if !left.contains(file) {
+            fs::remove_file(&path).map_err(|err| io(&path, err))?;
+            continue;
+        }
+        let content = fs::read_to_string(&path).map_err(|err| io(&path, err))?;
+        fs::write(&path, tidy(&content)).map_err(|err| io(&path, err))?;
+        if !reproduces(&root, &minimize.condition, &options) {
+            fs::write(&path, content).map_err(|err| io(&path, err))?;
+        }
+    }
+    let count = |count: usize, noun: &str| {
+        format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+    };
+    Ok(format!(
+        "removed {} and {}, from {} to {} bytes; the test case is in {}\n",
+        count(removed, "item"),
+        count(files.len() - left.len(), "file"),
+        original_size,
+        size(&left),
+        minimize.output.display()
+    ))
+}
+
+/// Removes the first item of `file` that compiling the design still does without, returning
+/// whether there was one
+fn remove_an_item(
+    file: &Path,
+    content: &str,
+    root: &Path,
+    condition: &Condition,
+    options: &Options,
+) -> std::io::Result<bool> {
+    let parsed = match FileParser::new().parse(content) {
+        Ok(parsed) => parsed,
+        Err(_) => return Ok(false),
+    };
+    let mut ranges = vec![];
+    item_ranges(&parsed.items, &mut ranges);
+    for range in ranges {
+        fs::write(file, without(content, range))?;
+        if reproduces(root, condition, options) {
+            return Ok(true);
+        }
+    }
+    fs::write(file, content)?;
+    Ok(false)
+}
+
+/// Whether compiling the design of `root` does what `condition` asks
+fn reproduces(root: &Path, condition: &Condition, options: &Options) -> bool {
+    // the hook of rhdlc reports an internal compiler error and exits
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let output = panic::catch_unwind(AssertUnwindSafe(|| {
+        crate::entry(FileContentProvider::File(root.to_path_buf()), options)
+    }));
+    panic::set_hook(hook);
+    match (condition, output) {
+        (Condition::Panic, Err(_)) => true,
+        (Condition::Diagnostic(text), Ok(output)) => output.contains(text.as_str()),
+        _ => false,
+    }
+}
+
+/// The files of the design of `root` in `dir`, relative to it, so libraries are left out
+fn design_files(root: &Path, options: &Options, dir: &Path) -> Vec<PathBuf> {
+    let mut finder = FileFinder::default();
+    finder.libraries = options.libraries.clone();
+    finder.library_path = options.library_path.clone();
+    finder.find_tree(FileContentProvider::File(root.to_path_buf()));
+    let dir = absolute(dir);
+    finder
+        .file_graph
+        .iter()
+        .filter_map(|file| finder.file_graph.absolute_path(*file))
+        .filter_map(|path| path.strip_prefix(&dir).ok().map(Path::to_path_buf))
+        .collect()
+}
+
+/// The spans of `items`, each before those of the items inside it
+fn item_ranges(items: &[Item], ranges: &mut Vec<Range<usize>>) {
+    for item in items {
+        ranges.push(item.span().into());
+        if let Item::Mod(item_mod) = item {
+            if let ModContent::Here(here) = &item_mod.content {
+                item_ranges(&here.items, ranges);
+            }
+        }
+    }
+}
+
+/// `content` without `range`, along with the lines it's on if nothing else is
+fn without(content: &str, range: Range<usize>) -> String {
+    let line_start = content[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[range.end..]
+        .find('\n')
+        .map_or(content.len(), |i| range.end + i + 1);
+    if content[line_start..range.start].trim().is_empty()
+        && content[range.end..line_end].trim().is_empty()
+    {
+        format!("{}{}", &content[..line_start], &content[line_end..])
+    } else {
+        format!("{}{}", &content[..range.start], &content[range.end..])
+    }
+}
+
+/// `content` without the blank lines at its start and end, or more than one in a row
+fn tidy(content: &str) -> String {
+    let mut tidied = String::new();
+    let mut blank = false;
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            blank = !tidied.is_empty();
+            continue;
+        }
+        if blank {
+            tidied.push('\n');
+            blank = false;
+        }
+        tidied.push_str(line);
+        tidied.push('\n');
+    }
+    tidied
+}
@@ -0,0 +1,124 @@
+//! Custom passes: checks that an embedder of rhdlc registers in [`Options::passes`] to run over
+//! each file of the design once it's resolved, so an organization can enforce rules of its own
+//! without forking the compiler. A pass usually walks the file with a [`Visit`] of its own, looks
+//! up what it finds in the resolution graph, and reports diagnostics to its context:
+//!
+//! ```ignore
+//! struct NoFns;
+//!
+//! impl Pass for NoFns {
+//!     fn name(&self) -> &'static str {
+//!         "no fns"
+//!     }
+//!
+//!     fn check_file<'ast>(&self, cx: &mut FileContext<'_, 'ast>, file: &'ast File) {
+//!         let mut finder = FnFinder { fns: vec![] };
+//!         finder.visit_file(file);
+//!         for item_fn in finder.fns {
+//!             cx.report(Diagnostic::warning().with_message("fns aren't allowed here"));
+//!         }
+//!     }
+//! }
+//!
+//! options.passes.register(NoFns);
+//! ```
+//!
+//! Passes run after the checks of rhdlc when resolving the design found no errors, in the order
+//! they were registered, and don't run over the files of libraries.
+//!
+//! [`Options::passes`]: crate::Options::passes
+//! [`Visit`]: rhdl::visit::Visit
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::File;
+
+use crate::error::Diagnostic;
+use crate::find_file::{FileGraph, FileId};
+use crate::resolution::{ResolutionIndex, Resolver};
+use crate::timing::{Instant, Timings};
+
+/// A check run over each file of the design
+pub trait Pass: Send + Sync {
+    /// What the pass is called in `--time-passes`
+    fn name(&self) -> &'static str;
+
+    /// Checks `file`, the parsed items of `cx.file`
+    fn check_file<'ast>(&self, cx: &mut FileContext<'_, 'ast>, file: &'ast File);
+}
+
+/// What a pass can see of the design while checking a file, and where it reports to
+pub struct FileContext<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub file_graph: &'ast FileGraph,
+    pub file: FileId,
+    /// The module whose items are in the file, or the root of the design
+    pub scope: ResolutionIndex,
+    errors: &'a mut Vec<Diagnostic>,
+}
+
+impl FileContext<'_, '_> {
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        self.errors.push(diagnostic);
+    }
+}
+
+/// The passes registered, which are shared by clones of the options they're in
+#[derive(Default, Clone)]
+pub struct Passes(Vec<Arc<dyn Pass>>);
+
+impl Passes {
+    /// Runs `pass` after those registered before it
+    pub fn register(&mut self, pass: impl Pass + 'static) {
+        self.0.push(Arc::new(pass));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Runs each pass over each file of the design that was parsed
+    pub fn run<'ast>(
+        &self,
+        resolver: &Resolver<'ast>,
+        file_graph: &'ast FileGraph,
+        timings: &mut Timings,
+        errors: &mut Vec<Diagnostic>,
+    ) {
+        let resolution_graph = &resolver.resolution_graph;
+        let scopes = resolution_graph
+            .content_files
+            .iter()
+            .filter(|(scope, _)| !resolution_graph.is_extern(**scope))
+            .map(|(scope, file)| (*file, *scope))
+            .collect::<HashMap<_, _>>();
+        // in the order the files were found, so the passes report in the same order each time
+        let files = file_graph
+            .iter()
+            .filter_map(|file| Some((scopes.get(file)?, *file, file_graph[*file].parsed.as_ref()?)))
+            .collect::<Vec<_>>();
+        for pass in self.0.iter() {
+            let start = Instant::now();
+            for (scope, file, parsed) in files.iter() {
+                let mut cx = FileContext {
+                    resolver,
+                    file_graph,
+                    file: *file,
+                    scope: **scope,
+                    errors: &mut *errors,
+                };
+                pass.check_file(&mut cx, parsed);
+            }
+            timings.record(pass.name(), start);
+        }
+    }
+}
+
+impl Debug for Passes {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|pass| pass.name()))
+            .finish()
+    }
+}
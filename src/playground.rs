@@ -0,0 +1,24 @@
+//! The binding that the online playground calls to check a design in the browser, where there
+//! are no files to read modules from, no clock to time passes with, and no z3. The design is
+//! checked like an editor's open file is by `rhdlc lsp`.
+use std::path::PathBuf;
+
+use fxhash::FxHashMap as HashMap;
+use serde_json::Value as Json;
+
+use crate::lsp::analysis::Analysis;
+use crate::query::Database;
+
+/// Where the design is said to be, which only shows in the locations of diagnostics
+const ROOT: &str = "/playground/top.rhdl";
+
+/// Checks the design in `source`, returning its diagnostics as a JSON array of LSP diagnostics
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen::prelude::wasm_bindgen)]
+pub fn compile_str(source: &str) -> String {
+    let root = PathBuf::from(ROOT);
+    let mut documents = HashMap::default();
+    documents.insert(root.clone(), source.to_string());
+    let analysis = Analysis::run(&root, &documents, &mut Database::default());
+    let diagnostics = analysis.lsp_diagnostics().remove(&root).unwrap_or_default();
+    Json::Array(diagnostics).to_string()
+}
@@ -0,0 +1,199 @@
+//! The `rhdlc` Python module of the `python` feature, for verification flows scripted in Python to
+//! compile designs and read their diagnostics and symbols as objects instead of parsing the output
+//! of `rhdlc`. It's built into a wheel named `rhdlc-py` with `maturin build`, and typed in
+//! `rhdlc.pyi`.
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use pyo3::prelude::*;
+use serde_json::Value as Json;
+
+use crate::find_file::{rhdl_path, FileContentProvider, FileFinder, FileGraph, FileId};
+use crate::report::ErrorFormat;
+use crate::resolution::{context, Resolver};
+use crate::Options;
+
+/// A diagnostic reported by compiling a design, located by its primary label if it has one
+#[pyclass(module = "rhdlc", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// `error`, `warning`, or `note`
+    severity: String,
+    code: Option<String>,
+    /// Along with its notes, each on a line of its own
+    message: String,
+    file: Option<String>,
+    /// One-based, like the lines and columns of editors
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+#[pymethods]
+impl Diagnostic {
+    fn __repr__(&self) -> String {
+        let location = match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => format!("{}:{}:{}: ", file, line, column),
+            _ => String::new(),
+        };
+        let code = self
+            .code
+            .as_ref()
+            .map(|code| format!("[{}]", code))
+            .unwrap_or_default();
+        format!("<{}{}{}: {}>", location, self.severity, code, self.message)
+    }
+}
+
+/// What compiling a design reported
+#[pyclass(module = "rhdlc", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct Output {
+    /// The status `rhdlc` would exit with
+    status: i32,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// An item declared in a file of a design, and the items declared inside it
+#[pyclass(module = "rhdlc", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    name: String,
+    /// Like `entity` or `fn`
+    kind: String,
+    /// The name of an architecture
+    detail: Option<String>,
+    file: String,
+    /// Where its name starts
+    line: usize,
+    column: usize,
+    children: Vec<Symbol>,
+}
+
+#[pymethods]
+impl Symbol {
+    fn __repr__(&self) -> String {
+        format!(
+            "<{} {} at {}:{}:{}>",
+            self.kind, self.name, self.file, self.line, self.column
+        )
+    }
+}
+
+/// The options of `rhdlc` without any arguments, except that the config file isn't read
+fn options(top: Option<String>, libraries: Option<BTreeMap<String, PathBuf>>) -> Options {
+    Options {
+        top,
+        error_format: ErrorFormat::Sarif,
+        libraries: libraries.unwrap_or_default().into_iter().collect(),
+        library_path: rhdl_path(),
+        ..Default::default()
+    }
+}
+
+/// Compiles the design whose root file is at `path` like `rhdlc` does
+#[pyfunction]
+#[pyo3(signature = (path, top = None, libraries = None))]
+fn compile(
+    py: Python<'_>,
+    path: PathBuf,
+    top: Option<String>,
+    libraries: Option<BTreeMap<String, PathBuf>>,
+) -> Output {
+    let (log, status) = py.allow_threads(|| {
+        crate::entry_with_status(FileContentProvider::File(path), &options(top, libraries))
+    });
+    // the diagnostics are written as a SARIF log, which is read back for where they are
+    let log = serde_json::from_str::<Json>(&log).unwrap_or_default();
+    let diagnostics = log["runs"][0]["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|result| {
+            let location = &result["locations"][0]["physicalLocation"];
+            let position = |name: &str| {
+                location["region"][name]
+                    .as_u64()
+                    .map(|position| position as usize)
+            };
+            Diagnostic {
+                severity: result["level"].as_str().unwrap_or_default().to_string(),
+                code: result["ruleId"].as_str().map(str::to_string),
+                message: result["message"]["text"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                file: location["artifactLocation"]["uri"]
+                    .as_str()
+                    .map(str::to_string),
+                line: position("startLine"),
+                column: position("startColumn"),
+            }
+        })
+        .collect();
+    Output {
+        status: status.code(),
+        diagnostics,
+    }
+}
+
+/// The items declared at the top level of each file of the design whose root file is at `path`,
+/// in the order the files were found. Files that don't parse have none.
+#[pyfunction]
+#[pyo3(signature = (path, libraries = None))]
+fn symbols(
+    py: Python<'_>,
+    path: PathBuf,
+    libraries: Option<BTreeMap<String, PathBuf>>,
+) -> Vec<Symbol> {
+    py.allow_threads(|| {
+        let options = options(None, libraries);
+        let mut finder = FileFinder::default();
+        finder.libraries = options.libraries;
+        finder.library_path = options.library_path;
+        finder.find_tree(FileContentProvider::File(path));
+        let file_graph = &finder.file_graph;
+        let ctx = context();
+        let mut resolver = Resolver::build(file_graph, &ctx);
+        resolver.build_graph();
+        file_graph
+            .iter()
+            .flat_map(|file| {
+                resolver
+                    .symbols(*file)
+                    .iter()
+                    .map(|symbol| self::symbol(file_graph, *file, symbol))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    })
+}
+
+fn symbol(file_graph: &FileGraph, file: FileId, symbol: &crate::resolution::Symbol) -> Symbol {
+    let start = file_graph
+        .line_columns(file, symbol.name_span.into())
+        .map(|(start, _)| start);
+    Symbol {
+        name: symbol.name.clone(),
+        kind: symbol.kind.name().to_string(),
+        detail: symbol.detail.clone(),
+        file: file_graph.inner.name(file).to_string_lossy().to_string(),
+        line: start.map_or(0, |start| start.line),
+        column: start.map_or(0, |start| start.column),
+        children: symbol
+            .children
+            .iter()
+            .map(|child| self::symbol(file_graph, file, child))
+            .collect(),
+    }
+}
+
+#[pymodule]
+fn rhdlc(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    module.add_class::<Diagnostic>()?;
+    module.add_class::<Output>()?;
+    module.add_class::<Symbol>()?;
+    module.add_function(wrap_pyfunction!(compile, module)?)?;
+    module.add_function(wrap_pyfunction!(symbols, module)?)?;
+    Ok(())
+}
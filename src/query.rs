@@ -0,0 +1,131 @@
+//! Memoized queries over the stages of compilation, for the language server.
+//!
+//! Each query is keyed on the contents of the files it reads, so when a file changes only the
+//! queries that read it are run again:
+//!
+//! * parsing: the contents of a file → its syntax tree
+//! * checking: the contents of each file of a design → the diagnostics from resolving and
+//!   checking it, i.e. building the resolution graph, visibility, use tracing, and the checks
+//!   after them
+//!
+//! Finding the files of a design always runs, since which files there are depends on the `mod`s
+//! in them, but with parsing memoized it only reads them. The resolution graph borrows the syntax
+//! trees, so requests about a position still resolve the design again, but an edit to one design
+//! doesn't check the others again.
+//!
+//! Queries that weren't used in the last revision are dropped at the start of the next one, so
+//! the memo only holds what the open designs need.
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::File as RhdlFile;
+
+use crate::error::Diagnostic;
+use crate::find_file::FileGraph;
+
+/// The memoized results of queries
+#[derive(Default)]
+pub struct Database {
+    revision: u64,
+    parses: HashMap<String, Memo<RhdlFile>>,
+    /// Keyed on the name and contents of each file of a design, in the order they were found, so
+    /// that the file ids of the diagnostics are the same
+    checks: HashMap<Vec<(String, String)>, Memo<Vec<Diagnostic>>>,
+    pub stats: Stats,
+}
+
+/// How many times each query was run or reused in this revision
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub parsed: usize,
+    pub parses_reused: usize,
+    pub checked: usize,
+    pub checks_reused: usize,
+}
+
+struct Memo<T> {
+    /// The last revision this was used in
+    used: u64,
+    value: T,
+}
+
+impl std::fmt::Debug for Database {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Database")
+            .field("revision", &self.revision)
+            .field("parses", &self.parses.len())
+            .field("checks", &self.checks.len())
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl Database {
+    /// Starts a revision after the inputs changed, dropping what the last one didn't use
+    pub fn new_revision(&mut self) {
+        let last = self.revision;
+        self.parses.retain(|_, memo| memo.used == last);
+        self.checks.retain(|_, memo| memo.used == last);
+        self.revision += 1;
+        self.stats = Stats::default();
+    }
+
+    /// The memoized syntax tree of a file with these contents, if it was parsed before
+    pub fn parsed(&mut self, content: &str) -> Option<RhdlFile> {
+        let revision = self.revision;
+        let memo = self.parses.get_mut(content)?;
+        memo.used = revision;
+        self.stats.parses_reused += 1;
+        Some(memo.value.clone())
+    }
+
+    /// The memoized syntax tree of a file with these contents without using it, to look ahead
+    pub fn memoized_parse(&self, content: &str) -> Option<&RhdlFile> {
+        self.parses.get(content).map(|memo| &memo.value)
+    }
+
+    /// Memoizes the syntax tree of a file that was just parsed. Files that don't parse aren't
+    /// memoized so that their errors are reported each time.
+    pub fn memoize_parse(&mut self, content: &str, parsed: &RhdlFile) {
+        self.stats.parsed += 1;
+        self.parses.insert(
+            content.to_string(),
+            Memo {
+                used: self.revision,
+                value: parsed.clone(),
+            },
+        );
+    }
+
+    /// The diagnostics of the design in `file_graph`, from `check` if its files changed since it
+    /// was last checked
+    pub fn check(
+        &mut self,
+        file_graph: &FileGraph,
+        check: impl FnOnce() -> Vec<Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let revision = self.revision;
+        let key = file_graph
+            .iter()
+            .map(|file| {
+                (
+                    file_graph.inner.name(*file).to_string_lossy().to_string(),
+                    file_graph[*file].as_ref().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        if let Some(memo) = self.checks.get_mut(&key) {
+            memo.used = revision;
+            self.stats.checks_reused += 1;
+            return memo.value.clone();
+        }
+        let diagnostics = check();
+        self.stats.checked += 1;
+        self.checks.insert(
+            key,
+            Memo {
+                used: revision,
+                value: diagnostics.clone(),
+            },
+        );
+        diagnostics
+    }
+}
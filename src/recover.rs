@@ -0,0 +1,163 @@
+//! Recovering from syntax errors. The parser stops at the first one, so a file that doesn't parse
+//! is split into its top level items, which are parsed on their own to report the errors after
+//! the first and to keep the items that do parse for resolution.
+use std::ops::Range;
+
+use lalrpop_util::{lexer::Token, ParseError};
+use rhdl::ast::File;
+use rhdl::parser::FileParser;
+
+pub type Error<'input> = ParseError<usize, Token<'input>, &'static str>;
+
+/// What could be parsed of a file with syntax errors
+pub struct Recovered<'input> {
+    /// The items that parsed, or `None` if none of them did
+    pub partial: Option<File>,
+    /// The first error of the file, then the first error of each item after the one it's in
+    pub errors: Vec<Error<'input>>,
+}
+
+pub fn parse(content: &str) -> Result<File, Recovered> {
+    let first = match FileParser::new().parse(content) {
+        Ok(file) => return Ok(file),
+        Err(err) => err,
+    };
+    // the items up to the one with the first error don't have any others
+    let covered = location(&first).unwrap_or(content.len());
+    let mut recovered = Recovered {
+        partial: None,
+        errors: vec![first],
+    };
+    let mut blanked = blank(content);
+    for item in items(content) {
+        let start = item.start;
+        // the item with only spaces before it, so its locations are the same as in `content`
+        blanked.replace_range(item.clone(), &content[item.clone()]);
+        let parsed = FileParser::new()
+            .parse(&blanked[..item.end])
+            .map_err(|err| rebase(content, err));
+        blanked.replace_range(item.clone(), &blank(&content[item]));
+        match parsed {
+            Ok(file) if file.items.is_empty() => {}
+            Ok(file) => match &mut recovered.partial {
+                Some(partial) => partial.items.extend(file.items),
+                None => recovered.partial = Some(file),
+            },
+            Err(err) if start > covered => recovered.errors.push(err),
+            Err(_) => {}
+        }
+    }
+    Err(recovered)
+}
+
+/// The ranges of the top level items in `content`, each from the end of the one before it to the
+/// `;` or `}` that ends it. An item that assigns, like a `const`, only ends at a `;`. Brackets
+/// in comments, strings and char literals are skipped.
+///
+/// Items that are split wrong are merged or cut short, which at worst loses the errors after the
+/// first in them.
+pub fn items(content: &str) -> Vec<Range<usize>> {
+    let bytes = content.as_bytes();
+    let mut items = vec![];
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut assigns = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = content[i..].find('\n').map_or(bytes.len(), |end| i + end);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = content[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'\'' => {
+                // a char literal, unless it's a lifetime
+                let mut chars = content[i..].char_indices().skip(1);
+                match (chars.next(), chars.next()) {
+                    (Some((_, '\\')), _) => {
+                        i += 1;
+                        while i < bytes.len() && bytes[i] != b'\'' {
+                            i += if bytes[i] == b'\\' { 2 } else { 1 };
+                        }
+                    }
+                    (Some(_), Some((end, '\''))) => i += end,
+                    _ => {}
+                }
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b'=' if depth == 0 => assigns = true,
+            b'}' | b';' => {
+                if bytes[i] == b'}' {
+                    depth = depth.saturating_sub(1);
+                }
+                if depth == 0 && (bytes[i] == b';' || !assigns) {
+                    items.push(start..i + 1);
+                    start = i + 1;
+                    assigns = false;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if start < content.len() && !content[start..].trim().is_empty() {
+        items.push(start..content.len());
+    }
+    items
+}
+
+/// `content` with everything but line breaks replaced by spaces of the same length
+fn blank(content: &str) -> String {
+    content
+        .chars()
+        .flat_map(|c| std::iter::repeat(if c == '\n' { c } else { ' ' }).take(c.len_utf8()))
+        .collect()
+}
+
+fn location(err: &Error) -> Option<usize> {
+    match err {
+        ParseError::UnrecognizedToken {
+            token: (start, ..), ..
+        }
+        | ParseError::ExtraToken { token: (start, ..) } => Some(*start),
+        ParseError::UnrecognizedEOF { location, .. } | ParseError::InvalidToken { location } => {
+            Some(*location)
+        }
+        ParseError::User { .. } => None,
+    }
+}
+
+/// An error from parsing a blanked copy of `content` with its tokens in `content` instead
+fn rebase<'input>(content: &'input str, err: Error) -> Error<'input> {
+    match err {
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected,
+        } => ParseError::UnrecognizedToken {
+            token: (start, Token(token.0, &content[start..end]), end),
+            expected,
+        },
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => ParseError::ExtraToken {
+            token: (start, Token(token.0, &content[start..end]), end),
+        },
+        ParseError::UnrecognizedEOF { location, expected } => {
+            ParseError::UnrecognizedEOF { location, expected }
+        }
+        ParseError::InvalidToken { location } => ParseError::InvalidToken { location },
+        ParseError::User { error } => ParseError::User { error },
+    }
+}
@@ -0,0 +1,258 @@
+//! Reporting diagnostics: how they're rendered, which can be set in the `[diagnostics]` of
+//! `.rhdlc.toml` or on the command line, how many are reported, and the summary after them.
+use std::path::{Path, MAIN_SEPARATOR};
+
+use codespan_reporting::diagnostic::Severity;
+use codespan_reporting::term::{emit, termcolor::Buffer, Chars, Config};
+
+use crate::error::{self, Diagnostic};
+use crate::find_file::{FileContentProvider, FileGraph, FileId};
+use crate::status::Status;
+use crate::timing::Instant;
+use crate::{events, sarif, Options};
+
+/// How diagnostics are rendered
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Render {
+    /// The width to wrap notes at, or not at all if `None`
+    pub width: Option<usize>,
+    /// The number of lines shown around the start and end of a label that spans lines
+    pub context_lines: Option<usize>,
+    /// Draw with ASCII characters instead of box drawing ones, for logs that mangle them
+    pub ascii: bool,
+    /// Name files relative to the directory of the root file, so output can be shared without
+    /// where it was compiled
+    pub anonymize_paths: bool,
+}
+
+impl Render {
+    fn config(&self) -> Config {
+        let mut config = Config::default();
+        if let Some(context_lines) = self.context_lines {
+            config.start_context_lines = context_lines;
+            config.end_context_lines = context_lines;
+        }
+        if self.ascii {
+            config.chars = Chars::ascii();
+        }
+        config
+    }
+}
+
+/// The format diagnostics are reported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Rendered with their source for a person to read
+    Human,
+    /// A SARIF log for code scanning tools, written once all of them are reported
+    Sarif,
+    /// Events streamed as they're reported, by `--message-format json-stream`
+    JsonStream,
+}
+
+impl Default for ErrorFormat {
+    fn default() -> Self {
+        Self::Human
+    }
+}
+
+/// Emits diagnostics until the limit on errors is reached, then notes that the rest were dropped
+pub struct Reporter<'a> {
+    writer: Buffer,
+    config: Config,
+    render: Render,
+    error_format: ErrorFormat,
+    /// The diagnostics reported so far, which are only kept for SARIF
+    reported: Vec<Diagnostic>,
+    file_graph: &'a FileGraph,
+    error_limit: Option<usize>,
+    /// Only emit errors
+    quiet: bool,
+    /// When compilation started, to end with a summary
+    pub summary: Option<Instant>,
+    /// The number of errors emitted so far
+    errors: usize,
+    /// The number of errors and warnings reported so far, including those that weren't emitted
+    total_errors: usize,
+    total_warnings: usize,
+    /// The status to exit with for the diagnostics so far, including those over the limit
+    status: Status,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(file_graph: &'a FileGraph, options: &Options) -> Self {
+        Self {
+            writer: if options.color {
+                Buffer::ansi()
+            } else {
+                Buffer::no_color()
+            },
+            config: options.render.config(),
+            render: options.render.clone(),
+            error_format: options.error_format,
+            reported: vec![],
+            file_graph,
+            error_limit: options.error_limit,
+            quiet: options.quiet,
+            summary: options.summary,
+            errors: 0,
+            total_errors: 0,
+            total_warnings: 0,
+            status: Status::Success,
+        }
+    }
+
+    pub fn report(&mut self, diagnostics: &[Diagnostic]) {
+        for diagnostic in diagnostics {
+            self.status = self.status.max(Status::of(diagnostic));
+            match diagnostic.severity {
+                Severity::Bug | Severity::Error => self.total_errors += 1,
+                Severity::Warning => self.total_warnings += 1,
+                Severity::Note | Severity::Help => {}
+            }
+            if self.is_full() || (self.quiet && diagnostic.severity < Severity::Error) {
+                continue;
+            }
+            self.emit(diagnostic);
+            if diagnostic.severity >= Severity::Error {
+                self.errors += 1;
+                if let Some(limit) = self.error_limit.filter(|limit| self.errors == *limit) {
+                    self.emit(&error::too_many_errors(limit));
+                }
+            }
+        }
+    }
+
+    fn emit(&mut self, diagnostic: &Diagnostic) {
+        if self.error_format == ErrorFormat::Sarif {
+            self.reported.push(diagnostic.clone());
+            return;
+        }
+        let mut diagnostic = diagnostic.clone();
+        if let Some(width) = self.render.width {
+            for note in diagnostic.notes.iter_mut() {
+                *note = wrap(note, width);
+            }
+        }
+        if self.error_format == ErrorFormat::JsonStream {
+            let mut rendered = Buffer::no_color();
+            emit(
+                &mut rendered,
+                &self.config,
+                &self.file_graph.inner,
+                &diagnostic,
+            )
+            .unwrap();
+            events::diagnostic(
+                &diagnostic,
+                self.file_graph,
+                &String::from_utf8_lossy(rendered.as_slice()),
+            );
+            return;
+        }
+        emit(
+            &mut self.writer,
+            &self.config,
+            &self.file_graph.inner,
+            &diagnostic,
+        )
+        .unwrap();
+    }
+
+    /// A line with the number of errors and warnings, the files of the design they're in, and how
+    /// long compiling it took, like `2 errors, 1 warning in 3 files, 0.412s`. The files of the
+    /// standard library aren't counted.
+    fn summarize(&self, start: Instant) -> String {
+        let count = |count: usize, noun: &str| {
+            format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+        };
+        let files = self
+            .file_graph
+            .iter()
+            .filter(|file| {
+                !matches!(
+                    self.file_graph[**file].provider,
+                    FileContentProvider::Embedded(_)
+                )
+            })
+            .count();
+        format!(
+            "{}, {} in {}, {:.3}s\n",
+            count(self.total_errors, "error"),
+            count(self.total_warnings, "warning"),
+            count(files, "file"),
+            start.elapsed().as_secs_f64()
+        )
+    }
+
+    /// Whether the limit was reached, so nothing else will be emitted
+    pub fn is_full(&self) -> bool {
+        self.error_limit.map_or(false, |limit| self.errors >= limit)
+    }
+
+    /// The diagnostics emitted and the status to exit with for them
+    pub fn finish(self) -> (String, Status) {
+        let root_dir = self
+            .file_graph
+            .roots
+            .first()
+            .and_then(|root| {
+                Path::new(self.file_graph.inner.name(*root))
+                    .parent()
+                    .map(|dir| dir.to_string_lossy().to_string())
+            })
+            .filter(|dir| !dir.is_empty() && self.render.anonymize_paths)
+            .map(|dir| format!("{}{}", dir, MAIN_SEPARATOR));
+        match self.error_format {
+            ErrorFormat::Human => {
+                let output = String::from_utf8_lossy(self.writer.as_slice()).to_string();
+                let mut output = match root_dir {
+                    Some(root_dir) => output.replace(&root_dir, ""),
+                    None => output,
+                };
+                if let Some(start) = self.summary {
+                    output.push_str(&self.summarize(start));
+                }
+                (output, self.status)
+            }
+            ErrorFormat::Sarif => {
+                let files = &self.file_graph.inner;
+                let uri = |file: FileId| {
+                    let name = files.name(file).to_string_lossy();
+                    let name = root_dir
+                        .as_ref()
+                        .and_then(|root_dir| name.strip_prefix(root_dir.as_str()))
+                        .unwrap_or(&name);
+                    // URIs are relative to where rhdlc was run and always separated by slashes
+                    name.trim_start_matches("./").replace('\\', "/")
+                };
+                (
+                    sarif::log(&self.reported, self.file_graph, &uri),
+                    self.status,
+                )
+            }
+            // each diagnostic was written as it was reported
+            ErrorFormat::JsonStream => (String::new(), self.status),
+        }
+    }
+}
+
+/// Wraps the lines of `text` at spaces to fit in `width`, less the gutter that notes are
+/// rendered after
+fn wrap(text: &str, width: usize) -> String {
+    let width = width.saturating_sub(8).max(1);
+    let mut wrapped = vec![];
+    for line in text.lines() {
+        let mut current = String::new();
+        for word in line.split(' ') {
+            if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+                wrapped.push(std::mem::take(&mut current));
+            } else if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        wrapped.push(current);
+    }
+    wrapped.join("\n")
+}
@@ -0,0 +1,214 @@
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::{Attribute, ItemArch, Span, Spanned};
+
+use super::{
+    path::r#type::PathFinder, r#pub::VisibilitySolver, Branch, ResolutionGraph, ResolutionIndex,
+    ResolutionNode,
+};
+use crate::attribute;
+use crate::error::*;
+use crate::find_file::FileId;
+
+/// Moves each architecture from the scope it's declared in to the children of the entity it
+/// implements. Unresolved entities are left for the type existence checker to report.
+pub struct ArchitectureBinder<'a, 'ast> {
+    pub resolution_graph: &'a mut ResolutionGraph<'ast>,
+    pub vis_solver: &'a VisibilitySolver<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> ArchitectureBinder<'a, 'ast> {
+    pub fn bind_all(&mut self) {
+        let arches: Vec<(ResolutionIndex, &'ast ItemArch)> = self
+            .resolution_graph
+            .node_indices()
+            .filter_map(|i| match &self.resolution_graph[i] {
+                ResolutionNode::Branch {
+                    branch: Branch::Arch(item_arch),
+                    ..
+                } => Some((i, *item_arch)),
+                _ => None,
+            })
+            .collect();
+        let mut entities = vec![];
+        for (arch, item_arch) in arches {
+            let found = {
+                let mut path_finder = PathFinder {
+                    resolution_graph: self.resolution_graph,
+                    vis_solver: self.vis_solver,
+                    visited_glob_scopes: Default::default(),
+                };
+                path_finder
+                    .find_at_path(arch, &item_arch.entity)
+                    .unwrap_or_default()
+            };
+            let found_entities: Vec<ResolutionIndex> = found
+                .iter()
+                .copied()
+                .filter(|i| self.resolution_graph[*i].is_entity())
+                .collect();
+            if let [entity] = found_entities.as_slice() {
+                let scope = self.resolution_graph[arch].parent().unwrap();
+                if let Some(declared) = self.resolution_graph[scope]
+                    .children_mut()
+                    .and_then(|children| children.get_mut(&None))
+                {
+                    declared.retain(|child| *child != arch);
+                }
+                self.resolution_graph.add_child(*entity, arch);
+                entities.push(*entity);
+            }
+        }
+
+        entities.sort();
+        entities.dedup();
+        for entity in entities {
+            self.check_names_and_defaults(entity);
+        }
+    }
+
+    fn check_names_and_defaults(&mut self, entity: ResolutionIndex) {
+        let entity_ident = self.resolution_graph[entity].name().unwrap();
+        let mut seen_names: HashMap<String, (FileId, Span)> = HashMap::default();
+        let mut seen_default: Option<(FileId, Span)> = None;
+        let black_box = black_box(self.resolution_graph, entity)
+            .map(|attr| (self.resolution_graph.file(entity), attr.span()));
+        for arch in self.resolution_graph.architectures(entity).iter().copied() {
+            let file = self.resolution_graph.file(arch);
+            let item_arch = item_arch(self.resolution_graph, arch);
+            if let Some(black_box) = black_box {
+                self.errors.push(black_box_with_architecture(
+                    entity_ident,
+                    black_box,
+                    (file, item_arch.entity.span()),
+                ));
+            }
+            if let Some(name) = attribute::find_str(&item_arch.attrs, "name") {
+                if let Some(original) = seen_names.insert(name.inner.clone(), (file, name.span())) {
+                    self.errors.push(multiple_architecture_definition(
+                        entity_ident,
+                        &name.inner,
+                        original,
+                        (file, name.span()),
+                    ));
+                }
+            }
+            if let Some(default) = attribute::find_flag(&item_arch.attrs, "default") {
+                if let Some(original) = seen_default {
+                    self.errors.push(multiple_default_architectures(
+                        entity_ident,
+                        original,
+                        (file, default.span()),
+                    ));
+                } else {
+                    seen_default = Some((file, default.span()));
+                }
+            }
+        }
+    }
+}
+
+pub fn item_arch<'ast>(
+    resolution_graph: &ResolutionGraph<'ast>,
+    arch: ResolutionIndex,
+) -> &'ast ItemArch {
+    match &resolution_graph[arch] {
+        ResolutionNode::Branch {
+            branch: Branch::Arch(item_arch),
+            ..
+        } => item_arch,
+        _ => unreachable!(),
+    }
+}
+
+/// The `#[black_box]` attribute of an entity that is defined outside of the design,
+/// like a vendor primitive or pre-compiled IP, and so has no architecture
+pub fn black_box<'ast>(
+    resolution_graph: &ResolutionGraph<'ast>,
+    entity: ResolutionIndex,
+) -> Option<&'ast Attribute> {
+    match &resolution_graph[entity] {
+        ResolutionNode::Branch {
+            branch: Branch::Entity(item_entity),
+            ..
+        } => attribute::find_flag(&item_entity.attrs, "black_box"),
+        _ => None,
+    }
+}
+
+/// The name given to an architecture with `#[name = "..."]`, if any
+pub fn architecture_name(
+    resolution_graph: &ResolutionGraph,
+    arch: ResolutionIndex,
+) -> Option<String> {
+    attribute::find_str(&item_arch(resolution_graph, arch).attrs, "name")
+        .map(|name| name.inner.clone())
+}
+
+/// Choose the architecture elaborated for an entity:
+/// 1. The architecture explicitly requested by name
+/// 2. The only architecture of the entity
+/// 3. The architecture marked `#[default]`
+///
+/// `site` is where the selection was made, i.e. an instantiation,
+/// and `requested` is the name asked for there, with where it was asked for unless it was asked
+/// for the top entity
+pub fn select_architecture(
+    resolution_graph: &ResolutionGraph,
+    entity: ResolutionIndex,
+    requested: Option<(&str, Option<(FileId, Span)>)>,
+    site: Option<(FileId, Span)>,
+) -> Result<ResolutionIndex, Diagnostic> {
+    let entity_ident = resolution_graph[entity].name().unwrap();
+    let arches = resolution_graph.architectures(entity);
+    if let Some((requested, requested_site)) = requested {
+        return arches
+            .iter()
+            .copied()
+            .find(|arch| {
+                architecture_name(resolution_graph, *arch)
+                    .map(|name| name == requested)
+                    .unwrap_or_default()
+            })
+            .ok_or_else(|| {
+                unknown_architecture(
+                    requested_site,
+                    entity_ident,
+                    requested,
+                    arches
+                        .iter()
+                        .filter_map(|arch| architecture_name(resolution_graph, *arch))
+                        .collect(),
+                )
+            });
+    }
+    match arches {
+        [] => Err(missing_architecture(
+            site,
+            resolution_graph.file(entity),
+            entity_ident,
+        )),
+        [arch] => Ok(*arch),
+        _ => arches
+            .iter()
+            .copied()
+            .find(|arch| {
+                attribute::find_flag(&item_arch(resolution_graph, *arch).attrs, "default").is_some()
+            })
+            .ok_or_else(|| {
+                ambiguous_architecture(
+                    site,
+                    entity_ident,
+                    arches
+                        .iter()
+                        .map(|arch| {
+                            (
+                                resolution_graph.file(*arch),
+                                item_arch(resolution_graph, *arch).entity.span(),
+                            )
+                        })
+                        .collect(),
+                )
+            }),
+    }
+}
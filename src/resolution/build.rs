@@ -1,5 +1,4 @@
 use codespan_reporting::diagnostic::Diagnostic;
-use fxhash::FxHashMap as HashMap;
 
 use rhdl::{
     ast::{
@@ -27,7 +26,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
             let mod_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
                 branch: Branch::Mod(item_mod),
                 parent,
-                children: HashMap::default(),
+                children: Children::default(),
             });
             self.resolution_graph.add_child(parent, mod_idx);
             self.scope_ancestry.push(mod_idx);
@@ -86,11 +85,14 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
                 let mod_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
                     branch: Branch::Mod(item_mod),
                     parent,
-                    children: HashMap::default(),
+                    children: Children::default(),
                 });
                 self.resolution_graph
                     .content_files
                     .insert(mod_idx, file_index);
+                if self.file_graph[file_index].incomplete {
+                    self.resolution_graph.incomplete_files.insert(file_index);
+                }
                 self.resolution_graph.add_child(parent, mod_idx);
                 if let Some(parsed) = &self.file_graph[file_index].parsed {
                     self.scope_ancestry.push(mod_idx);
@@ -103,7 +105,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
                 let mod_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
                     branch: Branch::Mod(item_mod),
                     parent,
-                    children: HashMap::default(),
+                    children: Children::default(),
                 });
                 self.resolution_graph.add_child(parent, mod_idx);
             }
@@ -115,7 +117,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Use(item_use),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
     }
@@ -134,7 +136,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Fn(item_fn),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
         self.scope_ancestry.push(item_idx);
@@ -147,7 +149,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Block(block),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, idx);
         self.scope_ancestry.push(idx);
@@ -173,7 +175,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Trait(item_trait),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
         // self.scope_ancestry.push(item_idx);
@@ -190,7 +192,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Struct(item_struct),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
         self.scope_ancestry.push(item_idx);
@@ -224,7 +226,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Enum(item_enum),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
         self.scope_ancestry.push(item_idx);
@@ -241,7 +243,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Variant(variant),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
         self.scope_ancestry.push(item_idx);
@@ -255,7 +257,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Impl(item_impl),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
         self.scope_ancestry.push(item_idx);
@@ -269,9 +271,10 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
     fn visit_item_entity(&mut self, item_entity: &'ast ItemEntity) {
         let parent = *self.scope_ancestry.last().unwrap();
 
-        let item_idx = self.resolution_graph.add_node(ResolutionNode::Leaf {
-            leaf: Leaf::Entity(item_entity),
+        let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
+            branch: Branch::Entity(item_entity),
             parent,
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
     }
@@ -282,7 +285,7 @@ impl<'a, 'ast> Visit<'ast> for ScopeBuilder<'a, 'ast> {
         let item_idx = self.resolution_graph.add_node(ResolutionNode::Branch {
             branch: Branch::Arch(item_arch),
             parent,
-            children: HashMap::default(),
+            children: Children::default(),
         });
         self.resolution_graph.add_child(parent, item_idx);
         self.scope_ancestry.push(item_idx);
@@ -0,0 +1,248 @@
+//! Completion: the names that can be written at a position. Like the first segment of a path,
+//! they're looked for from the innermost scope around the position out to its module, along with
+//! the signals and generic parameters of the architecture or fn it's in and the primitive types.
+use std::ops::Range;
+
+use fxhash::FxHashSet as HashSet;
+use rhdl::{
+    ast::{File, Ident, Item, ItemMod, PatIdent, Span, Spanned},
+    visit::Visit,
+};
+
+use super::definition::contains;
+use super::highlight::node_kind;
+use super::symbols::declaration;
+use crate::find_file::FileId;
+use crate::resolution::{Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver, TokenKind};
+
+/// Primitive types that are suggested everywhere, which aren't all of them since any width works
+const PRIMITIVES: &[&str] = &[
+    "bool", "u8", "u16", "u32", "u64", "u128", "i8", "i16", "i32", "i64", "i128",
+];
+
+/// A name that can be written at a position and what it names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub name: String,
+    pub kind: TokenKind,
+}
+
+impl<'ast> Resolver<'ast> {
+    /// The names visible at byte `offset` in `file`, sorted by name. Inner scopes shadow outer
+    /// ones, and items that aren't visible from the scope aren't included.
+    pub fn completions(&self, file: FileId, offset: usize) -> Vec<Completion> {
+        let resolution_graph = &self.resolution_graph;
+        let enclosing = match self.enclosing_scope(file, offset) {
+            Some(enclosing) => enclosing,
+            None => return vec![],
+        };
+        let mut completions = vec![];
+        let mut add = |name: String, kind: Option<TokenKind>| {
+            if let Some(kind) = kind {
+                completions.push(Completion { name, kind });
+            }
+        };
+        let mut current = Some(enclosing);
+        while let Some(scope) = current {
+            for child in resolution_graph[scope]
+                .children()
+                .into_iter()
+                .flat_map(|children| children.values().flatten())
+                .copied()
+                .filter(|child| self.vis_solver.is_target_visible(enclosing, *child))
+            {
+                match &resolution_graph[child] {
+                    ResolutionNode::Branch {
+                        branch: Branch::Use(_),
+                        ..
+                    } => {
+                        for leaf in resolution_graph[child]
+                            .children()
+                            .into_iter()
+                            .flat_map(|children| children.values().flatten())
+                            .copied()
+                        {
+                            match &resolution_graph[leaf] {
+                                ResolutionNode::Leaf {
+                                    leaf: Leaf::UseGlob(_, glob_scope),
+                                    ..
+                                } => {
+                                    for (name, imported) in resolution_graph[*glob_scope]
+                                        .children()
+                                        .into_iter()
+                                        .flatten()
+                                    {
+                                        for imported in
+                                            imported.iter().copied().filter(|imported| {
+                                                self.vis_solver
+                                                    .is_target_visible(enclosing, *imported)
+                                            })
+                                        {
+                                            if let Some(name) = name {
+                                                add(
+                                                    resolution_graph
+                                                        .names
+                                                        .as_str(*name)
+                                                        .to_string(),
+                                                    node_kind(resolution_graph, imported),
+                                                );
+                                            }
+                                        }
+                                    }
+                                }
+                                other => {
+                                    if let Some(name) = other.name() {
+                                        add(name.to_string(), node_kind(resolution_graph, leaf));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    other => {
+                        if let Some(name) = other.name() {
+                            add(name.to_string(), node_kind(resolution_graph, child));
+                        }
+                    }
+                }
+            }
+            for generic_param in resolution_graph[scope]
+                .generics()
+                .into_iter()
+                .flat_map(|generics| generics.params.iter())
+            {
+                add(
+                    generic_param.ident().to_string(),
+                    Some(TokenKind::GenericParam),
+                );
+            }
+            match &resolution_graph[scope] {
+                ResolutionNode::Branch {
+                    branch: Branch::Fn(item_fn),
+                    ..
+                } => {
+                    let mut collector = LocalCollector { locals: vec![] };
+                    collector.visit_item_fn(item_fn);
+                    for local in collector.locals {
+                        let range: Range<usize> = local.span().into();
+                        if range.end <= offset {
+                            add(local.to_string(), Some(TokenKind::Variable));
+                        }
+                    }
+                }
+                ResolutionNode::Branch {
+                    branch: Branch::Arch(_),
+                    ..
+                } => {
+                    let entity = resolution_graph
+                        .entity(scope)
+                        .map(|entity| &resolution_graph[entity]);
+                    if let Some(ResolutionNode::Branch {
+                        branch: Branch::Entity(item_entity),
+                        ..
+                    }) = entity
+                    {
+                        for port in item_entity.ports.iter() {
+                            add(port.ident.to_string(), Some(TokenKind::Variable));
+                        }
+                        for generic_param in item_entity
+                            .generics
+                            .iter()
+                            .flat_map(|generics| generics.params.iter())
+                        {
+                            add(
+                                generic_param.ident().to_string(),
+                                Some(TokenKind::GenericParam),
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+            // like paths, names are only looked for up to the enclosing module
+            current = if resolution_graph[scope].is_valid_pub_path_segment() {
+                None
+            } else {
+                resolution_graph[scope].parent()
+            };
+        }
+        for primitive in PRIMITIVES {
+            completions.push(Completion {
+                name: primitive.to_string(),
+                kind: TokenKind::Type,
+            });
+        }
+
+        let mut seen = HashSet::default();
+        completions.retain(|completion| seen.insert(completion.name.clone()));
+        completions.sort_by(|a, b| a.name.cmp(&b.name));
+        completions
+    }
+
+    /// The innermost scope in `file` around byte `offset`, or the scope of the whole file
+    pub(super) fn enclosing_scope(&self, file: FileId, offset: usize) -> Option<ResolutionIndex> {
+        let resolution_graph = &self.resolution_graph;
+        let innermost = resolution_graph
+            .node_indices()
+            .filter(|node| resolution_graph[*node].children().is_some())
+            .filter(|node| {
+                !matches!(resolution_graph[*node], ResolutionNode::Root { .. })
+                    && resolution_graph.file(*node) == file
+            })
+            .filter_map(|node| scope_span(&resolution_graph[node]).map(|span| (node, span)))
+            .filter(|(_, span)| contains(*span, offset))
+            .min_by_key(|(_, span)| {
+                let range: Range<usize> = (*span).into();
+                range.len()
+            })
+            .map(|(node, _)| node);
+        innermost.or_else(|| {
+            resolution_graph
+                .content_files
+                .iter()
+                .find(|(_, content_file)| **content_file == file)
+                .map(|(scope, _)| *scope)
+        })
+    }
+}
+
+/// The span of the scope a branch opens, `None` for modules declared in other files
+pub(super) fn scope_span(node: &ResolutionNode) -> Option<Span> {
+    match node {
+        ResolutionNode::Branch {
+            branch: Branch::Mod(item_mod),
+            ..
+        } if item_mod.content.is_file() => None,
+        ResolutionNode::Branch {
+            branch: Branch::Impl(item_impl),
+            ..
+        } => Some(item_impl.span()),
+        ResolutionNode::Branch {
+            branch: Branch::Block(block),
+            ..
+        } => Some(block.span()),
+        other => declaration(other).map(|(_, span)| span),
+    }
+}
+
+/// Collects the parameters and `let` bindings of a fn, but not those of the items inside it
+struct LocalCollector<'ast> {
+    locals: Vec<&'ast Ident>,
+}
+
+impl<'ast> Visit<'ast> for LocalCollector<'ast> {
+    fn visit_file(&mut self, _file: &'ast File) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_item_mod(&mut self, _item_mod: &'ast ItemMod) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_item(&mut self, _item: &'ast Item) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_pat_ident(&mut self, pat_ident: &'ast PatIdent) {
+        self.locals.push(pat_ident);
+    }
+}
@@ -1,16 +1,18 @@
 use fxhash::FxHashMap as HashMap;
+use rayon::prelude::*;
 use rhdl::ast::ItemEntity;
 
 use rhdl::{
     ast::{
         Fields, File as RhdlFile, GenericParam, Generics, Ident, Item, ItemEnum, ItemMod, PatIdent,
-        Sig,
+        Sig, Span, Spanned, UseTreeGlob,
     },
     visit::Visit,
 };
 
+use super::r#pub::VisibilitySolver;
 use super::{Branch, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode};
-use crate::error::{reimport, Diagnostic, DuplicateHint};
+use crate::error::{redundant_import, reimport, shadowed_import, Diagnostic, DuplicateHint};
 use crate::find_file::FileId;
 
 pub struct ConflictChecker<'a, 'ast> {
@@ -24,46 +26,65 @@ struct ConflictCheckerVisitor<'a> {
 }
 
 impl<'a, 'ast> ConflictChecker<'a, 'ast> {
+    /// Scopes are checked in parallel since they're independent, and their errors are appended
+    /// in the order of the scopes so they don't depend on which finishes first
     pub fn visit_all(&mut self) {
-        for node in self.resolution_graph.node_indices() {
-            let mut visitor = ConflictCheckerVisitor {
-                errors: self.errors,
-                file: self.resolution_graph.file(node),
-            };
-            self.resolution_graph[node].visit(&mut visitor);
-            let file = match &self.resolution_graph[node] {
-                ResolutionNode::Root { .. }
-                | ResolutionNode::Branch {
-                    branch: Branch::Impl(_),
-                    ..
+        let resolution_graph = self.resolution_graph;
+        let errors = resolution_graph
+            .checked_node_indices()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|node| {
+                let mut errors = vec![];
+                ConflictChecker {
+                    resolution_graph,
+                    errors: &mut errors,
                 }
-                | ResolutionNode::Branch {
-                    branch: Branch::Trait(_),
-                    ..
-                }
-                | ResolutionNode::Branch {
-                    branch: Branch::Arch(_),
-                    ..
-                }
-                | ResolutionNode::Branch {
-                    branch: Branch::Fn(_),
-                    ..
-                } => self.resolution_graph.file(node),
-                ResolutionNode::Branch {
-                    branch: Branch::Mod(_),
-                    ..
-                } => {
-                    if let Some(content_file) = self.resolution_graph.content_files.get(&node) {
-                        *content_file
-                    } else {
-                        self.resolution_graph.file(node)
-                    }
+                .visit(node);
+                errors
+            })
+            .collect::<Vec<_>>();
+        self.errors.extend(errors.into_iter().flatten());
+    }
+
+    fn visit(&mut self, node: ResolutionIndex) {
+        let mut visitor = ConflictCheckerVisitor {
+            errors: self.errors,
+            file: self.resolution_graph.file(node),
+        };
+        self.resolution_graph[node].visit(&mut visitor);
+        let file = match &self.resolution_graph[node] {
+            ResolutionNode::Root { .. }
+            | ResolutionNode::Branch {
+                branch: Branch::Impl(_),
+                ..
+            }
+            | ResolutionNode::Branch {
+                branch: Branch::Trait(_),
+                ..
+            }
+            | ResolutionNode::Branch {
+                branch: Branch::Arch(_),
+                ..
+            }
+            | ResolutionNode::Branch {
+                branch: Branch::Fn(_),
+                ..
+            } => self.resolution_graph.file(node),
+            ResolutionNode::Branch {
+                branch: Branch::Mod(_),
+                ..
+            } => {
+                if let Some(content_file) = self.resolution_graph.content_files.get(&node) {
+                    *content_file
+                } else {
+                    self.resolution_graph.file(node)
                 }
-                _ => continue,
-            };
-            self.find_name_conflicts_in(node, file);
-            self.find_use_conflicts_in(node, file);
-        }
+            }
+            _ => return,
+        };
+        self.find_name_conflicts_in(node, file);
+        self.find_use_conflicts_in(node, file);
     }
 
     fn find_name_conflicts_in(&mut self, node: ResolutionIndex, file_id: FileId) {
@@ -90,7 +111,7 @@ impl<'a, 'ast> ConflictChecker<'a, 'ast> {
                     .for_each(|child| {
                         if let Some(with_name) = self.resolution_graph[child]
                             .children()
-                            .and_then(|children| children.get(&Some(ident)))
+                            .and_then(|children| children.get(&Some(*ident)))
                         {
                             // The child index is used here because we want to
                             // respect the position of the use in the file
@@ -204,7 +225,8 @@ impl<'a, 'ast> ConflictChecker<'a, 'ast> {
         for (name, conflicts) in name_conflicts.iter() {
             if self.resolution_graph[node]
                 .children()
-                .map(|children| children.get(&Some(name)).is_some())
+                .zip(self.resolution_graph.symbol(name))
+                .map(|(children, name)| children.get(&Some(name)).is_some())
                 .unwrap_or_default()
             {
                 continue;
@@ -221,6 +243,180 @@ impl<'a, 'ast> ConflictChecker<'a, 'ast> {
     }
 }
 
+/// Warns about imports that don't bring anything new into scope: the names of a glob that a local
+/// item shadows, and items that are already imported by a glob in the same scope or by an import
+/// in an enclosing one
+pub struct ImportChecker<'a, 'ast> {
+    pub resolution_graph: &'a ResolutionGraph<'ast>,
+    pub vis_solver: &'a VisibilitySolver<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> ImportChecker<'a, 'ast> {
+    /// Unlike conflicts, scopes are checked one at a time since the visibility solver's z3 context
+    /// can't be shared between threads
+    pub fn visit_all(&mut self) {
+        for node in self.resolution_graph.checked_node_indices() {
+            if self.resolution_graph[node].is_use() {
+                continue;
+            }
+            self.find_shadowed_globs_in(node);
+            self.find_redundant_imports_in(node);
+        }
+    }
+
+    fn find_shadowed_globs_in(&mut self, scope: ResolutionIndex) {
+        let locals = match self.resolution_graph[scope].children() {
+            Some(children) => children,
+            None => return,
+        };
+        for (glob_leaf, glob, glob_scope) in self.globs(scope) {
+            for (name, imported) in self.resolution_graph[glob_scope]
+                .children()
+                .into_iter()
+                .flatten()
+                .filter(|(name, _)| name.is_some())
+            {
+                for local in locals.get(name).into_iter().flatten().copied() {
+                    let local_ident = match self.resolution_graph[local].name() {
+                        Some(local_ident) => local_ident,
+                        None => continue,
+                    };
+                    let shadowed = imported.iter().copied().find(|imported| {
+                        self.resolution_graph[local]
+                            .in_same_name_class(&self.resolution_graph[*imported])
+                            && self.vis_solver.is_target_visible(scope, *imported)
+                    });
+                    if let Some(shadowed) = shadowed {
+                        self.errors.push(shadowed_import(
+                            self.resolution_graph.file(glob_leaf),
+                            glob,
+                            self.resolution_graph.file(local),
+                            local_ident,
+                            self.resolution_graph.file(shadowed),
+                            self.resolution_graph[shadowed].name().unwrap(),
+                            self.resolution_graph[shadowed].item_hint(),
+                        ));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Imports of the same item in the same scope are already reported by the conflict checker
+    fn find_redundant_imports_in(&mut self, scope: ResolutionIndex) {
+        for leaf in self.use_leaves(scope) {
+            let imports = match &self.resolution_graph[leaf] {
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseName(.., imports),
+                    ..
+                }
+                | ResolutionNode::Leaf {
+                    leaf: Leaf::UseRename(.., imports),
+                    ..
+                } => imports,
+                _ => continue,
+            };
+            let ident = self.resolution_graph[leaf].name().unwrap();
+            for import in imports.iter().copied() {
+                if let Some((previous_file, previous)) = self.previous_import(scope, ident, import)
+                {
+                    self.errors.push(redundant_import(
+                        self.resolution_graph.file(leaf),
+                        ident,
+                        previous_file,
+                        previous,
+                        self.resolution_graph.file(import),
+                        self.resolution_graph[import].name().unwrap(),
+                        self.resolution_graph[import].item_hint(),
+                    ));
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Where `import` is already imported as `ident` in `scope` by a glob, or in the scopes around
+    /// it up to its module, where names are looked for
+    fn previous_import(
+        &self,
+        scope: ResolutionIndex,
+        ident: &Ident,
+        import: ResolutionIndex,
+    ) -> Option<(FileId, Span)> {
+        let name = Some(self.resolution_graph.symbol(ident)?);
+        let from_glob = |scope: ResolutionIndex| {
+            self.globs(scope)
+                .into_iter()
+                .find(|(_, _, glob_scope)| {
+                    self.resolution_graph[*glob_scope]
+                        .children()
+                        .and_then(|children| children.get(&name))
+                        .map_or(false, |children| children.contains(&import))
+                })
+                .map(|(glob_leaf, glob, _)| (self.resolution_graph.file(glob_leaf), glob.span()))
+        };
+        if let Some(previous) = from_glob(scope) {
+            return Some(previous);
+        }
+        let mut current = scope;
+        while !self.resolution_graph[current].is_valid_pub_path_segment() {
+            current = self.resolution_graph[current].parent()?;
+            let named = self.use_leaves(current).into_iter().find_map(|leaf| {
+                match &self.resolution_graph[leaf] {
+                    ResolutionNode::Leaf {
+                        leaf: Leaf::UseName(.., imports),
+                        ..
+                    }
+                    | ResolutionNode::Leaf {
+                        leaf: Leaf::UseRename(.., imports),
+                        ..
+                    } if imports.contains(&import) => self.resolution_graph[leaf]
+                        .name()
+                        .filter(|previous| *previous == ident)
+                        .map(|previous| (self.resolution_graph.file(leaf), previous.span())),
+                    _ => None,
+                }
+            });
+            if let Some(previous) = named.or_else(|| from_glob(current)) {
+                return Some(previous);
+            }
+        }
+        None
+    }
+
+    /// The leaves of the uses in `scope`
+    fn use_leaves(&self, scope: ResolutionIndex) -> Vec<ResolutionIndex> {
+        self.resolution_graph[scope]
+            .children()
+            .and_then(|children| children.get(&None))
+            .into_iter()
+            .flatten()
+            .filter(|child| self.resolution_graph[**child].is_use())
+            .filter_map(|item_use| self.resolution_graph[*item_use].children())
+            .flat_map(|use_children| use_children.values().flatten().copied())
+            .collect()
+    }
+
+    /// The glob leaves of the uses in `scope`, with the scopes they import from
+    fn globs(
+        &self,
+        scope: ResolutionIndex,
+    ) -> Vec<(ResolutionIndex, &'ast UseTreeGlob, ResolutionIndex)> {
+        self.use_leaves(scope)
+            .into_iter()
+            .filter_map(|leaf| match &self.resolution_graph[leaf] {
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseGlob(glob, glob_scope),
+                    ..
+                } => Some((leaf, *glob, *glob_scope)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 impl<'a, 'ast> Visit<'ast> for ConflictCheckerVisitor<'a> {
     fn visit_file(&mut self, _file: &'ast RhdlFile) {
         // purposefully do nothing so we don't recurse out of this scope
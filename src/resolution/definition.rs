@@ -0,0 +1,217 @@
+//! Go-to-definition: the name at a position in a file is found among the declarations, uses, and
+//! type paths of the items in that file, and paths are resolved again up to the segment it's in.
+use std::ops::Range;
+
+use rhdl::{
+    ast::{
+        Block, File, Item, ItemMod, Span, Spanned, Type, TypePath, UseTreeName, UseTreeRename,
+    },
+    visit::Visit,
+};
+
+use crate::find_file::FileId;
+use crate::resolution::{
+    path::r#type::PathFinder, Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver,
+};
+
+/// Where an item is declared
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub node: ResolutionIndex,
+    pub file: FileId,
+    /// The name of the item, or the whole item if it has none
+    pub span: Span,
+}
+
+impl<'ast> Resolver<'ast> {
+    /// The items that the name at byte `offset` in `file` refers to, which are the item itself
+    /// when it's the name of a declaration
+    pub fn find_definition(&self, file: FileId, offset: usize) -> Vec<Definition> {
+        let resolution_graph = &self.resolution_graph;
+        let mut found = vec![];
+        for node in resolution_graph.node_indices() {
+            if let ResolutionNode::Root { .. } = resolution_graph[node] {
+                continue;
+            }
+            if resolution_graph.file(node) != file {
+                continue;
+            }
+            match &resolution_graph[node] {
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseName(name, targets),
+                    ..
+                } if contains(name.span(), offset) => found.extend(targets.iter().copied()),
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseRename(rename, targets),
+                    ..
+                } if contains(rename.name.span(), offset) => {
+                    found.extend(targets.iter().copied())
+                }
+                other => {
+                    if other
+                        .name()
+                        .map(|name| contains(name.span(), offset))
+                        .unwrap_or_default()
+                    {
+                        found.push(node);
+                    } else if !other.is_use() {
+                        let at_offset = paths_in(other).into_iter().find_map(|path| {
+                            path.segments
+                                .iter()
+                                .position(|segment| contains(segment.ident.span(), offset))
+                                .map(|i| (path, i + 1))
+                        });
+                        if let Some((path, len)) = at_offset {
+                            let mut path_finder = PathFinder {
+                                resolution_graph,
+                                vis_solver: &self.vis_solver,
+                                visited_glob_scopes: Default::default(),
+                            };
+                            found.extend(
+                                path_finder
+                                    .find_at_path_prefix(node, path, len)
+                                    .unwrap_or_default(),
+                            );
+                        }
+                    }
+                }
+            }
+            if !found.is_empty() {
+                break;
+            }
+        }
+        found.sort();
+        found.dedup();
+        found
+            .into_iter()
+            .filter_map(|node| self.definition(node))
+            .collect()
+    }
+
+    /// The paths in `ty`, a type in `node`, and the first item each of them resolves to. A path's
+    /// range ends at the name of its last segment, so it doesn't overlap with the paths in its
+    /// generic arguments.
+    pub fn type_references(
+        &self,
+        node: ResolutionIndex,
+        ty: &'ast Type,
+    ) -> Vec<(Range<usize>, ResolutionIndex)> {
+        let mut collector = PathCollector {
+            paths: vec![],
+            block_visited: true,
+        };
+        collector.visit_type(ty);
+        let mut references = collector
+            .paths
+            .into_iter()
+            .filter_map(|path| {
+                let mut path_finder = PathFinder {
+                    resolution_graph: &self.resolution_graph,
+                    vis_solver: &self.vis_solver,
+                    visited_glob_scopes: Default::default(),
+                };
+                let target = path_finder
+                    .find_at_path_prefix(node, path, path.segments.len())
+                    .ok()?
+                    .into_iter()
+                    .next()?;
+                let start: Range<usize> = path.span().into();
+                let end: Range<usize> = path.segments.last()?.ident.span().into();
+                Some((start.start..end.end, target))
+            })
+            .collect::<Vec<_>>();
+        references.sort_by_key(|(range, _)| range.start);
+        references
+    }
+
+    /// The declaration of `node`, `None` for roots
+    pub fn definition(&self, node: ResolutionIndex) -> Option<Definition> {
+        let resolution_graph = &self.resolution_graph;
+        let span = match (&resolution_graph[node], resolution_graph[node].name()) {
+            (_, Some(name)) => name.span(),
+            (ResolutionNode::Branch { branch, .. }, None) => match branch {
+                Branch::Impl(item_impl) => item_impl.span(),
+                Branch::Arch(item_arch) => item_arch.span(),
+                Branch::Use(item_use) => item_use.span(),
+                Branch::Block(block) => block.span(),
+                _ => return None,
+            },
+            (ResolutionNode::Leaf { leaf, .. }, None) => match leaf {
+                Leaf::UseGlob(glob, _) => glob.span(),
+                Leaf::UnnamedField(field) => field.span(),
+                _ => return None,
+            },
+            (ResolutionNode::Root { .. }, None) => return None,
+        };
+        Some(Definition {
+            node,
+            file: resolution_graph.file(node),
+            span,
+        })
+    }
+}
+
+pub(super) fn contains(span: Span, offset: usize) -> bool {
+    let range: Range<usize> = span.into();
+    range.start <= offset && offset <= range.end
+}
+
+/// The type paths in a single node, including those in the generic arguments of others
+pub(super) fn paths_in<'ast>(node: &ResolutionNode<'ast>) -> Vec<&'ast TypePath> {
+    let mut collector = PathCollector {
+        paths: vec![],
+        block_visited: !matches!(
+            node,
+            ResolutionNode::Branch {
+                branch: Branch::Block(_),
+                ..
+            }
+        ),
+    };
+    node.visit(&mut collector);
+    collector.paths
+}
+
+struct PathCollector<'ast> {
+    paths: Vec<&'ast TypePath>,
+    block_visited: bool,
+}
+
+impl<'ast> Visit<'ast> for PathCollector<'ast> {
+    fn visit_file(&mut self, _file: &'ast File) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_item_mod(&mut self, _item_mod: &'ast ItemMod) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_item(&mut self, _item: &'ast Item) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_use_tree_name(&mut self, _name: &'ast UseTreeName) {
+        // uses are resolved ahead of time
+    }
+
+    fn visit_use_tree_rename(&mut self, _rename: &'ast UseTreeRename) {
+        // uses are resolved ahead of time
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        if !self.block_visited {
+            self.block_visited = true;
+            block
+                .statements
+                .iter()
+                .for_each(|stmt| self.visit_stmt(stmt));
+        }
+    }
+
+    fn visit_type_path(&mut self, type_path: &'ast TypePath) {
+        self.paths.push(type_path);
+        for segment in type_path.segments.iter() {
+            self.visit_path_segment(segment);
+        }
+    }
+}
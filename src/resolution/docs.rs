@@ -0,0 +1,91 @@
+//! Documentation: the `///` comments above items and the `//!` comments at the top of the files
+//! of modules. The parser drops comments, so they're read from the source of the files found and
+//! attached to the nodes of the graph once it's built.
+use std::ops::Range;
+
+use rhdl::ast::Spanned;
+
+use super::symbols::declaration;
+use crate::find_file::FileGraph;
+use crate::resolution::{Leaf, ResolutionGraph, ResolutionNode};
+
+/// Attaches the documentation of each node that has any, which is read with
+/// [`ResolutionGraph::docs`]. For modules, the `///` comments above their declaration are
+/// followed by the `//!` comments at the top of their file.
+pub(super) fn attach(file_graph: &FileGraph, resolution_graph: &mut ResolutionGraph) {
+    for node in resolution_graph.node_indices() {
+        let span = match &resolution_graph[node] {
+            ResolutionNode::Leaf {
+                leaf: Leaf::UnnamedField(unnamed),
+                ..
+            } => Some(unnamed.span()),
+            other => declaration(other).map(|(_, span)| span),
+        };
+        let outer = span.and_then(|span| {
+            let span: Range<usize> = span.into();
+            doc_comment(file_graph[resolution_graph.file(node)].as_ref(), span.start)
+        });
+        let inner = resolution_graph
+            .content_files
+            .get(&node)
+            .and_then(|file| inner_doc_comment(file_graph[*file].as_ref()));
+        let docs = match (outer, inner) {
+            (Some(outer), Some(inner)) => Some(format!("{}\n\n{}", outer, inner)),
+            (outer, inner) => outer.or(inner),
+        };
+        if let Some(docs) = docs {
+            resolution_graph.docs.insert(node, docs);
+        }
+    }
+}
+
+/// The text of the `///` comments right above the item at byte `offset` of `source`, skipping
+/// attributes between them and the item, with a single leading space removed from each line
+pub fn doc_comment(source: &str, offset: usize) -> Option<String> {
+    let line_start = source[..offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    if !source[line_start..offset].trim().is_empty() {
+        return None;
+    }
+    let is_doc_or_attribute = |line: &&str| {
+        let line = line.trim_start();
+        line.starts_with("///") || line.starts_with("#[")
+    };
+    let mut above = source[..line_start]
+        .lines()
+        .rev()
+        .take_while(is_doc_or_attribute)
+        .collect::<Vec<_>>();
+    above.reverse();
+    // the span of an item may start at its doc comments or attributes
+    let docs = above
+        .into_iter()
+        .chain(source[line_start..].lines().take_while(is_doc_or_attribute))
+        .filter_map(|line| line.trim_start().strip_prefix("///"))
+        .map(|doc| doc.strip_prefix(' ').unwrap_or(doc))
+        .collect::<Vec<_>>();
+    if docs.is_empty() {
+        None
+    } else {
+        Some(docs.join("\n"))
+    }
+}
+
+/// The text of the `//!` comments at the top of `source`
+fn inner_doc_comment(source: &str) -> Option<String> {
+    let docs = source
+        .lines()
+        .map(str::trim_start)
+        .take_while(|line| line.starts_with("//!"))
+        .map(|line| {
+            let doc = &line["//!".len()..];
+            doc.strip_prefix(' ').unwrap_or(doc)
+        })
+        .collect::<Vec<_>>();
+    if docs.is_empty() {
+        None
+    } else {
+        Some(docs.join("\n"))
+    }
+}
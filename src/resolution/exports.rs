@@ -0,0 +1,114 @@
+//! The public API of a design: the items that can be named from outside of it, because they and
+//! the modules they're in are `pub` from its root, for `--emit exports`. Entities don't have a
+//! visibility, so they're exported along with the module they're in.
+use rhdl::ast::Vis;
+
+use super::symbols::{declaration, SymbolKind};
+use crate::resolution::{Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver};
+
+/// An item that can be named from outside the design
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    /// The path it's named by from the root, i.e. `cpu::Core`
+    pub path: String,
+    /// The path it's declared at, which differs from `path` when it's exported by a `pub use`
+    pub canonical: String,
+    pub kind: SymbolKind,
+    pub node: ResolutionIndex,
+}
+
+impl<'ast> Resolver<'ast> {
+    /// The items exported from the roots of the design, ordered by path. Variants are exported
+    /// with their enum. The items in impls, traits and architectures aren't listed, since they
+    /// aren't named by a path, and neither are those of the libraries it uses.
+    pub fn exports(&self) -> Vec<Export> {
+        let mut exports = vec![];
+        for root in self.resolution_graph.roots.iter() {
+            if !self.resolution_graph.is_extern(*root) {
+                self.exports_in(*root, &mut exports);
+            }
+        }
+        exports.sort_by(|a, b| {
+            (&a.path, a.kind.name(), &a.canonical).cmp(&(&b.path, b.kind.name(), &b.canonical))
+        });
+        exports
+    }
+
+    fn exports_in(&self, scope: ResolutionIndex, exports: &mut Vec<Export>) {
+        let resolution_graph = &self.resolution_graph;
+        let is_variant = |node: &ResolutionNode| {
+            matches!(
+                node,
+                ResolutionNode::Branch {
+                    branch: Branch::Variant(_),
+                    ..
+                }
+            )
+        };
+        let inherited = is_variant(&resolution_graph[scope]);
+        let children = resolution_graph[scope]
+            .children()
+            .into_iter()
+            .flat_map(|children| children.values().flatten())
+            .copied();
+        for child in children {
+            let node = &resolution_graph[child];
+            let exported = inherited
+                || is_variant(node)
+                || node.is_entity()
+                || matches!(node.visibility(), Some(Vis::Pub(_)));
+            if !exported {
+                continue;
+            }
+            let is_scope = match node {
+                ResolutionNode::Branch { branch, .. } => matches!(
+                    branch,
+                    Branch::Mod(_) | Branch::Struct(_) | Branch::Enum(_) | Branch::Variant(_)
+                ),
+                ResolutionNode::Leaf { .. } | ResolutionNode::Root { .. } => false,
+            };
+            if node.is_use() {
+                self.reexports(child, exports);
+            } else {
+                self.export(child, child, exports);
+                if is_scope {
+                    self.exports_in(child, exports);
+                }
+            }
+        }
+    }
+
+    /// The items imported by name by the `pub use` at `item_use`; glob imports aren't listed
+    fn reexports(&self, item_use: ResolutionIndex, exports: &mut Vec<Export>) {
+        let resolution_graph = &self.resolution_graph;
+        for leaf in resolution_graph[item_use]
+            .children()
+            .into_iter()
+            .flat_map(|children| children.values().flatten())
+        {
+            let targets = match &resolution_graph[*leaf] {
+                ResolutionNode::Leaf { leaf, .. } => match leaf {
+                    Leaf::UseName(_, targets) | Leaf::UseRename(_, targets) => targets,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            for target in targets {
+                self.export(*leaf, *target, exports);
+            }
+        }
+    }
+
+    /// Adds the item declared at `target` as named by `named`, if it's an item
+    fn export(&self, named: ResolutionIndex, target: ResolutionIndex, exports: &mut Vec<Export>) {
+        let resolution_graph = &self.resolution_graph;
+        if let Some((kind, _)) = declaration(&resolution_graph[target]) {
+            exports.push(Export {
+                path: resolution_graph.path(named),
+                canonical: resolution_graph.path(target),
+                kind,
+                node: target,
+            });
+        }
+    }
+}
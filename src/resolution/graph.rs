@@ -1,4 +1,5 @@
-use fxhash::FxHashMap as HashMap;
+use fxhash::{FxBuildHasher, FxHashMap as HashMap, FxHashSet as HashSet};
+use indexmap::IndexMap;
 use rhdl::{
     ast::{
         Block, File, Generics, Ident, Item, ItemArch, ItemConst, ItemEntity, ItemEnum, ItemFn,
@@ -12,6 +13,11 @@ use std::fmt::Debug;
 
 use crate::error::ItemHint;
 use crate::find_file::FileId;
+use crate::intern::{Interner, Symbol};
+
+/// The children of a scope by name, in the order they were added so that everything that goes
+/// through them visits them in the same order each time
+pub type Children = IndexMap<Option<Symbol>, Vec<ResolutionIndex>, FxBuildHasher>;
 
 #[derive(Default, Debug)]
 pub struct ResolutionGraph<'ast> {
@@ -21,6 +27,15 @@ pub struct ResolutionGraph<'ast> {
     /// if value is None, it is visible from anywhere
     pub exports: HashMap<ResolutionIndex, Option<ResolutionIndex>>,
     pub content_files: HashMap<ResolutionIndex, FileId>,
+    /// Files with syntax errors, which only the items that parsed were added from
+    pub incomplete_files: HashSet<FileId>,
+    /// Files whose diagnostics are reused from an earlier run with `--incremental`, which aren't
+    /// checked again
+    pub reused_files: HashSet<FileId>,
+    /// The documentation of the nodes that have any
+    pub docs: HashMap<ResolutionIndex, String>,
+    /// The names of the children of scopes
+    pub names: Interner,
 }
 
 impl<'ast> ResolutionGraph<'ast> {
@@ -36,15 +51,158 @@ impl<'ast> ResolutionGraph<'ast> {
 
     pub fn add_child(&mut self, parent: ResolutionIndex, child: ResolutionIndex) {
         let name = self[child].name();
+        let name = name.map(|name| self.names.intern(&name.inner));
         if let Some(children) = self[parent].children_mut() {
             children.entry(name).or_default().push(child)
         }
     }
 
+    /// The key of the children named `name`, or `None` if nothing is named it
+    pub fn symbol(&self, name: &Ident) -> Option<Symbol> {
+        self.names.get(&name.inner)
+    }
+
     pub fn node_indices(&self) -> impl Iterator<Item = ResolutionIndex> {
         (0..self.inner.len()).map(|x| ResolutionIndex(x))
     }
 
+    /// The nodes to check for errors, which are those that aren't in a reused file. A module is
+    /// checked with the file of its items.
+    pub fn checked_node_indices(&self) -> impl Iterator<Item = ResolutionIndex> + '_ {
+        self.node_indices().filter(move |node| {
+            let file = match self.content_files.get(node) {
+                Some(content_file) => *content_file,
+                None => self.file(*node),
+            };
+            !self.reused_files.contains(&file)
+        })
+    }
+
+    /// The number of edges from scopes to their children and from uses to what they import
+    pub fn edge_count(&self) -> usize {
+        self.inner
+            .iter()
+            .map(|node| match node {
+                ResolutionNode::Root { children, .. } | ResolutionNode::Branch { children, .. } => {
+                    children.values().map(Vec::len).sum()
+                }
+                ResolutionNode::Leaf { leaf, .. } => match leaf {
+                    Leaf::UseName(_, targets) | Leaf::UseRename(_, targets) => targets.len(),
+                    _ => 0,
+                },
+            })
+            .sum()
+    }
+
+    /// The names of `node` and its named ancestors from its root file, i.e. `cpu::Core`
+    pub fn path(&self, node: ResolutionIndex) -> String {
+        let mut names = vec![];
+        let mut current = Some(node);
+        while let Some(node) = current {
+            if let ResolutionNode::Root { .. } = self[node] {
+                break;
+            }
+            if let Some(name) = self[node].name() {
+                names.push(name.to_string());
+            }
+            current = self[node].parent();
+        }
+        names.reverse();
+        names.join("::")
+    }
+
+    /// The path that names `node` from anywhere it's visible, i.e. `crate::cpu::Core`, or
+    /// `uart::Rx` in a library, or `None` if it isn't an item of a module, like a field or an
+    /// import
+    pub fn canonical_path(&self, node: ResolutionIndex) -> Option<String> {
+        let mut names = vec![self[node].name()?.to_string()];
+        let mut current = self[node].parent()?;
+        loop {
+            match &self[current] {
+                ResolutionNode::Root { name, .. } => {
+                    names.push(if name.is_empty() {
+                        "crate".to_string()
+                    } else {
+                        name.clone()
+                    });
+                    break;
+                }
+                ResolutionNode::Branch {
+                    branch: Branch::Mod(m),
+                    parent,
+                    ..
+                } => {
+                    names.push(m.ident.to_string());
+                    current = *parent;
+                }
+                _ => return None,
+            }
+        }
+        names.reverse();
+        Some(names.join("::"))
+    }
+
+    /// What the import `node` resolved to, or `None` if it isn't a named or renamed import
+    pub fn use_targets(&self, node: ResolutionIndex) -> Option<&[ResolutionIndex]> {
+        match &self[node] {
+            ResolutionNode::Leaf { leaf, .. } => match leaf {
+                Leaf::UseName(_, targets) | Leaf::UseRename(_, targets) => Some(targets),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether `node` is in a library the design uses, like `std`, rather than in the design
+    pub fn is_extern(&self, node: ResolutionIndex) -> bool {
+        let mut root = node;
+        while let Some(parent) = self[root].parent() {
+            root = parent;
+        }
+        matches!(&self[root], ResolutionNode::Root { name, .. } if !name.is_empty())
+    }
+
+    /// Whether `node` is `scope` or is declared somewhere inside of it
+    pub fn is_within(&self, node: ResolutionIndex, scope: ResolutionIndex) -> bool {
+        let mut current = Some(node);
+        while let Some(node) = current {
+            if node == scope {
+                return true;
+            }
+            current = self[node].parent();
+        }
+        false
+    }
+
+    /// The architectures implementing `entity` in declaration order, which are its children
+    pub fn architectures(&self, entity: ResolutionIndex) -> &[ResolutionIndex] {
+        self[entity]
+            .children()
+            .and_then(|children| children.get(&None))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The entity `arch` implements, or `None` if it wasn't found
+    pub fn entity(&self, arch: ResolutionIndex) -> Option<ResolutionIndex> {
+        self.node_indices()
+            .find(|node| self[*node].is_entity() && self.architectures(*node).contains(&arch))
+    }
+
+    /// The doc comments of `node`, or `None` if it doesn't have any
+    pub fn docs(&self, node: ResolutionIndex) -> Option<&str> {
+        self.docs.get(&node).map(String::as_str)
+    }
+
+    /// Whether the items in `scope` are from a file with syntax errors, so some might be missing
+    pub fn is_incomplete(&self, scope: ResolutionIndex) -> bool {
+        let file = match self.content_files.get(&scope) {
+            Some(content_file) => *content_file,
+            None => self.file(scope),
+        };
+        self.incomplete_files.contains(&file)
+    }
+
     pub fn file(&self, node: ResolutionIndex) -> FileId {
         let mut next_parent = match &self[node] {
             ResolutionNode::Root { .. } => node,
@@ -107,13 +265,13 @@ pub enum ResolutionNode<'ast> {
     Root {
         /// This information comes from an external source
         name: String,
-        children: HashMap<Option<&'ast Ident>, Vec<ResolutionIndex>>,
+        children: Children,
     },
     Branch {
         parent: ResolutionIndex,
         branch: Branch<'ast>,
         /// Child branches/leaves, whether named (structs/enums/etc.) or not (impls)
-        children: HashMap<Option<&'ast Ident>, Vec<ResolutionIndex>>,
+        children: Children,
     },
     Leaf {
         parent: ResolutionIndex,
@@ -209,8 +367,8 @@ impl<'ast> ResolutionNode<'ast> {
             leaf: Leaf::TraitAlias(_),
             ..
         }
-        | ResolutionNode::Leaf {
-            leaf: Leaf::Entity(_),
+        | ResolutionNode::Branch {
+            branch: Branch::Entity(_),
             ..
         }
         | ResolutionNode::Leaf {
@@ -318,8 +476,9 @@ impl<'ast> ResolutionNode<'ast> {
             | ResolutionNode::Branch {
                 branch: Enum(_), ..
             }
-            | ResolutionNode::Leaf {
-                leaf: Entity(_), ..
+            | ResolutionNode::Branch {
+                branch: Branch::Entity(_),
+                ..
             }
             | ResolutionNode::Branch {
                 branch: Trait(_), ..
@@ -369,9 +528,21 @@ impl<'ast> ResolutionNode<'ast> {
     }
 
     pub fn is_trait_or_impl_or_arch(&self) -> bool {
-        self.is_trait()
-            || self.is_impl()
-            || matches!(self, ResolutionNode::Branch{branch: Branch::Arch(_), ..})
+        self.is_trait() || self.is_impl() || self.is_arch()
+    }
+
+    pub fn is_entity(&self) -> bool {
+        matches!(self, ResolutionNode::Branch {
+            branch: Branch::Entity(_),
+            ..
+        })
+    }
+
+    pub fn is_arch(&self) -> bool {
+        matches!(self, ResolutionNode::Branch {
+            branch: Branch::Arch(_),
+            ..
+        })
     }
 
     pub fn is_type(&self) -> bool {
@@ -390,7 +561,7 @@ impl<'ast> ResolutionNode<'ast> {
         })
     }
 
-    pub fn children(&self) -> Option<&HashMap<Option<&'ast Ident>, Vec<ResolutionIndex>>> {
+    pub fn children(&self) -> Option<&Children> {
         if let ResolutionNode::Root { children, .. } | ResolutionNode::Branch { children, .. } =
             self
         {
@@ -400,9 +571,7 @@ impl<'ast> ResolutionNode<'ast> {
         }
     }
 
-    pub fn children_mut(
-        &mut self,
-    ) -> Option<&mut HashMap<Option<&'ast Ident>, Vec<ResolutionIndex>>> {
+    pub fn children_mut(&mut self) -> Option<&mut Children> {
         if let ResolutionNode::Root { children, .. } | ResolutionNode::Branch { children, .. } =
             self
         {
@@ -432,6 +601,7 @@ impl<'ast> ResolutionNode<'ast> {
                 Branch::Enum(e) => Some(&e.ident),
                 Branch::Variant(v) => Some(&v.ident),
                 Branch::Use(_) => None,
+                Branch::Entity(e) => Some(&e.ident),
                 Branch::Arch(_) => None,
                 Branch::Block(_) => None,
             },
@@ -443,7 +613,6 @@ impl<'ast> ResolutionNode<'ast> {
                 Leaf::UseRename(r, _) => Some(&r.rename),
                 Leaf::UseName(n, _) => Some(*n),
                 Leaf::UseGlob(_, _) | Leaf::UnnamedField(_) => None,
-                Leaf::Entity(e) => Some(&e.ident),
             },
         }
     }
@@ -463,6 +632,7 @@ impl<'ast> ResolutionNode<'ast> {
                 Branch::Enum(e) => v.visit_item_enum(e),
                 Branch::Variant(var) => v.visit_variant(var),
                 Branch::Use(u) => v.visit_item_use(u),
+                Branch::Entity(e) => v.visit_item_entity(e),
                 Branch::Arch(a) => v.visit_item_arch(a),
                 Branch::Block(b) => v.visit_block(b),
             },
@@ -475,7 +645,6 @@ impl<'ast> ResolutionNode<'ast> {
                 Leaf::UseName(n, _) => v.visit_use_tree_name(n),
                 Leaf::UseRename(r, _) => v.visit_use_tree_rename(r),
                 Leaf::UseGlob(g, _) => v.visit_use_tree_glob(g),
-                Leaf::Entity(e) => v.visit_item_entity(e),
             },
         }
     }
@@ -493,6 +662,7 @@ impl<'ast> ResolutionNode<'ast> {
                 Branch::Variant(..) => Some(ItemHint::Variant),
                 Branch::Use(..) => None,
                 Branch::Block(..) => None,
+                Branch::Entity(..) => Some(ItemHint::Entity),
                 Branch::Arch(..) => Some(ItemHint::Item),
             },
             ResolutionNode::Leaf { leaf, .. } => match leaf {
@@ -504,7 +674,6 @@ impl<'ast> ResolutionNode<'ast> {
                 Leaf::UseName(..) => Some(ItemHint::Item),
                 Leaf::UseRename(..) => Some(ItemHint::Item),
                 Leaf::UseGlob(..) => None,
-                Leaf::Entity(..) => Some(ItemHint::Type),
             },
         }
     }
@@ -525,6 +694,10 @@ pub enum Branch<'ast> {
     Enum(&'ast ItemEnum),
     Variant(&'ast Variant),
     Impl(&'ast ItemImpl),
+    /// Its children are the architectures implementing it, in declaration order
+    Entity(&'ast ItemEntity),
+    /// Its parent is the scope it's declared in, which the names in it are resolved from, but it's
+    /// a child of the entity it implements once that's found
     Arch(&'ast ItemArch),
     // TODO: split trait children down into leaves
     Trait(&'ast ItemTrait),
@@ -541,7 +714,6 @@ pub enum Leaf<'ast> {
     TraitAlias(&'ast ItemTraitAlias),
     NamedField(&'ast NamedField),
     UnnamedField(&'ast UnnamedField),
-    Entity(&'ast ItemEntity),
 }
 
 macro_rules! node_only_visitor {
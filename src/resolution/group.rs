@@ -0,0 +1,200 @@
+//! Grouping the errors of resolution before they're reported. A path can be resolved from more
+//! than one scope, so identical errors are only kept once, and the uses of a name that couldn't be
+//! imported are labeled on the error about the import instead of reported on their own.
+use std::ops::Range;
+
+use codespan_reporting::diagnostic::{Label, LabelStyle};
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{Ident, Spanned, UseTree};
+
+use super::completion::scope_span;
+use super::definition::contains;
+use crate::error::{Diagnostic, UNRESOLVED_ITEM_CODE};
+use crate::find_file::FileId;
+use crate::resolution::{Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver};
+
+impl<'ast> Resolver<'ast> {
+    pub(super) fn group_errors(&mut self) {
+        let mut seen = HashSet::default();
+        self.errors.retain(|diagnostic| {
+            seen.insert((
+                diagnostic.code.clone(),
+                diagnostic.message.clone(),
+                primary_labels(diagnostic),
+            ))
+        });
+
+        let mut grouped = vec![false; self.errors.len()];
+        for use_node in self.resolution_graph.node_indices() {
+            let item_use = match &self.resolution_graph[use_node] {
+                ResolutionNode::Branch {
+                    branch: Branch::Use(item_use),
+                    ..
+                } => *item_use,
+                _ => continue,
+            };
+            let mut failed = vec![];
+            self.failed_imports(use_node, &item_use.tree, &mut failed);
+            let file = self.resolution_graph.file(use_node);
+            for (name, imported) in failed {
+                // the error about the name itself, or else about a segment of the path before it
+                let in_use = |diagnostic: &Diagnostic, span: Range<usize>| {
+                    diagnostic.code.as_deref() == Some(UNRESOLVED_ITEM_CODE)
+                        && primary_labels(diagnostic)
+                            .iter()
+                            .any(|(label_file, range)| {
+                                *label_file == file
+                                    && span.start <= range.start
+                                    && range.end <= span.end
+                            })
+                };
+                let root = (0..self.errors.len())
+                    .filter(|i| !grouped[*i])
+                    .find(|i| in_use(&self.errors[*i], imported.span().into()))
+                    .or_else(|| {
+                        (0..self.errors.len())
+                            .filter(|i| !grouped[*i])
+                            .find(|i| in_use(&self.errors[*i], item_use.span().into()))
+                    });
+                let root = match root {
+                    Some(root) => root,
+                    None => continue,
+                };
+                let uses = (0..self.errors.len())
+                    .filter(|i| *i != root && !grouped[*i])
+                    .filter(|i| self.is_unresolved_use_of(&self.errors[*i], use_node, name))
+                    .collect::<Vec<_>>();
+                if uses.is_empty() {
+                    continue;
+                }
+                let mut labels = vec![];
+                for i in uses.iter().copied() {
+                    grouped[i] = true;
+                    if let Some((use_file, range)) = primary_labels(&self.errors[i]).pop() {
+                        labels.push(
+                            Label::secondary(use_file, range)
+                                .with_message("unresolved because of the import"),
+                        );
+                    }
+                }
+                let note = if uses.len() == 1 {
+                    format!("1 other use of `{}` is unresolved because of this", name)
+                } else {
+                    format!(
+                        "{} other uses of `{}` are unresolved because of this",
+                        uses.len(),
+                        name
+                    )
+                };
+                self.errors[root].labels.append(&mut labels);
+                self.errors[root].notes.push(note);
+            }
+        }
+        let mut grouped = grouped.into_iter();
+        self.errors.retain(|_| !grouped.next().unwrap_or_default());
+    }
+
+    /// The names in `tree` that `use_node` doesn't import, with the idents they were imported by
+    fn failed_imports(
+        &self,
+        use_node: ResolutionIndex,
+        tree: &'ast UseTree,
+        failed: &mut Vec<(&'ast Ident, &'ast Ident)>,
+    ) {
+        let leaves = || {
+            self.resolution_graph[use_node]
+                .children()
+                .into_iter()
+                .flat_map(|children| children.values().flatten())
+                .map(move |leaf| &self.resolution_graph[*leaf])
+        };
+        match tree {
+            UseTree::Path(use_tree_path) => {
+                self.failed_imports(use_node, &use_tree_path.tree, failed)
+            }
+            UseTree::Group(group) => {
+                for tree in group.trees.iter() {
+                    self.failed_imports(use_node, tree, failed);
+                }
+            }
+            UseTree::Name(ident) if ident != "self" => {
+                let imported = leaves().any(|leaf| {
+                    matches!(leaf, ResolutionNode::Leaf {
+                        leaf: Leaf::UseName(name, _),
+                        ..
+                    } if std::ptr::eq(*name, ident))
+                });
+                if !imported {
+                    failed.push((ident, ident));
+                }
+            }
+            UseTree::Rename(rename) if rename.name != "self" => {
+                let imported = leaves().any(|leaf| {
+                    matches!(leaf, ResolutionNode::Leaf {
+                        leaf: Leaf::UseRename(use_tree_rename, _),
+                        ..
+                    } if std::ptr::eq(*use_tree_rename, rename))
+                });
+                if !imported {
+                    failed.push((&rename.rename, &rename.name));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether `diagnostic` is about a path starting with `name` that `use_node` would have
+    /// imported it for
+    fn is_unresolved_use_of(
+        &self,
+        diagnostic: &Diagnostic,
+        use_node: ResolutionIndex,
+        name: &Ident,
+    ) -> bool {
+        let label = match diagnostic
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+        {
+            Some(label) if diagnostic.code.as_deref() == Some(UNRESOLVED_ITEM_CODE) => label,
+            _ => return false,
+        };
+        // only the first segment of a path is looked for in the scope of the use
+        if !label.message.starts_with(&format!("no `{}` ", name)) || label.message.contains(" in `")
+        {
+            return false;
+        }
+        let scope = match self.resolution_graph[use_node].parent() {
+            Some(scope) => scope,
+            None => return false,
+        };
+        let in_scope = label.file_id == self.resolution_graph.file(use_node)
+            && scope_span(&self.resolution_graph[scope])
+                .map_or(true, |span| contains(span, label.range.start));
+        // names aren't imported into the modules inside the module of the use
+        in_scope
+            && self
+                .enclosing_scope(label.file_id, label.range.start)
+                .map(|enclosing| self.module_of(enclosing))
+                == Some(self.module_of(scope))
+    }
+
+    fn module_of(&self, mut node: ResolutionIndex) -> ResolutionIndex {
+        while !self.resolution_graph[node].is_valid_pub_path_segment() {
+            match self.resolution_graph[node].parent() {
+                Some(parent) => node = parent,
+                None => break,
+            }
+        }
+        node
+    }
+}
+
+fn primary_labels(diagnostic: &Diagnostic) -> Vec<(FileId, Range<usize>)> {
+    diagnostic
+        .labels
+        .iter()
+        .filter(|label| label.style == LabelStyle::Primary)
+        .map(|label| (label.file_id, label.range.clone()))
+        .collect()
+}
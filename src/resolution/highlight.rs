@@ -0,0 +1,316 @@
+//! Semantic highlighting: the identifiers in a file are classified by what they name, using the
+//! resolution graph for declarations, uses, and paths. Names that paths can't reach, like
+//! signals, locals, and generic parameters, are classified by where they're declared instead.
+use std::ops::Range;
+
+use rhdl::{
+    ast::{
+        Block, ExprPath, File, Generics, GenericParam, Ident, Item, ItemEntity, ItemMod, PatIdent,
+        Span, Spanned, TypePath, UseTreeName, UseTreeRename,
+    },
+    visit::Visit,
+};
+
+use crate::find_file::FileId;
+use crate::resolution::{
+    path::r#type::PathFinder, Branch, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode,
+    Resolver,
+};
+
+/// An identifier and what it names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub kind: TokenKind,
+    /// Whether this is where the name is declared
+    pub declaration: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Module,
+    Type,
+    Entity,
+    Function,
+    Const,
+    Variant,
+    Field,
+    /// Signals, i.e. ports and locals, and fn parameters
+    Variable,
+    GenericParam,
+}
+
+impl<'ast> Resolver<'ast> {
+    /// The classified identifiers in `file`, in source order
+    pub fn semantic_tokens(&self, file: FileId) -> Vec<SemanticToken> {
+        let resolution_graph = &self.resolution_graph;
+        let mut tokens = vec![];
+        // a name is classified by the first thing that claims it: its declaration, then the path
+        // it's in, and then the generic parameter or signal it could be
+        let mut fallbacks = vec![];
+        let mut expressions = vec![];
+        for node in resolution_graph.node_indices() {
+            if let ResolutionNode::Root { .. } = resolution_graph[node] {
+                continue;
+            }
+            if resolution_graph.file(node) != file {
+                continue;
+            }
+            match &resolution_graph[node] {
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseName(name, targets),
+                    ..
+                } => {
+                    if let Some(kind) = targets_kind(resolution_graph, targets) {
+                        tokens.push(token(name.span(), kind, false));
+                    }
+                    continue;
+                }
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseRename(rename, targets),
+                    ..
+                } => {
+                    if let Some(kind) = targets_kind(resolution_graph, targets) {
+                        tokens.push(token(rename.name.span(), kind, false));
+                        tokens.push(token(rename.rename.span(), kind, true));
+                    }
+                    continue;
+                }
+                other => {
+                    let kind = node_kind(resolution_graph, node);
+                    if let (Some(name), Some(kind)) = (other.name(), kind) {
+                        tokens.push(token(name.span(), kind, true));
+                    }
+                }
+            }
+            if resolution_graph[node].is_use() {
+                continue;
+            }
+
+            let mut collector = TokenCollector {
+                paths: vec![],
+                expr_paths: vec![],
+                variables: vec![],
+                generic_params: vec![],
+                block_visited: !matches!(
+                    resolution_graph[node],
+                    ResolutionNode::Branch {
+                        branch: Branch::Block(_),
+                        ..
+                    }
+                ),
+            };
+            resolution_graph[node].visit(&mut collector);
+            for variable in collector.variables {
+                tokens.push(token(variable.span(), TokenKind::Variable, true));
+            }
+            for generic_param in collector.generic_params {
+                tokens.push(token(generic_param.span(), TokenKind::GenericParam, true));
+            }
+            for path in collector.paths {
+                for (i, segment) in path.segments.iter().enumerate() {
+                    let mut path_finder = PathFinder {
+                        resolution_graph,
+                        vis_solver: &self.vis_solver,
+                        visited_glob_scopes: Default::default(),
+                    };
+                    let kind = path_finder
+                        .find_at_path_prefix(node, path, i + 1)
+                        .ok()
+                        .and_then(|found| targets_kind(resolution_graph, &found));
+                    if let Some(kind) = kind {
+                        tokens.push(token(segment.ident.span(), kind, false));
+                    } else if path.segments.len() == 1 {
+                        fallbacks.push((node, &segment.ident));
+                    }
+                }
+            }
+            expressions.extend(collector.expr_paths);
+        }
+        for (node, ident) in fallbacks {
+            if self.is_generic_param_in_scope(node, ident) {
+                tokens.push(token(ident.span(), TokenKind::GenericParam, false));
+            } else if expressions.iter().any(|expr| std::ptr::eq(*expr, ident)) {
+                tokens.push(token(ident.span(), TokenKind::Variable, false));
+            }
+        }
+
+        let mut claimed = vec![];
+        tokens.retain(|token| {
+            let range: Range<usize> = token.span.into();
+            if claimed.contains(&range) {
+                false
+            } else {
+                claimed.push(range);
+                true
+            }
+        });
+        tokens.sort_by_key(|token| {
+            let range: Range<usize> = token.span.into();
+            range.start
+        });
+        tokens
+    }
+
+    /// Whether `ident` is a generic parameter of `node` or one of its ancestors, where the
+    /// generic parameters of an architecture are those of its entity
+    fn is_generic_param_in_scope(&self, node: ResolutionIndex, ident: &Ident) -> bool {
+        let resolution_graph = &self.resolution_graph;
+        let declares = |scope: ResolutionIndex| {
+            resolution_graph[scope]
+                .generics()
+                .map(|generics| {
+                    generics.params.iter().any(|generic_param| match generic_param {
+                        GenericParam::Type(ty) => ty.ident == *ident,
+                        GenericParam::Const(cons) => cons.ident == *ident,
+                    })
+                })
+                .unwrap_or_default()
+        };
+        let mut current = Some(node);
+        while let Some(scope) = current {
+            if declares(scope) {
+                return true;
+            }
+            if resolution_graph[scope].is_arch() {
+                if resolution_graph
+                    .entity(scope)
+                    .map(declares)
+                    .unwrap_or_default()
+                {
+                    return true;
+                }
+            }
+            current = resolution_graph[scope].parent();
+        }
+        false
+    }
+}
+
+fn token(span: Span, kind: TokenKind, declaration: bool) -> SemanticToken {
+    SemanticToken {
+        span,
+        kind,
+        declaration,
+    }
+}
+
+/// What a node is named as, `None` for nodes that aren't named or that paths can't refer to
+pub(super) fn node_kind(
+    resolution_graph: &ResolutionGraph,
+    node: ResolutionIndex,
+) -> Option<TokenKind> {
+    match &resolution_graph[node] {
+        ResolutionNode::Root { .. } => Some(TokenKind::Module),
+        ResolutionNode::Branch { branch, .. } => match branch {
+            Branch::Mod(_) => Some(TokenKind::Module),
+            Branch::Fn(_) => Some(TokenKind::Function),
+            Branch::Struct(_) | Branch::Enum(_) | Branch::Trait(_) => Some(TokenKind::Type),
+            Branch::Variant(_) => Some(TokenKind::Variant),
+            Branch::Entity(_) => Some(TokenKind::Entity),
+            Branch::Use(_) | Branch::Impl(_) | Branch::Arch(_) | Branch::Block(_) => None,
+        },
+        ResolutionNode::Leaf { leaf, .. } => match leaf {
+            Leaf::Const(_) => Some(TokenKind::Const),
+            Leaf::Type(_) | Leaf::TraitAlias(_) => Some(TokenKind::Type),
+            Leaf::NamedField(_) => Some(TokenKind::Field),
+            Leaf::UseName(_, targets) | Leaf::UseRename(_, targets) => {
+                targets_kind(resolution_graph, targets)
+            }
+            Leaf::UseGlob(..) | Leaf::UnnamedField(_) => None,
+        },
+    }
+}
+
+/// The kind of the first of the items a name refers to that has one
+fn targets_kind(
+    resolution_graph: &ResolutionGraph,
+    targets: &[ResolutionIndex],
+) -> Option<TokenKind> {
+    targets
+        .iter()
+        .find_map(|target| node_kind(resolution_graph, *target))
+}
+
+/// Collects the paths in a single node, which of them are single identifier expressions, and the
+/// signals and generic parameters it declares
+struct TokenCollector<'ast> {
+    paths: Vec<&'ast TypePath>,
+    expr_paths: Vec<&'ast Ident>,
+    variables: Vec<&'ast Ident>,
+    generic_params: Vec<&'ast Ident>,
+    block_visited: bool,
+}
+
+impl<'ast> Visit<'ast> for TokenCollector<'ast> {
+    fn visit_file(&mut self, _file: &'ast File) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_item_mod(&mut self, _item_mod: &'ast ItemMod) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_item(&mut self, _item: &'ast Item) {
+        // purposefully do nothing so we don't recurse out of this scope
+    }
+
+    fn visit_use_tree_name(&mut self, _name: &'ast UseTreeName) {
+        // uses are resolved ahead of time
+    }
+
+    fn visit_use_tree_rename(&mut self, _rename: &'ast UseTreeRename) {
+        // uses are resolved ahead of time
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        if !self.block_visited {
+            self.block_visited = true;
+            block
+                .statements
+                .iter()
+                .for_each(|stmt| self.visit_stmt(stmt));
+        }
+    }
+
+    fn visit_item_entity(&mut self, item_entity: &'ast ItemEntity) {
+        if let Some(generics) = &item_entity.generics {
+            self.visit_generics(generics);
+        }
+        for port in item_entity.ports.iter() {
+            self.variables.push(&port.ident);
+            self.visit_type(&port.ty);
+        }
+    }
+
+    fn visit_generics(&mut self, generics: &'ast Generics) {
+        for generic_param in generics.params.iter() {
+            self.generic_params.push(match generic_param {
+                GenericParam::Type(ty) => &ty.ident,
+                GenericParam::Const(cons) => &cons.ident,
+            });
+            self.visit_generic_param(generic_param);
+        }
+    }
+
+    fn visit_pat_ident(&mut self, pat_ident: &'ast PatIdent) {
+        self.variables.push(pat_ident);
+    }
+
+    /// Single identifiers in expressions are signals unless they resolve
+    fn visit_expr_path(&mut self, expr_path: &'ast ExprPath) {
+        if let Some(segment) = expr_path.path.segments.first() {
+            if expr_path.path.leading_sep.is_none() && expr_path.path.segments.len() == 1 {
+                self.expr_paths.push(&segment.ident);
+            }
+        }
+        self.visit_type_path(&expr_path.path);
+    }
+
+    fn visit_type_path(&mut self, type_path: &'ast TypePath) {
+        self.paths.push(type_path);
+        for segment in type_path.segments.iter() {
+            self.visit_path_segment(segment);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Hover information: what the name at a position refers to, with its path, kind, signature, and
+//! documentation.
+use std::ops::Range;
+
+use rhdl::ast::Spanned;
+
+use super::symbols::{declaration, SymbolKind};
+use crate::find_file::FileId;
+use crate::resolution::{Branch, ResolutionIndex, ResolutionNode, Resolver};
+
+/// An item described for display
+#[derive(Debug, Clone)]
+pub struct Hover {
+    pub node: ResolutionIndex,
+    /// The path of the item from its root file, i.e. `cpu::Core`
+    pub path: String,
+    pub kind: SymbolKind,
+    /// The declaration without its body: the ports of an entity, the fields of a struct, the
+    /// variants of an enum, or the signature of a fn
+    pub signature: String,
+    /// The text of the `///` comments above the item, followed by the `//!` comments at the top
+    /// of the file of a module
+    pub docs: Option<String>,
+}
+
+impl<'ast> Resolver<'ast> {
+    /// Describes the item that the name at byte `offset` in `file` refers to, or the first of them
+    /// if it's ambiguous
+    pub fn hover(&self, file: FileId, offset: usize) -> Option<Hover> {
+        let definition = self.find_definition(file, offset).into_iter().next()?;
+        self.describe(definition.node)
+    }
+
+    /// Describes an item, `None` for nodes that aren't items that paths can refer to
+    pub fn describe(&self, node: ResolutionIndex) -> Option<Hover> {
+        let resolution_graph = &self.resolution_graph;
+        let (kind, span) = declaration(&resolution_graph[node])?;
+        let name = resolution_graph[node].name()?;
+        let source = self.file_graph[resolution_graph.file(node)].as_ref();
+        let span: Range<usize> = span.into();
+        let end = match &resolution_graph[node] {
+            ResolutionNode::Branch { branch, .. } => match branch {
+                Branch::Fn(item_fn) => {
+                    let block: Range<usize> = item_fn.block.span().into();
+                    block.start
+                }
+                Branch::Mod(_) | Branch::Trait(_) => {
+                    let name: Range<usize> = name.span().into();
+                    name.end
+                }
+                _ => span.end,
+            },
+            ResolutionNode::Leaf { .. } | ResolutionNode::Root { .. } => span.end,
+        };
+        let signature = source[span.start..end]
+            .lines()
+            .skip_while(|line| {
+                let line = line.trim_start();
+                line.starts_with("///") || line.starts_with("#[")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end()
+            .to_string();
+        Some(Hover {
+            node,
+            path: resolution_graph.path(node),
+            kind,
+            signature,
+            docs: resolution_graph.docs(node).map(str::to_string),
+        })
+    }
+}
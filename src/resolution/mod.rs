@@ -32,50 +32,119 @@
 ///             * heuristic guess by type (fn, struct, var, mod, etc.)
 ///         * fall back all the way to "not found" if nothing is similar
 use codespan_reporting::diagnostic::Diagnostic;
-use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use fxhash::FxHashSet as HashSet;
 use rhdl::{
-    ast::{ToTokens, Tok},
+    ast::{ToTokens, Tok, TypePath},
     visit::Visit,
 };
 
+use crate::attribute::LintLevels;
 use crate::find_file::{FileGraph, FileId};
+use crate::timing::{Instant, Timings};
 
 mod r#use;
 
+mod arch;
 mod build;
+mod completion;
 mod conflicts;
+mod definition;
+mod docs;
+mod exports;
 mod graph;
+mod group;
+mod highlight;
+mod hover;
+mod module_graph;
 mod path;
+mod pragma;
+mod primitive;
 mod r#pub;
+mod references;
+mod rename;
+#[cfg(test)]
+mod snapshot;
+mod symbols;
 mod type_existence;
+mod unused_pub;
 
-pub use graph::{Branch, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode};
+pub use arch::{architecture_name, black_box, item_arch, select_architecture};
+pub use completion::Completion;
+pub use definition::Definition;
+pub use docs::doc_comment;
+pub use exports::Export;
+pub use graph::{Branch, Children, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode};
+pub use highlight::{SemanticToken, TokenKind};
+pub use hover::Hover;
+pub use module_graph::{ModuleDependency, ModuleGraph};
+pub use primitive::{is_float, primitive_width};
+pub use references::References;
+pub use rename::{Edit, Rename};
+pub use symbols::{Symbol, SymbolKind};
+pub use unused_pub::UnusedPubLinter;
+
+/// What the visibility solver is built in, which a resolver borrows for as long as it lives
+#[cfg(feature = "z3")]
+pub use z3::Context;
+
+/// Without z3, visibility is checked directly and there's nothing to build it in
+#[cfg(not(feature = "z3"))]
+#[derive(Debug, Default)]
+pub struct Context;
+
+/// A context for a resolver to borrow
+#[cfg(feature = "z3")]
+pub fn context() -> Context {
+    z3::Context::new(&z3::Config::new())
+}
+
+#[cfg(not(feature = "z3"))]
+pub fn context() -> Context {
+    Context
+}
 
 #[derive(Debug)]
 pub struct Resolver<'ast> {
     file_graph: &'ast FileGraph,
     pub resolution_graph: ResolutionGraph<'ast>,
     pub errors: Vec<Diagnostic<FileId>>,
-    ctx: &'ast z3::Context,
+    ctx: &'ast Context,
     vis_solver: r#pub::VisibilitySolver<'ast>,
     resolved_uses: HashSet<ResolutionIndex>,
+    /// How long each stage of resolution took
+    pub timings: Timings,
+    /// The levels lints are set to for the whole design, for the checks after resolution
+    pub lint_levels: LintLevels,
 }
 
 impl<'ast> Resolver<'ast> {
-    pub fn build(file_graph: &'ast FileGraph, ctx: &'ast z3::Context) -> Self {
+    pub fn build(file_graph: &'ast FileGraph, ctx: &'ast Context) -> Self {
         // Stage one: add nodes
+        let mut timings = Timings::default();
+        let start = Instant::now();
         let files: Vec<FileId> = file_graph.roots.clone();
         let mut resolution_graph: ResolutionGraph<'ast> = Default::default();
         let mut errors = vec![];
         for file_index in files {
+            log::debug!(
+                "resolving {}",
+                file_graph.inner.name(file_index).to_string_lossy()
+            );
             let resolution_index = resolution_graph.add_node(ResolutionNode::Root {
-                // TODO: attach a real name
-                name: String::default(),
-                children: HashMap::default(),
+                // libraries are named by what they're imported as, and the design's root isn't
+                name: file_graph
+                    .externs
+                    .get(&file_index)
+                    .cloned()
+                    .unwrap_or_default(),
+                children: Children::default(),
             });
             resolution_graph
                 .content_files
                 .insert(resolution_index, file_index);
+            if file_graph[file_index].incomplete {
+                resolution_graph.incomplete_files.insert(file_index);
+            }
             let mut builder = build::ScopeBuilder {
                 errors: &mut errors,
                 file_graph: &file_graph,
@@ -87,19 +156,39 @@ impl<'ast> Resolver<'ast> {
                 builder.visit_file(parsed);
             }
         }
+        docs::attach(file_graph, &mut resolution_graph);
+        timings.record("graph build", start);
+        log::info!(
+            "resolution graph has {} nodes and {} edges",
+            resolution_graph.inner.len(),
+            resolution_graph.edge_count()
+        );
 
+        let start = Instant::now();
+        let vis_solver = r#pub::build_visibility_solver(&mut resolution_graph, &mut errors, ctx);
+        timings.record("visibility solver", start);
         Self {
-            vis_solver: r#pub::build_visibility_solver(&mut resolution_graph, &mut errors, ctx),
+            vis_solver,
             file_graph,
             resolution_graph,
             errors,
             ctx,
             resolved_uses: Default::default(),
+            timings,
+            lint_levels: Default::default(),
         }
     }
 
+    /// The files the design was resolved from
+    pub fn file_graph(&self) -> &'ast FileGraph {
+        self.file_graph
+    }
+
     pub fn build_graph(&mut self) {
         // // Stage three: trace use nodes
+        // Uses are traced one at a time, since tracing a use traces the uses its path goes through
+        // and the visibility solver's z3 context can't be shared between threads
+        let start = Instant::now();
         let use_indices: Vec<ResolutionIndex> = self
             .resolution_graph
             .node_indices()
@@ -114,17 +203,34 @@ impl<'ast> Resolver<'ast> {
             };
             use_resolver.resolve_use(use_index);
         }
+        self.timings.record("use tracing", start);
+        log::info!(
+            "traced {} uses, the resolution graph has {} edges",
+            self.resolved_uses.len(),
+            self.resolution_graph.edge_count()
+        );
     }
 
     pub fn check_graph(&mut self) {
+        let start = Instant::now();
         self.errors.append(&mut self.find_invalid_names());
+        self.timings.record("invalid names", start);
+        let start = Instant::now();
         {
             let mut conflict_checker = conflicts::ConflictChecker {
                 resolution_graph: &self.resolution_graph,
                 errors: &mut self.errors,
             };
             conflict_checker.visit_all();
+            let mut import_checker = conflicts::ImportChecker {
+                resolution_graph: &self.resolution_graph,
+                vis_solver: &self.vis_solver,
+                errors: &mut self.errors,
+            };
+            import_checker.visit_all();
         }
+        self.timings.record("conflicts", start);
+        let start = Instant::now();
         {
             let mut type_existence_checker = type_existence::TypeExistenceChecker {
                 resolution_graph: &self.resolution_graph,
@@ -133,23 +239,61 @@ impl<'ast> Resolver<'ast> {
             };
             type_existence_checker.visit_all();
         }
+        self.timings.record("type existence", start);
+        let start = Instant::now();
+        {
+            let mut architecture_binder = arch::ArchitectureBinder {
+                resolution_graph: &mut self.resolution_graph,
+                vis_solver: &self.vis_solver,
+                errors: &mut self.errors,
+            };
+            architecture_binder.bind_all();
+        }
+        self.timings.record("architectures", start);
+        let start = Instant::now();
+        {
+            let mut pragma_checker = pragma::PragmaChecker {
+                resolution_graph: &self.resolution_graph,
+                errors: &mut self.errors,
+            };
+            pragma_checker.visit_all();
+        }
+        self.timings.record("pragmas", start);
+        self.vis_solver.log_statistics();
+        self.group_errors();
+    }
+
+    /// Resolve a type path as if it were written in `scope`
+    pub fn find_at_type_path(
+        &self,
+        scope: ResolutionIndex,
+        path: &'ast TypePath,
+    ) -> Result<Vec<ResolutionIndex>, Diagnostic<FileId>> {
+        let mut path_finder = path::r#type::PathFinder {
+            resolution_graph: &self.resolution_graph,
+            vis_solver: &self.vis_solver,
+            visited_glob_scopes: Default::default(),
+        };
+        path_finder.find_at_path(scope, path)
+    }
+
+    /// Whether `target` can be named from `dest`, for the visibility fuzz target to check the
+    /// solver against
+    #[cfg(feature = "fuzz")]
+    pub fn is_visible(&self, dest: ResolutionIndex, target: ResolutionIndex) -> bool {
+        self.vis_solver.is_target_visible(dest, target)
     }
 
     fn find_invalid_names(&self) -> Vec<Diagnostic<FileId>> {
         let mut errors = vec![];
         for file_id in self.file_graph.iter().cloned() {
+            if self.resolution_graph.reused_files.contains(&file_id) {
+                continue;
+            }
             if let Some(parsed) = &self.file_graph[file_id].parsed {
                 for token in parsed.to_tokens() {
                     if let Tok::Ident(ident) = token {
-                        let inner = &ident.inner;
-                        // https://github.com/rust-lang/rust/blob/5ef299eb9805b4c86b227b718b39084e8bf24454/src/librustc_span/symbol.rs#L1592
-                        if inner == "r#_"
-                            || inner == "r#"
-                            || inner == "r#super"
-                            || inner == "r#self"
-                            || inner == "r#Self"
-                            || inner == "r#crate"
-                        {
+                        if is_invalid_raw_identifier(&ident.inner) {
                             errors.push(crate::error::invalid_raw_identifier(file_id, &ident));
                         }
                     }
@@ -159,3 +303,9 @@ impl<'ast> Resolver<'ast> {
         errors
     }
 }
+
+/// Keywords that can't be raw identifiers either
+fn is_invalid_raw_identifier(inner: &str) -> bool {
+    // https://github.com/rust-lang/rust/blob/5ef299eb9805b4c86b227b718b39084e8bf24454/src/librustc_span/symbol.rs#L1592
+    matches!(inner, "r#_" | "r#" | "r#super" | "r#self" | "r#Self" | "r#crate")
+}
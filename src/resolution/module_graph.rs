@@ -0,0 +1,124 @@
+//! Which modules of a design import items from which other modules, for `--emit module-graph`.
+//! An import depends on the module the item it resolved to is declared in, or on the module it
+//! imports, and a glob import on the module it imports from.
+use std::collections::BTreeMap;
+
+use crate::resolution::{Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver};
+
+/// The modules of a design and the dependencies between them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleGraph {
+    /// The paths of the modules of the design, sorted, which doesn't include the libraries it
+    /// depends on
+    pub modules: Vec<String>,
+    pub dependencies: Vec<ModuleDependency>,
+}
+
+/// The imports of one module from another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleDependency {
+    /// The path of the importing module, i.e. `crate::cpu`
+    pub from: String,
+    /// The path of the imported module, i.e. `crate::alu` or `uart`
+    pub to: String,
+    /// The names imported, i.e. `Alu`, `Op as AluOp`, or `*`, sorted and without duplicates
+    pub names: Vec<String>,
+}
+
+impl<'ast> Resolver<'ast> {
+    /// The dependencies between the modules of the design and on the libraries it uses, ordered
+    /// by the paths of the modules. Imports of items in the same module aren't listed.
+    pub fn module_graph(&self) -> ModuleGraph {
+        let resolution_graph = &self.resolution_graph;
+        let mut modules = resolution_graph
+            .node_indices()
+            .filter(|node| {
+                matches!(
+                    resolution_graph[*node],
+                    ResolutionNode::Root { .. }
+                        | ResolutionNode::Branch {
+                            branch: Branch::Mod(_),
+                            ..
+                        }
+                ) && !resolution_graph.is_extern(*node)
+            })
+            .map(|module| self.module_path(module))
+            .collect::<Vec<_>>();
+        modules.sort();
+        modules.dedup();
+        let mut dependencies: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+        for node in resolution_graph.node_indices() {
+            let (name, targets) = match &resolution_graph[node] {
+                ResolutionNode::Leaf { leaf, .. } => match leaf {
+                    Leaf::UseName(name, targets) => (name.to_string(), targets.clone()),
+                    Leaf::UseRename(rename, targets) => (
+                        format!("{} as {}", rename.name, rename.rename),
+                        targets.clone(),
+                    ),
+                    Leaf::UseGlob(_, scope) => ("*".to_string(), vec![*scope]),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if resolution_graph.is_extern(node) {
+                continue;
+            }
+            let from = match resolution_graph[node].parent() {
+                Some(parent) => self.module_of(parent),
+                None => continue,
+            };
+            for target in targets {
+                let to = self.module_of(target);
+                if to == from {
+                    continue;
+                }
+                dependencies
+                    .entry((self.module_path(from), self.module_path(to)))
+                    .or_default()
+                    .push(name.clone());
+            }
+        }
+        let dependencies = dependencies
+            .into_iter()
+            .map(|((from, to), mut names)| {
+                names.sort();
+                names.dedup();
+                ModuleDependency { from, to, names }
+            })
+            .collect();
+        ModuleGraph {
+            modules,
+            dependencies,
+        }
+    }
+
+    /// `node` if it's a module, or else the module it's declared in
+    fn module_of(&self, node: ResolutionIndex) -> ResolutionIndex {
+        let resolution_graph = &self.resolution_graph;
+        let mut current = node;
+        loop {
+            match &resolution_graph[current] {
+                ResolutionNode::Root { .. }
+                | ResolutionNode::Branch {
+                    branch: Branch::Mod(_),
+                    ..
+                } => return current,
+                ResolutionNode::Branch { parent, .. } | ResolutionNode::Leaf { parent, .. } => {
+                    current = *parent
+                }
+            }
+        }
+    }
+
+    /// The path that names `module` from anywhere, i.e. `crate::cpu`, or the name of a library
+    fn module_path(&self, module: ResolutionIndex) -> String {
+        let resolution_graph = &self.resolution_graph;
+        match &resolution_graph[module] {
+            ResolutionNode::Root { name, .. } if name.is_empty() => "crate".to_string(),
+            ResolutionNode::Root { name, .. } => name.clone(),
+            _ => resolution_graph
+                .canonical_path(module)
+                .unwrap_or_else(|| resolution_graph.path(module)),
+        }
+    }
+}
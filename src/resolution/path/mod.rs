@@ -1,9 +1,12 @@
 use crate::resolution::r#pub::VisibilitySolver;
+use codespan_reporting::diagnostic::Label;
+use fxhash::FxHashSet as HashSet;
 use rhdl::ast::Ident;
 use rhdl::ast::PathSep;
+use rhdl::ast::Spanned;
 
 use crate::error::*;
-use crate::resolution::{FileId, ResolutionGraph, ResolutionIndex};
+use crate::resolution::{FileId, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode};
 
 pub mod simple;
 pub mod r#type;
@@ -14,6 +17,8 @@ pub struct TracingContext<'ast> {
     pub dest: ResolutionIndex,
     pub previous_idents: Vec<&'ast Ident>,
     pub leading_sep: Option<&'ast PathSep>,
+    /// Labels for the uses that the previous idents were found through
+    pub hops: Vec<Label<FileId>>,
 }
 
 impl<'ast> TracingContext<'ast> {
@@ -32,6 +37,7 @@ impl<'ast> TracingContext<'ast> {
             root,
             previous_idents: vec![],
             leading_sep,
+            hops: vec![],
         }
     }
 }
@@ -206,3 +212,120 @@ fn find_children_from_globs<'ast>(
         Ok(local_from_globs)
     }
 }
+
+/// Labels the `use`s in `scope` that a segment of a path was found through when it isn't declared
+/// there, so an error about a later segment shows how the path got to the scope it's looked for in
+fn use_hops(
+    resolution_graph: &ResolutionGraph,
+    scope: ResolutionIndex,
+    found: &[ResolutionIndex],
+) -> Vec<Label<FileId>> {
+    let mut leaves = vec![];
+    find_use_hops(
+        resolution_graph,
+        scope,
+        found,
+        &mut HashSet::default(),
+        &mut leaves,
+    );
+    leaves
+        .into_iter()
+        .filter_map(|leaf| {
+            let span = match &resolution_graph[leaf] {
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseName(name, _),
+                    ..
+                } => name.span(),
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseRename(rename, _),
+                    ..
+                } => rename.span(),
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseGlob(glob, _),
+                    ..
+                } => glob.span(),
+                _ => return None,
+            };
+            let is_reexport = resolution_graph[leaf].parent().map_or(false, |item_use| {
+                resolution_graph[item_use].visibility().is_some()
+            });
+            Some(
+                Label::secondary(resolution_graph.file(leaf), span).with_message(if is_reexport {
+                    "re-exported here"
+                } else {
+                    "imported here"
+                }),
+            )
+        })
+        .collect()
+}
+
+/// The use leaves followed from `scope` to `found`, outermost first, which are empty if it's
+/// declared in `scope`
+fn find_use_hops(
+    resolution_graph: &ResolutionGraph,
+    scope: ResolutionIndex,
+    found: &[ResolutionIndex],
+    visited_glob_scopes: &mut HashSet<ResolutionIndex>,
+    leaves: &mut Vec<ResolutionIndex>,
+) -> bool {
+    let children = match resolution_graph[scope].children() {
+        Some(children) => children,
+        None => return false,
+    };
+    if children
+        .values()
+        .flatten()
+        .any(|child| found.contains(child))
+    {
+        return true;
+    }
+    let use_leaves = children
+        .get(&None)
+        .into_iter()
+        .flatten()
+        .filter(|child| resolution_graph[**child].is_use())
+        .filter_map(|item_use| resolution_graph[*item_use].children())
+        .flat_map(|use_children| use_children.values().flatten().copied())
+        .collect::<Vec<_>>();
+    for leaf in use_leaves.iter().copied() {
+        match &resolution_graph[leaf] {
+            ResolutionNode::Leaf {
+                leaf: Leaf::UseName(_, imports),
+                ..
+            }
+            | ResolutionNode::Leaf {
+                leaf: Leaf::UseRename(_, imports),
+                ..
+            } if imports.iter().any(|import| found.contains(import)) => {
+                leaves.push(leaf);
+                return true;
+            }
+            _ => {}
+        }
+    }
+    // names are only found through globs when they aren't imported by name
+    for leaf in use_leaves {
+        if let ResolutionNode::Leaf {
+            leaf: Leaf::UseGlob(_, glob_scope),
+            ..
+        } = &resolution_graph[leaf]
+        {
+            if !visited_glob_scopes.insert(*glob_scope) {
+                continue;
+            }
+            leaves.push(leaf);
+            if find_use_hops(
+                resolution_graph,
+                *glob_scope,
+                found,
+                visited_glob_scopes,
+                leaves,
+            ) {
+                return true;
+            }
+            leaves.pop();
+        }
+    }
+    false
+}
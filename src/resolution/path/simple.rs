@@ -1,5 +1,6 @@
 //! This is ONLY meant to be used for use-tracing, which is a niche case
 
+use codespan_reporting::diagnostic::Label;
 use fxhash::FxHashSet as HashSet;
 
 use rhdl::{
@@ -9,7 +10,9 @@ use rhdl::{
     visit::Visit,
 };
 
-use super::super::{r#use::UseResolver, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode};
+use super::super::{
+    r#use::UseResolver, FileId, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode,
+};
 use super::TracingContext;
 use crate::error::*;
 use crate::resolution::r#pub::VisibilitySolver;
@@ -23,10 +26,13 @@ pub struct PathFinder<'a, 'ast> {
 }
 
 impl<'a, 'ast> PathFinder<'a, 'ast> {
+    /// Labels for the uses that the segments of `path` are found through are added to `hops`,
+    /// which are attached to the error if a segment isn't found
     pub fn find_at_path(
         &mut self,
         dest: ResolutionIndex,
         path: &'ast Path,
+        hops: &mut Vec<Label<FileId>>,
     ) -> Result<Vec<ResolutionIndex>, Diagnostic> {
         self.visited_glob_scopes.clear();
         let mut ctx = TracingContext::new(self.resolution_graph, dest, path.leading_sep.as_ref());
@@ -58,7 +64,21 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                 })
                 .collect();
             if results.iter().all(|res| res.is_err()) {
-                return results.drain(..).next().unwrap();
+                let incomplete = scopes
+                    .iter()
+                    .any(|scope| self.resolution_graph.is_incomplete(*scope));
+                return results.drain(..).next().unwrap().map_err(|mut err| {
+                    err.labels.extend(hops.iter().cloned());
+                    if incomplete {
+                        err = in_incomplete_scope(err);
+                    }
+                    err
+                });
+            }
+            for (scope, found) in scopes.iter().zip(results.iter()) {
+                if let Ok(found) = found {
+                    hops.append(&mut super::use_hops(self.resolution_graph, *scope, found));
+                }
             }
             scopes = results
                 .drain(..)
@@ -87,8 +107,10 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
         } else {
             let local = if !is_entry || ctx.leading_sep.is_none() {
                 if let Some(children) = self.resolution_graph[scope].children() {
-                    let mut local = children
-                        .get(&Some(ident))
+                    let mut local = self
+                        .resolution_graph
+                        .symbol(ident)
+                        .and_then(|name| children.get(&Some(name)))
                         .map(|children_with_name| {
                             children_with_name
                                 .iter()
@@ -123,6 +145,10 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                     .iter()
                     .copied()
                     .filter(|child| *child != ctx.root)
+                    .filter(|child| match &self.resolution_graph[*child] {
+                        ResolutionNode::Root { name, .. } => ident == name.as_str(),
+                        _ => false,
+                    })
                     .filter(|child| {
                         !paths_only || self.resolution_graph[*child].is_valid_use_path_segment()
                     })
@@ -229,8 +255,10 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                             self.visited_glob_scopes.insert(glob);
                             let glob_src_children = self.resolution_graph[glob].children().unwrap();
                             matches.append(
-                                &mut glob_src_children
-                                    .get(&Some(ident_to_look_for))
+                                &mut self
+                                    .resolution_graph
+                                    .symbol(ident_to_look_for)
+                                    .and_then(|name| glob_src_children.get(&Some(name)))
                                     .map(|glob_src_children_with_name| {
                                         glob_src_children_with_name
                                             .iter()
@@ -271,8 +299,9 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                     .unwrap_or_default();
                 matches
             } else {
-                use_children
-                    .get(&Some(ident_to_look_for))
+                self.resolution_graph
+                    .symbol(ident_to_look_for)
+                    .and_then(|name| use_children.get(&Some(name)))
                     .map(|named| {
                         named
                             .iter()
@@ -18,6 +18,16 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
         &mut self,
         dest: ResolutionIndex,
         path: &'a TypePath,
+    ) -> Result<Vec<ResolutionIndex>, Diagnostic> {
+        self.find_at_path_prefix(dest, path, path.segments.len())
+    }
+
+    /// Resolve only the first `len` segments of `path`, i.e. `a::b` of `a::b::C`
+    pub fn find_at_path_prefix(
+        &mut self,
+        dest: ResolutionIndex,
+        path: &'a TypePath,
+        len: usize,
     ) -> Result<Vec<ResolutionIndex>, Diagnostic> {
         self.visited_glob_scopes.clear();
         let mut ctx = TracingContext::new(self.resolution_graph, dest, path.leading_sep.as_ref());
@@ -60,21 +70,15 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
         // DFS from each scope, followed by a check on that scope's generics
         for scope in scopes.iter().rev().copied() {
             let mut dfs_state = vec![scope];
-            for (i, segment) in path.segments.iter().enumerate() {
+            let mut hops = vec![];
+            for (i, segment) in path.segments.iter().take(len).enumerate() {
                 // already seeded earlier
                 if i == 0 && segment.ident == "Self" {
                     continue;
                 }
                 let mut results: Vec<Result<Vec<ResolutionIndex>, Diagnostic>> = dfs_state
                     .iter()
-                    .map(|scope| {
-                        self.find_children(
-                            &ctx,
-                            *scope,
-                            &segment.ident,
-                            i + 1 != path.segments.len(),
-                        )
-                    })
+                    .map(|scope| self.find_children(&ctx, *scope, &segment.ident, i + 1 != len))
                     .collect();
 
                 if i == 0 {
@@ -94,7 +98,21 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                         return Err(err.clone());
                     }
                 } else if results.iter().all(|res| res.is_err()) {
-                    return results.first().unwrap().clone();
+                    let incomplete = dfs_state
+                        .iter()
+                        .any(|scope| self.resolution_graph.is_incomplete(*scope));
+                    return results.first().unwrap().clone().map_err(|mut err| {
+                        err.labels.append(&mut hops);
+                        if incomplete {
+                            err = in_incomplete_scope(err);
+                        }
+                        err
+                    });
+                }
+                for (scope, found) in dfs_state.iter().zip(results.iter()) {
+                    if let Ok(found) = found {
+                        hops.append(&mut super::use_hops(self.resolution_graph, *scope, found));
+                    }
                 }
                 dfs_state = results
                     .drain(..)
@@ -124,13 +142,15 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                 }
             }
         }
-        return Err(unresolved_item(
-            ctx.file,
-            None,
-            &first.ident,
-            ItemHint::Item,
-            vec![],
-        ));
+        let err = unresolved_item(ctx.file, None, &first.ident, ItemHint::Item, vec![]);
+        if scopes
+            .iter()
+            .any(|scope| self.resolution_graph.is_incomplete(*scope))
+        {
+            Err(in_incomplete_scope(err))
+        } else {
+            Err(err)
+        }
     }
 
     pub fn find_children(
@@ -149,8 +169,10 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
         } else {
             let local = if !is_entry || ctx.leading_sep.is_none() {
                 if let Some(children) = self.resolution_graph[scope].children() {
-                    let mut local = children
-                        .get(&Some(ident))
+                    let mut local = self
+                        .resolution_graph
+                        .symbol(ident)
+                        .and_then(|name| children.get(&Some(name)))
                         .map(|children_with_name| {
                             children_with_name
                                 .iter()
@@ -185,6 +207,10 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                     .roots
                     .iter()
                     .filter(|child| **child != ctx.root)
+                    .filter(|child| match &self.resolution_graph[**child] {
+                        ResolutionNode::Root { name, .. } => ident == name.as_str(),
+                        _ => false,
+                    })
                     .filter(|child| {
                         !paths_only || self.resolution_graph[**child].is_valid_type_path_segment()
                     })
@@ -271,8 +297,10 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                             self.visited_glob_scopes.insert(glob);
                             let glob_src_children = self.resolution_graph[glob].children().unwrap();
                             matches.append(
-                                &mut glob_src_children
-                                    .get(&Some(ident_to_look_for))
+                                &mut self
+                                    .resolution_graph
+                                    .symbol(ident_to_look_for)
+                                    .and_then(|name| glob_src_children.get(&Some(name)))
                                     .map(|glob_src_children_with_name| {
                                         glob_src_children_with_name
                                             .iter()
@@ -311,8 +339,9 @@ impl<'a, 'ast> PathFinder<'a, 'ast> {
                     .unwrap_or_default();
                 matches
             } else {
-                use_children
-                    .get(&Some(ident_to_look_for))
+                self.resolution_graph
+                    .symbol(ident_to_look_for)
+                    .and_then(|name| use_children.get(&Some(name)))
                     .map(|named| {
                         named
                             .iter()
@@ -0,0 +1,89 @@
+use rhdl::{
+    ast::{Attribute, Local},
+    visit::Visit,
+};
+
+use super::{Branch, ResolutionGraph, ResolutionNode};
+use crate::attribute::{pragma_names, synthesis_pragmas, PragmaError, PragmaTarget};
+use crate::error::*;
+use crate::find_file::FileId;
+
+/// Checks that the items of `#[synthesis(...)]` attributes are known pragmas,
+/// with a value only if they take one, applied to the signals or entities they apply to
+pub struct PragmaChecker<'a, 'ast> {
+    pub resolution_graph: &'a ResolutionGraph<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> PragmaChecker<'a, 'ast> {
+    pub fn visit_all(&mut self) {
+        for node in self.resolution_graph.checked_node_indices() {
+            let file = self.resolution_graph.file(node);
+            match &self.resolution_graph[node] {
+                ResolutionNode::Branch {
+                    branch: Branch::Entity(item_entity),
+                    ..
+                } => {
+                    check_pragmas(file, &item_entity.attrs, PragmaTarget::Entity, self.errors);
+                    for port in item_entity.ports.iter() {
+                        check_pragmas(file, &port.attrs, PragmaTarget::Signal, self.errors);
+                    }
+                }
+                ResolutionNode::Branch {
+                    branch: Branch::Arch(_),
+                    ..
+                } => {
+                    let mut local_visitor = LocalVisitor {
+                        file,
+                        errors: self.errors,
+                    };
+                    self.resolution_graph[node].visit(&mut local_visitor);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+struct LocalVisitor<'a> {
+    file: FileId,
+    errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> Visit<'ast> for LocalVisitor<'a> {
+    fn visit_local(&mut self, local: &'ast Local) {
+        check_pragmas(self.file, &local.attrs, PragmaTarget::Signal, self.errors);
+        if let Some((_, init)) = &local.init {
+            self.visit_expr(init);
+        }
+    }
+}
+
+fn check_pragmas(
+    file: FileId,
+    attrs: &[Attribute],
+    target: PragmaTarget,
+    errors: &mut Vec<Diagnostic>,
+) {
+    for (span, error) in synthesis_pragmas(attrs, target)
+        .into_iter()
+        .filter_map(Result::err)
+    {
+        errors.push(match error {
+            PragmaError::Unknown => unknown_synthesis_pragma(file, span, &pragma_names()),
+            PragmaError::Value { name, takes_value } => {
+                invalid_synthesis_pragma_value(file, span, name, takes_value)
+            }
+            PragmaError::Target { name, targets } => misplaced_synthesis_pragma(
+                file,
+                span,
+                name,
+                &targets
+                    .iter()
+                    .map(|target| target.name())
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+            ),
+        });
+    }
+}
@@ -0,0 +1,18 @@
+//! Primitive types are always in scope unless shadowed by an item with the same name.
+
+/// The width in bits of a primitive type like `bool`, `u8`, or `i32`
+pub fn primitive_width(name: &str) -> Option<u128> {
+    if name == "bool" {
+        return Some(1);
+    }
+    let bits = name.strip_prefix('u').or_else(|| name.strip_prefix('i'))?;
+    if bits.starts_with('0') {
+        return None;
+    }
+    bits.parse::<u128>().ok().filter(|bits| *bits > 0)
+}
+
+/// Whether a primitive type is `f32` or `f64`, which exist but can't be synthesized
+pub fn is_float(name: &str) -> bool {
+    name == "f32" || name == "f64"
+}
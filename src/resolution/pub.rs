@@ -1,14 +1,18 @@
+use std::cell::Cell;
+
 use rhdl::ast::{Spanned, Vis, VisRestricted};
-use z3::{ast::*, Context, Solver, Sort};
+#[cfg(feature = "z3")]
+use z3::{ast::*, Sort};
 
-use super::{Branch, Leaf, ResolutionGraph, ResolutionIndex, ResolutionNode};
+use super::{Branch, Context, ResolutionGraph, ResolutionIndex, ResolutionNode};
 use crate::error::*;
 use crate::find_file::FileId;
 
+#[cfg(feature = "z3")]
 #[derive(Debug)]
 pub struct VisibilitySolver<'ast> {
     ctx: &'ast Context,
-    solver: Solver<'ast>,
+    solver: z3::Solver<'ast>,
     nodes: Vec<Dynamic<'ast>>,
     /// Since this isn't a crate root, name it `base` to avoid confusion
     base: Dynamic<'ast>,
@@ -16,8 +20,12 @@ pub struct VisibilitySolver<'ast> {
     parents: Array<'ast>,
     children: Array<'ast>,
     exports: Array<'ast>,
+    /// The number of visibility queries checked and how many were visible, for `-v`
+    queries: Cell<usize>,
+    visible: Cell<usize>,
 }
 
+#[cfg(feature = "z3")]
 impl<'ast> VisibilitySolver<'ast> {
     /// Possibilities:
     /// 1. Target is exported to grandparent scope (implicitly assume the visibility of the grandparent scope was already checked)
@@ -63,10 +71,65 @@ impl<'ast> VisibilitySolver<'ast> {
             Unsat | Unknown => false,
         };
         self.solver.pop(1);
+        self.count(visible)
+    }
+}
+
+/// Answers the same queries as the z3 solver by checking each possibility directly, for builds
+/// without z3 like the one for the browser
+#[cfg(not(feature = "z3"))]
+#[derive(Debug)]
+pub struct VisibilitySolver<'ast> {
+    /// The scopes each node is inside of, from its parent up to its root
+    ancestry: Vec<Vec<ResolutionIndex>>,
+    exports: Vec<Option<ResolutionIndex>>,
+    /// The number of visibility queries checked and how many were visible, for `-v`
+    queries: Cell<usize>,
+    visible: Cell<usize>,
+    ctx: std::marker::PhantomData<&'ast Context>,
+}
+
+#[cfg(not(feature = "z3"))]
+impl<'ast> VisibilitySolver<'ast> {
+    pub fn is_target_visible(&self, dest: ResolutionIndex, target: ResolutionIndex) -> bool {
+        let ancestry = |node: ResolutionIndex| &self.ancestry[Into::<usize>::into(node)];
+        let visible = match self.exports[Into::<usize>::into(target)] {
+            None => true,
+            Some(export) => {
+                export == dest
+                    || ancestry(dest).contains(&export)
+                    || ancestry(target)
+                        .iter()
+                        .skip(1)
+                        .any(|scope| *scope == export)
+                    || ancestry(target)
+                        .iter()
+                        .all(|scope| ancestry(dest).contains(scope))
+            }
+        };
+        self.count(visible)
+    }
+}
+
+impl<'ast> VisibilitySolver<'ast> {
+    fn count(&self, visible: bool) -> bool {
+        self.queries.set(self.queries.get() + 1);
+        self.visible.set(self.visible.get() + visible as usize);
         visible
     }
+
+    /// Logs how many queries the solver answered
+    pub fn log_statistics(&self) {
+        log::info!(
+            "{} visibility queries, {} visible and {} not",
+            self.queries.get(),
+            self.visible.get(),
+            self.queries.get() - self.visible.get()
+        );
+    }
 }
 
+#[cfg(feature = "z3")]
 pub fn build_visibility_solver<'ast>(
     resolution_graph: &mut ResolutionGraph<'ast>,
     errors: &mut Vec<Diagnostic>,
@@ -75,7 +138,8 @@ pub fn build_visibility_solver<'ast>(
     let node_ty = Sort::int(ctx);
     let node_set_ty = Sort::set(&ctx, &node_ty);
     let empty_set = Set::empty(&ctx, &node_ty);
-    let solver = Solver::new(&ctx);
+    let solver = z3::Solver::new(&ctx);
+    let mut assertions = 0;
 
     // Create nodes
     let base: Dynamic = Int::from_i64(ctx, -1).into();
@@ -100,6 +164,7 @@ pub fn build_visibility_solver<'ast>(
             .fold(empty_set.clone(), |acc, root| acc.add(root)),
     );
     let mut z3_exports = Array::new_const(&ctx, "exports", &node_ty, &node_ty);
+    let mut exports = vec![];
     for node in resolution_graph.node_indices() {
         let z3_node = &nodes[Into::<usize>::into(node)];
 
@@ -120,6 +185,7 @@ pub fn build_visibility_solver<'ast>(
             })
             .unwrap_or_else(|| empty_set.clone().add(&base));
         solver.assert(&ancestry_const._eq(&ancestry_val));
+        assertions += 1;
         z3_ancestry = z3_ancestry.store(z3_node, &ancestry_const);
         let children_const = resolution_graph[node]
             .children()
@@ -134,8 +200,6 @@ pub fn build_visibility_solver<'ast>(
             .unwrap_or_else(|| empty_set.clone());
         z3_children = z3_children.store(z3_node, &children_const);
 
-        use Vis::*;
-        let file = resolution_graph.file(node);
         let parent = ancestry
             .first()
             .map(|g| -> usize { (*g).into() })
@@ -143,60 +207,21 @@ pub fn build_visibility_solver<'ast>(
             .unwrap_or(&base);
         z3_parents = z3_parents.store(z3_node, parent);
 
-        let grandparent = ancestry
-            .iter()
-            .skip(1)
-            .next()
-            .map(|g| -> usize { (*g).into() })
-            .map(|g| &nodes[g])
-            .unwrap_or(&base);
-        // TODO: once trait items are split into leaves, assert their exports to same as trait
-        if matches!(resolution_graph[node], ResolutionNode::Branch{branch: Branch::Variant(_), ..})
-            || ancestry.first().map(|p| matches!(resolution_graph[*p], ResolutionNode::Branch{branch: Branch::Variant(_), ..})).unwrap_or_default()
-        {
-            // bad visibility usage
-            if let Some(vis) = resolution_graph[node].visibility() {
-                errors.push(unnecessary_visibility(file, vis));
-            }
-            solver.assert(&z3_exports.select(z3_node)._eq(&z3_exports.select(parent)));
-        } else if let Some(vis) = resolution_graph[node].visibility() {
-            match vis {
-                Pub(_) | Super(_) => {
-                    z3_exports = z3_exports.store(z3_node, grandparent);
-                }
-                Crate(_) => {
-                    z3_exports = z3_exports.store(
-                        z3_node,
-                        &nodes[Into::<usize>::into(*ancestry.last().unwrap())],
-                    );
-                }
-                Restricted(r) => match apply_visibility_in(resolution_graph, node, file, r) {
-                    Ok(dest) => {
-                        z3_exports = z3_exports.store(
-                            z3_node,
-                            if let Some(dest) = dest {
-                                &nodes[Into::<usize>::into(dest)]
-                            } else {
-                                &base
-                            },
-                        );
-                    }
-                    Err(err) => {
-                        errors.push(err);
-                        z3_exports = z3_exports.store(z3_node, parent);
-                    }
-                },
-                // export to parent is an easy way of not making it visible anywhere else
-                Priv(_) | LowerSelf(_) => {
-                    z3_exports = z3_exports.store(z3_node, parent);
-                }
-            }
-        } else {
-            // treated the same as a pub(self)
-            z3_exports = z3_exports.store(z3_node, parent);
-        }
+        let export = export(resolution_graph, node, &exports, errors);
+        exports.push(export);
+        z3_exports = z3_exports.store(
+            z3_node,
+            export
+                .map(|export| &nodes[Into::<usize>::into(export)])
+                .unwrap_or(&base),
+        );
     }
 
+    log::debug!(
+        "visibility solver has {} nodes and {} assertions",
+        nodes.len(),
+        assertions
+    );
     VisibilitySolver {
         ctx,
         solver,
@@ -206,6 +231,80 @@ pub fn build_visibility_solver<'ast>(
         parents: z3_parents,
         children: z3_children,
         exports: z3_exports,
+        queries: Cell::new(0),
+        visible: Cell::new(0),
+    }
+}
+
+#[cfg(not(feature = "z3"))]
+pub fn build_visibility_solver<'ast>(
+    resolution_graph: &mut ResolutionGraph<'ast>,
+    errors: &mut Vec<Diagnostic>,
+    _ctx: &'ast Context,
+) -> VisibilitySolver<'ast> {
+    let mut exports = vec![];
+    for node in resolution_graph.node_indices() {
+        let export = export(resolution_graph, node, &exports, errors);
+        exports.push(export);
+    }
+    VisibilitySolver {
+        ancestry: resolution_graph
+            .node_indices()
+            .map(|node| build_ancestry(resolution_graph, node, false))
+            .collect(),
+        exports,
+        queries: Cell::new(0),
+        visible: Cell::new(0),
+        ctx: std::marker::PhantomData,
+    }
+}
+
+/// The scope `node` is exported to, which it's visible from along with the scopes inside of it,
+/// or `None` if it's visible from anywhere, given the exports of the nodes before it. Reports the
+/// visibilities that aren't allowed.
+fn export<'ast>(
+    resolution_graph: &ResolutionGraph<'ast>,
+    node: ResolutionIndex,
+    exports: &[Option<ResolutionIndex>],
+    errors: &mut Vec<Diagnostic>,
+) -> Option<ResolutionIndex> {
+    use Vis::*;
+    let ancestry = build_ancestry(resolution_graph, node, false);
+    let file = resolution_graph.file(node);
+    let parent = ancestry.first().copied();
+    let grandparent = ancestry.get(1).copied();
+    let is_variant = |node: ResolutionIndex| {
+        matches!(resolution_graph[node], ResolutionNode::Branch{branch: Branch::Variant(_), ..})
+    };
+    // TODO: once trait items are split into leaves, export them to the same as their trait
+    if is_variant(node) || parent.map_or(false, is_variant) {
+        // bad visibility usage
+        if let Some(vis) = resolution_graph[node].visibility() {
+            errors.push(unnecessary_visibility(file, vis));
+        }
+        // variants and their fields are as visible as their enum
+        parent.and_then(|parent| exports[Into::<usize>::into(parent)])
+    } else if let Some(vis) = resolution_graph[node].visibility() {
+        match vis {
+            Pub(_) | Super(_) => grandparent,
+            Crate(_) => ancestry.last().copied(),
+            Restricted(r) => match apply_visibility_in(resolution_graph, node, file, r) {
+                Ok(dest) => dest,
+                Err(err) => {
+                    errors.push(err);
+                    parent
+                }
+            },
+            // export to parent is an easy way of not making it visible anywhere else
+            Priv(_) | LowerSelf(_) => parent,
+        }
+    } else if resolution_graph[node].is_entity() {
+        // entities don't have a visibility, and can be instantiated wherever their module is
+        // visible, like those of the standard library
+        grandparent
+    } else {
+        // treated the same as a pub(self)
+        parent
     }
 }
 
@@ -290,7 +389,8 @@ fn apply_visibility_in<'ast>(
             // a regular path goes down to some scope that is also an ancestor
             let has_matching_child = resolution_graph[ancestry[ancestry_position]]
                 .children()
-                .and_then(|children| children.get(&Some(&segment)))
+                .zip(resolution_graph.symbol(&segment))
+                .and_then(|(children, name)| children.get(&Some(name)))
                 .map(|named_children| {
                     named_children
                         .iter()
@@ -0,0 +1,91 @@
+//! The reverse-reference index: the nodes that refer to each item, by a path or a use. A path
+//! refers to the item each of its prefixes resolves to, and a use to what it imports and the
+//! ancestors of that it had to name. A glob import refers to the scope it's of and everything
+//! in it, since what it brings in depends on their visibility.
+use fxhash::FxHashMap as HashMap;
+
+use super::definition::paths_in;
+use crate::resolution::{
+    path::r#type::PathFinder, Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver,
+};
+
+/// The nodes that refer to each item, once for each reference
+pub type References = HashMap<ResolutionIndex, Vec<ResolutionIndex>>;
+
+impl<'ast> Resolver<'ast> {
+    pub fn references(&self) -> References {
+        let resolution_graph = &self.resolution_graph;
+        let mut references = References::default();
+        for node in resolution_graph.node_indices() {
+            match &resolution_graph[node] {
+                ResolutionNode::Root { .. }
+                | ResolutionNode::Branch {
+                    branch: Branch::Use(_),
+                    ..
+                } => {}
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseName(_, imported),
+                    ..
+                }
+                | ResolutionNode::Leaf {
+                    leaf: Leaf::UseRename(_, imported),
+                    ..
+                } => {
+                    for target in imported {
+                        self.refer_through_ancestors(node, *target, &mut references);
+                    }
+                }
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseGlob(_, scope),
+                    ..
+                } => {
+                    self.refer_through_ancestors(node, *scope, &mut references);
+                    for child in resolution_graph[*scope]
+                        .children()
+                        .into_iter()
+                        .flat_map(|children| children.values().flatten())
+                    {
+                        references.entry(*child).or_default().push(node);
+                    }
+                }
+                other => {
+                    for path in paths_in(other) {
+                        for len in 1..=path.segments.len() {
+                            let mut path_finder = PathFinder {
+                                resolution_graph,
+                                vis_solver: &self.vis_solver,
+                                visited_glob_scopes: Default::default(),
+                            };
+                            for found in path_finder
+                                .find_at_path_prefix(node, path, len)
+                                .unwrap_or_default()
+                            {
+                                references.entry(found).or_default().push(node);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        references
+    }
+
+    /// Adds a reference from `from` to `target` and to the ancestors of `target` that aren't
+    /// also ancestors of `from`, which a path from one to the other goes through
+    fn refer_through_ancestors(
+        &self,
+        from: ResolutionIndex,
+        target: ResolutionIndex,
+        references: &mut References,
+    ) {
+        let resolution_graph = &self.resolution_graph;
+        let mut current = Some(target);
+        while let Some(node) = current {
+            if resolution_graph.is_within(from, node) {
+                break;
+            }
+            references.entry(node).or_default().push(from);
+            current = resolution_graph[node].parent();
+        }
+    }
+}
@@ -0,0 +1,418 @@
+//! Renaming an item changes its declaration and every path and use that refers to it by its name.
+//! Uses that import it under another name with `as` keep that name, and so do the paths that
+//! refer to it by that name.
+use std::fs;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use rhdl::{
+    ast::{Ident, Item, Spanned, UseTree},
+    parser::FileParser,
+};
+
+use super::definition::paths_in;
+use crate::error::*;
+use crate::find_file::{absolute, FileContentProvider, FileId};
+use crate::resolution::{
+    is_invalid_raw_identifier, path::r#type::PathFinder, Branch, Leaf, ResolutionIndex,
+    ResolutionNode, Resolver,
+};
+
+/// Replace the text at `range` in `file` with `text`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub file: FileId,
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// `rhdlc rename`: rename the item named at a position in a file
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub path: PathBuf,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+    pub name: String,
+    /// Rewrite the files in place instead of listing the edits on standard output
+    pub write: bool,
+}
+
+impl Rename {
+    /// Parses a position written as `<file>:<line>:<column>`
+    pub fn new(at: &str, name: &str, write: bool) -> Result<Self, String> {
+        let mut parts = at.rsplitn(3, ':');
+        let (column, line, path) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(column), Some(line), Some(path)) if !path.is_empty() => (column, line, path),
+            _ => {
+                return Err(format!(
+                    "invalid position `{}`, expected <file>:<line>:<column>",
+                    at
+                ))
+            }
+        };
+        let number = |value: &str, what: &str| match value.parse::<usize>() {
+            Ok(number) if number > 0 => Ok(number),
+            _ => Err(format!("invalid {} `{}` in position `{}`", what, value, at)),
+        };
+        Ok(Self {
+            path: path.into(),
+            line: number(line, "line")?,
+            column: number(column, "column")?,
+            name: name.to_string(),
+            write,
+        })
+    }
+
+    fn location(&self) -> String {
+        format!("{}:{}:{}", self.path.display(), self.line, self.column)
+    }
+}
+
+impl<'ast> Resolver<'ast> {
+    /// Renames the item at the position in `rename`, either writing the renamed files or listing
+    /// the edits
+    pub fn rename_at(&self, rename: &Rename, errors: &mut Vec<Diagnostic>) {
+        let location = rename.location();
+        let file = self.file_graph.iter().copied().find(|file| {
+            match &self.file_graph[*file].provider {
+                FileContentProvider::File(_) => {
+                    self.file_graph.absolute_path(*file) == Some(absolute(&rename.path))
+                }
+                FileContentProvider::Reader(name, _) => Path::new(name) == rename.path,
+                FileContentProvider::Embedded(_) => false,
+            }
+        });
+        let offset = file.and_then(|file| {
+            offset(self.file_graph[file].as_ref(), rename.line, rename.column)
+        });
+        let (file, offset) = match (file, offset) {
+            (Some(file), Some(offset)) => (file, offset),
+            _ => {
+                errors.push(nothing_to_rename(&location));
+                return;
+            }
+        };
+        let definition = match self.find_definition(file, offset).as_slice() {
+            [] => {
+                errors.push(nothing_to_rename(&location));
+                return;
+            }
+            [definition] => definition.clone(),
+            _ => {
+                errors.push(ambiguous_rename(&location));
+                return;
+            }
+        };
+        let edits = match self.rename(definition.node, &rename.name) {
+            Ok(edits) => edits,
+            Err(err) => {
+                errors.push(err);
+                return;
+            }
+        };
+
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        if !rename.write {
+            for edit in edits.iter() {
+                let source = self.file_graph[edit.file].as_ref();
+                let listed = match self.file_graph.line_column(edit.file, edit.range.start) {
+                    Some(start) => writeln!(
+                        stdout,
+                        "{}:{}:{}: `{}` -> `{}`",
+                        self.file_graph.inner.name(edit.file).to_string_lossy(),
+                        start.line,
+                        start.column,
+                        &source[edit.range.clone()],
+                        edit.text
+                    ),
+                    None => Ok(()),
+                };
+                if let Err(err) = listed {
+                    errors.push(write_output("stdout", err));
+                    return;
+                }
+            }
+            return;
+        }
+        let mut edited_files = edits.iter().map(|edit| edit.file).collect::<Vec<_>>();
+        edited_files.dedup();
+        for edited_file in edited_files {
+            let mut content = self.file_graph[edited_file].as_ref().to_string();
+            // edits are in source order, so later ones are applied first to keep offsets valid
+            for edit in edits.iter().rev().filter(|edit| edit.file == edited_file) {
+                content.replace_range(edit.range.clone(), &edit.text);
+            }
+            let written = match &self.file_graph[edited_file].provider {
+                FileContentProvider::File(path) => fs::write(path, content)
+                    .map_err(|err| write_output(&path.to_string_lossy(), err)),
+                // a design read from standard input is written to standard output
+                FileContentProvider::Reader(..) => stdout
+                    .write_all(content.as_bytes())
+                    .map_err(|err| write_output("stdout", err)),
+                // items of the standard library aren't renamed, and it can't refer to the design
+                FileContentProvider::Embedded(_) => Ok(()),
+            };
+            if let Err(err) = written {
+                errors.push(err);
+            }
+        }
+    }
+
+    /// The edits that rename `node` to `name`, in file and then source order
+    pub fn rename(&self, node: ResolutionIndex, name: &str) -> Result<Vec<Edit>, Diagnostic> {
+        let resolution_graph = &self.resolution_graph;
+        let file = resolution_graph.file(node);
+        let renameable = !matches!(
+            &resolution_graph[node],
+            ResolutionNode::Leaf {
+                leaf: Leaf::NamedField(_),
+                ..
+            } | ResolutionNode::Leaf {
+                leaf: Leaf::UseName(..),
+                ..
+            } | ResolutionNode::Leaf {
+                leaf: Leaf::UseRename(..),
+                ..
+            }
+        );
+        let old = match resolution_graph[node].name() {
+            Some(old) if resolution_graph.is_extern(node) => {
+                return Err(cannot_rename_extern(file, old))
+            }
+            Some(old) if renameable => old,
+            _ => {
+                return Err(cannot_rename(
+                    self.definition(node)
+                        .map(|definition| (definition.file, definition.span)),
+                ))
+            }
+        };
+        if !is_valid_identifier(name) {
+            return Err(invalid_identifier(name));
+        }
+        self.check_rename_conflicts(node, old, name)?;
+
+        let mut renamed: Vec<(FileId, Range<usize>)> = vec![(file, old.span().into())];
+        for scope in resolution_graph.node_indices() {
+            match &resolution_graph[scope] {
+                ResolutionNode::Root { .. } => {}
+                ResolutionNode::Branch {
+                    branch: Branch::Use(item_use),
+                    ..
+                } => self.find_in_use(scope, &item_use.tree, &mut vec![], node, old, &mut renamed),
+                ResolutionNode::Leaf {
+                    leaf: Leaf::UseName(..),
+                    ..
+                }
+                | ResolutionNode::Leaf {
+                    leaf: Leaf::UseRename(..),
+                    ..
+                }
+                | ResolutionNode::Leaf {
+                    leaf: Leaf::UseGlob(..),
+                    ..
+                } => {}
+                other => {
+                    let scope_file = resolution_graph.file(scope);
+                    for path in paths_in(other) {
+                        for (i, segment) in path.segments.iter().enumerate() {
+                            if segment.ident != *old {
+                                continue;
+                            }
+                            let mut path_finder = PathFinder {
+                                resolution_graph,
+                                vis_solver: &self.vis_solver,
+                                visited_glob_scopes: Default::default(),
+                            };
+                            if path_finder
+                                .find_at_path_prefix(scope, path, i + 1)
+                                .map(|found| found.contains(&node))
+                                .unwrap_or_default()
+                            {
+                                renamed.push((scope_file, segment.ident.span().into()));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        renamed.sort_by_key(|(file, range)| (*file, range.start));
+        renamed.dedup();
+        Ok(renamed
+            .into_iter()
+            .map(|(file, range)| Edit {
+                file,
+                range,
+                text: name.to_string(),
+            })
+            .collect())
+    }
+
+    /// Items in the same scope and name class as `node` can't have its new name
+    fn check_rename_conflicts(
+        &self,
+        node: ResolutionIndex,
+        old: &Ident,
+        name: &str,
+    ) -> Result<(), Diagnostic> {
+        let resolution_graph = &self.resolution_graph;
+        let parent = match resolution_graph[node].parent() {
+            Some(parent) => parent,
+            None => return Ok(()),
+        };
+        let children = resolution_graph[parent].children().unwrap();
+        let imported = children
+            .get(&None)
+            .into_iter()
+            .flatten()
+            .filter(|child| resolution_graph[**child].is_use())
+            .filter_map(|child| resolution_graph[*child].children())
+            .flat_map(|use_children| use_children.values().flatten());
+        for sibling in children.values().flatten().chain(imported).copied() {
+            let sibling_name = match resolution_graph[sibling].name() {
+                Some(sibling_name) if sibling != node && sibling_name.inner == name => sibling_name,
+                _ => continue,
+            };
+            if resolution_graph[sibling].in_same_name_class(&resolution_graph[node]) {
+                return Err(rename_conflict(
+                    resolution_graph.file(node),
+                    old,
+                    name,
+                    resolution_graph.file(sibling),
+                    sibling_name,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the names in a use tree that import `node` and the path segments that name it.
+    /// Segments are matched to the ancestors of what the use imports, since the tree is already
+    /// resolved and paths in uses can't go through other uses' renames.
+    fn find_in_use(
+        &self,
+        use_node: ResolutionIndex,
+        tree: &'ast UseTree,
+        prefix: &mut Vec<&'ast Ident>,
+        node: ResolutionIndex,
+        old: &Ident,
+        renamed: &mut Vec<(FileId, Range<usize>)>,
+    ) {
+        let resolution_graph = &self.resolution_graph;
+        let file = resolution_graph.file(use_node);
+        let leaves = || {
+            resolution_graph[use_node]
+                .children()
+                .into_iter()
+                .flat_map(|children| children.values().flatten())
+                .map(move |leaf| &resolution_graph[*leaf])
+        };
+        // what the use imports, and whether it's the scope of the last segment of `prefix`
+        let (imported, is_scope) = match tree {
+            UseTree::Path(use_tree_path) => {
+                prefix.extend(use_tree_path.path.segments.iter());
+                self.find_in_use(use_node, &use_tree_path.tree, prefix, node, old, renamed);
+                prefix.truncate(prefix.len() - use_tree_path.path.segments.len());
+                return;
+            }
+            UseTree::Group(group) => {
+                for tree in group.trees.iter() {
+                    self.find_in_use(use_node, tree, prefix, node, old, renamed);
+                }
+                return;
+            }
+            UseTree::Name(ident) => {
+                let imported = leaves()
+                    .find_map(|leaf| match leaf {
+                        ResolutionNode::Leaf {
+                            leaf: Leaf::UseName(name, imported),
+                            ..
+                        } if std::ptr::eq(*name, ident) => Some(imported.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                if imported.contains(&node) && ident == old {
+                    renamed.push((file, ident.span().into()));
+                }
+                (imported, ident == "self")
+            }
+            UseTree::Rename(rename) => {
+                let imported = leaves()
+                    .find_map(|leaf| match leaf {
+                        ResolutionNode::Leaf {
+                            leaf: Leaf::UseRename(use_tree_rename, imported),
+                            ..
+                        } if std::ptr::eq(*use_tree_rename, rename) => Some(imported.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                if imported.contains(&node) && rename.name == *old {
+                    renamed.push((file, rename.name.span().into()));
+                }
+                (imported, rename.name == "self")
+            }
+            UseTree::Glob(glob) => {
+                let scope = leaves().find_map(|leaf| match leaf {
+                    ResolutionNode::Leaf {
+                        leaf: Leaf::UseGlob(use_tree_glob, scope),
+                        ..
+                    } if std::ptr::eq(*use_tree_glob, glob) => Some(*scope),
+                    _ => None,
+                });
+                (scope.into_iter().collect(), true)
+            }
+        };
+        for imported in imported {
+            let mut ancestor = if is_scope {
+                Some(imported)
+            } else {
+                resolution_graph[imported].parent()
+            };
+            for segment in prefix.iter().rev() {
+                let current = match ancestor {
+                    Some(current) => current,
+                    None => break,
+                };
+                if current == node && *segment == old {
+                    renamed.push((file, segment.span().into()));
+                }
+                ancestor = resolution_graph[current].parent();
+            }
+        }
+    }
+}
+
+/// Whether `name` parses as an identifier that can be declared, i.e. isn't a keyword
+fn is_valid_identifier(name: &str) -> bool {
+    let parsed = match FileParser::new().parse(&format!("mod {};", name)) {
+        Ok(parsed) => parsed,
+        Err(_) => return false,
+    };
+    match parsed.items.as_slice() {
+        [Item::Mod(item_mod)] => {
+            item_mod.ident.inner == name && !is_invalid_raw_identifier(&item_mod.ident.inner)
+        }
+        _ => false,
+    }
+}
+
+/// The byte offset of a 1-based line and column, counted in characters
+fn offset(source: &str, line: usize, column: usize) -> Option<usize> {
+    let mut line_start = 0;
+    for (i, text) in source.split('\n').enumerate() {
+        if i + 1 == line {
+            return text
+                .char_indices()
+                .map(|(offset, _)| offset)
+                .chain(std::iter::once(text.len()))
+                .nth(column - 1)
+                .map(|offset| line_start + offset);
+        }
+        line_start += text.len() + 1;
+    }
+    None
+}
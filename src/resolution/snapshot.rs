@@ -0,0 +1,122 @@
+//! A snapshot of the resolution graph as JSON, for tests that check a change to the resolver
+//! builds the same graph. Nodes are nested in their scopes in the order they were added, and
+//! named by where they're declared instead of by their indices, so the snapshot only changes
+//! when what's resolved does.
+use std::ops::Range;
+
+use serde_json::{json, Value as Json};
+
+use crate::find_file::{FileGraph, FileId};
+use crate::resolution::{Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver};
+
+impl<'ast> Resolver<'ast> {
+    /// The graph from each of its roots, with files named by `name`
+    pub fn snapshot(&self, file_graph: &FileGraph, name: &dyn Fn(FileId) -> String) -> Json {
+        Json::Array(
+            self.resolution_graph
+                .roots
+                .iter()
+                .map(|root| self.snapshot_of(*root, file_graph, name))
+                .collect(),
+        )
+    }
+
+    fn snapshot_of(
+        &self,
+        node: ResolutionIndex,
+        file_graph: &FileGraph,
+        name: &dyn Fn(FileId) -> String,
+    ) -> Json {
+        let resolution_graph = &self.resolution_graph;
+        let mut snapshot = json!({ "kind": kind(&resolution_graph[node]) });
+        if let ResolutionNode::Root {
+            name: extern_name, ..
+        } = &resolution_graph[node]
+        {
+            snapshot["file"] = json!(name(resolution_graph.content_files[&node]));
+            if !extern_name.is_empty() {
+                snapshot["name"] = json!(extern_name);
+            }
+        }
+        if let Some(ident) = resolution_graph[node].name() {
+            snapshot["name"] = json!(ident.to_string());
+        }
+        if let Some(at) = self.at(node, file_graph, name) {
+            snapshot["at"] = json!(at);
+        }
+        let targets = match &resolution_graph[node] {
+            ResolutionNode::Leaf { leaf, .. } => match leaf {
+                Leaf::UseName(_, targets) | Leaf::UseRename(_, targets) => targets.clone(),
+                Leaf::UseGlob(_, scope) => vec![*scope],
+                _ => vec![],
+            },
+            _ => vec![],
+        };
+        if !targets.is_empty() {
+            snapshot["targets"] = targets
+                .iter()
+                .map(|target| {
+                    self.at(*target, file_graph, name)
+                        .unwrap_or_else(|| name(resolution_graph.file(*target)))
+                })
+                .collect();
+        }
+        let children = resolution_graph[node]
+            .children()
+            .into_iter()
+            .flat_map(|children| children.values().flatten())
+            .map(|child| self.snapshot_of(*child, file_graph, name))
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            snapshot["children"] = Json::Array(children);
+        }
+        snapshot
+    }
+
+    /// Where `node` is declared, as `<file>:<line>:<column>`, or `None` for roots
+    fn at(
+        &self,
+        node: ResolutionIndex,
+        file_graph: &FileGraph,
+        name: &dyn Fn(FileId) -> String,
+    ) -> Option<String> {
+        let definition = self.definition(node)?;
+        let range: Range<usize> = definition.span.into();
+        let start = file_graph.line_column(definition.file, range.start)?;
+        Some(format!(
+            "{}:{}:{}",
+            name(definition.file),
+            start.line,
+            start.column
+        ))
+    }
+}
+
+fn kind(node: &ResolutionNode) -> &'static str {
+    match node {
+        ResolutionNode::Root { .. } => "root",
+        ResolutionNode::Branch { branch, .. } => match branch {
+            Branch::Mod(_) => "mod",
+            Branch::Use(_) => "use",
+            Branch::Fn(_) => "fn",
+            Branch::Struct(_) => "struct",
+            Branch::Enum(_) => "enum",
+            Branch::Variant(_) => "variant",
+            Branch::Impl(_) => "impl",
+            Branch::Entity(_) => "entity",
+            Branch::Arch(_) => "arch",
+            Branch::Trait(_) => "trait",
+            Branch::Block(_) => "block",
+        },
+        ResolutionNode::Leaf { leaf, .. } => match leaf {
+            Leaf::UseName(..) => "use name",
+            Leaf::UseRename(..) => "use rename",
+            Leaf::UseGlob(..) => "use glob",
+            Leaf::Const(_) => "const",
+            Leaf::Type(_) => "type",
+            Leaf::TraitAlias(_) => "trait alias",
+            Leaf::NamedField(_) => "field",
+            Leaf::UnnamedField(_) => "unnamed field",
+        },
+    }
+}
@@ -0,0 +1,182 @@
+//! Outlines of the items declared in each file, nested by the scopes they're declared in, for the
+//! document symbols of editors and `--emit symbols`.
+use std::ops::Range;
+
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::{Span, Spanned};
+
+use crate::find_file::FileId;
+use crate::resolution::{architecture_name, Branch, Leaf, ResolutionIndex, ResolutionNode, Resolver};
+
+/// An item declared in a file and the items declared inside it
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The name of an architecture
+    pub detail: Option<String>,
+    /// The whole item
+    pub span: Span,
+    /// The name of the item, or the entity an architecture implements
+    pub name_span: Span,
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Mod,
+    Entity,
+    Arch,
+    Struct,
+    Enum,
+    Variant,
+    Field,
+    Fn,
+    Const,
+    Trait,
+    Type,
+}
+
+impl SymbolKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymbolKind::Mod => "mod",
+            SymbolKind::Entity => "entity",
+            SymbolKind::Arch => "arch",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Variant => "variant",
+            SymbolKind::Field => "field",
+            SymbolKind::Fn => "fn",
+            SymbolKind::Const => "const",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Type => "type",
+        }
+    }
+}
+
+impl<'ast> Resolver<'ast> {
+    /// The items declared at the top level of `file`, in source order. Architectures are listed in
+    /// the entity they implement, or in the scope they're declared in if that entity is in
+    /// another file.
+    pub fn symbols(&self, file: FileId) -> Vec<Symbol> {
+        let resolution_graph = &self.resolution_graph;
+        let mut elsewhere: HashMap<ResolutionIndex, Vec<ResolutionIndex>> = HashMap::default();
+        for entity in resolution_graph.node_indices() {
+            if !resolution_graph[entity].is_entity() || resolution_graph.file(entity) == file {
+                continue;
+            }
+            for arch in resolution_graph.architectures(entity).iter().copied() {
+                if resolution_graph.file(arch) == file {
+                    let scope = resolution_graph[arch].parent().unwrap();
+                    elsewhere.entry(scope).or_default().push(arch);
+                }
+            }
+        }
+        resolution_graph
+            .content_files
+            .iter()
+            .find(|(_, content_file)| **content_file == file)
+            .map(|(scope, _)| self.symbols_in(*scope, file, &elsewhere))
+            .unwrap_or_default()
+    }
+
+    /// The symbols of the children of `scope` that are declared in `file`, and of the
+    /// architectures in `elsewhere` declared in it. The items in impls are listed in the scope of
+    /// the impl, and uses and the items local to blocks aren't listed.
+    fn symbols_in(
+        &self,
+        scope: ResolutionIndex,
+        file: FileId,
+        elsewhere: &HashMap<ResolutionIndex, Vec<ResolutionIndex>>,
+    ) -> Vec<Symbol> {
+        let resolution_graph = &self.resolution_graph;
+        let mut symbols = resolution_graph[scope]
+            .children()
+            .into_iter()
+            .flat_map(|children| children.values().flatten())
+            .chain(elsewhere.get(&scope).into_iter().flatten())
+            .copied()
+            .filter(|child| resolution_graph.file(*child) == file)
+            .flat_map(|child| match &resolution_graph[child] {
+                ResolutionNode::Branch {
+                    branch: Branch::Impl(_),
+                    ..
+                } => self.symbols_in(child, file, elsewhere),
+                _ => self.symbol(child, file, elsewhere).into_iter().collect(),
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by_key(|symbol| {
+            let range: Range<usize> = symbol.span.into();
+            range.start
+        });
+        symbols
+    }
+
+    fn symbol(
+        &self,
+        node: ResolutionIndex,
+        file: FileId,
+        elsewhere: &HashMap<ResolutionIndex, Vec<ResolutionIndex>>,
+    ) -> Option<Symbol> {
+        let resolution_graph = &self.resolution_graph;
+        let (kind, span) = declaration(&resolution_graph[node])?;
+        let (name, name_span, detail) = match &resolution_graph[node] {
+            ResolutionNode::Branch {
+                branch: Branch::Arch(item_arch),
+                ..
+            } => (
+                item_arch
+                    .entity
+                    .segments
+                    .iter()
+                    .map(|segment| segment.ident.to_string())
+                    .collect::<Vec<_>>()
+                    .join("::"),
+                item_arch.entity.span(),
+                architecture_name(resolution_graph, node),
+            ),
+            other => {
+                let name = other.name()?;
+                (name.to_string(), name.span(), None)
+            }
+        };
+        Some(Symbol {
+            name,
+            kind,
+            detail,
+            span,
+            name_span,
+            children: self.symbols_in(node, file, elsewhere),
+        })
+    }
+}
+
+/// The kind of item `node` declares and its span, `None` for nodes that aren't symbols
+pub(super) fn declaration(node: &ResolutionNode) -> Option<(SymbolKind, Span)> {
+    match node {
+        ResolutionNode::Branch { branch, .. } => match branch {
+            Branch::Mod(item_mod) => Some((SymbolKind::Mod, item_mod.span())),
+            Branch::Fn(item_fn) => Some((SymbolKind::Fn, item_fn.span())),
+            Branch::Struct(item_struct) => Some((SymbolKind::Struct, item_struct.span())),
+            Branch::Enum(item_enum) => Some((SymbolKind::Enum, item_enum.span())),
+            Branch::Variant(variant) => Some((SymbolKind::Variant, variant.span())),
+            Branch::Entity(item_entity) => Some((SymbolKind::Entity, item_entity.span())),
+            Branch::Arch(item_arch) => Some((SymbolKind::Arch, item_arch.span())),
+            Branch::Trait(item_trait) => Some((SymbolKind::Trait, item_trait.span())),
+            Branch::Impl(_) | Branch::Use(_) | Branch::Block(_) => None,
+        },
+        ResolutionNode::Leaf { leaf, .. } => match leaf {
+            Leaf::Const(item_const) => Some((SymbolKind::Const, item_const.span())),
+            Leaf::Type(item_type) => Some((SymbolKind::Type, item_type.span())),
+            Leaf::TraitAlias(item_trait_alias) => {
+                Some((SymbolKind::Trait, item_trait_alias.span()))
+            }
+            Leaf::NamedField(field) => Some((SymbolKind::Field, field.span())),
+            Leaf::UnnamedField(_) | Leaf::UseName(..) | Leaf::UseRename(..) | Leaf::UseGlob(..) => {
+                None
+            }
+        },
+        ResolutionNode::Root { .. } => None,
+    }
+}
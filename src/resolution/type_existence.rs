@@ -1,15 +1,20 @@
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
 use rhdl::{
     ast::{
-        Block, File, GenericParam, GenericParamType, Generics, Item, ItemArch, ItemImpl, ItemMod,
-        ItemTrait, Qualifier, TypePath,
+        Block, ExprStruct, File, GenericParam, GenericParamType, Generics, Item, ItemArch,
+        ItemImpl, ItemMod, ItemTrait, Qualifier, TypePath,
     },
     visit::Visit,
 };
 
+use super::completion::scope_span;
+use super::definition::contains;
 use crate::error::*;
+use crate::find_file::FileId;
 use crate::resolution::r#pub::VisibilitySolver;
 use crate::resolution::{
-    path::r#type::PathFinder, Branch, ResolutionGraph, ResolutionIndex, ResolutionNode,
+    is_float, path::r#type::PathFinder, primitive_width, Branch, ResolutionGraph, ResolutionIndex,
+    ResolutionNode,
 };
 
 pub struct TypeExistenceChecker<'a, 'ast> {
@@ -28,8 +33,24 @@ struct TypeExistenceCheckerVisitor<'a, 'ast> {
 
 impl<'a, 'ast> TypeExistenceChecker<'a, 'ast> {
     pub fn visit_all(&mut self) {
-        for scope in self.resolution_graph.node_indices() {
-            if self.resolution_graph[scope].is_type_existence_checking_candidate() {
+        // Items that earlier stages already found errors in aren't checked, since their types are
+        // likely to be wrong because of those errors
+        let errors = self
+            .errors
+            .iter()
+            .filter(|diagnostic| diagnostic.severity >= Severity::Error)
+            .filter_map(|diagnostic| {
+                diagnostic
+                    .labels
+                    .iter()
+                    .find(|label| label.style == LabelStyle::Primary)
+            })
+            .map(|label| (label.file_id, label.range.start))
+            .collect::<Vec<(FileId, usize)>>();
+        for scope in self.resolution_graph.checked_node_indices() {
+            if self.resolution_graph[scope].is_type_existence_checking_candidate()
+                && !self.has_errors(scope, &errors)
+            {
                 let mut ctx_checker = TypeExistenceCheckerVisitor {
                     resolution_graph: self.resolution_graph,
                     vis_solver: &self.vis_solver,
@@ -41,6 +62,15 @@ impl<'a, 'ast> TypeExistenceChecker<'a, 'ast> {
             }
         }
     }
+
+    fn has_errors(&self, scope: ResolutionIndex, errors: &[(FileId, usize)]) -> bool {
+        let file = self.resolution_graph.file(scope);
+        scope_span(&self.resolution_graph[scope]).map_or(false, |span| {
+            errors
+                .iter()
+                .any(|(error_file, offset)| *error_file == file && contains(span, *offset))
+        })
+    }
 }
 
 impl<'a, 'ast> TypeExistenceCheckerVisitor<'a, 'ast> {
@@ -127,7 +157,16 @@ impl<'a, 'ast> Visit<'ast> for TypeExistenceCheckerVisitor<'a, 'ast> {
         if let Some(generics) = &item_arch.generics {
             self.visit_generics(generics);
         }
-        self.visit_type_path(&item_arch.entity);
+        if let Err(err) = self.find_in_scope(
+            &item_arch.entity,
+            |i| self.resolution_graph[i].is_entity(),
+            ItemHint::Entity,
+        ) {
+            self.errors.push(err)
+        }
+        for seg in item_arch.entity.segments.iter() {
+            self.visit_path_segment(seg);
+        }
     }
 
     fn visit_item_trait(&mut self, item_trait: &'ast ItemTrait) {
@@ -160,6 +199,29 @@ impl<'a, 'ast> Visit<'ast> for TypeExistenceCheckerVisitor<'a, 'ast> {
         }
     }
 
+    /// Struct expressions also instantiate entities inside architectures
+    fn visit_expr_struct(&mut self, expr_struct: &'ast ExprStruct) {
+        let is_entity = self
+            .find_in_scope(
+                &expr_struct.path,
+                |i| self.resolution_graph[i].is_entity(),
+                ItemHint::Entity,
+            )
+            .is_ok();
+        if is_entity {
+            for seg in expr_struct.path.segments.iter() {
+                self.visit_path_segment(seg);
+            }
+        } else {
+            self.visit_type_path(&expr_struct.path);
+        }
+        for field_value in expr_struct.fields.iter() {
+            if let Some((_, expr)) = &field_value.expr {
+                self.visit_expr(expr);
+            }
+        }
+    }
+
     fn visit_block(&mut self, block: &'ast Block) {
         if !self.block_visited {
             self.block_visited = true;
@@ -208,10 +270,16 @@ impl<'a, 'ast> Visit<'ast> for TypeExistenceCheckerVisitor<'a, 'ast> {
             },
             ItemHint::Type,
         ) {
-            // Find a generic, if there is one
+            // Find a generic or primitive, if there is one
             if type_path.segments.len() == 1 {
                 let first = &type_path.segments.first().unwrap();
                 if first.generic_args.is_none() {
+                    let name = first.ident.to_string();
+                    if type_path.leading_sep.is_none()
+                        && (primitive_width(&name).is_some() || is_float(&name))
+                    {
+                        return;
+                    }
                     let mut current = self.scope;
                     loop {
                         if let Some(param) =
@@ -0,0 +1,119 @@
+//! `unused_pub`: items that are `pub` or `pub(crate)` but aren't referred to from outside of the
+//! module they're declared in, so they could be private.
+use codespan_reporting::diagnostic::Severity;
+use fxhash::FxHashSet as HashSet;
+use rhdl::ast::{Attribute, Spanned, Vis};
+
+use crate::attribute::{self, LintLevel};
+use crate::error::*;
+use crate::resolution::{Branch, Leaf, ResolutionNode, Resolver};
+
+const LINT: &str = "unused_pub";
+
+/// Warns about the items whose visibility is wider than the references to them need, found with
+/// [`Resolver::references`]. Exported items are the API of the design and aren't linted, and
+/// neither are uses, fields, variants, or the items of impls and traits, which can be referred
+/// to by expressions the resolver doesn't follow. Libraries like `std` aren't linted either.
+///
+/// The warning can be set to another level with `#[allow(unused_pub)]` or `#[deny(unused_pub)]`
+/// on the item, or for the whole design in the config file.
+pub struct UnusedPubLinter<'a, 'ast> {
+    pub resolver: &'a Resolver<'ast>,
+    pub errors: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> UnusedPubLinter<'a, 'ast> {
+    pub fn check(&mut self) {
+        let resolution_graph = &self.resolver.resolution_graph;
+        let references = self.resolver.references();
+        let exported = self
+            .resolver
+            .exports()
+            .into_iter()
+            .map(|export| export.node)
+            .collect::<HashSet<_>>();
+        for node in resolution_graph.node_indices() {
+            let attrs = match item_attrs(&resolution_graph[node]) {
+                Some(attrs) if !resolution_graph.is_extern(node) => attrs,
+                _ => continue,
+            };
+            let (vis, name) = match (
+                resolution_graph[node].visibility(),
+                resolution_graph[node].name(),
+            ) {
+                (Some(vis), Some(name)) if matches!(vis, Vis::Pub(_) | Vis::Crate(_)) => {
+                    (vis, name)
+                }
+                _ => continue,
+            };
+            let module = match resolution_graph[node].parent() {
+                Some(parent) if is_module(&resolution_graph[parent]) => parent,
+                _ => continue,
+            };
+            if exported.contains(&node) {
+                continue;
+            }
+            let used_outside = references
+                .get(&node)
+                .into_iter()
+                .flatten()
+                .any(|referrer| !resolution_graph.is_within(*referrer, module));
+            if used_outside {
+                continue;
+            }
+            let file = resolution_graph.file(node);
+            let vis_range: std::ops::Range<usize> = vis.span().into();
+            let mut diagnostic = unused_pub(
+                file,
+                vis,
+                &self.resolver.file_graph[file].as_ref()[vis_range],
+                name,
+                &match resolution_graph.path(module) {
+                    path if path.is_empty() => "crate".to_string(),
+                    path => path,
+                },
+            );
+            let level = attribute::lint_level(attrs, LINT)
+                .or_else(|| self.resolver.lint_levels.get(LINT).copied())
+                .unwrap_or(LintLevel::Warn);
+            match level {
+                LintLevel::Allow => continue,
+                LintLevel::Warn => {}
+                LintLevel::Deny => diagnostic.severity = Severity::Error,
+            }
+            self.errors.push(diagnostic);
+        }
+    }
+}
+
+fn is_module(node: &ResolutionNode) -> bool {
+    matches!(
+        node,
+        ResolutionNode::Root { .. }
+            | ResolutionNode::Branch {
+                branch: Branch::Mod(_),
+                ..
+            }
+    )
+}
+
+/// The attributes of the items that are linted
+fn item_attrs<'ast>(node: &ResolutionNode<'ast>) -> Option<&'ast [Attribute]> {
+    match node {
+        ResolutionNode::Branch { branch, .. } => match branch {
+            Branch::Mod(item_mod) => Some(&item_mod.attrs),
+            Branch::Fn(item_fn) => Some(&item_fn.attrs),
+            Branch::Struct(item_struct) => Some(&item_struct.attrs),
+            Branch::Enum(item_enum) => Some(&item_enum.attrs),
+            Branch::Trait(item_trait) => Some(&item_trait.attrs),
+            _ => None,
+        },
+        ResolutionNode::Leaf { leaf, .. } => match leaf {
+            Leaf::Const(item_const) => Some(&item_const.attrs),
+            Leaf::Type(item_type) => Some(&item_type.attrs),
+            Leaf::TraitAlias(item_trait_alias) => Some(&item_trait_alias.attrs),
+            _ => None,
+        },
+        ResolutionNode::Root { .. } => None,
+    }
+}
@@ -78,13 +78,16 @@ impl<'a, 'ast> UseResolver<'a, 'ast> {
                     resolved_uses: self.resolved_uses,
                     visited_glob_scopes: Default::default(),
                 };
-                let found_children = match path_finder.find_at_path(scope, &path_tree.path) {
-                    Ok(v) => v,
-                    Err(err) => {
-                        self.errors.push(err);
-                        return;
-                    }
-                };
+                let hops = ctx.hops.len();
+                let found_children =
+                    match path_finder.find_at_path(scope, &path_tree.path, &mut ctx.hops) {
+                        Ok(v) => v,
+                        Err(err) => {
+                            self.errors.push(err);
+                            ctx.hops.truncate(hops);
+                            return;
+                        }
+                    };
                 if found_children.len() > 1 {
                     self.errors.push(disambiguation_needed(
                         ctx.file,
@@ -108,6 +111,7 @@ impl<'a, 'ast> UseResolver<'a, 'ast> {
                 self.trace_use(ctx, new_scope, &path_tree.tree, false);
                 ctx.previous_idents
                     .truncate(ctx.previous_idents.len() - path_tree.path.segments.len());
+                ctx.hops.truncate(hops);
             }
             Name(ident) | Rename(UseTreeRename { name: ident, .. }) => {
                 let found_children: Vec<ResolutionIndex> = if ident == "self" {
@@ -133,7 +137,8 @@ impl<'a, 'ast> UseResolver<'a, 'ast> {
                     };
                     match path_finder.find_children(ctx, scope, ident, false) {
                         Ok(v) => v,
-                        Err(err) => {
+                        Err(mut err) => {
+                            err.labels.extend(ctx.hops.iter().cloned());
                             self.errors.push(err);
                             return;
                         }
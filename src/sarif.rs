@@ -0,0 +1,112 @@
+//! `--error-format=sarif`: diagnostics as a SARIF 2.1.0 log, the format that GitHub code scanning
+//! and other static analysis dashboards ingest.
+use codespan_reporting::diagnostic::{Label, LabelStyle, Severity};
+use serde_json::{json, Value as Json};
+
+use crate::error::{Diagnostic, CODES};
+use crate::find_file::{FileGraph, FileId};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// A log of one run of rhdlc that reported `diagnostics`, with files named by `uri`
+pub fn log(
+    diagnostics: &[Diagnostic],
+    file_graph: &FileGraph,
+    uri: &dyn Fn(FileId) -> String,
+) -> String {
+    let rules = CODES
+        .iter()
+        .map(|(code, severity, description)| {
+            json!({
+                "id": code,
+                "shortDescription": { "text": description },
+                "defaultConfiguration": { "level": level(*severity) },
+            })
+        })
+        .collect::<Vec<_>>();
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| result(diagnostic, file_graph, uri))
+        .collect::<Vec<_>>();
+    let log = json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rhdlc",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).unwrap() + "\n"
+}
+
+fn result(diagnostic: &Diagnostic, file_graph: &FileGraph, uri: &dyn Fn(FileId) -> String) -> Json {
+    // viewers only show the message, so the notes are appended to it
+    let mut message = diagnostic.message.clone();
+    for note in diagnostic.notes.iter() {
+        message.push('\n');
+        message.push_str(note);
+    }
+    let location = |label: &Label<FileId>| {
+        let mut location = json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": uri(label.file_id) },
+                "region": region(file_graph, label),
+            },
+        });
+        if !label.message.is_empty() {
+            location["message"] = json!({ "text": label.message });
+        }
+        location
+    };
+    let mut result = json!({
+        "level": level(diagnostic.severity),
+        "message": { "text": message },
+        "locations": diagnostic
+            .labels
+            .iter()
+            .filter(|label| label.style == LabelStyle::Primary)
+            .map(location)
+            .collect::<Vec<_>>(),
+        "relatedLocations": diagnostic
+            .labels
+            .iter()
+            .filter(|label| label.style == LabelStyle::Secondary)
+            .map(location)
+            .collect::<Vec<_>>(),
+    });
+    if let Some(code) = &diagnostic.code {
+        result["ruleId"] = json!(code);
+        if let Some(index) = CODES.iter().position(|(known, ..)| known == code) {
+            result["ruleIndex"] = json!(index);
+        }
+    }
+    result
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug | Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note | Severity::Help => "note",
+    }
+}
+
+/// The one-based lines and columns a label spans
+fn region(file_graph: &FileGraph, label: &Label<FileId>) -> Json {
+    match file_graph.line_columns(label.file_id, label.range.clone()) {
+        Some((start, end)) => json!({
+            "startLine": start.line,
+            "startColumn": start.column,
+            "endLine": end.line,
+            "endColumn": end.column,
+        }),
+        None => json!({}),
+    }
+}
@@ -0,0 +1,223 @@
+//! Statement and toggle coverage of simulations, written as an lcov tracefile.
+//!
+//! A statement is executed by a combinational process once per cycle on the settled values, by a
+//! clocked process on each edge of its clock, and by a reset body after each cycle its reset is
+//! asserted in. The hits of a line are those of the statement on it that executed most, or of the
+//! `if` whose condition it has, summed over the instances of its module.
+//! A bit of a signal toggles when it's known at two samples, every half cycle, and differs. The
+//! rises and falls of each bit are branches of the line the signal is declared on, in a block
+//! numbered by its column.
+//!
+//! `rhdlc test --lcov <file>` writes the coverage of every testbench to one tracefile, which
+//! coverage dashboards and `genhtml` read like that of software tests.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use fxhash::FxHashMap as HashMap;
+use rhdl::ast::{Span, Spanned};
+
+use super::{write_report, Value};
+use crate::error::*;
+use crate::find_file::{FileContentProvider, FileGraph, FileId};
+use crate::ir::{Clocking, Ir, ModuleIndex, ProcessKind, Stmt, Ty};
+
+/// The statements executed and the bits toggled in a simulation
+pub struct CodeCoverage {
+    /// How many times each statement of an instantiated module executed, by its file and the
+    /// start of its span
+    statements: HashMap<(FileId, usize), u64>,
+    instances: Vec<InstanceToggles>,
+}
+
+/// The bits of the signals of an instance, by signal
+struct InstanceToggles {
+    module: ModuleIndex,
+    /// The values at the last sample
+    values: Vec<Value>,
+    /// How many times each bit rose and fell
+    counts: Vec<Vec<(u64, u64)>>,
+}
+
+impl CodeCoverage {
+    /// Starts tracking instances of modules from their current values
+    pub fn new<'v>(ir: &Ir, instances: impl Iterator<Item = (ModuleIndex, &'v [Value])>) -> Self {
+        let mut statements = HashMap::default();
+        let instances = instances
+            .map(|(module, values)| {
+                let module_ir = &ir[module];
+                for process in module_ir.processes.iter() {
+                    collect(&process.body, module_ir.file, &mut statements);
+                    if let ProcessKind::Clocked(Clocking {
+                        reset: Some(reset), ..
+                    }) = &process.kind
+                    {
+                        collect(&reset.body, module_ir.file, &mut statements);
+                    }
+                }
+                InstanceToggles {
+                    module,
+                    values: values.to_vec(),
+                    counts: module_ir
+                        .signals
+                        .iter()
+                        .map(|signal| vec![(0, 0); signal.ty.width() as usize])
+                        .collect(),
+                }
+            })
+            .collect();
+        CodeCoverage {
+            statements,
+            instances,
+        }
+    }
+
+    /// Counts the statements executed by instances, by their position among the instances
+    pub fn executed(&mut self, ir: &Ir, executed: &[(usize, Span)]) {
+        for (instance, span) in executed.iter() {
+            let file = ir[self.instances[*instance].module].file;
+            if let Some(hits) = self.statements.get_mut(&(file, span.start)) {
+                *hits += 1;
+            }
+        }
+    }
+
+    /// Counts the bits of each instance's signals that toggled since the last sample
+    pub fn sample<'v>(&mut self, ir: &Ir, values: impl Iterator<Item = &'v [Value]>) {
+        for (instance, values) in self.instances.iter_mut().zip(values) {
+            let module = &ir[instance.module];
+            for (i, signal) in module.signals.iter().enumerate() {
+                let (mut before, mut after) = (vec![], vec![]);
+                bits(&instance.values[i], &signal.ty, &mut before);
+                bits(&values[i], &signal.ty, &mut after);
+                for ((count, before), after) in instance.counts[i].iter_mut().zip(before).zip(after)
+                {
+                    match (before, after) {
+                        (Some(false), Some(true)) => count.0 += 1,
+                        (Some(true), Some(false)) => count.1 += 1,
+                        _ => {}
+                    }
+                }
+            }
+            instance.values = values.to_vec();
+        }
+    }
+
+    /// Adds the hits of the lines and the toggles of the signals to a tracefile, leaving out the
+    /// files of the standard library
+    pub fn report(&self, ir: &Ir, file_graph: &FileGraph, lcov: &mut Lcov) {
+        let in_design =
+            |file: FileId| !matches!(file_graph[file].provider, FileContentProvider::Embedded(_));
+        let mut lines: HashMap<(FileId, usize), u64> = HashMap::default();
+        for ((file, start), hits) in self.statements.iter() {
+            if let (true, Some(location)) =
+                (in_design(*file), file_graph.line_column(*file, *start))
+            {
+                let line = lines.entry((*file, location.line)).or_default();
+                *line = (*line).max(*hits);
+            }
+        }
+        for ((file, line), hits) in lines {
+            *lcov.file(file_graph, file).lines.entry(line).or_default() += hits;
+        }
+
+        for instance in self.instances.iter() {
+            let module = &ir[instance.module];
+            for (signal, counts) in module.signals.iter().zip(instance.counts.iter()) {
+                let location = match file_graph.line_column(signal.file, signal.ident.span().start)
+                {
+                    Some(location) if in_design(signal.file) => location,
+                    _ => continue,
+                };
+                let branches = &mut lcov.file(file_graph, signal.file).branches;
+                for (bit, (rises, falls)) in counts.iter().enumerate() {
+                    *branches
+                        .entry((location.line, location.column, bit * 2))
+                        .or_default() += rises;
+                    *branches
+                        .entry((location.line, location.column, bit * 2 + 1))
+                        .or_default() += falls;
+                }
+            }
+        }
+    }
+}
+
+/// The statements and conditions of `if`s in a body, with no hits
+fn collect(stmts: &[Stmt], file: FileId, statements: &mut HashMap<(FileId, usize), u64>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign { span, .. } | Stmt::Property { span, .. } => {
+                statements.entry((file, span.start)).or_default();
+            }
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                statements.entry((file, cond.span.start)).or_default();
+                collect(then_branch, file, statements);
+                collect(else_branch, file, statements);
+            }
+        }
+    }
+}
+
+/// Each bit of a value of a type, from the lowest bit of its first element, `None` if it's unknown
+fn bits(value: &Value, ty: &Ty, bits_out: &mut Vec<Option<bool>>) {
+    match (value, ty) {
+        (Value::Vector(elems), Ty::Vector(elem_ty, _)) => {
+            for elem in elems.iter() {
+                bits(elem, elem_ty, bits_out);
+            }
+        }
+        (Value::Int { bits, unknown }, ty) => {
+            for bit in 0..ty.width() {
+                bits_out.push(Some(bits >> bit & 1 == 1).filter(|_| unknown >> bit & 1 == 0));
+            }
+        }
+        (Value::Vector(_), _) => unreachable!("vectors have vector types"),
+    }
+}
+
+/// The hits of lines and branches by file, over the simulations of one or more testbenches
+#[derive(Default)]
+pub struct Lcov {
+    files: BTreeMap<String, LcovFile>,
+}
+
+#[derive(Default)]
+struct LcovFile {
+    lines: BTreeMap<usize, u64>,
+    /// By line, block, and branch
+    branches: BTreeMap<(usize, usize, usize), u64>,
+}
+
+impl Lcov {
+    fn file(&mut self, file_graph: &FileGraph, file: FileId) -> &mut LcovFile {
+        let name = file_graph.inner.name(file).to_string_lossy().to_string();
+        self.files.entry(name).or_default()
+    }
+
+    /// Writes the tracefile, with a record for each file
+    pub fn write(&self, path: &Path, errors: &mut Vec<Diagnostic>) {
+        let mut tracefile = String::new();
+        for (name, file) in self.files.iter() {
+            writeln!(tracefile, "SF:{}", name).unwrap();
+            for ((line, block, branch), taken) in file.branches.iter() {
+                writeln!(tracefile, "BRDA:{},{},{},{}", line, block, branch, taken).unwrap();
+            }
+            let hit = file.branches.values().filter(|taken| **taken != 0).count();
+            writeln!(tracefile, "BRF:{}", file.branches.len()).unwrap();
+            writeln!(tracefile, "BRH:{}", hit).unwrap();
+            for (line, hits) in file.lines.iter() {
+                writeln!(tracefile, "DA:{},{}", line, hits).unwrap();
+            }
+            let hit = file.lines.values().filter(|hits| **hits != 0).count();
+            writeln!(tracefile, "LF:{}", file.lines.len()).unwrap();
+            writeln!(tracefile, "LH:{}", hit).unwrap();
+            tracefile += "end_of_record\n";
+        }
+        write_report(Some(path), &tracefile, errors);
+    }
+}
@@ -0,0 +1,699 @@
+//! The compiled backend of `rhdlc sim`, which lowers the intermediate representation of a design
+//! to a Rust program, compiles it with `rustc` and runs it, for simulations long enough that
+//! building the program pays for itself.
+//!
+//! Each process becomes a function of straight-line Rust on the bits and unknown bits of the
+//! signals of its module, and so does each expression an input of an instance is connected to,
+//! while the runtime in `runtime.rs` schedules them the way the interpreter does. The program
+//! writes the values and events of the simulation to standard output, which the report and
+//! diagnostics are made from like the interpreter's, so the backends can be used in place of
+//! each other.
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::coverage::Coverage;
+use super::eval::{format_message, mask};
+use super::model::Models;
+use super::{
+    can_simulate, covers, edge_value, initial_values, report_events, report_unknown,
+    testbench_clocks, write_coverage, write_table, Backend, Event, Simulate, UnknownOutput, Value,
+};
+use crate::analysis::signal_graph::SignalKind;
+use crate::attribute;
+use crate::error::*;
+use crate::find_file::FileId;
+use crate::ir::{
+    assigns, Connection, Expr, ExprKind, Instance, Ir, Level, MessagePart, Module, ModuleIndex,
+    ProcessKind, PropertyKind, SignalIndex, Stmt, UnaryOp,
+};
+use crate::resolution::Resolver;
+
+/// Included in every program, see its documentation
+const RUNTIME: &str = include_str!("runtime.rs");
+
+/// The number of programs built by this process, to build each in a directory of its own
+static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+/// An instance of a module in the hierarchy, the testbench first and every instance before its
+/// children, like the instances of the interpreter
+struct Flat<'a> {
+    module: ModuleIndex,
+    instance: Option<&'a Instance>,
+    /// The positions of the instances of the module
+    children: Vec<usize>,
+}
+
+fn flatten<'a>(
+    ir: &'a Ir,
+    module: ModuleIndex,
+    instance: Option<&'a Instance>,
+    flat: &mut Vec<Flat<'a>>,
+) -> usize {
+    let index = flat.len();
+    flat.push(Flat {
+        module,
+        instance,
+        children: vec![],
+    });
+    let children = ir[module]
+        .instances
+        .iter()
+        .map(|instance| flatten(ir, instance.module, Some(instance), flat))
+        .collect();
+    flat[index].children = children;
+    index
+}
+
+/// Simulates a design like [`super::simulate`] by compiling it
+pub fn simulate(
+    resolver: &Resolver,
+    ir: &Ir,
+    models: &Models,
+    simulate: &Simulate,
+    errors: &mut Vec<Diagnostic>,
+) {
+    if !can_simulate(ir, models, Backend::Compiled, errors) {
+        return;
+    }
+    let top = match ir.module_indices().next() {
+        Some(top) => top,
+        None => return,
+    };
+    let mut instances = vec![];
+    flatten(ir, top, None, &mut instances);
+    let covers = covers(instances.iter().map(|flat| &ir[flat.module]));
+    let mut coverage = match Coverage::new(ir, covers.clone(), errors) {
+        Some(coverage) => coverage,
+        None => return,
+    };
+    let clocks = testbench_clocks(ir);
+    let reported = ir[top]
+        .signal_indices()
+        .filter(|signal| !clocks.contains(signal))
+        .collect::<Vec<_>>();
+    let mut generator = Generator {
+        ir,
+        code: String::new(),
+        properties: vec![],
+        fns: 0,
+    };
+    generator.generate(&instances, &clocks, &reported);
+
+    let dir = env::temp_dir().join(format!(
+        "rhdlc-sim-{}-{}",
+        std::process::id(),
+        BUILDS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let output = build(&dir, &generator.code).and_then(|program| run(&program, simulate.cycles));
+    let _ = fs::remove_dir_all(&dir);
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            errors.push(err);
+            return;
+        }
+    };
+
+    let testbench = &ir[top];
+    let mut rows = vec![std::iter::once("cycle".to_string())
+        .chain(
+            reported
+                .iter()
+                .map(|signal| testbench[*signal].name.clone()),
+        )
+        .collect::<Vec<_>>()];
+    let mut events = vec![];
+    let mut unknown_outputs = vec![];
+    let mut cycles = 0;
+    // the clocks aren't reported, and can't be coverpoints
+    let mut values = initial_values(testbench, None);
+    for line in output.lines() {
+        let mut words = line.split(' ');
+        let keyword = words.next().unwrap_or_default();
+        if keyword == "row" {
+            let cycle = (rows.len() - 1).to_string();
+            let mut row = vec![cycle];
+            for (value, signal) in words.zip(reported.iter()) {
+                match parse_value(value) {
+                    Some(value) => {
+                        row.push(value.display(&testbench[*signal].ty).to_string());
+                        values[signal.index()] = value;
+                    }
+                    None => {
+                        errors.push(run_simulation("its output couldn't be read"));
+                        return;
+                    }
+                }
+            }
+            rows.push(row);
+            coverage.sample(&values);
+            continue;
+        }
+        if keyword == "event" {
+            match parse_event(&generator.properties, words) {
+                Some(event) => events.push(event),
+                None => {
+                    errors.push(run_simulation("its output couldn't be read"));
+                    return;
+                }
+            }
+            continue;
+        }
+        let numbers = words
+            .map(|word| word.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok();
+        match (keyword, numbers.as_deref()) {
+            ("unknown", Some(&[instance, port, cycle])) => {
+                let module = &ir[testbench.instances[instance as usize].module];
+                unknown_outputs.push(UnknownOutput {
+                    instance: instance as usize,
+                    port: module.signal_indices().nth(port as usize).unwrap(),
+                    cycle,
+                });
+            }
+            ("unsettled", Some(&[cycle])) => errors.push(unsettled_simulation(cycle)),
+            ("cycles", Some(&[count])) => cycles = count,
+            _ => {}
+        }
+    }
+    // the design didn't settle before the first cycle
+    if rows.len() == 1 {
+        return;
+    }
+    report_events(&events, &covers, cycles, errors);
+    report_unknown(resolver, ir, &unknown_outputs, errors);
+    write_table(rows, simulate.output.as_deref(), errors);
+    coverage.count(&events);
+    write_coverage(resolver, &coverage, simulate.coverage.as_deref(), errors);
+}
+
+/// Parses the property, cycle and message values of an event written by the program
+fn parse_event<'w>(
+    properties: &[(&Stmt, FileId)],
+    mut words: impl Iterator<Item = &'w str>,
+) -> Option<Event> {
+    let id = words.next()?.parse::<usize>().ok()?;
+    let cycle = words.next()?.parse::<u64>().ok()?;
+    let values = words.map(parse_value).collect::<Option<Vec<_>>>()?;
+    match properties.get(id)? {
+        (
+            Stmt::Property {
+                kind,
+                level,
+                message,
+                span,
+                ..
+            },
+            file,
+        ) => Some(Event {
+            kind: *kind,
+            file: *file,
+            span: *span,
+            cycle,
+            test: None,
+            level: *level,
+            message: Some(format_message(message, values.into_iter()))
+                .filter(|_| !message.is_empty()),
+        }),
+        _ => None,
+    }
+}
+
+/// Compiles a program in a new directory, returning its path
+fn build(dir: &Path, code: &str) -> Result<std::path::PathBuf, Diagnostic> {
+    let source = dir.join("sim.rs");
+    fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&source, code))
+        .map_err(|err| write_output(&source.to_string_lossy(), err))?;
+    let program = dir.join(format!("sim{}", env::consts::EXE_SUFFIX));
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    log::info!(
+        "compiling the simulation with {} in {}",
+        rustc.to_string_lossy(),
+        dir.display()
+    );
+    let output = Command::new(&rustc)
+        .args(&["--edition", "2018", "-O", "--crate-name", "rhdlc_sim", "-o"])
+        .arg(&program)
+        .arg(&source)
+        .output()
+        .map_err(|err| run_rustc(&rustc.to_string_lossy(), err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let err = stderr
+            .lines()
+            .find(|line| line.starts_with("error"))
+            .unwrap_or("rustc failed");
+        return Err(compile_simulation(err));
+    }
+    Ok(program)
+}
+
+/// Runs a program for a number of cycles, returning its output
+fn run(program: &Path, cycles: u64) -> Result<String, Diagnostic> {
+    let output = Command::new(program)
+        .arg(cycles.to_string())
+        .output()
+        .map_err(start_simulation)?;
+    if !output.status.success() {
+        return Err(run_simulation(&format!("it exited with {}", output.status)));
+    }
+    String::from_utf8(output.stdout).map_err(|err| run_simulation(&err.to_string()))
+}
+
+/// Parses a value written by the program, see `write_value` in the runtime
+fn parse_value(text: &str) -> Option<Value> {
+    fn parse(text: &mut &str) -> Option<Value> {
+        if let Some(rest) = text.strip_prefix('[') {
+            *text = rest;
+            let mut elems = vec![];
+            if let Some(rest) = text.strip_prefix(']') {
+                *text = rest;
+                return Some(Value::Vector(elems));
+            }
+            loop {
+                elems.push(parse(text)?);
+                let separator = text.chars().next()?;
+                *text = &text[1..];
+                match separator {
+                    ',' => {}
+                    ']' => return Some(Value::Vector(elems)),
+                    _ => return None,
+                }
+            }
+        }
+        let end = text.find(|c| c == ',' || c == ']').unwrap_or(text.len());
+        let slash = text[..end].find('/')?;
+        let value = Value::Int {
+            bits: u128::from_str_radix(&text[..slash], 16).ok()?,
+            unknown: u128::from_str_radix(&text[slash + 1..end], 16).ok()?,
+        };
+        *text = &text[end..];
+        Some(value)
+    }
+    let mut text = text;
+    parse(&mut text).filter(|_| text.is_empty())
+}
+
+/// Generates the program simulating a design
+struct Generator<'a, 'ast> {
+    ir: &'a Ir<'ast>,
+    code: String,
+    /// Each property and the file it's in, by the number the program reports it with
+    properties: Vec<(&'a Stmt, FileId)>,
+    /// The number of functions generated for branches so far, to name them apart
+    fns: usize,
+}
+
+impl<'a, 'ast> Generator<'a, 'ast> {
+    fn generate(&mut self, instances: &[Flat], clocks: &[SignalIndex], reported: &[SignalIndex]) {
+        let ir = self.ir;
+        writeln!(
+            self.code,
+            "// Generated by rhdlc to simulate `{}`\n#![allow(warnings)]\n",
+            ir.modules[0].name
+        )
+        .unwrap();
+        self.code += RUNTIME;
+
+        let mut modules = String::new();
+        for (m, module) in ir.modules.iter().enumerate() {
+            let tristate = module
+                .signal_indices()
+                .filter(|signal| attribute::find_flag(module[*signal].attrs, "tristate").is_some())
+                .collect::<Vec<_>>();
+            let mut processes = vec![];
+            for (p, process) in module.processes.iter().enumerate() {
+                let body = format!("m{}_p{}", m, p);
+                match &process.kind {
+                    ProcessKind::Comb => {
+                        self.body(&body, module, &process.body, true);
+                        let buses = tristate
+                            .iter()
+                            .filter(|signal| assigns(&process.body, **signal))
+                            .map(|signal| {
+                                format!("({}, m{}_z{})", signal.index(), m, signal.index())
+                            })
+                            .collect::<Vec<_>>();
+                        processes.push(format!(
+                            "Process::Comb {{ body: {}, buses: &[{}] }}",
+                            body,
+                            buses.join(", ")
+                        ));
+                    }
+                    ProcessKind::Clocked(clocking) => {
+                        self.body(&body, module, &process.body, false);
+                        let reset = match &clocking.reset {
+                            Some(reset) => {
+                                let reset_body = format!("m{}_r{}", m, p);
+                                self.body(&reset_body, module, &reset.body, false);
+                                format!(
+                                    "Some(Reset {{ signal: {}, active_high: {}, body: {} }})",
+                                    reset.signal.index(),
+                                    reset.active_high,
+                                    reset_body
+                                )
+                            }
+                            None => "None".to_string(),
+                        };
+                        processes.push(format!(
+                            "Process::Clocked {{ clock: {}, to: {}, reset: {}, body: {} }}",
+                            clocking.clock.index(),
+                            edge_value(clocking.edge),
+                            reset,
+                            body
+                        ));
+                    }
+                }
+            }
+            for signal in tristate.iter() {
+                writeln!(
+                    self.code,
+                    "fn m{}_z{}() -> Value {{ {} }}",
+                    m,
+                    signal.index(),
+                    value_code(&Value::z(&module[*signal].ty))
+                )
+                .unwrap();
+            }
+
+            let mut instance_infos = vec![];
+            for (i, instance) in module.instances.iter().enumerate() {
+                let child = &ir[instance.module];
+                let mut connections = vec![];
+                for (c, connection) in instance.connections.iter().enumerate() {
+                    connections.push(match connection {
+                        Connection::Input { port, value } => {
+                            let name = format!("m{}_i{}_c{}", m, i, c);
+                            writeln!(
+                                self.code,
+                                "fn {}(next: &[Value]) -> Value {{ {} }}",
+                                name,
+                                value_expr(value, "next")
+                            )
+                            .unwrap();
+                            format!(
+                                "Connection::Input {{ port: {}, value: {} }}",
+                                port.index(),
+                                name
+                            )
+                        }
+                        Connection::Output { port, signal } => format!(
+                            "Connection::Output {{ port: {}, signal: {} }}",
+                            port.index(),
+                            signal.index()
+                        ),
+                        Connection::InOut { port, signal } => format!(
+                            "Connection::InOut {{ port: {}, signal: {}, assigned: {} }}",
+                            port.index(),
+                            signal.index(),
+                            child.is_assigned(*port)
+                        ),
+                    });
+                }
+                instance_infos.push(format!(
+                    "InstanceInfo {{ connections: &[{}] }}",
+                    connections.join(", ")
+                ));
+            }
+
+            writeln!(
+                modules,
+                "    ModuleInfo {{\n        outputs: &[{}],\n        tristate: &[{}],\n        \
+                 processes: &[{}],\n        instances: &[{}],\n    }},",
+                list(
+                    module
+                        .signals
+                        .iter()
+                        .map(|signal| signal.kind == SignalKind::Output)
+                ),
+                list(tristate.iter().map(|signal| signal.index())),
+                processes.join(", "),
+                instance_infos.join(", ")
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            self.code,
+            "\nconst MODULES: &[ModuleInfo] = &[\n{}];",
+            modules
+        )
+        .unwrap();
+        writeln!(
+            self.code,
+            "const INSTANCES: &[(usize, &[usize])] = &[{}];",
+            instances
+                .iter()
+                .map(|flat| format!(
+                    "({}, &[{}])",
+                    flat.module.index(),
+                    list(flat.children.iter())
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .unwrap();
+        writeln!(
+            self.code,
+            "const CLOCKS: &[usize] = &[{}];",
+            list(clocks.iter().map(|signal| signal.index()))
+        )
+        .unwrap();
+        writeln!(
+            self.code,
+            "const REPORTED: &[usize] = &[{}];",
+            list(reported.iter().map(|signal| signal.index()))
+        )
+        .unwrap();
+        let kinds = self.properties.iter().map(|(property, _)| match property {
+            Stmt::Property { kind, level, .. } => (*kind, *level),
+            _ => unreachable!("only properties are reported"),
+        });
+        writeln!(
+            self.code,
+            "const IS_COVER: &[bool] = &[{}];",
+            list(kinds.clone().map(|(kind, _)| kind == PropertyKind::Cover))
+        )
+        .unwrap();
+        writeln!(
+            self.code,
+            "const IS_FATAL: &[bool] = &[{}];",
+            list(kinds.map(|(kind, level)| kind != PropertyKind::Cover && level == Level::Fatal))
+        )
+        .unwrap();
+        writeln!(self.code, "fn init() -> Vec<Vec<Value>> {{\n    vec![").unwrap();
+        for flat in instances {
+            let values = initial_values(&ir[flat.module], flat.instance)
+                .iter()
+                .map(value_code)
+                .collect::<Vec<_>>();
+            writeln!(self.code, "        vec![{}],", values.join(", ")).unwrap();
+        }
+        writeln!(self.code, "    ]\n}}").unwrap();
+    }
+
+    /// Generates a function executing statements, with the signature of `Body` in the runtime.
+    /// Blocking statements read the values they assigned, from `next`, and the others the
+    /// values before them, from `cur`.
+    fn body(&mut self, name: &str, module: &Module, stmts: &'a [Stmt], blocking: bool) {
+        let read = if blocking { "next" } else { "cur" };
+        let mut code = format!(
+            "fn {}(cur: &[Value], next: &mut [Value], ev: &mut Vec<(usize, Vec<Value>)>) {{\n",
+            name
+        );
+        for stmt in stmts {
+            match stmt {
+                Stmt::Assign {
+                    target,
+                    index,
+                    value,
+                    ..
+                } => {
+                    let index = match index {
+                        Some(index) => format!("Some({})", int_expr(index, read)),
+                        None => "None".to_string(),
+                    };
+                    writeln!(
+                        code,
+                        "    {{\n        let value = {};\n        let index = {};\n        \
+                         assign(&mut next[{}], index, value, {});\n    }}",
+                        value_expr(value, read),
+                        index,
+                        target.index(),
+                        module[*target].ty.width()
+                    )
+                    .unwrap();
+                }
+                Stmt::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => {
+                    // each branch is a function of its own, since both are taken under an
+                    // unknown condition
+                    let then_name = format!("{}_{}", name, self.fns);
+                    let else_name = format!("{}_{}", name, self.fns + 1);
+                    self.fns += 2;
+                    self.body(&then_name, module, then_branch, blocking);
+                    self.body(&else_name, module, else_branch, blocking);
+                    writeln!(
+                        code,
+                        "    match truth({}) {{\n        Some(true) => {}(cur, next, ev),\n        \
+                         Some(false) => {}(cur, next, ev),\n        \
+                         None => branches(cur, next, ev, {}, {}),\n    }}",
+                        int_expr(cond, read),
+                        then_name,
+                        else_name,
+                        then_name,
+                        else_name
+                    )
+                    .unwrap();
+                }
+                Stmt::Property {
+                    kind,
+                    cond,
+                    message,
+                    ..
+                } => {
+                    let id = self.properties.len();
+                    self.properties.push((stmt, module.file));
+                    let op = if *kind == PropertyKind::Cover {
+                        "=="
+                    } else {
+                        "!="
+                    };
+                    let values = message.iter().filter_map(|part| match part {
+                        MessagePart::Text(_) => None,
+                        MessagePart::Value(expr) => Some(value_expr(expr, read)),
+                    });
+                    writeln!(
+                        code,
+                        "    if truth({}) {} Some(true) {{\n        \
+                         ev.push(({}, vec![{}]));\n    }}",
+                        int_expr(cond, read),
+                        op,
+                        id,
+                        list(values)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        code += "}\n";
+        self.code += &code;
+    }
+}
+
+/// The items of a list, separated by commas
+fn list<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    items
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A Rust expression for an integer expression, of type `Int`
+fn int_expr(expr: &Expr, read: &str) -> String {
+    let width = expr.ty.width();
+    match &expr.kind {
+        ExprKind::Const(value) => format!("({:#x}, 0)", *value as u128 & mask(width)),
+        ExprKind::Signal(signal) => format!("{}[{}].int()", read, signal.index()),
+        ExprKind::Unary(op, operand) => {
+            let op = match op {
+                UnaryOp::Not => "not",
+                UnaryOp::Neg => "neg",
+                UnaryOp::ReduceOr => "reduce_or",
+            };
+            format!("{}({}, {})", op, int_expr(operand, read), width)
+        }
+        ExprKind::Binary(op, lhs, rhs) => format!(
+            "binary(Op::{:?}, {}, {}, {}, {}, {})",
+            op,
+            int_expr(lhs, read),
+            int_expr(rhs, read),
+            lhs.ty.width(),
+            lhs.ty.is_signed(),
+            width
+        ),
+        ExprKind::Index(base, _) if base.ty.is_vector() => {
+            format!("{}.int()", value_expr(expr, read))
+        }
+        ExprKind::Index(base, index) => format!(
+            "bit({}, {}, {})",
+            int_expr(base, read),
+            int_expr(index, read),
+            width
+        ),
+        ExprKind::Resize(operand) => format!(
+            "resize({}, {}, {}, {})",
+            int_expr(operand, read),
+            operand.ty.width(),
+            operand.ty.is_signed(),
+            width
+        ),
+        ExprKind::Mux(cond, then_value, else_value) => format!(
+            "mux_int(truth({}), || {}, || {})",
+            int_expr(cond, read),
+            int_expr(then_value, read),
+            int_expr(else_value, read)
+        ),
+    }
+}
+
+/// A Rust expression for an expression, of type `Value`
+fn value_expr(expr: &Expr, read: &str) -> String {
+    match &expr.kind {
+        ExprKind::Signal(signal) => format!("{}[{}].clone()", read, signal.index()),
+        ExprKind::Index(base, index) if base.ty.is_vector() => {
+            let base = match &base.kind {
+                ExprKind::Signal(signal) => format!("&{}[{}]", read, signal.index()),
+                _ => format!("&{}", value_expr(base, read)),
+            };
+            format!(
+                "element({}, {}, || {}, || {})",
+                base,
+                int_expr(index, read),
+                value_code(&Value::x(&expr.ty)),
+                value_code(&Value::zero(&expr.ty))
+            )
+        }
+        ExprKind::Mux(cond, then_value, else_value) if expr.ty.is_vector() => format!(
+            "mux(truth({}), || {}, || {})",
+            int_expr(cond, read),
+            value_expr(then_value, read),
+            value_expr(else_value, read)
+        ),
+        _ => format!("value({})", int_expr(expr, read)),
+    }
+}
+
+/// A Rust expression constructing a value
+fn value_code(value: &Value) -> String {
+    match value {
+        Value::Int { bits, unknown } => {
+            format!(
+                "Value::Int {{ bits: {:#x}, unknown: {:#x} }}",
+                bits, unknown
+            )
+        }
+        Value::Vector(elems) => match elems.first() {
+            Some(first) if elems.iter().all(|elem| elem == first) => {
+                format!(
+                    "Value::Vector(vec![{}; {}])",
+                    value_code(first),
+                    elems.len()
+                )
+            }
+            _ => format!(
+                "Value::Vector(vec![{}])",
+                list(elems.iter().map(value_code))
+            ),
+        },
+    }
+}
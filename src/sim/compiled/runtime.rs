@@ -0,0 +1,709 @@
+//! The runtime of the programs the compiled backend generates, included in each of them as it is
+//! rather than compiled into rhdlc.
+//!
+//! It mirrors the interpreter: the operators have the semantics of `eval.rs` on the bits and
+//! unknown bits of integers, and [`Simulation`] schedules the processes and connections of the
+//! instances like the interpreter's does, so both backends report the same values. The generated
+//! code defines the processes and connections of each module as functions, and the tables of
+//! the design: `MODULES`, `INSTANCES`, `CLOCKS`, `REPORTED`, `IS_COVER`, `IS_FATAL` and `init`.
+//!
+//! The program simulates the number of cycles it's given and writes what it found to standard
+//! output a line at a time: `row` and the reported values after each cycle, `event` and the
+//! property, cycle and values of the message of each event, `unknown` and the instance, port and
+//! cycle of each unknown output, `unsettled` and the cycle the design didn't settle in, and
+//! `cycles` and the number of cycles simulated. It stops after a cycle a `fatal!` failed in.
+use std::io::{self, BufWriter, Write};
+
+/// How many times signals may change before a cycle is considered stuck in a loop
+const SETTLE_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    /// The bits that are unknown are X if their bit is clear, or Z if it is set
+    Int {
+        bits: u128,
+        unknown: u128,
+    },
+    Vector(Vec<Value>),
+}
+
+/// The bits and unknown bits of an integer
+type Int = (u128, u128);
+
+impl Value {
+    fn int(&self) -> Int {
+        match self {
+            Value::Int { bits, unknown } => (*bits, *unknown),
+            Value::Vector(_) => unreachable!("vectors are only indexed or assigned as a whole"),
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        match self {
+            Value::Int { unknown, .. } => *unknown == 0,
+            Value::Vector(elems) => elems.iter().all(Value::is_known),
+        }
+    }
+}
+
+fn value((bits, unknown): Int) -> Value {
+    Value::Int { bits, unknown }
+}
+
+fn known(bits: u128) -> Value {
+    Value::Int { bits, unknown: 0 }
+}
+
+fn mask(width: u128) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+fn to_signed(bits: u128, width: u128) -> i128 {
+    if width == 0 || width >= 128 {
+        bits as i128
+    } else if bits >> (width - 1) & 1 == 1 {
+        (bits | !mask(width)) as i128
+    } else {
+        bits as i128
+    }
+}
+
+fn int(bits: u128, width: u128) -> Int {
+    (bits & mask(width), 0)
+}
+
+fn x(width: u128) -> Int {
+    (0, mask(width))
+}
+
+/// The result of logic, whose unknown bits are X
+fn logic(bits: u128, unknown: u128, width: u128) -> Int {
+    let mask = mask(width);
+    (bits & !unknown & mask, unknown & mask)
+}
+
+/// Whether a condition holds, or `None` if it's unknown
+fn truth((bits, unknown): Int) -> Option<bool> {
+    match unknown {
+        0 => Some(bits != 0),
+        _ => None,
+    }
+}
+
+/// The integer either of two integers could be, unknown where they differ
+fn merge_int((a_bits, a_unknown): Int, (b_bits, b_unknown): Int) -> Int {
+    let unknown = a_unknown | b_unknown | (a_bits ^ b_bits);
+    (a_bits & !unknown, unknown)
+}
+
+fn merge(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Vector(a), Value::Vector(b)) => {
+            Value::Vector(a.into_iter().zip(b).map(|(a, b)| merge(a, b)).collect())
+        }
+        (a, b) => value(merge_int(a.int(), b.int())),
+    }
+}
+
+/// The value of a tri-state bus driven by two drivers
+fn resolve(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Vector(a), Value::Vector(b)) => {
+            Value::Vector(a.into_iter().zip(b).map(|(a, b)| resolve(a, b)).collect())
+        }
+        (a, b) => {
+            let ((a_bits, a_unknown), (b_bits, b_unknown)) = (a.int(), b.int());
+            let a_z = a_bits & a_unknown;
+            let b_z = b_bits & b_unknown & !a_z;
+            let driven = !(a_z | b_z);
+            let (merged_bits, merged_unknown) = merge_int(a.int(), b.int());
+            Value::Int {
+                bits: (a_z & b_bits) | (b_z & a_bits) | (driven & merged_bits),
+                unknown: (a_z & b_unknown) | (b_z & a_unknown) | (driven & merged_unknown),
+            }
+        }
+    }
+}
+
+/// `cond ? a : b`, either of them where the condition is unknown
+#[inline(always)]
+fn mux_int(cond: Option<bool>, a: impl FnOnce() -> Int, b: impl FnOnce() -> Int) -> Int {
+    match cond {
+        Some(true) => a(),
+        Some(false) => b(),
+        None => merge_int(a(), b()),
+    }
+}
+
+fn mux(cond: Option<bool>, a: impl FnOnce() -> Value, b: impl FnOnce() -> Value) -> Value {
+    match cond {
+        Some(true) => a(),
+        Some(false) => b(),
+        None => merge(a(), b()),
+    }
+}
+
+fn not((bits, unknown): Int, width: u128) -> Int {
+    logic(!bits, unknown, width)
+}
+
+fn neg((bits, unknown): Int, width: u128) -> Int {
+    if unknown != 0 {
+        x(width)
+    } else {
+        int(bits.wrapping_neg(), width)
+    }
+}
+
+fn reduce_or((bits, unknown): Int, width: u128) -> Int {
+    if bits & !unknown != 0 {
+        (1, 0)
+    } else {
+        logic(0, (unknown != 0) as u128, width)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A binary operator on operands of `width` bits, whose result has `out` bits
+#[inline(always)]
+fn binary(
+    op: Op,
+    (lhs, lhs_unknown): Int,
+    (rhs, rhs_unknown): Int,
+    width: u128,
+    signed: bool,
+    out: u128,
+) -> Int {
+    let unknown = lhs_unknown | rhs_unknown;
+    let (lhs_signed, rhs_signed) = (to_signed(lhs, width), to_signed(rhs, width));
+    match op {
+        Op::BitAnd | Op::And => {
+            let zeros = (!lhs & !lhs_unknown) | (!rhs & !rhs_unknown);
+            logic(lhs & rhs, unknown & !zeros, width)
+        }
+        Op::BitOr | Op::Or => {
+            let ones = (lhs & !lhs_unknown) | (rhs & !rhs_unknown);
+            logic(ones, unknown & !ones, width)
+        }
+        Op::BitXor => logic(lhs ^ rhs, unknown, width),
+        Op::Eq | Op::Ne if (lhs ^ rhs) & !unknown != 0 => ((op == Op::Ne) as u128, 0),
+        Op::Shl | Op::Shr if rhs_unknown != 0 => x(out),
+        Op::Shl if rhs >= width => (0, 0),
+        Op::Shl => logic(lhs << rhs, lhs_unknown << rhs, width),
+        Op::Shr if signed => logic(
+            (lhs_signed >> rhs.min(127)) as u128,
+            (to_signed(lhs_unknown, width) >> rhs.min(127)) as u128,
+            width,
+        ),
+        Op::Shr if rhs >= width => (0, 0),
+        Op::Shr => logic(lhs >> rhs, lhs_unknown >> rhs, width),
+        _ if unknown != 0 => x(out),
+        Op::Add => int(lhs.wrapping_add(rhs), width),
+        Op::Sub => int(lhs.wrapping_sub(rhs), width),
+        Op::Mul => int(lhs.wrapping_mul(rhs), width),
+        Op::Div if rhs == 0 => int(u128::MAX, width),
+        Op::Rem if rhs == 0 => (lhs, 0),
+        Op::Div if signed => int(lhs_signed.wrapping_div(rhs_signed) as u128, width),
+        Op::Rem if signed => int(lhs_signed.wrapping_rem(rhs_signed) as u128, width),
+        Op::Div => int(lhs / rhs, width),
+        Op::Rem => int(lhs % rhs, width),
+        Op::Eq => ((lhs == rhs) as u128, 0),
+        Op::Ne => ((lhs != rhs) as u128, 0),
+        Op::Lt if signed => ((lhs_signed < rhs_signed) as u128, 0),
+        Op::Le if signed => ((lhs_signed <= rhs_signed) as u128, 0),
+        Op::Gt if signed => ((lhs_signed > rhs_signed) as u128, 0),
+        Op::Ge if signed => ((lhs_signed >= rhs_signed) as u128, 0),
+        Op::Lt => ((lhs < rhs) as u128, 0),
+        Op::Le => ((lhs <= rhs) as u128, 0),
+        Op::Gt => ((lhs > rhs) as u128, 0),
+        Op::Ge => ((lhs >= rhs) as u128, 0),
+    }
+}
+
+/// A bit of an integer
+fn bit((bits, unknown): Int, (index, index_unknown): Int, out: u128) -> Int {
+    if index_unknown != 0 {
+        x(out)
+    } else if index < 128 {
+        (bits >> index & 1, unknown >> index & 1)
+    } else {
+        (0, 0)
+    }
+}
+
+/// An element of a vector, given the unknown and zero values of an element
+fn element(
+    base: &Value,
+    (index, index_unknown): Int,
+    x: impl Fn() -> Value,
+    zero: impl Fn() -> Value,
+) -> Value {
+    match base {
+        Value::Vector(elems) if index_unknown != 0 => {
+            let mut elems = elems.iter().cloned();
+            match elems.next() {
+                Some(first) => elems.fold(first, merge),
+                None => x(),
+            }
+        }
+        Value::Int { .. } if index_unknown != 0 => x(),
+        Value::Vector(elems) if index < elems.len() as u128 => elems[index as usize].clone(),
+        Value::Vector(_) => zero(),
+        Value::Int { bits, unknown } if index < 128 => Value::Int {
+            bits: bits >> index & 1,
+            unknown: unknown >> index & 1,
+        },
+        Value::Int { .. } => known(0),
+    }
+}
+
+fn resize((bits, unknown): Int, from: u128, signed: bool, to: u128) -> Int {
+    if signed {
+        (
+            to_signed(bits, from) as u128 & mask(to),
+            to_signed(unknown, from) as u128 & mask(to),
+        )
+    } else {
+        (bits & mask(to), unknown & mask(to))
+    }
+}
+
+/// Assigns to a signal of `width` bits, or to an element or bit of it
+fn assign(target: &mut Value, index: Option<Int>, value: Value, width: u128) {
+    match (index, target) {
+        (None, target) => *target = value,
+        (Some((_, index_unknown)), Value::Vector(elems)) if index_unknown != 0 => {
+            for elem in elems.iter_mut() {
+                *elem = merge(elem.clone(), value.clone());
+            }
+        }
+        (Some((_, index_unknown)), target) if index_unknown != 0 => {
+            let (bit, bit_unknown) = value.int();
+            let spread = Value::Int {
+                bits: if bit == 1 { mask(width) } else { 0 },
+                unknown: if bit_unknown == 1 { mask(width) } else { 0 },
+            };
+            *target = merge(target.clone(), spread);
+        }
+        (Some((index, _)), Value::Vector(elems)) => {
+            if index < elems.len() as u128 {
+                elems[index as usize] = value;
+            }
+        }
+        (Some((index, _)), Value::Int { bits, unknown }) => {
+            if index < width {
+                let (bit, bit_unknown) = value.int();
+                *bits = (*bits & !(1 << index)) | (bit << index);
+                *unknown = (*unknown & !(1 << index)) | (bit_unknown << index);
+            }
+        }
+    }
+}
+
+/// The statements of a process, reading the values before them and writing the values after
+/// them, and the events of its properties with the values of their messages
+type Body = fn(&[Value], &mut [Value], &mut Vec<(usize, Vec<Value>)>);
+
+/// Takes both branches of an `if` whose condition is unknown, leaving the values they disagree
+/// on unknown. Only the failures of the properties in them are events.
+fn branches(
+    cur: &[Value],
+    next: &mut [Value],
+    ev: &mut Vec<(usize, Vec<Value>)>,
+    then_branch: Body,
+    else_branch: Body,
+) {
+    let mut else_next = next.to_vec();
+    let mut branch_events = vec![];
+    then_branch(cur, next, &mut branch_events);
+    else_branch(cur, &mut else_next, &mut branch_events);
+    for (value, else_value) in next.iter_mut().zip(else_next) {
+        *value = merge(value.clone(), else_value);
+    }
+    ev.extend(branch_events.into_iter().filter(|(id, _)| !IS_COVER[*id]));
+}
+
+struct ModuleInfo {
+    /// Whether each signal is an output port
+    outputs: &'static [bool],
+    /// The signals marked `#[tristate]`
+    tristate: &'static [usize],
+    processes: &'static [Process],
+    instances: &'static [InstanceInfo],
+}
+
+enum Process {
+    Comb {
+        body: Body,
+        /// The tri-state buses it drives, and their value while it doesn't
+        buses: &'static [(usize, fn() -> Value)],
+    },
+    Clocked {
+        clock: usize,
+        /// The value of the clock after the edge
+        to: u128,
+        reset: Option<Reset>,
+        body: Body,
+    },
+}
+
+struct Reset {
+    signal: usize,
+    active_high: bool,
+    body: Body,
+}
+
+struct InstanceInfo {
+    connections: &'static [Connection],
+}
+
+enum Connection {
+    Input {
+        port: usize,
+        value: fn(&[Value]) -> Value,
+    },
+    Output {
+        port: usize,
+        signal: usize,
+    },
+    InOut {
+        port: usize,
+        signal: usize,
+        /// Whether the instance assigns the port, rather than its parent the signal
+        assigned: bool,
+    },
+}
+
+fn level(active_high: bool) -> Value {
+    known(active_high as u128)
+}
+
+struct Simulation {
+    /// The values of each instance, the testbench first, indexed like `INSTANCES`
+    values: Vec<Vec<Value>>,
+    /// Whether an asynchronous reset of each instance has been asserted
+    reset: Vec<bool>,
+    cycle: u64,
+    /// The properties, cycles and message values of the events of clocked processes, since they
+    /// were last taken
+    events: Vec<(usize, u64, Vec<Value>)>,
+    /// The instances among the testbench's, ports and cycles of unknown outputs
+    unknown_outputs: Vec<(usize, usize, u64)>,
+}
+
+impl Simulation {
+    fn new() -> Self {
+        let values = init();
+        Simulation {
+            reset: vec![false; values.len()],
+            values,
+            cycle: 0,
+            events: vec![],
+            unknown_outputs: vec![],
+        }
+    }
+
+    fn step(&mut self) -> Result<(), ()> {
+        self.drive_clocks(1)?;
+        self.drive_clocks(0)?;
+        self.cycle += 1;
+        self.find_unknown_outputs();
+        Ok(())
+    }
+
+    fn find_unknown_outputs(&mut self) {
+        for (i, child) in INSTANCES[0].1.iter().enumerate() {
+            if !self.is_out_of_reset(*child) {
+                continue;
+            }
+            let module = &MODULES[INSTANCES[*child].0];
+            for (port, value) in self.values[*child].iter().enumerate() {
+                if module.outputs[port]
+                    && !value.is_known()
+                    && !self
+                        .unknown_outputs
+                        .iter()
+                        .any(|(instance, other, _)| *instance == i && *other == port)
+                {
+                    self.unknown_outputs.push((i, port, self.cycle));
+                }
+            }
+        }
+    }
+
+    fn is_out_of_reset(&self, index: usize) -> bool {
+        let (module, children) = INSTANCES[index];
+        let values = &self.values[index];
+        let released = MODULES[module]
+            .processes
+            .iter()
+            .all(|process| match process {
+                Process::Clocked {
+                    reset: Some(reset), ..
+                } => self.reset[index] && values[reset.signal] == level(!reset.active_high),
+                _ => true,
+            });
+        released && children.iter().all(|child| self.is_out_of_reset(*child))
+    }
+
+    /// Checks the properties of combinational processes on the current values,
+    /// and returns every event since the last check
+    fn check(&mut self) -> Vec<(usize, u64, Vec<Value>)> {
+        let mut events = std::mem::take(&mut self.events);
+        for (index, values) in self.values.iter().enumerate() {
+            for process in MODULES[INSTANCES[index].0].processes.iter() {
+                if let Process::Comb { body, .. } = process {
+                    let mut process_events = vec![];
+                    let mut next = values.clone();
+                    body(values, &mut next, &mut process_events);
+                    let cycle = self.cycle;
+                    events.extend(
+                        process_events
+                            .into_iter()
+                            .map(|(id, values)| (id, cycle, values)),
+                    );
+                }
+            }
+        }
+        events
+    }
+
+    fn drive_clocks(&mut self, value: u128) -> Result<(), ()> {
+        let mut before = self.values.clone();
+        for clock in CLOCKS.iter() {
+            self.values[0][*clock] = known(value);
+        }
+        for _ in 0..SETTLE_LIMIT {
+            self.settle()?;
+            let updates = self.clock_edge(&before);
+            if updates.is_empty() {
+                return Ok(());
+            }
+            before = self.values.clone();
+            for (instance, signal, value) in updates {
+                self.values[instance][signal] = value;
+            }
+        }
+        Err(())
+    }
+
+    fn clock_edge(&mut self, before: &[Vec<Value>]) -> Vec<(usize, usize, Value)> {
+        let mut updates = vec![];
+        for (index, values) in self.values.iter().enumerate() {
+            for process in MODULES[INSTANCES[index].0].processes.iter() {
+                let (clock, to, reset, body) = match process {
+                    Process::Clocked {
+                        clock,
+                        to,
+                        reset,
+                        body,
+                    } => (*clock, *to, reset, body),
+                    Process::Comb { .. } => continue,
+                };
+                if let Some(reset) = reset {
+                    if values[reset.signal] == level(reset.active_high) {
+                        continue;
+                    }
+                }
+                if before[index][clock] != known(1 - to) || values[clock] != known(to) {
+                    continue;
+                }
+                let mut next = values.clone();
+                let mut process_events = vec![];
+                body(values, &mut next, &mut process_events);
+                let cycle = self.cycle;
+                self.events.extend(
+                    process_events
+                        .into_iter()
+                        .map(|(id, values)| (id, cycle, values)),
+                );
+                for (signal, value) in next.into_iter().enumerate() {
+                    if value != values[signal] {
+                        updates.push((index, signal, value));
+                    }
+                }
+            }
+        }
+        updates
+    }
+
+    fn settle(&mut self) -> Result<(), ()> {
+        for _ in 0..SETTLE_LIMIT {
+            let mut changed = false;
+            for index in 0..self.values.len() {
+                changed |= self.settle_instance(index);
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+        Err(())
+    }
+
+    fn settle_instance(&mut self, index: usize) -> bool {
+        let (module, children) = INSTANCES[index];
+        let module = &MODULES[module];
+        let mut next = self.values[index].clone();
+        let mut reset_asserted = false;
+        let mut bus_drivers = vec![];
+        let mut events = vec![];
+        for process in module.processes.iter() {
+            match process {
+                Process::Comb { body, buses } => {
+                    for (bus, z) in buses.iter() {
+                        next[*bus] = z();
+                    }
+                    let current = next.clone();
+                    body(&current, &mut next, &mut events);
+                    for (bus, _) in buses.iter() {
+                        bus_drivers.push((*bus, next[*bus].clone()));
+                    }
+                }
+                Process::Clocked {
+                    reset: Some(reset), ..
+                } => {
+                    if next[reset.signal] == level(reset.active_high) {
+                        reset_asserted = true;
+                        let current = next.clone();
+                        (reset.body)(&current, &mut next, &mut events);
+                    }
+                }
+                Process::Clocked { .. } => {}
+            }
+        }
+
+        let mut changed = false;
+        self.reset[index] |= reset_asserted;
+        for (instance, child) in module.instances.iter().zip(children.iter()) {
+            let child_values = &mut self.values[*child];
+            for connection in instance.connections.iter() {
+                match connection {
+                    Connection::Input { port, value } => {
+                        let value = value(&next);
+                        changed |= child_values[*port] != value;
+                        child_values[*port] = value;
+                    }
+                    Connection::Output { port, signal } if module.tristate.contains(signal) => {
+                        bus_drivers.push((*signal, child_values[*port].clone()));
+                    }
+                    Connection::Output { port, signal }
+                    | Connection::InOut {
+                        port,
+                        signal,
+                        assigned: true,
+                    } => {
+                        changed |= child_values[*port] != next[*signal];
+                        next[*signal] = child_values[*port].clone();
+                    }
+                    Connection::InOut { port, signal, .. } => {
+                        changed |= child_values[*port] != next[*signal];
+                        child_values[*port] = next[*signal].clone();
+                    }
+                }
+            }
+        }
+        for bus in module.tristate.iter() {
+            let mut values = bus_drivers
+                .iter()
+                .filter(|(signal, _)| signal == bus)
+                .map(|(_, value)| value.clone());
+            if let Some(first) = values.next() {
+                next[*bus] = values.fold(first, resolve);
+            }
+        }
+        changed |= next != self.values[index];
+        self.values[index] = next;
+        changed
+    }
+
+    fn row(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "row")?;
+        for signal in REPORTED.iter() {
+            write!(out, " ")?;
+            write_value(out, &self.values[0][*signal])?;
+        }
+        writeln!(out)
+    }
+}
+
+/// Writes a value as its bits and unknown bits in hexadecimal, i.e. `[f/0,0/f]`
+fn write_value(out: &mut impl Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Int { bits, unknown } => write!(out, "{:x}/{:x}", bits, unknown),
+        Value::Vector(elems) => {
+            write!(out, "[")?;
+            for (i, elem) in elems.iter().enumerate() {
+                if i != 0 {
+                    write!(out, ",")?;
+                }
+                write_value(out, elem)?;
+            }
+            write!(out, "]")
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let cycles = std::env::args()
+        .nth(1)
+        .and_then(|cycles| cycles.parse::<u64>().ok())
+        .unwrap_or(0);
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut sim = Simulation::new();
+    if sim.settle().is_err() {
+        writeln!(out, "unsettled 0")?;
+        return Ok(());
+    }
+    sim.find_unknown_outputs();
+    sim.row(&mut out)?;
+    let mut events = sim.check();
+    for _ in 0..cycles {
+        if events.iter().any(|(id, ..)| IS_FATAL[*id]) {
+            break;
+        }
+        if sim.step().is_err() {
+            writeln!(out, "unsettled {}", sim.cycle)?;
+            break;
+        }
+        sim.row(&mut out)?;
+        events.extend(sim.check());
+    }
+    for (id, cycle, values) in events {
+        write!(out, "event {} {}", id, cycle)?;
+        for value in values.iter() {
+            write!(out, " ")?;
+            write_value(out, value)?;
+        }
+        writeln!(out)?;
+    }
+    for (instance, port, cycle) in sim.unknown_outputs.iter() {
+        writeln!(out, "unknown {} {} {}", instance, port, cycle)?;
+    }
+    writeln!(out, "cycles {}", sim.cycle)
+}
@@ -0,0 +1,435 @@
+//! Functional coverage of testbenches.
+//!
+//! Every `cover!` of a design is a coverage point, hit in each cycle it holds. A signal of the
+//! testbench marked `#[coverpoint]` is sampled after each cycle into bins: one per value for
+//! signals with at most 64 values, or the named ones of
+//! `#[coverpoint(bins = "idle: 0, busy: 1..=14, done: 15")]`, where a name given more than once
+//! collects several ranges in the same bin.
+//!
+//! With `--coverage <dir>`, the hits of a run are added to those of the earlier runs in
+//! `coverage.json` in the directory, and `coverage.txt` lists the hits of each point and bin,
+//! marking the ones that were never hit.
+use std::fs;
+use std::path::Path;
+
+use rhdl::ast::{Attribute, Lit, Meta, NestedMeta, Span, Spanned};
+use serde_json::{json, Value as Json};
+
+use super::{bounds, parse_range, table, testbench_clocks, write_report, Event, Value};
+use crate::attribute::is_named;
+use crate::codegen::hierarchy::location;
+use crate::error::*;
+use crate::find_file::{FileGraph, FileId};
+use crate::ir::{Ir, PropertyKind, SignalIndex, Ty};
+
+pub const COVERAGE_JSON: &str = "coverage.json";
+pub const COVERAGE_TXT: &str = "coverage.txt";
+
+/// The widest signals that get a bin for each value
+const MAX_VALUE_BINS_WIDTH: u128 = 6;
+
+/// The hits of the coverage points of a testbench in a run
+pub struct Coverage {
+    testbench: String,
+    covers: Vec<((FileId, Span), u64)>,
+    coverpoints: Vec<Coverpoint>,
+}
+
+struct Coverpoint {
+    signal: SignalIndex,
+    name: String,
+    ty: Ty,
+    bins: Vec<Bin>,
+}
+
+struct Bin {
+    name: String,
+    /// Inclusive ranges of values
+    ranges: Vec<(i128, i128)>,
+    hits: u64,
+}
+
+impl Coverage {
+    /// The coverage points of the testbench of a design and its covers, or `None` if a
+    /// `#[coverpoint]` is misplaced or its bins are invalid
+    pub fn new(ir: &Ir, covers: Vec<(FileId, Span)>, errors: &mut Vec<Diagnostic>) -> Option<Self> {
+        let error_count = errors.len();
+        let clocks = testbench_clocks(ir);
+        let mut coverpoints = vec![];
+        for (index, module) in ir.modules.iter().enumerate() {
+            for signal in module.signal_indices() {
+                let attr = match find_coverpoint(module[signal].attrs) {
+                    Some(attr) => attr,
+                    None => continue,
+                };
+                let ty = &module[signal].ty;
+                if index != 0 || clocks.contains(&signal) || ty.is_vector() {
+                    errors.push(coverpoint_not_sampled(
+                        module[signal].file,
+                        module[signal].ident,
+                    ));
+                    continue;
+                }
+                match bins(attr, ty) {
+                    Ok(bins) => coverpoints.push(Coverpoint {
+                        signal,
+                        name: module[signal].name.clone(),
+                        ty: ty.clone(),
+                        bins,
+                    }),
+                    Err((span, msg)) => {
+                        errors.push(invalid_coverpoint(module[signal].file, span, &msg))
+                    }
+                }
+            }
+        }
+        if errors.len() != error_count {
+            return None;
+        }
+        Some(Coverage {
+            testbench: ir.modules.first()?.name.clone(),
+            covers: covers.into_iter().map(|cover| (cover, 0)).collect(),
+            coverpoints,
+        })
+    }
+
+    /// Samples the values of the testbench's signals after a cycle into the bins of its
+    /// coverpoints. Unknown values aren't in any bin.
+    pub fn sample(&mut self, values: &[Value]) {
+        for coverpoint in self.coverpoints.iter_mut() {
+            let value = match values[coverpoint.signal.index()].to_int(&coverpoint.ty) {
+                Some(value) => value,
+                None => continue,
+            };
+            for bin in coverpoint.bins.iter_mut() {
+                if bin
+                    .ranges
+                    .iter()
+                    .any(|(first, last)| (*first..=*last).contains(&value))
+                {
+                    bin.hits += 1;
+                }
+            }
+        }
+    }
+
+    /// Counts the hits of the covers among the events of the run
+    pub fn count(&mut self, events: &[Event]) {
+        for event in events
+            .iter()
+            .filter(|event| event.kind == PropertyKind::Cover)
+        {
+            if let Some((_, hits)) = self
+                .covers
+                .iter_mut()
+                .find(|(cover, _)| *cover == (event.file, event.span))
+            {
+                *hits += 1;
+            }
+        }
+    }
+}
+
+/// The `#[coverpoint]` attribute of a signal
+fn find_coverpoint(attrs: &[Attribute]) -> Option<&Attribute> {
+    attrs.iter().find(|attr| match &attr.meta {
+        Meta::Path(path) => is_named(path, "coverpoint"),
+        Meta::List(meta_list) => is_named(&meta_list.path, "coverpoint"),
+        Meta::NameValue(name_value) => is_named(&name_value.path, "coverpoint"),
+    })
+}
+
+/// The bins of a coverpoint on a signal of a type, or the span and description of invalid ones
+fn bins(attr: &Attribute, ty: &Ty) -> Result<Vec<Bin>, (Span, String)> {
+    let (lowest, highest) = bounds(ty);
+    let bins_str = match &attr.meta {
+        Meta::Path(_) if ty.width() <= MAX_VALUE_BINS_WIDTH => {
+            return Ok((lowest..=highest)
+                .map(|value| Bin {
+                    name: value.to_string(),
+                    ranges: vec![(value, value)],
+                    hits: 0,
+                })
+                .collect())
+        }
+        Meta::Path(_) => {
+            return Err((
+                attr.span(),
+                format!(
+                    "has more than {} values, so its bins need to be named",
+                    1 << MAX_VALUE_BINS_WIDTH
+                ),
+            ))
+        }
+        Meta::List(meta_list) => meta_list.nested.iter().find_map(|nested| match nested {
+            NestedMeta::Meta(Meta::NameValue(name_value)) if is_named(&name_value.path, "bins") => {
+                match &name_value.lit {
+                    Lit::Str(lit_str) => Some(lit_str),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }),
+        Meta::NameValue(_) => None,
+    };
+    let bins_str = bins_str.ok_or_else(|| {
+        (
+            attr.span(),
+            "expected `#[coverpoint]` or `#[coverpoint(bins = \"...\")]`".to_string(),
+        )
+    })?;
+
+    let mut bins: Vec<Bin> = vec![];
+    for entry in bins_str.inner.split(',') {
+        let invalid = || {
+            (
+                bins_str.span(),
+                format!(
+                    "`{}` isn't a name with a value or an inclusive range in `{}..={}`",
+                    entry.trim(),
+                    lowest,
+                    highest
+                ),
+            )
+        };
+        let colon = entry.find(':').ok_or_else(invalid)?;
+        let name = entry[..colon].trim();
+        let range = parse_range(&entry[colon + 1..])
+            .filter(|(first, last)| *first >= lowest && *last <= highest)
+            .ok_or_else(invalid)?;
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        match bins.iter_mut().find(|bin| bin.name == name) {
+            Some(bin) => bin.ranges.push(range),
+            None => bins.push(Bin {
+                name: name.to_string(),
+                ranges: vec![range],
+                hits: 0,
+            }),
+        }
+    }
+    Ok(bins)
+}
+
+/// The hits of the coverage points of testbenches over one or more runs, as read from and
+/// written to `coverage.json`
+#[derive(Default)]
+pub struct CoverageReport {
+    runs: u64,
+    testbenches: Vec<TestbenchHits>,
+}
+
+/// The hits of each cover by location, and of each bin of each coverpoint
+struct TestbenchHits {
+    name: String,
+    covers: Vec<(String, u64)>,
+    coverpoints: Vec<(String, Vec<(String, u64)>)>,
+}
+
+impl CoverageReport {
+    /// Adds the hits of a testbench in this run, locating its covers in the files of the design
+    pub fn add(&mut self, coverage: &Coverage, file_graph: &FileGraph) {
+        let covers = coverage
+            .covers
+            .iter()
+            .map(|((file, span), hits)| (location(file_graph, *file, *span), *hits));
+        let coverpoints = coverage.coverpoints.iter().map(|coverpoint| {
+            (
+                coverpoint.name.clone(),
+                coverpoint
+                    .bins
+                    .iter()
+                    .map(|bin| (bin.name.clone(), bin.hits))
+                    .collect::<Vec<_>>(),
+            )
+        });
+        self.merge(&coverage.testbench, covers, coverpoints);
+    }
+
+    fn merge(
+        &mut self,
+        testbench: &str,
+        covers: impl Iterator<Item = (String, u64)>,
+        coverpoints: impl Iterator<Item = (String, Vec<(String, u64)>)>,
+    ) {
+        let index = match self
+            .testbenches
+            .iter()
+            .position(|hits| hits.name == testbench)
+        {
+            Some(index) => index,
+            None => {
+                self.testbenches.push(TestbenchHits {
+                    name: testbench.to_string(),
+                    covers: vec![],
+                    coverpoints: vec![],
+                });
+                self.testbenches.len() - 1
+            }
+        };
+        let into = &mut self.testbenches[index];
+        for (location, hits) in covers {
+            add_hits(&mut into.covers, location, hits);
+        }
+        for (name, bins) in coverpoints {
+            let index = match into
+                .coverpoints
+                .iter()
+                .position(|(other, _)| *other == name)
+            {
+                Some(index) => index,
+                None => {
+                    into.coverpoints.push((name, vec![]));
+                    into.coverpoints.len() - 1
+                }
+            };
+            for (bin, hits) in bins {
+                add_hits(&mut into.coverpoints[index].1, bin, hits);
+            }
+        }
+    }
+
+    /// Adds the hits of the runs in `coverage.json` in a directory, if there were any, to those
+    /// of this run, and writes `coverage.json` and `coverage.txt` with all of them
+    pub fn write(self, dir: &Path, errors: &mut Vec<Diagnostic>) {
+        let mut report = CoverageReport::read(dir);
+        report.runs += 1;
+        for testbench in self.testbenches {
+            report.merge(
+                &testbench.name,
+                testbench.covers.into_iter(),
+                testbench.coverpoints.into_iter(),
+            );
+        }
+        if let Err(err) = fs::create_dir_all(dir) {
+            errors.push(write_output(&dir.to_string_lossy(), err));
+            return;
+        }
+        let json = serde_json::to_string_pretty(&report.to_json()).unwrap() + "\n";
+        write_report(Some(&dir.join(COVERAGE_JSON)), &json, errors);
+        write_report(Some(&dir.join(COVERAGE_TXT)), &report.to_text(), errors);
+    }
+
+    /// The hits in `coverage.json` in a directory, which are none if it can't be read
+    fn read(dir: &Path) -> Self {
+        let json = fs::read_to_string(dir.join(COVERAGE_JSON))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Json>(&json).ok())
+            .unwrap_or_default();
+        let hits = |json: &Json, key: &str| {
+            json.as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|point| {
+                    Some((point[key].as_str()?.to_string(), point["hits"].as_u64()?))
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut report = CoverageReport {
+            runs: json["runs"].as_u64().unwrap_or_default(),
+            testbenches: vec![],
+        };
+        for testbench in json["testbenches"].as_array().into_iter().flatten() {
+            let name = match testbench["name"].as_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            let coverpoints = testbench["coverpoints"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|coverpoint| {
+                    Some((
+                        coverpoint["name"].as_str()?.to_string(),
+                        hits(&coverpoint["bins"], "name"),
+                    ))
+                });
+            report.merge(
+                name,
+                hits(&testbench["covers"], "location").into_iter(),
+                coverpoints,
+            );
+        }
+        report
+    }
+
+    fn to_json(&self) -> Json {
+        json!({
+            "runs": self.runs,
+            "testbenches": self.testbenches.iter().map(|testbench| json!({
+                "name": testbench.name,
+                "covers": testbench.covers.iter().map(|(location, hits)| json!({
+                    "location": location,
+                    "hits": hits,
+                })).collect::<Vec<_>>(),
+                "coverpoints": testbench.coverpoints.iter().map(|(name, bins)| json!({
+                    "name": name,
+                    "bins": bins.iter().map(|(bin, hits)| json!({
+                        "name": bin,
+                        "hits": hits,
+                    })).collect::<Vec<_>>(),
+                    "uncovered": bins
+                        .iter()
+                        .filter(|(_, hits)| *hits == 0)
+                        .map(|(bin, _)| bin)
+                        .collect::<Vec<_>>(),
+                })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// A line with how many points were hit, and a table of the hits of each of them
+    fn to_text(&self) -> String {
+        let mut rows = vec![vec![
+            "testbench".to_string(),
+            "point".to_string(),
+            "bin".to_string(),
+            "hits".to_string(),
+        ]];
+        for testbench in self.testbenches.iter() {
+            for (location, hits) in testbench.covers.iter() {
+                rows.push(vec![
+                    testbench.name.clone(),
+                    location.clone(),
+                    String::new(),
+                    hits.to_string(),
+                ]);
+            }
+            for (name, bins) in testbench.coverpoints.iter() {
+                for (bin, hits) in bins.iter() {
+                    rows.push(vec![
+                        testbench.name.clone(),
+                        name.clone(),
+                        bin.clone(),
+                        hits.to_string(),
+                    ]);
+                }
+            }
+        }
+        let covered = rows[1..].iter().filter(|row| row[3] != "0").count();
+        for row in rows[1..].iter_mut() {
+            if row[3] == "0" {
+                row.push("uncovered".to_string());
+            } else {
+                row.push(String::new());
+            }
+        }
+        rows[0].push(String::new());
+        format!(
+            "{} of {} points covered in {} run{}\n\n{}",
+            covered,
+            rows.len() - 1,
+            self.runs,
+            if self.runs == 1 { "" } else { "s" },
+            table(rows)
+        )
+    }
+}
+
+fn add_hits(points: &mut Vec<(String, u64)>, name: String, hits: u64) {
+    match points.iter_mut().find(|(other, _)| *other == name) {
+        Some((_, total)) => *total += hits,
+        None => points.push((name, hits)),
+    }
+}
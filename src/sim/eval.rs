@@ -0,0 +1,495 @@
+//! Evaluation of expressions and statements on the values of one module instance
+//!
+//! Values are 4-state: each bit of an integer is 0, 1, X for unknown, or Z for undriven.
+//! Logic reads Z as X. An operator whose result depends on an unknown bit has unknown bits
+//! itself: bitwise operators only where an operand bit is unknown and the other doesn't decide
+//! it, and arithmetic, ordering, and shifts by an unknown amount entirely.
+//! A condition or index that is unknown takes both branches or every element,
+//! leaving the bits they disagree on unknown.
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+use rhdl::ast::Span;
+
+use crate::ir::{
+    BinaryOp, Expr, ExprKind, Level, MessagePart, Module, PropertyKind, Stmt, Ty, UnaryOp,
+};
+
+/// The value of a signal, holding the bits of integers masked to their width
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// The bits that are unknown are X if their bit is clear, or Z if it is set
+    Int {
+        bits: u128,
+        unknown: u128,
+    },
+    Vector(Vec<Value>),
+}
+
+impl Value {
+    /// The value the signals of a testbench start out with
+    pub fn zero(ty: &Ty) -> Value {
+        Value::filled(ty, 0, 0)
+    }
+
+    /// The value of registers that haven't been reset, and signals that haven't been assigned
+    pub fn x(ty: &Ty) -> Value {
+        Value::filled(ty, 0, mask(ty.width()))
+    }
+
+    /// The value of inputs that aren't connected
+    pub fn z(ty: &Ty) -> Value {
+        let all = mask(ty.width());
+        Value::filled(ty, all, all)
+    }
+
+    fn filled(ty: &Ty, bits: u128, unknown: u128) -> Value {
+        match ty {
+            Ty::UInt(_) | Ty::SInt(_) => Value::Int { bits, unknown },
+            Ty::Vector(elem, len) => {
+                let elem_value = Value::filled(
+                    elem,
+                    bits & mask(elem.width()),
+                    unknown & mask(elem.width()),
+                );
+                Value::Vector(vec![elem_value; *len as usize])
+            }
+        }
+    }
+
+    pub fn known(bits: u128) -> Value {
+        Value::Int { bits, unknown: 0 }
+    }
+
+    /// Whether every bit is 0 or 1
+    pub fn is_known(&self) -> bool {
+        match self {
+            Value::Int { unknown, .. } => *unknown == 0,
+            Value::Vector(elems) => elems.iter().all(Value::is_known),
+        }
+    }
+
+    /// The bits and unknown bits of an integer
+    fn int(&self) -> (u128, u128) {
+        match self {
+            Value::Int { bits, unknown } => (*bits, *unknown),
+            Value::Vector(_) => unreachable!("vectors are only indexed or assigned as a whole"),
+        }
+    }
+
+    /// Whether a condition holds, or `None` if it's unknown
+    fn truth(&self) -> Option<bool> {
+        match self.int() {
+            (bits, 0) => Some(bits != 0),
+            _ => None,
+        }
+    }
+
+    /// The integer a known value of an integer type is read as, or `None` if it's unknown or
+    /// above the highest `i128`
+    pub fn to_int(&self, ty: &Ty) -> Option<i128> {
+        match (self, ty) {
+            (Value::Int { bits, unknown: 0 }, Ty::SInt(width)) => Some(to_signed(*bits, *width)),
+            (Value::Int { bits, unknown: 0 }, _) => i128::try_from(*bits).ok(),
+            _ => None,
+        }
+    }
+
+    /// Displays the value as the given type would be read, two's complement for signed integers,
+    /// and `x` or `z` if any bit is unknown
+    pub fn display<'a>(&'a self, ty: &'a Ty) -> impl Display + 'a {
+        DisplayValue { value: self, ty }
+    }
+}
+
+struct DisplayValue<'a> {
+    value: &'a Value,
+    ty: &'a Ty,
+}
+
+impl<'a> Display for DisplayValue<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (self.value, self.ty) {
+            (Value::Int { bits, unknown }, ty) if *unknown != 0 => {
+                let undriven = *unknown == mask(ty.width()) && *bits == *unknown;
+                write!(f, "{}", if undriven { "z" } else { "x" })
+            }
+            (Value::Int { bits, .. }, Ty::SInt(width)) => write!(f, "{}", to_signed(*bits, *width)),
+            (Value::Int { bits, .. }, _) => write!(f, "{}", bits),
+            (Value::Vector(elems), ty) => {
+                let elem_ty = ty.elem();
+                write!(f, "[")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", elem.display(&elem_ty))?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+pub(super) fn mask(width: u128) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+fn to_signed(bits: u128, width: u128) -> i128 {
+    if width == 0 || width >= 128 {
+        bits as i128
+    } else if bits >> (width - 1) & 1 == 1 {
+        (bits | !mask(width)) as i128
+    } else {
+        bits as i128
+    }
+}
+
+fn int(bits: u128, ty: &Ty) -> Value {
+    Value::known(bits & mask(ty.width()))
+}
+
+/// The result of logic, whose unknown bits are X
+fn logic(bits: u128, unknown: u128, ty: &Ty) -> Value {
+    let mask = mask(ty.width());
+    Value::Int {
+        bits: bits & !unknown & mask,
+        unknown: unknown & mask,
+    }
+}
+
+/// The value either of two values could be, unknown where they differ
+fn merge(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Vector(a), Value::Vector(b)) => {
+            Value::Vector(a.into_iter().zip(b).map(|(a, b)| merge(a, b)).collect())
+        }
+        (a, b) => {
+            let ((a_bits, a_unknown), (b_bits, b_unknown)) = (a.int(), b.int());
+            let unknown = a_unknown | b_unknown | (a_bits ^ b_bits);
+            Value::Int {
+                bits: a_bits & !unknown,
+                unknown,
+            }
+        }
+    }
+}
+
+/// The value of a tri-state bus driven by two drivers: where one of them is Z, the other wins,
+/// and where both drive it, bits they disagree on are X
+pub fn resolve(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Vector(a), Value::Vector(b)) => {
+            Value::Vector(a.into_iter().zip(b).map(|(a, b)| resolve(a, b)).collect())
+        }
+        (a, b) => {
+            let ((a_bits, a_unknown), (b_bits, b_unknown)) = (a.int(), b.int());
+            let a_z = a_bits & a_unknown;
+            let b_z = b_bits & b_unknown & !a_z;
+            let driven = !(a_z | b_z);
+            let (merged_bits, merged_unknown) = merge(a, b).int();
+            Value::Int {
+                bits: (a_z & b_bits) | (b_z & a_bits) | (driven & merged_bits),
+                unknown: (a_z & b_unknown) | (b_z & a_unknown) | (driven & merged_unknown),
+            }
+        }
+    }
+}
+
+/// Evaluates an expression on the current values of a module instance's signals
+pub fn eval(expr: &Expr, values: &[Value]) -> Value {
+    match &expr.kind {
+        ExprKind::Const(value) => int(*value as u128, &expr.ty),
+        ExprKind::Signal(signal) => values[signal.index()].clone(),
+        ExprKind::Unary(op, operand) => {
+            let (bits, unknown) = eval(operand, values).int();
+            match op {
+                UnaryOp::Not => logic(!bits, unknown, &expr.ty),
+                UnaryOp::Neg if unknown != 0 => Value::x(&expr.ty),
+                UnaryOp::Neg => int(bits.wrapping_neg(), &expr.ty),
+                UnaryOp::ReduceOr if bits & !unknown != 0 => Value::known(1),
+                UnaryOp::ReduceOr => logic(0, (unknown != 0) as u128, &expr.ty),
+            }
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let ty = &lhs.ty;
+            let width = ty.width();
+            let signed = ty.is_signed();
+            let (lhs, lhs_unknown) = eval(lhs, values).int();
+            let (rhs, rhs_unknown) = eval(rhs, values).int();
+            let unknown = lhs_unknown | rhs_unknown;
+            let (lhs_signed, rhs_signed) = (to_signed(lhs, width), to_signed(rhs, width));
+            match op {
+                // A known 0 decides an and, and a known 1 an or
+                BinaryOp::BitAnd | BinaryOp::And => {
+                    let zeros = (!lhs & !lhs_unknown) | (!rhs & !rhs_unknown);
+                    logic(lhs & rhs, unknown & !zeros, ty)
+                }
+                BinaryOp::BitOr | BinaryOp::Or => {
+                    let ones = (lhs & !lhs_unknown) | (rhs & !rhs_unknown);
+                    logic(ones, unknown & !ones, ty)
+                }
+                BinaryOp::BitXor => logic(lhs ^ rhs, unknown, ty),
+                // Known bits that differ decide an equality
+                BinaryOp::Eq | BinaryOp::Ne if (lhs ^ rhs) & !unknown != 0 => {
+                    Value::known((*op == BinaryOp::Ne) as u128)
+                }
+                BinaryOp::Shl | BinaryOp::Shr if rhs_unknown != 0 => Value::x(&expr.ty),
+                BinaryOp::Shl if rhs >= width => Value::known(0),
+                BinaryOp::Shl => logic(lhs << rhs, lhs_unknown << rhs, ty),
+                BinaryOp::Shr if signed => logic(
+                    (lhs_signed >> rhs.min(127)) as u128,
+                    (to_signed(lhs_unknown, width) >> rhs.min(127)) as u128,
+                    ty,
+                ),
+                BinaryOp::Shr if rhs >= width => Value::known(0),
+                BinaryOp::Shr => logic(lhs >> rhs, lhs_unknown >> rhs, ty),
+                _ if unknown != 0 => Value::x(&expr.ty),
+                BinaryOp::Add => int(lhs.wrapping_add(rhs), ty),
+                BinaryOp::Sub => int(lhs.wrapping_sub(rhs), ty),
+                BinaryOp::Mul => int(lhs.wrapping_mul(rhs), ty),
+                // Division by zero has all bits set and the remainder is the dividend,
+                // which is what most hardware dividers produce
+                BinaryOp::Div if rhs == 0 => int(u128::MAX, ty),
+                BinaryOp::Rem if rhs == 0 => Value::known(lhs),
+                BinaryOp::Div if signed => int(lhs_signed.wrapping_div(rhs_signed) as u128, ty),
+                BinaryOp::Rem if signed => int(lhs_signed.wrapping_rem(rhs_signed) as u128, ty),
+                BinaryOp::Div => int(lhs / rhs, ty),
+                BinaryOp::Rem => int(lhs % rhs, ty),
+                BinaryOp::Eq => Value::known((lhs == rhs) as u128),
+                BinaryOp::Ne => Value::known((lhs != rhs) as u128),
+                BinaryOp::Lt if signed => Value::known((lhs_signed < rhs_signed) as u128),
+                BinaryOp::Le if signed => Value::known((lhs_signed <= rhs_signed) as u128),
+                BinaryOp::Gt if signed => Value::known((lhs_signed > rhs_signed) as u128),
+                BinaryOp::Ge if signed => Value::known((lhs_signed >= rhs_signed) as u128),
+                BinaryOp::Lt => Value::known((lhs < rhs) as u128),
+                BinaryOp::Le => Value::known((lhs <= rhs) as u128),
+                BinaryOp::Gt => Value::known((lhs > rhs) as u128),
+                BinaryOp::Ge => Value::known((lhs >= rhs) as u128),
+            }
+        }
+        ExprKind::Index(base, index) => {
+            let (index, index_unknown) = eval(index, values).int();
+            match eval(base, values) {
+                Value::Vector(elems) if index_unknown != 0 => {
+                    let mut elems = elems.into_iter();
+                    match elems.next() {
+                        Some(first) => elems.fold(first, merge),
+                        None => Value::x(&expr.ty),
+                    }
+                }
+                Value::Int { .. } if index_unknown != 0 => Value::x(&expr.ty),
+                // Out of range elements read as zero
+                Value::Vector(mut elems) if index < elems.len() as u128 => {
+                    elems.swap_remove(index as usize)
+                }
+                Value::Vector(_) => Value::zero(&expr.ty),
+                Value::Int { bits, unknown } if index < 128 => Value::Int {
+                    bits: bits >> index & 1,
+                    unknown: unknown >> index & 1,
+                },
+                Value::Int { .. } => Value::known(0),
+            }
+        }
+        ExprKind::Resize(operand) => {
+            let (bits, unknown) = eval(operand, values).int();
+            let ty = &expr.ty;
+            if operand.ty.is_signed() {
+                let width = operand.ty.width();
+                Value::Int {
+                    bits: to_signed(bits, width) as u128 & mask(ty.width()),
+                    unknown: to_signed(unknown, width) as u128 & mask(ty.width()),
+                }
+            } else {
+                Value::Int {
+                    bits: bits & mask(ty.width()),
+                    unknown: unknown & mask(ty.width()),
+                }
+            }
+        }
+        ExprKind::Mux(cond, then_value, else_value) => match eval(cond, values).truth() {
+            Some(true) => eval(then_value, values),
+            Some(false) => eval(else_value, values),
+            None => merge(eval(then_value, values), eval(else_value, values)),
+        },
+    }
+}
+
+/// An assertion or assumption that failed, or a cover that held, when its statement executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertyEvent {
+    pub kind: PropertyKind,
+    pub level: Level,
+    pub span: Span,
+    /// Formatted with the values read when it failed, if it has one
+    pub message: Option<String>,
+}
+
+/// Formats the message of a property with the values of the expressions in it, in order
+pub fn format_message(message: &[MessagePart], mut values: impl Iterator<Item = Value>) -> String {
+    let mut formatted = String::new();
+    for part in message {
+        match part {
+            MessagePart::Text(text) => formatted += text,
+            MessagePart::Value(expr) => {
+                if let Some(value) = values.next() {
+                    formatted += &value.display(&expr.ty).to_string();
+                }
+            }
+        }
+    }
+    formatted
+}
+
+/// Executes statements, writing assigned values to `next`, the assertions and assumptions
+/// that fail and the covers that hold to `events`, and the span of each statement executed, or
+/// of the condition of an `if`, to `executed`.
+///
+/// Blocking assignments take effect immediately, so statements read from `next`,
+/// otherwise they read the values before the statements from `current`.
+/// An assertion or assumption whose condition is unknown fails, and so does one in a branch
+/// taken under an unknown condition, while a cover has to hold for certain.
+pub fn exec(
+    module: &Module,
+    stmts: &[Stmt],
+    current: &[Value],
+    next: &mut [Value],
+    blocking: bool,
+    events: &mut Vec<PropertyEvent>,
+    executed: &mut Vec<Span>,
+) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign {
+                target,
+                index,
+                value,
+                span,
+            } => {
+                executed.push(*span);
+                let read = if blocking { &*next } else { current };
+                let value = eval(value, read);
+                let index = index.as_ref().map(|index| eval(index, read).int());
+                let target_value = &mut next[target.index()];
+                match (index, target_value) {
+                    (None, target_value) => *target_value = value,
+                    // Any element could be written
+                    (Some((_, index_unknown)), Value::Vector(elems)) if index_unknown != 0 => {
+                        for elem in elems.iter_mut() {
+                            *elem = merge(elem.clone(), value.clone());
+                        }
+                    }
+                    (Some((_, index_unknown)), target_value) if index_unknown != 0 => {
+                        let (bit, bit_unknown) = value.int();
+                        let width = module[*target].ty.width();
+                        let spread = Value::Int {
+                            bits: if bit == 1 { mask(width) } else { 0 },
+                            unknown: if bit_unknown == 1 { mask(width) } else { 0 },
+                        };
+                        *target_value = merge(target_value.clone(), spread);
+                    }
+                    (Some((index, _)), Value::Vector(elems)) => {
+                        // Out of range assignments are ignored
+                        if index < elems.len() as u128 {
+                            elems[index as usize] = value;
+                        }
+                    }
+                    (Some((index, _)), Value::Int { bits, unknown }) => {
+                        if index < module[*target].ty.width() {
+                            let (bit, bit_unknown) = value.int();
+                            *bits = (*bits & !(1 << index)) | (bit << index);
+                            *unknown = (*unknown & !(1 << index)) | (bit_unknown << index);
+                        }
+                    }
+                }
+            }
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                executed.push(cond.span);
+                let read = if blocking { &*next } else { current };
+                match eval(cond, read).truth() {
+                    Some(true) => exec(
+                        module,
+                        then_branch,
+                        current,
+                        next,
+                        blocking,
+                        events,
+                        executed,
+                    ),
+                    Some(false) => exec(
+                        module,
+                        else_branch,
+                        current,
+                        next,
+                        blocking,
+                        events,
+                        executed,
+                    ),
+                    None => {
+                        let mut else_next = next.to_vec();
+                        let mut branch_events = vec![];
+                        exec(
+                            module,
+                            then_branch,
+                            current,
+                            next,
+                            blocking,
+                            &mut branch_events,
+                            executed,
+                        );
+                        exec(
+                            module,
+                            else_branch,
+                            current,
+                            &mut else_next,
+                            blocking,
+                            &mut branch_events,
+                            executed,
+                        );
+                        for (value, else_value) in next.iter_mut().zip(else_next) {
+                            *value = merge(value.clone(), else_value);
+                        }
+                        events.extend(
+                            branch_events
+                                .into_iter()
+                                .filter(|event| event.kind != PropertyKind::Cover),
+                        );
+                    }
+                }
+            }
+            Stmt::Property {
+                kind,
+                level,
+                cond,
+                message,
+                span,
+            } => {
+                executed.push(*span);
+                let read = if blocking { &*next } else { current };
+                let holds = eval(cond, read).truth() == Some(true);
+                if holds == (*kind == PropertyKind::Cover) {
+                    let values = message.iter().filter_map(|part| match part {
+                        MessagePart::Text(_) => None,
+                        MessagePart::Value(expr) => Some(eval(expr, read)),
+                    });
+                    events.push(PropertyEvent {
+                        kind: *kind,
+                        level: *level,
+                        span: *span,
+                        message: Some(format_message(message, values))
+                            .filter(|_| !message.is_empty()),
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1042 @@
+//! A cycle-accurate simulator that executes the intermediate representation of a design.
+//!
+//! The top entity is a testbench. The simulator drives each of its inputs that clocks registers
+//! with one period per cycle, starting low, and holds its other inputs at zero, so a testbench
+//! generates stimulus for the design it instantiates with its own registers and logic. Inputs
+//! marked `#[random]` are drawn at random instead; see [`random`].
+//! The signals of the testbench start out as zero, and those of the design as unknown, X,
+//! until they're assigned or reset, except its inputs that aren't connected, which stay undriven,
+//! Z. After each cycle, the signals of the testbench are reported.
+//! A tri-state bus, a signal marked `#[tristate]`, is Z while none of its drivers is enabled,
+//! and X where two enabled drivers disagree.
+//! Black boxes are simulated by the external [`model`]s registered for their entities.
+//! The hits of covers and the bins of `#[coverpoint]` signals are collected with `--coverage`;
+//! see [`coverage`]. The statements executed and the bits toggled are reported as lcov with
+//! `rhdlc test --lcov`; see [`code_coverage`].
+//! With `--backend compiled`, the design is lowered to a Rust program instead, which is
+//! compiled with `rustc` and run, for long simulations; see [`compiled`].
+//!
+//! A failing assertion is reported with its message, like `assert!(count < 8, "count is {count}")`,
+//! where `{name}` is the value of a signal when it failed and `{}` that of the next argument.
+//! `warning!("...")`, `error!("...")`, and `fatal!("...")` fail whenever they're reached, as a
+//! warning that doesn't fail the simulation, an error, and an error that stops it after the cycle.
+//!
+//! `unknown_output` warns about outputs of the instances of the testbench that are unknown once
+//! every asynchronous reset below them has been asserted and released, which is any time for a
+//! design without resets. It can be set to another level with `#[allow(unknown_output)]` on the
+//! testbench's architecture, or in the config file.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use codespan_reporting::diagnostic::Severity;
+use rhdl::ast::Span;
+
+use crate::analysis::signal_graph::{Edge, SignalKind};
+use crate::attribute::{self, LintLevel};
+use crate::elaboration::const_eval::parse_int;
+use crate::error::*;
+use crate::events;
+use crate::find_file::FileId;
+use crate::ir::{
+    assigns, clock_signals, Clocking, Connection, Instance, Ir, Level, Module, ModuleIndex,
+    Process, ProcessKind, PropertyKind, SignalIndex, Stmt, Ty,
+};
+use crate::resolution::{item_arch, Resolver};
+
+mod code_coverage;
+mod compiled;
+mod coverage;
+pub(crate) mod eval;
+pub mod model;
+mod random;
+pub mod test;
+mod vcd;
+
+pub use code_coverage::{CodeCoverage, Lcov};
+use coverage::{Coverage, CoverageReport};
+use eval::PropertyEvent;
+pub use eval::Value;
+use model::{ModelContext, ModelInstance, Models};
+pub use vcd::Vcd;
+
+/// How many times signals may change before a cycle is considered stuck in a loop
+const SETTLE_LIMIT: usize = 1000;
+
+const UNKNOWN_OUTPUT_LINT: &str = "unknown_output";
+
+/// `rhdlc sim`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulate {
+    pub cycles: u64,
+    /// `None` for standard output
+    pub output: Option<PathBuf>,
+    pub backend: Backend,
+    /// The seed random stimulus is drawn with, `None` for one from the time
+    pub seed: Option<u64>,
+    /// The directory to collect coverage in over runs
+    pub coverage: Option<PathBuf>,
+}
+
+/// How a design is simulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// By executing its intermediate representation
+    Interpreted,
+    /// By a program generated from it, which is slow to build but fast to run
+    Compiled,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Interpreted
+    }
+}
+
+/// An assertion or assumption that didn't hold, or a cover that did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub kind: PropertyKind,
+    pub file: FileId,
+    pub span: Span,
+    /// The cycle whose values the property read
+    pub cycle: u64,
+    /// The `#[test]` function of the testbench the property is in
+    pub test: Option<String>,
+    pub level: Level,
+    /// The message of the property, formatted with the values it read
+    pub message: Option<String>,
+}
+
+impl Event {
+    /// Whether it fails the simulation, which a cover or a warning doesn't
+    pub fn is_failure(&self) -> bool {
+        self.kind != PropertyKind::Cover && self.level != Level::Warning
+    }
+
+    /// Whether the simulation stops after the cycle it's in
+    pub fn is_fatal(&self) -> bool {
+        self.kind != PropertyKind::Cover && self.level == Level::Fatal
+    }
+}
+
+/// The signal values of one instance of a module in the hierarchy
+struct InstanceState {
+    module: ModuleIndex,
+    values: Vec<Value>,
+    /// The index of the state of each instance of the module
+    children: Vec<usize>,
+    /// Whether an asynchronous reset of the instance has been asserted
+    reset: bool,
+    /// The model simulating a black box
+    model: Option<Box<dyn ModelInstance>>,
+}
+
+/// An output of an instance of the testbench with unknown bits after the design was reset
+struct UnknownOutput {
+    /// The position of the instance among the testbench's instances
+    instance: usize,
+    port: SignalIndex,
+    /// The first cycle it was unknown in
+    cycle: u64,
+}
+
+pub struct Simulation<'a, 'ast> {
+    ir: &'a Ir<'ast>,
+    /// The testbench first, and every instance before its children
+    instances: Vec<InstanceState>,
+    /// Inputs of the testbench toggled once per cycle
+    clocks: Vec<SignalIndex>,
+    cycle: u64,
+    /// Events of clocked processes, since they were last taken
+    events: Vec<Event>,
+    unknown_outputs: Vec<UnknownOutput>,
+    /// The random inputs of the testbench, if it has any
+    stimulus: Option<random::Stimulus>,
+    /// The statements executed and the bits toggled, once tracking them is started
+    code_coverage: Option<CodeCoverage>,
+}
+
+impl<'a, 'ast> Simulation<'a, 'ast> {
+    /// Sets up the testbench with every signal at zero and the design with every signal unknown,
+    /// settling the combinational logic. Black boxes are simulated by the models registered for
+    /// their entities, and random inputs are drawn with the seed, reported if there are any.
+    pub fn new(
+        ir: &'a Ir<'ast>,
+        models: &Models,
+        seed: Option<u64>,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let random_inputs = random::random_inputs(ir, errors);
+        if !can_simulate(ir, models, Backend::Interpreted, errors) {
+            return None;
+        }
+        let random_inputs = random_inputs?;
+
+        let top = ir.module_indices().next()?;
+        let stimulus = if random_inputs.is_empty() {
+            None
+        } else {
+            let seed = seed.unwrap_or_else(random::time_seed);
+            errors.push(random_seed(&ir[top].name, seed));
+            Some(random::Stimulus::new(random_inputs, seed))
+        };
+        let mut sim = Simulation {
+            ir,
+            instances: vec![],
+            clocks: testbench_clocks(ir),
+            cycle: 0,
+            events: vec![],
+            unknown_outputs: vec![],
+            stimulus,
+            code_coverage: None,
+        };
+        sim.instantiate(models, top, None, String::new());
+        if let Err(err) = sim.randomize() {
+            errors.push(err);
+            return None;
+        }
+        sim.find_unknown_outputs();
+        Some(sim)
+    }
+
+    /// Adds the state of the testbench, or of an instance of the design at `path`
+    fn instantiate(
+        &mut self,
+        models: &Models,
+        module: ModuleIndex,
+        instance: Option<&Instance>,
+        path: String,
+    ) -> usize {
+        let ir = self.ir;
+        let index = self.instances.len();
+        let values = initial_values(&ir[module], instance);
+        let model = models
+            .get(&ir[module].name)
+            .filter(|_| ir[module].is_black_box())
+            .map(|model| model.instantiate(&ModelContext::new(&path, &ir[module])));
+        self.instances.push(InstanceState {
+            module,
+            values,
+            children: vec![],
+            reset: false,
+            model,
+        });
+        let children = ir[module]
+            .instances
+            .iter()
+            .map(|instance| {
+                let child_path = if path.is_empty() {
+                    instance.name.clone()
+                } else {
+                    format!("{}.{}", path, instance.name)
+                };
+                self.instantiate(models, instance.module, Some(instance), child_path)
+            })
+            .collect();
+        self.instances[index].children = children;
+        index
+    }
+
+    /// The number of cycles simulated so far
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// The inputs of the testbench that the simulator toggles
+    pub fn clocks(&self) -> &[SignalIndex] {
+        &self.clocks
+    }
+
+    /// The current values of the testbench's signals
+    pub fn values(&self) -> &[Value] {
+        &self.instances[0].values
+    }
+
+    /// Starts counting the statements executed and the bits toggled from the current values
+    pub fn track_code_coverage(&mut self) {
+        self.code_coverage = Some(CodeCoverage::new(
+            self.ir,
+            self.instances
+                .iter()
+                .map(|state| (state.module, state.values.as_slice())),
+        ));
+    }
+
+    /// The statements executed and the bits toggled since tracking them was started
+    pub fn code_coverage(&self) -> Option<&CodeCoverage> {
+        self.code_coverage.as_ref()
+    }
+
+    /// Simulates a rising and then a falling edge of the testbench's clocks, after which the
+    /// random inputs are drawn again, tracing the values after each of them
+    pub fn step(&mut self, mut trace: Option<&mut Vcd>) -> Result<(), Diagnostic> {
+        self.drive_clocks(1)?;
+        self.sample_toggles();
+        if let Some(vcd) = trace.as_deref_mut() {
+            vcd.sample(self, self.cycle * 2 + 1);
+        }
+        self.drive_clocks(0)?;
+        self.randomize()?;
+        self.sample_toggles();
+        self.cycle += 1;
+        if let Some(vcd) = trace {
+            vcd.sample(self, self.cycle * 2);
+        }
+        self.find_unknown_outputs();
+        Ok(())
+    }
+
+    /// Records the outputs of the testbench's instances that are unknown for the first time since
+    /// the instance was reset
+    fn find_unknown_outputs(&mut self) {
+        let ir = self.ir;
+        let testbench = &ir[self.instances[0].module];
+        for (i, (instance, child)) in testbench
+            .instances
+            .iter()
+            .zip(self.instances[0].children.iter())
+            .enumerate()
+        {
+            if !self.is_out_of_reset(*child) {
+                continue;
+            }
+            let module = &ir[instance.module];
+            for port in module.signal_indices() {
+                if module[port].kind == SignalKind::Output
+                    && !self.instances[*child].values[port.index()].is_known()
+                    && !self
+                        .unknown_outputs
+                        .iter()
+                        .any(|unknown| unknown.instance == i && unknown.port == port)
+                {
+                    self.unknown_outputs.push(UnknownOutput {
+                        instance: i,
+                        port,
+                        cycle: self.cycle,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Whether every asynchronous reset of an instance and the instances below it has been
+    /// asserted and is released now
+    fn is_out_of_reset(&self, index: usize) -> bool {
+        let state = &self.instances[index];
+        let released = self.ir[state.module]
+            .processes
+            .iter()
+            .all(|process| match &process.kind {
+                ProcessKind::Clocked(Clocking {
+                    reset: Some(reset), ..
+                }) => {
+                    state.reset && state.values[reset.signal.index()] == level(!reset.active_high)
+                }
+                _ => true,
+            });
+        released
+            && state
+                .children
+                .iter()
+                .all(|child| self.is_out_of_reset(*child))
+    }
+
+    /// Checks the properties of combinational processes on the current values,
+    /// and returns every event since the last check
+    pub fn check(&mut self) -> Vec<Event> {
+        let mut executed = vec![];
+        let mut events = std::mem::take(&mut self.events);
+        events.extend(self.comb_events(&mut executed));
+        if let Some(code_coverage) = &mut self.code_coverage {
+            // the statements of an asserted reset ran when it was settled
+            for (index, state) in self.instances.iter().enumerate() {
+                for process in self.ir[state.module].processes.iter() {
+                    if let ProcessKind::Clocked(Clocking {
+                        reset: Some(reset), ..
+                    }) = &process.kind
+                    {
+                        if state.values[reset.signal.index()] == level(reset.active_high) {
+                            let mut spans = vec![];
+                            eval::exec(
+                                &self.ir[state.module],
+                                &reset.body,
+                                &state.values,
+                                &mut state.values.clone(),
+                                false,
+                                &mut vec![],
+                                &mut spans,
+                            );
+                            executed.extend(spans.into_iter().map(|span| (index, span)));
+                        }
+                    }
+                }
+            }
+            code_coverage.executed(self.ir, &executed);
+        }
+        events
+    }
+
+    /// The events of the properties of combinational processes on the current values, and the
+    /// statements they executed by the position of their instance
+    fn comb_events(&self, executed: &mut Vec<(usize, Span)>) -> Vec<Event> {
+        let mut events = vec![];
+        for (index, state) in self.instances.iter().enumerate() {
+            let module = &self.ir[state.module];
+            for process in module.processes.iter() {
+                if let ProcessKind::Comb = process.kind {
+                    let mut process_events = vec![];
+                    let mut spans = vec![];
+                    let mut next = state.values.clone();
+                    eval::exec(
+                        module,
+                        &process.body,
+                        &state.values,
+                        &mut next,
+                        true,
+                        &mut process_events,
+                        &mut spans,
+                    );
+                    events.extend(self.to_events(index, process, process_events));
+                    executed.extend(spans.into_iter().map(|span| (index, span)));
+                }
+            }
+        }
+        events
+    }
+
+    fn to_events<'p>(
+        &'p self,
+        index: usize,
+        process: &'p Process,
+        events: Vec<PropertyEvent>,
+    ) -> impl Iterator<Item = Event> + 'p {
+        let file = self.ir[self.instances[index].module].file;
+        let cycle = self.cycle;
+        events.into_iter().map(move |event| Event {
+            kind: event.kind,
+            file,
+            span: event.span,
+            cycle,
+            // the tests of a testbench are only its own functions
+            test: process.test.clone().filter(|_| index == 0),
+            level: event.level,
+            message: event.message,
+        })
+    }
+
+    /// Every cover in the design
+    pub fn covers(&self) -> Vec<(FileId, Span)> {
+        covers(self.instances.iter().map(|state| &self.ir[state.module]))
+    }
+
+    /// Draws the random inputs of the testbench, again until the assumptions of the design hold
+    /// for them, and settles the design
+    fn randomize(&mut self) -> Result<(), Diagnostic> {
+        for _ in 0..random::DRAWS {
+            match &mut self.stimulus {
+                Some(stimulus) => stimulus.draw(&mut self.instances[0].values),
+                None => return self.settle(),
+            }
+            self.settle()?;
+            let violated = self
+                .comb_events(&mut vec![])
+                .iter()
+                .any(|event| event.kind == PropertyKind::Assume);
+            if !violated {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the testbench's clocks and runs the processes triggered by it,
+    /// until the design is stable again
+    fn drive_clocks(&mut self, value: u128) -> Result<(), Diagnostic> {
+        let mut before = self.snapshot();
+        for clock in self.clocks.iter() {
+            self.instances[0].values[clock.index()] = Value::known(value);
+        }
+        for _ in 0..SETTLE_LIMIT {
+            self.settle()?;
+            let mut executed = vec![];
+            let (updates, events) = self.clock_edge(&before, &mut executed);
+            self.events.extend(events);
+            if let Some(code_coverage) = &mut self.code_coverage {
+                code_coverage.executed(self.ir, &executed);
+            }
+            let updated_models = self.update_models(&before);
+            if updates.is_empty() && !updated_models {
+                return Ok(());
+            }
+            before = self.snapshot();
+            for (instance, signal, value) in updates {
+                self.instances[instance].values[signal.index()] = value;
+            }
+        }
+        Err(unsettled_simulation(self.cycle))
+    }
+
+    /// Counts the bits that toggled since the last sample, if code coverage is tracked
+    fn sample_toggles(&mut self) {
+        if let Some(code_coverage) = &mut self.code_coverage {
+            code_coverage.sample(
+                self.ir,
+                self.instances.iter().map(|state| state.values.as_slice()),
+            );
+        }
+    }
+
+    fn snapshot(&self) -> Vec<Vec<Value>> {
+        self.instances
+            .iter()
+            .map(|state| state.values.clone())
+            .collect()
+    }
+
+    /// The register updates and property events of the clocked processes whose clock had an edge
+    /// since `before`, and the statements they executed. All of them read the values before the
+    /// edge.
+    fn clock_edge(
+        &self,
+        before: &[Vec<Value>],
+        executed: &mut Vec<(usize, Span)>,
+    ) -> (Vec<(usize, SignalIndex, Value)>, Vec<Event>) {
+        let mut updates = vec![];
+        let mut events = vec![];
+        for (index, state) in self.instances.iter().enumerate() {
+            let module = &self.ir[state.module];
+            for process in module.processes.iter() {
+                let clocking = match &process.kind {
+                    ProcessKind::Clocked(clocking) => clocking,
+                    ProcessKind::Comb => continue,
+                };
+                if let Some(reset) = &clocking.reset {
+                    if state.values[reset.signal.index()] == level(reset.active_high) {
+                        continue;
+                    }
+                }
+                let to = edge_value(clocking.edge);
+                let clock = clocking.clock.index();
+                if before[index][clock] != Value::known(1 - to)
+                    || state.values[clock] != Value::known(to)
+                {
+                    continue;
+                }
+                let mut next = state.values.clone();
+                let mut process_events = vec![];
+                let mut spans = vec![];
+                eval::exec(
+                    module,
+                    &process.body,
+                    &state.values,
+                    &mut next,
+                    false,
+                    &mut process_events,
+                    &mut spans,
+                );
+                events.extend(self.to_events(index, process, process_events));
+                executed.extend(spans.into_iter().map(|span| (index, span)));
+                for signal in module.signal_indices() {
+                    if next[signal.index()] != state.values[signal.index()] {
+                        updates.push((index, signal, next[signal.index()].clone()));
+                    }
+                }
+            }
+        }
+        (updates, events)
+    }
+
+    /// Lets the models of the black boxes whose ports changed since `before` update their state,
+    /// returning whether any of them did
+    fn update_models(&mut self, before: &[Vec<Value>]) -> bool {
+        let mut updated = false;
+        for (state, before) in self.instances.iter_mut().zip(before) {
+            if let Some(model) = &mut state.model {
+                if *before != state.values {
+                    updated |= model.update(before, &state.values);
+                }
+            }
+        }
+        updated
+    }
+
+    /// Evaluates combinational logic and asynchronous resets until no signal changes
+    fn settle(&mut self) -> Result<(), Diagnostic> {
+        for _ in 0..SETTLE_LIMIT {
+            let mut changed = false;
+            for index in 0..self.instances.len() {
+                changed |= self.settle_instance(index);
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+        Err(unsettled_simulation(self.cycle))
+    }
+
+    /// Evaluates the processes of an instance once and passes values through its connections,
+    /// returning whether anything changed
+    fn settle_instance(&mut self, index: usize) -> bool {
+        let ir = self.ir;
+        let module = &ir[self.instances[index].module];
+        let values = &self.instances[index].values;
+        let mut next = values.clone();
+        let mut reset_asserted = false;
+        let tristate = module
+            .signal_indices()
+            .filter(|signal| attribute::find_flag(module[*signal].attrs, "tristate").is_some())
+            .collect::<Vec<_>>();
+        // the values each driver of a tri-state bus puts on it, Z while it's disabled
+        let mut bus_drivers = vec![];
+        for process in module.processes.iter() {
+            match &process.kind {
+                ProcessKind::Comb => {
+                    let buses = tristate
+                        .iter()
+                        .copied()
+                        .filter(|signal| assigns(&process.body, *signal))
+                        .collect::<Vec<_>>();
+                    for bus in buses.iter() {
+                        next[bus.index()] = Value::z(&module[*bus].ty);
+                    }
+                    let current = next.clone();
+                    eval::exec(
+                        module,
+                        &process.body,
+                        &current,
+                        &mut next,
+                        true,
+                        &mut vec![],
+                        &mut vec![],
+                    );
+                    for bus in buses {
+                        bus_drivers.push((bus, next[bus.index()].clone()));
+                    }
+                }
+                ProcessKind::Clocked(clocking) => {
+                    if let Some(reset) = &clocking.reset {
+                        if next[reset.signal.index()] == level(reset.active_high) {
+                            reset_asserted = true;
+                            let current = next.clone();
+                            eval::exec(
+                                module,
+                                &reset.body,
+                                &current,
+                                &mut next,
+                                false,
+                                &mut vec![],
+                                &mut vec![],
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(model) = &self.instances[index].model {
+            let mut ports = next.clone();
+            model.eval(&mut ports);
+            for (signal, value) in module.signal_indices().zip(ports) {
+                if module[signal].kind != SignalKind::Input {
+                    next[signal.index()] = value;
+                }
+            }
+        }
+
+        let mut changed = false;
+        self.instances[index].reset |= reset_asserted;
+        let children = self.instances[index].children.clone();
+        for (instance, child) in module.instances.iter().zip(children) {
+            let child_module = &ir[instance.module];
+            for connection in instance.connections.iter() {
+                let child_values = &mut self.instances[child].values;
+                let (from, to) = match connection {
+                    Connection::Input { port, value } => {
+                        let value = eval::eval(value, &next);
+                        changed |= child_values[port.index()] != value;
+                        child_values[port.index()] = value;
+                        continue;
+                    }
+                    Connection::Output { port, signal } if tristate.contains(signal) => {
+                        bus_drivers.push((*signal, child_values[port.index()].clone()));
+                        continue;
+                    }
+                    Connection::Output { port, signal } => {
+                        (&child_values[port.index()], &mut next[signal.index()])
+                    }
+                    // Whichever side assigns the signal drives it
+                    Connection::InOut { port, signal } if child_module.is_assigned(*port) => {
+                        (&child_values[port.index()], &mut next[signal.index()])
+                    }
+                    Connection::InOut { port, signal } => {
+                        let value = &next[signal.index()];
+                        changed |= child_values[port.index()] != *value;
+                        child_values[port.index()] = value.clone();
+                        continue;
+                    }
+                };
+                changed |= *from != *to;
+                *to = from.clone();
+            }
+        }
+        for bus in tristate {
+            let mut values = bus_drivers
+                .iter()
+                .filter(|(signal, _)| *signal == bus)
+                .map(|(_, value)| value.clone());
+            if let Some(first) = values.next() {
+                next[bus.index()] = values.fold(first, eval::resolve);
+            }
+        }
+        changed |= next != self.instances[index].values;
+        self.instances[index].values = next;
+        changed
+    }
+}
+
+/// Reports the signals that are too wide for a backend to simulate, the black boxes it has no
+/// model for, and random stimulus it can't draw, returning whether there were none
+fn can_simulate(ir: &Ir, models: &Models, backend: Backend, errors: &mut Vec<Diagnostic>) -> bool {
+    let error_count = errors.len();
+    for module in ir.modules.iter() {
+        for signal in module.signals.iter() {
+            if int_width(&signal.ty) > 128 {
+                errors.push(too_wide_to_simulate(
+                    signal.file,
+                    signal.ident,
+                    int_width(&signal.ty),
+                ));
+            }
+            // the generator is part of the interpreter
+            if backend == Backend::Compiled && random::find_random(signal.attrs).is_some() {
+                errors.push(random_not_compiled(signal.file, signal.ident));
+            }
+        }
+        for instance in module.instances.iter() {
+            let child = &ir[instance.module];
+            if !child.is_black_box() {
+                continue;
+            }
+            match models.get(&child.name) {
+                None => errors.push(black_box_not_simulated(
+                    module.file,
+                    instance.span,
+                    &child.name,
+                )),
+                // models are linked into rhdlc rather than the generated program
+                Some(_) if backend == Backend::Compiled => {
+                    errors.push(model_not_compiled(module.file, instance.span, &child.name))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    errors.len() == error_count
+}
+
+/// The values the signals of the testbench, or of an instance of the design, start out with
+fn initial_values(module: &Module, instance: Option<&Instance>) -> Vec<Value> {
+    module
+        .signal_indices()
+        .map(|signal| {
+            let ty = &module[signal].ty;
+            let instance = match instance {
+                Some(instance) => instance,
+                None => return Value::zero(ty),
+            };
+            let connected = instance.connections.iter().any(|connection| {
+                matches!(connection, Connection::Input { port, .. } if *port == signal)
+            });
+            if module[signal].kind == SignalKind::Input && !connected {
+                Value::z(ty)
+            } else {
+                Value::x(ty)
+            }
+        })
+        .collect()
+}
+
+/// The inputs of the testbench that clock registers, which are toggled once per cycle
+pub fn testbench_clocks(ir: &Ir) -> Vec<SignalIndex> {
+    let top = match ir.module_indices().next() {
+        Some(top) => top,
+        None => return vec![],
+    };
+    let clocks = clock_signals(ir);
+    ir[top]
+        .signal_indices()
+        .filter(|signal| {
+            ir[top][*signal].kind == SignalKind::Input && clocks.contains(&(top, *signal))
+        })
+        .collect()
+}
+
+/// The covers in the processes of the instances of modules, in order and without repeats
+fn covers<'m, 'ast: 'm>(modules: impl Iterator<Item = &'m Module<'ast>>) -> Vec<(FileId, Span)> {
+    fn collect(stmts: &[Stmt], file: FileId, covers: &mut Vec<(FileId, Span)>) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Property {
+                    kind: PropertyKind::Cover,
+                    span,
+                    ..
+                } if !covers.contains(&(file, *span)) => covers.push((file, *span)),
+                Stmt::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    collect(then_branch, file, covers);
+                    collect(else_branch, file, covers);
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut covers = vec![];
+    for module in modules {
+        for process in module.processes.iter() {
+            collect(&process.body, module.file, &mut covers);
+        }
+    }
+    covers
+}
+
+/// The widest integer in a type
+fn int_width(ty: &Ty) -> u128 {
+    match ty {
+        Ty::UInt(width) | Ty::SInt(width) => *width,
+        Ty::Vector(elem, _) => int_width(elem),
+    }
+}
+
+/// The lowest and highest values of an integer type, up to the highest `i128`
+fn bounds(ty: &Ty) -> (i128, i128) {
+    let width = ty.width().min(128) as u32;
+    match ty {
+        Ty::SInt(_) if width > 0 => (i128::MIN >> (128 - width), i128::MAX >> (128 - width)),
+        _ if width < 128 => (0, (1 << width) - 1),
+        _ => (0, i128::MAX),
+    }
+}
+
+/// A value or an inclusive range of values in an attribute, like `-1` or `0..=7`, as its first
+/// and last value
+fn parse_range(text: &str) -> Option<(i128, i128)> {
+    let (first, last) = match text.find("..=") {
+        Some(dots) => (&text[..dots], &text[dots + 3..]),
+        None => (text, text),
+    };
+    let first = parse_int(first.trim())?;
+    let last = parse_int(last.trim())?;
+    Some((first, last)).filter(|_| first <= last)
+}
+
+/// The value of a clock after the edge
+fn edge_value(edge: Edge) -> u128 {
+    match edge {
+        Edge::Rising => 1,
+        Edge::Falling => 0,
+    }
+}
+
+fn level(active_high: bool) -> Value {
+    Value::known(active_high as u128)
+}
+
+/// Simulates a design for a number of cycles and writes a table of the testbench's signals
+/// after each of them
+pub fn simulate(
+    resolver: &Resolver,
+    ir: &Ir,
+    models: &Models,
+    simulate: &Simulate,
+    errors: &mut Vec<Diagnostic>,
+) {
+    if simulate.backend == Backend::Compiled {
+        compiled::simulate(resolver, ir, models, simulate, errors);
+        return;
+    }
+    let mut sim = match Simulation::new(ir, models, simulate.seed, errors) {
+        Some(sim) => sim,
+        None => return,
+    };
+    let mut coverage = match Coverage::new(ir, sim.covers(), errors) {
+        Some(coverage) => coverage,
+        None => return,
+    };
+    let top = &ir.modules[0];
+    let reported = top
+        .signal_indices()
+        .filter(|signal| !sim.clocks().contains(signal))
+        .collect::<Vec<_>>();
+    let row = |sim: &Simulation| {
+        std::iter::once(sim.cycle().to_string())
+            .chain(reported.iter().map(|signal| {
+                sim.values()[signal.index()]
+                    .display(&top[*signal].ty)
+                    .to_string()
+            }))
+            .collect::<Vec<_>>()
+    };
+    let mut rows = vec![std::iter::once("cycle".to_string())
+        .chain(reported.iter().map(|signal| top[*signal].name.clone()))
+        .collect::<Vec<_>>()];
+    rows.push(row(&sim));
+    coverage.sample(sim.values());
+    let mut events = sim.check();
+    // the report up to a failing cycle is still useful
+    for _ in 0..simulate.cycles {
+        if events.iter().any(Event::is_fatal) {
+            break;
+        }
+        if let Err(err) = sim.step(None) {
+            errors.push(err);
+            break;
+        }
+        rows.push(row(&sim));
+        coverage.sample(sim.values());
+        events.extend(sim.check());
+    }
+    report_properties(&sim, &events, errors);
+    report_unknown_outputs(resolver, &sim, errors);
+    write_table(rows, simulate.output.as_deref(), errors);
+    coverage.count(&events);
+    write_coverage(resolver, &coverage, simulate.coverage.as_deref(), errors);
+}
+
+/// Adds the coverage of a run to that of the earlier runs in a directory, if there is one
+fn write_coverage(
+    resolver: &Resolver,
+    coverage: &Coverage,
+    dir: Option<&Path>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    if let Some(dir) = dir {
+        let mut report = CoverageReport::default();
+        report.add(coverage, resolver.file_graph());
+        report.write(dir, errors);
+    }
+}
+
+/// Writes rows of cells as a table, aligning the columns
+fn write_table(rows: Vec<Vec<String>>, output: Option<&Path>, errors: &mut Vec<Diagnostic>) {
+    write_report(output, &table(rows), errors);
+}
+
+/// Rows of cells with their columns aligned
+pub fn table(rows: Vec<Vec<String>>) -> String {
+    let widths = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    let mut report = String::new();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        report += line.trim_end();
+        report.push('\n');
+    }
+    report
+}
+
+/// Reports the first failure of each assertion and assumption, and the covers that never held
+fn report_properties(sim: &Simulation, events: &[Event], errors: &mut Vec<Diagnostic>) {
+    report_events(events, &sim.covers(), sim.cycle(), errors);
+}
+
+/// Reports the first failure of each assertion and assumption, and the covers that didn't hold
+/// in the cycles simulated
+fn report_events(
+    events: &[Event],
+    covers: &[(FileId, Span)],
+    cycles: u64,
+    errors: &mut Vec<Diagnostic>,
+) {
+    let mut reported = vec![];
+    for event in events.iter() {
+        if event.kind == PropertyKind::Cover || reported.contains(&(event.file, event.span)) {
+            continue;
+        }
+        reported.push((event.file, event.span));
+        let message = event.message.as_deref();
+        errors.push(match event.kind {
+            PropertyKind::Assume => {
+                assumption_violated(event.file, event.span, event.cycle, message)
+            }
+            _ => assertion_failed(event.file, event.span, event.cycle, event.level, message),
+        });
+    }
+    for (file, span) in covers.iter().copied() {
+        if !events
+            .iter()
+            .any(|event| !event.is_failure() && event.file == file && event.span == span)
+        {
+            errors.push(cover_not_reached(file, span, cycles));
+        }
+    }
+}
+
+/// Warns about the outputs of the design that were unknown after it was reset, at the level of
+/// the testbench's architecture or the config file
+fn report_unknown_outputs(resolver: &Resolver, sim: &Simulation, errors: &mut Vec<Diagnostic>) {
+    report_unknown(resolver, sim.ir, &sim.unknown_outputs, errors);
+}
+
+fn report_unknown(
+    resolver: &Resolver,
+    ir: &Ir,
+    unknown_outputs: &[UnknownOutput],
+    errors: &mut Vec<Diagnostic>,
+) {
+    let testbench = &ir.modules[0];
+    let level = testbench
+        .arch
+        .and_then(|arch| {
+            attribute::lint_level(
+                &item_arch(&resolver.resolution_graph, arch).attrs,
+                UNKNOWN_OUTPUT_LINT,
+            )
+        })
+        .or_else(|| resolver.lint_levels.get(UNKNOWN_OUTPUT_LINT).copied())
+        .unwrap_or(LintLevel::Warn);
+    if level == LintLevel::Allow {
+        return;
+    }
+    for unknown in unknown_outputs.iter() {
+        let instance = &testbench.instances[unknown.instance];
+        let port = &ir[instance.module][unknown.port];
+        let mut diagnostic = unknown_output(
+            testbench.file,
+            instance.span,
+            &instance.name,
+            &port.name,
+            unknown.cycle,
+        );
+        if level == LintLevel::Deny {
+            diagnostic.severity = Severity::Error;
+        }
+        errors.push(diagnostic);
+    }
+}
+
+/// Writes a report to a file, or to standard output when there is none
+pub fn write_report(output: Option<&Path>, report: &str, errors: &mut Vec<Diagnostic>) {
+    match output {
+        None => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            if let Err(err) = stdout.write_all(report.as_bytes()) {
+                errors.push(write_output("stdout", err));
+            }
+        }
+        Some(path) => match fs::write(path, report) {
+            Ok(()) => events::artifact_written(path),
+            Err(err) => errors.push(write_output(&path.to_string_lossy(), err)),
+        },
+    }
+}
@@ -0,0 +1,120 @@
+//! External models: behavioral models that an embedder of rhdlc registers in [`Options::models`]
+//! to simulate black boxes with, so a design can be simulated against reference models written
+//! in Rust, or in C or C++ through the C API, like the DPI of a Verilog simulator:
+//!
+//! ```ignore
+//! struct Multiplier;
+//!
+//! impl Model for Multiplier {
+//!     fn instantiate(&self, _: &ModelContext) -> Box<dyn ModelInstance> {
+//!         Box::new(MultiplierInstance { product: Value::known(0) })
+//!     }
+//! }
+//!
+//! options.models.register("Multiplier", Multiplier);
+//! ```
+//!
+//! Each instance of a black box gets an instance of the model registered for its entity.
+//! Ports are given in declaration order, with the values they have in the simulator, and an
+//! instance can only change its outputs, which it writes with values of their types:
+//! * [`ModelInstance::eval`] computes the outputs from the inputs and the state of the instance,
+//!   any number of times while the design settles, so it doesn't change the state
+//! * [`ModelInstance::update`] is called once the design settled after inputs changed with
+//!   the clocks of the testbench, with the values before and after, for the instance to update
+//!   its state on the edges of its own clocks as a register would
+//!
+//! [`Options::models`]: crate::Options::models
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use super::Value;
+use crate::analysis::signal_graph::SignalKind;
+use crate::elaboration::const_eval::ConstValue;
+use crate::ir::{Module, Ty};
+
+/// A behavioral model of an entity without an architecture
+pub trait Model: Send + Sync {
+    /// A new instance of the model for an instance of the black box
+    fn instantiate(&self, cx: &ModelContext) -> Box<dyn ModelInstance>;
+}
+
+/// An instance of a model, holding its state
+pub trait ModelInstance {
+    /// Writes the outputs among `ports` for the inputs among them
+    fn eval(&self, ports: &mut [Value]);
+
+    /// Updates the state for inputs that changed from `before` to `ports`, returning whether it
+    /// changed
+    fn update(&mut self, before: &[Value], ports: &[Value]) -> bool;
+}
+
+/// The instance of a black box a model is instantiated for
+pub struct ModelContext<'a> {
+    /// The names of the instances from the testbench down to it, separated by `.`
+    pub path: &'a str,
+    /// The values of the generics of the entity, in declaration order
+    pub generics: Vec<(String, ConstValue)>,
+    pub ports: Vec<ModelPort<'a>>,
+}
+
+pub struct ModelPort<'a> {
+    pub name: &'a str,
+    pub kind: SignalKind,
+    pub ty: &'a Ty,
+}
+
+impl<'a> ModelContext<'a> {
+    pub(super) fn new(path: &'a str, module: &'a Module) -> Self {
+        ModelContext {
+            path,
+            generics: module
+                .generics
+                .iter()
+                .map(|(ident, value)| (ident.to_string(), *value))
+                .collect(),
+            ports: module
+                .signals
+                .iter()
+                .filter(|signal| signal.kind != SignalKind::Local)
+                .map(|signal| ModelPort {
+                    name: &signal.name,
+                    kind: signal.kind,
+                    ty: &signal.ty,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The models registered for the entities they simulate, which are shared by clones of the
+/// options they're in
+#[derive(Default, Clone)]
+pub struct Models(Vec<(String, Arc<dyn Model>)>);
+
+impl Models {
+    /// Simulates the black boxes of the entity named `entity` with `model`, in place of the one
+    /// registered for it before
+    pub fn register(&mut self, entity: &str, model: impl Model + 'static) {
+        self.0.retain(|(name, _)| name != entity);
+        self.0.push((entity.to_string(), Arc::new(model)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, entity: &str) -> Option<&dyn Model> {
+        self.0
+            .iter()
+            .find(|(name, _)| name == entity)
+            .map(|(_, model)| &**model)
+    }
+}
+
+impl Debug for Models {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
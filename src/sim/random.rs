@@ -0,0 +1,274 @@
+//! Constrained random stimulus for testbenches.
+//!
+//! An input of the testbench marked `#[random]` is driven with random values of its type instead
+//! of zero, drawn before the first cycle and again after each falling edge of its clocks.
+//! `#[random(min = 1, max = 9)]` constrains them to a range, and
+//! `#[random(weights = "-1: 2, 0..=7: 1")]` draws each value or inclusive range in proportion
+//! to its weight, with the values of a range equally likely. The elements of a vector are drawn
+//! independently.
+//!
+//! Relations between inputs are the `assume!`s of the design: the inputs are drawn again, up to
+//! [`DRAWS`] times, until all of them hold, and an assumption that still doesn't is reported as
+//! violated. The values come from a generator seeded with `--seed`, or from the time, and the
+//! seed is reported so a failing run can be repeated.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rhdl::ast::{Attribute, Lit, Meta, NestedMeta, Span, Spanned};
+
+use super::eval::{mask, Value};
+use super::{bounds, parse_range};
+use crate::analysis::signal_graph::SignalKind;
+use crate::attribute::is_named;
+use crate::elaboration::const_eval::parse_int;
+use crate::error::*;
+use crate::ir::{Ir, SignalIndex, Ty};
+
+/// How many times the inputs are drawn for a cycle before an assumption is given up on
+pub const DRAWS: usize = 100;
+
+/// An input of the testbench and the values it's drawn from
+pub struct RandomInput {
+    signal: SignalIndex,
+    ty: Ty,
+    distribution: Distribution,
+}
+
+/// Ranges of the bits of an integer, as their first value and the number of values after it,
+/// with their weights
+struct Distribution(Vec<(u128, u128, u128)>);
+
+impl Distribution {
+    /// Every value of an integer, equally likely
+    fn uniform(width: u128) -> Self {
+        Distribution(vec![(0, mask(width), 1)])
+    }
+
+    fn draw(&self, rng: &mut Rng, width: u128) -> u128 {
+        let total = self.0.iter().map(|(.., weight)| weight).sum();
+        let mut pick = rng.below(total);
+        for (first, after, weight) in self.0.iter() {
+            if pick < *weight {
+                return first.wrapping_add(rng.below(after.wrapping_add(1))) & mask(width);
+            }
+            pick -= weight;
+        }
+        unreachable!("the pick is below the total weight")
+    }
+}
+
+/// SplitMix64, so the stimulus drawn for a seed is the same everywhere
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n` with every one equally likely, or any number if `n` is zero,
+    /// which is how the count of every 128 bit number wraps around
+    fn below(&mut self, n: u128) -> u128 {
+        loop {
+            let bits = (u128::from(self.next()) << 64) | u128::from(self.next());
+            if n == 0 {
+                return bits;
+            }
+            // the numbers past the last multiple of `n` would make the low ones likelier
+            if bits <= u128::MAX - (u128::MAX % n + 1) % n {
+                return bits % n;
+            }
+        }
+    }
+}
+
+/// The random inputs of a testbench and the generator they're drawn with
+pub struct Stimulus {
+    rng: Rng,
+    inputs: Vec<RandomInput>,
+}
+
+impl Stimulus {
+    pub fn new(inputs: Vec<RandomInput>, seed: u64) -> Self {
+        Stimulus {
+            rng: Rng(seed),
+            inputs,
+        }
+    }
+
+    /// Drives the random inputs among the testbench's values with new ones
+    pub fn draw(&mut self, values: &mut [Value]) {
+        for input in self.inputs.iter() {
+            values[input.signal.index()] = draw(&mut self.rng, &input.distribution, &input.ty);
+        }
+    }
+}
+
+fn draw(rng: &mut Rng, distribution: &Distribution, ty: &Ty) -> Value {
+    match ty {
+        Ty::Vector(elem, len) => {
+            Value::Vector((0..*len).map(|_| draw(rng, distribution, elem)).collect())
+        }
+        _ => Value::known(distribution.draw(rng, ty.width())),
+    }
+}
+
+/// A seed for runs that aren't given one
+pub fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// The `#[random]` attribute of a signal
+pub fn find_random(attrs: &[Attribute]) -> Option<&Attribute> {
+    attrs.iter().find(|attr| match &attr.meta {
+        Meta::Path(path) => is_named(path, "random"),
+        Meta::List(meta_list) => is_named(&meta_list.path, "random"),
+        Meta::NameValue(name_value) => is_named(&name_value.path, "random"),
+    })
+}
+
+/// The random inputs of the testbench of a design, or `None` if any `#[random]` is misplaced or
+/// its constraints are invalid
+pub fn random_inputs(ir: &Ir, errors: &mut Vec<Diagnostic>) -> Option<Vec<RandomInput>> {
+    let error_count = errors.len();
+    let clocks = super::testbench_clocks(ir);
+    let mut inputs = vec![];
+    for (index, module) in ir.modules.iter().enumerate() {
+        for signal in module.signal_indices() {
+            let attr = match find_random(module[signal].attrs) {
+                Some(attr) => attr,
+                None => continue,
+            };
+            if index != 0 || module[signal].kind != SignalKind::Input || clocks.contains(&signal) {
+                errors.push(random_not_stimulus(
+                    module[signal].file,
+                    module[signal].ident,
+                ));
+                continue;
+            }
+            match distribution(attr, &module[signal].ty) {
+                Ok(distribution) => inputs.push(RandomInput {
+                    signal,
+                    ty: module[signal].ty.clone(),
+                    distribution,
+                }),
+                Err((span, msg)) => errors.push(invalid_random(module[signal].file, span, &msg)),
+            }
+        }
+    }
+    if errors.len() == error_count {
+        Some(inputs)
+    } else {
+        None
+    }
+}
+
+/// The values an input of a type is drawn from, or the span and description of an invalid
+/// constraint
+fn distribution(attr: &Attribute, ty: &Ty) -> Result<Distribution, (Span, String)> {
+    let mut int_ty = ty;
+    while let Ty::Vector(elem, _) = int_ty {
+        int_ty = elem;
+    }
+    let width = int_ty.width();
+    let meta_list = match &attr.meta {
+        Meta::List(meta_list) => meta_list,
+        Meta::Path(_) => return Ok(Distribution::uniform(width)),
+        Meta::NameValue(_) => {
+            return Err((
+                attr.span(),
+                "expected `#[random]` or `#[random(...)]`".to_string(),
+            ))
+        }
+    };
+
+    let (mut min, mut max, mut weights) = (None, None, None);
+    for nested in meta_list.nested.iter() {
+        let (name, lit) = match nested {
+            NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                match ["min", "max", "weights"]
+                    .iter()
+                    .find(|name| is_named(&name_value.path, **name))
+                {
+                    Some(name) => (*name, &name_value.lit),
+                    None => return Err((nested.span(), expected_constraint())),
+                }
+            }
+            _ => return Err((nested.span(), expected_constraint())),
+        };
+        match (name, lit) {
+            ("weights", Lit::Str(lit_str)) => weights = Some(lit_str),
+            ("min", Lit::Int(lit_int)) => min = Some((lit.span(), parse_int(&lit_int.inner))),
+            ("max", Lit::Int(lit_int)) => max = Some((lit.span(), parse_int(&lit_int.inner))),
+            _ => return Err((lit.span(), expected_constraint())),
+        }
+    }
+
+    let (lowest, highest) = bounds(int_ty);
+    let in_range = |span: Span, value: Option<i128>| {
+        value
+            .filter(|value| (lowest..=highest).contains(value))
+            .ok_or_else(|| (span, format!("not in `{}..={}`", lowest, highest)))
+    };
+    if let Some(weights) = weights {
+        if min.is_some() || max.is_some() {
+            return Err((
+                weights.span(),
+                "a range can't be combined with weights".to_string(),
+            ));
+        }
+        let mut entries = vec![];
+        for entry in weights.inner.split(',') {
+            let invalid = || {
+                (
+                    weights.span(),
+                    format!(
+                        "`{}` isn't a value or an inclusive range with a positive weight",
+                        entry.trim()
+                    ),
+                )
+            };
+            let colon = entry.find(':').ok_or_else(invalid)?;
+            let (values, weight) = (&entry[..colon], &entry[colon + 1..]);
+            let weight = parse_int(weight.trim())
+                .filter(|weight| *weight > 0)
+                .ok_or_else(invalid)?;
+            let (first, last) = parse_range(values).ok_or_else(invalid)?;
+            let first = in_range(weights.span(), Some(first))?;
+            let last = in_range(weights.span(), Some(last))?;
+            entries.push(range(first, last, width, weight as u128));
+        }
+        return Ok(Distribution(entries));
+    }
+
+    let min = match min {
+        Some((span, value)) => in_range(span, value)?,
+        None => lowest,
+    };
+    let max = match max {
+        Some((span, value)) => in_range(span, value)?,
+        None => highest,
+    };
+    if min > max {
+        return Err((attr.span(), "`min` is above `max`".to_string()));
+    }
+    Ok(Distribution(vec![range(min, max, width, 1)]))
+}
+
+/// The values of a range in a distribution of integers of a width
+fn range(first: i128, last: i128, width: u128, weight: u128) -> (u128, u128, u128) {
+    (
+        first as u128 & mask(width),
+        last.wrapping_sub(first) as u128,
+        weight,
+    )
+}
+
+fn expected_constraint() -> String {
+    "expected `min = <int>`, `max = <int>`, or `weights = \"...\"`".to_string()
+}
@@ -0,0 +1,227 @@
+//! `rhdlc test` simulates the testbenches of a design and checks their assertions.
+//!
+//! A testbench is an entity of a root file marked `#[test]`, or one whose architecture has
+//! `#[test]` functions. Each of those functions is a test of its own, and the properties outside
+//! of them belong to every test of the testbench. A test fails in the first cycle one of its
+//! assertions or assumptions doesn't hold.
+use std::fs;
+use std::path::PathBuf;
+
+use rhdl::visit::Visit;
+
+use super::coverage::{Coverage, CoverageReport};
+use super::model::Models;
+use super::{
+    report_properties, report_unknown_outputs, write_report, Event, Lcov, Simulation, Vcd,
+};
+use crate::attribute;
+use crate::elaboration::item_entity;
+use crate::error::*;
+use crate::events;
+use crate::ir::{process::FnCollector, Ir};
+use crate::resolution::{ResolutionIndex, Resolver};
+
+/// `rhdlc test`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Test {
+    /// How many cycles to simulate each testbench for
+    pub cycles: u64,
+    /// `None` for standard output
+    pub output: Option<PathBuf>,
+    /// The directory to write the value changes of failing testbenches to
+    pub vcd: Option<PathBuf>,
+    /// The seed random stimulus is drawn with, `None` for one from the time
+    pub seed: Option<u64>,
+    /// The directory to collect the coverage of the testbenches in over runs
+    pub coverage: Option<PathBuf>,
+    /// The file to write the lines executed and the bits toggled by the testbenches to, as lcov
+    pub lcov: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct Testbench {
+    pub name: String,
+    /// Whether the entity itself is a test, rather than only its functions
+    pub is_test: bool,
+}
+
+/// The testbenches of the root files, by name
+pub fn find_testbenches(resolver: &Resolver) -> Vec<Testbench> {
+    let resolution_graph = &resolver.resolution_graph;
+    let mut testbenches = resolution_graph
+        .roots
+        .iter()
+        .filter(|root| !resolution_graph.is_extern(**root))
+        .filter_map(|root| resolution_graph[*root].children())
+        .flat_map(|children| children.values())
+        .flat_map(|indices| indices.iter().copied())
+        .filter(|i| resolution_graph[*i].is_entity())
+        .filter(|entity| is_testbench(resolver, *entity))
+        .map(|entity| {
+            let item_entity = item_entity(resolver, entity);
+            Testbench {
+                name: item_entity.ident.to_string(),
+                is_test: attribute::find_flag(&item_entity.attrs, "test").is_some(),
+            }
+        })
+        .collect::<Vec<_>>();
+    testbenches.sort_by(|a, b| a.name.cmp(&b.name));
+    testbenches
+}
+
+/// Whether an entity is marked `#[test]` or has an architecture with `#[test]` functions
+pub fn is_testbench(resolver: &Resolver, entity: ResolutionIndex) -> bool {
+    let resolution_graph = &resolver.resolution_graph;
+    attribute::find_flag(&item_entity(resolver, entity).attrs, "test").is_some()
+        || resolution_graph.architectures(entity).iter().any(|arch| {
+            let mut fn_collector = FnCollector { fns: vec![] };
+            resolution_graph[*arch].visit(&mut fn_collector);
+            fn_collector
+                .fns
+                .iter()
+                .any(|item_fn| attribute::find_flag(&item_fn.attrs, "test").is_some())
+        })
+}
+
+/// The outcome of each test
+#[derive(Default)]
+pub struct TestReport {
+    lines: Vec<String>,
+    passed: usize,
+    failed: usize,
+    /// The coverage of the testbenches simulated
+    coverage: CoverageReport,
+    /// The lines executed and bits toggled by the testbenches simulated
+    lcov: Lcov,
+}
+
+impl TestReport {
+    /// Simulates a testbench and records its tests
+    pub fn run(
+        &mut self,
+        resolver: &Resolver,
+        ir: &Ir,
+        models: &Models,
+        testbench: &Testbench,
+        test: &Test,
+        errors: &mut Vec<Diagnostic>,
+    ) {
+        let top = &ir.modules[0];
+        // `None` for the testbench itself
+        let mut tests: Vec<Option<&str>> = vec![];
+        if testbench.is_test {
+            tests.push(None);
+        }
+        for process in top.processes.iter() {
+            if let Some(name) = process.test.as_deref() {
+                if !tests.contains(&Some(name)) {
+                    tests.push(Some(name));
+                }
+            }
+        }
+        if tests.is_empty() {
+            return;
+        }
+
+        let mut sim = match Simulation::new(ir, models, test.seed, errors) {
+            Some(sim) => sim,
+            None => return self.compile_failed(testbench),
+        };
+        let mut coverage = match Coverage::new(ir, sim.covers(), errors) {
+            Some(coverage) => coverage,
+            None => return self.compile_failed(testbench),
+        };
+        let mut vcd = test.vcd.as_ref().map(|_| Vcd::new(&sim));
+        if test.lcov.is_some() {
+            sim.track_code_coverage();
+        }
+        coverage.sample(sim.values());
+        let mut events = sim.check();
+        let mut unsettled = None;
+        while sim.cycle() < test.cycles && !events.iter().any(Event::is_fatal) {
+            if let Err(err) = sim.step(vcd.as_mut()) {
+                errors.push(err);
+                unsettled = Some(sim.cycle());
+                break;
+            }
+            coverage.sample(sim.values());
+            events.extend(sim.check());
+        }
+        coverage.count(&events);
+        self.coverage.add(&coverage, resolver.file_graph());
+        if let Some(code_coverage) = sim.code_coverage() {
+            code_coverage.report(ir, resolver.file_graph(), &mut self.lcov);
+        }
+
+        let failures = events
+            .iter()
+            .filter(|event| event.is_failure())
+            .collect::<Vec<_>>();
+        for name in tests.iter() {
+            let failed_in = failures
+                .iter()
+                .filter(|failure| failure.test.is_none() || failure.test.as_deref() == *name)
+                .map(|failure| failure.cycle)
+                .chain(unsettled)
+                .min();
+            let full_name = match name {
+                Some(name) => format!("{}::{}", testbench.name, name),
+                None => testbench.name.clone(),
+            };
+            match failed_in {
+                Some(cycle) => {
+                    self.failed += 1;
+                    self.lines
+                        .push(format!("test {} ... FAILED in cycle {}", full_name, cycle));
+                }
+                None => {
+                    self.passed += 1;
+                    self.lines.push(format!("test {} ... ok", full_name));
+                }
+            }
+        }
+
+        report_properties(&sim, &events, errors);
+        report_unknown_outputs(resolver, &sim, errors);
+
+        if let (Some(dir), Some(vcd), false) =
+            (&test.vcd, vcd, failures.is_empty() && unsettled.is_none())
+        {
+            let path = dir.join(format!("{}.vcd", testbench.name));
+            let written = fs::create_dir_all(dir).and_then(|_| fs::write(&path, vcd.finish()));
+            match written {
+                Ok(()) => events::artifact_written(&path),
+                Err(err) => errors.push(write_output(&path.to_string_lossy(), err)),
+            }
+        }
+    }
+
+    /// Records a testbench that couldn't be simulated as a failed test
+    pub fn compile_failed(&mut self, testbench: &Testbench) {
+        self.failed += 1;
+        self.lines
+            .push(format!("test {} ... FAILED to compile", testbench.name));
+    }
+
+    /// Writes the outcome of each test and a summary
+    pub fn finish(self, test: &Test, errors: &mut Vec<Diagnostic>) {
+        let mut report = format!("running {} tests\n", self.passed + self.failed);
+        for line in self.lines {
+            report += &line;
+            report.push('\n');
+        }
+        report += &format!(
+            "\ntest result: {}. {} passed; {} failed\n",
+            if self.failed == 0 { "ok" } else { "FAILED" },
+            self.passed,
+            self.failed
+        );
+        write_report(test.output.as_deref(), &report, errors);
+        if let Some(dir) = &test.coverage {
+            self.coverage.write(dir, errors);
+        }
+        if let Some(path) = &test.lcov {
+            self.lcov.write(path, errors);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Value change dumps of a simulation, for waveform viewers like GTKWave
+
+use super::{Simulation, Value};
+use crate::ir::{Ir, ModuleIndex, Ty};
+
+/// Nanoseconds per half of a clock period
+const HALF_PERIOD: u64 = 5;
+
+/// Records the values of every signal in the hierarchy whenever they change
+pub struct Vcd {
+    out: String,
+    /// The identifier code of each signal of each instance
+    codes: Vec<Vec<String>>,
+    /// The type of each signal of each instance
+    tys: Vec<Vec<Ty>>,
+    /// The values last dumped
+    last: Vec<Vec<Value>>,
+}
+
+impl Vcd {
+    /// Declares every signal of the simulation and dumps their current values
+    pub fn new(sim: &Simulation) -> Vcd {
+        let hierarchy = sim
+            .instances
+            .iter()
+            .map(|state| (state.module, state.children.clone()))
+            .collect::<Vec<_>>();
+        let values = sim.instances.iter().map(|state| state.values.as_slice());
+        Vcd::with_hierarchy(sim.ir, &hierarchy, values)
+    }
+
+    /// Declares every signal of a hierarchy of instances, as their modules and the indices of
+    /// their children with the top first, and dumps their values
+    pub fn with_hierarchy<'v>(
+        ir: &Ir,
+        hierarchy: &[(ModuleIndex, Vec<usize>)],
+        values: impl Iterator<Item = &'v [Value]>,
+    ) -> Vcd {
+        let mut vcd = Vcd {
+            out: "$timescale 1ns $end\n".to_string(),
+            codes: vec![vec![]; hierarchy.len()],
+            tys: hierarchy
+                .iter()
+                .map(|(module, _)| {
+                    ir[*module]
+                        .signals
+                        .iter()
+                        .map(|signal| signal.ty.clone())
+                        .collect()
+                })
+                .collect(),
+            last: vec![],
+        };
+        let top = ir[hierarchy[0].0].specialized_name();
+        vcd.declare(ir, hierarchy, 0, &top, &mut 0);
+        vcd.out += "$enddefinitions $end\n#0\n$dumpvars\n";
+        for (index, values) in values.enumerate() {
+            for (i, value) in values.iter().enumerate() {
+                vcd.out += &dump(value, &vcd.tys[index][i], &vcd.codes[index][i]);
+            }
+            vcd.last.push(values.to_vec());
+        }
+        vcd.out += "$end\n";
+        vcd
+    }
+
+    fn declare(
+        &mut self,
+        ir: &Ir,
+        hierarchy: &[(ModuleIndex, Vec<usize>)],
+        index: usize,
+        scope: &str,
+        next_code: &mut usize,
+    ) {
+        let (module, children) = &hierarchy[index];
+        let module = &ir[*module];
+        self.out += &format!("$scope module {} $end\n", scope);
+        for signal in module.signals.iter() {
+            let code = code(*next_code);
+            *next_code += 1;
+            self.out += &format!(
+                "$var wire {} {} {} $end\n",
+                signal.ty.width(),
+                code,
+                signal.name
+            );
+            self.codes[index].push(code);
+        }
+        for (instance, child) in module.instances.iter().zip(children.iter()) {
+            self.declare(ir, hierarchy, *child, &instance.name, next_code);
+        }
+        self.out += "$upscope $end\n";
+    }
+
+    /// Dumps the values that changed, at a time in half clock periods
+    pub fn sample(&mut self, sim: &Simulation, time: u64) {
+        let values = sim.instances.iter().map(|state| state.values.as_slice());
+        self.sample_values(values, time);
+    }
+
+    /// Dumps the values of the instances of the hierarchy that changed, at a time in half clock
+    /// periods
+    pub fn sample_values<'v>(&mut self, values: impl Iterator<Item = &'v [Value]>, time: u64) {
+        let mut changes = String::new();
+        for (index, values) in values.enumerate() {
+            for (i, value) in values.iter().enumerate() {
+                if *value != self.last[index][i] {
+                    changes += &dump(value, &self.tys[index][i], &self.codes[index][i]);
+                    self.last[index][i] = value.clone();
+                }
+            }
+        }
+        if !changes.is_empty() {
+            self.out += &format!("#{}\n", time * HALF_PERIOD);
+            self.out += &changes;
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+/// Short identifier codes of printable characters
+fn code(mut n: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (n % 94) as u8) as char);
+        n /= 94;
+        if n == 0 {
+            return code;
+        }
+    }
+}
+
+fn dump(value: &Value, ty: &Ty, code: &str) -> String {
+    let mut bits = String::new();
+    push_bits(value, ty, &mut bits);
+    if bits.len() == 1 {
+        format!("{}{}\n", bits, code)
+    } else {
+        format!("b{} {}\n", bits, code)
+    }
+}
+
+/// The most significant bit first, and the last element of a vector first
+fn push_bits(value: &Value, ty: &Ty, bits: &mut String) {
+    match value {
+        Value::Int { bits: int, unknown } => {
+            for bit in (0..ty.width()).rev() {
+                bits.push(match (unknown >> bit & 1, int >> bit & 1) {
+                    (0, 0) => '0',
+                    (0, _) => '1',
+                    (_, 0) => 'x',
+                    _ => 'z',
+                });
+            }
+        }
+        Value::Vector(elems) => {
+            let elem_ty = ty.elem();
+            for elem in elems.iter().rev() {
+                push_bits(elem, &elem_ty, bits);
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! The exit status of rhdlc, so scripts wrapping it can tell why it failed without parsing the
+//! diagnostics:
+//!
+//! | Status | Meaning                                                     |
+//! |--------|-------------------------------------------------------------|
+//! | 0      | Success, possibly with warnings                             |
+//! | 1      | The design has errors, or isn't formatted with `fmt --check` |
+//! | 2      | The command line is invalid                                 |
+//! | 3      | A file couldn't be read or written, or a program like `rustc` couldn't be run |
+//! | 101    | rhdlc panicked, which is a bug in the compiler              |
+use crate::error::{self, Diagnostic};
+use codespan_reporting::diagnostic::Severity;
+
+/// Why rhdlc exited, ordered so the most severe reason is the greatest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Success,
+    CompileError,
+    Usage,
+    Io,
+    InternalError,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self::Success
+    }
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Success => 0,
+            Self::CompileError => 1,
+            Self::Usage => 2,
+            Self::Io => 3,
+            Self::InternalError => 101,
+        }
+    }
+
+    /// The status of reporting `diagnostic`, which only fails for errors
+    pub fn of(diagnostic: &Diagnostic) -> Self {
+        if diagnostic.severity < Severity::Error {
+            Self::Success
+        } else if error::is_io(diagnostic) {
+            Self::Io
+        } else {
+            Self::CompileError
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+/// Exits with the usage status for an invalid command line, which clap would exit with 1 for
+#[cfg(not(feature = "fuzz"))]
+pub fn usage_error(err: clap::Error) -> ! {
+    if err.use_stderr() {
+        eprintln!("{}", err.message);
+        Status::Usage.exit()
+    } else {
+        // `--help` and `--version` are reported as errors too
+        println!("{}", err.message);
+        Status::Success.exit()
+    }
+}
+
+#[cfg(not(feature = "fuzz"))]
+pub fn invalid_value(message: &str) -> ! {
+    usage_error(clap::Error::with_description(
+        message,
+        clap::ErrorKind::InvalidValue,
+    ))
+}
@@ -0,0 +1,27 @@
+//! The standard library of RHDL: registers, counters, synchronizers, and common widths, written in
+//! RHDL and embedded in rhdlc. It's loaded as the extern root `std` of a design that names it,
+//! like the libraries registered with `--lib` or found in `RHDL_PATH`, which shadow it.
+use std::path::Path;
+
+/// The name the library is imported by
+pub const NAME: &str = "std";
+
+/// The path of the root file of the library
+pub const ROOT: &str = "std/lib.rhdl";
+
+/// The files of the library by path, which its modules are found at like files on disk
+const FILES: &[(&str, &str)] = &[
+    ("std/lib.rhdl", include_str!("../std/lib.rhdl")),
+    ("std/counter.rhdl", include_str!("../std/counter.rhdl")),
+    ("std/register.rhdl", include_str!("../std/register.rhdl")),
+    ("std/sync.rhdl", include_str!("../std/sync.rhdl")),
+    ("std/width.rhdl", include_str!("../std/width.rhdl")),
+];
+
+/// The source of the file of the library at `path`, if there is one
+pub fn source(path: &Path) -> Option<&'static str> {
+    FILES
+        .iter()
+        .find(|(file, _)| Path::new(file) == path)
+        .map(|(_, source)| *source)
+}
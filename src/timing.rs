@@ -0,0 +1,138 @@
+//! `--time-passes`: how long each stage of compilation took and the peak memory of the compiler
+//! after it, to keep track of performance.
+use std::fmt::Write;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+/// A clock that's always at zero, since there isn't one in the browser and
+/// `std::time::Instant::now` panics there. Nothing is timed instead.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy)]
+pub struct Instant;
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub fn now() -> Self {
+        Instant
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::default()
+    }
+}
+
+/// The passes run so far, in the order they first ran
+#[derive(Default, Debug)]
+pub struct Timings {
+    passes: Vec<Pass>,
+}
+
+#[derive(Debug)]
+struct Pass {
+    name: &'static str,
+    time: Duration,
+    /// The peak resident memory of the compiler in bytes when the pass last ran, if it's known
+    peak_memory: Option<u64>,
+}
+
+impl Timings {
+    /// Records a pass that started at `start` and just finished
+    pub fn record(&mut self, name: &'static str, start: Instant) {
+        self.add(name, start.elapsed());
+    }
+
+    /// Adds `time` to a pass, which can run more than once, like parsing each file
+    pub fn add(&mut self, name: &'static str, time: Duration) {
+        log::trace!("{} took {}", name, seconds(time));
+        self.merge(Pass {
+            name,
+            time,
+            peak_memory: peak_memory(),
+        });
+    }
+
+    /// The time spent in a pass so far
+    pub fn time(&self, name: &str) -> Duration {
+        self.passes
+            .iter()
+            .find(|pass| pass.name == name)
+            .map(|pass| pass.time)
+            .unwrap_or_default()
+    }
+
+    /// Moves the passes of `other` after these, like those of a stage run by another part of the
+    /// compiler
+    pub fn append(&mut self, other: &mut Timings) {
+        for pass in other.passes.drain(..) {
+            self.merge(pass);
+        }
+    }
+
+    fn merge(&mut self, pass: Pass) {
+        match self.passes.iter_mut().find(|other| other.name == pass.name) {
+            Some(other) => {
+                other.time += pass.time;
+                other.peak_memory = pass.peak_memory.or(other.peak_memory);
+            }
+            None => self.passes.push(pass),
+        }
+    }
+
+    /// A table of the passes with a total
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "{:<24}{:>12}{:>16}", "pass", "time", "peak memory").unwrap();
+        for pass in self.passes.iter() {
+            writeln!(
+                out,
+                "{:<24}{:>12}{:>16}",
+                pass.name,
+                seconds(pass.time),
+                mebibytes(pass.peak_memory)
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "{:<24}{:>12}{:>16}",
+            "total",
+            seconds(self.passes.iter().map(|pass| pass.time).sum()),
+            mebibytes(self.passes.iter().filter_map(|pass| pass.peak_memory).max())
+        )
+        .unwrap();
+        out
+    }
+}
+
+fn seconds(time: Duration) -> String {
+    format!("{:.3}s", time.as_secs_f64())
+}
+
+fn mebibytes(bytes: Option<u64>) -> String {
+    bytes.map_or_else(
+        || "-".to_string(),
+        |bytes| format!("{:.1} MiB", bytes as f64 / (1024. * 1024.)),
+    )
+}
+
+/// The peak resident memory of the compiler so far, which is only known on Linux
+#[cfg(target_os = "linux")]
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kibibytes = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kibibytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_memory() -> Option<u64> {
+    None
+}
@@ -0,0 +1,18 @@
+module Top (
+    input clk_in,
+    input [7:0] d,
+    output [7:0] q
+);
+    wire clk;
+
+    Bufg buffer (
+        .i(clk_in),
+        .o(clk)
+    );
+
+    Fifo #(.WIDTH(8), .DEPTH(16)) fifo (
+        .clk(clk),
+        .din(d),
+        .dout(q)
+    );
+endmodule
@@ -0,0 +1,17 @@
+module Counter_WIDTH_8 (
+    input clk,
+    input rst_n,
+    input enable,
+    output reg [7:0] count
+);
+
+    always @(posedge clk or negedge rst_n) begin
+        if (!rst_n) begin
+            count <= 8'd0;
+        end else begin
+            if (enable) begin
+                count <= count + 8'd1;
+            end
+        end
+    end
+endmodule
@@ -0,0 +1,14 @@
+module Top (
+    input clk,
+    input rst_n,
+    input enable,
+    output [7:0] count
+);
+
+    Counter_WIDTH_8 counter (
+        .clk(clk),
+        .rst_n(rst_n),
+        .enable(enable),
+        .count(count)
+    );
+endmodule
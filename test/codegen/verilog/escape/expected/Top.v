@@ -0,0 +1,15 @@
+module Top (
+    input clk,
+    input \input ,
+    output reg \reg 
+);
+    reg \wire ;
+
+    always @* begin
+        \wire  = ~\input ;
+    end
+
+    always @(posedge clk) begin
+        \reg  <= \wire ;
+    end
+endmodule
@@ -0,0 +1,28 @@
+module Top (
+    input clk,
+    input rst,
+    input start,
+    output reg done
+);
+    (* fsm_encoding = "one_hot" *) reg [2:0] state;
+
+    always @* begin
+        done = state == 3'd4;
+    end
+
+    always @(posedge clk or posedge rst) begin
+        if (rst) begin
+            state <= 3'd1;
+        end else begin
+            if (state == 3'd1) begin
+                if (start) begin
+                    state <= 3'd2;
+                end
+            end else if (state == 3'd2) begin
+                state <= 3'd4;
+            end else begin
+                state <= 3'd1;
+            end
+        end
+    end
+endmodule
@@ -0,0 +1,13 @@
+(* keep_hierarchy = "true" *)
+module Sync (
+    input clk,
+    input d,
+    output reg q
+);
+    (* async_reg = "true", keep = "true" *) reg meta;
+
+    always @(posedge clk) begin
+        meta <= d;
+        q <= meta;
+    end
+endmodule
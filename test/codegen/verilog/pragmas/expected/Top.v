@@ -0,0 +1,19 @@
+module Top (
+    input clk_in,
+    input d,
+    (* max_fanout = 16 *) output q
+);
+    wire clk;
+
+    (* dont_touch = "true" *)
+    Pll pll (
+        .clk_in(clk_in),
+        .clk_out(clk)
+    );
+
+    Sync sync (
+        .clk(clk),
+        .d(d),
+        .q(q)
+    );
+endmodule
@@ -0,0 +1,12 @@
+module Top (
+    input [3:0] d,
+    output reg [3:0] q
+);
+
+    always @* begin
+        q[1'b0] = ~d[1'b0];
+        q[1'b1] = ~d[1'b1];
+        q[2'd2] = ~d[2'd2];
+        q[2'd3] = ~d[2'd3];
+    end
+endmodule